@@ -1,6 +1,6 @@
 use clap::{Parser, Subcommand};
-use simperby_core::MemberName;
-use simperby_node::simperby_core::BlockHeight;
+use simperby_core::{LeaderOrderStrategy, MemberName};
+use simperby_node::simperby_core::{BlockHeight, Timestamp};
 
 /**
 Welcome to the Simperby CLI!
@@ -10,6 +10,12 @@ Welcome to the Simperby CLI!
 #[clap(about = "A Simperby client CLI", long_about = None)]
 pub struct Cli {
     pub path: std::path::PathBuf,
+    /// Override a config field, in the form of `<field>=<value>` (e.g. `fetch_interval_ms=1000`).
+    ///
+    /// Nested fields are separated by `.`. The overrides apply on top of `config.json`
+    /// and the `SIMPERBY_*` environment variables, only for this run.
+    #[clap(long = "set", value_name = "FIELD=VALUE", global = true)]
+    pub config_overrides: Vec<String>,
     #[clap(subcommand)]
     pub command: Commands,
 }
@@ -25,6 +31,11 @@ pub enum CreateCommands {
         block_height: BlockHeight,
         proof: String,
         chain_name: String,
+        /// Must be greater than the nonce of the last (un)delegation of the delegator.
+        nonce: u64,
+        /// Print the commit to create without writing it.
+        #[clap(long, action)]
+        dry_run: bool,
     },
     /// An extra-agenda transaction that undelegates the consensus voting power and
     /// the governance voting power (if delegated).
@@ -33,26 +44,184 @@ pub enum CreateCommands {
         block_height: BlockHeight,
         proof: String,
         chain_name: String,
+        /// Must be greater than the nonce of the last (un)delegation of the delegator.
+        nonce: u64,
+        /// Print the commit to create without writing it.
+        #[clap(long, action)]
+        dry_run: bool,
     },
     /// An extra-agenda transaction that reports a misbehaving validator.
     TxReport, // TODO
+    /// An extra-agenda transaction that bans a member from the network and the consensus
+    /// until the block of `until_height` is finalized.
+    ///
+    /// Collect the signatures of the majority of the governance with `sign tx-ban`.
+    TxBan {
+        member: MemberName,
+        until_height: BlockHeight,
+        block_height: BlockHeight,
+        chain_name: String,
+        /// The timestamp that the signers have signed with.
+        timestamp: i64,
+        proofs: Vec<String>,
+        /// Print the commit to create without writing it.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+    /// A transaction that takes away the consensus voting power of an offline validator,
+    /// from the report drafted by the node (see `stats`).
+    OfflineReport {
+        offender: MemberName,
+        /// Schedule the change to take effect once the block of the height is finalized,
+        /// instead of right after the approval.
+        #[clap(long)]
+        activation_height: Option<BlockHeight>,
+    },
+    /// A transaction that replaces the metadata (the public profile) of a member.
+    MemberMetadata {
+        member: MemberName,
+        /// The new metadata in the form of `<key>=<value>`; none clears it.
+        ///
+        /// The keys are `contact`, `website`, `description`, `location` and `logo`.
+        entries: Vec<String>,
+        /// Schedule the change as in `offline-report`.
+        #[clap(long)]
+        activation_height: Option<BlockHeight>,
+    },
+    /// A transaction that replaces the consensus leader order with the eligible leaders
+    /// (the validators not banned) ordered by the strategy.
+    LeaderOrder {
+        /// `alphabetical`, `stake-descending` or `keep-with-insertions`
+        /// (the current order with the new leaders appended by the names).
+        strategy: LeaderOrderStrategy,
+        /// Schedule the change as in `offline-report`.
+        #[clap(long)]
+        activation_height: Option<BlockHeight>,
+        /// Print the current and the resulting orders without creating the transaction.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
     /// A block waiting for finalization.
-    Block,
+    Block {
+        /// Print the commit to create without writing it.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
     /// An agenda waiting for governance approval.
-    Agenda,
+    Agenda {
+        /// The title of the agenda, which gives it a description
+        /// covered by the agenda hash (and so by the votes).
+        #[clap(long)]
+        title: Option<String>,
+        /// Why the agenda should be approved; requires `--title`.
+        #[clap(long, default_value = "", requires = "title")]
+        rationale: String,
+        /// Where the agenda is discussed (e.g., a forum thread or the hash of a poll);
+        /// can be repeated, and requires `--title`.
+        #[clap(long = "link", requires = "title")]
+        links: Vec<String>,
+        /// The last time (a Unix timestamp in milliseconds) that the agenda can be approved at;
+        /// the votes arriving after it never approve the agenda.
+        #[clap(long)]
+        deadline: Option<Timestamp>,
+        /// Print the commit to create without writing it.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Debug, Subcommand)]
 pub enum PeerCommand {
     /// Add a peer with the given name and address.
-    Add { address: String, name: String },
+    ///
+    /// The name must be of a member, whose key the peer is expected to hold.
+    /// The address is either of IPv4 (`1.2.3.4:1155`), IPv6 (`[::1]:1155`) or a hostname.
+    Add {
+        address: String,
+        name: String,
+        /// The member that relays for the peer, if the peer is behind a NAT.
+        #[clap(long)]
+        relay: Option<String>,
+    },
     /// Remove the peer with the given name.
     Remove { name: String },
     /// Updates the peer list using the peer discovery protocol.
     /// This may leave some remote repositories with the prefix `>`.
     Update,
-    /// Prints the status of the discovered peers.
-    Status,
+    /// Prints the peers with the last time they were seen alive.
+    List,
+    /// Performs a handshake with the peer, printing the latency and
+    /// whether it holds the key of the member.
+    Ping { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JoinCommands {
+    /// Print a join request signed with the configured private key,
+    /// to be handed to an existing member.
+    Generate {
+        /// The desired member name.
+        name: String,
+        /// The requested voting power, for both the governance and the consensus.
+        voting_power: u64,
+        chain_name: String,
+    },
+    /// Print the member set that results from the join request, without submitting it.
+    Review { request: String },
+    /// Create a transaction that adds the member of the join request,
+    /// to be included in the next agenda.
+    Submit {
+        request: String,
+        /// Schedule the change as in `create offline-report`.
+        #[clap(long)]
+        activation_height: Option<BlockHeight>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PatchCommands {
+    /// Print a patch bundle of the uncommitted changes in the working tree,
+    /// signed with the configured private key, to be handed to a member.
+    ///
+    /// This needs neither the write access to the repository nor the membership.
+    Generate {
+        /// The message of the transaction to make, the title in the first line.
+        message: String,
+    },
+    /// Print the contributor, the message and the patch of the bundle, without submitting it.
+    Review { bundle: String },
+    /// Check the bundle and keep it in the transaction pool, printing its hash.
+    Submit { bundle: String },
+    /// Print the patches in the transaction pool.
+    List,
+    /// Create a transaction from the patch of the given hash in the transaction pool,
+    /// to be included in the next agenda.
+    Accept { hash: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PollCommands {
+    /// Broadcast a non-binding poll to the governance members, printing its hash.
+    ///
+    /// Put `Poll: <hash>` in the body of a transaction to reference the poll.
+    Create {
+        title: String,
+        /// The options to vote for, at least two.
+        #[clap(required = true, num_args = 2..)]
+        options: Vec<String>,
+        #[clap(long, default_value = "")]
+        description: String,
+        /// How long the poll is open, in milliseconds.
+        #[clap(long, default_value_t = 7 * 24 * 60 * 60 * 1000)]
+        duration_ms: u64,
+    },
+    /// Vote for the option of the poll, by its index in `poll list`.
+    Vote { poll: String, option: u32 },
+    /// Print the polls in the governance DMS with their results, the latest first.
+    List,
+    /// Print the result of the poll weighted by the governance voting power,
+    /// with the voters of each option.
+    Tally { poll: String },
 }
 
 #[derive(Debug, Subcommand)]
@@ -64,17 +233,109 @@ pub enum SignCommands {
         governance: bool,
         target_height: u64,
         chain_name: String,
+        /// Must be greater than the nonce of the last (un)delegation of the delegator.
+        nonce: u64,
     },
     TxUndelegate {
         delegator: MemberName,
         target_height: u64,
         chain_name: String,
+        /// Must be greater than the nonce of the last (un)delegation of the delegator.
+        nonce: u64,
+    },
+    TxBan {
+        member: MemberName,
+        /// The height of the block whose finalization lifts the ban.
+        until_height: u64,
+        target_height: u64,
+        chain_name: String,
+        /// Must be the same for all the signers.
+        timestamp: i64,
+    },
+    /// Sign the release manifest (`release.json` of the node) in the given file
+    /// with the release key, printing the signed one.
+    ReleaseManifest {
+        path: String,
+    },
+    /// Check the agenda bundle (from `export-agenda-bundle`) in the given file
+    /// and print the vote on the agenda signed with the configured private key,
+    /// to be imported with `import-agenda-review`.
+    ///
+    /// This needs no network access.
+    AgendaReview {
+        path: String,
+        /// Print the agenda, its commits with the patches and the changes of the reserved state
+        /// without signing.
+        #[clap(long, action)]
+        dry_run: bool,
     },
     Custom {
         hash: String,
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum AuditLogCommands {
+    /// Print the finalized agendas of the given heights with the votes and the proofs
+    /// that approved them, sealed with the configured private key.
+    ///
+    /// It's in JSON Lines, an agenda per line followed by the seal.
+    Export {
+        /// The first height to include.
+        #[clap(long, default_value_t = 1)]
+        from: BlockHeight,
+        /// The last height to include. If not specified, the last finalized one.
+        #[clap(long)]
+        to: Option<BlockHeight>,
+        /// Print a vote per row in CSV instead, which can't be verified.
+        #[clap(long, action)]
+        csv: bool,
+    },
+    /// Verify the audit log in the given file against the finalized history,
+    /// checking that no agenda or vote is left out or altered.
+    Verify { path: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DebugCommands {
+    /// Print the log of the consensus rounds of the given height:
+    /// the proposer, the proposal, the votes, the timeouts and the outcome of each round.
+    Rounds {
+        /// The height of the block being decided. If not specified, the current one.
+        height: Option<BlockHeight>,
+    },
+    /// Print the DMS packets recorded by the message tap (`message_tap` in the config):
+    /// the direction, the peer, the DMS key, the type, the hash, the size and the verdict of each.
+    Messages {
+        /// Keep printing the packets as they're recorded.
+        #[clap(long, action)]
+        follow: bool,
+        /// Print only those of the DMS of the given key.
+        #[clap(long)]
+        dms: Option<String>,
+    },
+    /// Replay the messages of a consensus DMS storage (e.g., `consensus/dms` of a node directory
+    /// submitted by an operator) with a mock clock, printing each state transition.
+    ///
+    /// The storage doesn't record when the messages arrived,
+    /// so they're delivered by the round and then by the step, a tick apart.
+    Replay {
+        /// The directory of the consensus DMS storage.
+        #[clap(long)]
+        from: String,
+        /// The file of the last finalized block header in JSON.
+        /// If not specified, the one of the consensus state storage (`../state`) beside the DMS.
+        #[clap(long)]
+        header: Option<String>,
+        /// The public key (in hex) of the validator to replay as. If not specified, the configured one.
+        #[clap(long = "as")]
+        validator: Option<String>,
+        /// How far the mock clock advances for each message, in milliseconds.
+        #[clap(long, default_value_t = 100)]
+        tick_ms: Timestamp,
+    },
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     // ----- Initialization Commands ----- //
@@ -82,10 +343,35 @@ pub enum Commands {
     /// from the given existing Git repository.
     ///
     /// This will seek the reserved state, verify it, and add a genesis commit.
-    /// You have to run `init` after this to initialize the Simperby node.
+    /// Run `init` before this to scaffold the pre-genesis repository.
     Genesis,
-    /// Initialize a new Simperby node from the given existing Simperby repository.
-    Init,
+    /// Scaffold a new chain directory.
+    ///
+    /// This generates a keypair, writes `config.json` and `peers.json`,
+    /// and creates the repository with the genesis reserved state of the given members.
+    /// The options not given are asked interactively.
+    ///
+    /// The genesis proof needs the signatures of the members,
+    /// which they produce by running `sign custom` on the printed genesis sign target.
+    /// Run `init` again on the same directory with the collected signatures to add them.
+    Init {
+        /// The name of the chain.
+        #[clap(long)]
+        chain_name: Option<String>,
+        /// Your member name.
+        #[clap(long)]
+        name: Option<String>,
+        /// Another member, in the form of `<name>:<public key in hex>`.
+        #[clap(long = "member")]
+        members: Vec<String>,
+        /// A signature on the genesis sign target,
+        /// in the form of `<public key in hex>:<signature in hex>`.
+        #[clap(long = "genesis-signature")]
+        genesis_signatures: Vec<String>,
+        /// Fail instead of asking for the options not given.
+        #[clap(long, action)]
+        non_interactive: bool,
+    },
     /// Clone a remote Simperby repository to the current directory,
     /// and initialize a new Simperby node after verification.
     ///
@@ -93,9 +379,32 @@ pub enum Commands {
     Clone {
         /// The URL of the remote repository.
         url: String,
+        /// The height of the trusted checkpoint to bootstrap from,
+        /// which downloads only the history from that height.
+        #[clap(long, requires = "trust_hash")]
+        trust_height: Option<BlockHeight>,
+        /// The block hash of the trusted checkpoint.
+        #[clap(long, requires = "trust_height")]
+        trust_hash: Option<String>,
+    },
+
+    /// Upgrade the on-disk formats of the node directory to this version of Simperby.
+    ///
+    /// The node directory is migrated automatically on every command;
+    /// this is for checking the pending migrations in advance.
+    Migrate {
+        /// Print the pending migrations without applying them.
+        #[clap(long, action)]
+        dry_run: bool,
     },
 
     // ----- Modification Commands ----- //
+    /// Finalize the last block of the `work` branch with the given proof,
+    /// moving the `finalized` branch to it.
+    Sync {
+        /// The last finalization proof of the block, in JSON.
+        last_finalization_proof: String,
+    },
     /// Clean the repository, removing all the outdated (incompatible with `finalized`) commits.
     Clean {
         /// If enabled, it will remove
@@ -108,9 +417,50 @@ pub enum Commands {
     /// Create a new commit on top of the `work` branch.
     #[command(subcommand)]
     Create(CreateCommands),
+    /// Manage the requests to join the network as a new member.
+    #[command(subcommand)]
+    Join(JoinCommands),
+    /// Manage the patches submitted by the contributors who can't push to the repository.
+    #[command(subcommand)]
+    Patch(PatchCommands),
+    /// Manage the non-binding polls of the governance members.
+    #[command(subcommand)]
+    Poll(PollCommands),
     /// Vote on the agenda, broadcasting to the network.
     /// It will also leave a `vote` tag on the given commit (with some postfix).
-    Vote { revision: String },
+    Vote {
+        revision: String,
+        /// Print the message to broadcast without tagging the commit or broadcasting it.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+    /// Print the payload (in hex) to sign for voting on the agenda with an offline key.
+    ///
+    /// Sign it with `sign custom` on the offline machine and run `import-vote` with the result.
+    ExportVote { revision: String },
+    /// Import a vote on the agenda signed with an offline key, broadcasting to the network.
+    ImportVote {
+        revision: String,
+        /// The public key of the signer, in hex.
+        signer: String,
+        /// The signature on the payload from `export-vote`, in hex.
+        signature: String,
+    },
+    /// Print the agenda with its commits, patches and changes of the reserved state,
+    /// sealed with the configured private key, to be reviewed and voted on offline
+    /// with `sign agenda-review`.
+    ExportAgendaBundle { revision: String },
+    /// Import a vote from an offline review (`sign agenda-review`), broadcasting to the network.
+    ImportAgendaReview { review: String },
+    /// Veto the agenda as a veto holder, broadcasting to the network.
+    ///
+    /// The agenda can't be approved regardless of the votes until the veto expires.
+    VetoAgenda {
+        revision: String,
+        /// How long the veto lasts, in milliseconds.
+        #[clap(long, default_value_t = 7 * 24 * 60 * 60 * 1000)]
+        duration_ms: u64,
+    },
     /// Veto the round.
     ///
     /// It will be broadcasted to the network as a nil-vote
@@ -131,11 +481,26 @@ pub enum Commands {
         /// the consensus is 'global' so this option is not associated with any commit.
         #[clap(long, action)]
         show: bool,
+        /// Remove the sign watermark, which refuses to sign the consensus messages at or below it,
+        /// after asking for a confirmation.
+        ///
+        /// Use it only if sure that the key hasn't signed the refused messages before;
+        /// otherwise it may double-sign.
+        #[clap(long, action)]
+        override_watermark: bool,
     },
 
     // ----- Information Commands ----- //
     /// Show the overall information of the given commit.
     Show { revision: String },
+    /// Show the members of the last finalized reserved state,
+    /// with their effective voting powers, delegations and metadata.
+    ShowMembers {
+        /// Print the delegation graph in the DOT language of Graphviz instead,
+        /// e.g. `simperby show-members --dot | dot -Tsvg > delegation.svg`.
+        #[clap(long, action)]
+        dot: bool,
+    },
     /// Show the status of the Simperby repository.
     ///
     /// It checkes the following for the current directory:
@@ -155,9 +520,59 @@ pub enum Commands {
         full: bool,
     },
 
+    /// Replay the finalized branch, re-verifying every block header, agenda proof,
+    /// finalization proof and reserved state transition.
+    ///
+    /// Run this after cloning an existing chain, not to trust the history fetched.
+    Verify {
+        /// The height to start from, whose block is trusted.
+        /// The genesis block is verified with the genesis proof instead.
+        #[clap(long, default_value_t = 0)]
+        from: BlockHeight,
+    },
+    /// Verify the signatures of the finalized commits, from the genesis block.
+    ///
+    /// It checks both the in-commit signatures by the member keys
+    /// and the git's native (GPG/SSH) signatures by the git signing keys of the members,
+    /// and fails if any of them is invalid.
+    VerifyCommits,
+    /// Show the statistics of the recent blocks for governance reporting.
+    ///
+    /// It shows the precommit participation rate and the proposals (against the schedule)
+    /// of each validator, the average block interval and the number of agendas per block.
+    Stats {
+        /// The number of the most recent blocks to compute the statistics over.
+        #[clap(long, default_value_t = simperby_node::stats::DEFAULT_STATS_WINDOW)]
+        blocks: u64,
+    },
+    /// Write the finalized blocks, agendas, votes and member changes to the indexer database.
+    ///
+    /// The blocks written before are overwritten, so it's safe to run again
+    /// over the heights that the running node has already indexed.
+    Index {
+        /// The height to start from.
+        #[clap(long, default_value_t = 0)]
+        from: BlockHeight,
+    },
+    /// Export or verify the audit log of the governance.
+    #[command(subcommand)]
+    AuditLog(AuditLogCommands),
+    /// Show the evidence of the forks observed by the node.
+    ///
+    /// A fork halts the finalization until it is resolved by a manual governance action.
+    /// The evidence is printed in full so that it can be shared with the other members;
+    /// remove `fork_evidence.json` in the node directory to resume afterwards.
+    Forks,
+    /// Inspect the internal states of the node for diagnosing failures.
+    #[command(subcommand)]
+    Debug(DebugCommands),
+
     // ----- Network Commands ----- //
     /// Show the current status of the p2p network.
     Network,
+    /// Show which members have acknowledged the message of the given hash (e.g., a vote),
+    /// once it's broadcast.
+    Propagation { message: String },
     /// Manages the peer list for the p2p network.
     /// Note that this is independent from the Git remotes.
     #[command(subcommand)]
@@ -203,6 +618,12 @@ pub enum Commands {
     /// Sign a message with the configured private key.
     #[command(subcommand)]
     Sign(SignCommands),
+    /// Print the version of this release and the protocol versions it supports.
+    Version {
+        /// Compare them with the version of the chain and its scheduled forks.
+        #[clap(long, action)]
+        chain: bool,
+    },
     /// A special command triggered by the Git hook, which is used to verify the push request.
     CheckPush {
         /// The revision of the branch that is being pushed.
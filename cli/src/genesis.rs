@@ -91,7 +91,7 @@ pub async fn run_genesis_proposer(private_key: &str) {
     );
     println!("PRESS ENTER TO CREATE AN AGENDA --------");
     get_input();
-    node.create_agenda().await.unwrap();
+    node.create_agenda(None, None).await.unwrap();
     run_command(format!("cd {dir}/repository/repo && git show")).await;
 
     println!("PRESS ENTER TO RUN SERVER -------- [A]");
@@ -181,6 +181,7 @@ pub async fn run_genesis_non_proposer(private_key: &str) {
             ports,
             message: "123".to_owned(),
             recently_seen_timestamp: 0,
+            relay: None,
         }],
     )
     .await;
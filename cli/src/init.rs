@@ -0,0 +1,191 @@
+//! The `init` wizard, which scaffolds a new chain directory.
+use eyre::{eyre, Result};
+use simperby_node::config_layers::{
+    DEFAULT_BLOB_PORT, DEFAULT_CONSENSUS_PORT, DEFAULT_GOVERNANCE_PORT, DEFAULT_HEARTBEAT_PORT,
+    DEFAULT_REPOSITORY_PORT, DEFAULT_TRANSACTION_POOL_PORT,
+};
+use simperby_node::simperby_core::*;
+use simperby_node::Config;
+use std::io::{self, BufRead, Write};
+
+pub const DEFAULT_HEARTBEAT_INTERVAL_MS: u64 = 5000;
+
+pub fn parse_public_key(s: &str) -> Result<PublicKey> {
+    PublicKey::from_array(
+        hex::decode(s)?
+            .as_slice()
+            .try_into()
+            .map_err(|_| eyre!("a public key must be in 33 bytes"))?,
+    )
+    .map_err(|_| eyre!("invalid public key"))
+}
+
+/// Parses a member in the form of `<name>:<public key in hex>`.
+pub fn parse_member(s: &str) -> Result<(MemberName, PublicKey)> {
+    let (name, public_key) = s
+        .split_once(':')
+        .ok_or_else(|| eyre!("a member must be in the form of `<name>:<public key>`"))?;
+    Ok((name.to_owned(), parse_public_key(public_key)?))
+}
+
+/// Parses a genesis signature in the form of `<public key in hex>:<signature in hex>`,
+/// where the signature is from `sign custom` on the genesis sign target.
+pub fn parse_genesis_signature(s: &str) -> Result<TypedSignature<FinalizationSignTarget>> {
+    let (public_key, signature) = s.split_once(':').ok_or_else(|| {
+        eyre!("a genesis signature must be in the form of `<public key>:<signature>`")
+    })?;
+    let signature = Signature::from_array(
+        hex::decode(signature)?
+            .as_slice()
+            .try_into()
+            .map_err(|_| eyre!("a signature must be in 65 bytes"))?,
+    );
+    Ok(TypedSignature::new(
+        signature,
+        parse_public_key(public_key)?,
+    ))
+}
+
+/// Asks a question on the terminal, returning the default for an empty answer.
+fn prompt(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) if !default.is_empty() => print!("{question} [{default}]: "),
+        _ => print!("{question}: "),
+    }
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    match (answer.is_empty(), default) {
+        (true, Some(default)) => Ok(default.to_owned()),
+        (true, None) => prompt(question, default),
+        (false, _) => Ok(answer.to_owned()),
+    }
+}
+
+/// Runs the `init` command.
+///
+/// If the directory is already initialized, it only adds the given genesis signatures.
+/// Otherwise, it generates a keypair and scaffolds the directory,
+/// asking for the missing options unless `non_interactive` is set.
+pub async fn run(
+    path: &str,
+    chain_name: Option<String>,
+    name: Option<String>,
+    members: Vec<String>,
+    genesis_signatures: Vec<String>,
+    non_interactive: bool,
+) -> Result<()> {
+    let genesis_signatures = genesis_signatures
+        .iter()
+        .map(|s| parse_genesis_signature(s))
+        .collect::<Result<Vec<_>>>()?;
+    if std::path::Path::new(&format!("{path}/config.json")).exists() {
+        if simperby_node::add_genesis_signatures(path, genesis_signatures).await? {
+            println!("the genesis proof is complete; run `genesis` to create the genesis commit");
+        } else {
+            println!("the genesis proof still needs more signatures");
+        }
+        return Ok(());
+    }
+
+    let ask = |question: &str, default: Option<&str>| -> Result<String> {
+        if non_interactive {
+            default
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| eyre!("missing option: {question}"))
+        } else {
+            prompt(question, default)
+        }
+    };
+    let chain_name = match chain_name {
+        Some(x) => x,
+        None => ask("chain name", None)?,
+    };
+    let name = match name {
+        Some(x) => x,
+        None => ask("your member name", None)?,
+    };
+    let mut members = members
+        .iter()
+        .map(|s| parse_member(s))
+        .collect::<Result<Vec<_>>>()?;
+    if members.is_empty() && !non_interactive {
+        loop {
+            let member = prompt(
+                "another member as `<name>:<public key>` (empty to finish)",
+                Some(""),
+            )?;
+            if member.is_empty() {
+                break;
+            }
+            match parse_member(&member) {
+                Ok(member) => members.push(member),
+                Err(e) => println!("{e}"),
+            }
+        }
+    }
+
+    let (public_key, private_key) = generate_keypair_random();
+    members.push((name, public_key.clone()));
+    let mut reserved_state =
+        ReservedState::genesis_template(chain_name.clone(), members).map_err(|e| eyre!(e))?;
+    let target = reserved_state.genesis_sign_target();
+    reserved_state
+        .add_genesis_signature(
+            TypedSignature::sign(&target, &private_key).map_err(|_| eyre!("failed to sign"))?,
+        )
+        .map_err(|e| eyre!(e))?;
+    for signature in genesis_signatures {
+        reserved_state
+            .add_genesis_signature(signature)
+            .map_err(|e| eyre!(e))?;
+    }
+
+    let config = Config {
+        chain_name,
+        public_key: Some(public_key.clone()),
+        private_key: Some(private_key),
+        broadcast_interval_ms: None,
+        fetch_interval_ms: None,
+        heartbeat_interval_ms: Some(DEFAULT_HEARTBEAT_INTERVAL_MS),
+        prune_interval_ms: None,
+        retention: Default::default(),
+        public_repo_url: vec![],
+        governance_port: DEFAULT_GOVERNANCE_PORT,
+        consensus_port: DEFAULT_CONSENSUS_PORT,
+        repository_port: DEFAULT_REPOSITORY_PORT,
+        heartbeat_port: DEFAULT_HEARTBEAT_PORT,
+        blob_port: DEFAULT_BLOB_PORT,
+        transaction_pool_port: DEFAULT_TRANSACTION_POOL_PORT,
+        peers: vec![],
+        relay: None,
+        transport: Default::default(),
+        consensus_params: Default::default(),
+        round_history_heights: None,
+        offline_report_policy: None,
+        webhooks: vec![],
+        trusted_checkpoint: None,
+        observer: false,
+        standby: None,
+        require_signed_commits: false,
+        git_signer: None,
+        message_tap: None,
+        vote_reveal_delay_ms: None,
+        watchdog: None,
+        indexer: None,
+        gateway: None,
+        version_check: Default::default(),
+    };
+    println!("your public key: {public_key}");
+    if simperby_node::init(&config, path, &reserved_state).await? {
+        println!("the genesis proof is complete; run `genesis` to create the genesis commit");
+    } else {
+        println!(
+            "to complete the genesis proof, the members have to run `sign custom {}` and \
+             give you the result to run `init --genesis-signature <public key>:<signature>`",
+            target.to_hash256()
+        );
+    }
+    Ok(())
+}
@@ -2,18 +2,113 @@ use clap::Parser;
 use eyre::{eyre, Result};
 use simperby_cli::cli::{self, *};
 use simperby_core::utils::get_timestamp;
-use simperby_node::{clone, genesis, initialize, serve, simperby_core::*, CommitInfo, Config};
+use simperby_node::members::delegation_graph_dot;
+use simperby_node::page::PageRequest;
+use simperby_node::release::{self, ReleaseManifest, SignedReleaseManifest};
+use simperby_node::simperby_consensus::{
+    read_state_block_header, replay, ConsensusMessage, PendingVeto, ReplayConfig, ReplayEvent,
+    ReplayStep, VetoExpiry, VetoTarget,
+};
+use simperby_node::simperby_governance::audit::AuditLog;
+use simperby_node::simperby_governance::poll::PollTally;
+use simperby_node::simperby_network::dms::{read_stored_messages, TapReader};
+use simperby_node::simperby_network::{Storage, StorageImpl};
+use simperby_node::simperby_repository::patch::PatchBundle;
+use simperby_node::simperby_repository::progress::Progress;
+use simperby_node::simperby_repository::raw::SemanticCommit;
+use simperby_node::simperby_repository::review::{AgendaBundle, AgendaReview};
+use simperby_node::simperby_repository::{interpret::SignatureStatus, TrustedCheckpoint};
+use simperby_node::{
+    bootstrap, clone, config_layers, create_patch_bundle, genesis, initialize, migrations, serve,
+    simperby_core::*, CommitInfo, Config, MESSAGE_TAP_FILE,
+};
+use std::io::Write;
+use tokio::sync::watch;
 
 async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
     match args.command {
         Commands::Genesis => {
             genesis(config, &path).await?;
         }
-        Commands::Init => todo!(),
-        Commands::Clone { url } => {
-            clone(config, &path, &url).await?;
+        Commands::Init { .. } | Commands::Migrate { .. } => {
+            unreachable!("handled before loading the config")
+        }
+        Commands::Clone {
+            url,
+            trust_height,
+            trust_hash,
+        } => {
+            if let (Some(height), Some(hash)) = (trust_height, trust_hash) {
+                let hash = Hash256::from_array(
+                    hex::decode(hash)?
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| eyre!("a hash must be in 32 bytes"))?,
+                );
+                bootstrap(config, &path, &url, TrustedCheckpoint { height, hash }).await?;
+            } else {
+                clone(config, &path, &url).await?;
+            }
         }
         Commands::Show { revision } => show(config, &path, revision).await?,
+        // It reads only the tap file, so that it can watch a running node.
+        Commands::Debug(DebugCommands::Messages { follow, dms }) => {
+            if config.message_tap.is_none() {
+                eprintln!("the message tap is disabled; set `message_tap` in the config");
+            }
+            let mut reader = TapReader::new(format!("{path}/{MESSAGE_TAP_FILE}"));
+            loop {
+                for record in reader.read_new()? {
+                    if dms.as_ref().is_none_or(|key| &record.dms_key == key) {
+                        println!("{record}");
+                    }
+                }
+                if !follow {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            }
+        }
+        Commands::Debug(DebugCommands::Replay {
+            from,
+            header,
+            validator,
+            tick_ms,
+        }) => {
+            let (dms_config, messages) =
+                read_stored_messages::<_, ConsensusMessage>(&StorageImpl::open(&from).await?)
+                    .await?;
+            let block_header = match header {
+                Some(header) => serde_spb::from_str(&std::fs::read_to_string(header)?)?,
+                None => {
+                    read_state_block_header(&StorageImpl::open(&format!("{from}/../state")).await?)
+                        .await?
+                }
+            };
+            let this_node = match validator {
+                Some(validator) => PublicKey::from_array(
+                    hex::decode(validator)?
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| eyre!("a public key must be in 33 bytes"))?,
+                )
+                .map_err(|_| eyre!("invalid public key"))?,
+                None => config.signing_key()?.public_key(),
+            };
+            let steps = replay(
+                &ReplayConfig {
+                    block_header,
+                    params: config.consensus_params.clone(),
+                    this_node,
+                    tick_ms,
+                },
+                &dms_config.dms_key,
+                messages,
+            )?;
+            for step in steps {
+                print_replay_step(&step);
+            }
+        }
         Commands::Network => todo!(),
         Commands::Serve => {
             serve(config, &path).await?;
@@ -25,48 +120,155 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
             governance,
             target_height,
             chain_name,
+            nonce,
         }) => {
-            let delegation_transaction_data = DelegationTransactionData {
+            let tx = TxDelegate::builder()
+                .delegator(delegator)
+                .delegatee(delegatee)
+                .governance(governance)
+                .block_height(target_height)
+                .timestamp(get_timestamp())
+                .chain_name(chain_name)
+                .nonce(nonce)
+                .build(config.signing_key()?)
+                .map_err(|e| eyre!("failed to sign: {e}"))?;
+            println!("{:?}", serde_spb::to_string(&tx.proof));
+        }
+        Commands::Sign(SignCommands::TxUndelegate {
+            delegator,
+            target_height,
+            chain_name,
+            nonce,
+        }) => {
+            let undelegation_transaction_data = UndelegationTransactionData {
                 delegator,
-                delegatee,
-                governance,
                 block_height: target_height,
                 timestamp: get_timestamp(),
                 chain_name,
+                nonce,
             };
             println!(
                 "{:?}",
                 serde_spb::to_string(
-                    &TypedSignature::<DelegationTransactionData>::sign(
-                        &delegation_transaction_data,
-                        &config.private_key,
+                    &TypedSignature::<UndelegationTransactionData>::sign(
+                        &undelegation_transaction_data,
+                        config.signing_key()?,
                     )
                     .map_err(|_| eyre!("failed to sign"))?
                 )
             );
         }
-        Commands::Sign(SignCommands::TxUndelegate {
-            delegator,
+        Commands::Sign(SignCommands::TxBan {
+            member,
+            until_height,
             target_height,
             chain_name,
+            timestamp,
         }) => {
-            let undelegation_transaction_data = UndelegationTransactionData {
-                delegator,
+            let ban_transaction_data = BanTransactionData {
+                member,
+                until_height,
                 block_height: target_height,
-                timestamp: get_timestamp(),
+                timestamp,
                 chain_name,
             };
             println!(
                 "{:?}",
                 serde_spb::to_string(
-                    &TypedSignature::<UndelegationTransactionData>::sign(
-                        &undelegation_transaction_data,
-                        &config.private_key,
+                    &TypedSignature::<BanTransactionData>::sign(
+                        &ban_transaction_data,
+                        config.signing_key()?,
                     )
                     .map_err(|_| eyre!("failed to sign"))?
                 )
             );
         }
+        Commands::Join(JoinCommands::Generate {
+            name,
+            voting_power,
+            chain_name,
+        }) => {
+            let data = JoinRequestData {
+                name,
+                public_key: config.signing_key()?.public_key(),
+                voting_power,
+                timestamp: get_timestamp(),
+                chain_name,
+            };
+            println!(
+                "{}",
+                serde_spb::to_string(&JoinRequest {
+                    proof: TypedSignature::sign(&data, config.signing_key()?)
+                        .map_err(|_| eyre!("failed to sign"))?,
+                    data,
+                })?
+            );
+        }
+        Commands::Patch(PatchCommands::Generate { message }) => {
+            let bundle = create_patch_bundle(&config, &path, message).await?;
+            println!("{}", serde_spb::to_string(&bundle)?);
+        }
+        Commands::Patch(PatchCommands::Review { bundle }) => {
+            let bundle: PatchBundle =
+                serde_spb::from_str(&bundle).map_err(|_| eyre!("invalid patch bundle"))?;
+            bundle.verify()?;
+            println!("contributor: {}", bundle.contributor());
+            println!("{}\n", bundle.patch.message);
+            print!("{}", bundle.patch.diff);
+        }
+        Commands::Sign(SignCommands::ReleaseManifest { path }) => {
+            let mut release: SignedReleaseManifest =
+                serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            release.signature = Some(
+                TypedSignature::sign(&release.manifest, config.signing_key()?)
+                    .map_err(|_| eyre!("failed to sign"))?,
+            );
+            println!("{}", serde_json::to_string_pretty(&release)?);
+        }
+        Commands::Sign(SignCommands::AgendaReview { path, dry_run }) => {
+            let bundle: AgendaBundle = serde_spb::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|_| eyre!("invalid agenda bundle"))?;
+            bundle.verify()?;
+            if dry_run {
+                print_agenda_bundle(&bundle);
+            } else {
+                println!(
+                    "{}",
+                    serde_spb::to_string(&bundle.sign_review(config.signing_key()?)?)?
+                );
+            }
+        }
+        Commands::Version { chain } => {
+            let compatibility = if chain {
+                Some(release::read_compatibility(&path).await?)
+            } else {
+                None
+            };
+            let release = match &compatibility {
+                Some(compatibility) => compatibility.release.clone(),
+                None => ReleaseManifest::current()?,
+            };
+            println!("node version: {}", release.manifest.node_version);
+            println!("protocol versions: {}", release.manifest.protocol_versions);
+            match &release.signature {
+                Some(signature) => println!("signed by: {}", signature.signer()),
+                None => println!("signed by: (unsigned)"),
+            }
+            for chain_version in compatibility.iter().flat_map(|c| &c.chain_versions) {
+                let supported = if chain_version.supported {
+                    "supported"
+                } else {
+                    "NOT supported"
+                };
+                match chain_version.activation_height {
+                    None => println!("chain version: {} ({supported})", chain_version.version),
+                    Some(height) => println!(
+                        "scheduled fork at height {height}: {} ({supported})",
+                        chain_version.version
+                    ),
+                }
+            }
+        }
         Commands::Sign(SignCommands::Custom { hash }) => {
             let hash = Hash256::from_array(
                 hex::decode(hash)?
@@ -77,7 +279,7 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
             println!(
                 "{}",
                 hex::encode(
-                    Signature::sign(hash, &config.private_key)
+                    Signature::sign(hash, config.signing_key()?)
                         .map_err(|_| eyre!("failed to sign"))?
                 )
             );
@@ -91,12 +293,15 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                 Commands::Sync {
                     last_finalization_proof,
                 } => {
-                    simperby_node
+                    let progress_bar = spawn_progress_bar(simperby_node.subscribe_progress());
+                    let result = simperby_node
                         .sync(
                             serde_spb::from_str(&last_finalization_proof)
                                 .map_err(|_| eyre!("invalid last finalization proof for sync"))?,
                         )
-                        .await?;
+                        .await;
+                    finish_progress_bar(progress_bar).await;
+                    result?;
                 }
                 Commands::Clean { hard } => {
                     simperby_node.clean(hard).await?;
@@ -108,93 +313,702 @@ async fn run(args: cli::Cli, path: String, config: Config) -> eyre::Result<()> {
                     block_height,
                     proof,
                     chain_name,
+                    nonce,
+                    dry_run,
                 }) => {
-                    simperby_node
-                        .create_extra_agenda_transaction(ExtraAgendaTransaction::Delegate(
-                            TxDelegate {
-                                data: DelegationTransactionData {
-                                    delegator: serde_spb::from_str(&delegator).map_err(|_| {
-                                        eyre!("invalid delegator for a delegation transaction")
-                                    })?,
-                                    delegatee: serde_spb::from_str(&delegatee).map_err(|_| {
-                                        eyre!("invalid delegatee for a delegation transaction")
-                                    })?,
-                                    governance,
-                                    block_height,
-                                    timestamp: get_timestamp(),
-                                    chain_name,
-                                },
-                                proof: serde_spb::from_str(&proof).map_err(|_| {
-                                    eyre!("invalid proof for a delegation transaction")
-                                })?,
-                            },
-                        ))
-                        .await?;
+                    let tx = ExtraAgendaTransaction::Delegate(TxDelegate {
+                        data: DelegationTransactionData {
+                            delegator: serde_spb::from_str(&delegator).map_err(|_| {
+                                eyre!("invalid delegator for a delegation transaction")
+                            })?,
+                            delegatee: serde_spb::from_str(&delegatee).map_err(|_| {
+                                eyre!("invalid delegatee for a delegation transaction")
+                            })?,
+                            governance,
+                            block_height,
+                            timestamp: get_timestamp(),
+                            chain_name,
+                            nonce,
+                        },
+                        proof: serde_spb::from_str(&proof)
+                            .map_err(|_| eyre!("invalid proof for a delegation transaction"))?,
+                    });
+                    if dry_run {
+                        print_semantic_commit(
+                            &simperby_node
+                                .preview_create_extra_agenda_transaction(tx)
+                                .await?,
+                        );
+                    } else {
+                        simperby_node.create_extra_agenda_transaction(tx).await?;
+                    }
                 }
                 Commands::Create(CreateCommands::TxUndelegate {
                     delegator,
                     block_height,
                     proof,
                     chain_name,
+                    nonce,
+                    dry_run,
                 }) => {
-                    simperby_node
-                        .create_extra_agenda_transaction(ExtraAgendaTransaction::Undelegate(
-                            TxUndelegate {
-                                data: UndelegationTransactionData {
-                                    delegator: serde_spb::from_str(&delegator).map_err(|_| {
-                                        eyre!("invalid delegator for an undelegation transaction")
-                                    })?,
-                                    block_height,
-                                    timestamp: get_timestamp(),
-                                    chain_name,
-                                },
-                                proof: serde_spb::from_str(&proof).map_err(|_| {
-                                    eyre!("invalid proof for an undelegation transaction")
-                                })?,
-                            },
-                        ))
-                        .await?;
+                    let tx = ExtraAgendaTransaction::Undelegate(TxUndelegate {
+                        data: UndelegationTransactionData {
+                            delegator: serde_spb::from_str(&delegator).map_err(|_| {
+                                eyre!("invalid delegator for an undelegation transaction")
+                            })?,
+                            block_height,
+                            timestamp: get_timestamp(),
+                            chain_name,
+                            nonce,
+                        },
+                        proof: serde_spb::from_str(&proof)
+                            .map_err(|_| eyre!("invalid proof for an undelegation transaction"))?,
+                    });
+                    if dry_run {
+                        print_semantic_commit(
+                            &simperby_node
+                                .preview_create_extra_agenda_transaction(tx)
+                                .await?,
+                        );
+                    } else {
+                        simperby_node.create_extra_agenda_transaction(tx).await?;
+                    }
+                }
+                Commands::Create(CreateCommands::TxBan {
+                    member,
+                    until_height,
+                    block_height,
+                    chain_name,
+                    timestamp,
+                    proofs,
+                    dry_run,
+                }) => {
+                    let tx = ExtraAgendaTransaction::Ban(TxBan {
+                        data: BanTransactionData {
+                            member,
+                            until_height,
+                            block_height,
+                            timestamp,
+                            chain_name,
+                        },
+                        proof: proofs
+                            .iter()
+                            .map(|proof| {
+                                serde_spb::from_str(proof)
+                                    .map_err(|_| eyre!("invalid proof for a ban transaction"))
+                            })
+                            .collect::<Result<_>>()?,
+                    });
+                    if dry_run {
+                        print_semantic_commit(
+                            &simperby_node
+                                .preview_create_extra_agenda_transaction(tx)
+                                .await?,
+                        );
+                    } else {
+                        simperby_node.create_extra_agenda_transaction(tx).await?;
+                    }
                 }
                 Commands::Create(CreateCommands::TxReport) => {
                     todo!("TxReport is not implemented yet")
                 }
-                Commands::Create(CreateCommands::Block) => {
-                    simperby_node.create_block().await?;
+                Commands::Create(CreateCommands::OfflineReport {
+                    offender,
+                    activation_height,
+                }) => {
+                    simperby_node
+                        .create_offline_report_transaction(&offender, activation_height)
+                        .await?;
+                }
+                Commands::Create(CreateCommands::MemberMetadata {
+                    member,
+                    entries,
+                    activation_height,
+                }) => {
+                    let metadata = entries
+                        .iter()
+                        .map(|entry| {
+                            entry
+                                .split_once('=')
+                                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                                .ok_or_else(|| eyre!("invalid metadata entry: {entry}"))
+                        })
+                        .collect::<Result<_>>()?;
+                    simperby_node
+                        .create_member_metadata_transaction(
+                            TxUpdateMemberMetadata { member, metadata },
+                            activation_height,
+                        )
+                        .await?;
+                }
+                Commands::Create(CreateCommands::LeaderOrder {
+                    strategy,
+                    activation_height,
+                    dry_run,
+                }) => {
+                    if dry_run {
+                        let reserved_state = simperby_node.review_leader_order(strategy)?;
+                        println!(
+                            "current: {}",
+                            simperby_node
+                                .get_last_finalization_info()
+                                .await?
+                                .reserved_state
+                                .consensus_leader_order
+                                .join(", ")
+                        );
+                        println!(
+                            "{strategy}: {}",
+                            reserved_state.consensus_leader_order.join(", ")
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            simperby_node
+                                .create_leader_order_transaction(strategy, activation_height)
+                                .await?
+                        );
+                    }
+                }
+                Commands::Join(JoinCommands::Review { request }) => {
+                    let request: JoinRequest =
+                        serde_spb::from_str(&request).map_err(|_| eyre!("invalid join request"))?;
+                    let reserved_state = simperby_node.review_join_request(&request)?;
+                    for member in &reserved_state.members {
+                        print_member(member);
+                    }
+                }
+                Commands::ShowMembers { dot } => {
+                    let members = simperby_node.get_members(&PageRequest::all())?.items;
+                    if dot {
+                        print!("{}", delegation_graph_dot(&members));
+                    } else {
+                        println!(
+                            "{:<20} {:>6} {:>11} {:>11} delegation",
+                            "name", "leader", "consensus", "governance"
+                        );
+                        for member in &members {
+                            let delegation = [
+                                member
+                                    .consensus_delegatee
+                                    .as_ref()
+                                    .map(|x| format!("consensus to {x}")),
+                                member
+                                    .governance_delegatee
+                                    .as_ref()
+                                    .map(|x| format!("governance to {x}")),
+                            ]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<_>>();
+                            println!(
+                                "{:<20} {:>6} {:>11} {:>11} {}",
+                                member.name,
+                                member
+                                    .leader_order
+                                    .map_or_else(|| "-".to_owned(), |x| x.to_string()),
+                                format!(
+                                    "{}/{}",
+                                    member.effective_consensus_power, member.consensus_voting_power
+                                ),
+                                format!(
+                                    "{}/{}",
+                                    member.effective_governance_power,
+                                    member.governance_voting_power
+                                ),
+                                if delegation.is_empty() {
+                                    "-".to_owned()
+                                } else {
+                                    delegation.join(", ")
+                                }
+                            );
+                            for (key, value) in &member.metadata {
+                                println!("    {key}: {value}");
+                            }
+                        }
+                    }
+                }
+                Commands::Join(JoinCommands::Submit {
+                    request,
+                    activation_height,
+                }) => {
+                    let request =
+                        serde_spb::from_str(&request).map_err(|_| eyre!("invalid join request"))?;
+                    simperby_node
+                        .create_join_transaction(request, activation_height)
+                        .await?;
+                }
+                Commands::Patch(PatchCommands::Submit { bundle }) => {
+                    let bundle =
+                        serde_spb::from_str(&bundle).map_err(|_| eyre!("invalid patch bundle"))?;
+                    println!("{}", simperby_node.submit_patch(bundle).await?);
+                }
+                Commands::Patch(PatchCommands::List) => {
+                    for bundle in simperby_node.list_patches(&PageRequest::all()).await?.items {
+                        println!(
+                            "{} {} {}",
+                            bundle.to_hash256(),
+                            bundle.contributor(),
+                            bundle.title()
+                        );
+                    }
+                }
+                Commands::Patch(PatchCommands::Accept { hash }) => {
+                    let hash = Hash256::from_array(
+                        hex::decode(hash)?
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| eyre!("a hash must be in 32 bytes"))?,
+                    );
+                    println!("{}", simperby_node.accept_patch(hash).await?);
+                }
+                Commands::Poll(PollCommands::Create {
+                    title,
+                    options,
+                    description,
+                    duration_ms,
+                }) => {
+                    let poll_hash = simperby_node
+                        .create_poll(title, description, options, duration_ms)
+                        .await?;
+                    println!("{poll_hash}");
+                }
+                Commands::Poll(PollCommands::Vote { poll, option }) => {
+                    simperby_node.vote_poll(parse_hash(&poll)?, option).await?;
+                }
+                Commands::Propagation { message } => {
+                    let propagation = simperby_node
+                        .get_message_propagation(parse_hash(&message)?)
+                        .await?
+                        .ok_or_else(|| eyre!("unknown message: {message}"))?;
+                    let reserved_state = simperby_node
+                        .get_last_finalization_info()
+                        .await?
+                        .reserved_state;
+                    let name = |public_key: &PublicKey| {
+                        reserved_state
+                            .query_name(public_key)
+                            .unwrap_or_else(|| public_key.to_string())
+                    };
+                    println!(
+                        "message {} in {}",
+                        propagation.message_hash, propagation.dms_key
+                    );
+                    for (public_key, timestamp) in &propagation.acknowledged {
+                        println!("acknowledged by {} at {timestamp}", name(public_key));
+                    }
+                    for public_key in &propagation.pending {
+                        println!("pending: {}", name(public_key));
+                    }
+                }
+                Commands::Poll(PollCommands::List) => {
+                    for tally in simperby_node.get_polls(&PageRequest::all()).await?.items {
+                        print_poll_tally(&tally);
+                        println!();
+                    }
+                }
+                Commands::Poll(PollCommands::Tally { poll }) => {
+                    print_poll_tally(&simperby_node.tally_poll(parse_hash(&poll)?).await?);
+                }
+                Commands::Create(CreateCommands::Block { dry_run }) => {
+                    if dry_run {
+                        print_semantic_commit(&simperby_node.preview_create_block().await?.1);
+                    } else {
+                        simperby_node.create_block().await?;
+                    }
+                }
+                Commands::Create(CreateCommands::Agenda {
+                    title,
+                    rationale,
+                    links,
+                    deadline,
+                    dry_run,
+                }) => {
+                    let description = title.map(|title| AgendaDescription {
+                        title,
+                        rationale,
+                        links,
+                    });
+                    let deadline = deadline.map(VotingDeadline::Timestamp);
+                    if dry_run {
+                        print_semantic_commit(
+                            &simperby_node
+                                .preview_create_agenda(description, deadline)
+                                .await?
+                                .1,
+                        );
+                    } else {
+                        simperby_node.create_agenda(description, deadline).await?;
+                    }
+                }
+                Commands::Vote { revision, dry_run } => {
+                    let commit_hash = simperby_node
+                        .get_raw_repo()
+                        .read()
+                        .await
+                        .retrieve_commit_hash(revision)
+                        .await?;
+                    if dry_run {
+                        println!(
+                            "{}",
+                            serde_spb::to_string(&simperby_node.preview_vote(commit_hash).await?)?
+                        );
+                    } else {
+                        simperby_node.vote(commit_hash).await?;
+                    }
+                }
+                Commands::ExportVote { revision } => {
+                    let commit_hash = simperby_node
+                        .get_raw_repo()
+                        .read()
+                        .await
+                        .retrieve_commit_hash(revision)
+                        .await?;
+                    println!(
+                        "{}",
+                        hex::encode(simperby_node.export_vote_payload(commit_hash).await?)
+                    );
                 }
-                Commands::Create(CreateCommands::Agenda) => {
-                    simperby_node.create_agenda().await?;
+                Commands::ImportVote {
+                    revision,
+                    signer,
+                    signature,
+                } => {
+                    let commit_hash = simperby_node
+                        .get_raw_repo()
+                        .read()
+                        .await
+                        .retrieve_commit_hash(revision)
+                        .await?;
+                    let signer = PublicKey::from_array(
+                        hex::decode(signer)?
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| eyre!("a public key must be in 33 bytes"))?,
+                    )
+                    .map_err(|_| eyre!("invalid public key"))?;
+                    let signature = Signature::from_array(
+                        hex::decode(signature)?
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| eyre!("a signature must be in 65 bytes"))?,
+                    );
+                    simperby_node
+                        .import_signed_vote(commit_hash, signer, signature)
+                        .await?;
+                }
+                Commands::ExportAgendaBundle { revision } => {
+                    let commit_hash = simperby_node
+                        .get_raw_repo()
+                        .read()
+                        .await
+                        .retrieve_commit_hash(revision)
+                        .await?;
+                    println!(
+                        "{}",
+                        serde_spb::to_string(
+                            &simperby_node.export_agenda_bundle(commit_hash).await?
+                        )?
+                    );
+                }
+                Commands::ImportAgendaReview { review } => {
+                    let review: AgendaReview =
+                        serde_spb::from_str(&review).map_err(|_| eyre!("invalid agenda review"))?;
+                    simperby_node.import_agenda_review(review).await?;
                 }
-                Commands::Vote { revision } => {
+                Commands::VetoAgenda {
+                    revision,
+                    duration_ms,
+                } => {
                     let commit_hash = simperby_node
                         .get_raw_repo()
+                        .read()
+                        .await
                         .retrieve_commit_hash(revision)
                         .await?;
-                    simperby_node.vote(commit_hash).await?;
+                    simperby_node.veto_agenda(commit_hash, duration_ms).await?;
                 }
                 Commands::Veto { revision } => {
-                    if revision.is_none() {
-                        simperby_node.veto_round().await?;
-                    } else {
+                    if let Some(revision) = revision {
                         let commit_hash = simperby_node
                             .get_raw_repo()
-                            .retrieve_commit_hash(revision.expect("has been checked to be Some"))
+                            .read()
+                            .await
+                            .retrieve_commit_hash(revision)
                             .await?;
                         simperby_node.veto_block(commit_hash).await?;
+                    } else {
+                        simperby_node.veto_round().await?;
                     }
                 }
-                Commands::Consensus { show } => {
-                    if show {
-                        // TODO: show the status of the consensus instead of making a progress.
+                Commands::Consensus {
+                    show,
+                    override_watermark,
+                } => {
+                    if override_watermark {
+                        let Some(watermark) = simperby_node.get_sign_watermark().await? else {
+                            println!("no sign watermark");
+                            return Ok(());
+                        };
+                        println!(
+                            "the watermark is at height {}, round {}, {:?}",
+                            watermark.height, watermark.round, watermark.step
+                        );
+                        print!(
+                            "overriding it double-signs if the refused messages were signed; \
+                             type `override` to confirm: "
+                        );
+                        std::io::stdout().flush()?;
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        if answer.trim() != "override" {
+                            return Err(eyre!("not confirmed"));
+                        }
+                        simperby_node.override_sign_watermark().await?;
+                        println!("the watermark has been removed");
+                    } else if show {
+                        let status = simperby_node.get_consensus_status().await?;
+                        let reserved_state = simperby_node
+                            .get_last_finalization_info()
+                            .await?
+                            .reserved_state;
+                        if status.pending_vetoes.is_empty() {
+                            println!("no pending vetoes");
+                        }
+                        for veto in &status.pending_vetoes {
+                            print_pending_veto(veto, &reserved_state);
+                        }
+                    } else {
+                        println!("{}", simperby_node.progress_for_consensus().await?);
+                    }
+                }
+                Commands::Verify { from } => {
+                    let report = simperby_node.verify_finalized_history(from).await?;
+                    println!(
+                        "verified heights {} to {} of {} ({} commits) in {}ms",
+                        report.from_height,
+                        report.verified_height,
+                        report.last_finalized_height,
+                        report.commits,
+                        report.elapsed_ms
+                    );
+                    if let Some(failure) = report.failure {
+                        return Err(eyre!(
+                            "verification failed above height {}: {failure}",
+                            report.verified_height
+                        ));
+                    }
+                }
+                Commands::Index { from } => match simperby_node.backfill_index(from).await? {
+                    Some(height) => println!("indexed heights {from} to {height}"),
+                    None => println!("nothing to index"),
+                },
+                Commands::VerifyCommits => {
+                    let mut invalid = 0;
+                    for report in simperby_node
+                        .verify_commit_signatures(&PageRequest::all())
+                        .await?
+                        .items
+                    {
+                        let kind = match report.commit {
+                            Commit::Block(_) => "block",
+                            Commit::Transaction(_) => "transaction",
+                            Commit::Agenda(_) => "agenda",
+                            Commit::AgendaProof(_) => "agenda-proof",
+                            Commit::ExtraAgendaTransaction(_) => "extra-agenda-transaction",
+                            Commit::ChatLog(_) => "chat-log",
+                        };
+                        for status in [&report.in_commit, &report.git] {
+                            if let SignatureStatus::Invalid(_) = status {
+                                invalid += 1;
+                            }
+                        }
+                        println!(
+                            "{} {kind} in-commit: {} git: {}",
+                            report.commit_hash,
+                            format_signature_status(&report.in_commit),
+                            format_signature_status(&report.git)
+                        );
+                    }
+                    if invalid > 0 {
+                        return Err(eyre!("{invalid} invalid signature(s)"));
+                    }
+                }
+                Commands::Stats { blocks } => {
+                    let stats = simperby_node.get_chain_stats(blocks).await?;
+                    let reserved_state = simperby_node
+                        .get_last_finalization_info()
+                        .await?
+                        .reserved_state;
+                    println!("blocks {} to {}", stats.from_height, stats.to_height);
+                    match stats.average_block_interval_ms {
+                        Some(interval) => println!("average block interval: {interval:.0}ms"),
+                        None => println!("average block interval: -"),
+                    }
+                    println!("agendas per block: {:.2}", stats.agendas_per_block);
+                    for validator in &stats.validators {
+                        let name = reserved_state
+                            .query_name(&validator.public_key)
+                            .unwrap_or_else(|| validator.public_key.to_string());
+                        println!(
+                            "{name} participation: {:.1}% ({}/{}) proposed: {} (expected {})",
+                            validator.participation_rate() * 100.0,
+                            validator.precommits,
+                            validator.eligible_blocks,
+                            validator.proposed_blocks,
+                            validator.expected_proposals
+                        );
+                    }
+                    for report in simperby_node.get_offline_reports() {
+                        println!(
+                            "offline report drafted: {} missed {}/{} precommits (blocks {} to {})",
+                            report.offender,
+                            report.missed_precommits,
+                            report.eligible_blocks,
+                            report.from_height,
+                            report.to_height
+                        );
+                    }
+                }
+                Commands::AuditLog(AuditLogCommands::Export { from, to, csv }) => {
+                    let to = match to {
+                        Some(to) => to,
+                        None => {
+                            simperby_node
+                                .get_last_finalization_info()
+                                .await?
+                                .header
+                                .height
+                        }
+                    };
+                    let log = simperby_node.export_audit_log(from, to).await?;
+                    if csv {
+                        print!("{}", log.to_csv());
                     } else {
-                        simperby_node.progress_for_consensus().await?;
+                        print!("{}", log.to_jsonl()?);
+                    }
+                }
+                Commands::AuditLog(AuditLogCommands::Verify { path }) => {
+                    let log = AuditLog::from_jsonl(&std::fs::read_to_string(path)?)?;
+                    match simperby_node.verify_audit_log(&log).await? {
+                        Ok(()) => println!(
+                            "heights {} to {}: complete, sealed by {}",
+                            log.seal.from, log.seal.to, log.seal.exporter
+                        ),
+                        Err(e) => return Err(eyre!("invalid audit log: {e}")),
+                    }
+                }
+                Commands::Forks => {
+                    let forks = simperby_node.get_fork_evidence();
+                    if forks.is_empty() {
+                        println!("no fork observed");
+                    }
+                    for evidence in forks {
+                        println!(
+                            "fork at height {}: {} and {}",
+                            evidence.height(),
+                            evidence.first.to_hash256(),
+                            evidence.second.to_hash256()
+                        );
+                        println!("{}", serde_spb::to_string(evidence)?);
                     }
                 }
-                Commands::Update => {
-                    simperby_node.fetch().await?;
+                Commands::Debug(DebugCommands::Rounds { height }) => {
+                    let height = match height {
+                        Some(height) => height,
+                        None => {
+                            simperby_node
+                                .get_last_finalization_info()
+                                .await?
+                                .header
+                                .height
+                                + 1
+                        }
+                    };
+                    let history = simperby_node
+                        .get_round_history(height)
+                        .await?
+                        .ok_or_else(|| eyre!("no round history for height {height}"))?;
+                    for record in history.rounds {
+                        println!(
+                            "round {} proposer: {} proposal: {} outcome: {:?}",
+                            record.round,
+                            record.proposer,
+                            format_block_hash(record.proposal),
+                            record.outcome
+                        );
+                        for vote in record.votes {
+                            println!(
+                                "  {:?} {} by {} at {}",
+                                vote.kind,
+                                format_block_hash(vote.block_hash),
+                                vote.signer,
+                                vote.timestamp
+                            );
+                        }
+                        for (step, timestamp) in record.timeouts {
+                            println!("  {step:?} timeout at {timestamp}");
+                        }
+                    }
+                }
+                Commands::Update { no_network } => {
+                    let progress_bar = spawn_progress_bar(simperby_node.subscribe_progress());
+                    let result = if no_network {
+                        simperby_node.update().await
+                    } else {
+                        simperby_node.fetch().await
+                    };
+                    finish_progress_bar(progress_bar).await;
+                    result?;
                 }
                 Commands::Broadcast => {
                     simperby_node.broadcast().await?;
                 }
+                Commands::Peer(PeerCommand::Add {
+                    address,
+                    name,
+                    relay,
+                }) => {
+                    let address = address.parse().map_err(|e| eyre!("invalid address: {e}"))?;
+                    simperby_node.add_peer(name, address, relay).await?;
+                }
+                Commands::Peer(PeerCommand::Remove { name }) => {
+                    simperby_node.remove_peer(&name).await?;
+                }
+                Commands::Peer(PeerCommand::List) => {
+                    let now = get_timestamp();
+                    for status in simperby_node.list_peers(&PageRequest::all()).await?.items {
+                        let last_seen = match status.last_seen {
+                            Some(timestamp) => format!("{}s ago", (now - timestamp) / 1000),
+                            None => "never".to_owned(),
+                        };
+                        println!(
+                            "{} {} {}{}{}",
+                            status.peer.name,
+                            status.peer.address,
+                            last_seen,
+                            if status.peer.relay.is_some() {
+                                " (relayed)"
+                            } else {
+                                ""
+                            },
+                            if status.managed { "" } else { " (config)" }
+                        );
+                    }
+                }
+                Commands::Peer(PeerCommand::Ping { name }) => {
+                    let report = simperby_node.ping_peer(&name).await?;
+                    println!(
+                        "{name}: {}ms, protocol version {}, key {}",
+                        report.latency_ms,
+                        report.protocol_version,
+                        if report.key_verified {
+                            "verified"
+                        } else {
+                            "NOT verified"
+                        }
+                    );
+                }
                 _ => unimplemented!("not implemented yet"),
             }
         }
@@ -212,8 +1026,47 @@ async fn main() -> eyre::Result<()> {
 
     let args = cli::Cli::parse();
     let path = args.path.display().to_string();
-    let config: Config =
-        serde_spb::from_str(&tokio::fs::read_to_string(&format!("{path}/config.json")).await?)?;
+    if let Commands::Migrate { dry_run } = args.command {
+        let migrations = migrations::migrate(&path, dry_run)?;
+        if migrations.is_empty() {
+            println!("already up to date");
+        }
+        for migration in migrations {
+            println!(
+                "{} version {}: {}",
+                if dry_run { "pending" } else { "applied" },
+                migration.version,
+                migration.description
+            );
+        }
+        return Ok(());
+    }
+    if let Commands::Init {
+        chain_name,
+        name,
+        members,
+        genesis_signatures,
+        non_interactive,
+    } = args.command
+    {
+        return simperby_cli::init::run(
+            &path,
+            chain_name,
+            name,
+            members,
+            genesis_signatures,
+            non_interactive,
+        )
+        .await;
+    }
+    // The config schema is subject to migration, so it must be done first.
+    migrations::migrate(&path, false)?;
+    let overrides = args
+        .config_overrides
+        .iter()
+        .map(|flag| config_layers::parse_flag(flag))
+        .collect::<Result<Vec<_>>>()?;
+    let config = config_layers::load(&path, &overrides).await?;
 
     if let Err(e) = run(args, path, config).await {
         if let Ok(_err) = e.downcast::<simperby_node::simperby_repository::IntegrityError>() {
@@ -235,6 +1088,8 @@ async fn show(config: Config, path: &str, revision_selection: String) -> Result<
     let node = simperby_node::initialize(config, path).await?;
     let commit_hash = node
         .get_raw_repo()
+        .read()
+        .await
         .retrieve_commit_hash(revision_selection)
         .await?;
     let result = node.show(commit_hash).await?;
@@ -243,7 +1098,253 @@ async fn show(config: Config, path: &str, revision_selection: String) -> Result<
             println!("hash: {}", block_header.to_hash256());
             // TODO
         }
+        CommitInfo::Agenda { agenda, tally, .. } => {
+            println!("hash: {}", agenda.to_hash256());
+            if let Some(description) = &agenda.description {
+                println!("{}", description.title);
+                if !description.rationale.is_empty() {
+                    println!("\n{}\n", description.rationale);
+                }
+                for link in &description.links {
+                    println!("link: {link}");
+                }
+            }
+            for vote in &tally.votes {
+                println!("{} {} {:?}", vote.name, vote.voting_power, vote.status);
+            }
+            println!(
+                "for: {} against: {} absent: {}",
+                tally.for_power, tally.against_power, tally.absent_power
+            );
+            if tally.threshold_met {
+                println!("the agenda can be approved");
+            } else {
+                println!("needs votes from: {}", tally.additional_voters.join(", "));
+            }
+        }
+        CommitInfo::Transaction { transaction, .. } => {
+            println!("hash: {}", transaction.to_hash256());
+            println!("{}", transaction.head);
+            // The members are what a reserved transaction usually changes.
+            if let Diff::Reserved(reserved_state) = &transaction.diff {
+                for member in &reserved_state.members {
+                    print_member(member);
+                }
+            }
+        }
         _ => todo!(),
     }
     Ok(())
 }
+
+fn print_semantic_commit(commit: &SemanticCommit) {
+    println!("{}\n\n{}", commit.title, commit.body);
+}
+
+fn print_pending_veto(veto: &PendingVeto, reserved_state: &ReservedState) {
+    let target = match &veto.target {
+        VetoTarget::Round(round) => format!("round {round}"),
+        VetoTarget::Block(block_hash) => format!("block {block_hash}"),
+    };
+    let expiry = match &veto.expiry {
+        VetoExpiry::EndOfRound(round) => format!("until round {round} ends"),
+        VetoExpiry::EndOfHeight(height) => format!("until height {height} is finalized"),
+    };
+    println!(
+        "veto on {target}: {}/{} ({}), {expiry}",
+        veto.voting_power,
+        veto.total_voting_power,
+        if veto.threshold_met {
+            "threshold met"
+        } else {
+            "below threshold"
+        }
+    );
+    for (public_key, voting_power) in &veto.vetoers {
+        let name = reserved_state
+            .query_name(public_key)
+            .unwrap_or_else(|| public_key.to_string());
+        println!("  {name}: {voting_power}");
+    }
+}
+
+fn print_member(member: &Member) {
+    println!(
+        "{} {} {}",
+        member.name, member.public_key, member.consensus_voting_power
+    );
+    for (key, value) in &member.metadata {
+        println!("  {key}: {value}");
+    }
+}
+
+fn print_poll_tally(tally: &PollTally) {
+    println!("{} {}", tally.poll_hash, tally.poll.title);
+    if !tally.poll.description.is_empty() {
+        println!("{}", tally.poll.description);
+    }
+    println!(
+        "deadline: {}{}",
+        tally.poll.deadline,
+        if tally.closed { " (closed)" } else { "" }
+    );
+    for (i, option) in tally.options.iter().enumerate() {
+        println!(
+            "[{i}] {} {} {}",
+            option.option,
+            option.voting_power,
+            option.voters.join(", ")
+        );
+    }
+    println!("absent: {}", tally.absent_power);
+    if !tally.conflicting_voters.is_empty() {
+        println!(
+            "not counted for voting more than once: {}",
+            tally.conflicting_voters.join(", ")
+        );
+    }
+}
+
+fn print_agenda_bundle(bundle: &AgendaBundle) {
+    let content = &bundle.content;
+    println!("chain: {}", content.chain_name);
+    println!(
+        "agenda {} ({}) at height {} by {}",
+        content.agenda_hash, content.agenda_commit, content.agenda.height, content.agenda.author
+    );
+    println!("exported by: {}", bundle.exporter());
+    if let Some(deadline) = &content.agenda.deadline {
+        println!("deadline: {deadline:?}");
+    }
+    let implications = &content.implications;
+    if implications.is_empty() {
+        println!("reserved state: unchanged");
+    } else {
+        println!(
+            "reserved state: {} -> {}",
+            implications.before_hash, implications.after_hash
+        );
+        println!("  added: {}", implications.added_members.join(", "));
+        println!("  removed: {}", implications.removed_members.join(", "));
+        println!("  changed: {}", implications.changed_members.join(", "));
+        if let Some((before, after)) = &implications.version_change {
+            println!("  version: {before} -> {after}");
+        }
+        if implications.other_changes {
+            println!("  and other fields");
+        }
+    }
+    for commit in &content.commits {
+        let title = match &commit.commit {
+            Commit::Transaction(transaction) => transaction.head.as_str(),
+            Commit::Agenda(_) => "(agenda)",
+            Commit::ExtraAgendaTransaction(_) => "(extra-agenda transaction)",
+            _ => "(other)",
+        };
+        println!("\n{} {title}", commit.commit_hash);
+        print!("{}", commit.patch);
+    }
+}
+
+fn print_replay_step(step: &ReplayStep) {
+    let event = match &step.event {
+        ReplayEvent::Start => "start".to_owned(),
+        ReplayEvent::Delivered { message, signer } => format!("{message:?} from {signer}"),
+        ReplayEvent::Rejected {
+            message,
+            signer,
+            reason,
+        } => format!("REJECTED {message:?} from {signer}: {reason}"),
+        ReplayEvent::Tick => "tick".to_owned(),
+    };
+    println!("[{}ms] round {}: {event}", step.timestamp, step.round);
+    for result in &step.results {
+        println!("  -> {result:?}");
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<Hash256> {
+    Ok(Hash256::from_array(
+        hex::decode(hash)?
+            .as_slice()
+            .try_into()
+            .map_err(|_| eyre!("a hash must be in 32 bytes"))?,
+    ))
+}
+
+fn format_block_hash(block_hash: Option<Hash256>) -> String {
+    block_hash
+        .map(|hash| hash.to_string())
+        .unwrap_or_else(|| "nil".to_owned())
+}
+
+fn format_signature_status(status: &SignatureStatus) -> String {
+    match status {
+        SignatureStatus::Verified(signer) => format!("verified ({signer})"),
+        SignatureStatus::Unsigned => "unsigned".to_owned(),
+        SignatureStatus::Invalid(e) => format!("invalid ({e})"),
+    }
+}
+
+/// Draws the progress on a single line of the stderr, until [`finish_progress_bar`].
+fn spawn_progress_bar(
+    mut progress: watch::Receiver<Option<Progress>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while progress.changed().await.is_ok() {
+            if let Some(progress) = &*progress.borrow() {
+                eprint!("\r\x1b[2K{}", format_progress(progress));
+                let _ = std::io::stderr().flush();
+            }
+        }
+    })
+}
+
+async fn finish_progress_bar(progress_bar: tokio::task::JoinHandle<()>) {
+    progress_bar.abort();
+    let _ = progress_bar.await;
+    eprintln!();
+}
+
+fn format_progress(progress: &Progress) -> String {
+    const WIDTH: u64 = 30;
+    let (label, done, total, detail, eta_ms) = match progress {
+        Progress::Fetching {
+            remote,
+            received_objects,
+            total_objects,
+            received_bytes,
+            eta_ms,
+        } => (
+            format!("fetching {remote}"),
+            *received_objects,
+            *total_objects,
+            format!(
+                "{received_objects}/{total_objects} objects, {:.1} MiB",
+                *received_bytes as f64 / (1024.0 * 1024.0)
+            ),
+            *eta_ms,
+        ),
+        Progress::Verifying {
+            verified_commits,
+            total_commits,
+            height,
+            eta_ms,
+        } => (
+            "verifying".to_owned(),
+            *verified_commits,
+            *total_commits,
+            format!("{verified_commits}/{total_commits} commits, height {height}"),
+            *eta_ms,
+        ),
+    };
+    let filled = (done * WIDTH).checked_div(total).unwrap_or(0).min(WIDTH);
+    let eta = eta_ms
+        .map(|eta_ms| format!(", ETA {}s", eta_ms / 1000))
+        .unwrap_or_default();
+    format!(
+        "{label} [{}{}] {detail}{eta}",
+        "#".repeat(filled as usize),
+        ".".repeat((WIDTH - filled) as usize)
+    )
+}
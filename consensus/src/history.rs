@@ -0,0 +1,113 @@
+//! The log of the consensus rounds, kept for the post-mortem analysis of liveness failures.
+use serde::{Deserialize, Serialize};
+use simperby_core::*;
+use vetomint::TimeoutStep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+/// A vote in a round, either received from a validator or cast by this node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedVote {
+    pub signer: PublicKey,
+    pub kind: VoteKind,
+    /// `None` for a nil vote.
+    pub block_hash: Option<Hash256>,
+    /// The local time when the vote was accepted.
+    pub timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundOutcome {
+    /// The round is still in progress.
+    InProgress,
+    /// The block was finalized in this round.
+    Finalized(Hash256),
+    /// The consensus moved on to a later round without finalizing.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundRecord {
+    pub round: ConsensusRound,
+    pub proposer: PublicKey,
+    /// The block hash of the first proposal seen in this round.
+    pub proposal: Option<Hash256>,
+    pub votes: Vec<RecordedVote>,
+    /// The timeouts fired in this round, with the local time.
+    ///
+    /// They are inferred from the nil votes and the round changes that the timer triggered.
+    pub timeouts: Vec<(TimeoutStep, Timestamp)>,
+    pub outcome: RoundOutcome,
+}
+
+/// The rounds of a single height, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RoundHistory {
+    /// The height of the block being decided.
+    pub height: BlockHeight,
+    pub rounds: Vec<RoundRecord>,
+}
+
+impl RoundHistory {
+    pub fn new(height: BlockHeight) -> Self {
+        Self {
+            height,
+            rounds: Vec::new(),
+        }
+    }
+
+    pub fn get_round(&self, round: ConsensusRound) -> Option<&RoundRecord> {
+        self.rounds.iter().find(|r| r.round == round)
+    }
+
+    /// Returns the record of the round, creating it if absent.
+    ///
+    /// A record may be created before the round starts, for the messages from the validators ahead.
+    pub fn round_mut(&mut self, round: ConsensusRound, proposer: PublicKey) -> &mut RoundRecord {
+        let index = match self.rounds.binary_search_by_key(&round, |r| r.round) {
+            Ok(index) => index,
+            Err(index) => {
+                self.rounds.insert(
+                    index,
+                    RoundRecord {
+                        round,
+                        proposer,
+                        proposal: None,
+                        votes: Vec::new(),
+                        timeouts: Vec::new(),
+                        outcome: RoundOutcome::InProgress,
+                    },
+                );
+                index
+            }
+        };
+        &mut self.rounds[index]
+    }
+
+    /// Starts the round, marking the earlier unfinished rounds as failed.
+    pub fn enter_round(&mut self, round: ConsensusRound, proposer: PublicKey) {
+        self.round_mut(round, proposer);
+        for record in self.rounds.iter_mut() {
+            if record.round < round && record.outcome == RoundOutcome::InProgress {
+                record.outcome = RoundOutcome::Failed;
+            }
+        }
+    }
+}
+
+impl RoundRecord {
+    /// Adds the vote, ignoring the duplicates of the same signer and kind.
+    pub fn add_vote(&mut self, vote: RecordedVote) {
+        if !self
+            .votes
+            .iter()
+            .any(|v| v.signer == vote.signer && v.kind == vote.kind)
+        {
+            self.votes.push(vote);
+        }
+    }
+}
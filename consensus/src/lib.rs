@@ -1,20 +1,62 @@
+mod history;
+mod replay;
+mod snapshot;
 mod state;
+mod veto;
+mod watermark;
 
 use eyre::eyre;
+pub use history::*;
+pub use replay::{read_state_block_header, replay, ReplayConfig, ReplayEvent, ReplayStep};
 use serde::{Deserialize, Serialize};
 use simperby_core::utils::get_timestamp;
 use simperby_core::*;
 use simperby_network::*;
+pub use snapshot::{read_latest_snapshot, read_pruning_metrics, ConsensusSnapshot, PruningMetrics};
+pub use state::ConsensusMessage;
 use state::*;
 use std::collections::BTreeSet;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+pub use veto::*;
+pub use watermark::{read_sign_watermark, SignStep, SignWatermark};
 
 pub type Error = eyre::Error;
 
-pub use vetomint::ConsensusParams;
+pub use vetomint::{ConsensusParams, TimeoutStep};
 
 const STATE_FILE_NAME: &str = "state.json";
+const ROUND_HISTORY_FILE_PREFIX: &str = "round-history-";
+/// The number of heights to keep the round history of, by default.
+///
+/// Only the [snapshot](ConsensusSnapshot) of the latest finalized height is kept regardless.
+pub const DEFAULT_ROUND_HISTORY_HEIGHTS: usize = 100;
+
+/// Generates the DMS key for the consensus of the height next to the given block.
+pub fn generate_dms_key(last_finalized_header: &BlockHeader) -> DmsKey {
+    format!("consensus-{}", last_finalized_header.to_hash256())
+}
+
+/// Returns the proposer of the given round, deciding the block next to the header.
+pub fn get_proposer(
+    block_header: &BlockHeader,
+    params: &ConsensusParams,
+    round: ConsensusRound,
+) -> PublicKey {
+    let height_info = vetomint::HeightInfo {
+        validators: block_header
+            .validator_set
+            .iter()
+            .map(|(_, power)| *power)
+            .collect(),
+        this_node_index: None,
+        timestamp: 0,
+        consensus_params: params.clone(),
+        initial_block_candidate: 0,
+    };
+    let index = vetomint::decide_proposer(round as usize, &height_info);
+    block_header.validator_set[index].0.clone()
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProgressResult {
@@ -33,6 +75,16 @@ pub struct Consensus {
     dms: Arc<RwLock<Dms<ConsensusMessage>>>,
     /// The local storage for the consensus state.
     state_storage: StorageImpl,
+    /// The local storage for the round history of each height, with the number of heights to keep,
+    /// and for the snapshot of the last finalized height.
+    ///
+    /// Unlike the state, it survives across the heights.
+    history_storage: Option<(StorageImpl, usize)>,
+    /// The local storage for the [`SignWatermark`], which survives across the heights.
+    watermark_storage: Option<StorageImpl>,
+    this_node: Option<PublicKey>,
+    /// The validators banned by the governance, whose votes are rejected.
+    banned_validators: BTreeSet<PublicKey>,
 }
 
 impl Consensus {
@@ -48,7 +100,14 @@ impl Consensus {
         round_zero_timestamp: Timestamp,
         this_node_key: Option<PrivateKey>,
     ) -> Result<Self, Error> {
-        let mut this = Self { dms, state_storage };
+        let mut this = Self {
+            dms,
+            state_storage,
+            history_storage: None,
+            watermark_storage: None,
+            this_node: this_node_key.as_ref().map(PrivateKey::public_key),
+            banned_validators: BTreeSet::new(),
+        };
         // Prepare new state in case of storage reset.
         let new_state = State::new(
             &block_header,
@@ -90,12 +149,24 @@ impl Consensus {
         Ok(state.block_header().clone())
     }
 
+    /// Returns the round in progress.
+    pub async fn get_round(&self) -> Result<ConsensusRound, Error> {
+        let state = self.read_state().await?;
+        Ok(state.round())
+    }
+
     /// Checks whether the consensus is finalized.
     pub async fn check_finalized(&self) -> Result<Option<FinalizationProof>, Error> {
         let state = self.read_state().await?;
         Ok(state.check_finalized())
     }
 
+    /// Returns the vetoes on the rounds in progress and on the blocks proposed at this height.
+    pub async fn get_pending_vetoes(&self) -> Result<Vec<PendingVeto>, Error> {
+        let state = self.read_state().await?;
+        Ok(state.pending_vetoes())
+    }
+
     pub async fn register_verified_block_hash(&mut self, block_hash: Hash256) -> Result<(), Error> {
         let mut state = self.read_state().await?;
         state.register_verified_block_hash(block_hash);
@@ -108,6 +179,16 @@ impl Consensus {
         let mut state = self.read_state().await?;
         let result = state.progress(timestamp);
         self.commit_state(&state).await?;
+        for x in &result {
+            if let ProgressResult::Finalized(block_hash, _, proof) = x {
+                self.commit_snapshot(ConsensusSnapshot::new(
+                    state.round_history(),
+                    *block_hash,
+                    proof.clone(),
+                ))
+                .await?;
+            }
+        }
         Ok(result)
     }
 
@@ -140,22 +221,57 @@ impl Consensus {
         Ok(())
     }
 
+    /// Returns the messages by this node that `flush()` broadcasts.
+    pub async fn messages_to_broadcast(&self) -> Result<Vec<ConsensusMessage>, Error> {
+        let mut state = self.read_state().await?;
+        Ok(state.drain_messages_to_broadcast())
+    }
+
     pub async fn flush(&mut self) -> Result<(), Error> {
+        // The state that produced the votes must survive a crash before they leave the node;
+        // otherwise the node could vote differently in the same round after a restart.
+        self.state_storage.checkpoint().await?;
         // TODO: filter unverified messages (due to the lack of the block verification)
-        let mut state = self.read_state().await?;
-        let messages = state.drain_messages_to_broadcast();
+        let messages = self.messages_to_broadcast().await?;
+        let height = self.read_state().await?.block_header().height + 1;
+        let mut dms = self.dms.write().await;
         for message in messages {
-            self.dms.write().await.commit_message(&message).await?;
+            if let (Some(storage), Some(this_node)) = (&mut self.watermark_storage, &self.this_node)
+            {
+                let signed = dms
+                    .query_message(message.to_hash256())
+                    .await?
+                    .is_some_and(|m| m.committers.iter().any(|c| &c.committer == this_node));
+                if !signed {
+                    let next = SignWatermark::new(height, &message);
+                    if let Some(watermark) = read_sign_watermark(storage).await? {
+                        watermark.check(&next)?;
+                    }
+                    watermark::write_sign_watermark(storage, &next).await?;
+                }
+            }
+            dms.commit_message(&message).await?;
         }
         Ok(())
     }
 
+    /// Sets the validators banned by the governance (see [`ReservedState::banned_members`]).
+    ///
+    /// Their votes are rejected from the next `update()`, even if the DMS has stored them.
+    /// They still count in the voting power of the height, as the block header fixes it.
+    pub fn set_banned_validators(&mut self, validators: Vec<PublicKey>) {
+        self.banned_validators = validators.into_iter().collect();
+    }
+
     pub async fn update(&mut self) -> Result<(), Error> {
         let mut state = self.read_state().await?;
         let messages = self.dms.read().await.read_messages().await?;
         let mut result = Vec::new();
         for message in messages {
             for commitment in message.committers {
+                if self.banned_validators.contains(&commitment.committer) {
+                    continue;
+                }
                 result.push((
                     message.message.clone(),
                     commitment.committer,
@@ -167,6 +283,88 @@ impl Consensus {
         self.commit_state(&state).await?;
         Ok(())
     }
+
+    pub fn get_dms(&self) -> Arc<RwLock<Dms<ConsensusMessage>>> {
+        Arc::clone(&self.dms)
+    }
+
+    /// Sets the storage to persist the round history in,
+    /// keeping only that of the last `retained_heights` heights.
+    pub async fn set_history_storage(
+        &mut self,
+        storage: StorageImpl,
+        retained_heights: usize,
+    ) -> Result<(), Error> {
+        self.history_storage = Some((storage, retained_heights));
+        let state = self.read_state().await?;
+        self.commit_round_history(state.round_history()).await
+    }
+
+    /// Sets the storage to persist the [`SignWatermark`] in,
+    /// which guards `flush()` against signing a message at or below it.
+    pub fn set_watermark_storage(&mut self, storage: StorageImpl) {
+        self.watermark_storage = Some(storage);
+    }
+
+    pub async fn get_sign_watermark(&self) -> Result<Option<SignWatermark>, Error> {
+        match &self.watermark_storage {
+            Some(storage) => read_sign_watermark(storage).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the sign watermark, returning it, so that the messages refused by it can be signed.
+    ///
+    /// It's an escape hatch for the operator who has made sure that the refused messages
+    /// weren't signed before, e.g., when the watermark was left by an aborted height.
+    pub async fn override_sign_watermark(&mut self) -> Result<Option<SignWatermark>, Error> {
+        let Some(storage) = &mut self.watermark_storage else {
+            return Ok(None);
+        };
+        let watermark = read_sign_watermark(storage).await?;
+        watermark::remove_sign_watermark(storage).await?;
+        Ok(watermark)
+    }
+
+    /// Reads the snapshot of the last finalized height in the history storage, if any.
+    pub async fn get_latest_snapshot(&self) -> Result<Option<ConsensusSnapshot>, Error> {
+        match &self.history_storage {
+            Some((storage, _)) => read_latest_snapshot(storage).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Returns what the pruning of the history storage has reclaimed so far.
+    pub async fn get_pruning_metrics(&self) -> Result<PruningMetrics, Error> {
+        match &self.history_storage {
+            Some((storage, _)) => read_pruning_metrics(storage).await,
+            None => Ok(PruningMetrics::default()),
+        }
+    }
+
+    /// Reads the log of the rounds of the given height (of the block being decided).
+    ///
+    /// Returns `None` if the height is neither the current one nor kept in the history storage.
+    pub async fn get_round_history(
+        &self,
+        height: BlockHeight,
+    ) -> Result<Option<RoundHistory>, Error> {
+        let state = self.read_state().await?;
+        if state.round_history().height == height {
+            return Ok(Some(state.round_history().clone()));
+        }
+        let storage = match &self.history_storage {
+            Some((storage, _)) => storage,
+            None => return Ok(None),
+        };
+        let file_name = format!("{ROUND_HISTORY_FILE_PREFIX}{height}.json");
+        if !storage.list_files().await?.contains(&file_name) {
+            return Ok(None);
+        }
+        Ok(Some(serde_spb::from_str(
+            &storage.read_file(&file_name).await?,
+        )?))
+    }
 }
 
 // Various private methods.
@@ -181,6 +379,331 @@ impl Consensus {
         self.state_storage
             .add_or_overwrite_file(STATE_FILE_NAME, serde_spb::to_string(state).unwrap())
             .await
-            .map_err(|_| eyre!("failed to commit consensus state to the storage"))
+            .map_err(|_| eyre!("failed to commit consensus state to the storage"))?;
+        self.commit_round_history(state.round_history()).await
+    }
+
+    /// Writes the round history of the current height, removing those of the oldest heights.
+    async fn commit_round_history(&mut self, history: &RoundHistory) -> Result<(), Error> {
+        let (storage, retained_heights) = match &mut self.history_storage {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        storage
+            .add_or_overwrite_file(
+                &format!("{ROUND_HISTORY_FILE_PREFIX}{}.json", history.height),
+                serde_spb::to_string(history).unwrap(),
+            )
+            .await
+            .map_err(|_| eyre!("failed to commit the round history to the storage"))?;
+        let outdated = snapshot::list_heights(storage, ROUND_HISTORY_FILE_PREFIX)
+            .await?
+            .into_iter()
+            .skip(*retained_heights)
+            .map(|height| format!("{ROUND_HISTORY_FILE_PREFIX}{height}.json"))
+            .collect::<Vec<_>>();
+        snapshot::remove_files(storage, &outdated).await
+    }
+
+    /// Writes the snapshot of the finalized height, removing the older ones.
+    async fn commit_snapshot(&mut self, snapshot: ConsensusSnapshot) -> Result<(), Error> {
+        let Some((storage, _)) = &mut self.history_storage else {
+            return Ok(());
+        };
+        storage
+            .add_or_overwrite_file(
+                &snapshot::snapshot_file_name(snapshot.height),
+                serde_spb::to_string(&snapshot).unwrap(),
+            )
+            .await
+            .map_err(|_| eyre!("failed to commit the snapshot to the storage"))?;
+        let outdated = snapshot::list_heights(storage, snapshot::SNAPSHOT_FILE_PREFIX)
+            .await?
+            .into_iter()
+            .skip(1)
+            .map(snapshot::snapshot_file_name)
+            .collect::<Vec<_>>();
+        snapshot::remove_files(storage, &outdated).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    async fn create_storage(path: &str) -> StorageImpl {
+        StorageImpl::create(path).await.unwrap();
+        StorageImpl::open(path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_history() {
+        setup_test();
+        let (public_key, private_key) = generate_keypair_random();
+        let history_path = create_temp_dir();
+        StorageImpl::create(&history_path).await.unwrap();
+        for height in 0..3 {
+            let header = BlockHeader {
+                author: public_key.clone(),
+                prev_block_finalization_proof: FinalizationProof::genesis(),
+                previous_hash: Hash256::zero(),
+                height,
+                timestamp: 0,
+                commit_merkle_root: Hash256::zero(),
+                repository_merkle_root: Hash256::zero(),
+                validator_set: vec![(public_key.clone(), 1)],
+                version: "0.0.0".to_owned(),
+            };
+            let dms = create_test_dms(
+                format!("consensus-{height}"),
+                vec![public_key.clone()],
+                private_key.clone(),
+            )
+            .await;
+            let mut consensus = Consensus::new(
+                Arc::new(RwLock::new(dms)),
+                create_storage(&create_temp_dir()).await,
+                header,
+                ConsensusParams::default(),
+                0,
+                Some(private_key.clone()),
+            )
+            .await
+            .unwrap();
+            consensus
+                .set_history_storage(StorageImpl::open(&history_path).await.unwrap(), 2)
+                .await
+                .unwrap();
+
+            let history = consensus
+                .get_round_history(height + 1)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(history.rounds.len(), 1);
+            assert_eq!(history.rounds[0].proposer, public_key);
+            assert_eq!(history.rounds[0].outcome, RoundOutcome::InProgress);
+            // The previous height is read from the history storage.
+            if height > 0 {
+                assert_eq!(
+                    consensus
+                        .get_round_history(height)
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .height,
+                    height
+                );
+            }
+            if height > 1 {
+                assert!(consensus
+                    .get_round_history(height - 1)
+                    .await
+                    .unwrap()
+                    .is_none());
+            }
+        }
+
+        // Only the last two heights are kept.
+        let history_storage = StorageImpl::open(&history_path).await.unwrap();
+        let mut files = history_storage.list_files().await.unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "pruning-metrics.json".to_owned(),
+                "round-history-2.json".to_owned(),
+                "round-history-3.json".to_owned()
+            ]
+        );
+    }
+
+    /// Delivers the messages of `from` to `to`, which share the DMS key.
+    async fn relay(from: &Consensus, to: &Consensus) {
+        let messages = from.get_dms().read().await.read_messages().await.unwrap();
+        let dms = to.get_dms();
+        let mut dms = dms.write().await;
+        for message in messages {
+            dms.add_committed_messages(&message.message, message.committers)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshots() {
+        setup_test();
+        let keys = (0..2)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        let members = keys.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        let history_path = create_temp_dir();
+        StorageImpl::create(&history_path).await.unwrap();
+        for height in 0..2 {
+            let header = BlockHeader {
+                author: members[0].clone(),
+                prev_block_finalization_proof: FinalizationProof::genesis(),
+                previous_hash: Hash256::zero(),
+                height,
+                timestamp: 0,
+                commit_merkle_root: Hash256::zero(),
+                repository_merkle_root: Hash256::zero(),
+                validator_set: members.iter().map(|key| (key.clone(), 1)).collect(),
+                version: "0.0.0".to_owned(),
+            };
+            let block_hash = Hash256::hash(format!("block-{height}"));
+            let mut nodes = Vec::new();
+            for (_, private_key) in &keys {
+                let dms = create_test_dms(
+                    format!("consensus-{height}"),
+                    members.clone(),
+                    private_key.clone(),
+                )
+                .await;
+                let mut consensus = Consensus::new(
+                    Arc::new(RwLock::new(dms)),
+                    create_storage(&create_temp_dir()).await,
+                    header.clone(),
+                    ConsensusParams::default(),
+                    0,
+                    Some(private_key.clone()),
+                )
+                .await
+                .unwrap();
+                consensus
+                    .register_verified_block_hash(block_hash)
+                    .await
+                    .unwrap();
+                consensus
+                    .set_proposal_candidate(block_hash, 0)
+                    .await
+                    .unwrap();
+                nodes.push(consensus);
+            }
+            // Only the first one keeps the history.
+            nodes[0]
+                .set_history_storage(StorageImpl::open(&history_path).await.unwrap(), 1)
+                .await
+                .unwrap();
+
+            for _ in 0..5 {
+                for node in nodes.iter_mut() {
+                    node.progress(0).await.unwrap();
+                    node.flush().await.unwrap();
+                }
+                relay(&nodes[0], &nodes[1]).await;
+                relay(&nodes[1], &nodes[0]).await;
+                for node in nodes.iter_mut() {
+                    node.update().await.unwrap();
+                }
+            }
+            let snapshot = nodes[0].get_latest_snapshot().await.unwrap().unwrap();
+            assert_eq!(snapshot.height, height + 1);
+            assert_eq!(snapshot.block_hash, block_hash);
+            assert_eq!(snapshot.proof.round, 0);
+            assert_eq!(snapshot.proof.signatures.len(), 2);
+            assert_eq!(snapshot.rounds, 1);
+        }
+
+        // The snapshot and the round history of the first height are pruned.
+        let history_storage = StorageImpl::open(&history_path).await.unwrap();
+        let mut files = history_storage.list_files().await.unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "pruning-metrics.json".to_owned(),
+                "round-history-2.json".to_owned(),
+                "snapshot-2.json".to_owned()
+            ]
+        );
+        let metrics = read_pruning_metrics(&history_storage).await.unwrap();
+        assert_eq!(metrics.removed_files, 2);
+        assert!(metrics.reclaimed_bytes > 0);
+        assert_eq!(
+            read_latest_snapshot(&history_storage)
+                .await
+                .unwrap()
+                .unwrap()
+                .height,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn sign_watermark() {
+        setup_test();
+        let keys = (0..2)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        let members = keys.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        let header = BlockHeader {
+            author: members[0].clone(),
+            prev_block_finalization_proof: FinalizationProof::genesis(),
+            previous_hash: Hash256::zero(),
+            height: 0,
+            timestamp: 0,
+            commit_merkle_root: Hash256::zero(),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: members.iter().map(|key| (key.clone(), 1)).collect(),
+            version: "0.0.0".to_owned(),
+        };
+        let block_hash = Hash256::hash("block");
+        let watermark_path = create_temp_dir();
+        StorageImpl::create(&watermark_path).await.unwrap();
+        // The proposer of the first round signs a proposal and a prevote.
+        let proposer = members
+            .iter()
+            .position(|key| *key == get_proposer(&header, &ConsensusParams::default(), 0))
+            .unwrap();
+        let open = |dms_name: String| {
+            let (members, header, private_key) =
+                (members.clone(), header.clone(), keys[proposer].1.clone());
+            let watermark_path = watermark_path.clone();
+            async move {
+                let dms = create_test_dms(dms_name, members, private_key.clone()).await;
+                let mut consensus = Consensus::new(
+                    Arc::new(RwLock::new(dms)),
+                    create_storage(&create_temp_dir()).await,
+                    header,
+                    ConsensusParams::default(),
+                    0,
+                    Some(private_key),
+                )
+                .await
+                .unwrap();
+                consensus.set_watermark_storage(StorageImpl::open(&watermark_path).await.unwrap());
+                consensus
+                    .register_verified_block_hash(block_hash)
+                    .await
+                    .unwrap();
+                consensus
+                    .set_proposal_candidate(block_hash, 0)
+                    .await
+                    .unwrap();
+                consensus
+            }
+        };
+
+        let mut consensus = open("consensus-0".to_owned()).await;
+        consensus.progress(0).await.unwrap();
+        consensus.flush().await.unwrap();
+        // Flushing the signed messages again is fine.
+        consensus.flush().await.unwrap();
+        let watermark = consensus.get_sign_watermark().await.unwrap().unwrap();
+        assert_eq!((watermark.height, watermark.round), (1, 0));
+        assert_eq!(watermark.step, SignStep::Prevote);
+
+        // The state and the DMS are lost, so the proposal would be signed again.
+        drop(consensus);
+        let mut consensus = open("consensus-1".to_owned()).await;
+        consensus.progress(0).await.unwrap();
+        let _ = consensus.flush().await.unwrap_err();
+        assert_eq!(
+            consensus.override_sign_watermark().await.unwrap(),
+            Some(watermark)
+        );
+        consensus.flush().await.unwrap();
     }
 }
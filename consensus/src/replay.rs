@@ -0,0 +1,376 @@
+//! Replaying the messages of a consensus DMS to reproduce a reported bug offline.
+//!
+//! The messages are fed one by one to a fresh state machine of one of the validators,
+//! as its node does with [`Consensus::update`] and [`Consensus::progress`],
+//! under a mock clock advancing by [`ReplayConfig::tick_ms`] for each message.
+//! The storage doesn't record when the messages arrived, so they're delivered in the order
+//! of the protocol: by the round, then the proposals, the prevotes and the precommits.
+//!
+//! The state machine makes the votes of the validator itself;
+//! comparing them with the ones recorded in the DMS shows where the node went astray.
+use super::*;
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// The last finalized block header, of which the messages decide the next block.
+    pub block_header: BlockHeader,
+    pub params: ConsensusParams,
+    /// The validator to replay as.
+    pub this_node: PublicKey,
+    /// How far the mock clock advances for each message, in milliseconds.
+    pub tick_ms: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    /// The state machine starts the round zero.
+    Start,
+    Delivered {
+        message: ConsensusMessage,
+        signer: PublicKey,
+    },
+    /// The message isn't delivered, for an invalid commitment or a signer not a validator.
+    Rejected {
+        message: ConsensusMessage,
+        signer: PublicKey,
+        reason: String,
+    },
+    /// The mock clock advances after the last message, firing the timeouts.
+    Tick,
+}
+
+/// A step of the replay, with the state transitions it made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayStep {
+    pub timestamp: Timestamp,
+    pub event: ReplayEvent,
+    /// The round that the state machine is in after the step.
+    pub round: ConsensusRound,
+    pub results: Vec<ProgressResult>,
+}
+
+impl ReplayStep {
+    pub fn is_finalized(&self) -> bool {
+        self.results
+            .iter()
+            .any(|result| matches!(result, ProgressResult::Finalized(..)))
+    }
+}
+
+/// Reads the block header that the consensus state in the storage is performing on,
+/// which is the one to replay the DMS of the same node on.
+pub async fn read_state_block_header(state_storage: &StorageImpl) -> Result<BlockHeader, Error> {
+    let state: State = serde_spb::from_str(&state_storage.read_file(STATE_FILE_NAME).await?)?;
+    Ok(state.block_header().clone())
+}
+
+fn delivery_order(message: &ConsensusMessage) -> (ConsensusRound, u8) {
+    match message {
+        ConsensusMessage::Proposal { round, .. } => (*round, 0),
+        ConsensusMessage::NonNilPreVoted(round, _) | ConsensusMessage::NilPreVoted(round) => {
+            (*round, 1)
+        }
+        ConsensusMessage::NonNilPreCommitted(round, ..)
+        | ConsensusMessage::NilPreCommitted(round) => (*round, 2),
+    }
+}
+
+fn block_hash(message: &ConsensusMessage) -> Option<Hash256> {
+    match message {
+        ConsensusMessage::Proposal { block_hash, .. }
+        | ConsensusMessage::NonNilPreVoted(_, block_hash)
+        | ConsensusMessage::NonNilPreCommitted(_, block_hash, _) => Some(*block_hash),
+        _ => None,
+    }
+}
+
+/// Replays the messages of the consensus DMS of the given key, returning the steps.
+///
+/// Every block that the messages refer to is taken as verified, as the storage has no blocks.
+/// After the messages, the clock keeps advancing until the block is finalized
+/// or all the timeouts of the round have passed. The replay stops at the finalization.
+pub fn replay(
+    config: &ReplayConfig,
+    dms_key: &DmsKey,
+    messages: Vec<Message<ConsensusMessage>>,
+) -> Result<Vec<ReplayStep>, Error> {
+    if config.tick_ms == 0 {
+        return Err(eyre!("the tick of the mock clock must be positive"));
+    }
+    let validator_set = &config.block_header.validator_set;
+    if !validator_set
+        .iter()
+        .any(|(key, _)| key == &config.this_node)
+    {
+        return Err(eyre!("{} is not one of the validators", config.this_node));
+    }
+    let mut state = State::new_as(
+        &config.block_header,
+        config.params.clone(),
+        0,
+        &config.this_node,
+    )?;
+    let mut deliveries = messages
+        .into_iter()
+        .flat_map(|message| {
+            message
+                .committers
+                .into_iter()
+                .map(move |commitment| (message.message.clone(), commitment))
+        })
+        .collect::<Vec<_>>();
+    deliveries.sort_by_key(|(message, commitment)| {
+        (
+            delivery_order(message),
+            commitment.committer.clone(),
+            message.to_hash256(),
+        )
+    });
+    for block_hash in deliveries
+        .iter()
+        .filter_map(|(message, _)| block_hash(message))
+        .collect::<BTreeSet<_>>()
+    {
+        state.register_verified_block_hash(block_hash);
+    }
+
+    let mut timestamp = 0;
+    let mut steps = vec![ReplayStep {
+        timestamp,
+        event: ReplayEvent::Start,
+        results: state.progress(timestamp),
+        round: state.round(),
+    }];
+    for (message, commitment) in deliveries {
+        if steps.iter().any(ReplayStep::is_finalized) {
+            return Ok(steps);
+        }
+        timestamp += config.tick_ms;
+        let signer = commitment.committer.clone();
+        let rejection = if !validator_set.iter().any(|(key, _)| key == &signer) {
+            Some("the signer is not a validator".to_owned())
+        } else if let Err(e) = message.verify_commitment(&commitment, dms_key) {
+            Some(format!("invalid commitment: {e}"))
+        } else {
+            None
+        };
+        let (event, results) = match rejection {
+            Some(reason) => (
+                ReplayEvent::Rejected {
+                    message,
+                    signer,
+                    reason,
+                },
+                Vec::new(),
+            ),
+            None => {
+                state.add_consensus_messages(
+                    vec![(message.clone(), signer.clone(), commitment.signature)],
+                    timestamp,
+                );
+                (
+                    ReplayEvent::Delivered { message, signer },
+                    state.progress(timestamp),
+                )
+            }
+        };
+        steps.push(ReplayStep {
+            timestamp,
+            event,
+            round: state.round(),
+            results,
+        });
+    }
+
+    let round = state.round();
+    let deadline = timestamp
+        + [
+            TimeoutStep::Propose,
+            TimeoutStep::Prevote,
+            TimeoutStep::Precommit,
+        ]
+        .into_iter()
+        .map(|step| vetomint::decide_timeout(&config.params, step, round as usize))
+        .sum::<Timestamp>();
+    while !steps.iter().any(ReplayStep::is_finalized) && timestamp < deadline {
+        timestamp += config.tick_ms;
+        let results = state.progress(timestamp);
+        if !results.is_empty() || state.round() != steps.last().unwrap().round {
+            steps.push(ReplayStep {
+                timestamp,
+                event: ReplayEvent::Tick,
+                round: state.round(),
+                results,
+            });
+        }
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_network::dms;
+    use simperby_test_suite::*;
+
+    #[tokio::test]
+    async fn replay_finalized_height() {
+        setup_test();
+        let keys = (0..2)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        let members = keys.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        let header = BlockHeader {
+            author: members[0].clone(),
+            prev_block_finalization_proof: FinalizationProof::genesis(),
+            previous_hash: Hash256::zero(),
+            height: 0,
+            timestamp: 0,
+            commit_merkle_root: Hash256::zero(),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: members.iter().map(|key| (key.clone(), 1)).collect(),
+            version: "0.0.0".to_owned(),
+        };
+        let block_hash = Hash256::hash("block");
+        let dms_config = dms::Config {
+            dms_key: "consensus-0".to_owned(),
+            members: members.clone(),
+            priority_weights: Default::default(),
+        };
+        let mut paths = Vec::new();
+        let mut nodes = Vec::new();
+        for (_, private_key) in &keys {
+            let (dms_path, state_path) = (create_temp_dir(), create_temp_dir());
+            StorageImpl::create(&dms_path).await.unwrap();
+            StorageImpl::create(&state_path).await.unwrap();
+            let dms = Dms::new(
+                StorageImpl::open(&dms_path).await.unwrap(),
+                dms_config.clone(),
+                private_key.clone(),
+            )
+            .await
+            .unwrap();
+            let mut consensus = Consensus::new(
+                Arc::new(RwLock::new(dms)),
+                StorageImpl::open(&state_path).await.unwrap(),
+                header.clone(),
+                ConsensusParams::default(),
+                0,
+                Some(private_key.clone()),
+            )
+            .await
+            .unwrap();
+            consensus
+                .register_verified_block_hash(block_hash)
+                .await
+                .unwrap();
+            consensus
+                .set_proposal_candidate(block_hash, 0)
+                .await
+                .unwrap();
+            paths.push((dms_path, state_path));
+            nodes.push(consensus);
+        }
+        for _ in 0..5 {
+            for node in nodes.iter_mut() {
+                node.progress(0).await.unwrap();
+                node.flush().await.unwrap();
+            }
+            for (from, to) in [(0, 1), (1, 0)] {
+                let messages = nodes[from]
+                    .get_dms()
+                    .read()
+                    .await
+                    .read_messages()
+                    .await
+                    .unwrap();
+                for message in messages {
+                    nodes[to]
+                        .get_dms()
+                        .write()
+                        .await
+                        .add_committed_messages(&message.message, message.committers)
+                        .await
+                        .unwrap();
+                }
+            }
+            for node in nodes.iter_mut() {
+                node.update().await.unwrap();
+            }
+        }
+        drop(nodes);
+
+        // The storages of the first node, as submitted by its operator.
+        let (dms_path, state_path) = &paths[0];
+        let (config, mut messages) = dms::read_stored_messages::<_, ConsensusMessage>(
+            &StorageImpl::open(dms_path).await.unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(config, dms_config);
+        let block_header = read_state_block_header(&StorageImpl::open(state_path).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(block_header, header);
+        // A message committed by someone else than the validators.
+        let (_, stranger) = generate_keypair_random();
+        let forged = ConsensusMessage::NilPreVoted(0);
+        messages.push(Message {
+            committers: vec![forged.commit(&config.dms_key, &stranger).unwrap()],
+            message: forged,
+        });
+
+        for (this_node, _) in &keys {
+            let steps = replay(
+                &ReplayConfig {
+                    block_header: block_header.clone(),
+                    params: ConsensusParams::default(),
+                    this_node: this_node.clone(),
+                    tick_ms: 100,
+                },
+                &config.dms_key,
+                messages.clone(),
+            )
+            .unwrap();
+            assert_eq!(steps[0].event, ReplayEvent::Start);
+            assert!(steps.iter().any(|step| matches!(
+                &step.event,
+                ReplayEvent::Rejected { message, .. } if *message == ConsensusMessage::NilPreVoted(0)
+            )));
+            let last = steps.last().unwrap();
+            assert!(last.is_finalized());
+            assert!(last.results.iter().any(
+                |result| matches!(result, ProgressResult::Finalized(hash, ..) if *hash == block_hash)
+            ));
+            // Deterministic under the mock clock.
+            assert_eq!(
+                replay(
+                    &ReplayConfig {
+                        block_header: block_header.clone(),
+                        params: ConsensusParams::default(),
+                        this_node: this_node.clone(),
+                        tick_ms: 100,
+                    },
+                    &config.dms_key,
+                    messages.clone(),
+                )
+                .unwrap(),
+                steps
+            );
+        }
+
+        // Not as one of the validators.
+        let (stranger, _) = generate_keypair_random();
+        assert!(replay(
+            &ReplayConfig {
+                block_header,
+                params: ConsensusParams::default(),
+                this_node: stranger,
+                tick_ms: 100,
+            },
+            &config.dms_key,
+            messages,
+        )
+        .is_err());
+    }
+}
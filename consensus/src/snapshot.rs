@@ -0,0 +1,125 @@
+//! Compact snapshots of the finalized consensus, kept in place of the per-round artifacts.
+//!
+//! When a height is finalized, what's left of its consensus is the block and the proof,
+//! which are written in a [`ConsensusSnapshot`] to the history storage.
+//! Only the latest snapshot is kept, and the round histories only of the recent heights.
+//! A node that crashed before handing the finalization over to the repository
+//! can restore it from the latest snapshot, even after the state is reset.
+use super::*;
+
+pub(super) const SNAPSHOT_FILE_PREFIX: &str = "snapshot-";
+const PRUNING_METRICS_FILE_NAME: &str = "pruning-metrics.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsensusSnapshot {
+    /// The height of the finalized block.
+    pub height: BlockHeight,
+    pub block_hash: Hash256,
+    pub proof: FinalizationProof,
+    /// The number of rounds that the height took.
+    pub rounds: u64,
+}
+
+/// What the pruning of the history storage has reclaimed so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruningMetrics {
+    pub removed_files: u64,
+    pub reclaimed_bytes: u64,
+}
+
+impl ConsensusSnapshot {
+    pub(super) fn new(
+        history: &RoundHistory,
+        block_hash: Hash256,
+        proof: FinalizationProof,
+    ) -> Self {
+        Self {
+            height: history.height,
+            block_hash,
+            proof,
+            rounds: history.rounds.len() as u64,
+        }
+    }
+}
+
+pub(super) fn snapshot_file_name(height: BlockHeight) -> String {
+    format!("{SNAPSHOT_FILE_PREFIX}{height}.json")
+}
+
+/// Lists the heights of the files of the prefix, the latest first.
+pub(super) async fn list_heights(
+    storage: &StorageImpl,
+    prefix: &str,
+) -> Result<Vec<BlockHeight>, Error> {
+    let mut heights = storage
+        .list_files()
+        .await?
+        .into_iter()
+        .filter_map(|file_name| {
+            file_name
+                .strip_prefix(prefix)?
+                .strip_suffix(".json")?
+                .parse::<BlockHeight>()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(heights)
+}
+
+/// Reads the latest snapshot in the history storage, if any.
+///
+/// It doesn't need the consensus to be opened, which may fail with a stale state.
+pub async fn read_latest_snapshot(
+    history_storage: &StorageImpl,
+) -> Result<Option<ConsensusSnapshot>, Error> {
+    let Some(height) = list_heights(history_storage, SNAPSHOT_FILE_PREFIX)
+        .await?
+        .first()
+        .copied()
+    else {
+        return Ok(None);
+    };
+    Ok(Some(serde_spb::from_str(
+        &history_storage
+            .read_file(&snapshot_file_name(height))
+            .await?,
+    )?))
+}
+
+pub async fn read_pruning_metrics(history_storage: &StorageImpl) -> Result<PruningMetrics, Error> {
+    if !history_storage
+        .list_files()
+        .await?
+        .iter()
+        .any(|file_name| file_name == PRUNING_METRICS_FILE_NAME)
+    {
+        return Ok(PruningMetrics::default());
+    }
+    Ok(serde_spb::from_str(
+        &history_storage.read_file(PRUNING_METRICS_FILE_NAME).await?,
+    )?)
+}
+
+/// Removes the files, adding them to the metrics.
+pub(super) async fn remove_files(
+    storage: &mut StorageImpl,
+    file_names: &[String],
+) -> Result<(), Error> {
+    if file_names.is_empty() {
+        return Ok(());
+    }
+    let mut metrics = read_pruning_metrics(storage).await?;
+    for file_name in file_names {
+        metrics.reclaimed_bytes += storage.read_file(file_name).await?.len() as u64;
+        metrics.removed_files += 1;
+        storage.remove_file(file_name).await?;
+    }
+    storage
+        .add_or_overwrite_file(
+            PRUNING_METRICS_FILE_NAME,
+            serde_spb::to_string(&metrics).unwrap(),
+        )
+        .await?;
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use super::history::*;
+use super::veto::{self, *};
 use super::ProgressResult;
 use eyre::eyre;
 use serde::{Deserialize, Serialize};
@@ -5,7 +7,8 @@ use simperby_core::*;
 use simperby_network::*;
 use std::collections::{BTreeMap, BTreeSet};
 use vetomint::{
-    BlockIdentifier, ConsensusEvent, ConsensusParams, ConsensusResponse, HeightInfo, Vetomint,
+    decide_proposer, BlockIdentifier, ConsensusEvent, ConsensusParams, ConsensusResponse,
+    HeightInfo, TimeoutStep, Vetomint,
 };
 
 pub type Error = eyre::Error;
@@ -21,7 +24,8 @@ pub enum ConsensusMessage {
         block_hash: Hash256,
     },
     NonNilPreVoted(ConsensusRound, Hash256),
-    NonNilPreCommitted(ConsensusRound, Hash256),
+    /// The timestamp is the local time of the signer, used for the BFT time of the next block.
+    NonNilPreCommitted(ConsensusRound, Hash256, Timestamp),
     NilPreVoted(ConsensusRound),
     NilPreCommitted(ConsensusRound),
 }
@@ -37,6 +41,10 @@ impl DmsMessage for ConsensusMessage {
         Ok(())
     }
 
+    fn priority(&self) -> MessagePriority {
+        MessagePriority::Consensus
+    }
+
     fn commit(
         &self,
         dms_key: &DmsKey,
@@ -47,14 +55,17 @@ impl DmsMessage for ConsensusMessage {
     {
         Ok(MessageCommitmentProof {
             signature: match self {
-                ConsensusMessage::NonNilPreCommitted(round, block_hash) => Signature::sign(
-                    FinalizationSignTarget {
-                        block_hash: *block_hash,
-                        round: *round,
-                    }
-                    .to_hash256(),
-                    private_key,
-                )?,
+                ConsensusMessage::NonNilPreCommitted(round, block_hash, timestamp) => {
+                    Signature::sign(
+                        FinalizationSignTarget {
+                            block_hash: *block_hash,
+                            round: *round,
+                            timestamp: *timestamp,
+                        }
+                        .to_hash256(),
+                        private_key,
+                    )?
+                }
                 _ => Signature::sign(
                     self.to_hash256().aggregate(&dms_key.to_hash256()),
                     private_key,
@@ -70,14 +81,17 @@ impl DmsMessage for ConsensusMessage {
         dms_key: &DmsKey,
     ) -> Result<(), simperby_core::CryptoError> {
         match self {
-            ConsensusMessage::NonNilPreCommitted(round, block_hash) => proof.signature.verify(
-                FinalizationSignTarget {
-                    block_hash: *block_hash,
-                    round: *round,
-                }
-                .to_hash256(),
-                &proof.committer,
-            ),
+            ConsensusMessage::NonNilPreCommitted(round, block_hash, timestamp) => {
+                proof.signature.verify(
+                    FinalizationSignTarget {
+                        block_hash: *block_hash,
+                        round: *round,
+                        timestamp: *timestamp,
+                    }
+                    .to_hash256(),
+                    &proof.committer,
+                )
+            }
             _ => proof.signature.verify(
                 self.to_hash256().aggregate(&dms_key.to_hash256()),
                 &proof.committer,
@@ -105,10 +119,14 @@ pub struct State {
     /// Messages by this node, which are to be broadcasted.
     messages_to_broadcast: Vec<ConsensusMessage>,
     /// Precommits collected so far, for each `(block, round)`.
-    precommits: BTreeMap<(Hash256, ConsensusRound), Vec<TypedSignature<FinalizationSignTarget>>>,
+    #[serde(with = "map_as_entries")]
+    precommits: BTreeMap<(Hash256, ConsensusRound), Vec<PrecommitSignature>>,
     /// If `Some`, any operation on the consensus module will fail;
     /// the user must run `new()` with the next height info.
     finalized: Option<FinalizationProof>,
+    /// The log of the rounds of this height.
+    #[serde(default)]
+    round_history: RoundHistory,
 }
 
 impl State {
@@ -117,25 +135,43 @@ impl State {
         consensus_parameters: ConsensusParams,
         round_zero_timestamp: Timestamp,
         this_node_key: PrivateKey,
+    ) -> Result<State, Error> {
+        Self::new_as(
+            block_header,
+            consensus_parameters,
+            round_zero_timestamp,
+            &this_node_key.public_key(),
+        )
+    }
+
+    /// Same as [`Self::new`], but only with the public key of this node,
+    /// as the state itself never signs.
+    pub fn new_as(
+        block_header: &BlockHeader,
+        consensus_parameters: ConsensusParams,
+        round_zero_timestamp: Timestamp,
+        this_node: &PublicKey,
     ) -> Result<State, Error> {
         let height_info = generate_height_info(
             block_header,
             consensus_parameters,
             round_zero_timestamp,
-            this_node_key,
+            this_node,
         )?;
-        let state = State {
+        let mut state = State {
             vetomint: Vetomint::new(height_info),
             block_header: block_header.clone(),
             block_identifier_count: 0,
-            to_be_processed_events: Vec::new(),
+            to_be_processed_events: vec![(ConsensusEvent::Start, round_zero_timestamp)],
             updated_events: BTreeSet::new(),
             verified_block_hashes: BTreeMap::new(),
             vetoed_block_hashes: BTreeSet::new(),
             messages_to_broadcast: Vec::new(),
             precommits: BTreeMap::new(),
             finalized: None,
+            round_history: RoundHistory::new(block_header.height + 1),
         };
+        state.enter_round(0);
         Ok(state)
     }
 
@@ -147,6 +183,29 @@ impl State {
         &self.block_header
     }
 
+    /// The round that the state machine is currently in.
+    pub fn round(&self) -> ConsensusRound {
+        self.vetomint.get_round() as ConsensusRound
+    }
+
+    pub fn round_history(&self) -> &RoundHistory {
+        &self.round_history
+    }
+
+    pub fn pending_vetoes(&self) -> Vec<PendingVeto> {
+        let this_node_key = self
+            .vetomint
+            .get_height_info()
+            .this_node_index
+            .map(|index| &self.block_header.validator_set[index].0);
+        veto::pending_vetoes(
+            &self.round_history,
+            &self.block_header.validator_set,
+            this_node_key,
+            &self.vetoed_block_hashes,
+        )
+    }
+
     pub fn register_verified_block_hash(&mut self, block_hash: Hash256) {
         self.assert_not_finalized();
         if self.verified_block_hashes.contains_key(&block_hash) {
@@ -204,11 +263,16 @@ impl State {
             if self.updated_events.contains(&event) {
                 continue;
             }
+            self.record_message(&message, author.clone(), timestamp);
             self.to_be_processed_events.push((event, timestamp));
-            if let ConsensusMessage::NonNilPreCommitted(round, block_hash) = message {
+            if let ConsensusMessage::NonNilPreCommitted(round, block_hash, timestamp) = message {
                 self.precommits
                     .entry((block_hash, round))
-                    .and_modify(|v| v.push(TypedSignature::new(signature, author)));
+                    .or_default()
+                    .push(PrecommitSignature(
+                        TypedSignature::new(signature, author),
+                        timestamp,
+                    ));
             }
         }
     }
@@ -219,17 +283,23 @@ impl State {
         self.to_be_processed_events
             .push((ConsensusEvent::Timer, timestamp));
         while let Some((event, timestamp)) = self.to_be_processed_events.pop() {
+            let round = self.vetomint.get_round() as ConsensusRound;
             let responses = self.vetomint.progress(event.clone(), timestamp);
+            if event == ConsensusEvent::Timer {
+                self.record_timeouts(round, &responses, timestamp);
+            }
             self.updated_events.insert(event);
             for response in responses {
                 let (x, message) =
                     self.process_consensus_response_to_progress_result(response, timestamp);
+                self.record_progress_result(&x);
                 result.push(x);
                 if let Some(message) = message {
                     self.messages_to_broadcast.push(message);
                 }
             }
         }
+        self.enter_round(self.vetomint.get_round() as ConsensusRound);
         result
     }
 
@@ -253,6 +323,125 @@ impl State {
             .cloned()
     }
 
+    fn get_proposer(&self, round: ConsensusRound) -> PublicKey {
+        let index = decide_proposer(round as usize, self.vetomint.get_height_info());
+        self.block_header.validator_set[index].0.clone()
+    }
+
+    fn history_round(&mut self, round: ConsensusRound) -> &mut RoundRecord {
+        let proposer = self.get_proposer(round);
+        self.round_history.round_mut(round, proposer)
+    }
+
+    fn enter_round(&mut self, round: ConsensusRound) {
+        let proposer = self.get_proposer(round);
+        self.round_history.enter_round(round, proposer);
+    }
+
+    fn record_message(
+        &mut self,
+        message: &ConsensusMessage,
+        signer: PublicKey,
+        timestamp: Timestamp,
+    ) {
+        let (round, kind, block_hash) = match message {
+            ConsensusMessage::Proposal {
+                round, block_hash, ..
+            } => {
+                self.history_round(*round)
+                    .proposal
+                    .get_or_insert(*block_hash);
+                return;
+            }
+            ConsensusMessage::NonNilPreVoted(round, block_hash) => {
+                (*round, VoteKind::Prevote, Some(*block_hash))
+            }
+            ConsensusMessage::NonNilPreCommitted(round, block_hash, _) => {
+                (*round, VoteKind::Precommit, Some(*block_hash))
+            }
+            ConsensusMessage::NilPreVoted(round) => (*round, VoteKind::Prevote, None),
+            ConsensusMessage::NilPreCommitted(round) => (*round, VoteKind::Precommit, None),
+        };
+        self.history_round(round).add_vote(RecordedVote {
+            signer,
+            kind,
+            block_hash,
+            timestamp,
+        });
+    }
+
+    /// Records the results of this node, which it doesn't receive from the DMS until broadcasted.
+    fn record_progress_result(&mut self, result: &ProgressResult) {
+        let this_node_key = match self.vetomint.get_height_info().this_node_index {
+            Some(index) => self.block_header.validator_set[index].0.clone(),
+            None => return,
+        };
+        let (round, kind, block_hash, timestamp) = match result {
+            ProgressResult::Proposed(round, block_hash, _) => {
+                self.history_round(*round)
+                    .proposal
+                    .get_or_insert(*block_hash);
+                return;
+            }
+            ProgressResult::NonNilPreVoted(round, block_hash, timestamp) => {
+                (*round, VoteKind::Prevote, Some(*block_hash), *timestamp)
+            }
+            ProgressResult::NonNilPreCommitted(round, block_hash, timestamp) => {
+                (*round, VoteKind::Precommit, Some(*block_hash), *timestamp)
+            }
+            ProgressResult::NilPreVoted(round, timestamp) => {
+                (*round, VoteKind::Prevote, None, *timestamp)
+            }
+            ProgressResult::NilPreCommitted(round, timestamp) => {
+                (*round, VoteKind::Precommit, None, *timestamp)
+            }
+            ProgressResult::Finalized(block_hash, _, proof) => {
+                self.history_round(proof.round).outcome = RoundOutcome::Finalized(*block_hash);
+                return;
+            }
+            ProgressResult::ViolationReported(..) => return,
+        };
+        self.history_round(round).add_vote(RecordedVote {
+            signer: this_node_key,
+            kind,
+            block_hash,
+            timestamp,
+        });
+    }
+
+    /// Infers the timeouts fired by the timer in the given round from its responses.
+    ///
+    /// The timer makes a nil vote or moves to the next round only on a timeout,
+    /// except that a nil precommit on the nil prevotes of this node is counted as a prevote timeout.
+    fn record_timeouts(
+        &mut self,
+        round: ConsensusRound,
+        responses: &[ConsensusResponse],
+        timestamp: Timestamp,
+    ) {
+        let mut timeouts = Vec::new();
+        for response in responses {
+            match response {
+                ConsensusResponse::BroadcastPrevote {
+                    proposal: None,
+                    round: r,
+                } if *r as ConsensusRound == round => timeouts.push(TimeoutStep::Propose),
+                ConsensusResponse::BroadcastPrecommit {
+                    proposal: None,
+                    round: r,
+                } if *r as ConsensusRound == round => timeouts.push(TimeoutStep::Prevote),
+                _ => (),
+            }
+        }
+        if self.vetomint.get_round() as ConsensusRound > round {
+            timeouts.push(TimeoutStep::Precommit);
+        }
+        let record = self.history_round(round);
+        for step in timeouts {
+            record.timeouts.push((step, timestamp));
+        }
+    }
+
     fn get_validator_index(&self, public_key: &PublicKey) -> Result<usize, Error> {
         self.block_header
             .validator_set
@@ -271,7 +460,7 @@ impl State {
             ConsensusMessage::NonNilPreVoted(_, block_hash) => {
                 self.verified_block_hashes.contains_key(block_hash)
             }
-            ConsensusMessage::NonNilPreCommitted(_, block_hash) => {
+            ConsensusMessage::NonNilPreCommitted(_, block_hash, _) => {
                 self.verified_block_hashes.contains_key(block_hash)
             }
             _ => true,
@@ -325,7 +514,7 @@ impl State {
                 let (consensus_message, progress_result) = if let Some(block_index) = proposal {
                     let block_hash = get_block_hash(self, block_index);
                     (
-                        ConsensusMessage::NonNilPreCommitted(round as u64, block_hash),
+                        ConsensusMessage::NonNilPreCommitted(round as u64, block_hash, timestamp),
                         ProgressResult::NonNilPreCommitted(round as u64, block_hash, timestamp),
                     )
                 } else {
@@ -409,7 +598,7 @@ impl State {
                     round: *round as usize,
                 }
             }
-            ConsensusMessage::NonNilPreCommitted(round, block_hash) => {
+            ConsensusMessage::NonNilPreCommitted(round, block_hash, _) => {
                 let index = self
                     .get_block_index(block_hash)
                     .expect("this must be already verified by the message filter");
@@ -433,16 +622,40 @@ impl State {
     }
 }
 
+/// (De)serializes a map as the list of its entries, since JSON allows only the string keys.
+mod map_as_entries {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &BTreeMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
 fn generate_height_info(
     header: &BlockHeader,
     consensus_params: ConsensusParams,
     round_zero_timestamp: Timestamp,
-    this_node_key: PrivateKey,
+    this_node: &PublicKey,
 ) -> Result<HeightInfo, Error> {
     let this_node_index = header
         .validator_set
         .iter()
-        .position(|(pubkey, _)| *pubkey == this_node_key.public_key());
+        .position(|(pubkey, _)| pubkey == this_node);
     let info = HeightInfo {
         validators: header
             .validator_set
@@ -456,3 +669,190 @@ fn generate_height_info(
     };
     Ok(info)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_history() {
+        let (this_key, this_private_key) = generate_keypair_random();
+        let (other_key, other_private_key) = generate_keypair_random();
+        let header = BlockHeader {
+            author: this_key.clone(),
+            prev_block_finalization_proof: FinalizationProof::genesis(),
+            previous_hash: Hash256::zero(),
+            height: 0,
+            timestamp: 0,
+            commit_merkle_root: Hash256::zero(),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: vec![(this_key.clone(), 1), (other_key.clone(), 1)],
+            version: "0.0.0".to_owned(),
+        };
+        let params = ConsensusParams::default();
+        let mut state = State::new(&header, params.clone(), 0, this_private_key).unwrap();
+        let block_hash = Hash256::hash("block");
+        let other_block_hash = Hash256::hash("other-block");
+        state.register_verified_block_hash(block_hash);
+        state.register_verified_block_hash(other_block_hash);
+        state.set_proposal_candidate(block_hash, 0).unwrap();
+        let signature = Signature::sign(Hash256::hash("message"), &other_private_key).unwrap();
+        let receive = |state: &mut State, message: ConsensusMessage, timestamp| {
+            state.add_consensus_messages(
+                vec![(message, other_key.clone(), signature.clone())],
+                timestamp,
+            );
+            state.progress(timestamp)
+        };
+
+        // This node proposes and prevotes, but the other prevotes nil,
+        // and both precommit nil so that the round times out.
+        state.progress(0);
+        receive(&mut state, ConsensusMessage::NilPreVoted(0), 10);
+        receive(&mut state, ConsensusMessage::NilPreCommitted(0), 20);
+        let precommit_timeout = 20 + params.precommit_timeout_ms as Timestamp;
+        state.progress(precommit_timeout);
+        // The other proposes its block in the next round, which is finalized.
+        for message in [
+            ConsensusMessage::Proposal {
+                round: 1,
+                valid_round: None,
+                block_hash: other_block_hash,
+            },
+            ConsensusMessage::NonNilPreVoted(1, other_block_hash),
+            ConsensusMessage::NonNilPreCommitted(1, other_block_hash, precommit_timeout + 20),
+        ] {
+            receive(&mut state, message, precommit_timeout + 20);
+        }
+
+        let history = state.round_history();
+        assert_eq!(history.height, 1);
+        assert_eq!(history.rounds.len(), 2);
+        let (failed, finalized) = (&history.rounds[0], &history.rounds[1]);
+        assert_eq!(failed.proposer, this_key);
+        assert_eq!(failed.proposal, Some(block_hash));
+        assert_eq!(failed.outcome, RoundOutcome::Failed);
+        assert_eq!(
+            failed.timeouts,
+            vec![(TimeoutStep::Precommit, precommit_timeout)]
+        );
+        assert_eq!(
+            failed.votes,
+            vec![
+                RecordedVote {
+                    signer: this_key.clone(),
+                    kind: VoteKind::Prevote,
+                    block_hash: Some(block_hash),
+                    timestamp: 0,
+                },
+                RecordedVote {
+                    signer: other_key.clone(),
+                    kind: VoteKind::Prevote,
+                    block_hash: None,
+                    timestamp: 10,
+                },
+                RecordedVote {
+                    signer: this_key.clone(),
+                    kind: VoteKind::Precommit,
+                    block_hash: None,
+                    timestamp: 10,
+                },
+                RecordedVote {
+                    signer: other_key.clone(),
+                    kind: VoteKind::Precommit,
+                    block_hash: None,
+                    timestamp: 20,
+                },
+            ]
+        );
+        assert_eq!(finalized.proposer, other_key);
+        assert_eq!(finalized.proposal, Some(other_block_hash));
+        assert_eq!(finalized.outcome, RoundOutcome::Finalized(other_block_hash));
+        assert_eq!(finalized.votes.len(), 4);
+        assert!(finalized.timeouts.is_empty());
+    }
+
+    #[test]
+    fn pending_vetoes() {
+        let keys = (0..3)
+            .map(|_| generate_keypair_random())
+            .collect::<Vec<_>>();
+        let header = BlockHeader {
+            author: keys[0].0.clone(),
+            prev_block_finalization_proof: FinalizationProof::genesis(),
+            previous_hash: Hash256::zero(),
+            height: 0,
+            timestamp: 0,
+            commit_merkle_root: Hash256::zero(),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: keys.iter().map(|(key, _)| (key.clone(), 1)).collect(),
+            version: "0.0.0".to_owned(),
+        };
+        let params = ConsensusParams::default();
+        let mut state = State::new(&header, params.clone(), 0, keys[0].1.clone()).unwrap();
+        let block_hash = Hash256::hash("block");
+        let vetoed_block_hash = Hash256::hash("vetoed-block");
+        state.register_verified_block_hash(block_hash);
+        state.register_verified_block_hash(vetoed_block_hash);
+        state.veto_block(vetoed_block_hash);
+        assert_eq!(
+            state.pending_vetoes(),
+            vec![PendingVeto {
+                target: VetoTarget::Block(vetoed_block_hash),
+                vetoers: vec![(keys[0].0.clone(), 1)],
+                voting_power: 1,
+                total_voting_power: 3,
+                threshold_met: false,
+                expiry: VetoExpiry::EndOfHeight(1),
+            }]
+        );
+
+        let proposer = crate::get_proposer(&header, &params, 0);
+        let signature = Signature::sign(Hash256::hash("message"), &keys[1].1).unwrap();
+        state.add_consensus_messages(
+            vec![
+                (
+                    ConsensusMessage::Proposal {
+                        round: 0,
+                        valid_round: None,
+                        block_hash,
+                    },
+                    proposer,
+                    signature.clone(),
+                ),
+                (
+                    ConsensusMessage::NilPreVoted(0),
+                    keys[1].0.clone(),
+                    signature.clone(),
+                ),
+                (
+                    ConsensusMessage::NilPreCommitted(0),
+                    keys[1].0.clone(),
+                    signature.clone(),
+                ),
+                (
+                    ConsensusMessage::NilPreCommitted(0),
+                    keys[2].0.clone(),
+                    signature,
+                ),
+            ],
+            10,
+        );
+        let vetoes = state.pending_vetoes();
+        assert_eq!(vetoes.len(), 3);
+        // Two of the three precommitted nil, so the round can't be finalized.
+        assert_eq!(vetoes[0].target, VetoTarget::Round(0));
+        assert_eq!(
+            vetoes[0].vetoers,
+            vec![(keys[1].0.clone(), 1), (keys[2].0.clone(), 1)]
+        );
+        assert!(vetoes[0].threshold_met);
+        assert_eq!(vetoes[0].expiry, VetoExpiry::EndOfRound(0));
+        let block_veto = vetoes
+            .iter()
+            .find(|veto| veto.target == VetoTarget::Block(block_hash))
+            .unwrap();
+        assert_eq!(block_veto.vetoers, vec![(keys[1].0.clone(), 1)]);
+        assert!(!block_veto.threshold_met);
+    }
+}
@@ -0,0 +1,125 @@
+//! The vetoes pending in the height being decided, as far as this node has observed.
+//!
+//! A validator vetoes a round by precommitting nil in it,
+//! and a block by prevoting nil on the proposal of it (or by [`Consensus::veto_block`](crate::Consensus::veto_block) for this node).
+//! A veto of more than a third of the voting power keeps the target from being finalized.
+use serde::{Deserialize, Serialize};
+use simperby_core::*;
+
+use crate::history::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VetoTarget {
+    Round(ConsensusRound),
+    Block(Hash256),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VetoExpiry {
+    /// The veto is void once the consensus moves on from the round.
+    EndOfRound(ConsensusRound),
+    /// The veto stands until a block is finalized at the height.
+    EndOfHeight(BlockHeight),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingVeto {
+    pub target: VetoTarget,
+    /// The validators who vetoed, with their voting power.
+    pub vetoers: Vec<(PublicKey, VotingPower)>,
+    pub voting_power: VotingPower,
+    pub total_voting_power: VotingPower,
+    /// Whether the vetoers have more than a third of the total voting power.
+    pub threshold_met: bool,
+    pub expiry: VetoExpiry,
+}
+
+impl PendingVeto {
+    fn new(
+        target: VetoTarget,
+        vetoers: Vec<&PublicKey>,
+        validator_set: &[(PublicKey, VotingPower)],
+        expiry: VetoExpiry,
+    ) -> Self {
+        let vetoers = validator_set
+            .iter()
+            .filter(|(public_key, _)| vetoers.contains(&public_key))
+            .cloned()
+            .collect::<Vec<_>>();
+        let voting_power = vetoers.iter().map(|(_, power)| power).sum::<VotingPower>();
+        let total_voting_power = validator_set
+            .iter()
+            .map(|(_, power)| power)
+            .sum::<VotingPower>();
+        Self {
+            target,
+            vetoers,
+            voting_power,
+            total_voting_power,
+            threshold_met: voting_power * 3 > total_voting_power,
+            expiry,
+        }
+    }
+}
+
+/// Collects the vetoes in the rounds still in progress and on the blocks proposed in the height,
+/// including those of this node on the blocks it has vetoed locally.
+pub(crate) fn pending_vetoes<'a>(
+    history: &'a RoundHistory,
+    validator_set: &[(PublicKey, VotingPower)],
+    this_node: Option<&PublicKey>,
+    vetoed_blocks: impl IntoIterator<Item = &'a Hash256>,
+) -> Vec<PendingVeto> {
+    let nil_voters = |record: &'a RoundRecord, kind: VoteKind| {
+        record
+            .votes
+            .iter()
+            .filter(move |vote| vote.kind == kind && vote.block_hash.is_none())
+            .map(|vote| &vote.signer)
+    };
+    let mut vetoes = Vec::new();
+    for record in &history.rounds {
+        if record.outcome != RoundOutcome::InProgress {
+            continue;
+        }
+        let vetoers = nil_voters(record, VoteKind::Precommit).collect::<Vec<_>>();
+        if !vetoers.is_empty() {
+            vetoes.push(PendingVeto::new(
+                VetoTarget::Round(record.round),
+                vetoers,
+                validator_set,
+                VetoExpiry::EndOfRound(record.round),
+            ));
+        }
+    }
+
+    let vetoed_blocks = vetoed_blocks.into_iter().collect::<Vec<_>>();
+    let mut blocks = history
+        .rounds
+        .iter()
+        .filter_map(|record| record.proposal.as_ref())
+        .chain(vetoed_blocks.iter().copied())
+        .collect::<Vec<_>>();
+    blocks.sort();
+    blocks.dedup();
+    for block_hash in blocks {
+        let mut vetoers = history
+            .rounds
+            .iter()
+            .filter(|record| record.proposal.as_ref() == Some(block_hash))
+            .flat_map(|record| nil_voters(record, VoteKind::Prevote))
+            .collect::<Vec<_>>();
+        if let Some(this_node) = this_node.filter(|_| vetoed_blocks.contains(&block_hash)) {
+            vetoers.push(this_node);
+        }
+        if !vetoers.is_empty() {
+            vetoes.push(PendingVeto::new(
+                VetoTarget::Block(*block_hash),
+                vetoers,
+                validator_set,
+                VetoExpiry::EndOfHeight(history.height),
+            ));
+        }
+    }
+    vetoes
+}
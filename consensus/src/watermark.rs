@@ -0,0 +1,104 @@
+//! The guard against double-signing across the restarts.
+//!
+//! Before a consensus message of this node is signed, the position of it,
+//! `(height, round, step)`, is persisted as the [`SignWatermark`].
+//! A message at or below the watermark is refused unless it's the very message of the watermark
+//! or one already signed in the DMS, so the node never signs two different messages
+//! at the same position even if its consensus state is lost or rolled back.
+use super::*;
+
+const SIGN_WATERMARK_FILE_NAME: &str = "sign-watermark.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SignStep {
+    Proposal,
+    Prevote,
+    Precommit,
+}
+
+/// The last consensus message signed by this node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignWatermark {
+    pub height: BlockHeight,
+    pub round: ConsensusRound,
+    pub step: SignStep,
+    pub message_hash: Hash256,
+}
+
+impl SignWatermark {
+    pub(super) fn new(height: BlockHeight, message: &ConsensusMessage) -> Self {
+        let (round, step) = match message {
+            ConsensusMessage::Proposal { round, .. } => (*round, SignStep::Proposal),
+            ConsensusMessage::NonNilPreVoted(round, _) | ConsensusMessage::NilPreVoted(round) => {
+                (*round, SignStep::Prevote)
+            }
+            ConsensusMessage::NonNilPreCommitted(round, _, _)
+            | ConsensusMessage::NilPreCommitted(round) => (*round, SignStep::Precommit),
+        };
+        Self {
+            height,
+            round,
+            step,
+            message_hash: message.to_hash256(),
+        }
+    }
+
+    fn position(&self) -> (BlockHeight, ConsensusRound, SignStep) {
+        (self.height, self.round, self.step)
+    }
+
+    /// Checks whether the message of `next` may be signed after this one.
+    pub(super) fn check(&self, next: &SignWatermark) -> Result<(), Error> {
+        if next.position() > self.position() || next.message_hash == self.message_hash {
+            return Ok(());
+        }
+        Err(eyre!(
+            "refused to sign the consensus message at (height {}, round {}, {:?}), \
+             not above the watermark at (height {}, round {}, {:?})",
+            next.height,
+            next.round,
+            next.step,
+            self.height,
+            self.round,
+            self.step
+        ))
+    }
+}
+
+pub async fn read_sign_watermark(storage: &StorageImpl) -> Result<Option<SignWatermark>, Error> {
+    if !storage
+        .list_files()
+        .await?
+        .iter()
+        .any(|file_name| file_name == SIGN_WATERMARK_FILE_NAME)
+    {
+        return Ok(None);
+    }
+    Ok(Some(serde_spb::from_str(
+        &storage.read_file(SIGN_WATERMARK_FILE_NAME).await?,
+    )?))
+}
+
+/// Persists the watermark, which must reach the disk before the message is signed.
+pub(super) async fn write_sign_watermark(
+    storage: &mut StorageImpl,
+    watermark: &SignWatermark,
+) -> Result<(), Error> {
+    storage
+        .add_or_overwrite_file(
+            SIGN_WATERMARK_FILE_NAME,
+            serde_spb::to_string(watermark).unwrap(),
+        )
+        .await
+        .map_err(|_| eyre!("failed to commit the sign watermark to the storage"))?;
+    storage.checkpoint().await?;
+    Ok(())
+}
+
+pub(super) async fn remove_sign_watermark(storage: &mut StorageImpl) -> Result<(), Error> {
+    if read_sign_watermark(storage).await?.is_some() {
+        storage.remove_file(SIGN_WATERMARK_FILE_NAME).await?;
+        storage.checkpoint().await?;
+    }
+    Ok(())
+}
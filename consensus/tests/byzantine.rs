@@ -0,0 +1,237 @@
+use simperby_consensus::*;
+use simperby_core::utils::get_timestamp;
+use simperby_core::*;
+use simperby_network::*;
+use simperby_test_suite::*;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+fn header(
+    height: BlockHeight,
+    validator_set: &[(PublicKey, VotingPower)],
+    tag: &str,
+) -> BlockHeader {
+    BlockHeader {
+        author: validator_set[0].0.clone(),
+        prev_block_finalization_proof: FinalizationProof::genesis(),
+        previous_hash: Hash256::hash(tag),
+        height,
+        timestamp: 0,
+        commit_merkle_root: Hash256::zero(),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: validator_set.to_vec(),
+        version: "0.0.0".to_owned(),
+    }
+}
+
+struct Simulation {
+    honest: Vec<Consensus>,
+    byzantine: ByzantineNode,
+    byzantine_key: PublicKey,
+    /// The two blocks at the next height, which all the nodes have verified.
+    blocks: Vec<BlockHeader>,
+}
+
+/// Sets up four validators with the same voting power, one of which is byzantine
+/// with the behaviors given on the blocks.
+async fn setup(
+    test_name: &str,
+    byzantine_index: usize,
+    behaviors: impl FnOnce(&[BlockHeader]) -> Vec<ByzantineBehavior>,
+) -> Simulation {
+    let keys = (0..4)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let validator_set = keys
+        .iter()
+        .map(|(public_key, _)| (public_key.clone(), 1))
+        .collect::<Vec<_>>();
+    let last_header = header(0, &validator_set, "genesis");
+    let blocks = vec![
+        header(1, &validator_set, "block"),
+        header(1, &validator_set, "another block"),
+    ];
+    let params = ConsensusParams {
+        propose_timeout_ms: 300,
+        prevote_timeout_ms: 300,
+        precommit_timeout_ms: 300,
+        ..Default::default()
+    };
+
+    let mut nodes = Vec::new();
+    for (_, private_key) in &keys {
+        let dms = create_test_dms(
+            format!("consensus-byzantine-{test_name}"),
+            keys.iter()
+                .map(|(public_key, _)| public_key.clone())
+                .collect(),
+            private_key.clone(),
+        )
+        .await;
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let mut consensus = Consensus::new(
+            Arc::new(RwLock::new(dms)),
+            StorageImpl::open(&path).await.unwrap(),
+            last_header.clone(),
+            params.clone(),
+            get_timestamp(),
+            Some(private_key.clone()),
+        )
+        .await
+        .unwrap();
+        for block in &blocks {
+            consensus
+                .register_verified_block_hash(block.to_hash256())
+                .await
+                .unwrap();
+        }
+        consensus
+            .set_proposal_candidate(blocks[0].to_hash256(), get_timestamp())
+            .await
+            .unwrap();
+        nodes.push(consensus);
+    }
+    let byzantine = ByzantineNode::new(
+        nodes.remove(byzantine_index),
+        keys[byzantine_index].1.clone(),
+        behaviors(&blocks),
+    );
+    Simulation {
+        honest: nodes,
+        byzantine,
+        byzantine_key: keys[byzantine_index].0.clone(),
+        blocks,
+    }
+}
+
+/// Runs the consensus until every honest node finalizes,
+/// returning the finalized blocks and the number of messages rejected by the honest nodes.
+async fn run(simulation: &mut Simulation) -> (Vec<Hash256>, usize) {
+    let mut finalized = vec![None; simulation.honest.len()];
+    let mut rejected = 0;
+    for _ in 0..200 {
+        for (i, node) in simulation.honest.iter_mut().enumerate() {
+            node.update().await.unwrap();
+            for result in node.progress(get_timestamp()).await.unwrap() {
+                if let ProgressResult::Finalized(block_hash, _, _) = result {
+                    finalized[i].get_or_insert(block_hash);
+                }
+            }
+            node.flush().await.unwrap();
+        }
+        let byzantine = simulation.byzantine.consensus_mut();
+        byzantine.update().await.unwrap();
+        byzantine.progress(get_timestamp()).await.unwrap();
+        simulation.byzantine.flush().await.unwrap();
+
+        // Gossip all the messages.
+        let byzantine_dms = simulation.byzantine.consensus().get_dms();
+        for (i, from) in simulation.honest.iter().enumerate() {
+            for (j, to) in simulation.honest.iter().enumerate() {
+                if i != j {
+                    relay_messages(
+                        &*from.get_dms().read().await,
+                        &mut *to.get_dms().write().await,
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+            relay_messages(
+                &*from.get_dms().read().await,
+                &mut *byzantine_dms.write().await,
+            )
+            .await
+            .unwrap();
+            rejected += simulation
+                .byzantine
+                .deliver(&mut *from.get_dms().write().await)
+                .await
+                .unwrap();
+        }
+
+        if finalized.iter().all(Option::is_some) {
+            return (finalized.into_iter().flatten().collect(), rejected);
+        }
+        sleep_ms(20).await;
+    }
+    panic!("the honest nodes failed to finalize: {finalized:?}");
+}
+
+#[tokio::test]
+async fn equivocation() {
+    setup_test();
+    let mut simulation = setup("equivocation", 3, |blocks| {
+        vec![ByzantineBehavior::Equivocate {
+            other_block_hash: blocks[1].to_hash256(),
+        }]
+    })
+    .await;
+    let (finalized, _) = run(&mut simulation).await;
+    assert!(finalized
+        .iter()
+        .all(|block_hash| *block_hash == simulation.blocks[0].to_hash256()));
+
+    // Every honest node has the evidence of the double precommit.
+    for node in &simulation.honest {
+        let evidence =
+            collect_double_sign_evidence(&*node.get_dms().read().await, &simulation.blocks)
+                .await
+                .unwrap();
+        assert!(!evidence.is_empty());
+        for evidence in evidence {
+            verify::verify_double_sign_evidence(&evidence).unwrap();
+            assert_eq!(evidence.offender(), &simulation.byzantine_key);
+        }
+    }
+}
+
+#[tokio::test]
+async fn withholding_votes() {
+    setup_test();
+    let mut simulation = setup("withholding-votes", 3, |_| {
+        vec![ByzantineBehavior::WithholdVotes]
+    })
+    .await;
+    let (finalized, _) = run(&mut simulation).await;
+    assert!(finalized
+        .iter()
+        .all(|block_hash| *block_hash == simulation.blocks[0].to_hash256()));
+}
+
+#[tokio::test]
+async fn invalid_proposal() {
+    setup_test();
+    // The first validator is the proposer of the first round.
+    let mut simulation = setup("invalid-proposal", 0, |_| {
+        vec![ByzantineBehavior::ProposeInvalidBlock {
+            block_hash: Hash256::hash("invalid block"),
+        }]
+    })
+    .await;
+    let (finalized, _) = run(&mut simulation).await;
+    assert!(finalized
+        .iter()
+        .all(|block_hash| *block_hash == simulation.blocks[0].to_hash256()));
+    // The first round fails as nobody accepts the proposal.
+    for node in &simulation.honest {
+        let history = node.get_round_history(1).await.unwrap().unwrap();
+        assert_eq!(history.rounds[0].outcome, RoundOutcome::Failed);
+        assert_eq!(history.rounds[0].proposal, None);
+    }
+}
+
+#[tokio::test]
+async fn malformed_messages() {
+    setup_test();
+    let mut simulation = setup("malformed-messages", 3, |_| {
+        vec![ByzantineBehavior::SendMalformedMessages]
+    })
+    .await;
+    let (finalized, rejected) = run(&mut simulation).await;
+    assert!(finalized
+        .iter()
+        .all(|block_hash| *block_hash == simulation.blocks[0].to_hash256()));
+    assert!(rejected > 0);
+}
@@ -91,7 +91,11 @@ async fn single_server_propose_1() {
     let voting_powers = vec![1, 1, 1, 1, 1];
     let num_nodes = voting_powers.len();
     let params = ConsensusParams {
-        timeout_ms: 60 * 1_000,
+        propose_timeout_ms: 60 * 1_000, // 1 minute
+        prevote_timeout_ms: 60 * 1_000,
+        precommit_timeout_ms: 60 * 1_000,
+        timeout_backoff_permille: 1_000,
+        max_timeout_ms: 60 * 1_000,
         repeat_round_for_first_leader: 100,
     };
     let round_zero_timestamp = get_timestamp();
@@ -344,7 +348,11 @@ async fn single_seperate_server_propose_1() {
     let voting_powers = vec![1, 1, 1, 1, 1];
     let num_nodes = voting_powers.len();
     let params = ConsensusParams {
-        timeout_ms: 60 * 1_000, // 1 minute
+        propose_timeout_ms: 60 * 1_000, // 1 minute
+        prevote_timeout_ms: 60 * 1_000,
+        precommit_timeout_ms: 60 * 1_000,
+        timeout_backoff_permille: 1_000,
+        max_timeout_ms: 60 * 1_000,
         repeat_round_for_first_leader: 100,
     };
     let round_zero_timestamp = get_timestamp();
@@ -0,0 +1,217 @@
+use simperby_consensus::*;
+use simperby_core::utils::get_timestamp;
+use simperby_core::*;
+use simperby_network::*;
+use simperby_test_suite::*;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+fn header(
+    height: BlockHeight,
+    validator_set: &[(PublicKey, VotingPower)],
+    tag: &str,
+) -> BlockHeader {
+    BlockHeader {
+        author: validator_set[0].0.clone(),
+        prev_block_finalization_proof: FinalizationProof::genesis(),
+        previous_hash: Hash256::hash(tag),
+        height,
+        timestamp: 0,
+        commit_merkle_root: Hash256::zero(),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: validator_set.to_vec(),
+        version: "0.0.0".to_owned(),
+    }
+}
+
+struct Simulation {
+    nodes: Vec<Consensus>,
+    network: TestNetwork<ConsensusMessage>,
+    block: BlockHeader,
+    /// The block finalized by each node.
+    finalized: Vec<Option<Hash256>>,
+    /// The payloads delivered by the network, and the commitments they carried.
+    delivered: (usize, usize),
+}
+
+/// Sets up four validators with the same voting power, connected by a test network.
+async fn setup(test_name: &str, seed: u64) -> Simulation {
+    let keys = (0..4)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let validator_set = keys
+        .iter()
+        .map(|(public_key, _)| (public_key.clone(), 1))
+        .collect::<Vec<_>>();
+    let last_header = header(0, &validator_set, "genesis");
+    let block = header(1, &validator_set, "block");
+    let params = ConsensusParams {
+        propose_timeout_ms: 300,
+        prevote_timeout_ms: 300,
+        precommit_timeout_ms: 300,
+        timeout_backoff_permille: 1000,
+        ..Default::default()
+    };
+
+    let mut nodes = Vec::new();
+    for (_, private_key) in &keys {
+        let dms = create_test_dms(
+            format!("consensus-partition-{test_name}"),
+            keys.iter()
+                .map(|(public_key, _)| public_key.clone())
+                .collect(),
+            private_key.clone(),
+        )
+        .await;
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let mut consensus = Consensus::new(
+            Arc::new(RwLock::new(dms)),
+            StorageImpl::open(&path).await.unwrap(),
+            last_header.clone(),
+            params.clone(),
+            get_timestamp(),
+            Some(private_key.clone()),
+        )
+        .await
+        .unwrap();
+        consensus
+            .register_verified_block_hash(block.to_hash256())
+            .await
+            .unwrap();
+        consensus
+            .set_proposal_candidate(block.to_hash256(), get_timestamp())
+            .await
+            .unwrap();
+        nodes.push(consensus);
+    }
+    let network = TestNetwork::new(nodes.iter().map(|node| node.get_dms()).collect(), seed);
+    Simulation {
+        finalized: vec![None; nodes.len()],
+        delivered: (0, 0),
+        nodes,
+        network,
+        block,
+    }
+}
+
+impl Simulation {
+    /// Runs the consensus for the given number of steps, or until every node finalizes.
+    async fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            for (i, node) in self.nodes.iter_mut().enumerate() {
+                node.update().await.unwrap();
+                for result in node.progress(get_timestamp()).await.unwrap() {
+                    if let ProgressResult::Finalized(block_hash, _, _) = result {
+                        if let Some(previous) = self.finalized[i] {
+                            assert_eq!(previous, block_hash, "node {i} finalized twice");
+                        }
+                        self.finalized[i] = Some(block_hash);
+                    }
+                }
+                node.flush().await.unwrap();
+            }
+            let report = self.network.tick().await.unwrap();
+            self.delivered.0 += report.delivered;
+            self.delivered.1 += report.delivered_commitments;
+            if self.finalized.iter().all(Option::is_some) {
+                return;
+            }
+            sleep_ms(20).await;
+        }
+    }
+
+    /// The number of the received commitments dropped as already known, by all the nodes.
+    async fn deduplicated(&self) -> u64 {
+        let mut result = 0;
+        for node in &self.nodes {
+            result += node
+                .get_dms()
+                .read()
+                .await
+                .get_sync_statistics()
+                .packets_deduplicated;
+        }
+        result
+    }
+
+    fn assert_all_finalized(&self) {
+        for (i, finalized) in self.finalized.iter().enumerate() {
+            assert_eq!(
+                *finalized,
+                Some(self.block.to_hash256()),
+                "node {i} failed to finalize"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn even_partition() {
+    setup_test();
+    let mut simulation = setup("even-partition", 0).await;
+    // Neither side has the quorum.
+    simulation.network.partition(&[&[0, 1], &[2, 3]]);
+    simulation.run(50).await;
+    assert_eq!(simulation.finalized, vec![None; 4]);
+
+    simulation.network.heal();
+    simulation.run(200).await;
+    simulation.assert_all_finalized();
+}
+
+#[tokio::test]
+async fn minority_partition() {
+    setup_test();
+    let mut simulation = setup("minority-partition", 0).await;
+    simulation.network.partition(&[&[0, 1, 2]]);
+    for _ in 0..200 {
+        simulation.run(1).await;
+        if simulation.finalized[..3].iter().all(Option::is_some) {
+            break;
+        }
+    }
+    // The majority finalizes, while the isolated node stalls.
+    assert!(simulation.finalized[..3]
+        .iter()
+        .all(|x| *x == Some(simulation.block.to_hash256())));
+    simulation.run(20).await;
+    assert_eq!(simulation.finalized[3], None);
+
+    simulation.network.heal();
+    simulation.run(200).await;
+    simulation.assert_all_finalized();
+}
+
+#[tokio::test]
+async fn faulty_links() {
+    setup_test();
+    let mut simulation = setup("faulty-links", 42).await;
+    simulation.network.set_faults(Faults {
+        delay_ticks: 3,
+        loss_rate: 0.3,
+        duplication_rate: 0.3,
+    });
+    simulation.run(300).await;
+    simulation.assert_all_finalized();
+}
+
+#[tokio::test]
+async fn batched_votes() {
+    setup_test();
+    let mut simulation = setup("batched-votes", 7).await;
+    simulation.network.set_faults(Faults {
+        duplication_rate: 0.5,
+        ..Default::default()
+    });
+    simulation.run(200).await;
+    simulation.assert_all_finalized();
+    // The votes of the members on the same block share the payloads,
+    // and the duplicated ones are dropped before the storage.
+    let (payloads, commitments) = simulation.delivered;
+    assert!(
+        payloads < commitments,
+        "{payloads} payloads for {commitments} commitments"
+    );
+    assert!(simulation.deduplicated().await > 0);
+}
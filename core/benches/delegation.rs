@@ -0,0 +1,78 @@
+//! Applying a delegation to the reserved state of 10k members, in place and as a new state.
+//!
+//! Run with `cargo bench -p simperby-core --bench delegation`.
+use simperby_core::*;
+use std::time::Instant;
+
+const MEMBERS: usize = 10_000;
+const ITERATIONS: usize = 100;
+
+fn measure(name: &str, f: impl FnOnce()) {
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed().as_secs_f64() / ITERATIONS as f64;
+    println!("{name}: {:.3} ms", elapsed * 1000.0);
+}
+
+fn main() {
+    let keys = (0..MEMBERS)
+        .map(|i| generate_keypair(format!("member-{i}")))
+        .collect::<Vec<_>>();
+    let state = ReservedState::genesis_template(
+        "bench".to_owned(),
+        keys.iter()
+            .enumerate()
+            .map(|(i, (public_key, _))| (format!("member-{i:05}"), public_key.clone()))
+            .collect(),
+    )
+    .unwrap();
+    let (delegator, delegatee) = (state.members[0].name.clone(), state.members[1].name.clone());
+    let private_key = &keys[0].1;
+    let delegate = |nonce| {
+        let data = DelegationTransactionData {
+            delegator: delegator.clone(),
+            delegatee: delegatee.clone(),
+            governance: true,
+            block_height: 1,
+            timestamp: 0,
+            chain_name: "bench".to_owned(),
+            nonce,
+        };
+        TxDelegate {
+            proof: TypedSignature::sign(&data, private_key).unwrap(),
+            data,
+        }
+    };
+    let undelegate = |nonce| {
+        let data = UndelegationTransactionData {
+            delegator: delegator.clone(),
+            block_height: 1,
+            timestamp: 0,
+            chain_name: "bench".to_owned(),
+            nonce,
+        };
+        TxUndelegate {
+            proof: TypedSignature::sign(&data, private_key).unwrap(),
+            data,
+        }
+    };
+
+    println!("--- {MEMBERS} members ---");
+    let tx = delegate(1);
+    measure("apply_delegate", || {
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(state.apply_delegate(&tx).unwrap());
+        }
+    });
+    // Alternates, since the same delegation can't be applied twice.
+    let txs = (0..ITERATIONS as u64 / 2)
+        .map(|i| (delegate(2 * i + 1), undelegate(2 * i + 2)))
+        .collect::<Vec<_>>();
+    let mut state = state;
+    measure("delegate (in place)", || {
+        for (delegation, undelegation) in &txs {
+            state.delegate(delegation).unwrap();
+            state.undelegate(undelegation).unwrap();
+        }
+    });
+}
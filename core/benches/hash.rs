@@ -0,0 +1,43 @@
+//! Throughput of hashing multi-MB transaction payloads.
+//!
+//! Run with `cargo bench -p simperby-core --bench hash`.
+use simperby_core::*;
+use std::time::Instant;
+
+const MB: usize = 1 << 20;
+
+fn measure(name: &str, size: usize, f: impl Fn() -> Hash256) {
+    let iterations = 10;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(f());
+    }
+    let elapsed = start.elapsed().as_secs_f64() / iterations as f64;
+    println!(
+        "{name}: {:.2} ms, {:.1} MB/s",
+        elapsed * 1000.0,
+        size as f64 / MB as f64 / elapsed
+    );
+}
+
+fn main() {
+    for size in [MB, 16 * MB, 64 * MB] {
+        let payload = (0..size).map(|i| i as u8).collect::<Vec<_>>();
+        println!("--- {} MB payload ---", size / MB);
+        measure("oneshot", size, || Hash256::hash(&payload));
+        measure("incremental (64 KB chunks)", size, || {
+            let mut hasher = Hash256::hasher();
+            for chunk in payload.chunks(64 * 1024) {
+                hasher.update(chunk);
+            }
+            hasher.finalize()
+        });
+        measure("reader", size, || {
+            Hash256::hash_reader(payload.as_slice()).unwrap()
+        });
+        let transactions = payload.chunks(MB).collect::<Vec<_>>();
+        measure("batch (1 MB each)", size, || {
+            Hash256::hash_batch(&transactions)[0]
+        });
+    }
+}
@@ -0,0 +1,77 @@
+//! Verification of the finalization proofs of 100 validators, one by one and in parallel.
+//!
+//! Run with `cargo bench -p simperby-core --bench signature`.
+use simperby_core::*;
+use std::time::Instant;
+
+const VALIDATORS: usize = 100;
+
+fn measure(name: &str, f: impl Fn()) -> f64 {
+    let iterations = 20;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed().as_secs_f64() / iterations as f64;
+    println!("{name}: {:.2} ms", elapsed * 1000.0);
+    elapsed
+}
+
+fn bench<S: SignatureScheme>(scheme: &str) {
+    let keys = (0..VALIDATORS)
+        .map(|i| S::generate_keypair(format!("validator-{i}")))
+        .collect::<Vec<_>>();
+    let header = BlockHeader {
+        author: keys[0].0.clone(),
+        prev_block_finalization_proof: FinalizationProof::genesis(),
+        previous_hash: Hash256::zero(),
+        height: 1,
+        timestamp: 0,
+        commit_merkle_root: Hash256::zero(),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: keys
+            .iter()
+            .map(|(public_key, _)| (public_key.clone(), 1))
+            .collect(),
+        version: "0.0.0".to_owned(),
+    };
+    let proof = FinalizationProof {
+        round: 0,
+        signatures: keys
+            .iter()
+            .map(|(_, private_key)| {
+                let target = FinalizationSignTarget {
+                    block_hash: header.to_hash256(),
+                    round: 0,
+                    timestamp: 0,
+                };
+                PrecommitSignature(
+                    TypedSignature::sign_with::<S>(&target, private_key).unwrap(),
+                    target.timestamp,
+                )
+            })
+            .collect(),
+    };
+
+    println!("--- {scheme}, {VALIDATORS} validators ---");
+    let serial = measure("one by one", || {
+        let block_hash = header.to_hash256();
+        for precommit in &proof.signatures {
+            precommit.verify(block_hash, proof.round).unwrap();
+        }
+    });
+    let parallel = measure("verify_finalization_proof", || {
+        verify::verify_finalization_proof(&header, &proof).unwrap();
+    });
+    println!("speedup: {:.1}x", serial / parallel);
+}
+
+fn main() {
+    // The speedup is bounded by this.
+    println!(
+        "available parallelism: {}",
+        std::thread::available_parallelism().map_or(1, |x| x.get())
+    );
+    bench::<Secp256k1Scheme>("secp256k1");
+    bench::<Ed25519Scheme>("Ed25519");
+}
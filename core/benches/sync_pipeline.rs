@@ -0,0 +1,115 @@
+//! Verification of a 1,000-block chain, commit by commit and pipelined as the sync does
+//! (the commits hashed in parallel and the signatures verified in a batch across the blocks).
+//!
+//! Run with `cargo bench -p simperby-core --bench sync_pipeline`.
+use simperby_core::verify::{CommitSequenceVerifier, SignatureBatch};
+use simperby_core::*;
+use std::time::Instant;
+
+const BLOCKS: u64 = 1_000;
+/// Fewer than a proof is verified in parallel with, as in most of the chains.
+const VALIDATORS: usize = 7;
+
+fn measure(name: &str, f: impl Fn()) -> f64 {
+    let iterations = 3;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed().as_secs_f64() / iterations as f64;
+    println!("{name}: {:.2} ms", elapsed * 1000.0);
+    elapsed
+}
+
+/// Creates the commits of the chain, an agenda, its proof and the block for each height.
+fn create_chain(reserved_state: &ReservedState, keys: &[(PublicKey, PrivateKey)]) -> Vec<Commit> {
+    let mut commits = Vec::new();
+    let mut last_header = reserved_state.genesis_info.header.clone();
+    // The commits since the last block, of which the block commits to.
+    let mut block_commits = Vec::new();
+    for height in 1..=BLOCKS {
+        let timestamp = height as Timestamp;
+        let agenda = Agenda {
+            height,
+            author: reserved_state.members[0].name.clone(),
+            timestamp,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            description: None,
+            deadline: None,
+        };
+        let agenda_proof = AgendaProof {
+            height,
+            agenda_hash: agenda.to_hash256(),
+            proof: keys
+                .iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            timestamp,
+        };
+        for commit in [Commit::Agenda(agenda), Commit::AgendaProof(agenda_proof)] {
+            block_commits.push(commit.clone());
+            commits.push(commit);
+        }
+        let header = BlockHeader {
+            author: keys[0].0.clone(),
+            prev_block_finalization_proof: FinalizationProof {
+                round: 0,
+                signatures: keys
+                    .iter()
+                    .map(|(_, private_key)| {
+                        PrecommitSignature::sign(
+                            last_header.to_hash256(),
+                            0,
+                            timestamp,
+                            private_key,
+                        )
+                        .unwrap()
+                    })
+                    .collect(),
+            },
+            previous_hash: last_header.to_hash256(),
+            height,
+            timestamp,
+            commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&block_commits),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: last_header.validator_set.clone(),
+            version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+        };
+        block_commits = vec![Commit::Block(header.clone())];
+        commits.push(Commit::Block(header.clone()));
+        last_header = header;
+    }
+    commits
+}
+
+fn main() {
+    // The speedup is bounded by this.
+    println!(
+        "available parallelism: {}",
+        std::thread::available_parallelism().map_or(1, |x| x.get())
+    );
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(VALIDATORS);
+    let start_header = reserved_state.genesis_info.header.clone();
+    let commits = create_chain(&reserved_state, &keys);
+
+    println!("--- {BLOCKS} blocks, {VALIDATORS} validators ---");
+    let serial = measure("commit by commit", || {
+        let mut csv =
+            CommitSequenceVerifier::new(start_header.clone(), reserved_state.clone()).unwrap();
+        for commit in &commits {
+            csv.apply_commit(commit).unwrap();
+        }
+    });
+    let pipelined = measure("pipelined", || {
+        let mut csv =
+            CommitSequenceVerifier::new(start_header.clone(), reserved_state.clone()).unwrap();
+        let hashes = utils::map_in_parallel(&commits, 64, Commit::to_hash256);
+        let mut batch = SignatureBatch::default();
+        batch.add_commits(&start_header, &commits);
+        csv.set_verified_signatures(batch.verify());
+        for (commit, hash) in commits.iter().zip(hashes) {
+            csv.apply_commit_with_hash(commit, hash).unwrap();
+        }
+    });
+    println!("speedup: {:.1}x", serial / pipelined);
+}
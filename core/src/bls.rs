@@ -0,0 +1,447 @@
+//! BLS12-381 signature aggregation for finalization proofs.
+//!
+//! A [`FinalizationProof`] carries one signature per validator, so it grows linearly
+//! with the validator set. An [`AggregatedFinalizationProof`] instead carries a single
+//! aggregated BLS signature with a bitmap of the signers.
+//!
+//! The types are always available so that the data format doesn't depend on the build,
+//! but signing and verification require the `bls` feature.
+use crate::prelude::*;
+use crate::verify::Error;
+use crate::*;
+use serde::{Deserialize, Serialize};
+
+/// The first protocol version whose finalization proofs are aggregated.
+pub const AGGREGATED_FINALIZATION_PROOF_VERSION: &str = "0.2.0";
+
+#[cfg(feature = "bls")]
+const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_AUG_";
+
+/// A BLS public key, in the compressed form.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlsPublicKey {
+    key: HexSerializedBytes<48>,
+}
+
+/// A BLS private key.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlsPrivateKey {
+    key: HexSerializedBytes<32>,
+}
+
+/// A BLS signature (possibly aggregated), in the compressed form.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlsSignature {
+    signature: HexSerializedBytes<96>,
+}
+
+/// The scheme of the finalization proofs, decided by the protocol version of the reserved state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalizationProofScheme {
+    /// One secp256k1 signature per validator ([`FinalizationProof`]).
+    Individual,
+    /// A single aggregated BLS signature ([`AggregatedFinalizationProof`]).
+    Aggregated,
+}
+
+impl FinalizationProofScheme {
+    pub fn from_version(version: &str) -> Result<Self, Error> {
+        if crate::verify::version_at_least(version, AGGREGATED_FINALIZATION_PROOF_VERSION)? {
+            Ok(Self::Aggregated)
+        } else {
+            Ok(Self::Individual)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AggregatedFinalizationProof {
+    pub round: ConsensusRound,
+    /// The `i`-th bit (in the little-endian order) is set if the `i`-th validator
+    /// of the header's validator set signed.
+    pub signers: HexSerializedVec,
+    /// The timestamps signed along, in the order of the signers.
+    pub timestamps: Vec<Timestamp>,
+    pub signature: BlsSignature,
+}
+
+impl AggregatedFinalizationProof {
+    /// Returns the indices of the signers in the validator set.
+    pub fn signer_indices(&self) -> Vec<usize> {
+        self.signers
+            .data
+            .iter()
+            .enumerate()
+            .flat_map(|(i, byte)| {
+                (0..8)
+                    .filter(move |j| byte & (1 << j) != 0)
+                    .map(move |j| i * 8 + j)
+            })
+            .collect()
+    }
+}
+
+/// A finalization proof in either scheme.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum AnyFinalizationProof {
+    Individual(FinalizationProof),
+    Aggregated(AggregatedFinalizationProof),
+}
+
+/// Verifies the finalization proof of the given block header,
+/// checking that the scheme is the one that `reserved_state` uses.
+pub fn verify_any_finalization_proof(
+    reserved_state: &ReservedState,
+    header: &BlockHeader,
+    proof: &AnyFinalizationProof,
+) -> Result<(), Error> {
+    match (
+        FinalizationProofScheme::from_version(&reserved_state.version)?,
+        proof,
+    ) {
+        (FinalizationProofScheme::Individual, AnyFinalizationProof::Individual(proof)) => {
+            verify::verify_finalization_proof(header, proof)
+        }
+        (FinalizationProofScheme::Aggregated, AnyFinalizationProof::Aggregated(proof)) => {
+            let bls_public_keys = header
+                .validator_set
+                .iter()
+                .map(|(public_key, _)| {
+                    reserved_state
+                        .query_bls_public_key(public_key)
+                        .ok_or_else(|| {
+                            Error::InvalidArgument(format!(
+                                "the BLS public key of {public_key} is not registered"
+                            ))
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            verify_aggregated_finalization_proof(header, &bls_public_keys, proof)
+        }
+        (scheme, _) => Err(Error::InvalidProof(format!(
+            "invalid finalization proof - expected the {scheme:?} scheme"
+        ))),
+    }
+}
+
+/// Verifies the aggregated finalization proof of the given block header.
+///
+/// `bls_public_keys` are the BLS public keys of the header's validator set, in the same order.
+#[cfg(feature = "bls")]
+pub fn verify_aggregated_finalization_proof(
+    header: &BlockHeader,
+    bls_public_keys: &[BlsPublicKey],
+    proof: &AggregatedFinalizationProof,
+) -> Result<(), Error> {
+    let signer_indices = proof.signer_indices();
+    if signer_indices.len() != proof.timestamps.len() {
+        return Err(Error::InvalidProof(
+            "invalid finalization proof - the number of timestamps doesn't match the signers"
+                .to_owned(),
+        ));
+    }
+    if signer_indices
+        .iter()
+        .any(|i| *i >= header.validator_set.len())
+        || bls_public_keys.len() != header.validator_set.len()
+    {
+        return Err(Error::InvalidProof(
+            "invalid finalization proof - signer out of the validator set".to_owned(),
+        ));
+    }
+    let messages = signer_indices
+        .iter()
+        .zip(&proof.timestamps)
+        .map(|(i, timestamp)| {
+            signing_message(
+                FinalizationSignTarget {
+                    block_hash: header.to_hash256(),
+                    round: proof.round,
+                    timestamp: *timestamp,
+                }
+                .to_hash256(),
+                &bls_public_keys[*i],
+            )
+        })
+        .collect::<Vec<_>>();
+    let public_keys = signer_indices
+        .iter()
+        .map(|i| bls_public_keys[*i].to_blst())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::CryptoError("invalid finalization proof".to_owned(), e))?;
+    let signature = proof
+        .signature
+        .to_blst()
+        .map_err(|e| Error::CryptoError("invalid finalization proof".to_owned(), e))?;
+    let result = signature.aggregate_verify(
+        true,
+        &messages.iter().map(|x| x.as_slice()).collect::<Vec<_>>(),
+        DST,
+        &public_keys.iter().collect::<Vec<_>>(),
+        true,
+    );
+    if result != blst::BLST_ERROR::BLST_SUCCESS {
+        return Err(Error::CryptoError(
+            "invalid finalization proof".to_owned(),
+            CryptoError::VerificationFailed,
+        ));
+    }
+    let total_voting_power: VotingPower = header.validator_set.iter().map(|(_, v)| v).sum();
+    let voted_voting_power: VotingPower = signer_indices
+        .iter()
+        .map(|i| header.validator_set[*i].1)
+        .sum();
+    if voted_voting_power * 3 <= total_voting_power * 2 {
+        return Err(Error::InvalidProof(format!(
+            "invalid finalization proof - voted voting power is too low: {voted_voting_power} / {total_voting_power}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "bls"))]
+pub fn verify_aggregated_finalization_proof(
+    _header: &BlockHeader,
+    _bls_public_keys: &[BlsPublicKey],
+    _proof: &AggregatedFinalizationProof,
+) -> Result<(), Error> {
+    Err(Error::InvalidArgument(
+        "BLS signatures are not supported; build with the `bls` feature".to_owned(),
+    ))
+}
+
+/// The signer's public key is appended to the message (the 'message augmentation' scheme),
+/// so that the messages in an aggregate are always distinct and rogue-key attacks are prevented.
+#[cfg(feature = "bls")]
+fn signing_message(data: Hash256, public_key: &BlsPublicKey) -> Vec<u8> {
+    [data.as_ref(), public_key.key.data.as_ref()].concat()
+}
+
+#[cfg(feature = "bls")]
+impl BlsPublicKey {
+    fn to_blst(&self) -> Result<blst::min_pk::PublicKey, CryptoError> {
+        blst::min_pk::PublicKey::uncompress(&self.key.data)
+            .map_err(|_| CryptoError::InvalidFormat(format!("BLS public key: {}", self.key)))
+    }
+}
+
+#[cfg(feature = "bls")]
+impl BlsPrivateKey {
+    pub fn public_key(&self) -> BlsPublicKey {
+        BlsPublicKey {
+            key: HexSerializedBytes {
+                data: self.to_blst().sk_to_pk().compress(),
+            },
+        }
+    }
+
+    fn to_blst(&self) -> blst::min_pk::SecretKey {
+        blst::min_pk::SecretKey::from_bytes(&self.key.data).expect("invalid BLS private key")
+    }
+}
+
+#[cfg(feature = "bls")]
+impl BlsSignature {
+    /// Creates a new signature from the given data and key.
+    pub fn sign(data: Hash256, private_key: &BlsPrivateKey) -> Self {
+        let message = signing_message(data, &private_key.public_key());
+        BlsSignature {
+            signature: HexSerializedBytes {
+                data: private_key.to_blst().sign(&message, DST, &[]).compress(),
+            },
+        }
+    }
+
+    /// Verifies the signature against the given data and public key.
+    pub fn verify(&self, data: Hash256, public_key: &BlsPublicKey) -> Result<(), CryptoError> {
+        let result = self.to_blst()?.verify(
+            true,
+            &signing_message(data, public_key),
+            DST,
+            &[],
+            &public_key.to_blst()?,
+            true,
+        );
+        if result == blst::BLST_ERROR::BLST_SUCCESS {
+            Ok(())
+        } else {
+            Err(CryptoError::VerificationFailed)
+        }
+    }
+
+    /// Aggregates the given signatures into one.
+    pub fn aggregate(signatures: &[BlsSignature]) -> Result<Self, CryptoError> {
+        let signatures = signatures
+            .iter()
+            .map(|x| x.to_blst())
+            .collect::<Result<Vec<_>, _>>()?;
+        let aggregated = blst::min_pk::AggregateSignature::aggregate(
+            &signatures.iter().collect::<Vec<_>>(),
+            true,
+        )
+        .map_err(|_| CryptoError::InvalidFormat("empty or invalid signatures".to_owned()))?;
+        Ok(BlsSignature {
+            signature: HexSerializedBytes {
+                data: aggregated.to_signature().compress(),
+            },
+        })
+    }
+
+    fn to_blst(&self) -> Result<blst::min_pk::Signature, CryptoError> {
+        blst::min_pk::Signature::uncompress(&self.signature.data)
+            .map_err(|_| CryptoError::InvalidFormat(format!("BLS signature: {}", self.signature)))
+    }
+}
+
+#[cfg(feature = "bls")]
+impl AggregatedFinalizationProof {
+    /// Aggregates the precommit signatures on the given header.
+    ///
+    /// Each precommit is given with the public key of the validator, the timestamp and the signature.
+    pub fn aggregate(
+        header: &BlockHeader,
+        round: ConsensusRound,
+        mut precommits: Vec<(PublicKey, Timestamp, BlsSignature)>,
+    ) -> Result<Self, Error> {
+        let index_of = |public_key: &PublicKey| {
+            header
+                .validator_set
+                .iter()
+                .position(|(x, _)| x == public_key)
+                .ok_or_else(|| Error::InvalidArgument(format!("{public_key} is not a validator")))
+        };
+        for (public_key, _, _) in &precommits {
+            index_of(public_key)?;
+        }
+        precommits.sort_by_key(|(public_key, _, _)| index_of(public_key).unwrap());
+        precommits.dedup_by(|x, y| x.0 == y.0);
+        let mut signers = vec![0u8; header.validator_set.len().div_ceil(8)];
+        for (public_key, _, _) in &precommits {
+            let i = index_of(public_key)?;
+            signers[i / 8] |= 1 << (i % 8);
+        }
+        let signature = BlsSignature::aggregate(
+            &precommits
+                .iter()
+                .map(|(_, _, signature)| signature.clone())
+                .collect::<Vec<_>>(),
+        )
+        .map_err(|e| Error::CryptoError("failed to aggregate".to_owned(), e))?;
+        Ok(Self {
+            round,
+            signers: signers.into(),
+            timestamps: precommits
+                .iter()
+                .map(|(_, timestamp, _)| *timestamp)
+                .collect(),
+            signature,
+        })
+    }
+}
+
+/// Generates a new BLS keypair using the seed.
+#[cfg(feature = "bls")]
+pub fn generate_bls_keypair(seed: impl AsRef<[u8]>) -> (BlsPublicKey, BlsPrivateKey) {
+    let private_key = blst::min_pk::SecretKey::key_gen(Hash256::hash(seed).as_ref(), &[])
+        .expect("the seed is long enough");
+    let private_key = BlsPrivateKey {
+        key: HexSerializedBytes {
+            data: private_key.to_bytes(),
+        },
+    };
+    (private_key.public_key(), private_key)
+}
+
+#[cfg(all(test, feature = "bls"))]
+mod tests {
+    use super::*;
+    use crate::test_utils::generate_standard_genesis;
+
+    fn setup(signers: usize) -> (ReservedState, BlockHeader, AggregatedFinalizationProof) {
+        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        reserved_state.version = AGGREGATED_FINALIZATION_PROOF_VERSION.to_owned();
+        let bls_keys = (0..4)
+            .map(|i| generate_bls_keypair(format!("bls-{i}")))
+            .collect::<Vec<_>>();
+        for (member, (bls_public_key, _)) in reserved_state.members.iter_mut().zip(&bls_keys) {
+            member.bls_public_key = Some(bls_public_key.clone());
+        }
+        let header = reserved_state.genesis_info.header.clone();
+        let precommits = keys
+            .iter()
+            .zip(&bls_keys)
+            .take(signers)
+            .enumerate()
+            .map(|(i, ((public_key, _), (_, bls_private_key)))| {
+                let target = FinalizationSignTarget {
+                    block_hash: header.to_hash256(),
+                    round: 0,
+                    timestamp: i as Timestamp,
+                };
+                (
+                    public_key.clone(),
+                    i as Timestamp,
+                    BlsSignature::sign(target.to_hash256(), bls_private_key),
+                )
+            })
+            .rev()
+            .collect();
+        let proof = AggregatedFinalizationProof::aggregate(&header, 0, precommits).unwrap();
+        (reserved_state, header, proof)
+    }
+
+    #[test]
+    fn aggregate_and_verify() {
+        let (reserved_state, header, proof) = setup(3);
+        assert_eq!(proof.signer_indices(), vec![0, 1, 2]);
+        verify_any_finalization_proof(
+            &reserved_state,
+            &header,
+            &AnyFinalizationProof::Aggregated(proof.clone()),
+        )
+        .unwrap();
+        // The reserved state requires the aggregated scheme.
+        verify_any_finalization_proof(
+            &reserved_state,
+            &header,
+            &AnyFinalizationProof::Individual(FinalizationProof::genesis()),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn reject_insufficient_or_forged_signers() {
+        let (reserved_state, header, proof) = setup(2);
+        verify_any_finalization_proof(
+            &reserved_state,
+            &header,
+            &AnyFinalizationProof::Aggregated(proof),
+        )
+        .unwrap_err();
+
+        let (reserved_state, header, mut proof) = setup(3);
+        proof.signers.data[0] = 0b1011;
+        proof.timestamps = vec![0, 1, 3];
+        verify_any_finalization_proof(
+            &reserved_state,
+            &header,
+            &AnyFinalizationProof::Aggregated(proof),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn scheme_by_version() {
+        assert_eq!(
+            FinalizationProofScheme::from_version(SIMPERBY_CORE_PROTOCOL_VERSION).unwrap(),
+            FinalizationProofScheme::Individual
+        );
+        assert_eq!(
+            FinalizationProofScheme::from_version("0.10.0").unwrap(),
+            FinalizationProofScheme::Aggregated
+        );
+    }
+}
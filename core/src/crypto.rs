@@ -1,14 +1,19 @@
 //! A set of types and functions related to cryptography, that are widely used in the entire Simperby project.
+use crate::prelude::*;
+use core::fmt;
 use secp256k1::{
     ecdsa::{RecoverableSignature, RecoveryId},
     Message, Secp256k1, SecretKey,
 };
 use serde::{ser::SerializeTuple, Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
-use std::fmt;
 use thiserror::Error;
 
 const EVM_EC_RECOVERY_OFFSET: u8 = 27;
+/// The first byte of an uncompressed secp256k1 public key.
+const SECP256K1_UNCOMPRESSED_TAG: u8 = 0x04;
+/// The tag of Ed25519, placed in the first byte of a public key and the last byte of a signature.
+const ED25519_TAG: u8 = 0xed;
 
 #[derive(Error, Debug, Clone)]
 pub enum CryptoError {
@@ -205,6 +210,35 @@ impl Hash256 {
         }
     }
 
+    /// Creates an incremental hasher, which produces the same hash as [`Hash256::hash`]
+    /// on the concatenation of the written data.
+    pub fn hasher() -> HashWriter {
+        HashWriter {
+            hasher: Keccak256::new(),
+        }
+    }
+
+    /// Hashes the data from the reader without buffering it fully in memory.
+    #[cfg(feature = "std")]
+    pub fn hash_reader(mut reader: impl std::io::Read) -> std::io::Result<Self> {
+        let mut hasher = Self::hasher();
+        std::io::copy(&mut reader, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
+    /// Hashes each of the given data, in parallel if they are large enough.
+    pub fn hash_batch<T: AsRef<[u8]> + Sync>(data: &[T]) -> Vec<Self> {
+        /// Below this size in total, spawning threads costs more than hashing.
+        const PARALLEL_THRESHOLD: usize = 1 << 20;
+        let total_size: usize = data.iter().map(|x| x.as_ref().len()).sum();
+        let threshold = if total_size < PARALLEL_THRESHOLD {
+            usize::MAX
+        } else {
+            2
+        };
+        crate::utils::map_in_parallel(data, threshold, |x| Self::hash(x))
+    }
+
     pub fn from_array(data: [u8; 32]) -> Self {
         Hash256 {
             hash: HexSerializedBytes { data },
@@ -216,7 +250,7 @@ impl Hash256 {
     }
 }
 
-impl std::convert::AsRef<[u8]> for Hash256 {
+impl AsRef<[u8]> for Hash256 {
     fn as_ref(&self) -> &[u8] {
         &self.hash.data
     }
@@ -228,6 +262,45 @@ impl fmt::Display for Hash256 {
     }
 }
 
+/// An incremental hasher created by [`Hash256::hasher`].
+#[derive(Clone)]
+pub struct HashWriter {
+    hasher: Keccak256,
+}
+
+impl HashWriter {
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.hasher.update(data);
+    }
+
+    pub fn finalize(self) -> Hash256 {
+        Hash256 {
+            hash: HexSerializedBytes {
+                data: self.hasher.finalize().as_slice().try_into().unwrap(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl bincode::enc::write::Writer for HashWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), bincode::error::EncodeError> {
+        self.update(bytes);
+        Ok(())
+    }
+}
+
 /// A cryptographic signature.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -242,37 +315,33 @@ impl Signature {
         }
     }
 
-    /// Creates a new signature from the given data and keys.
+    /// Creates a new signature from the given data and keys, using the [`DefaultScheme`].
     pub fn sign(data: Hash256, private_key: &PrivateKey) -> Result<Self, Error> {
-        let private_key = secp256k1::SecretKey::from_slice(&private_key.key.data)
-            .map_err(|_| Error::InvalidFormat("private key: [omitted]".to_owned()))?;
-        let message = Message::from_slice(data.as_ref()).unwrap();
-        let (recovery_id, rs) = Secp256k1::signing_only()
-            .sign_ecdsa_recoverable(&message, &private_key)
-            .serialize_compact();
-        let v = recovery_id.to_i32() as u8;
-        let bytes: [u8; 65] = {
-            let mut whole: [u8; 65] = [0; 65];
-            let (left, right) = whole.split_at_mut(rs.len());
-            left.copy_from_slice(&rs);
-            right.copy_from_slice(&[v + EVM_EC_RECOVERY_OFFSET; 1]);
-            whole
-        };
-        Ok(Signature {
-            signature: HexSerializedBytes { data: bytes },
-        })
+        DefaultScheme::sign(data, private_key)
     }
 
-    /// Verifies the signature against the given data and public key.
+    /// Verifies the signature against the given data and public key,
+    /// using the scheme that the encodings of them indicate.
     pub fn verify(&self, data: Hash256, public_key: &PublicKey) -> Result<(), Error> {
-        let signature = secp256k1::ecdsa::Signature::from_compact(&self.signature.data[0..64])
-            .map_err(|_| Error::InvalidFormat(format!("signature: {self}")))?;
-        let public_key = secp256k1::PublicKey::from_slice(&public_key.key.data)
-            .map_err(|_| Error::InvalidFormat(format!("public_key: {public_key}")))?;
-        let message = Message::from_slice(data.as_ref()).unwrap();
-        Secp256k1::verification_only()
-            .verify_ecdsa(&message, &signature, &public_key)
-            .map_err(|_| Error::VerificationFailed)
+        let algorithm = self.algorithm()?;
+        if algorithm != public_key.algorithm()? {
+            return Err(Error::InvalidFormat(format!(
+                "the public key is not of {algorithm:?}"
+            )));
+        }
+        match algorithm {
+            SignatureAlgorithm::Secp256k1 => Secp256k1Scheme::verify(data, self, public_key),
+            SignatureAlgorithm::Ed25519 => Ed25519Scheme::verify(data, self, public_key),
+        }
+    }
+
+    /// Returns the algorithm of the signature, which is embedded in the last byte.
+    pub fn algorithm(&self) -> Result<SignatureAlgorithm, Error> {
+        match self.signature.data[64] {
+            ED25519_TAG => Ok(SignatureAlgorithm::Ed25519),
+            v if v >= EVM_EC_RECOVERY_OFFSET => Ok(SignatureAlgorithm::Secp256k1),
+            _ => Err(Error::InvalidFormat(format!("signature: {self}"))),
+        }
     }
 
     /// Recover a public key from the given signature.
@@ -283,7 +352,6 @@ impl Signature {
         )
         .unwrap();
         if recovery_id.to_i32() != 0 && recovery_id.to_i32() != 1 {
-            println!("recid: {}", recovery_id.to_i32());
             return Err(Error::VerificationFailed);
         }
         let signature =
@@ -312,7 +380,7 @@ pub struct TypedSignature<T> {
     signature: Signature,
     signer: PublicKey,
     #[serde(skip)]
-    _mark: std::marker::PhantomData<T>,
+    _mark: core::marker::PhantomData<T>,
 }
 
 impl<T: ToHash256> TypedSignature<T> {
@@ -322,7 +390,30 @@ impl<T: ToHash256> TypedSignature<T> {
         Signature::sign(data, private_key).map(|signature| TypedSignature {
             signature,
             signer: private_key.public_key(),
-            _mark: std::marker::PhantomData,
+            _mark: core::marker::PhantomData,
+        })
+    }
+
+    /// Creates a new signature from the given data and keys, using the given scheme.
+    pub fn sign_with<S: SignatureScheme>(
+        data: &T,
+        private_key: &PrivateKey,
+    ) -> Result<Self, Error> {
+        let data = data.to_hash256();
+        Ok(TypedSignature {
+            signature: S::sign(data, private_key)?,
+            signer: S::public_key(private_key)?,
+            _mark: core::marker::PhantomData,
+        })
+    }
+
+    /// Creates a new signature from the given data, by the given signer.
+    pub fn sign_by<S: Signer + ?Sized>(data: &T, signer: &S) -> Result<Self, Error> {
+        let data = data.to_hash256();
+        Ok(TypedSignature {
+            signature: signer.sign(data)?,
+            signer: signer.public_key()?,
+            _mark: core::marker::PhantomData,
         })
     }
 
@@ -330,7 +421,7 @@ impl<T: ToHash256> TypedSignature<T> {
         TypedSignature {
             signature,
             signer,
-            _mark: std::marker::PhantomData,
+            _mark: core::marker::PhantomData,
         }
     }
 
@@ -349,7 +440,7 @@ impl<T: ToHash256> TypedSignature<T> {
     }
 }
 
-impl std::convert::AsRef<[u8]> for Signature {
+impl AsRef<[u8]> for Signature {
     fn as_ref(&self) -> &[u8] {
         &self.signature.data
     }
@@ -368,7 +459,7 @@ pub struct PublicKey {
     key: HexSerializedBytes<65>,
 }
 
-impl std::convert::AsRef<[u8]> for PublicKey {
+impl AsRef<[u8]> for PublicKey {
     fn as_ref(&self) -> &[u8] {
         &self.key.data
     }
@@ -381,6 +472,15 @@ impl fmt::Display for PublicKey {
 }
 
 impl PublicKey {
+    /// Returns the algorithm of the public key, which is embedded in the first byte.
+    pub fn algorithm(&self) -> Result<SignatureAlgorithm, Error> {
+        match self.key.data[0] {
+            SECP256K1_UNCOMPRESSED_TAG => Ok(SignatureAlgorithm::Secp256k1),
+            ED25519_TAG => Ok(SignatureAlgorithm::Ed25519),
+            _ => Err(Error::InvalidFormat(format!("public key: {self}"))),
+        }
+    }
+
     pub fn zero() -> Self {
         Self {
             key: HexSerializedBytes::zero(),
@@ -413,7 +513,7 @@ pub struct PrivateKey {
     pub key: HexSerializedBytes<32>,
 }
 
-impl std::convert::AsRef<[u8]> for PrivateKey {
+impl AsRef<[u8]> for PrivateKey {
     fn as_ref(&self) -> &[u8] {
         &self.key.data
     }
@@ -435,11 +535,9 @@ impl PrivateKey {
         })
     }
 
+    /// Returns the public key of the [`DefaultScheme`].
     pub fn public_key(&self) -> PublicKey {
-        let private_key = SecretKey::from_slice(&self.key.data).expect("invalid private key");
-        let secp = Secp256k1::new();
-        let public_key = private_key.public_key(&secp);
-        PublicKey::from_array(public_key.serialize()).expect("invalid public key")
+        DefaultScheme::public_key(self).expect("invalid private key")
     }
 }
 
@@ -450,23 +548,28 @@ pub fn check_keypair_match(public_key: &PublicKey, private_key: &PrivateKey) ->
     signature.verify(Hash256::hash(msg), public_key)
 }
 
-/// Generates a new keypair using the seed.
+/// Runs `verify` on each item, in parallel if there are many,
+/// returning the results in the order of the items.
+///
+/// It is meant for the signatures that come in dozens, like those of a finalization proof,
+/// where verifying them one by one dominates the time to sync.
+pub fn verify_in_parallel<T: Sync>(
+    items: &[T],
+    verify: impl Fn(&T) -> Result<(), Error> + Sync,
+) -> Vec<Result<(), Error>> {
+    /// Below this number of items, spawning threads costs more than verifying.
+    const PARALLEL_THRESHOLD: usize = 8;
+    crate::utils::map_in_parallel(items, PARALLEL_THRESHOLD, verify)
+}
+
+/// Generates a new keypair using the seed, with the [`DefaultScheme`].
+#[cfg(feature = "keygen")]
 pub fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey) {
-    let mut seed_: [u8; 32] = [0; 32];
-    for (i, x) in Hash256::hash(seed).as_ref()[0..32].iter().enumerate() {
-        seed_[i] = *x;
-    }
-    use secp256k1::rand::SeedableRng;
-    let mut rng = secp256k1::rand::rngs::StdRng::from_seed(seed_);
-    let secp = Secp256k1::new();
-    let (private_key, public_key) = secp.generate_keypair(&mut rng);
-    (
-        PublicKey::from_array(public_key.serialize()).expect("invalid public key"),
-        PrivateKey::from_array(private_key.secret_bytes()).expect("invalid private key"),
-    )
+    DefaultScheme::generate_keypair(seed)
 }
 
 /// Generates a new keypair randomly
+#[cfg(feature = "keygen")]
 pub fn generate_keypair_random() -> (PublicKey, PrivateKey) {
     use secp256k1::rand::SeedableRng;
     let mut rng = secp256k1::rand::rngs::StdRng::from_entropy();
@@ -478,6 +581,187 @@ pub fn generate_keypair_random() -> (PublicKey, PrivateKey) {
     )
 }
 
+/// The algorithm of a key or a signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureAlgorithm {
+    Secp256k1,
+    Ed25519,
+}
+
+/// A digital signature scheme that backs [`PublicKey`], [`PrivateKey`] and [`Signature`].
+///
+/// The encodings of public keys and signatures are of fixed length regardless of the scheme,
+/// and carry the algorithm so that [`Signature::verify`] can pick the right scheme.
+/// A [`PrivateKey`] doesn't carry it, so signing must be done with a specific scheme.
+pub trait SignatureScheme {
+    const ALGORITHM: SignatureAlgorithm;
+
+    /// Generates a new keypair using the seed.
+    #[cfg(feature = "keygen")]
+    fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey);
+
+    fn public_key(private_key: &PrivateKey) -> Result<PublicKey, Error>;
+
+    fn sign(data: Hash256, private_key: &PrivateKey) -> Result<Signature, Error>;
+
+    fn verify(data: Hash256, signature: &Signature, public_key: &PublicKey) -> Result<(), Error>;
+}
+
+/// Something that signs on behalf of a key, without necessarily exposing the [`PrivateKey`],
+/// e.g. a hardware wallet.
+pub trait Signer {
+    fn public_key(&self) -> Result<PublicKey, Error>;
+
+    fn sign(&self, data: Hash256) -> Result<Signature, Error>;
+}
+
+impl Signer for PrivateKey {
+    fn public_key(&self) -> Result<PublicKey, Error> {
+        DefaultScheme::public_key(self)
+    }
+
+    fn sign(&self, data: Hash256) -> Result<Signature, Error> {
+        DefaultScheme::sign(data, self)
+    }
+}
+
+/// The scheme used by [`Signature::sign`], [`PrivateKey::public_key`] and [`generate_keypair`].
+pub type DefaultScheme = Secp256k1Scheme;
+
+/// ECDSA over secp256k1 with the EVM-compatible recoverable signatures.
+///
+/// A public key is encoded as an uncompressed point, and a signature as `r || s || v`.
+pub struct Secp256k1Scheme;
+
+impl SignatureScheme for Secp256k1Scheme {
+    const ALGORITHM: SignatureAlgorithm = SignatureAlgorithm::Secp256k1;
+
+    #[cfg(feature = "keygen")]
+    fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey) {
+        let mut seed_: [u8; 32] = [0; 32];
+        for (i, x) in Hash256::hash(seed).as_ref()[0..32].iter().enumerate() {
+            seed_[i] = *x;
+        }
+        use secp256k1::rand::SeedableRng;
+        let mut rng = secp256k1::rand::rngs::StdRng::from_seed(seed_);
+        let secp = Secp256k1::new();
+        let (private_key, public_key) = secp.generate_keypair(&mut rng);
+        (
+            PublicKey::from_array(public_key.serialize()).expect("invalid public key"),
+            PrivateKey::from_array(private_key.secret_bytes()).expect("invalid private key"),
+        )
+    }
+
+    fn public_key(private_key: &PrivateKey) -> Result<PublicKey, Error> {
+        let private_key = SecretKey::from_slice(&private_key.key.data)
+            .map_err(|_| Error::InvalidFormat("private key: [omitted]".to_owned()))?;
+        let secp = Secp256k1::new();
+        PublicKey::from_array(private_key.public_key(&secp).serialize())
+    }
+
+    fn sign(data: Hash256, private_key: &PrivateKey) -> Result<Signature, Error> {
+        let private_key = secp256k1::SecretKey::from_slice(&private_key.key.data)
+            .map_err(|_| Error::InvalidFormat("private key: [omitted]".to_owned()))?;
+        let message = Message::from_slice(data.as_ref()).unwrap();
+        let (recovery_id, rs) = Secp256k1::signing_only()
+            .sign_ecdsa_recoverable(&message, &private_key)
+            .serialize_compact();
+        let v = recovery_id.to_i32() as u8;
+        let bytes: [u8; 65] = {
+            let mut whole: [u8; 65] = [0; 65];
+            let (left, right) = whole.split_at_mut(rs.len());
+            left.copy_from_slice(&rs);
+            right.copy_from_slice(&[v + EVM_EC_RECOVERY_OFFSET; 1]);
+            whole
+        };
+        Ok(Signature {
+            signature: HexSerializedBytes { data: bytes },
+        })
+    }
+
+    fn verify(data: Hash256, signature: &Signature, public_key: &PublicKey) -> Result<(), Error> {
+        let signature_ =
+            secp256k1::ecdsa::Signature::from_compact(&signature.signature.data[0..64])
+                .map_err(|_| Error::InvalidFormat(format!("signature: {signature}")))?;
+        let public_key = secp256k1::PublicKey::from_slice(&public_key.key.data)
+            .map_err(|_| Error::InvalidFormat(format!("public_key: {public_key}")))?;
+        let message = Message::from_slice(data.as_ref()).unwrap();
+        Secp256k1::verification_only()
+            .verify_ecdsa(&message, &signature_, &public_key)
+            .map_err(|_| Error::VerificationFailed)
+    }
+}
+
+/// Ed25519, for the interoperability with the tools that don't support secp256k1.
+///
+/// A public key is encoded as `0xed || key || zero padding`, and a signature as `signature || 0xed`.
+pub struct Ed25519Scheme;
+
+impl Ed25519Scheme {
+    fn signing_key(private_key: &PrivateKey) -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&private_key.key.data)
+    }
+
+    fn verifying_key(public_key: &PublicKey) -> Result<ed25519_dalek::VerifyingKey, Error> {
+        if public_key.key.data[0] != ED25519_TAG
+            || public_key.key.data[33..].iter().any(|x| *x != 0)
+        {
+            return Err(Error::InvalidFormat(format!("public key: {public_key}")));
+        }
+        ed25519_dalek::VerifyingKey::from_bytes(public_key.key.data[1..33].try_into().unwrap())
+            .map_err(|_| Error::InvalidFormat(format!("public key: {public_key}")))
+    }
+}
+
+impl SignatureScheme for Ed25519Scheme {
+    const ALGORITHM: SignatureAlgorithm = SignatureAlgorithm::Ed25519;
+
+    #[cfg(feature = "keygen")]
+    fn generate_keypair(seed: impl AsRef<[u8]>) -> (PublicKey, PrivateKey) {
+        let private_key = PrivateKey {
+            key: HexSerializedBytes {
+                data: Hash256::hash(seed).hash.data,
+            },
+        };
+        (
+            Self::public_key(&private_key).expect("invalid private key"),
+            private_key,
+        )
+    }
+
+    fn public_key(private_key: &PrivateKey) -> Result<PublicKey, Error> {
+        let mut data = [0; 65];
+        data[0] = ED25519_TAG;
+        data[1..33].copy_from_slice(Self::signing_key(private_key).verifying_key().as_bytes());
+        Ok(PublicKey {
+            key: HexSerializedBytes { data },
+        })
+    }
+
+    fn sign(data: Hash256, private_key: &PrivateKey) -> Result<Signature, Error> {
+        use ed25519_dalek::Signer;
+        let mut bytes = [0; 65];
+        bytes[0..64].copy_from_slice(
+            &Self::signing_key(private_key)
+                .sign(data.as_ref())
+                .to_bytes(),
+        );
+        bytes[64] = ED25519_TAG;
+        Ok(Signature {
+            signature: HexSerializedBytes { data: bytes },
+        })
+    }
+
+    fn verify(data: Hash256, signature: &Signature, public_key: &PublicKey) -> Result<(), Error> {
+        let signature = ed25519_dalek::Signature::from_bytes(
+            signature.signature.data[0..64].try_into().unwrap(),
+        );
+        Self::verifying_key(public_key)?
+            .verify_strict(data.as_ref(), &signature)
+            .map_err(|_| Error::VerificationFailed)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -556,6 +840,31 @@ mod tests {
             .unwrap_err();
     }
 
+    #[test]
+    fn verify_parallel() {
+        let signatures = (0..50)
+            .map(|i| {
+                let (public_key, private_key) = generate_keypair(format!("{i}"));
+                let data = Hash256::hash(format!("{i}"));
+                let signature = Signature::sign(data, &private_key).unwrap();
+                (data, signature, public_key)
+            })
+            .collect::<Vec<_>>();
+        let mut invalid = signatures.clone();
+        invalid[7].0 = Hash256::hash("something else");
+        invalid[42].2 = signatures[41].2.clone();
+        let results = verify_in_parallel(&invalid, |(data, signature, public_key)| {
+            signature.verify(*data, public_key)
+        });
+        let failed = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| result.is_err())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, vec![7, 42]);
+    }
+
     #[test]
     fn compressed() {
         let public_key = "0479c0e6973634b801da80fdf9274c13e327880e6360ca7735877f16e6a903c811afc2f0bb2c17de59110b022956dee0d625a694132b0da03fbba8ccdca219657c";
@@ -579,4 +888,46 @@ mod tests {
             hex::encode(recovered.as_ref())
         );
     }
+
+    #[test]
+    fn ed25519() {
+        let (public_key, private_key) = Ed25519Scheme::generate_keypair("hello world");
+        assert_eq!(public_key.algorithm().unwrap(), SignatureAlgorithm::Ed25519);
+        let signature = Ed25519Scheme::sign(Hash256::hash("hello world"), &private_key).unwrap();
+        assert_eq!(signature.algorithm().unwrap(), SignatureAlgorithm::Ed25519);
+        let encoded = serde_spb::to_string(&signature).unwrap();
+        let decoded: Signature = serde_spb::from_str(&encoded).unwrap();
+        decoded
+            .verify(Hash256::hash("hello world"), &public_key)
+            .unwrap();
+        signature
+            .verify(Hash256::hash("hello world2"), &public_key)
+            .unwrap_err();
+        // The algorithms of the signature and the public key must match.
+        let (secp256k1_public_key, _) = generate_keypair("hello world");
+        signature
+            .verify(Hash256::hash("hello world"), &secp256k1_public_key)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn incremental_hash() {
+        let data = (0..300_000u32)
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut hasher = Hash256::hasher();
+        for chunk in data.chunks(777) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), Hash256::hash(&data));
+        assert_eq!(
+            Hash256::hash_reader(data.as_slice()).unwrap(),
+            Hash256::hash(&data)
+        );
+        let batch = data.chunks(1000).collect::<Vec<_>>();
+        assert_eq!(
+            Hash256::hash_batch(&batch),
+            batch.iter().map(Hash256::hash).collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,203 @@
+//! Exporting the finality of Simperby commits to external chains.
+//!
+//! A [`CommitFinalityProof`] is a self-contained package that proves a specific commit
+//! has been finalized, so that a bridge contract or a relayer can verify it
+//! without running a Simperby node.
+use crate::merkle_tree::*;
+use crate::prelude::*;
+use crate::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The version of the binary format produced by [`CommitFinalityProof::encode`].
+pub const COMMIT_FINALITY_PROOF_VERSION: u8 = 1;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExportError {
+    #[error("unsupported version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("malformed data: {0}")]
+    Malformed(String),
+    #[error("invalid proof: {0}")]
+    InvalidProof(String),
+}
+
+/// A proof that a commit is included in a finalized block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitFinalityProof {
+    /// The header of the block that contains the commit.
+    pub header: BlockHeader,
+    /// The finalization proof of the block.
+    pub finalization_proof: FinalizationProof,
+    /// The validator set that finalized the block.
+    pub validator_set: Vec<(PublicKey, VotingPower)>,
+    /// The commit to prove.
+    pub commit: Commit,
+    /// The Merkle proof of the commit against `header.commit_merkle_root`.
+    pub merkle_proof: MerkleProof,
+}
+
+impl CommitFinalityProof {
+    /// Creates a proof for `commit`, given the commits of the block that `header` finalizes.
+    ///
+    /// Returns `None` if `commit` is not one of `block_commits`.
+    pub fn create(
+        header: BlockHeader,
+        finalization_proof: FinalizationProof,
+        block_commits: &[Commit],
+        commit: Commit,
+    ) -> Option<Self> {
+        let merkle_proof =
+            OneshotMerkleTree::create(block_commits.iter().map(|x| x.to_hash256()).collect())
+                .create_merkle_proof(commit.to_hash256())?;
+        Some(Self {
+            validator_set: header.validator_set.clone(),
+            header,
+            finalization_proof,
+            commit,
+            merkle_proof,
+        })
+    }
+
+    /// Encodes the proof into the versioned binary format.
+    ///
+    /// The first byte is the version, followed by the `serde_spb` encoding of the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = vec![COMMIT_FINALITY_PROOF_VERSION];
+        result.extend(serde_spb::to_vec(self).unwrap());
+        result
+    }
+
+    /// Decodes the proof from the versioned binary format.
+    pub fn decode(data: &[u8]) -> Result<Self, ExportError> {
+        let (version, body) = data
+            .split_first()
+            .ok_or_else(|| ExportError::Malformed("empty data".to_owned()))?;
+        if *version != COMMIT_FINALITY_PROOF_VERSION {
+            return Err(ExportError::UnsupportedVersion(*version));
+        }
+        serde_spb::from_slice(body).map_err(|e| ExportError::Malformed(e.to_string()))
+    }
+
+    /// Verifies the proof against the validator set that the verifier trusts.
+    ///
+    /// The verifier is responsible for tracking the trusted validator set,
+    /// for example with a [`crate::light_client::LightClient`].
+    pub fn verify(
+        &self,
+        trusted_validator_set: &[(PublicKey, VotingPower)],
+    ) -> Result<(), ExportError> {
+        if self.validator_set != trusted_validator_set {
+            return Err(ExportError::InvalidProof(
+                "the validator set is not trusted".to_owned(),
+            ));
+        }
+        if self.header.validator_set != self.validator_set {
+            return Err(ExportError::InvalidProof(
+                "the validator set does not match the header".to_owned(),
+            ));
+        }
+        verify::verify_finalization_proof(&self.header, &self.finalization_proof)
+            .map_err(|e| ExportError::InvalidProof(e.to_string()))?;
+        self.merkle_proof
+            .verify(
+                self.header.commit_merkle_root,
+                &commit_leaf_data(&self.commit),
+            )
+            .map_err(|e| ExportError::InvalidProof(e.to_string()))
+    }
+}
+
+/// Returns the data whose hash is the Merkle leaf of the commit.
+fn commit_leaf_data(commit: &Commit) -> Vec<u8> {
+    match commit {
+        Commit::Block(x) => serde_spb::to_vec(x),
+        Commit::Transaction(x) => serde_spb::to_vec(x),
+        Commit::Agenda(x) => serde_spb::to_vec(x),
+        Commit::AgendaProof(x) => serde_spb::to_vec(x),
+        Commit::ExtraAgendaTransaction(x) => serde_spb::to_vec(x),
+        Commit::ChatLog(x) => serde_spb::to_vec(x),
+    }
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::generate_standard_genesis;
+
+    fn setup() -> (CommitFinalityProof, Vec<(PublicKey, VotingPower)>) {
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let commits = (0..5)
+            .map(|i| {
+                Commit::Transaction(Transaction {
+                    author: "doesn't matter".to_owned(),
+                    timestamp: i,
+                    head: format!("tx-{i}"),
+                    body: String::new(),
+                    diff: Diff::None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let genesis_header = reserved_state.genesis_info.header;
+        let header = BlockHeader {
+            author: keys[0].0.clone(),
+            prev_block_finalization_proof: reserved_state.genesis_info.genesis_proof,
+            previous_hash: genesis_header.to_hash256(),
+            height: 1,
+            timestamp: 0,
+            commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&commits),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: genesis_header.validator_set.clone(),
+            version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+        };
+        let finalization_proof = FinalizationProof {
+            round: 0,
+            signatures: keys
+                .iter()
+                .map(|(_, private_key)| {
+                    PrecommitSignature::sign(header.to_hash256(), 0, 0, private_key).unwrap()
+                })
+                .collect(),
+        };
+        let proof =
+            CommitFinalityProof::create(header, finalization_proof, &commits, commits[3].clone())
+                .unwrap();
+        (proof, genesis_header.validator_set)
+    }
+
+    #[test]
+    fn encode_and_verify() {
+        let (proof, validator_set) = setup();
+        let decoded = CommitFinalityProof::decode(&proof.encode()).unwrap();
+        assert_eq!(decoded, proof);
+        decoded.verify(&validator_set).unwrap();
+    }
+
+    #[test]
+    fn reject_untrusted_validator_set() {
+        let (proof, mut validator_set) = setup();
+        validator_set.pop();
+        proof.verify(&validator_set).unwrap_err();
+    }
+
+    #[test]
+    fn reject_wrong_commit() {
+        let (mut proof, validator_set) = setup();
+        if let Commit::Transaction(tx) = &mut proof.commit {
+            tx.head = "forged".to_owned();
+        }
+        proof.verify(&validator_set).unwrap_err();
+    }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let (proof, _) = setup();
+        let mut encoded = proof.encode();
+        encoded[0] = COMMIT_FINALITY_PROOF_VERSION + 1;
+        assert_eq!(
+            CommitFinalityProof::decode(&encoded).unwrap_err(),
+            ExportError::UnsupportedVersion(COMMIT_FINALITY_PROOF_VERSION + 1)
+        );
+    }
+}
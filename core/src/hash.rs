@@ -1,3 +1,4 @@
+use crate::prelude::*;
 use crate::*;
 
 impl ToHash256 for String {
@@ -38,73 +39,131 @@ where
 
 impl ToHash256 for Member {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for FinalizationSignTarget {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for BlockHeader {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for Diff {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for Transaction {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for Agenda {
+    /// An agenda without the description (or the deadline) hashes as it did before they were added,
+    /// so that the existing agenda proofs stay valid.
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        let fields = (
+            self.height,
+            &self.author,
+            self.timestamp,
+            self.transactions_hash,
+        );
+        let description = self.description.as_ref().map(ToHash256::to_hash256);
+        match (description, self.deadline) {
+            (None, None) => serde_spb::to_hash256(&fields).unwrap(),
+            (Some(description), None) => serde_spb::to_hash256(&(fields, description)).unwrap(),
+            (description, Some(deadline)) => {
+                serde_spb::to_hash256(&(fields, description, deadline)).unwrap()
+            }
+        }
+    }
+}
+
+impl ToHash256 for AgendaDescription {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for AgendaProof {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+impl ToHash256 for AgendaVeto {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for ExtraAgendaTransaction {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for DelegationTransactionData {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+/// The hash doesn't depend on the order of the two precommits,
+/// so that the swapped ones are the same evidence.
+impl ToHash256 for DoubleSignEvidence {
+    fn to_hash256(&self) -> Hash256 {
+        let mut hashes = [
+            serde_spb::to_hash256(&self.first).unwrap(),
+            serde_spb::to_hash256(&self.second).unwrap(),
+        ];
+        hashes.sort();
+        hashes[0].aggregate(&hashes[1])
     }
 }
 
 impl ToHash256 for UndelegationTransactionData {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+impl ToHash256 for BanTransactionData {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+impl ToHash256 for JoinRequestData {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for ChatLog {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
 impl ToHash256 for GenesisInfo {
     fn to_hash256(&self) -> Hash256 {
-        Hash256::hash(serde_spb::to_vec(self).unwrap())
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+impl ToHash256 for reserved::ReservedState {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
     }
 }
 
@@ -151,7 +210,14 @@ impl BlockHeader {
         merkle_tree.root()
     }
 
-    // note that `repository_merkle_root` is calculated from `simperby-repository`.
+    /// Encodes a file of the repository as a leaf of `repository_merkle_root`,
+    /// whose tree is calculated from `simperby-repository`.
+    ///
+    /// The leaf commits to both the path and the content,
+    /// so a proof can't be replayed for a file at another path.
+    pub fn repository_merkle_leaf(path: &str, content: &[u8]) -> Vec<u8> {
+        crate::serde_spb::to_vec(&(path, Hash256::hash(content))).unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -160,10 +226,10 @@ mod tests {
     use crate::serde_spb;
     use std::mem::size_of;
 
-    unsafe fn read<T: Clone>(offset: &mut usize, data: &[u8]) -> T {
+    unsafe fn read<T>(offset: &mut usize, data: &[u8]) -> T {
         let size = size_of::<T>();
         let p = data[*offset..*offset + size].as_ptr() as *const T;
-        let x = (*p).clone();
+        let x = std::ptr::read_unaligned(p);
         *offset += size;
         x
     }
@@ -185,9 +251,10 @@ mod tests {
             unsafe { read::<usize>(&mut offset, &encoded) };
         let mut prev_block_finalization_proof_signatures = Vec::new();
         for _ in 0..prev_block_finalization_proof_signatures_len {
-            prev_block_finalization_proof_signatures.push(unsafe {
-                read::<TypedSignature<FinalizationSignTarget>>(&mut offset, &encoded)
-            });
+            let signature =
+                unsafe { read::<TypedSignature<FinalizationSignTarget>>(&mut offset, &encoded) };
+            let timestamp = unsafe { read::<Timestamp>(&mut offset, &encoded) };
+            prev_block_finalization_proof_signatures.push(PrecommitSignature(signature, timestamp));
         }
         let previous_hash = unsafe { read::<Hash256>(&mut offset, &encoded) };
         let height = unsafe { read::<BlockHeight>(&mut offset, &encoded) };
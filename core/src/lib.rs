@@ -1,14 +1,49 @@
+//! The core types and the verification logic of Simperby.
+//!
+//! The finality of a Simperby chain can be verified with this crate alone,
+//! which depends neither on an async runtime nor on Git: see [`verify`] for the headers
+//! and the finalization proofs, [`merkle_tree`] for the commitments, and [`light_client`]
+//! and [`export`] which put them together.
+//!
+//! Without the default `std` feature (and `keygen`, which needs the randomness of the OS),
+//! it's `no_std` with `alloc`, so that the block explorers and the smart-contract environments
+//! can use it on `wasm32-unknown-unknown`:
+//!
+//! ```text
+//! cargo build -p simperby-core --no-default-features --target wasm32-unknown-unknown
+//! ```
+//!
+//! Then the verification runs on a single thread, and [`utils::get_timestamp`],
+//! [`Hash256::hash_reader`] and [`test_vectors::regenerate`] are not available.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod bls;
 pub mod crypto;
+pub mod export;
 pub mod hash;
 pub mod light_client;
 pub mod merkle_tree;
 pub mod reserved;
 pub mod serde_spb;
+#[cfg(feature = "keygen")]
 pub mod test_utils;
+pub mod test_vectors;
 pub mod types;
 pub mod utils;
 pub mod verify;
 
+/// What the standard prelude has in addition to the core one, for `no_std`.
+mod prelude {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+}
+
 pub use crypto::*;
 pub use reserved::*;
 pub use types::*;
@@ -1,3 +1,4 @@
+use crate::prelude::*;
 use crate::*;
 use merkle_tree::*;
 use serde::{Deserialize, Serialize};
@@ -54,12 +55,24 @@ impl LightClient {
     }
 
     /// Verifies the state entry with its proof.
+    ///
+    /// For a file of the repository, the message is [`BlockHeader::repository_merkle_leaf`].
     pub fn verify_state_commitment(
         &self,
-        _message: Vec<u8>,
-        _block_height: u64,
-        _proof: MerkleProof,
+        message: Vec<u8>,
+        block_height: u64,
+        proof: MerkleProof,
     ) -> bool {
-        todo!()
+        if block_height < self.height_offset
+            || block_height >= self.height_offset + self.repository_roots.len() as u64
+        {
+            return false;
+        }
+        proof
+            .verify(
+                self.repository_roots[(block_height - self.height_offset) as usize],
+                &message,
+            )
+            .is_ok()
     }
 }
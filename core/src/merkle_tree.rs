@@ -1,3 +1,4 @@
+use crate::prelude::*;
 use crate::*;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
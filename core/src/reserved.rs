@@ -1,12 +1,38 @@
+use crate::bls::BlsPublicKey;
+use crate::prelude::*;
 use crate::*;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use alloc::collections::BTreeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of the serialized layout of [`ReservedState`].
+///
+/// - `0`: the untagged layout of the first releases.
+///   The signatures of the genesis proof have no timestamps, and the fields added later
+///   (e.g., [`ReservedState::max_blob_size`], [`Member::auth`]) are absent.
+/// - `1`: tagged with `schema_version`.
+/// - `2`: added [`ReservedState::max_commit_body_size`].
+/// - `3`: added [`ReservedState::max_agenda_transactions`]
+///   and [`ReservedState::max_block_extra_agenda_transactions`].
+/// - `4`: added [`Member::nonce`].
+/// - `5`: added [`Member::metadata`].
+/// - `6`: added [`ReservedState::scheduled_changes`].
+/// - `7`: added [`ReservedState::binding`].
+/// - `8`: added [`ReservedState::banned_members`].
+/// - `9`: added [`ReservedState::veto_holders`].
+/// - `10`: added [`ReservedState::transaction_spec`].
+/// - `11`: added [`ReservedState::processed_evidence`].
+///
+/// Bump it on every change of the layout, upgrading the older ones in `upgrade_schema()`.
+pub const RESERVED_STATE_SCHEMA_VERSION: u32 = 11;
 
 /// The partial set of the blockchain state which is reserved and protected.
 ///
 /// It is stored in the reserved directory of the repository.
 /// Any transaction which modifies this state MUST produce a valid next one.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+///
+/// It's always serialized in the current schema (see [`RESERVED_STATE_SCHEMA_VERSION`]),
+/// while the older schemas are upgraded on deserialization from a self-describing format.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ReservedState {
     /// The genesis info. This must never be changed.
     pub genesis_info: GenesisInfo,
@@ -14,13 +40,533 @@ pub struct ReservedState {
     pub members: Vec<Member>,
     /// The leader order of the consensus rounds.
     ///
-    /// It MUST be sorted by the name of the members.
+    /// It's sorted by the name of the members, unless rebalanced otherwise
+    /// (see [`ReservedState::rebalance_leader_order`]).
     pub consensus_leader_order: Vec<MemberName>,
     /// The semantic version of Simperby protocol for this network.
     pub version: String,
+    /// The maximum size of a blob that a transaction can reference, in bytes.
+    ///
+    /// If zero, transactions can't reference blobs.
+    pub max_blob_size: u64,
+    /// The maximum size of the body of a transaction commit, in bytes.
+    ///
+    /// If zero, the body size is not limited.
+    pub max_commit_body_size: u64,
+    /// The maximum number of transactions in an agenda.
+    ///
+    /// If zero, the number is not limited.
+    pub max_agenda_transactions: u64,
+    /// The maximum number of extra-agenda transactions in a block.
+    ///
+    /// If zero, the number is not limited.
+    pub max_block_extra_agenda_transactions: u64,
+    /// The approved changes waiting for their activation heights, in the order of the heights.
+    pub scheduled_changes: Vec<ScheduledChange>,
+    /// What binds this state to the finalized history, so that it can't be rolled back.
+    ///
+    /// It's `None` for the genesis state and for those created before the binding was introduced.
+    pub binding: Option<ReservedStateBinding>,
+    /// The members cut off from the network and the consensus, in the order of the names.
+    ///
+    /// Their governance voting power is kept, so that the governance can still act on them.
+    pub banned_members: Vec<BannedMember>,
+    /// The members who can block an agenda with an [`AgendaVeto`], regardless of the votes.
+    pub veto_holders: Vec<MemberName>,
+    /// The rules on the files that a transaction can change, enforced by the repository.
+    ///
+    /// If `None`, a transaction can change any file.
+    pub transaction_spec: Option<TransactionSpec>,
+    /// The hashes of the misbehavior evidence already reported, in the order of the reports.
+    ///
+    /// A report of the same evidence again is rejected (see [`ReservedState::report`]).
+    pub processed_evidence: Vec<Hash256>,
+}
+
+/// How [`ReservedState::rebalance_leader_order`] orders the eligible leaders.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum LeaderOrderStrategy {
+    /// By the names.
+    Alphabetical,
+    /// By the consensus voting power including the delegated one, the greatest first,
+    /// and then by the names.
+    StakeDescending,
+    /// Keeps the current order of the eligible leaders, appending the new ones by the names.
+    KeepWithInsertions,
+}
+
+impl core::fmt::Display for LeaderOrderStrategy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LeaderOrderStrategy::Alphabetical => write!(f, "alphabetical"),
+            LeaderOrderStrategy::StakeDescending => write!(f, "stake-descending"),
+            LeaderOrderStrategy::KeepWithInsertions => write!(f, "keep-with-insertions"),
+        }
+    }
+}
+
+impl core::str::FromStr for LeaderOrderStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alphabetical" => Ok(LeaderOrderStrategy::Alphabetical),
+            "stake-descending" => Ok(LeaderOrderStrategy::StakeDescending),
+            "keep-with-insertions" => Ok(LeaderOrderStrategy::KeepWithInsertions),
+            _ => Err(format!(
+                "unknown leader order strategy `{s}`; \
+                 expected `alphabetical`, `stake-descending` or `keep-with-insertions`"
+            )),
+        }
+    }
+}
+
+/// A member banned by a [`TxBan`] until the block of `until_height` is finalized.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BannedMember {
+    pub name: MemberName,
+    pub until_height: BlockHeight,
+}
+
+/// Binds a reserved state to the block that finalizes it and to the state it replaces.
+///
+/// A state with a binding can only be replaced by one bound to it (see [`ReservedState::bind`]),
+/// so the bound states form a chain of increasing heights that an older one can't be passed off in.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ReservedStateBinding {
+    /// The height of the block that includes the transaction creating the state.
+    pub height: BlockHeight,
+    /// The hash of the state it replaces.
+    pub previous_hash: Hash256,
+}
+
+/// The rules on the files that a transaction can change (see [`ReservedState::transaction_spec`]).
+///
+/// The paths are relative to the root of the repository, separated by `/`, and matched by the globs
+/// where `*` matches within a directory, `**` matches any number of directories and `?` matches
+/// a character (see [`glob_match`]).
+///
+/// The reserved directory is out of the globs: only a transaction that changes nothing but
+/// the reserved directory, which makes a reserved-diff verified by the protocol, can change it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct TransactionSpec {
+    /// The globs of the paths that a transaction can change. If empty, any path is allowed.
+    #[serde(default)]
+    pub allowed_paths: Vec<String>,
+    /// The globs of the paths that a transaction can't change, even if allowed.
+    #[serde(default)]
+    pub forbidden_paths: Vec<String>,
+    /// The maximum number of files that a transaction can change.
+    ///
+    /// If zero, the number is not limited.
+    #[serde(default)]
+    pub max_files: u64,
+}
+
+/// How a transaction violates the [`TransactionSpec`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransactionSpecViolation {
+    /// The transaction changes the reserved directory along with the other files.
+    #[error("{path} in the reserved directory can't be changed along with the other files")]
+    ReservedPath { path: String },
+    #[error("{path} is forbidden by `{glob}`")]
+    ForbiddenPath { path: String, glob: String },
+    #[error("{path} is not allowed by any of the globs")]
+    NotAllowedPath { path: String },
+    #[error("too many files are changed: {files} > {max}")]
+    TooManyFiles { files: u64, max: u64 },
+}
+
+impl TransactionSpec {
+    /// Checks that the globs are relative paths that don't go out of the repository.
+    pub fn check(&self) -> Result<(), String> {
+        for glob in self.allowed_paths.iter().chain(&self.forbidden_paths) {
+            if glob.is_empty()
+                || glob.starts_with('/')
+                || glob
+                    .split('/')
+                    .any(|segment| segment.is_empty() || segment == "..")
+            {
+                return Err(format!("invalid glob of the transaction spec: `{glob}`"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the paths of the files that a transaction changes against the spec.
+    pub fn check_paths(&self, paths: &[String]) -> Result<(), TransactionSpecViolation> {
+        let is_reserved = |path: &String| path == "reserved" || path.starts_with("reserved/");
+        if paths.iter().all(is_reserved) {
+            return Ok(());
+        }
+        if let Some(path) = paths.iter().find(|path| is_reserved(path)) {
+            return Err(TransactionSpecViolation::ReservedPath { path: path.clone() });
+        }
+        for path in paths {
+            if let Some(glob) = self
+                .forbidden_paths
+                .iter()
+                .find(|glob| glob_match(glob, path))
+            {
+                return Err(TransactionSpecViolation::ForbiddenPath {
+                    path: path.clone(),
+                    glob: glob.clone(),
+                });
+            }
+            if !self.allowed_paths.is_empty()
+                && !self.allowed_paths.iter().any(|glob| glob_match(glob, path))
+            {
+                return Err(TransactionSpecViolation::NotAllowedPath { path: path.clone() });
+            }
+        }
+        let files = paths.len() as u64;
+        if self.max_files != 0 && files > self.max_files {
+            return Err(TransactionSpecViolation::TooManyFiles {
+                files,
+                max: self.max_files,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Matches the path to the glob of a [`TransactionSpec`].
+///
+/// A `**` segment matches zero or more directories, `*` matches any characters but `/`,
+/// and `?` matches a character but `/`.
+pub fn glob_match(glob: &str, path: &str) -> bool {
+    fn match_segments(globs: &[&str], segments: &[&str]) -> bool {
+        match globs.split_first() {
+            None => segments.is_empty(),
+            Some((&"**", rest)) => {
+                (0..=segments.len()).any(|skip| match_segments(rest, &segments[skip..]))
+            }
+            Some((glob, rest)) => segments.split_first().is_some_and(|(segment, others)| {
+                match_segment(glob.as_bytes(), segment.as_bytes()) && match_segments(rest, others)
+            }),
+        }
+    }
+    fn match_segment(glob: &[u8], segment: &[u8]) -> bool {
+        match glob.split_first() {
+            None => segment.is_empty(),
+            Some((b'*', rest)) => {
+                (0..=segment.len()).any(|skip| match_segment(rest, &segment[skip..]))
+            }
+            Some((b'?', rest)) => !segment.is_empty() && match_segment(rest, &segment[1..]),
+            Some((c, rest)) => segment.first() == Some(c) && match_segment(rest, &segment[1..]),
+        }
+    }
+    match_segments(
+        &glob.split('/').collect::<Vec<_>>(),
+        &path.split('/').collect::<Vec<_>>(),
+    )
+}
+
+/// A change of the reserved state approved in advance,
+/// which takes effect once the block of `activation_height` is finalized.
+///
+/// Only the fields set are changed. A field can be in only one pending change,
+/// and can't be changed otherwise until it's activated (see [`ReservedState::check_scheduled_changes`]).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ScheduledChange {
+    pub activation_height: BlockHeight,
+    #[serde(default)]
+    pub members: Option<Vec<Member>>,
+    #[serde(default)]
+    pub consensus_leader_order: Option<Vec<MemberName>>,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl ScheduledChange {
+    fn apply(self, state: &mut ReservedState) {
+        if let Some(members) = self.members {
+            state.members = members;
+        }
+        if let Some(consensus_leader_order) = self.consensus_leader_order {
+            state.consensus_leader_order = consensus_leader_order;
+        }
+        if let Some(version) = self.version {
+            state.version = version;
+        }
+    }
+}
+
+/// The layout of the current schema.
+#[derive(Serialize, Deserialize)]
+struct TaggedReservedState {
+    schema_version: u32,
+    genesis_info: GenesisInfo,
+    members: Vec<Member>,
+    consensus_leader_order: Vec<MemberName>,
+    version: String,
+    #[serde(default)]
+    max_blob_size: u64,
+    #[serde(default)]
+    max_commit_body_size: u64,
+    #[serde(default)]
+    max_agenda_transactions: u64,
+    #[serde(default)]
+    max_block_extra_agenda_transactions: u64,
+    #[serde(default)]
+    scheduled_changes: Vec<ScheduledChange>,
+    #[serde(default)]
+    binding: Option<ReservedStateBinding>,
+    #[serde(default)]
+    banned_members: Vec<BannedMember>,
+    #[serde(default)]
+    veto_holders: Vec<MemberName>,
+    #[serde(default)]
+    transaction_spec: Option<TransactionSpec>,
+    #[serde(default)]
+    processed_evidence: Vec<Hash256>,
+}
+
+impl Serialize for ReservedState {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TaggedReservedState {
+            schema_version: RESERVED_STATE_SCHEMA_VERSION,
+            genesis_info: self.genesis_info.clone(),
+            members: self.members.clone(),
+            consensus_leader_order: self.consensus_leader_order.clone(),
+            version: self.version.clone(),
+            max_blob_size: self.max_blob_size,
+            max_commit_body_size: self.max_commit_body_size,
+            max_agenda_transactions: self.max_agenda_transactions,
+            max_block_extra_agenda_transactions: self.max_block_extra_agenda_transactions,
+            scheduled_changes: self.scheduled_changes.clone(),
+            binding: self.binding.clone(),
+            banned_members: self.banned_members.clone(),
+            veto_holders: self.veto_holders.clone(),
+            transaction_spec: self.transaction_spec.clone(),
+            processed_evidence: self.processed_evidence.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReservedState {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+        // A non-self-describing format (i.e., bincode) can't tell the schemas apart,
+        // but it's never persisted in the older ones.
+        let tagged = if deserializer.is_human_readable() {
+            let mut value = serde_json::Value::deserialize(deserializer)?;
+            upgrade_schema(&mut value).map_err(D::Error::custom)?;
+            serde_json::from_value(value).map_err(D::Error::custom)?
+        } else {
+            TaggedReservedState::deserialize(deserializer)?
+        };
+        if tagged.schema_version != RESERVED_STATE_SCHEMA_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported schema version of the reserved state: {}",
+                tagged.schema_version
+            )));
+        }
+        Ok(ReservedState {
+            genesis_info: tagged.genesis_info,
+            members: tagged.members,
+            consensus_leader_order: tagged.consensus_leader_order,
+            version: tagged.version,
+            max_blob_size: tagged.max_blob_size,
+            max_commit_body_size: tagged.max_commit_body_size,
+            max_agenda_transactions: tagged.max_agenda_transactions,
+            max_block_extra_agenda_transactions: tagged.max_block_extra_agenda_transactions,
+            scheduled_changes: tagged.scheduled_changes,
+            binding: tagged.binding,
+            banned_members: tagged.banned_members,
+            veto_holders: tagged.veto_holders,
+            transaction_spec: tagged.transaction_spec,
+            processed_evidence: tagged.processed_evidence,
+        })
+    }
+}
+
+/// Upgrades the serialized reserved state to the current schema, step by step.
+///
+/// The fields added with `#[serde(default)]` are filled on deserialization, so they need no step.
+fn upgrade_schema(value: &mut serde_json::Value) -> Result<(), String> {
+    let object = value
+        .as_object_mut()
+        .ok_or("the reserved state is not an object")?;
+    let mut schema_version = match object.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or("the schema version is not a number")? as u32,
+        None => 0,
+    };
+    if schema_version > RESERVED_STATE_SCHEMA_VERSION {
+        return Err(format!(
+            "the reserved state is of a newer schema version: {schema_version}"
+        ));
+    }
+    while schema_version < RESERVED_STATE_SCHEMA_VERSION {
+        match schema_version {
+            0 => {
+                // The finalization signatures gained the timestamps.
+                let genesis_info = object
+                    .get_mut("genesis_info")
+                    .ok_or("missing genesis info")?;
+                for pointer in ["/genesis_proof", "/header/prev_block_finalization_proof"] {
+                    let signatures = genesis_info
+                        .pointer_mut(&format!("{pointer}/signatures"))
+                        .and_then(serde_json::Value::as_array_mut);
+                    for signature in signatures.into_iter().flatten() {
+                        if signature.is_object() {
+                            *signature = serde_json::json!([signature.take(), 0]);
+                        }
+                    }
+                }
+            }
+            1 | 2 => {
+                // The added limits are filled with the defaults.
+            }
+            3 => {
+                // The nonces of the members start from zero.
+            }
+            4 => {
+                // The metadata of the members is empty.
+            }
+            5 => {
+                // No change is scheduled.
+            }
+            6 => {
+                // The state is not bound.
+            }
+            7 => {
+                // No member is banned.
+            }
+            8 => {
+                // No member holds a veto.
+            }
+            9 => {
+                // The transactions are not restricted.
+            }
+            10 => {
+                // No evidence is processed.
+            }
+            _ => unreachable!(),
+        }
+        schema_version += 1;
+    }
+    object.insert("schema_version".to_owned(), schema_version.into());
+    Ok(())
 }
 
 impl ReservedState {
+    /// Creates a genesis reserved state of the given members,
+    /// each with a voting power of 1 and no delegation.
+    ///
+    /// The genesis proof is left empty;
+    /// collect the members' signatures on [`ReservedState::genesis_sign_target`]
+    /// with [`ReservedState::add_genesis_signature`].
+    pub fn genesis_template(
+        chain_name: String,
+        mut members: Vec<(MemberName, PublicKey)>,
+    ) -> Result<Self, String> {
+        members.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let members = members
+            .into_iter()
+            .map(|(name, public_key)| Member {
+                public_key,
+                auth: MemberAuth::Single,
+                bls_public_key: None,
+                git_signing_key: None,
+                name,
+                governance_voting_power: 1,
+                consensus_voting_power: 1,
+                governance_delegatee: None,
+                consensus_delegatee: None,
+                nonce: 0,
+                metadata: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let header = BlockHeader {
+            author: PublicKey::zero(),
+            prev_block_finalization_proof: FinalizationProof::genesis(),
+            previous_hash: Hash256::zero(),
+            height: 0,
+            timestamp: 0,
+            commit_merkle_root: Hash256::zero(),
+            repository_merkle_root: Hash256::zero(),
+            validator_set: members
+                .iter()
+                .map(|member| (member.public_key.clone(), member.consensus_voting_power))
+                .collect::<Vec<_>>(),
+            version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+        };
+        let state = ReservedState {
+            genesis_info: GenesisInfo {
+                header,
+                genesis_proof: FinalizationProof::genesis(),
+                chain_name,
+            },
+            consensus_leader_order: members.iter().map(|member| member.name.clone()).collect(),
+            members,
+            version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
+        };
+        state.check_genesis()?;
+        Ok(state)
+    }
+
+    /// Returns what the members sign to produce the genesis proof.
+    pub fn genesis_sign_target(&self) -> FinalizationSignTarget {
+        FinalizationSignTarget {
+            block_hash: self.genesis_info.header.to_hash256(),
+            round: self.genesis_info.genesis_proof.round,
+            timestamp: 0,
+        }
+    }
+
+    /// Adds a signature on [`ReservedState::genesis_sign_target`] to the genesis proof.
+    pub fn add_genesis_signature(
+        &mut self,
+        signature: TypedSignature<FinalizationSignTarget>,
+    ) -> Result<(), String> {
+        if !self
+            .genesis_info
+            .header
+            .validator_set
+            .iter()
+            .any(|(public_key, _)| public_key == signature.signer())
+        {
+            return Err(format!("{} is not a genesis validator", signature.signer()));
+        }
+        if self
+            .genesis_info
+            .genesis_proof
+            .signatures
+            .iter()
+            .any(|precommit| precommit.signer() == signature.signer())
+        {
+            return Err(format!("{} has already signed", signature.signer()));
+        }
+        signature
+            .verify(&self.genesis_sign_target())
+            .map_err(|e| format!("invalid genesis signature: {e}"))?;
+        self.genesis_info
+            .genesis_proof
+            .signatures
+            .push(PrecommitSignature(signature, 0));
+        Ok(())
+    }
+
     pub fn get_validator_set(&self) -> Result<Vec<(PublicKey, VotingPower)>, String> {
         let validator_set = self
             .members
@@ -81,46 +627,770 @@ impl ReservedState {
         Ok(governance_set)
     }
 
-    pub fn apply_delegate(&mut self, tx: &TxDelegate) -> Result<Self, String> {
+    /// Returns the governance voting power of the members authorized by the given signers.
+    ///
+    /// A threshold member is counted only if enough of its keys are among the signers.
+    /// It fails if there is a signer who can't sign for any member.
+    pub fn get_governance_voting_power(
+        &self,
+        signers: &[PublicKey],
+    ) -> Result<VotingPower, String> {
+        for signer in signers {
+            if !self
+                .members
+                .iter()
+                .any(|member| member.governance_keys().contains(signer))
+            {
+                return Err(format!("{signer} is not a governance key of any member"));
+            }
+        }
+        let governance_set = self
+            .get_governance_set()?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        self.members
+            .iter()
+            .filter(|member| member.is_authorized_by(signers))
+            .map(|member| {
+                governance_set
+                    .get(&member.public_key)
+                    .copied()
+                    .ok_or_else(|| {
+                        format!("{} has delegated its governance voting power", member.name)
+                    })
+            })
+            .sum()
+    }
+
+    /// Checks that the authorization of every member is well-formed.
+    ///
+    /// A governance key must belong to only one member, and a threshold must be reachable.
+    pub fn check_member_auths(&self) -> Result<(), String> {
+        let mut keys = alloc::collections::BTreeSet::new();
+        for member in &self.members {
+            if let MemberAuth::Threshold { threshold, keys } = &member.auth {
+                if *threshold == 0 || *threshold as usize > keys.len() {
+                    return Err(format!(
+                        "invalid threshold of {}: {threshold} out of {}",
+                        member.name,
+                        keys.len()
+                    ));
+                }
+            }
+            for key in member.governance_keys() {
+                if !keys.insert(key.clone()) {
+                    return Err(format!("duplicate governance key {key} of {}", member.name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the state with the delegation applied; see [`Self::delegate`].
+    pub fn apply_delegate(&self, tx: &TxDelegate) -> Result<Self, String> {
+        let mut state = self.clone();
+        state.delegate(tx)?;
+        Ok(state)
+    }
+
+    /// Applies the delegation in place, leaving the state untouched if it's invalid.
+    pub fn delegate(&mut self, tx: &TxDelegate) -> Result<(), String> {
         if tx.data.delegator == tx.data.delegatee {
             return Err(format!(
                 "delegator and delegatee are the same: {}",
                 tx.data.delegator
             ));
         }
+        let index = self.check_delegation_domain(
+            &tx.data.delegator,
+            &tx.data.chain_name,
+            tx.data.nonce,
+            tx.proof.signer(),
+        )?;
         if tx.proof.verify(&tx.data).is_err() {
             return Err("delegation proof verification failed".to_string());
         }
-        for delegator in &mut self.members {
-            if delegator.name == tx.data.delegator {
-                if tx.data.governance {
-                    delegator.governance_delegatee = Some(tx.data.delegatee.clone());
-                    delegator.consensus_delegatee = Some(tx.data.delegatee.clone());
-                } else {
-                    delegator.consensus_delegatee = Some(tx.data.delegatee.clone());
+        let delegator = &mut self.members[index];
+        if tx.data.governance {
+            delegator.governance_delegatee = Some(tx.data.delegatee.clone());
+        }
+        delegator.consensus_delegatee = Some(tx.data.delegatee.clone());
+        delegator.nonce = tx.data.nonce;
+        Ok(())
+    }
+
+    /// Adds the member of the join request, returning the resulting reserved state.
+    pub fn apply_join_request(&self, request: &JoinRequest) -> Result<Self, String> {
+        let data = &request.data;
+        if request.proof.signer() != &data.public_key || request.proof.verify(data).is_err() {
+            return Err("join request proof verification failed".to_string());
+        }
+        if data.chain_name != self.genesis_info.chain_name {
+            return Err(format!(
+                "join request for another chain: {}",
+                data.chain_name
+            ));
+        }
+        if data.voting_power == 0 {
+            return Err("join request with zero voting power".to_string());
+        }
+        let mut state = self.clone();
+        state.members.push(Member {
+            public_key: data.public_key.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
+            name: data.name.clone(),
+            governance_voting_power: data.voting_power,
+            consensus_voting_power: data.voting_power,
+            governance_delegatee: None,
+            consensus_delegatee: None,
+            nonce: 0,
+            metadata: Default::default(),
+        });
+        state.members.sort_by(|a, b| a.name.cmp(&b.name));
+        state.consensus_leader_order.push(data.name.clone());
+        state.consensus_leader_order.sort();
+        state.check_member_consistency()?;
+        Ok(state)
+    }
+
+    /// Returns the reserved state that takes away the consensus voting power
+    /// of the reported offline validator, along with the consensus delegations to it.
+    ///
+    /// The governance voting power is kept, so that the member can still take part in the governance
+    /// (e.g., to rejoin the consensus once it's back online).
+    pub fn apply_offline_report(&self, report: &OfflineReport) -> Result<Self, String> {
+        let mut state = self.clone();
+        let offender = state
+            .members
+            .iter_mut()
+            .find(|member| member.name == report.offender)
+            .ok_or_else(|| format!("{} is not a member", report.offender))?;
+        if offender.consensus_voting_power == 0 {
+            return Err(format!("{} is not a validator", report.offender));
+        }
+        offender.consensus_voting_power = 0;
+        for member in &mut state.members {
+            if member.consensus_delegatee.as_ref() == Some(&report.offender) {
+                member.consensus_delegatee = None;
+            }
+        }
+        Ok(state)
+    }
+
+    /// Checks that a delegation or undelegation is signed by the delegator for this chain
+    /// and hasn't been applied yet, returning the index of the delegator.
+    ///
+    /// The height is checked by the caller, which knows the block being built.
+    fn check_delegation_domain(
+        &self,
+        delegator: &MemberName,
+        chain_name: &str,
+        nonce: u64,
+        signer: &PublicKey,
+    ) -> Result<usize, String> {
+        if chain_name != self.genesis_info.chain_name {
+            return Err(format!("delegation for another chain: {chain_name}"));
+        }
+        // It would be overwritten by the scheduled members.
+        if let Some(change) = self
+            .scheduled_changes
+            .iter()
+            .find(|change| change.members.is_some())
+        {
+            return Err(format!(
+                "the members are scheduled to change at height {}",
+                change.activation_height
+            ));
+        }
+        let index = self
+            .members
+            .iter()
+            .position(|member| &member.name == delegator)
+            .ok_or_else(|| format!("delegator {delegator} is not a member"))?;
+        let member = &self.members[index];
+        if signer != &member.public_key {
+            return Err(format!(
+                "delegation not signed by the delegator {delegator}"
+            ));
+        }
+        if nonce <= member.nonce {
+            return Err(format!(
+                "stale delegation nonce of {delegator}: {nonce} (last {})",
+                member.nonce
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Checks that the member names are unique
+    /// and the consensus leader order consists of the members.
+    pub fn check_member_consistency(&self) -> Result<(), String> {
+        let mut names = alloc::collections::BTreeSet::new();
+        for member in &self.members {
+            if !names.insert(member.name.clone()) {
+                return Err(format!("duplicate member name: {}", member.name));
+            }
+        }
+        for name in &self.consensus_leader_order {
+            if !names.contains(name) {
+                return Err(format!("{name} in the leader order is not a member"));
+            }
+        }
+        for name in &self.veto_holders {
+            if !names.contains(name) {
+                return Err(format!("veto holder {name} is not a member"));
+            }
+        }
+        self.check_member_auths()?;
+        self.check_member_metadata()
+    }
+
+    /// Returns the members eligible to lead a round, by the names:
+    /// the validators with positive voting power (see [`Self::get_validator_set`])
+    /// that are not banned.
+    pub fn eligible_leaders(&self) -> Result<Vec<MemberName>, String> {
+        let validator_set = self
+            .get_validator_set()?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let mut leaders = self
+            .members
+            .iter()
+            .filter(|member| {
+                validator_set
+                    .get(&member.public_key)
+                    .is_some_and(|power| *power > 0)
+            })
+            .filter(|member| {
+                !self
+                    .banned_members
+                    .iter()
+                    .any(|banned| banned.name == member.name)
+            })
+            .map(|member| member.name.clone())
+            .collect::<Vec<_>>();
+        leaders.sort();
+        Ok(leaders)
+    }
+
+    /// Checks that the leader order consists of exactly the eligible leaders
+    /// (see [`Self::eligible_leaders`]), each once.
+    pub fn check_leader_order(&self) -> Result<(), String> {
+        let eligible = self
+            .eligible_leaders()?
+            .into_iter()
+            .collect::<alloc::collections::BTreeSet<_>>();
+        let mut seen = alloc::collections::BTreeSet::new();
+        let mut mismatches = Vec::new();
+        for name in &self.consensus_leader_order {
+            if !seen.insert(name) {
+                mismatches.push(format!("duplicate {name}"));
+            } else if !eligible.contains(name) {
+                mismatches.push(format!("ineligible {name}"));
+            }
+        }
+        for name in &eligible {
+            if !seen.contains(name) {
+                mismatches.push(format!("missing {name}"));
+            }
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "the leader order doesn't match the eligible leaders: {}",
+            mismatches.join(", ")
+        ))
+    }
+
+    /// Replaces the leader order with the eligible leaders ordered by the strategy,
+    /// returning the resulting reserved state.
+    pub fn rebalance_leader_order(&self, strategy: LeaderOrderStrategy) -> Result<Self, String> {
+        let mut leaders = self.eligible_leaders()?;
+        if leaders.is_empty() {
+            return Err("no member is eligible to lead a round".to_string());
+        }
+        match strategy {
+            LeaderOrderStrategy::Alphabetical => {}
+            LeaderOrderStrategy::StakeDescending => {
+                let validator_set = self
+                    .get_validator_set()?
+                    .into_iter()
+                    .collect::<BTreeMap<_, _>>();
+                let power = |name: &MemberName| {
+                    self.query_public_key(name)
+                        .and_then(|public_key| validator_set.get(&public_key).copied())
+                        .unwrap_or_default()
+                };
+                // Stable, so the ties stay in the order of the names.
+                leaders.sort_by_key(|name| core::cmp::Reverse(power(name)));
+            }
+            LeaderOrderStrategy::KeepWithInsertions => {
+                let (mut kept, inserted): (Vec<_>, Vec<_>) = leaders
+                    .into_iter()
+                    .partition(|name| self.consensus_leader_order.contains(name));
+                kept.sort_by_key(|name| {
+                    self.consensus_leader_order
+                        .iter()
+                        .position(|x| x == name)
+                        .expect("partitioned by the containment")
+                });
+                kept.extend(inserted);
+                leaders = kept;
+            }
+        }
+        let mut state = self.clone();
+        state.consensus_leader_order = leaders;
+        state.check_member_consistency()?;
+        state.check_leader_order()?;
+        Ok(state)
+    }
+
+    /// Checks that this is a valid genesis state,
+    /// whose genesis header has the validator set of the members.
+    pub fn check_genesis(&self) -> Result<(), String> {
+        self.check_member_consistency()?;
+        self.check_validator_set(&self.genesis_info.header)
+    }
+
+    /// Checks that the header has the validator set of this state (see [`Self::get_validator_set`]),
+    /// reporting every validator missing, unexpected or with a different voting power.
+    ///
+    /// The header of a block has the validator set of the state in effect at the previous block,
+    /// or that of the genesis state for the genesis block. The order doesn't matter.
+    pub fn check_validator_set(&self, header: &BlockHeader) -> Result<(), String> {
+        let expected = self
+            .get_validator_set()?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let mut mismatches = Vec::new();
+        let mut actual = BTreeMap::new();
+        for (public_key, voting_power) in &header.validator_set {
+            if actual.insert(public_key, *voting_power).is_some() {
+                mismatches.push(format!("duplicate validator {public_key}"));
+            }
+        }
+        for (public_key, voting_power) in &expected {
+            match actual.get(public_key) {
+                None => mismatches.push(format!(
+                    "missing validator {public_key} (voting power {voting_power})"
+                )),
+                Some(actual) if actual != voting_power => mismatches.push(format!(
+                    "voting power of {public_key}: expected {voting_power}, got {actual}"
+                )),
+                _ => {}
+            }
+        }
+        for (public_key, voting_power) in &actual {
+            if !expected.contains_key(*public_key) {
+                mismatches.push(format!(
+                    "unexpected validator {public_key} (voting power {voting_power})"
+                ));
+            }
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "the validator set of the block at height {} doesn't match the reserved state: {}",
+            header.height,
+            mismatches.join(", ")
+        ))
+    }
+
+    /// Checks that the metadata of every member has only the allowed keys, within the size limits.
+    pub fn check_member_metadata(&self) -> Result<(), String> {
+        for member in &self.members {
+            let mut size = 0;
+            for (key, value) in &member.metadata {
+                if !MEMBER_METADATA_KEYS.contains(&key.as_str()) {
+                    return Err(format!("unknown metadata key of {}: {key}", member.name));
+                }
+                if value.len() > MAX_MEMBER_METADATA_VALUE_SIZE {
+                    return Err(format!(
+                        "too long metadata of {}: {key} ({} > {MAX_MEMBER_METADATA_VALUE_SIZE})",
+                        member.name,
+                        value.len()
+                    ));
                 }
-                break;
+                size += key.len() + value.len();
+            }
+            if size > MAX_MEMBER_METADATA_SIZE {
+                return Err(format!(
+                    "too large metadata of {}: {size} > {MAX_MEMBER_METADATA_SIZE}",
+                    member.name
+                ));
             }
         }
-        Ok(self.clone())
+        Ok(())
     }
 
-    pub fn apply_undelegate(&mut self, tx: &TxUndelegate) -> Result<Self, String> {
+    /// Replaces the metadata of the member, returning the resulting reserved state.
+    pub fn apply_member_metadata_update(
+        &self,
+        update: &TxUpdateMemberMetadata,
+    ) -> Result<Self, String> {
+        let mut state = self.clone();
+        state
+            .members
+            .iter_mut()
+            .find(|member| member.name == update.member)
+            .ok_or_else(|| format!("{} is not a member", update.member))?
+            .metadata = update.metadata.clone();
+        state.check_member_metadata()?;
+        Ok(state)
+    }
+
+    /// Returns the state with the undelegation applied; see [`Self::undelegate`].
+    pub fn apply_undelegate(&self, tx: &TxUndelegate) -> Result<Self, String> {
+        let mut state = self.clone();
+        state.undelegate(tx)?;
+        Ok(state)
+    }
+
+    /// Applies the undelegation in place, leaving the state untouched if it's invalid.
+    pub fn undelegate(&mut self, tx: &TxUndelegate) -> Result<(), String> {
+        let index = self.check_delegation_domain(
+            &tx.data.delegator,
+            &tx.data.chain_name,
+            tx.data.nonce,
+            tx.proof.signer(),
+        )?;
         if tx.proof.verify(&tx.data).is_err() {
             return Err("delegation proof verification failed".to_string());
         }
-        for delegator in &mut self.members {
-            if delegator.name == tx.data.delegator {
-                if delegator.consensus_delegatee.is_some() {
-                    delegator.consensus_delegatee = None;
-                    delegator.governance_delegatee = None;
-                    break;
-                } else {
-                    return Err("consensus delegatee is not set".to_string());
+        let delegator = &mut self.members[index];
+        if delegator.consensus_delegatee.is_none() {
+            return Err("consensus delegatee is not set".to_string());
+        }
+        delegator.consensus_delegatee = None;
+        delegator.governance_delegatee = None;
+        delegator.nonce = tx.data.nonce;
+        Ok(())
+    }
+
+    /// Returns the state with the report applied; see [`Self::report`].
+    pub fn apply_report(&self, tx: &TxReport) -> Result<Self, String> {
+        let mut state = self.clone();
+        state.report(tx)?;
+        Ok(state)
+    }
+
+    /// Slashes the offender of the reported misbehavior in place, taking away all of its voting power
+    /// and the delegations to it, leaving the state untouched if the report is invalid.
+    ///
+    /// The hash of the evidence is recorded in [`ReservedState::processed_evidence`],
+    /// and an evidence already processed is rejected, as is a misbehavior of an already slashed member.
+    pub fn report(&mut self, tx: &TxReport) -> Result<(), String> {
+        verify::verify_double_sign_evidence(&tx.evidence).map_err(|e| e.to_string())?;
+        let evidence_hash = tx.evidence.to_hash256();
+        if self.processed_evidence.contains(&evidence_hash) {
+            return Err(format!("the evidence {evidence_hash} is already processed"));
+        }
+        let offender = self
+            .query_name(tx.evidence.offender())
+            .ok_or_else(|| format!("offender {} is not a member", tx.evidence.offender()))?;
+        if self.members.iter().any(|member| {
+            member.name == offender
+                && member.consensus_voting_power == 0
+                && member.governance_voting_power == 0
+        }) {
+            return Err(format!("{offender} is already slashed"));
+        }
+        // The scheduled members are slashed too, so that it's not undone on their activation.
+        let scheduled_members = self
+            .scheduled_changes
+            .iter_mut()
+            .filter_map(|change| change.members.as_mut());
+        for members in core::iter::once(&mut self.members).chain(scheduled_members) {
+            for member in members {
+                if member.name == offender {
+                    member.consensus_voting_power = 0;
+                    member.governance_voting_power = 0;
+                }
+                if member.consensus_delegatee.as_ref() == Some(&offender) {
+                    member.consensus_delegatee = None;
+                    member.governance_delegatee = None;
                 }
             }
         }
-        Ok(self.clone())
+        self.processed_evidence.push(evidence_hash);
+        Ok(())
+    }
+
+    /// Bans the member until the block of `until_height` is finalized, replacing its existing ban.
+    ///
+    /// It must be signed by the members holding more than half of the governance voting power,
+    /// as an agenda proof. The height is checked by the caller, which knows the block being built.
+    pub fn apply_ban(&self, tx: &TxBan) -> Result<Self, String> {
+        let data = &tx.data;
+        if data.chain_name != self.genesis_info.chain_name {
+            return Err(format!("ban for another chain: {}", data.chain_name));
+        }
+        if !self.members.iter().any(|member| member.name == data.member) {
+            return Err(format!("{} is not a member", data.member));
+        }
+        if data.until_height < data.block_height {
+            return Err(format!(
+                "the ban is lifted at height {}, before the block {} including it",
+                data.until_height, data.block_height
+            ));
+        }
+        for signature in &tx.proof {
+            signature
+                .verify(data)
+                .map_err(|_| "ban proof verification failed".to_string())?;
+        }
+        let total_weight = self
+            .get_governance_set()?
+            .iter()
+            .map(|(_, weight)| weight)
+            .sum::<VotingPower>();
+        let signed_weight = self.get_governance_voting_power(
+            &tx.proof
+                .iter()
+                .map(|signature| signature.signer().clone())
+                .collect::<Vec<_>>(),
+        )?;
+        if signed_weight * 2 <= total_weight {
+            return Err("insufficient signed weight of the ban".to_string());
+        }
+        let mut state = self.clone();
+        state
+            .banned_members
+            .retain(|banned| banned.name != data.member);
+        state.banned_members.push(BannedMember {
+            name: data.member.clone(),
+            until_height: data.until_height,
+        });
+        state.banned_members.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(state)
+    }
+
+    /// Returns the public keys of the banned members.
+    pub fn banned_public_keys(&self) -> Vec<PublicKey> {
+        self.banned_members
+            .iter()
+            .filter_map(|banned| self.query_public_key(&banned.name))
+            .collect()
+    }
+
+    /// Returns the veto holders who have vetoed the agenda with the vetoes unexpired at `timestamp`.
+    ///
+    /// A veto counts only if it's signed by a governance key of a veto holder,
+    /// and a holder under a threshold authorization needs enough of its keys to veto.
+    pub fn vetoers(
+        &self,
+        agenda_hash: Hash256,
+        vetoes: &[SignedAgendaVeto],
+        timestamp: Timestamp,
+    ) -> Vec<MemberName> {
+        let signers = vetoes
+            .iter()
+            .filter(|veto| veto.veto.agenda_hash == agenda_hash && veto.veto.expires_at > timestamp)
+            .filter(|veto| veto.signature.verify(&veto.veto).is_ok())
+            .map(|veto| veto.signature.signer().clone())
+            .collect::<Vec<_>>();
+        self.members
+            .iter()
+            .filter(|member| self.veto_holders.contains(&member.name))
+            .filter(|member| member.is_authorized_by(&signers))
+            .map(|member| member.name.clone())
+            .collect()
+    }
+
+    /// Returns the state in effect once the block of `height` is finalized,
+    /// with the scheduled changes due by then applied and removed, and the expired bans lifted.
+    pub fn effective_at(&self, height: BlockHeight) -> ReservedState {
+        let mut state = self.clone();
+        state
+            .banned_members
+            .retain(|banned| banned.until_height > height);
+        let (mut due, pending): (Vec<_>, Vec<_>) = core::mem::take(&mut state.scheduled_changes)
+            .into_iter()
+            .partition(|change| change.activation_height <= height);
+        due.sort_by_key(|change| change.activation_height);
+        for change in due {
+            change.apply(&mut state);
+        }
+        state.scheduled_changes = pending;
+        state
+    }
+
+    /// Returns the state that schedules the changes from this to `next`
+    /// to take effect at `activation_height`, instead of applying them right away.
+    ///
+    /// Only the members, the consensus leader order and the version can be scheduled.
+    pub fn schedule(
+        &self,
+        next: &ReservedState,
+        activation_height: BlockHeight,
+    ) -> Result<ReservedState, String> {
+        let change = ScheduledChange {
+            activation_height,
+            members: (next.members != self.members).then(|| next.members.clone()),
+            consensus_leader_order: (next.consensus_leader_order != self.consensus_leader_order)
+                .then(|| next.consensus_leader_order.clone()),
+            version: (next.version != self.version).then(|| next.version.clone()),
+        };
+        let mut unchanged = next.clone();
+        unchanged.members = self.members.clone();
+        unchanged.consensus_leader_order = self.consensus_leader_order.clone();
+        unchanged.version = self.version.clone();
+        if &unchanged != self {
+            return Err(
+                "only the members, the leader order and the version can be scheduled".to_owned(),
+            );
+        }
+        if change
+            == (ScheduledChange {
+                activation_height,
+                members: None,
+                consensus_leader_order: None,
+                version: None,
+            })
+        {
+            return Err("nothing to schedule".to_owned());
+        }
+        let mut state = self.clone();
+        state.scheduled_changes.push(change);
+        state
+            .scheduled_changes
+            .sort_by_key(|change| change.activation_height);
+        Ok(state)
+    }
+
+    /// Checks the scheduled changes of `next`, the state after a transaction of this one
+    /// in the block of `height`.
+    ///
+    /// A new change must be activated after the block, each field can be in only one pending change,
+    /// and a field with a pending change can't be changed right away.
+    /// The changes can be cancelled; a modified one counts as a new one.
+    pub fn check_scheduled_changes(
+        &self,
+        next: &ReservedState,
+        height: BlockHeight,
+    ) -> Result<(), String> {
+        for change in &next.scheduled_changes {
+            if !self.scheduled_changes.contains(change) && change.activation_height <= height {
+                return Err(format!(
+                    "the activation height {} must be after the block {height} including it",
+                    change.activation_height
+                ));
+            }
+        }
+        let count = |f: fn(&ScheduledChange) -> bool| {
+            next.scheduled_changes
+                .iter()
+                .filter(|change| f(change))
+                .count()
+        };
+        for (field, scheduled, changed) in [
+            (
+                "members",
+                count(|change| change.members.is_some()),
+                next.members != self.members,
+            ),
+            (
+                "consensus leader order",
+                count(|change| change.consensus_leader_order.is_some()),
+                next.consensus_leader_order != self.consensus_leader_order,
+            ),
+            (
+                "version",
+                count(|change| change.version.is_some()),
+                next.version != self.version,
+            ),
+        ] {
+            if scheduled > 1 {
+                return Err(format!("conflicting scheduled changes of the {field}"));
+            }
+            if scheduled == 1 && changed {
+                return Err(format!(
+                    "the {field} can't change while a change of it is scheduled"
+                ));
+            }
+        }
+        for change in &next.scheduled_changes {
+            next.effective_at(change.activation_height)
+                .check_member_consistency()
+                .map_err(|e| {
+                    format!(
+                        "invalid change scheduled at height {}: {e}",
+                        change.activation_height
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Returns `next` bound to this state, to be created by a transaction in the block of `height`.
+    pub fn bind(&self, mut next: ReservedState, height: BlockHeight) -> ReservedState {
+        next.binding = Some(ReservedStateBinding {
+            height,
+            previous_hash: self.to_hash256(),
+        });
+        next
+    }
+
+    /// Checks the binding of `next`, the state after a transaction of this one in the block of `height`.
+    ///
+    /// It must be bound to this state (see [`Self::bind`]), unless neither is bound.
+    pub fn check_binding(&self, next: &ReservedState, height: BlockHeight) -> Result<(), String> {
+        let Some(binding) = &next.binding else {
+            return match self.binding {
+                Some(_) => Err("the binding of the reserved state can't be dropped".to_owned()),
+                None => Ok(()),
+            };
+        };
+        if binding.height != height {
+            return Err(format!(
+                "the reserved state is bound to the block {}, not {height} including it",
+                binding.height
+            ));
+        }
+        let hash = self.to_hash256();
+        if binding.previous_hash != hash {
+            return Err(format!(
+                "the reserved state is bound to {}, not the previous one {hash}",
+                binding.previous_hash
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that this state can be the one in effect after the block of `height` is finalized.
+    pub fn check_finalized_at(&self, height: BlockHeight) -> Result<(), String> {
+        match &self.binding {
+            Some(binding) if binding.height > height => Err(format!(
+                "the reserved state is bound to the block {}, after the finalized {height}",
+                binding.height
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that this state doesn't roll back `previous`, the one in effect before.
+    ///
+    /// Once bound, a state can only be followed by one bound to a greater height
+    /// or with the same binding (i.e., itself, possibly with the scheduled changes applied).
+    pub fn check_not_rolled_back(&self, previous: &ReservedState) -> Result<(), String> {
+        let Some(previous) = &previous.binding else {
+            return Ok(());
+        };
+        match &self.binding {
+            Some(binding) if binding.height > previous.height || binding == previous => Ok(()),
+            Some(binding) => Err(format!(
+                "the reserved state bound to the block {} rolls back the one bound to {}",
+                binding.height, previous.height
+            )),
+            None => Err(format!(
+                "the unbound reserved state rolls back the one bound to the block {}",
+                previous.height
+            )),
+        }
     }
 
     pub fn query_name(&self, public_key: &PublicKey) -> Option<MemberName> {
@@ -132,6 +1402,13 @@ impl ReservedState {
         None
     }
 
+    pub fn query_bls_public_key(&self, public_key: &PublicKey) -> Option<BlsPublicKey> {
+        self.members
+            .iter()
+            .find(|member| &member.public_key == public_key)
+            .and_then(|member| member.bls_public_key.clone())
+    }
+
     pub fn query_public_key(&self, name: &MemberName) -> Option<PublicKey> {
         for member in &self.members {
             if &member.name == name {
@@ -152,11 +1429,16 @@ mod tests {
     fn create_member(keys: Vec<(PublicKey, PrivateKey)>, member_num: u8) -> Member {
         Member {
             public_key: keys[member_num as usize].0.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             name: format!("member-{member_num:04}"),
             governance_voting_power: 1,
             consensus_voting_power: 1,
             governance_delegatee: None,
             consensus_delegatee: None,
+            nonce: 0,
+            metadata: Default::default(),
         }
     }
 
@@ -167,11 +1449,16 @@ mod tests {
     ) -> Member {
         Member {
             public_key: keys[member_num as usize].0.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             name: format!("member-{member_num:04}"),
             governance_voting_power: 1,
             consensus_voting_power: 1,
             governance_delegatee: None,
             consensus_delegatee: Some(format!("member-{delegatee_member_num:04}")),
+            nonce: 0,
+            metadata: Default::default(),
         }
     }
 
@@ -182,11 +1469,16 @@ mod tests {
     ) -> Member {
         Member {
             public_key: keys[member_num as usize].0.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             name: format!("member-{member_num:04}"),
             governance_voting_power: 1,
             consensus_voting_power: 1,
             governance_delegatee: Some(format!("member-{delegatee_member_num:04}")),
             consensus_delegatee: None,
+            nonce: 0,
+            metadata: Default::default(),
         }
     }
 
@@ -223,14 +1515,8 @@ mod tests {
                 signatures: keys
                     .iter()
                     .map(|(_, private_key)| {
-                        TypedSignature::sign(
-                            &FinalizationSignTarget {
-                                block_hash: genesis_header.to_hash256(),
-                                round: 0,
-                            },
-                            private_key,
-                        )
-                        .unwrap()
+                        PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                            .unwrap()
                     })
                     .collect::<Vec<_>>(),
             },
@@ -241,6 +1527,16 @@ mod tests {
             members,
             consensus_leader_order: vec!["member-0003".to_string()],
             version: "0.1.0".to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         };
         assert_eq!(
             reserved_state.get_validator_set().unwrap(),
@@ -282,14 +1578,8 @@ mod tests {
                 signatures: keys
                     .iter()
                     .map(|(_, private_key)| {
-                        TypedSignature::sign(
-                            &FinalizationSignTarget {
-                                block_hash: genesis_header.to_hash256(),
-                                round: 0,
-                            },
-                            private_key,
-                        )
-                        .unwrap()
+                        PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                            .unwrap()
                     })
                     .collect::<Vec<_>>(),
             },
@@ -300,6 +1590,16 @@ mod tests {
             members,
             consensus_leader_order: vec!["member-0001".to_string(), "member-0003".to_string()],
             version: "0.1.0".to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         };
         assert_eq!(
             reserved_state.get_validator_set().unwrap(),
@@ -340,14 +1640,8 @@ mod tests {
                 signatures: keys
                     .iter()
                     .map(|(_, private_key)| {
-                        TypedSignature::sign(
-                            &FinalizationSignTarget {
-                                block_hash: genesis_header.to_hash256(),
-                                round: 0,
-                            },
-                            private_key,
-                        )
-                        .unwrap()
+                        PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                            .unwrap()
                     })
                     .collect::<Vec<_>>(),
             },
@@ -358,6 +1652,16 @@ mod tests {
             members,
             consensus_leader_order: (0..4).map(|i| format!("member-{i:04}")).collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         };
         assert_eq!(
             reserved_state.get_governance_set().unwrap(),
@@ -398,14 +1702,8 @@ mod tests {
                 signatures: keys
                     .iter()
                     .map(|(_, private_key)| {
-                        TypedSignature::sign(
-                            &FinalizationSignTarget {
-                                block_hash: genesis_header.to_hash256(),
-                                round: 0,
-                            },
-                            private_key,
-                        )
-                        .unwrap()
+                        PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                            .unwrap()
                     })
                     .collect::<Vec<_>>(),
             },
@@ -416,6 +1714,16 @@ mod tests {
             members,
             consensus_leader_order: (0..4).map(|i| format!("member-{i:04}")).collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         };
         assert_eq!(
             reserved_state
@@ -433,7 +1741,7 @@ mod tests {
     fn test_apply_delegate_on_governance_and_consensus_success() {
         // given
         setup_test();
-        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        let (reserved_state, keys) = generate_standard_genesis(4);
 
         // delegator: member-0000, delegatee: member-0002
         let delegator = reserved_state.members[0].clone();
@@ -441,17 +1749,16 @@ mod tests {
         let delegatee = reserved_state.members[2].clone();
 
         // when
-        let data: DelegationTransactionData = DelegationTransactionData {
-            delegator: delegator.name,
-            delegatee: delegatee.name.clone(),
-            governance: true,
-            block_height: 0,
-            timestamp: 0,
-            chain_name: reserved_state.genesis_info.chain_name.clone(),
-        };
-        let proof = TypedSignature::sign(&data, &delegator_private_key).unwrap();
-
-        let tx = TxDelegate { data, proof };
+        let tx = TxDelegate::builder()
+            .delegator(delegator.name)
+            .delegatee(delegatee.name.clone())
+            .governance(true)
+            .block_height(0)
+            .timestamp(0)
+            .chain_name(reserved_state.genesis_info.chain_name.clone())
+            .nonce(1)
+            .build(&delegator_private_key)
+            .unwrap();
         let new_state = reserved_state.apply_delegate(&tx);
 
         // then
@@ -495,7 +1802,7 @@ mod tests {
     fn test_apply_delegate_on_consensus_success() {
         // given
         setup_test();
-        let (mut state, keys) = generate_standard_genesis(3);
+        let (state, keys) = generate_standard_genesis(3);
 
         // delegator: member-0000, delegatee: member-0002
         let delegator = state.members[0].clone();
@@ -510,6 +1817,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: state.genesis_info.chain_name.clone(),
+            nonce: 1,
         };
         let proof = TypedSignature::sign(&data, &delegator_private_key).unwrap();
 
@@ -553,7 +1861,7 @@ mod tests {
 
     #[test]
     fn test_apply_delegate_on_consensus_failure() {
-        let (mut state, keys) = generate_standard_genesis(1);
+        let (state, keys) = generate_standard_genesis(1);
 
         let delegator = state.members[0].clone();
         let delegator_private_key = keys[0].1.clone();
@@ -566,6 +1874,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: state.genesis_info.chain_name.clone(),
+            nonce: 1,
         };
         let proof = TypedSignature::sign(&data, &delegator_private_key).unwrap();
 
@@ -578,11 +1887,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn delegation_replay() {
+        setup_test();
+        let (state, keys) = generate_standard_genesis(3);
+        let delegate = |state: &ReservedState, chain_name: &str, nonce: u64, signer: usize| {
+            TxDelegate::builder()
+                .delegator(state.members[0].name.clone())
+                .delegatee(state.members[2].name.clone())
+                .block_height(1)
+                .timestamp(0)
+                .chain_name(chain_name.to_owned())
+                .nonce(nonce)
+                .build(&keys[signer].1)
+                .unwrap()
+        };
+        let chain_name = state.genesis_info.chain_name.clone();
+        let tx = delegate(&state, &chain_name, 1, 0);
+        let mut delegated = state.apply_delegate(&tx).unwrap();
+        assert_eq!(delegated.members[0].nonce, 1);
+
+        // The same transaction can't be applied again, leaving the state untouched.
+        let before = delegated.clone();
+        assert!(delegated.delegate(&tx).is_err());
+        assert_eq!(delegated, before);
+        // Nor one signed for another chain, or by another member.
+        assert!(state
+            .apply_delegate(&delegate(&state, "another-chain", 1, 0))
+            .is_err());
+        assert!(state
+            .apply_delegate(&delegate(&state, &chain_name, 1, 1))
+            .is_err());
+
+        let data = UndelegationTransactionData {
+            delegator: state.members[0].name.clone(),
+            block_height: 1,
+            timestamp: 0,
+            chain_name: chain_name.clone(),
+            nonce: 1,
+        };
+        let stale = TxUndelegate {
+            proof: TypedSignature::sign(&data, &keys[0].1).unwrap(),
+            data: data.clone(),
+        };
+        assert!(delegated.apply_undelegate(&stale).is_err());
+        let data = UndelegationTransactionData { nonce: 2, ..data };
+        let tx = TxUndelegate {
+            proof: TypedSignature::sign(&data, &keys[0].1).unwrap(),
+            data,
+        };
+        delegated.undelegate(&tx).unwrap();
+        assert_eq!(delegated.members[0].nonce, 2);
+        assert_eq!(delegated.members[0].consensus_delegatee, None);
+    }
+
+    #[test]
+    fn delegation_builder() {
+        setup_test();
+        let (state, keys) = generate_standard_genesis(3);
+        let builder = TxDelegate::builder()
+            .delegator(state.members[0].name.clone())
+            .delegatee(state.members[1].name.clone())
+            .block_height(1)
+            .timestamp(0)
+            .chain_name(state.genesis_info.chain_name.clone());
+        assert!(builder.clone().build(&keys[0].1).is_err());
+        assert!(builder.clone().nonce(0).build(&keys[0].1).is_err());
+        assert!(builder
+            .clone()
+            .nonce(1)
+            .delegatee(state.members[0].name.clone())
+            .build(&keys[0].1)
+            .is_err());
+        assert!(builder
+            .clone()
+            .nonce(1)
+            .chain_name(String::new())
+            .build(&keys[0].1)
+            .is_err());
+
+        // An external signer signs the same payload.
+        let builder = builder.nonce(1);
+        let payload = builder.payload().unwrap();
+        let external = TypedSignature::sign(&payload, &keys[0].1).unwrap();
+        let tx = builder.build(&keys[0].1).unwrap();
+        assert_eq!(tx.data, payload);
+        assert_eq!(tx.proof, external);
+        state.apply_delegate(&tx).unwrap();
+    }
+
     #[test]
     fn test_apply_undelegate_on_governance_and_consensus_success() {
         // given
         setup_test();
-        let (mut reserved_state, keys) = generate_delegated_genesis(4, true);
+        let (reserved_state, keys) = generate_delegated_genesis(4, true);
 
         // delegator: member-0000, delegatee: member-0002
         let delegator = reserved_state.members[0].clone();
@@ -595,6 +1993,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: reserved_state.genesis_info.chain_name.clone(),
+            nonce: 2,
         };
 
         let proof = TypedSignature::sign(&data, &delegator_private_key).unwrap();
@@ -636,7 +2035,7 @@ mod tests {
     fn test_apply_undelegate_on_consensus_success() {
         // given
         setup_test();
-        let (mut reserved_state, keys) = generate_delegated_genesis(4, false);
+        let (reserved_state, keys) = generate_delegated_genesis(4, false);
 
         // delegator: member-0000, delegatee: member-0002
         let delegator = reserved_state.members[0].clone();
@@ -649,6 +2048,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: reserved_state.genesis_info.chain_name.clone(),
+            nonce: 2,
         };
 
         let proof = TypedSignature::sign(&data, &delegator_private_key).unwrap();
@@ -685,4 +2085,484 @@ mod tests {
             1
         );
     }
+
+    #[test]
+    fn threshold_member_auth() {
+        setup_test();
+        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        let signing_keys = (0..3)
+            .map(|i| generate_keypair(format!("signing-{i}")).0)
+            .collect::<Vec<_>>();
+        reserved_state.members[0].auth = MemberAuth::Threshold {
+            threshold: 2,
+            keys: signing_keys.clone(),
+        };
+        reserved_state.check_member_auths().unwrap();
+
+        // The member key alone no longer authorizes the threshold member.
+        reserved_state
+            .get_governance_voting_power(&[keys[0].0.clone()])
+            .unwrap_err();
+        assert_eq!(
+            reserved_state
+                .get_governance_voting_power(&[signing_keys[0].clone(), keys[1].0.clone()])
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            reserved_state
+                .get_governance_voting_power(&[
+                    signing_keys[0].clone(),
+                    signing_keys[2].clone(),
+                    keys[1].0.clone()
+                ])
+                .unwrap(),
+            2
+        );
+
+        reserved_state.members[0].auth = MemberAuth::Threshold {
+            threshold: 4,
+            keys: signing_keys.clone(),
+        };
+        reserved_state.check_member_auths().unwrap_err();
+        reserved_state.members[0].auth = MemberAuth::Threshold {
+            threshold: 1,
+            keys: vec![signing_keys[0].clone(), keys[1].0.clone()],
+        };
+        reserved_state.check_member_auths().unwrap_err();
+    }
+
+    #[test]
+    fn genesis_template() {
+        setup_test();
+        let keys = (0..4)
+            .map(|i| generate_keypair(format!("{i}")))
+            .collect::<Vec<_>>();
+        let mut reserved_state = ReservedState::genesis_template(
+            "test-chain".to_owned(),
+            keys.iter()
+                .enumerate()
+                .rev()
+                .map(|(i, (public_key, _))| (format!("member-{i:04}"), public_key.clone()))
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(
+            reserved_state.consensus_leader_order,
+            (0..4).map(|i| format!("member-{i:04}")).collect::<Vec<_>>()
+        );
+
+        let target = reserved_state.genesis_sign_target();
+        for (_, private_key) in &keys[0..2] {
+            reserved_state
+                .add_genesis_signature(TypedSignature::sign(&target, private_key).unwrap())
+                .unwrap();
+        }
+        // Not enough voting power yet.
+        verify::verify_finalization_proof(
+            &reserved_state.genesis_info.header,
+            &reserved_state.genesis_info.genesis_proof,
+        )
+        .unwrap_err();
+        // Duplicate and non-member signatures are rejected.
+        reserved_state
+            .add_genesis_signature(TypedSignature::sign(&target, &keys[0].1).unwrap())
+            .unwrap_err();
+        reserved_state
+            .add_genesis_signature(
+                TypedSignature::sign(&target, &generate_keypair("outsider").1).unwrap(),
+            )
+            .unwrap_err();
+        reserved_state
+            .add_genesis_signature(TypedSignature::sign(&target, &keys[2].1).unwrap())
+            .unwrap();
+        verify::verify_finalization_proof(
+            &reserved_state.genesis_info.header,
+            &reserved_state.genesis_info.genesis_proof,
+        )
+        .unwrap();
+
+        ReservedState::genesis_template(
+            "test-chain".to_owned(),
+            vec![
+                ("a".to_owned(), keys[0].0.clone()),
+                ("a".to_owned(), keys[1].0.clone()),
+            ],
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn genesis_validator_set() {
+        setup_test();
+        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        reserved_state.check_genesis().unwrap();
+
+        // The members drift apart from the genesis header.
+        reserved_state.members[0].consensus_voting_power = 3;
+        reserved_state.members.pop();
+        reserved_state.consensus_leader_order.pop();
+        let outsider = generate_keypair("outsider").0;
+        reserved_state
+            .genesis_info
+            .header
+            .validator_set
+            .push((outsider.clone(), 1));
+        let error = reserved_state.check_genesis().unwrap_err();
+        assert!(error.contains(&format!("voting power of {}: expected 3, got 1", keys[0].0)));
+        assert!(error.contains(&format!(
+            "unexpected validator {} (voting power 1)",
+            keys[3].0
+        )));
+        assert!(error.contains(&format!("unexpected validator {outsider} (voting power 1)")));
+
+        let (mut reserved_state, keys) = generate_standard_genesis(4);
+        reserved_state.genesis_info.header.validator_set.remove(2);
+        reserved_state
+            .genesis_info
+            .header
+            .validator_set
+            .push((keys[1].0.clone(), 1));
+        let error = reserved_state.check_genesis().unwrap_err();
+        assert!(error.contains(&format!("missing validator {} (voting power 1)", keys[2].0)));
+        assert!(error.contains(&format!("duplicate validator {}", keys[1].0)));
+    }
+
+    #[test]
+    fn rebalance_leader_order() {
+        setup_test();
+        let (mut reserved_state, _) = generate_standard_genesis(4);
+        reserved_state.check_leader_order().unwrap();
+        let names = |names: &[&str]| names.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        reserved_state.members[2].consensus_voting_power = 3;
+        reserved_state.members[1].consensus_delegatee = Some("member-0003".to_owned());
+        // The delegator leads no more.
+        assert_eq!(
+            reserved_state.check_leader_order().unwrap_err(),
+            "the leader order doesn't match the eligible leaders: ineligible member-0001"
+        );
+        let next = reserved_state
+            .rebalance_leader_order(LeaderOrderStrategy::Alphabetical)
+            .unwrap();
+        assert_eq!(
+            next.consensus_leader_order,
+            names(&["member-0000", "member-0002", "member-0003"])
+        );
+        let next = reserved_state
+            .rebalance_leader_order(LeaderOrderStrategy::StakeDescending)
+            .unwrap();
+        assert_eq!(
+            next.consensus_leader_order,
+            names(&["member-0002", "member-0003", "member-0000"])
+        );
+        reserved_state.consensus_leader_order =
+            names(&["member-0003", "member-0001", "member-0000"]);
+        let next = reserved_state
+            .rebalance_leader_order(LeaderOrderStrategy::KeepWithInsertions)
+            .unwrap();
+        assert_eq!(
+            next.consensus_leader_order,
+            names(&["member-0003", "member-0000", "member-0002"])
+        );
+        next.check_leader_order().unwrap();
+
+        // A banned member leads no more either.
+        reserved_state.banned_members.push(BannedMember {
+            name: "member-0000".to_owned(),
+            until_height: 10,
+        });
+        assert_eq!(
+            reserved_state
+                .rebalance_leader_order(LeaderOrderStrategy::KeepWithInsertions)
+                .unwrap()
+                .consensus_leader_order,
+            names(&["member-0003", "member-0002"])
+        );
+        reserved_state.consensus_leader_order =
+            names(&["member-0002", "member-0002", "member-0003"]);
+        assert_eq!(
+            reserved_state.check_leader_order().unwrap_err(),
+            "the leader order doesn't match the eligible leaders: duplicate member-0002"
+        );
+
+        for member in &mut reserved_state.members {
+            member.consensus_voting_power = 0;
+        }
+        reserved_state
+            .rebalance_leader_order(LeaderOrderStrategy::Alphabetical)
+            .unwrap_err();
+
+        for strategy in [
+            LeaderOrderStrategy::Alphabetical,
+            LeaderOrderStrategy::StakeDescending,
+            LeaderOrderStrategy::KeepWithInsertions,
+        ] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+        "by-age".parse::<LeaderOrderStrategy>().unwrap_err();
+    }
+
+    #[test]
+    fn transaction_spec() {
+        assert!(glob_match("docs/*.md", "docs/a.md"));
+        assert!(!glob_match("docs/*.md", "docs/a/b.md"));
+        assert!(glob_match("docs/**/*.md", "docs/a.md"));
+        assert!(glob_match("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(glob_match("**", "a/b"));
+        assert!(glob_match("data/?.json", "data/1.json"));
+        assert!(!glob_match("data/?.json", "data/10.json"));
+
+        let spec = TransactionSpec {
+            allowed_paths: vec!["docs/**".to_owned(), "README.md".to_owned()],
+            forbidden_paths: vec!["docs/secret/**".to_owned()],
+            max_files: 2,
+        };
+        spec.check().unwrap();
+        let paths = |paths: &[&str]| paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        spec.check_paths(&paths(&["README.md", "docs/a/b.md"]))
+            .unwrap();
+        // Only the reserved directory, of which the protocol verifies the diff.
+        spec.check_paths(&paths(&[
+            "reserved/members/a.json",
+            "reserved/version",
+            "reserved/x",
+        ]))
+        .unwrap();
+        assert_eq!(
+            spec.check_paths(&paths(&["docs/a.md", "reserved/version"])),
+            Err(TransactionSpecViolation::ReservedPath {
+                path: "reserved/version".to_owned()
+            })
+        );
+        assert_eq!(
+            spec.check_paths(&paths(&["docs/secret/key"])),
+            Err(TransactionSpecViolation::ForbiddenPath {
+                path: "docs/secret/key".to_owned(),
+                glob: "docs/secret/**".to_owned()
+            })
+        );
+        assert_eq!(
+            spec.check_paths(&paths(&["src/main.rs"])),
+            Err(TransactionSpecViolation::NotAllowedPath {
+                path: "src/main.rs".to_owned()
+            })
+        );
+        assert_eq!(
+            spec.check_paths(&paths(&["docs/a", "docs/b", "docs/c"])),
+            Err(TransactionSpecViolation::TooManyFiles { files: 3, max: 2 })
+        );
+        for glob in ["", "/etc/**", "docs/../reserved/**", "docs//a"] {
+            assert!(TransactionSpec {
+                forbidden_paths: vec![glob.to_owned()],
+                ..Default::default()
+            }
+            .check()
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn join_request() {
+        setup_test();
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let (public_key, private_key) = generate_keypair("new");
+        let data = JoinRequestData {
+            name: "member-0002a".to_owned(),
+            public_key: public_key.clone(),
+            voting_power: 2,
+            timestamp: 0,
+            chain_name: reserved_state.genesis_info.chain_name.clone(),
+        };
+        let request = JoinRequest {
+            data: data.clone(),
+            proof: TypedSignature::sign(&data, &private_key).unwrap(),
+        };
+        let next = reserved_state.apply_join_request(&request).unwrap();
+        assert_eq!(next.members.len(), 5);
+        assert_eq!(next.consensus_leader_order[3], "member-0002a");
+        assert_eq!(next.query_public_key(&data.name), Some(public_key));
+        assert_eq!(
+            next.get_validator_set()
+                .unwrap()
+                .iter()
+                .map(|(_, power)| power)
+                .sum::<VotingPower>(),
+            6
+        );
+
+        // Signed by another key
+        let forged = JoinRequest {
+            data: data.clone(),
+            proof: TypedSignature::sign(&data, &keys[0].1).unwrap(),
+        };
+        reserved_state.apply_join_request(&forged).unwrap_err();
+        // Already taken name and key
+        next.apply_join_request(&request).unwrap_err();
+        let data = JoinRequestData {
+            name: "member-0000".to_owned(),
+            public_key: generate_keypair("another").0,
+            ..data
+        };
+        let taken = JoinRequest {
+            data: data.clone(),
+            proof: TypedSignature::sign(&data, &generate_keypair("another").1).unwrap(),
+        };
+        reserved_state.apply_join_request(&taken).unwrap_err();
+    }
+
+    #[test]
+    fn member_metadata() {
+        setup_test();
+        let (reserved_state, _) = generate_standard_genesis(2);
+        let mut update = TxUpdateMemberMetadata {
+            member: reserved_state.members[1].name.clone(),
+            metadata: [
+                ("website".to_owned(), "https://example.com".to_owned()),
+                ("contact".to_owned(), "ops@example.com".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let next = reserved_state
+            .apply_member_metadata_update(&update)
+            .unwrap();
+        assert_eq!(next.members[1].metadata, update.metadata);
+        assert!(next.members[0].metadata.is_empty());
+        next.check_member_consistency().unwrap();
+
+        update.metadata.insert(
+            "website".to_owned(),
+            "x".repeat(MAX_MEMBER_METADATA_VALUE_SIZE + 1),
+        );
+        reserved_state
+            .apply_member_metadata_update(&update)
+            .unwrap_err();
+        update.metadata.remove("website");
+        update
+            .metadata
+            .insert("password".to_owned(), "hunter2".to_owned());
+        reserved_state
+            .apply_member_metadata_update(&update)
+            .unwrap_err();
+        update.member = "nobody".to_owned();
+        reserved_state
+            .apply_member_metadata_update(&update)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn offline_report() {
+        setup_test();
+        let (mut reserved_state, _) = generate_standard_genesis(4);
+        reserved_state.members[2].consensus_delegatee = Some("member-0001".to_owned());
+        let report = OfflineReport {
+            offender: "member-0001".to_owned(),
+            from_height: 1,
+            to_height: 100,
+            missed_precommits: 90,
+            eligible_blocks: 100,
+        };
+        let next = reserved_state.apply_offline_report(&report).unwrap();
+        assert_eq!(next.members[1].consensus_voting_power, 0);
+        assert_eq!(next.members[1].governance_voting_power, 1);
+        // The delegation to the offender is revoked.
+        assert_eq!(next.members[2].consensus_delegatee, None);
+        next.apply_offline_report(&report).unwrap_err();
+        reserved_state
+            .apply_offline_report(&OfflineReport {
+                offender: "unknown".to_owned(),
+                ..report
+            })
+            .unwrap_err();
+    }
+
+    #[test]
+    fn scheduled_changes() {
+        setup_test();
+        let (reserved_state, _) = generate_standard_genesis(2);
+        let mut next = reserved_state
+            .apply_member_metadata_update(&TxUpdateMemberMetadata {
+                member: reserved_state.members[1].name.clone(),
+                metadata: [("website".to_owned(), "https://example.com".to_owned())]
+                    .into_iter()
+                    .collect(),
+            })
+            .unwrap();
+        next.version = "0.2.0".to_owned();
+        let scheduled = reserved_state.schedule(&next, 10).unwrap();
+        assert_eq!(scheduled.members, reserved_state.members);
+        assert_eq!(scheduled.scheduled_changes.len(), 1);
+        reserved_state
+            .check_scheduled_changes(&scheduled, 5)
+            .unwrap();
+        // Must be activated after the block including it.
+        reserved_state
+            .check_scheduled_changes(&scheduled, 10)
+            .unwrap_err();
+        reserved_state.schedule(&reserved_state, 10).unwrap_err();
+        let mut other = next.clone();
+        other.max_blob_size += 1;
+        reserved_state.schedule(&other, 10).unwrap_err();
+
+        assert_eq!(scheduled.effective_at(9), scheduled);
+        let activated = scheduled.effective_at(10);
+        assert_eq!(activated.members, next.members);
+        assert_eq!(activated.version, "0.2.0");
+        assert!(activated.scheduled_changes.is_empty());
+
+        // Another change of the version conflicts.
+        let mut version = scheduled.clone();
+        version.version = "0.3.0".to_owned();
+        scheduled.check_scheduled_changes(&version, 5).unwrap_err();
+        let twice = scheduled.schedule(&version, 20).unwrap();
+        scheduled.check_scheduled_changes(&twice, 5).unwrap_err();
+        // But the cancellation is fine.
+        scheduled
+            .check_scheduled_changes(&reserved_state, 5)
+            .unwrap();
+        // The delegation would be overwritten.
+        assert!(scheduled
+            .check_delegation_domain(
+                &reserved_state.members[0].name,
+                &reserved_state.genesis_info.chain_name,
+                1,
+                &reserved_state.members[0].public_key,
+            )
+            .unwrap_err()
+            .contains("scheduled"));
+    }
+
+    #[test]
+    fn binding() {
+        setup_test();
+        let (reserved_state, _) = generate_standard_genesis(2);
+        let mut next = reserved_state.clone();
+        next.version = "0.2.0".to_owned();
+        let bound = reserved_state.bind(next.clone(), 3);
+        reserved_state.check_binding(&bound, 3).unwrap();
+        reserved_state.check_binding(&bound, 4).unwrap_err();
+        next.check_binding(&bound, 3).unwrap_err();
+        // The unbound states stay unbound, but the bound ones can't be unbound.
+        reserved_state.check_binding(&next, 3).unwrap();
+        bound.check_binding(&next, 5).unwrap_err();
+
+        bound.check_finalized_at(3).unwrap();
+        bound.check_finalized_at(2).unwrap_err();
+        reserved_state.check_finalized_at(0).unwrap();
+
+        let later = bound.bind(bound.clone(), 5);
+        later.check_not_rolled_back(&bound).unwrap();
+        later.check_not_rolled_back(&reserved_state).unwrap();
+        bound.check_not_rolled_back(&later).unwrap_err();
+        reserved_state.check_not_rolled_back(&bound).unwrap_err();
+        // The same state with the scheduled changes applied.
+        let mut changed = bound.clone();
+        changed.version = "0.3.0".to_owned();
+        let scheduled = bound.schedule(&changed, 10).unwrap();
+        scheduled
+            .effective_at(10)
+            .check_not_rolled_back(&bound)
+            .unwrap();
+    }
 }
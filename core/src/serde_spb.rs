@@ -1,6 +1,16 @@
+use crate::crypto::Hash256;
+use crate::prelude::*;
 use serde::{de::DeserializeOwned, ser::Serialize};
 
 pub type Error = serde_json::Error;
+pub type EncodeError = bincode::error::EncodeError;
+pub type DecodeError = bincode::error::DecodeError;
+
+/// The configuration of the canonical encoding, which is compatible with the one of `bincode` 1.x.
+const CONFIG: bincode::config::Configuration<
+    bincode::config::LittleEndian,
+    bincode::config::Fixint,
+> = bincode::config::legacy();
 
 pub fn to_string<T: Serialize>(t: &T) -> Result<String, Error> {
     serde_json::to_string_pretty(t)
@@ -10,10 +20,17 @@ pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, Error> {
     serde_json::from_str(s)
 }
 
-pub fn to_vec<T: Serialize>(t: &T) -> Result<Vec<u8>, bincode::Error> {
-    bincode::serialize(t)
+pub fn to_vec<T: Serialize>(t: &T) -> Result<Vec<u8>, EncodeError> {
+    bincode::serde::encode_to_vec(t, CONFIG)
+}
+
+pub fn from_slice<T: DeserializeOwned>(s: &[u8]) -> Result<T, DecodeError> {
+    bincode::serde::decode_from_slice(s, CONFIG).map(|(t, _)| t)
 }
 
-pub fn from_slice<T: DeserializeOwned>(s: &[u8]) -> Result<T, bincode::Error> {
-    bincode::deserialize_from(s)
+/// Hashes the result of `to_vec()` without buffering it.
+pub fn to_hash256<T: Serialize>(t: &T) -> Result<Hash256, EncodeError> {
+    let mut hasher = Hash256::hasher();
+    bincode::serde::encode_into_writer(t, &mut hasher, CONFIG)?;
+    Ok(hasher.finalize())
 }
@@ -13,12 +13,17 @@ pub fn generate_standard_genesis(
         .enumerate()
         .map(|(i, (public_key, _))| Member {
             public_key: public_key.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             // lexicographically ordered
             name: format!("member-{i:04}"),
             governance_voting_power: 1,
             consensus_voting_power: 1,
             governance_delegatee: None,
             consensus_delegatee: None,
+            nonce: 0,
+            metadata: Default::default(),
         })
         .collect::<Vec<_>>();
     let genesis_header = BlockHeader {
@@ -42,14 +47,8 @@ pub fn generate_standard_genesis(
             signatures: keys
                 .iter()
                 .map(|(_, private_key)| {
-                    TypedSignature::sign(
-                        &FinalizationSignTarget {
-                            block_hash: genesis_header.to_hash256(),
-                            round: 0,
-                        },
-                        private_key,
-                    )
-                    .unwrap()
+                    PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                        .unwrap()
                 })
                 .collect::<Vec<_>>(),
         },
@@ -63,6 +62,16 @@ pub fn generate_standard_genesis(
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         },
         keys,
     )
@@ -86,6 +95,9 @@ pub fn generate_delegated_genesis(
         .enumerate()
         .map(|(i, (public_key, _))| Member {
             public_key: public_key.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             // lexicographically ordered
             name: format!("member-{i:04}"),
             governance_voting_power: 1,
@@ -100,6 +112,8 @@ pub fn generate_delegated_genesis(
             } else {
                 None
             },
+            nonce: 0,
+            metadata: Default::default(),
         })
         .collect::<Vec<_>>();
     let genesis_header = BlockHeader {
@@ -123,14 +137,8 @@ pub fn generate_delegated_genesis(
             signatures: keys
                 .iter()
                 .map(|(_, private_key)| {
-                    TypedSignature::sign(
-                        &FinalizationSignTarget {
-                            block_hash: genesis_header.to_hash256(),
-                            round: 0,
-                        },
-                        private_key,
-                    )
-                    .unwrap()
+                    PrecommitSignature::sign(genesis_header.to_hash256(), 0, 0, private_key)
+                        .unwrap()
                 })
                 .collect::<Vec<_>>(),
         },
@@ -144,6 +152,16 @@ pub fn generate_delegated_genesis(
                 .map(|i| format!("member-{i:04}"))
                 .collect::<Vec<_>>(),
             version: "0.1.0".to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         },
         keys,
     )
@@ -0,0 +1,212 @@
+//! The test vectors of the hashing and the signing payloads, for the other implementations.
+//!
+//! Each vector freezes a payload with the bytes that are hashed for it, the hash,
+//! and the signature by a fixed key if it's signed. The golden files of them
+//! (`core/tests/vectors`) are checked byte-for-byte against [`generate`],
+//! so that an encoding can't change unnoticed.
+//!
+//! The hashed bytes may change only with a bump of [`SIMPERBY_CORE_PROTOCOL_VERSION`],
+//! while a payload may gain a field that is left out of them (like an unset [`Agenda::deadline`]);
+//! either way the golden files are rewritten with [`regenerate`].
+use crate::prelude::*;
+use crate::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    pub name: String,
+    /// The protocol version that the vector is frozen at.
+    pub protocol_version: String,
+    /// The payload, as serialized by [`serde_spb::to_string`].
+    pub payload: serde_json::Value,
+    /// The bytes hashed into `hash`, in hex.
+    ///
+    /// It's the canonical encoding of the payload ([`serde_spb::to_vec`]) except for an agenda,
+    /// which hashes a subset of the fields (see the `ToHash256` implementation of [`Agenda`]).
+    pub preimage: String,
+    pub hash: Hash256,
+    /// The signature on `hash` by [`signer`] if the payload is to be signed.
+    pub signature: Option<Signature>,
+}
+
+impl TestVector {
+    fn new<T: Serialize + ToHash256>(name: &str, payload: &T, preimage: Vec<u8>) -> Self {
+        Self {
+            name: name.to_owned(),
+            protocol_version: SIMPERBY_CORE_PROTOCOL_VERSION.to_owned(),
+            payload: serde_json::to_value(payload).unwrap(),
+            preimage: hex::encode(preimage),
+            hash: payload.to_hash256(),
+            signature: None,
+        }
+    }
+
+    fn signed(mut self, signature: Signature) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+
+    /// Returns the content of the golden file.
+    pub fn to_golden_file(&self) -> String {
+        format!("{}\n", serde_spb::to_string(self).unwrap())
+    }
+
+    /// Returns the name of the golden file.
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.name)
+    }
+}
+
+/// Returns the fixed key that signs the vectors.
+pub fn signer() -> PrivateKey {
+    PrivateKey::from_array([1; 32]).expect("valid private key")
+}
+
+/// Returns the fixed key of another member, which appears in the payloads.
+fn other_member() -> PrivateKey {
+    PrivateKey::from_array([2; 32]).expect("valid private key")
+}
+
+/// Generates the test vectors, which are the same on every run.
+pub fn generate() -> Vec<TestVector> {
+    let signer = signer();
+    let other = other_member();
+    let timestamp = 1_700_000_000_000;
+
+    let header = BlockHeader {
+        author: signer.public_key(),
+        prev_block_finalization_proof: FinalizationProof::genesis(),
+        previous_hash: Hash256::hash("previous block"),
+        height: 1,
+        timestamp,
+        commit_merkle_root: Hash256::hash("commits"),
+        repository_merkle_root: Hash256::hash("repository"),
+        validator_set: vec![(signer.public_key(), 1), (other.public_key(), 2)],
+        version: SIMPERBY_CORE_PROTOCOL_VERSION.to_owned(),
+    };
+    let block_header =
+        TestVector::new("block_header", &header, serde_spb::to_vec(&header).unwrap());
+
+    let agenda = Agenda {
+        height: 1,
+        author: "member-0000".to_owned(),
+        timestamp,
+        transactions_hash: Hash256::hash("transactions"),
+        description: None,
+        deadline: None,
+    };
+    let fields = (
+        agenda.height,
+        &agenda.author,
+        agenda.timestamp,
+        agenda.transactions_hash,
+    );
+    let agenda_vector = TestVector::new("agenda", &agenda, serde_spb::to_vec(&fields).unwrap());
+    let description = AgendaDescription {
+        title: "Test vector".to_owned(),
+        rationale: "An agenda with the description.".to_owned(),
+        links: vec!["https://example.com/agenda".to_owned()],
+    };
+    let described = Agenda {
+        description: Some(description.clone()),
+        ..agenda.clone()
+    };
+    let described_vector = TestVector::new(
+        "agenda_with_description",
+        &described,
+        serde_spb::to_vec(&(fields, description.to_hash256())).unwrap(),
+    );
+    let deadline = VotingDeadline::Timestamp(timestamp + 7 * 24 * 60 * 60 * 1000);
+    let with_deadline = Agenda {
+        deadline: Some(deadline),
+        ..agenda.clone()
+    };
+    let deadline_vector = TestVector::new(
+        "agenda_with_deadline",
+        &with_deadline,
+        serde_spb::to_vec(&(fields, None::<Hash256>, deadline)).unwrap(),
+    );
+
+    let delegation = TxDelegate::builder()
+        .delegator("member-0000".to_owned())
+        .delegatee("member-0001".to_owned())
+        .governance(true)
+        .block_height(1)
+        .timestamp(timestamp)
+        .chain_name("test-chain".to_owned())
+        .nonce(1)
+        .build(&signer)
+        .expect("coherent delegation");
+    let delegation_vector = TestVector::new(
+        "delegation",
+        &delegation.data,
+        serde_spb::to_vec(&delegation.data).unwrap(),
+    )
+    .signed(delegation.proof.get_raw_signature());
+
+    let target = FinalizationSignTarget {
+        block_hash: header.to_hash256(),
+        round: 0,
+        timestamp,
+    };
+    let finalization = TestVector::new(
+        "finalization_sign_target",
+        &target,
+        serde_spb::to_vec(&target).unwrap(),
+    )
+    .signed(
+        TypedSignature::sign(&target, &signer)
+            .expect("valid key")
+            .get_raw_signature(),
+    );
+
+    vec![
+        block_header,
+        agenda_vector,
+        described_vector,
+        deadline_vector,
+        delegation_vector,
+        finalization,
+    ]
+}
+
+/// Writes the golden files of the vectors into the directory, returning the names of those changed.
+///
+/// The hashed bytes, the hash or the signature of a vector can't change
+/// unless the protocol version has been bumped since its golden file.
+#[cfg(feature = "std")]
+pub fn regenerate(directory: &Path) -> Result<Vec<String>, String> {
+    let mut changed = Vec::new();
+    for vector in generate() {
+        let path = directory.join(vector.file_name());
+        let content = vector.to_golden_file();
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            if existing == content {
+                continue;
+            }
+            let existing: TestVector = serde_spb::from_str(&existing)
+                .map_err(|e| format!("invalid golden file {}: {e}", path.display()))?;
+            let frozen = |vector: &TestVector| {
+                (
+                    vector.preimage.clone(),
+                    vector.hash,
+                    vector.signature.clone(),
+                )
+            };
+            if existing.protocol_version == vector.protocol_version
+                && frozen(&existing) != frozen(&vector)
+            {
+                return Err(format!(
+                    "the vector {} has changed without a bump of the protocol version {}",
+                    vector.name, vector.protocol_version
+                ));
+            }
+        }
+        std::fs::write(&path, content)
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        changed.push(vector.name);
+    }
+    Ok(changed)
+}
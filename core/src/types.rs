@@ -1,4 +1,6 @@
-use crate::{crypto::*, reserved::ReservedState};
+use crate::prelude::*;
+use crate::{bls::BlsPublicKey, crypto::*, reserved::ReservedState};
+use alloc::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
 pub type VotingPower = u64;
@@ -11,7 +13,17 @@ pub type MemberName = String;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Member {
+    /// The key that identifies the member, which is also used to sign consensus messages.
     pub public_key: PublicKey,
+    /// The keys that authorize the governance actions of the member.
+    #[serde(default)]
+    pub auth: MemberAuth,
+    /// The BLS public key for the aggregated finalization proofs (see [`crate::bls`]).
+    #[serde(default)]
+    pub bls_public_key: Option<BlsPublicKey>,
+    /// The key for git's native commit signing, which hosting services can display as verified.
+    #[serde(default)]
+    pub git_signing_key: Option<GitSigningKey>,
     /// The name of the member that will be used in human-readable interfaces.
     /// This must be unique.
     pub name: MemberName,
@@ -23,6 +35,12 @@ pub struct Member {
     /// If this member delegated its governance consensus power to another member,
     /// the delegatee.
     pub consensus_delegatee: Option<MemberName>,
+    /// The nonce of the last delegation or undelegation of the member.
+    #[serde(default)]
+    pub nonce: u64,
+    /// The public profile of the member, keyed by one of [`MEMBER_METADATA_KEYS`].
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
     // TODO: add various conditions for each delegation.
     // - Unlock-Automatically-After-N-Blocks
     // - Unlock-Automatically-After-T-Seconds
@@ -30,16 +48,131 @@ pub struct Member {
     // - Unlock-If-The-Validator-Set-Changes
 }
 
+/// The keys allowed in [`Member::metadata`].
+pub const MEMBER_METADATA_KEYS: [&str; 5] =
+    ["contact", "website", "description", "location", "logo"];
+/// The maximum length of a value in [`Member::metadata`], in bytes.
+pub const MAX_MEMBER_METADATA_VALUE_SIZE: usize = 256;
+/// The maximum total length of the keys and the values in [`Member::metadata`], in bytes.
+pub const MAX_MEMBER_METADATA_SIZE: usize = 1024;
+
+/// How a member authorizes its governance actions (e.g., voting on an agenda).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub enum MemberAuth {
+    /// Signed by [`Member::public_key`] alone.
+    #[default]
+    Single,
+    /// Signed by at least `threshold` keys out of `keys`, for organizations sharing a membership.
+    ///
+    /// Consensus messages are still signed by [`Member::public_key`],
+    /// which must be held by the node operating on behalf of the member.
+    Threshold {
+        threshold: u32,
+        keys: Vec<PublicKey>,
+    },
+}
+
+/// A public key of git's native commit signing (`gpg.format`).
+///
+/// Git doesn't support the curve of [`Member::public_key`],
+/// so a member registers a separate key for it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum GitSigningKey {
+    /// A public key in the OpenSSH format (e.g., `ssh-ed25519 AAAA...`).
+    Ssh(String),
+    /// An ASCII-armored OpenPGP public key.
+    Gpg(String),
+}
+
+impl Member {
+    /// Returns the keys that can sign for the governance actions of the member.
+    pub fn governance_keys(&self) -> Vec<PublicKey> {
+        match &self.auth {
+            MemberAuth::Single => vec![self.public_key.clone()],
+            MemberAuth::Threshold { keys, .. } => keys.clone(),
+        }
+    }
+
+    /// Returns whether the given signers are enough to authorize the member.
+    pub fn is_authorized_by(&self, signers: &[PublicKey]) -> bool {
+        match &self.auth {
+            MemberAuth::Single => signers.contains(&self.public_key),
+            MemberAuth::Threshold { threshold, keys } => {
+                keys.iter().filter(|key| signers.contains(key)).count() >= *threshold as usize
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct FinalizationSignTarget {
     pub block_hash: Hash256,
     pub round: ConsensusRound,
+    /// The local time of the signer when it precommitted.
+    pub timestamp: Timestamp,
+}
+
+/// A precommit signature with the timestamp signed along.
+///
+/// The timestamp is kept only here; the signed [`FinalizationSignTarget`] is always
+/// derived from it by [`PrecommitSignature::sign_target`].
+/// It's encoded as a pair, the same as the tuple it replaces.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PrecommitSignature(pub TypedSignature<FinalizationSignTarget>, pub Timestamp);
+
+impl PrecommitSignature {
+    /// Signs the precommit on the given block, at the given local time of the signer.
+    pub fn sign(
+        block_hash: Hash256,
+        round: ConsensusRound,
+        timestamp: Timestamp,
+        private_key: &PrivateKey,
+    ) -> Result<Self, CryptoError> {
+        let target = FinalizationSignTarget {
+            block_hash,
+            round,
+            timestamp,
+        };
+        Ok(PrecommitSignature(
+            TypedSignature::sign(&target, private_key)?,
+            timestamp,
+        ))
+    }
+
+    pub fn signature(&self) -> &TypedSignature<FinalizationSignTarget> {
+        &self.0
+    }
+
+    pub fn signer(&self) -> &PublicKey {
+        self.0.signer()
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.1
+    }
+
+    /// Returns what has been signed, for the given block.
+    pub fn sign_target(
+        &self,
+        block_hash: Hash256,
+        round: ConsensusRound,
+    ) -> FinalizationSignTarget {
+        FinalizationSignTarget {
+            block_hash,
+            round,
+            timestamp: self.1,
+        }
+    }
+
+    pub fn verify(&self, block_hash: Hash256, round: ConsensusRound) -> Result<(), CryptoError> {
+        self.0.verify(&self.sign_target(block_hash, round))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct FinalizationProof {
     pub round: ConsensusRound,
-    pub signatures: Vec<TypedSignature<FinalizationSignTarget>>,
+    pub signatures: Vec<PrecommitSignature>,
 }
 
 impl FinalizationProof {
@@ -49,6 +182,36 @@ impl FinalizationProof {
             signatures: Vec::new(),
         }
     }
+
+    /// Calculates the BFT time of the proof, which is the median of the precommit timestamps
+    /// weighted by the voting power of the signers.
+    ///
+    /// Since it's a weighted median, a minority of the validators (including the block proposer)
+    /// can't move it outside the range of the timestamps of the honest validators.
+    ///
+    /// Returns `None` if none of the signers is in `validator_set`.
+    pub fn bft_time(&self, validator_set: &[(PublicKey, VotingPower)]) -> Option<Timestamp> {
+        let mut weighted_timestamps = self
+            .signatures
+            .iter()
+            .filter_map(|precommit| {
+                validator_set
+                    .iter()
+                    .find(|(public_key, _)| public_key == precommit.signer())
+                    .map(|(_, voting_power)| (precommit.timestamp(), *voting_power))
+            })
+            .collect::<Vec<_>>();
+        weighted_timestamps.sort();
+        let total_voting_power: VotingPower = weighted_timestamps.iter().map(|(_, v)| v).sum();
+        let mut accumulated = 0;
+        for (timestamp, voting_power) in weighted_timestamps {
+            accumulated += voting_power;
+            if accumulated * 2 > total_voting_power {
+                return Some(timestamp);
+            }
+        }
+        None
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -83,6 +246,99 @@ pub struct Agenda {
     pub author: MemberName,
     pub timestamp: Timestamp,
     pub transactions_hash: Hash256,
+    /// What the agenda is for, covered by the agenda hash (and so by the votes).
+    #[serde(default)]
+    pub description: Option<AgendaDescription>,
+    /// Until when the agenda can be approved, covered by the agenda hash like the description.
+    ///
+    /// The votes arriving after it never approve the agenda,
+    /// and a block with the agenda finalized after it is rejected.
+    #[serde(default)]
+    pub deadline: Option<VotingDeadline>,
+}
+
+impl Agenda {
+    /// Returns whether the agenda can still be approved at the given time.
+    pub fn is_open_at(&self, timestamp: Timestamp) -> bool {
+        match self.deadline {
+            None => true,
+            Some(VotingDeadline::Timestamp(deadline)) => timestamp <= deadline,
+        }
+    }
+
+    /// Checks that the deadline, if any, doesn't precede the agenda itself.
+    pub fn check_deadline(&self) -> Result<(), String> {
+        match self.deadline {
+            Some(VotingDeadline::Timestamp(deadline)) if deadline < self.timestamp => Err(format!(
+                "the voting deadline {deadline} precedes the agenda timestamp {}",
+                self.timestamp
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The deadline of the votes on an agenda.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum VotingDeadline {
+    /// The last time (inclusive) that the agenda can be approved at.
+    ///
+    /// The agenda proof must be timestamped by then, and the block with the agenda
+    /// must be finalized by then too, on the BFT time of its finalization proof;
+    /// the timestamp of the agenda proof is only claimed by its author.
+    Timestamp(Timestamp),
+}
+
+/// The maximum length of [`AgendaDescription::title`], in bytes.
+pub const MAX_AGENDA_TITLE_SIZE: usize = 256;
+/// The maximum length of [`AgendaDescription::rationale`], in bytes.
+pub const MAX_AGENDA_RATIONALE_SIZE: usize = 8 * 1024;
+/// The maximum number of [`AgendaDescription::links`].
+pub const MAX_AGENDA_LINKS: usize = 16;
+/// The maximum length of a link in [`AgendaDescription::links`], in bytes.
+pub const MAX_AGENDA_LINK_SIZE: usize = 512;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AgendaDescription {
+    pub title: String,
+    /// Why the agenda should be approved, in free text.
+    pub rationale: String,
+    /// Where the agenda is discussed (e.g., a forum thread or a poll).
+    pub links: Vec<String>,
+}
+
+impl AgendaDescription {
+    pub fn check(&self) -> Result<(), String> {
+        if self.title.is_empty() || self.title.len() > MAX_AGENDA_TITLE_SIZE {
+            return Err(format!(
+                "the agenda title must be in 1 to {MAX_AGENDA_TITLE_SIZE} bytes"
+            ));
+        }
+        if self.title.contains('\n') {
+            return Err("the agenda title must be in a line".to_owned());
+        }
+        if self.rationale.len() > MAX_AGENDA_RATIONALE_SIZE {
+            return Err(format!(
+                "too long agenda rationale: {} > {MAX_AGENDA_RATIONALE_SIZE}",
+                self.rationale.len()
+            ));
+        }
+        if self.links.len() > MAX_AGENDA_LINKS {
+            return Err(format!(
+                "too many agenda links: {} > {MAX_AGENDA_LINKS}",
+                self.links.len()
+            ));
+        }
+        for link in &self.links {
+            if link.is_empty()
+                || link.len() > MAX_AGENDA_LINK_SIZE
+                || link.contains(char::is_whitespace)
+            {
+                return Err(format!("invalid agenda link: {link}"));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -98,12 +354,43 @@ pub struct AgendaProof {
     pub timestamp: Timestamp,
 }
 
+impl AgendaProof {
+    /// The canonical order of the approved agendas: by the height, and then by the agenda hash.
+    ///
+    /// A block finalizes a single agenda (the commit sequence admits no second one),
+    /// so it's the order in which the proposers take the approved agendas into their blocks,
+    /// which makes the blocks reproducible across the proposers.
+    /// The approval time is not a part of it, since `timestamp` is not signed
+    /// and an agenda approved late could otherwise never follow the finalized ones.
+    /// From [`verify::CANONICAL_AGENDA_ORDER_VERSION`], the commit sequence verifier rejects
+    /// an agenda proof that precedes the one of the previous block.
+    pub fn canonical_cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.height, self.agenda_hash).cmp(&(other.height, other.agenda_hash))
+    }
+}
+
+/// A veto on an agenda by one of [`ReservedState::veto_holders`].
+///
+/// An agenda with an unexpired veto can't be approved regardless of the votes:
+/// an agenda proof timestamped before `expires_at` is rejected (see [`ReservedState::vetoers`]).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AgendaVeto {
+    pub agenda_hash: Hash256,
+    pub expires_at: Timestamp,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SignedAgendaVeto {
+    pub veto: AgendaVeto,
+    pub signature: TypedSignature<AgendaVeto>,
+}
+
 /// An abstracted diff of the state.
 ///
 /// - The actual content of the diff (for the non-reserved state)
-/// is not cared by the Simperby node. It only keeps the hash of it.
+///   is not cared by the Simperby node. It only keeps the hash of it.
 /// - It holds the reserved state as a `Box` to flatten the variant size.
-/// (see https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant)
+///   (see https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant)
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum Diff {
     /// Nothing changed in the repository; an empty commit.
@@ -120,14 +407,15 @@ pub enum Diff {
 
 /// A general transaction to be included in the agenda.
 ///
-/// Note that none of the fields are checked by the Simperby core protocol;
+/// Note that none of the fields are checked by the Simperby core protocol
+/// (except the blob references in `body`);
 /// they just represent a Git commit which is used for general data recording.
 ///
 /// - `author` and `timestamp` is that of the **author signature** of the git commit.
 /// - `committer` signature will be always the same as the `author` signature.
-/// (if not, it will be rejected by the node)
+///   (if not, it will be rejected by the node)
 /// - `head` and `body` might be used for the trustless message delivery.
-/// Please refer to the *simperby-settlement* crate.
+///   Please refer to the *simperby-settlement* crate.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Transaction {
     pub author: MemberName,
@@ -137,11 +425,47 @@ pub struct Transaction {
     pub diff: Diff,
 }
 
+/// The prefix of a line in [`Transaction::body`] that references a blob.
+///
+/// The line is in the form of `Blob: <hash> <size in bytes>`.
+pub const BLOB_REFERENCE_PREFIX: &str = "Blob: ";
+
+/// A reference to a large file that is kept in the blob store instead of the repository.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BlobReference {
+    /// The hash of the content.
+    pub hash: Hash256,
+    pub size: u64,
+}
+
+impl Transaction {
+    /// Parses the blob references in the body.
+    pub fn blob_references(&self) -> Result<Vec<BlobReference>, String> {
+        self.body
+            .lines()
+            .filter_map(|line| line.strip_prefix(BLOB_REFERENCE_PREFIX))
+            .map(|reference| {
+                let invalid = || format!("invalid blob reference: {reference}");
+                let (hash, size) = reference.trim().split_once(' ').ok_or_else(invalid)?;
+                let hash = hex::decode(hash)
+                    .ok()
+                    .and_then(|hash| hash.try_into().ok())
+                    .ok_or_else(invalid)?;
+                Ok(BlobReference {
+                    hash: Hash256::from_array(hash),
+                    size: size.parse().map_err(|_| invalid())?,
+                })
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum ExtraAgendaTransaction {
     Delegate(TxDelegate),
     Undelegate(TxUndelegate),
     Report(TxReport),
+    Ban(TxBan),
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -150,15 +474,187 @@ pub struct TxDelegate {
     pub proof: TypedSignature<DelegationTransactionData>,
 }
 
+impl TxDelegate {
+    pub fn builder() -> TxDelegateBuilder {
+        TxDelegateBuilder::default()
+    }
+}
+
+/// Builds a [`TxDelegate`], checking that the fields are coherent before signing.
+///
+/// The fields are the same as those of [`DelegationTransactionData`];
+/// all but `governance` (which defaults to `false`) must be set.
+#[derive(Debug, Clone, Default)]
+pub struct TxDelegateBuilder {
+    delegator: Option<MemberName>,
+    delegatee: Option<MemberName>,
+    governance: bool,
+    block_height: Option<BlockHeight>,
+    timestamp: Option<Timestamp>,
+    chain_name: Option<String>,
+    nonce: Option<u64>,
+}
+
+impl TxDelegateBuilder {
+    pub fn delegator(mut self, delegator: MemberName) -> Self {
+        self.delegator = Some(delegator);
+        self
+    }
+
+    pub fn delegatee(mut self, delegatee: MemberName) -> Self {
+        self.delegatee = Some(delegatee);
+        self
+    }
+
+    pub fn governance(mut self, governance: bool) -> Self {
+        self.governance = governance;
+        self
+    }
+
+    pub fn block_height(mut self, block_height: BlockHeight) -> Self {
+        self.block_height = Some(block_height);
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn chain_name(mut self, chain_name: String) -> Self {
+        self.chain_name = Some(chain_name);
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Returns the data to sign, or why the fields are not coherent.
+    ///
+    /// Its [`ToHash256`] is the canonical signing payload,
+    /// which is what an external signer has to sign for [`TxDelegate::proof`].
+    pub fn payload(&self) -> Result<DelegationTransactionData, String> {
+        fn required<T: Clone>(field: &Option<T>, name: &str) -> Result<T, String> {
+            field.clone().ok_or_else(|| format!("{name} is not set"))
+        }
+        let data = DelegationTransactionData {
+            delegator: required(&self.delegator, "delegator")?,
+            delegatee: required(&self.delegatee, "delegatee")?,
+            governance: self.governance,
+            block_height: required(&self.block_height, "block height")?,
+            timestamp: required(&self.timestamp, "timestamp")?,
+            chain_name: required(&self.chain_name, "chain name")?,
+            nonce: required(&self.nonce, "nonce")?,
+        };
+        if data.delegator.is_empty() || data.delegatee.is_empty() {
+            return Err("empty member name".to_string());
+        }
+        if data.delegator == data.delegatee {
+            return Err(format!(
+                "delegator and delegatee are the same: {}",
+                data.delegator
+            ));
+        }
+        if data.chain_name.is_empty() {
+            return Err("empty chain name".to_string());
+        }
+        // The nonce of a member starts at 0 and must increase.
+        if data.nonce == 0 {
+            return Err("nonce must be greater than 0".to_string());
+        }
+        Ok(data)
+    }
+
+    /// Signs the payload by the delegator.
+    pub fn build<S: Signer + ?Sized>(self, signer: &S) -> Result<TxDelegate, String> {
+        let data = self.payload()?;
+        let proof = TypedSignature::sign_by(&data, signer)
+            .map_err(|e| format!("failed to sign the delegation: {e}"))?;
+        Ok(TxDelegate { data, proof })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct TxUndelegate {
     pub data: UndelegationTransactionData,
     pub proof: TypedSignature<UndelegationTransactionData>,
 }
 
+/// A report of a misbehavior, which slashes the offender when the block is finalized.
+///
+/// It holds the evidence as a `Box` to flatten the variant size.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct TxReport {
-    // TODO
+    pub evidence: Box<DoubleSignEvidence>,
+    pub timestamp: Timestamp,
+}
+
+/// A ban of a member, signed by the members holding the majority of the governance voting power.
+///
+/// It cuts off a member whose infrastructure is compromised until the governance can remove it
+/// (see [`ReservedState::apply_ban`]), without waiting for an agenda.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TxBan {
+    pub data: BanTransactionData,
+    pub proof: Vec<TypedSignature<BanTransactionData>>,
+}
+
+/// A precommit on a block, signed by a validator.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SignedPrecommit {
+    pub header: BlockHeader,
+    pub round: ConsensusRound,
+    pub timestamp: Timestamp,
+    pub signature: TypedSignature<FinalizationSignTarget>,
+}
+
+impl SignedPrecommit {
+    /// Returns what has been signed.
+    pub fn sign_target(&self) -> FinalizationSignTarget {
+        FinalizationSignTarget {
+            block_hash: self.header.to_hash256(),
+            round: self.round,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+/// A proof that a validator has precommitted two different blocks of the same height in the same round.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DoubleSignEvidence {
+    pub first: SignedPrecommit,
+    pub second: SignedPrecommit,
+}
+
+impl DoubleSignEvidence {
+    /// Returns the height of the conflicting blocks.
+    pub fn height(&self) -> BlockHeight {
+        self.first.header.height
+    }
+
+    /// Returns the offender.
+    pub fn offender(&self) -> &PublicKey {
+        self.first.signature.signer()
+    }
+}
+
+/// A proof that two different blocks on top of the same block have been finalized,
+/// which is a fatal safety failure that only a manual governance action can resolve.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ForkEvidence {
+    pub first: BlockHeader,
+    pub first_proof: FinalizationProof,
+    pub second: BlockHeader,
+    pub second_proof: FinalizationProof,
+}
+
+impl ForkEvidence {
+    /// Returns the height of the conflicting blocks.
+    pub fn height(&self) -> BlockHeight {
+        self.first.height
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -167,19 +663,87 @@ pub struct DelegationTransactionData {
     pub delegatee: MemberName,
     /// Whether to delegate the governance voting power too.
     pub governance: bool,
+    /// The height of the block that the transaction is meant for; it's rejected in any other.
     pub block_height: BlockHeight,
     pub timestamp: Timestamp,
     pub chain_name: String,
+    /// Must be greater than [`Member::nonce`] of the delegator, which prevents replays.
+    pub nonce: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct UndelegationTransactionData {
     pub delegator: MemberName,
+    /// The height of the block that the transaction is meant for; it's rejected in any other.
+    pub block_height: BlockHeight,
+    pub timestamp: Timestamp,
+    pub chain_name: String,
+    /// Must be greater than [`Member::nonce`] of the delegator, which prevents replays.
+    pub nonce: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BanTransactionData {
+    pub member: MemberName,
+    /// The height of the block whose finalization lifts the ban.
+    ///
+    /// Setting it to `block_height` lifts an existing ban with the block.
+    pub until_height: BlockHeight,
+    /// The height of the block that the transaction is meant for; it's rejected in any other.
     pub block_height: BlockHeight,
     pub timestamp: Timestamp,
     pub chain_name: String,
 }
 
+/// The data of a request to join the network as a new member.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct JoinRequestData {
+    /// The desired member name.
+    pub name: MemberName,
+    pub public_key: PublicKey,
+    /// The requested voting power, for both the governance and the consensus.
+    pub voting_power: VotingPower,
+    pub timestamp: Timestamp,
+    pub chain_name: String,
+}
+
+/// A request to join the network, signed by the key of the prospective member.
+///
+/// An existing member wraps it into a transaction that adds the member to the reserved state
+/// (see [`ReservedState::apply_join_request`]), which takes effect once its agenda is approved.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct JoinRequest {
+    pub data: JoinRequestData,
+    pub proof: TypedSignature<JoinRequestData>,
+}
+
+/// A report that a validator has missed too many precommits,
+/// proposing to take away its consensus voting power.
+///
+/// Unlike a [`TxReport`], being offline can't be proven by the validator's signatures,
+/// so it's wrapped into an ordinary transaction
+/// (see [`ReservedState::apply_offline_report`]), which takes effect once its agenda is approved.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct OfflineReport {
+    pub offender: MemberName,
+    /// The heights of the blocks that the precommits were counted over, inclusive.
+    pub from_height: BlockHeight,
+    pub to_height: BlockHeight,
+    pub missed_precommits: u64,
+    pub eligible_blocks: u64,
+}
+
+/// A replacement of the metadata of a member.
+///
+/// Like an [`OfflineReport`], it's wrapped into an ordinary transaction
+/// (see [`ReservedState::apply_member_metadata_update`]), which takes effect once its agenda is approved.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TxUpdateMemberMetadata {
+    pub member: MemberName,
+    /// The new metadata, replacing the whole of the current one.
+    pub metadata: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct GenesisInfo {
     pub header: BlockHeader,
@@ -1,9 +1,45 @@
-use crate::Timestamp;
+use crate::prelude::*;
 
 /// Generates a timestamp in the same as the node does.
-pub fn get_timestamp() -> Timestamp {
+#[cfg(feature = "std")]
+pub fn get_timestamp() -> crate::Timestamp {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
-        .as_millis() as Timestamp
+        .as_millis() as crate::Timestamp
+}
+
+/// Maps each item by `f`, in parallel if there are at least `threshold` items,
+/// returning the results in the order of the items.
+///
+/// Without the `std` feature, it always maps them one by one.
+#[cfg(feature = "std")]
+pub fn map_in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    threshold: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |x| x.get());
+    if items.len() < threshold.max(2) || threads == 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(not(feature = "std"))]
+pub fn map_in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    _threshold: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    items.iter().map(f).collect()
 }
@@ -1,7 +1,9 @@
+use crate::merkle_tree::OneshotMerkleTree;
+use crate::prelude::*;
 use crate::reserved::ReservedState;
 use crate::*;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -14,6 +16,57 @@ pub enum Error {
     CryptoError(String, CryptoError),
     #[error("invalid commit: applied {0} commit cannot be applied at {1} phase")]
     PhaseMismatch(String, String),
+    #[error("{limit} exceeds the limit of the reserved state: {size} > {max}")]
+    LimitExceeded { limit: Limit, size: u64, max: u64 },
+}
+
+/// A size limit of the reserved state, which is not enforced if zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// [`ReservedState::max_commit_body_size`].
+    CommitBodySize,
+    /// [`ReservedState::max_agenda_transactions`].
+    AgendaTransactions,
+    /// [`ReservedState::max_block_extra_agenda_transactions`].
+    BlockExtraAgendaTransactions,
+}
+
+impl Limit {
+    pub fn max(&self, rs: &ReservedState) -> u64 {
+        match self {
+            Limit::CommitBodySize => rs.max_commit_body_size,
+            Limit::AgendaTransactions => rs.max_agenda_transactions,
+            Limit::BlockExtraAgendaTransactions => rs.max_block_extra_agenda_transactions,
+        }
+    }
+}
+
+impl core::fmt::Display for Limit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Limit::CommitBodySize => write!(f, "the body size of the transaction"),
+            Limit::AgendaTransactions => write!(f, "the number of transactions in the agenda"),
+            Limit::BlockExtraAgendaTransactions => {
+                write!(f, "the number of extra-agenda transactions in the block")
+            }
+        }
+    }
+}
+
+/// The first protocol version whose agenda proofs must be finalized in the canonical order
+/// (see [`AgendaProof::canonical_cmp`]).
+pub const CANONICAL_AGENDA_ORDER_VERSION: &str = "0.2.0";
+
+/// Checks whether the protocol `version` is `min_version` or later.
+pub fn version_at_least(version: &str, min_version: &str) -> Result<bool, Error> {
+    let parse = |version: &str| {
+        version
+            .split('.')
+            .map(|x| x.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Error::InvalidArgument(format!("invalid version: {version}")))
+    };
+    Ok(parse(version)? >= parse(min_version)?)
 }
 
 /// Verifies whether `h2` can be the direct child of `h1`.
@@ -23,6 +76,14 @@ pub enum Error {
 /// 2. finalization proof
 /// 3. protocol version of the node binary.
 pub fn verify_header_to_header(h1: &BlockHeader, h2: &BlockHeader) -> Result<(), Error> {
+    verify_header_to_header_with(h1, h2, &VerifiedSignatures::default())
+}
+
+fn verify_header_to_header_with(
+    h1: &BlockHeader,
+    h2: &BlockHeader,
+    verified: &VerifiedSignatures,
+) -> Result<(), Error> {
     if h2.height != h1.height + 1 {
         return Err(Error::InvalidArgument(format!(
             "invalid height: expected {}, got {}",
@@ -53,7 +114,19 @@ pub fn verify_header_to_header(h1: &BlockHeader, h2: &BlockHeader) -> Result<(),
             h1.timestamp, h2.timestamp
         )));
     }
-    verify_finalization_proof(h1, &h2.prev_block_finalization_proof)?;
+    verify_finalization_proof_with(h1, &h2.prev_block_finalization_proof, verified)?;
+    let bft_time = h2
+        .prev_block_finalization_proof
+        .bft_time(&h1.validator_set)
+        .ok_or_else(|| {
+            Error::InvalidProof("finalization proof has no valid timestamp".to_string())
+        })?;
+    if h2.timestamp != bft_time {
+        return Err(Error::InvalidArgument(format!(
+            "invalid timestamp: expected the BFT time {}, got {}",
+            bft_time, h2.timestamp
+        )));
+    }
     Ok(())
 }
 
@@ -61,17 +134,45 @@ pub fn verify_header_to_header(h1: &BlockHeader, h2: &BlockHeader) -> Result<(),
 pub fn verify_finalization_proof(
     header: &BlockHeader,
     block_finalization_proof: &FinalizationProof,
+) -> Result<(), Error> {
+    verify_finalization_proof_with(
+        header,
+        block_finalization_proof,
+        &VerifiedSignatures::default(),
+    )
+}
+
+fn verify_finalization_proof_with(
+    header: &BlockHeader,
+    block_finalization_proof: &FinalizationProof,
+    verified: &VerifiedSignatures,
 ) -> Result<(), Error> {
     let total_voting_power: VotingPower = header.validator_set.iter().map(|(_, v)| v).sum();
-    let mut voted_validators = HashSet::new();
-    for signature in &block_finalization_proof.signatures {
+    let block_hash = header.to_hash256();
+    let unverified = block_finalization_proof
+        .signatures
+        .iter()
+        .map(|precommit| {
+            let target = precommit.sign_target(block_hash, block_finalization_proof.round);
+            (precommit.signature(), target.to_hash256())
+        })
+        .filter(|(signature, hash)| !verified.contains(signature, *hash))
+        .collect::<Vec<_>>();
+    for result in verify_in_parallel(&unverified, |(signature, hash)| {
         signature
-            .verify(&FinalizationSignTarget {
-                block_hash: header.to_hash256(),
-                round: block_finalization_proof.round,
-            })
-            .map_err(|e| Error::CryptoError("invalid finalization proof".to_string(), e))?;
-        voted_validators.insert(signature.signer());
+            .get_raw_signature()
+            .verify(*hash, signature.signer())
+    }) {
+        result.map_err(|e| Error::CryptoError("invalid finalization proof".to_string(), e))?;
+    }
+    let mut voted_validators = BTreeSet::new();
+    for PrecommitSignature(signature, _) in &block_finalization_proof.signatures {
+        if !voted_validators.insert(signature.signer()) {
+            return Err(Error::InvalidProof(format!(
+                "invalid finalization proof - duplicate signature from {}",
+                signature.signer()
+            )));
+        }
     }
     let voted_voting_power: VotingPower = header
         .validator_set
@@ -87,6 +188,132 @@ pub fn verify_finalization_proof(
     Ok(())
 }
 
+fn verify_members(rs: &ReservedState) -> Result<(), Error> {
+    rs.check_member_auths()
+        .and_then(|_| rs.check_member_metadata())
+        .and_then(|_| {
+            rs.transaction_spec
+                .as_ref()
+                .map_or(Ok(()), TransactionSpec::check)
+        })
+        .map_err(|e| Error::InvalidArgument(format!("invalid reserved state: {e}")))
+}
+
+fn verify_blob_references(tx: &Transaction, rs: &ReservedState) -> Result<(), Error> {
+    for blob in tx.blob_references().map_err(Error::InvalidArgument)? {
+        if blob.size > rs.max_blob_size {
+            return Err(Error::InvalidArgument(format!(
+                "blob {} is too large: {} > {}",
+                blob.hash, blob.size, rs.max_blob_size
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn verify_limit(limit: Limit, size: usize, rs: &ReservedState) -> Result<(), Error> {
+    let (size, max) = (size as u64, limit.max(rs));
+    if max != 0 && size > max {
+        return Err(Error::LimitExceeded { limit, size, max });
+    }
+    Ok(())
+}
+
+/// The maximum number of blocks after which a misbehavior can no longer be reported.
+pub const EVIDENCE_MAX_AGE: BlockHeight = 100;
+
+/// Verifies the given double-sign evidence, which doesn't depend on any state.
+pub fn verify_double_sign_evidence(evidence: &DoubleSignEvidence) -> Result<(), Error> {
+    let (first, second) = (&evidence.first, &evidence.second);
+    if first.signature.signer() != second.signature.signer() {
+        return Err(Error::InvalidArgument(format!(
+            "invalid evidence: different signers {} and {}",
+            first.signature.signer(),
+            second.signature.signer()
+        )));
+    }
+    if first.header.height != second.header.height || first.round != second.round {
+        return Err(Error::InvalidArgument(format!(
+            "invalid evidence: precommits on different height or round: ({}, {}) and ({}, {})",
+            first.header.height, first.round, second.header.height, second.round
+        )));
+    }
+    if first.header.to_hash256() == second.header.to_hash256() {
+        return Err(Error::InvalidArgument(
+            "invalid evidence: precommits on the same block".to_string(),
+        ));
+    }
+    for precommit in [first, second] {
+        precommit
+            .signature
+            .verify(&precommit.sign_target())
+            .map_err(|e| Error::CryptoError("invalid evidence".to_string(), e))?;
+    }
+    Ok(())
+}
+
+/// Verifies the given fork evidence, which doesn't depend on any state.
+///
+/// Both blocks must extend the same block with the same validator set,
+/// so that the proofs can't be made up by a validator set that never existed.
+pub fn verify_fork_evidence(evidence: &ForkEvidence) -> Result<(), Error> {
+    let (first, second) = (&evidence.first, &evidence.second);
+    if first.height != second.height || first.previous_hash != second.previous_hash {
+        return Err(Error::InvalidArgument(format!(
+            "invalid evidence: blocks on different parents: ({}, {}) and ({}, {})",
+            first.height, first.previous_hash, second.height, second.previous_hash
+        )));
+    }
+    if first.validator_set != second.validator_set {
+        return Err(Error::InvalidArgument(
+            "invalid evidence: blocks with different validator sets".to_string(),
+        ));
+    }
+    if first.to_hash256() == second.to_hash256() {
+        return Err(Error::InvalidArgument(
+            "invalid evidence: the same block".to_string(),
+        ));
+    }
+    verify_finalization_proof(first, &evidence.first_proof)?;
+    verify_finalization_proof(second, &evidence.second_proof)?;
+    Ok(())
+}
+
+/// Checks whether the evidence can be included in the block of `height`.
+fn verify_evidence_age(evidence: &DoubleSignEvidence, height: BlockHeight) -> Result<(), Error> {
+    if evidence.height() > height || height - evidence.height() > EVIDENCE_MAX_AGE {
+        return Err(Error::InvalidArgument(format!(
+            "invalid evidence: height {} is expired or in the future at {}",
+            evidence.height(),
+            height
+        )));
+    }
+    Ok(())
+}
+
+/// Checks whether the delegation or undelegation is meant for the block of `height`,
+/// so that it can't be replayed in another block.
+fn verify_target_height(target: BlockHeight, height: BlockHeight) -> Result<(), Error> {
+    if target != height {
+        return Err(Error::InvalidArgument(format!(
+            "invalid extra-agenda transaction: meant for height {target}, not {height}"
+        )));
+    }
+    Ok(())
+}
+
+/// Checks the limits of the agenda description and the voting deadline, if any.
+fn verify_agenda_description(agenda: &Agenda) -> Result<(), Error> {
+    if let Some(description) = &agenda.description {
+        description
+            .check()
+            .map_err(|e| Error::InvalidArgument(format!("invalid agenda description: {e}")))?;
+    }
+    agenda
+        .check_deadline()
+        .map_err(|e| Error::InvalidArgument(format!("invalid agenda deadline: {e}")))
+}
+
 // Phases of the `CommitSequenceVerifier`.
 //
 // Note that `Phase::X` is agenda phase where `Commit::X` is the last commit.
@@ -110,12 +337,121 @@ enum Phase {
     // Extra phase consists of `ExtraAgendaTransaction`s and `ChatLog`s.
     ExtraAgendaTransaction {
         last_extra_agenda_timestamp: Timestamp,
+        extra_agenda_transactions: usize,
         // TODO: add `ChatLog` here.
     },
     // The block phase.
     Block,
 }
 
+/// The signatures to verify all at once, in parallel, ahead of the commits that carry them.
+///
+/// Verifying the signatures block by block leaves the threads idle on the small proofs,
+/// while the rest of [`CommitSequenceVerifier::apply_commit`] is cheap
+/// but has to follow the order of the commits.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureBatch {
+    signatures: Vec<(Hash256, Signature, PublicKey)>,
+}
+
+impl SignatureBatch {
+    /// Adds the signature on the data of the given hash.
+    pub fn add<T: ToHash256>(&mut self, signature: &TypedSignature<T>, hash: Hash256) {
+        self.signatures.push((
+            hash,
+            signature.get_raw_signature(),
+            signature.signer().clone(),
+        ));
+    }
+
+    /// Adds the signatures of the finalization proofs and the agenda proofs in the commits,
+    /// which follow the given header.
+    pub fn add_commits<'a>(
+        &mut self,
+        start_header: &BlockHeader,
+        commits: impl IntoIterator<Item = &'a Commit>,
+    ) {
+        let mut block_hash = start_header.to_hash256();
+        for commit in commits {
+            match commit {
+                Commit::Block(header) => {
+                    let proof = &header.prev_block_finalization_proof;
+                    for precommit in &proof.signatures {
+                        let target = precommit.sign_target(block_hash, proof.round);
+                        self.add(precommit.signature(), target.to_hash256());
+                    }
+                    block_hash = header.to_hash256();
+                }
+                Commit::AgendaProof(agenda_proof) => {
+                    for signature in &agenda_proof.proof {
+                        self.add(signature, agenda_proof.agenda_hash);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Verifies the signatures in parallel, leaving out the invalid ones
+    /// for the verifier to report in the order of the commits.
+    pub fn verify(self) -> VerifiedSignatures {
+        let results = verify_in_parallel(&self.signatures, |(hash, signature, signer)| {
+            signature.verify(*hash, signer)
+        });
+        VerifiedSignatures {
+            signatures: Arc::new(
+                self.signatures
+                    .into_iter()
+                    .zip(results)
+                    .filter_map(|(signature, result)| result.ok().map(|_| signature))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The signatures verified by a [`SignatureBatch`].
+///
+/// Only the signatures themselves are verified ahead; whether the signers can sign
+/// is still checked in the order of the commits, on the reserved state at the moment.
+#[derive(Debug, Clone, Default)]
+pub struct VerifiedSignatures {
+    signatures: Arc<BTreeSet<(Hash256, Signature, PublicKey)>>,
+}
+
+impl VerifiedSignatures {
+    pub fn contains<T: ToHash256>(&self, signature: &TypedSignature<T>, hash: Hash256) -> bool {
+        !self.signatures.is_empty()
+            && self.signatures.contains(&(
+                hash,
+                signature.get_raw_signature(),
+                signature.signer().clone(),
+            ))
+    }
+
+    /// Verifies the signature on the data of the given hash, unless it's verified already.
+    pub fn verify<T: ToHash256>(
+        &self,
+        signature: &TypedSignature<T>,
+        hash: Hash256,
+    ) -> Result<(), CryptoError> {
+        if self.contains(signature, hash) {
+            return Ok(());
+        }
+        signature
+            .get_raw_signature()
+            .verify(hash, signature.signer())
+    }
+}
+
 /// Verifies whether the given sequence of commits can be a partial sequence of a valid finalized chain.
 ///
 /// It may accept sequences that contain more than one `BlockHeader`.
@@ -124,22 +460,52 @@ pub struct CommitSequenceVerifier {
     header: BlockHeader,
     phase: Phase,
     reserved_state: ReservedState,
+    /// The state in effect at the last block, which determines the validator set of the next one.
+    block_reserved_state: ReservedState,
     commits_for_next_block: Vec<Commit>,
+    /// The hashes of `commits_for_next_block`, of which the commit merkle root is calculated.
+    commit_hashes_for_next_block: Vec<Hash256>,
     total_commits: Vec<Commit>,
+    verified_signatures: VerifiedSignatures,
+    /// The last agenda proof received, which the next one must not precede in the canonical order.
+    last_agenda_proof: Option<AgendaProof>,
+    /// The agenda of the next block, once its agenda proof is received.
+    next_block_agenda: Option<Agenda>,
+    /// The agenda of the last header, whose voting deadline the finalization of the header must meet.
+    last_header_agenda: Option<Agenda>,
 }
 
 impl CommitSequenceVerifier {
     /// Creates a new `CommitSequenceVerifier` with the given block header.
+    /// The reserved state is the one stored at the start header,
+    /// whose scheduled changes due by then are applied here.
     pub fn new(start_header: BlockHeader, reserved_state: ReservedState) -> Result<Self, Error> {
+        let reserved_state = reserved_state.effective_at(start_header.height);
         Ok(Self {
+            block_reserved_state: reserved_state.clone(),
+            reserved_state,
             header: start_header.clone(),
             phase: Phase::Block,
-            reserved_state,
             commits_for_next_block: vec![],
+            commit_hashes_for_next_block: vec![],
             total_commits: vec![Commit::Block(start_header)],
+            verified_signatures: VerifiedSignatures::default(),
+            last_agenda_proof: None,
+            next_block_agenda: None,
+            last_header_agenda: None,
         })
     }
 
+    /// Sets the signatures verified ahead, which are not verified again
+    /// (see [`SignatureBatch`]).
+    pub fn set_verified_signatures(&mut self, verified_signatures: VerifiedSignatures) {
+        self.verified_signatures = verified_signatures;
+    }
+
+    pub fn get_verified_signatures(&self) -> &VerifiedSignatures {
+        &self.verified_signatures
+    }
+
     /// Returns the commits received so far.
     pub fn get_total_commits(&self) -> &[Commit] {
         &self.total_commits
@@ -168,30 +534,66 @@ impl CommitSequenceVerifier {
     /// Note that due to the nature of the finalization proof (included in the next block)
     /// there is always an unverified last header (which may even not be the last commit).
     pub fn verify_last_header_finalization(&self, proof: &FinalizationProof) -> Result<(), Error> {
-        verify_finalization_proof(&self.header, proof)
+        verify_finalization_proof(&self.header, proof)?;
+        let bft_time = proof.bft_time(&self.header.validator_set).ok_or_else(|| {
+            Error::InvalidProof("finalization proof has no valid timestamp".to_string())
+        })?;
+        self.verify_agenda_deadline(bft_time)
+    }
+
+    /// Checks that the last header is finalized by the voting deadline of its agenda,
+    /// given the BFT time of its finalization proof.
+    fn verify_agenda_deadline(&self, bft_time: Timestamp) -> Result<(), Error> {
+        if let Some(agenda) = &self.last_header_agenda {
+            if !agenda.is_open_at(bft_time) {
+                return Err(Error::InvalidProof(format!(
+                    "invalid finalization proof: finalized at {}, after the voting deadline of the agenda {}",
+                    bft_time,
+                    agenda.to_hash256()
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Verifies whether the given reserved state is valid from the current state.
-    pub fn verify_reserved_state(&self, _rs: &ReservedState) -> Result<(), Error> {
+    pub fn verify_reserved_state(&self, rs: &ReservedState) -> Result<(), Error> {
+        verify_members(rs)?;
+        rs.check_leader_order()
+            .map_err(|e| Error::InvalidArgument(format!("invalid reserved state: {e}")))?;
         // TODO:
         // 1. Check that the number of members is at least 4.
         // 2. Check that the version advances correctly.
-        // 3. Check that `consensus_leader_order` is correct.
-        // 4. Check that `genesis_info` stays the same.
-        // 5. Check that the newly added (if exists) `Member::name` is unique.
-        // 6. Check that `member` monotonicaly increases (refer to `Member::expelled`).
-        // 7. Check that the delegation state doesn't change.
+        // 3. Check that `genesis_info` stays the same.
+        // 4. Check that the newly added (if exists) `Member::name` is unique.
+        // 5. Check that `member` monotonicaly increases (refer to `Member::expelled`).
+        // 6. Check that the delegation state doesn't change.
         Ok(())
     }
 
     /// Verifies the given commit and updates the internal reserved_state of CommitSequenceVerifier.
     pub fn apply_commit(&mut self, commit: &Commit) -> Result<(), Error> {
+        self.apply_commit_with_hash(commit, commit.to_hash256())
+    }
+
+    /// Same as [`Self::apply_commit`], with the hash of the commit calculated ahead
+    /// (e.g., in parallel with the other commits), which must be `commit.to_hash256()`.
+    pub fn apply_commit_with_hash(&mut self, commit: &Commit, hash: Hash256) -> Result<(), Error> {
+        debug_assert_eq!(hash, commit.to_hash256());
         match (commit, &mut self.phase) {
             (Commit::Block(block_header), Phase::AgendaProof { agenda_proof: _ }) => {
-                verify_header_to_header(&self.header, block_header)?;
+                verify_header_to_header_with(
+                    &self.header,
+                    block_header,
+                    &self.verified_signatures,
+                )?;
+                self.verify_agenda_deadline(block_header.timestamp)?;
+                self.block_reserved_state
+                    .check_validator_set(block_header)
+                    .map_err(Error::InvalidArgument)?;
                 // Verify commit merkle root
                 let commit_merkle_root =
-                    BlockHeader::calculate_commit_merkle_root(&self.commits_for_next_block);
+                    OneshotMerkleTree::create(self.commit_hashes_for_next_block.clone()).root();
                 if commit_merkle_root != block_header.commit_merkle_root {
                     return Err(Error::InvalidArgument(format!(
                         "invalid commit merkle root: expected {}, got {}",
@@ -199,26 +601,28 @@ impl CommitSequenceVerifier {
                     )));
                 };
                 self.header = block_header.clone();
+                self.last_header_agenda = self.next_block_agenda.take();
+                self.reserved_state = self.reserved_state.effective_at(block_header.height);
+                self.block_reserved_state = self.reserved_state.clone();
                 self.phase = Phase::Block;
                 self.commits_for_next_block = vec![];
+                self.commit_hashes_for_next_block = vec![];
             }
-            (
-                Commit::Block(block_header),
-                Phase::ExtraAgendaTransaction {
-                    last_extra_agenda_timestamp,
-                },
-            ) => {
-                verify_header_to_header(&self.header, block_header)?;
-                // Check if the block contains all the extra-agenda transactions.
-                if block_header.timestamp < *last_extra_agenda_timestamp {
-                    return Err(Error::InvalidArgument(format!(
-                        "invalid block timestamp: expected larger than or equal to the last extra-agenda transaction timestamp {}, got {}",
-                        last_extra_agenda_timestamp, block_header.timestamp
-                    )));
-                }
+            (Commit::Block(block_header), Phase::ExtraAgendaTransaction { .. }) => {
+                // Note that the block timestamp is not compared with the extra-agenda transactions,
+                // since it's the BFT time of the previous block, which always precedes them.
+                verify_header_to_header_with(
+                    &self.header,
+                    block_header,
+                    &self.verified_signatures,
+                )?;
+                self.verify_agenda_deadline(block_header.timestamp)?;
+                self.block_reserved_state
+                    .check_validator_set(block_header)
+                    .map_err(Error::InvalidArgument)?;
                 // Verify commit hash
                 let commit_merkle_root =
-                    BlockHeader::calculate_commit_merkle_root(&self.commits_for_next_block);
+                    OneshotMerkleTree::create(self.commit_hashes_for_next_block.clone()).root();
                 if commit_merkle_root != block_header.commit_merkle_root {
                     return Err(Error::InvalidArgument(format!(
                         "invalid commit merkle root: expected {}, got {}",
@@ -226,12 +630,26 @@ impl CommitSequenceVerifier {
                     )));
                 };
                 self.header = block_header.clone();
+                self.last_header_agenda = self.next_block_agenda.take();
+                self.reserved_state = self.reserved_state.effective_at(block_header.height);
+                self.block_reserved_state = self.reserved_state.clone();
                 self.phase = Phase::Block;
                 self.commits_for_next_block = vec![];
+                self.commit_hashes_for_next_block = vec![];
             }
             (Commit::Transaction(tx), Phase::Block) => {
+                verify_limit(Limit::CommitBodySize, tx.body.len(), &self.reserved_state)?;
+                verify_limit(Limit::AgendaTransactions, 1, &self.reserved_state)?;
+                verify_blob_references(tx, &self.reserved_state)?;
                 // Update reserved_state for reserved-diff transactions.
                 if let Diff::Reserved(rs) = &tx.diff {
+                    verify_members(rs)?;
+                    self.reserved_state
+                        .check_scheduled_changes(rs, self.header.height + 1)
+                        .map_err(Error::InvalidArgument)?;
+                    self.reserved_state
+                        .check_binding(rs, self.header.height + 1)
+                        .map_err(Error::InvalidArgument)?;
                     self.reserved_state = *rs.clone();
                 }
                 self.phase = Phase::Transaction {
@@ -246,6 +664,13 @@ impl CommitSequenceVerifier {
                     preceding_transactions,
                 },
             ) => {
+                verify_limit(Limit::CommitBodySize, tx.body.len(), &self.reserved_state)?;
+                verify_limit(
+                    Limit::AgendaTransactions,
+                    preceding_transactions.len() + 2,
+                    &self.reserved_state,
+                )?;
+                verify_blob_references(tx, &self.reserved_state)?;
                 // Check if transactions are in chronological order
                 if tx.timestamp < last_transaction.timestamp {
                     return Err(Error::InvalidArgument(format!(
@@ -255,6 +680,13 @@ impl CommitSequenceVerifier {
                 }
                 // Update reserved_state for reserved-diff transactions.
                 if let Diff::Reserved(rs) = &tx.diff {
+                    verify_members(rs)?;
+                    self.reserved_state
+                        .check_scheduled_changes(rs, self.header.height + 1)
+                        .map_err(Error::InvalidArgument)?;
+                    self.reserved_state
+                        .check_binding(rs, self.header.height + 1)
+                        .map_err(Error::InvalidArgument)?;
                     self.reserved_state = *rs.clone();
                 }
                 preceding_transactions.push(last_transaction.clone());
@@ -269,6 +701,7 @@ impl CommitSequenceVerifier {
                         agenda.height
                     )));
                 }
+                verify_agenda_description(agenda)?;
                 // Verify agenda without transactions
                 if agenda.transactions_hash != Agenda::calculate_transactions_hash(&[]) {
                     return Err(Error::InvalidArgument(format!(
@@ -296,6 +729,7 @@ impl CommitSequenceVerifier {
                         agenda.height
                     )));
                 }
+                verify_agenda_description(agenda)?;
                 // Check if agenda is in chronological order
                 if agenda.timestamp < last_transaction.timestamp {
                     return Err(Error::InvalidArgument(
@@ -337,77 +771,135 @@ impl CommitSequenceVerifier {
                     )));
                 }
                 // Verify the agenda proof
-                for signature in agenda_proof.proof.iter() {
-                    signature.verify(agenda).map_err(|e| {
+                let agenda_hash = agenda.to_hash256();
+                let unverified = agenda_proof
+                    .proof
+                    .iter()
+                    .filter(|signature| !self.verified_signatures.contains(signature, agenda_hash))
+                    .collect::<Vec<_>>();
+                for result in verify_in_parallel(&unverified, |signature| signature.verify(agenda))
+                {
+                    result.map_err(|e| {
                         Error::CryptoError("invalid agenda proof: invalid signature".to_string(), e)
                     })?;
                 }
                 // Check if the agenda proof is signed by the majority of the governance participants
-                let governance_set = self
+                let total_weight = self
                     .reserved_state
                     .get_governance_set()
                     .unwrap()
-                    .into_iter()
-                    .collect::<HashMap<_, _>>();
-                let total_weight = governance_set.values().sum::<u64>();
-                let signed_weight = agenda_proof
-                    .proof
-                    .iter()
-                    .map(|s| {
-                        if let Some(weight) = governance_set.get(s.signer()) {
-                            Ok(*weight)
-                        } else {
-                            Err(Error::InvalidArgument(format!(
-                                "invalid agenda proof: invalid signer {}",
-                                s.signer()
-                            )))
-                        }
-                    })
-                    .collect::<Result<Vec<_>, Error>>()?
                     .iter()
+                    .map(|(_, weight)| weight)
                     .sum::<u64>();
+                let signed_weight = self
+                    .reserved_state
+                    .get_governance_voting_power(
+                        &agenda_proof
+                            .proof
+                            .iter()
+                            .map(|s| s.signer().clone())
+                            .collect::<Vec<_>>(),
+                    )
+                    .map_err(|e| Error::InvalidArgument(format!("invalid agenda proof: {e}")))?;
                 if signed_weight * 2 <= total_weight {
                     return Err(Error::InvalidArgument(
                         "invalid agenda proof: insufficient signed weight".to_string(),
                     ));
                 }
+                // Check if the agenda is approved before its voting deadline,
+                // as claimed by the agenda proof; the finalization of the block is checked too.
+                if !agenda.is_open_at(agenda_proof.timestamp) {
+                    return Err(Error::InvalidArgument(format!(
+                        "invalid agenda proof: made at {}, after the voting deadline",
+                        agenda_proof.timestamp
+                    )));
+                }
+                // Check if the agenda proofs are in the canonical order across the blocks
+                if let Some(last_agenda_proof) = &self.last_agenda_proof {
+                    if version_at_least(
+                        &self.reserved_state.version,
+                        CANONICAL_AGENDA_ORDER_VERSION,
+                    )? && agenda_proof.canonical_cmp(last_agenda_proof).is_lt()
+                    {
+                        return Err(Error::InvalidArgument(format!(
+                            "invalid agenda proof: {} precedes the last agenda proof {} in the canonical order",
+                            agenda_proof.agenda_hash, last_agenda_proof.agenda_hash
+                        )));
+                    }
+                }
+                self.last_agenda_proof = Some(agenda_proof.clone());
+                self.next_block_agenda = Some(agenda.clone());
                 self.phase = Phase::AgendaProof {
                     agenda_proof: agenda_proof.clone(),
                 };
             }
             (Commit::ExtraAgendaTransaction(tx), Phase::AgendaProof { agenda_proof: _ }) => {
+                verify_limit(Limit::BlockExtraAgendaTransactions, 1, &self.reserved_state)?;
                 match tx {
                     ExtraAgendaTransaction::Delegate(tx) => {
                         // Update reserved reserved_state by applying delegation
-                        self.reserved_state.apply_delegate(tx).map_err(|e| {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state.delegate(tx).map_err(|e| {
                             Error::InvalidArgument(format!("invalid delegation: {e}"))
                         })?;
                         self.phase = Phase::ExtraAgendaTransaction {
                             last_extra_agenda_timestamp: tx.data.timestamp,
+                            extra_agenda_transactions: 1,
                         };
                     }
                     ExtraAgendaTransaction::Undelegate(tx) => {
                         // Update reserved reserved_state by applying undelegation
-                        self.reserved_state.apply_undelegate(tx).map_err(|e| {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state.undelegate(tx).map_err(|e| {
                             Error::InvalidArgument(format!("invalid undelegation: {e}"))
                         })?;
                         self.phase = Phase::ExtraAgendaTransaction {
                             last_extra_agenda_timestamp: tx.data.timestamp,
+                            extra_agenda_transactions: 1,
+                        };
+                    }
+                    ExtraAgendaTransaction::Report(tx) => {
+                        verify_evidence_age(&tx.evidence, self.header.height + 1)?;
+                        // Update reserved_state by slashing the offender
+                        self.reserved_state
+                            .report(tx)
+                            .map_err(|e| Error::InvalidArgument(format!("invalid report: {e}")))?;
+                        self.phase = Phase::ExtraAgendaTransaction {
+                            last_extra_agenda_timestamp: tx.timestamp,
+                            extra_agenda_transactions: 1,
+                        };
+                    }
+                    ExtraAgendaTransaction::Ban(tx) => {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state = self
+                            .reserved_state
+                            .apply_ban(tx)
+                            .map_err(|e| Error::InvalidArgument(format!("invalid ban: {e}")))?;
+                        self.phase = Phase::ExtraAgendaTransaction {
+                            last_extra_agenda_timestamp: tx.data.timestamp,
+                            extra_agenda_transactions: 1,
                         };
                     }
-                    ExtraAgendaTransaction::Report(_tx) => unimplemented!(),
                 }
             }
             (
                 Commit::ExtraAgendaTransaction(tx),
                 Phase::ExtraAgendaTransaction {
                     last_extra_agenda_timestamp,
+                    extra_agenda_transactions,
                 },
             ) => {
+                verify_limit(
+                    Limit::BlockExtraAgendaTransactions,
+                    *extra_agenda_transactions + 1,
+                    &self.reserved_state,
+                )?;
+                *extra_agenda_transactions += 1;
                 match tx {
                     ExtraAgendaTransaction::Delegate(tx) => {
                         // Update reserved reserved_state by applying delegation
-                        self.reserved_state.apply_delegate(tx).map_err(|e| {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state.delegate(tx).map_err(|e| {
                             Error::InvalidArgument(format!("invalid delegation: {e}"))
                         })?;
                         // Check if extra-agenda transactions are in chronological order
@@ -420,7 +912,8 @@ impl CommitSequenceVerifier {
                     }
                     ExtraAgendaTransaction::Undelegate(tx) => {
                         // Update reserved reserved_state by applying undelegation
-                        self.reserved_state.apply_undelegate(tx).map_err(|e| {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state.undelegate(tx).map_err(|e| {
                             Error::InvalidArgument(format!("invalid undelegation: {e}"))
                         })?;
                         // Check if extra-agenda transactions are in chronological order
@@ -431,7 +924,34 @@ impl CommitSequenceVerifier {
                         }
                         *last_extra_agenda_timestamp = tx.data.timestamp;
                     }
-                    ExtraAgendaTransaction::Report(_tx) => unimplemented!(),
+                    ExtraAgendaTransaction::Report(tx) => {
+                        verify_evidence_age(&tx.evidence, self.header.height + 1)?;
+                        // Update reserved_state by slashing the offender
+                        self.reserved_state
+                            .report(tx)
+                            .map_err(|e| Error::InvalidArgument(format!("invalid report: {e}")))?;
+                        // Check if extra-agenda transactions are in chronological order
+                        if tx.timestamp < *last_extra_agenda_timestamp {
+                            return Err(Error::InvalidArgument(
+                                format!("invalid extra-agenda transaction timestamp: expected larger than or equal to the last transaction timestamp {}, got {}", last_extra_agenda_timestamp, tx.timestamp)
+                            ));
+                        }
+                        *last_extra_agenda_timestamp = tx.timestamp;
+                    }
+                    ExtraAgendaTransaction::Ban(tx) => {
+                        verify_target_height(tx.data.block_height, self.header.height + 1)?;
+                        self.reserved_state = self
+                            .reserved_state
+                            .apply_ban(tx)
+                            .map_err(|e| Error::InvalidArgument(format!("invalid ban: {e}")))?;
+                        // Check if extra-agenda transactions are in chronological order
+                        if tx.data.timestamp < *last_extra_agenda_timestamp {
+                            return Err(Error::InvalidArgument(
+                                format!("invalid extra-agenda transaction timestamp: expected larger than or equal to the last transaction timestamp {}, got {}", last_extra_agenda_timestamp, tx.data.timestamp)
+                            ));
+                        }
+                        *last_extra_agenda_timestamp = tx.data.timestamp;
+                    }
                 }
             }
             (Commit::ChatLog(_chat_log), _) => unimplemented!(),
@@ -443,6 +963,7 @@ impl CommitSequenceVerifier {
             }
         }
         self.commits_for_next_block.push(commit.clone());
+        self.commit_hashes_for_next_block.push(hash);
         self.total_commits.push(commit.clone());
         Ok(())
     }
@@ -493,11 +1014,16 @@ mod test {
         for (i, (public_key, voting_power)) in validator_set.iter().enumerate() {
             members.push(Member {
                 public_key: public_key.clone(),
+                auth: MemberAuth::Single,
+                bls_public_key: None,
+                git_signing_key: None,
                 name: format!("member{i}").to_string(),
                 governance_voting_power: *voting_power,
                 consensus_voting_power: *voting_power,
                 governance_delegatee: None,
                 consensus_delegatee: None,
+                nonce: 0,
+                metadata: Default::default(),
             });
         }
         members
@@ -533,12 +1059,23 @@ mod test {
                     validator_keypair,
                     &genesis_header,
                     0,
+                    0,
                 ),
                 chain_name: "PDAO Chain".to_string(),
             },
             members, // TODO: fix to not use genesis header
             consensus_leader_order,
             version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
+            max_blob_size: 0,
+            max_commit_body_size: 0,
+            max_agenda_transactions: 0,
+            max_block_extra_agenda_transactions: 0,
+            scheduled_changes: Vec::new(),
+            binding: None,
+            banned_members: Vec::new(),
+            veto_holders: Vec::new(),
+            transaction_spec: None,
+            processed_evidence: Vec::new(),
         }
     }
 
@@ -574,14 +1111,19 @@ mod test {
         time: Timestamp,
     ) -> Commit {
         // Update reserved reserved_state
-        validator_keypair.push(generate_keypair([3]));
+        validator_keypair.push(generate_keypair([validator_keypair.len() as u8]));
         reserved_state.members.push(Member {
             public_key: validator_keypair.last().unwrap().0.clone(),
+            auth: MemberAuth::Single,
+            bls_public_key: None,
+            git_signing_key: None,
             name: format!("member{}", validator_keypair.len()),
             governance_voting_power: 1,
             consensus_voting_power: 1,
             governance_delegatee: None,
             consensus_delegatee: None,
+            nonce: 0,
+            metadata: Default::default(),
         });
         reserved_state
             .consensus_leader_order
@@ -621,18 +1163,13 @@ mod test {
         validator_keypair: &[(PublicKey, PrivateKey)],
         header: &BlockHeader,
         round: ConsensusRound,
+        timestamp: Timestamp,
     ) -> FinalizationProof {
-        let mut signatures: Vec<TypedSignature<FinalizationSignTarget>> = vec![];
+        let mut signatures: Vec<PrecommitSignature> = vec![];
         for (_, private_key) in validator_keypair {
             signatures.push(
-                TypedSignature::sign(
-                    &FinalizationSignTarget {
-                        round,
-                        block_hash: header.to_hash256(),
-                    },
-                    private_key,
-                )
-                .unwrap(),
+                PrecommitSignature::sign(header.to_hash256(), round, timestamp, private_key)
+                    .unwrap(),
             );
         }
         FinalizationProof { round, signatures }
@@ -652,6 +1189,7 @@ mod test {
                 validator_keypair,
                 &previous_header,
                 0,
+                time,
             ),
             previous_hash: Commit::Block(previous_header.clone()).to_hash256(),
             height: previous_header.height + 1,
@@ -732,6 +1270,8 @@ mod test {
             timestamp: 4,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -754,6 +1294,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -765,6 +1307,72 @@ mod test {
         .unwrap();
     }
 
+    #[test]
+    /// Test the case where the signatures are verified ahead in a batch.
+    fn batch_verified_signatures() {
+        let (validator_keypair, reserved_state, csv) = setup_test(4);
+        let agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        let agenda_commit = generate_agenda_commit(&agenda);
+        let agenda_proof_commit =
+            generate_agenda_proof_commit(&validator_keypair, &agenda, agenda.to_hash256());
+        let block_commit = generate_block_commit(
+            &validator_keypair,
+            0,
+            csv.header.clone(),
+            2,
+            BlockHeader::calculate_commit_merkle_root(&[
+                agenda_commit.clone(),
+                agenda_proof_commit.clone(),
+            ]),
+            Hash256::zero(),
+        );
+        let commits = vec![agenda_commit, agenda_proof_commit, block_commit];
+        let mut batch = SignatureBatch::default();
+        batch.add_commits(&csv.header, &commits);
+        assert_eq!(batch.len(), 8);
+        let verified = batch.verify();
+        let Commit::AgendaProof(agenda_proof) = &commits[1] else {
+            unreachable!()
+        };
+        assert!(verified.contains(&agenda_proof.proof[0], agenda.to_hash256()));
+
+        let mut pipelined = csv.clone();
+        pipelined.set_verified_signatures(verified);
+        for commit in &commits {
+            pipelined
+                .apply_commit_with_hash(commit, commit.to_hash256())
+                .unwrap();
+        }
+        assert_eq!(pipelined.get_block_headers().len(), 2);
+
+        // A forged signature is left out of the batch, to be rejected in order.
+        let forged_signature =
+            TypedSignature::new(Signature::zero(), validator_keypair[0].0.clone());
+        let mut forged = commits.clone();
+        let Commit::AgendaProof(agenda_proof) = &mut forged[1] else {
+            unreachable!()
+        };
+        agenda_proof.proof[0] = forged_signature.clone();
+        let mut batch = SignatureBatch::default();
+        batch.add_commits(&csv.header, &forged);
+        let verified = batch.verify();
+        assert!(!verified.contains(&forged_signature, agenda.to_hash256()));
+        let mut pipelined = csv;
+        pipelined.set_verified_signatures(verified);
+        pipelined.apply_commit(&forged[0]).unwrap();
+        assert!(matches!(
+            pipelined.apply_commit(&forged[1]),
+            Err(Error::CryptoError(..))
+        ));
+    }
+
     #[test]
     /// Test the case where the block commit is invalid because the block height is invalid.
     fn invalid_block_commit_with_invalid_height() {
@@ -776,6 +1384,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -792,6 +1402,7 @@ mod test {
                 &validator_keypair,
                 &csv.header,
                 0,
+                2,
             ),
             previous_hash: Commit::Block(csv.header.clone()).to_hash256(),
             height: csv.header.height + 2,
@@ -820,6 +1431,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -836,6 +1449,7 @@ mod test {
                 &validator_keypair,
                 &csv.header,
                 0,
+                2,
             ),
             previous_hash: Hash256::zero(),
             height: csv.header.height + 1,
@@ -864,6 +1478,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -880,6 +1496,7 @@ mod test {
                 &validator_keypair,
                 &csv.header,
                 0,
+                2,
             ),
             previous_hash: Commit::Block(csv.header.clone()).to_hash256(),
             height: csv.header.height + 1,
@@ -908,6 +1525,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -924,6 +1543,7 @@ mod test {
                 &validator_keypair,
                 &csv.header,
                 0,
+                -1,
             ),
             previous_hash: Commit::Block(csv.header.clone()).to_hash256(),
             height: csv.header.height + 1,
@@ -952,6 +1572,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -977,6 +1599,7 @@ mod test {
                     OneshotMerkleTree::create(vec![]).root(),
                 ),
                 0,
+                2,
             ),
             csv.header.to_hash256(),
             csv.header.height + 1,
@@ -997,6 +1620,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -1011,7 +1636,7 @@ mod test {
             author: validator_keypair[0].0.clone(),
             prev_block_finalization_proof: {
                 let mut proof =
-                    generate_unanimous_finalization_proof(&validator_keypair, &csv.header, 0);
+                    generate_unanimous_finalization_proof(&validator_keypair, &csv.header, 0, 2);
                 proof.signatures = vec![proof.signatures[0].clone()];
                 proof
             },
@@ -1042,6 +1667,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -1058,6 +1685,7 @@ mod test {
                 &validator_keypair,
                 &csv.header,
                 0,
+                2,
             ),
             previous_hash: Commit::Block(csv.header.clone()).to_hash256(),
             height: csv.header.height + 1,
@@ -1073,6 +1701,128 @@ mod test {
         .unwrap_err();
     }
 
+    #[test]
+    /// Test the case where the block commit is invalid because the timestamp is not the BFT time.
+    fn invalid_block_commit_with_invalid_bft_time() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        // Apply agenda commit
+        let agenda_transactions_hash = calculate_agenda_transactions_hash(csv.phase.clone());
+        let agenda: Agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: agenda_transactions_hash,
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        // Apply agenda-proof commit
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+        // Apply block commit with a timestamp different from the precommit timestamps
+        csv.apply_commit(&Commit::Block(generate_block_header(
+            &validator_keypair,
+            0,
+            generate_unanimous_finalization_proof(&validator_keypair, &csv.header, 0, 2),
+            Commit::Block(csv.header.clone()).to_hash256(),
+            csv.header.height + 1,
+            3,
+            BlockHeader::calculate_commit_merkle_root(&csv.commits_for_next_block),
+        )))
+        .unwrap_err();
+    }
+
+    #[test]
+    /// Test the case where the block commit is invalid because the validator set doesn't match the reserved state.
+    fn invalid_block_commit_with_invalid_validator_set() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        // Apply agenda commit
+        let agenda_transactions_hash = calculate_agenda_transactions_hash(csv.phase.clone());
+        let agenda: Agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: agenda_transactions_hash,
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        // Apply agenda-proof commit
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+        // Apply block commit with a voting power different from the reserved state
+        let mut block_header = generate_block_header(
+            &validator_keypair,
+            0,
+            generate_unanimous_finalization_proof(&validator_keypair, &csv.header, 0, 2),
+            Commit::Block(csv.header.clone()).to_hash256(),
+            csv.header.height + 1,
+            2,
+            BlockHeader::calculate_commit_merkle_root(&csv.commits_for_next_block),
+        );
+        block_header.validator_set[1].1 = 2;
+        let error = csv
+            .apply_commit(&Commit::Block(block_header.clone()))
+            .unwrap_err();
+        assert!(error.to_string().contains(&format!(
+            "voting power of {}: expected 1, got 2",
+            validator_keypair[1].0
+        )));
+        // The order doesn't matter.
+        block_header.validator_set[1].1 = 1;
+        block_header.validator_set.reverse();
+        csv.apply_commit(&Commit::Block(block_header)).unwrap();
+    }
+
+    #[test]
+    /// Test that the BFT time is the median of the precommit timestamps weighted by voting power.
+    fn bft_time_weighted_median() {
+        let validator_keypair = generate_validator_keypair(4);
+        let proof = |timestamps: &[Timestamp]| FinalizationProof {
+            round: 0,
+            signatures: validator_keypair
+                .iter()
+                .zip(timestamps)
+                .map(|((public_key, _), timestamp)| {
+                    PrecommitSignature(
+                        TypedSignature::new(Signature::zero(), public_key.clone()),
+                        *timestamp,
+                    )
+                })
+                .collect(),
+        };
+        let validator_set = |voting_powers: &[VotingPower]| {
+            validator_keypair
+                .iter()
+                .zip(voting_powers)
+                .map(|((public_key, _), voting_power)| (public_key.clone(), *voting_power))
+                .collect::<Vec<_>>()
+        };
+        // A single outlier can't skew the time.
+        assert_eq!(
+            proof(&[10, 11, 12, 1_000_000]).bft_time(&validator_set(&[1, 1, 1, 1])),
+            Some(12)
+        );
+        assert_eq!(
+            proof(&[10, 11, 12, 1_000_000]).bft_time(&validator_set(&[1, 1, 1, 5])),
+            Some(1_000_000)
+        );
+        // Signers out of the validator set are ignored.
+        assert_eq!(
+            proof(&[10, 11, 12, 1_000_000]).bft_time(&validator_set(&[1, 1])),
+            Some(11)
+        );
+        assert_eq!(FinalizationProof::genesis().bft_time(&[]), None);
+    }
+
     #[test]
     /// Test the case where the block commit is invalid because block commit already exists.
     fn phase_mismatch_for_block_commit1() {
@@ -1132,6 +1882,8 @@ mod test {
             timestamp: 4,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply block commit at agenda phase
@@ -1157,6 +1909,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply transaction commit at agenda phase
@@ -1175,6 +1929,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -1198,6 +1954,167 @@ mod test {
         todo!("Implement this test");
     }
 
+    #[test]
+    /// Test the case where the transaction references a blob larger than the limit.
+    fn invalid_transaction_with_too_large_blob() {
+        let (_, mut reserved_state, mut csv) = setup_test(4);
+        let tx = Commit::Transaction(Transaction {
+            author: "doesn't matter".to_owned(),
+            timestamp: 0,
+            head: "Add a dataset".to_string(),
+            body: format!("{BLOB_REFERENCE_PREFIX}{} 100", Hash256::hash("dataset")),
+            diff: Diff::None,
+        });
+        csv.apply_commit(&tx).unwrap_err();
+        reserved_state.max_blob_size = 100;
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state).unwrap();
+        csv.apply_commit(&tx).unwrap();
+    }
+
+    #[test]
+    fn invalid_transaction_with_too_large_body() {
+        let (_, mut reserved_state, csv) = setup_test(4);
+        let tx = Commit::Transaction(Transaction {
+            author: "doesn't matter".to_owned(),
+            timestamp: 0,
+            head: "Add a large note".to_string(),
+            body: "a".repeat(101),
+            diff: Diff::None,
+        });
+        reserved_state.max_commit_body_size = 100;
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state.clone()).unwrap();
+        csv.apply_commit(&tx).unwrap_err();
+        reserved_state.max_commit_body_size = 101;
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state).unwrap();
+        csv.apply_commit(&tx).unwrap();
+    }
+
+    #[test]
+    fn reserved_diff_binding() {
+        let (_, reserved_state, mut csv) = setup_test(4);
+        let height = csv.header.height + 1;
+        let transaction = |timestamp, reserved_state: &ReservedState| {
+            Commit::Transaction(Transaction {
+                author: "doesn't matter".to_owned(),
+                timestamp,
+                head: "Change the limit".to_string(),
+                body: String::new(),
+                diff: Diff::Reserved(Box::new(reserved_state.clone())),
+            })
+        };
+        let mut next = reserved_state.clone();
+        next.max_blob_size = 1 << 20;
+        // Bound to the wrong block or to another state
+        csv.clone()
+            .apply_commit(&transaction(
+                0,
+                &reserved_state.bind(next.clone(), height + 1),
+            ))
+            .unwrap_err();
+        csv.clone()
+            .apply_commit(&transaction(0, &next.bind(next.clone(), height)))
+            .unwrap_err();
+
+        let bound = reserved_state.bind(next.clone(), height);
+        csv.apply_commit(&transaction(0, &bound)).unwrap();
+        // The binding can't be dropped once made.
+        csv.clone()
+            .apply_commit(&transaction(1, &next))
+            .unwrap_err();
+        let mut next = bound.clone();
+        next.max_blob_size = 1 << 10;
+        csv.apply_commit(&transaction(1, &bound.bind(next, height)))
+            .unwrap();
+    }
+
+    #[test]
+    fn agenda_description() {
+        let (validator_keypair, reserved_state, csv) = setup_test(4);
+        let mut agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: calculate_agenda_transactions_hash(csv.phase.clone()),
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        // An agenda without the description hashes as before.
+        #[derive(serde::Serialize)]
+        struct LegacyAgenda {
+            height: BlockHeight,
+            author: MemberName,
+            timestamp: Timestamp,
+            transactions_hash: Hash256,
+        }
+        let legacy_hash = serde_spb::to_hash256(&LegacyAgenda {
+            height: agenda.height,
+            author: agenda.author.clone(),
+            timestamp: agenda.timestamp,
+            transactions_hash: agenda.transactions_hash,
+        })
+        .unwrap();
+        assert_eq!(agenda.to_hash256(), legacy_hash);
+
+        agenda.description = Some(AgendaDescription {
+            title: "Raise the block reward".to_owned(),
+            rationale: "The validators are underpaid.".to_owned(),
+            links: vec!["https://forum.example.com/t/42".to_owned()],
+        });
+        let hash = agenda.to_hash256();
+        assert_ne!(hash, legacy_hash);
+        agenda.description.as_mut().unwrap().rationale.push('!');
+        assert_ne!(agenda.to_hash256(), hash);
+        csv.clone()
+            .apply_commit(&generate_agenda_commit(&agenda))
+            .unwrap();
+
+        agenda.description.as_mut().unwrap().title = "x".repeat(MAX_AGENDA_TITLE_SIZE + 1);
+        csv.clone()
+            .apply_commit(&generate_agenda_commit(&agenda))
+            .unwrap_err();
+        agenda.description.as_mut().unwrap().title = "Raise the block reward".to_owned();
+        agenda.description.as_mut().unwrap().links = vec!["a link with spaces".to_owned()];
+        csv.clone()
+            .apply_commit(&generate_agenda_commit(&agenda))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn invalid_agenda_with_too_many_transactions() {
+        let (_, mut reserved_state, csv) = setup_test(4);
+        reserved_state.max_agenda_transactions = 2;
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state).unwrap();
+        csv.apply_commit(&generate_empty_transaction_commit())
+            .unwrap();
+        csv.apply_commit(&generate_empty_transaction_commit())
+            .unwrap();
+        assert!(matches!(
+            csv.apply_commit(&generate_empty_transaction_commit()),
+            Err(Error::LimitExceeded {
+                limit: Limit::AgendaTransactions,
+                size: 3,
+                max: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_block_with_too_many_extra_agenda_transactions() {
+        let (validator_keypair, mut reserved_state, csv) = setup_test(4);
+        reserved_state.max_block_extra_agenda_transactions = 1;
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state.clone()).unwrap();
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 2))
+            .unwrap();
+        assert!(matches!(
+            csv.apply_commit(&generate_report_commit(&validator_keypair, 2, 1, 3)),
+            Err(Error::LimitExceeded {
+                limit: Limit::BlockExtraAgendaTransactions,
+                ..
+            })
+        ));
+    }
+
     #[test]
     /// Test the case where the agenda commit is invalid because the agenda height is invalid.
     /// The agenda height should be the next height of the last header height.
@@ -1210,6 +2127,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: 0,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda))
             .unwrap_err();
@@ -1232,6 +2151,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda))
             .unwrap_err();
@@ -1251,6 +2172,8 @@ mod test {
             timestamp: 2,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda))
             .unwrap_err();
@@ -1269,6 +2192,8 @@ mod test {
             timestamp: 0,
             transactions_hash: Agenda::calculate_transactions_hash(&[]),
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda))
             .unwrap_err();
@@ -1285,6 +2210,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda commit again
@@ -1303,6 +2230,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -1338,6 +2267,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit with invalid height
@@ -1348,6 +2279,8 @@ mod test {
                 timestamp: 1,
                 transactions_hash: agenda_transactions_hash,
                 height: 0,
+                description: None,
+                deadline: None,
             },
             agenda.to_hash256(),
         ))
@@ -1365,6 +2298,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit with invalid agenda hash
@@ -1387,6 +2322,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit with invalid signature
@@ -1397,12 +2334,63 @@ mod test {
                 timestamp: 0,
                 transactions_hash: Hash256::zero(),
                 height: csv.header.height + 1,
+                description: None,
+                deadline: None,
             },
             agenda.to_hash256(),
         ))
         .unwrap_err();
     }
 
+    #[test]
+    /// Test the case where a threshold member votes with a part of its signing keys.
+    fn agenda_proof_with_threshold_member() {
+        let (validator_keypair, mut reserved_state, csv) = setup_test(4);
+        let signing_keypair = (0..3)
+            .map(|i| generate_keypair(format!("signing-{i}")))
+            .collect::<Vec<_>>();
+        reserved_state.members[0].auth = MemberAuth::Threshold {
+            threshold: 2,
+            keys: signing_keypair.iter().map(|(key, _)| key.clone()).collect(),
+        };
+        let mut csv = CommitSequenceVerifier::new(csv.header, reserved_state.clone()).unwrap();
+        // Apply agenda commit
+        let agenda_transactions_hash = calculate_agenda_transactions_hash(csv.phase.clone());
+        let agenda: Agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: agenda_transactions_hash,
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        // Apply agenda-proof commit below the threshold of the member
+        let voters = [
+            validator_keypair[1..3].to_vec(),
+            signing_keypair[0..1].to_vec(),
+        ]
+        .concat();
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &voters,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap_err();
+        // Apply agenda-proof commit reaching the threshold of the member
+        let voters = [
+            validator_keypair[1..3].to_vec(),
+            signing_keypair[1..3].to_vec(),
+        ]
+        .concat();
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &voters,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+    }
+
     #[test]
     /// Test the case where the agenda proof commit is invalid because agenda proof already exists.
     fn phase_mismatch_for_agenda_proof_commit1() {
@@ -1414,6 +2402,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
         // Apply agenda-proof commit
@@ -1446,6 +2436,8 @@ mod test {
             timestamp: 2,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_proof_commit(
             &validator_keypair,
@@ -1475,6 +2467,8 @@ mod test {
             timestamp: 1,
             transactions_hash: agenda_transactions_hash,
             height: csv.header.height + 1,
+            description: None,
+            deadline: None,
         };
         csv.apply_commit(&generate_agenda_proof_commit(
             &validator_keypair,
@@ -1565,6 +2559,435 @@ mod test {
         todo!("Implement this test")
     }
 
-    // TODO: add test cases where the `Report` extra-agenda transactions are invalid.
-    // These test cases are TODO because the `Report` extra-agenda transaction is not implemented yet.
+    fn generate_report_commit(
+        validator_keypair: &[(PublicKey, PrivateKey)],
+        offender_index: usize,
+        height: BlockHeight,
+        timestamp: Timestamp,
+    ) -> Commit {
+        let precommit = |time: Timestamp| {
+            let header = generate_block_header(
+                validator_keypair,
+                0,
+                FinalizationProof::genesis(),
+                Hash256::zero(),
+                height,
+                time,
+                OneshotMerkleTree::create(vec![]).root(),
+            );
+            SignedPrecommit {
+                signature: TypedSignature::sign(
+                    &FinalizationSignTarget {
+                        block_hash: header.to_hash256(),
+                        round: 0,
+                        timestamp: time,
+                    },
+                    &validator_keypair[offender_index].1,
+                )
+                .unwrap(),
+                header,
+                round: 0,
+                timestamp: time,
+            }
+        };
+        Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Report(TxReport {
+            evidence: Box::new(DoubleSignEvidence {
+                first: precommit(1),
+                second: precommit(2),
+            }),
+            timestamp,
+        }))
+    }
+
+    fn setup_extra_agenda_phase(
+        validator_keypair: &[(PublicKey, PrivateKey)],
+        reserved_state: &ReservedState,
+        csv: &mut CommitSequenceVerifier,
+    ) {
+        let agenda_transactions_hash = calculate_agenda_transactions_hash(csv.phase.clone());
+        let agenda: Agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: agenda_transactions_hash,
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        csv.apply_commit(&generate_agenda_proof_commit(
+            validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    /// Test the case where the leader order of the reserved state is not of the eligible leaders.
+    fn invalid_reserved_state_with_bad_leader_order() {
+        let (_, reserved_state, csv) = setup_test(4);
+        csv.verify_reserved_state(&reserved_state).unwrap();
+        // Any order of the eligible leaders is valid.
+        let mut reordered = reserved_state.clone();
+        reordered.consensus_leader_order.reverse();
+        csv.verify_reserved_state(&reordered).unwrap();
+
+        let mut duplicated = reserved_state.clone();
+        duplicated.consensus_leader_order[1] = duplicated.consensus_leader_order[0].clone();
+        let mut unknown = reserved_state.clone();
+        unknown.consensus_leader_order.push("stranger".to_owned());
+        let mut slashed = reserved_state;
+        slashed.members[0].consensus_voting_power = 0;
+        for rs in [duplicated, unknown, slashed] {
+            let error = csv.verify_reserved_state(&rs).unwrap_err();
+            assert!(error.to_string().contains("leader order"), "{error}");
+        }
+    }
+
+    #[test]
+    /// Test the case where the `Report` extra-agenda transaction slashes the offender.
+    fn report_transaction_slashes_offender() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 2))
+            .unwrap();
+        let validator_set = csv.get_reserved_state().get_validator_set().unwrap();
+        assert!(validator_set.contains(&(validator_keypair[3].0.clone(), 0)));
+        assert!(validator_set.contains(&(validator_keypair[2].0.clone(), 1)));
+    }
+
+    #[test]
+    /// Test the case where the `Report` extra-agenda transaction is invalid because the offender is already slashed.
+    fn invalid_report_transaction_with_duplicate_evidence() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 2))
+            .unwrap();
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 3))
+            .unwrap_err();
+    }
+
+    #[test]
+    /// Test the case where the `Report` extra-agenda transaction replays an evidence already processed,
+    /// even after the offender has regained its voting power.
+    fn invalid_report_transaction_with_replayed_evidence() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 2))
+            .unwrap();
+        assert_eq!(csv.get_reserved_state().processed_evidence.len(), 1);
+        for member in &mut csv.reserved_state.members {
+            if member.public_key == validator_keypair[3].0 {
+                member.consensus_voting_power = 1;
+                member.governance_voting_power = 1;
+            }
+        }
+        let mut swapped = generate_report_commit(&validator_keypair, 3, 1, 3);
+        if let Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Report(tx)) = &mut swapped {
+            core::mem::swap(&mut tx.evidence.first, &mut tx.evidence.second);
+        }
+        for replay in [generate_report_commit(&validator_keypair, 3, 1, 3), swapped] {
+            let error = csv.apply_commit(&replay).unwrap_err();
+            assert!(error.to_string().contains("already processed"), "{error}");
+        }
+    }
+
+    #[test]
+    /// Test the case where the `Report` extra-agenda transaction is invalid because the evidence is expired.
+    fn invalid_report_transaction_with_expired_evidence() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        csv.header.height = EVIDENCE_MAX_AGE + 1;
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 1, 2))
+            .unwrap_err();
+        csv.apply_commit(&generate_report_commit(&validator_keypair, 3, 2, 2))
+            .unwrap();
+    }
+
+    #[test]
+    /// Test the case where the `Report` extra-agenda transaction is invalid because the precommits don't conflict.
+    fn invalid_report_transaction_with_invalid_evidence() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        let mut report = generate_report_commit(&validator_keypair, 3, 1, 2);
+        if let Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Report(tx)) = &mut report {
+            tx.evidence.second = tx.evidence.first.clone();
+        }
+        csv.apply_commit(&report).unwrap_err();
+    }
+
+    fn generate_ban_commit(
+        validator_keypair: &[(PublicKey, PrivateKey)],
+        signers: &[usize],
+        member: &str,
+        until_height: BlockHeight,
+    ) -> Commit {
+        let data = BanTransactionData {
+            member: member.to_string(),
+            until_height,
+            block_height: 1,
+            timestamp: 2,
+            chain_name: "PDAO Chain".to_string(),
+        };
+        Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Ban(TxBan {
+            proof: signers
+                .iter()
+                .map(|&i| TypedSignature::sign(&data, &validator_keypair[i].1).unwrap())
+                .collect(),
+            data,
+        }))
+    }
+
+    #[test]
+    /// Test the case where the `Ban` extra-agenda transaction bans the member until the given height.
+    fn ban_transaction_bans_member() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_ban_commit(
+            &validator_keypair,
+            &[0, 1, 2],
+            "member3",
+            3,
+        ))
+        .unwrap();
+        let state = csv.get_reserved_state();
+        assert_eq!(
+            state.banned_public_keys(),
+            vec![validator_keypair[3].0.clone()]
+        );
+        assert_eq!(state.effective_at(2).banned_members.len(), 1);
+        assert!(state.effective_at(3).banned_members.is_empty());
+    }
+
+    #[test]
+    /// Test the case where the `Ban` extra-agenda transaction is invalid because it's not signed by the majority.
+    fn invalid_ban_transaction_with_insufficient_weight() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        setup_extra_agenda_phase(&validator_keypair, &reserved_state, &mut csv);
+        csv.apply_commit(&generate_ban_commit(
+            &validator_keypair,
+            &[0, 1],
+            "member3",
+            3,
+        ))
+        .unwrap_err();
+        csv.apply_commit(&generate_ban_commit(
+            &validator_keypair,
+            &[0, 1, 2],
+            "member4",
+            3,
+        ))
+        .unwrap_err();
+    }
+
+    #[test]
+    /// Test the case where the agenda is vetoed by a veto holder, which only the nodes
+    /// that know the veto act on; the agenda proof is still valid, as the vetoes are not on-chain.
+    fn vetoed_agenda_proof() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        csv.reserved_state.veto_holders = vec!["member3".to_string()];
+        let agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            height: csv.header.height + 1,
+            description: None,
+            deadline: None,
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        let veto = |index: usize, expires_at: Timestamp| {
+            let veto = AgendaVeto {
+                agenda_hash: agenda.to_hash256(),
+                expires_at,
+            };
+            SignedAgendaVeto {
+                signature: TypedSignature::sign(&veto, &validator_keypair[index].1).unwrap(),
+                veto,
+            }
+        };
+        let agenda_proof =
+            generate_agenda_proof_commit(&validator_keypair, &agenda, agenda.to_hash256());
+
+        // Expired, or not by a veto holder.
+        assert!(csv
+            .reserved_state
+            .vetoers(agenda.to_hash256(), &[veto(3, 0), veto(2, 1)], 0)
+            .is_empty());
+        assert_eq!(
+            csv.reserved_state
+                .vetoers(agenda.to_hash256(), &[veto(3, 1)], 0),
+            vec!["member3".to_string()]
+        );
+        csv.apply_commit(&agenda_proof).unwrap();
+    }
+
+    #[test]
+    /// Test the case where the agenda proof is timestamped before the one of the previous block,
+    /// which still follows it in the canonical order of the heights.
+    fn agenda_proofs_in_canonical_order() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        let agenda_with_proof = |height: BlockHeight, timestamp: Timestamp| {
+            let agenda = Agenda {
+                author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+                timestamp: 1,
+                transactions_hash: Agenda::calculate_transactions_hash(&[]),
+                height,
+                description: None,
+                deadline: None,
+            };
+            let Commit::AgendaProof(agenda_proof) =
+                generate_agenda_proof_commit(&validator_keypair, &agenda, agenda.to_hash256())
+            else {
+                unreachable!()
+            };
+            (
+                generate_agenda_commit(&agenda),
+                Commit::AgendaProof(AgendaProof {
+                    timestamp,
+                    ..agenda_proof
+                }),
+            )
+        };
+        let (agenda, agenda_proof) = agenda_with_proof(1, 5);
+        csv.apply_commit(&agenda).unwrap();
+        csv.apply_commit(&agenda_proof).unwrap();
+        csv.apply_commit(&generate_block_commit(
+            &validator_keypair,
+            0,
+            csv.header.clone(),
+            6,
+            BlockHeader::calculate_commit_merkle_root(&[agenda, agenda_proof]),
+            Hash256::zero(),
+        ))
+        .unwrap();
+
+        let (agenda, agenda_proof) = agenda_with_proof(2, 4);
+        csv.apply_commit(&agenda).unwrap();
+        csv.clone().apply_commit(&agenda_proof).unwrap();
+        csv.reserved_state.version = CANONICAL_AGENDA_ORDER_VERSION.to_owned();
+        csv.apply_commit(&agenda_proof).unwrap();
+    }
+
+    #[test]
+    /// Test the case where the agenda proof is made after the voting deadline of the agenda.
+    fn agenda_proof_after_deadline() {
+        let (validator_keypair, reserved_state, mut csv) = setup_test(4);
+        let agenda = Agenda {
+            author: reserved_state.query_name(&validator_keypair[0].0).unwrap(),
+            timestamp: 1,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            height: csv.header.height + 1,
+            description: None,
+            deadline: Some(VotingDeadline::Timestamp(10)),
+        };
+        // A deadline before the agenda itself is invalid.
+        csv.clone()
+            .apply_commit(&generate_agenda_commit(&Agenda {
+                deadline: Some(VotingDeadline::Timestamp(0)),
+                ..agenda.clone()
+            }))
+            .unwrap_err();
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        let agenda_proof = |timestamp: Timestamp| {
+            let Commit::AgendaProof(agenda_proof) =
+                generate_agenda_proof_commit(&validator_keypair, &agenda, agenda.to_hash256())
+            else {
+                unreachable!()
+            };
+            Commit::AgendaProof(AgendaProof {
+                timestamp,
+                ..agenda_proof
+            })
+        };
+        csv.clone().apply_commit(&agenda_proof(11)).unwrap_err();
+        csv.apply_commit(&agenda_proof(10)).unwrap();
+
+        // The block with the agenda must be finalized by the deadline too.
+        let block_commit = generate_block_commit(
+            &validator_keypair,
+            0,
+            csv.header.clone(),
+            2,
+            BlockHeader::calculate_commit_merkle_root(&csv.commits_for_next_block),
+            Hash256::zero(),
+        );
+        csv.apply_commit(&block_commit).unwrap();
+        let finalization_proof = |timestamp: Timestamp| {
+            generate_unanimous_finalization_proof(&validator_keypair, &csv.header, 0, timestamp)
+        };
+        csv.verify_last_header_finalization(&finalization_proof(10))
+            .unwrap();
+        csv.verify_last_header_finalization(&finalization_proof(11))
+            .unwrap_err();
+        // Or by the next block, whose timestamp is the BFT time of the finalization.
+        let agenda = Agenda {
+            height: 2,
+            deadline: None,
+            ..agenda
+        };
+        csv.apply_commit(&generate_agenda_commit(&agenda)).unwrap();
+        csv.apply_commit(&generate_agenda_proof_commit(
+            &validator_keypair,
+            &agenda,
+            agenda.to_hash256(),
+        ))
+        .unwrap();
+        let [late_block_commit, block_commit] = [11, 10].map(|timestamp| {
+            generate_block_commit(
+                &validator_keypair,
+                0,
+                csv.header.clone(),
+                timestamp,
+                BlockHeader::calculate_commit_merkle_root(&csv.commits_for_next_block),
+                Hash256::zero(),
+            )
+        });
+        assert!(matches!(
+            csv.clone().apply_commit(&late_block_commit),
+            Err(Error::InvalidProof(_))
+        ));
+        csv.apply_commit(&block_commit).unwrap();
+    }
+
+    #[test]
+    /// Test the case where two blocks on top of the same block are finalized.
+    fn fork_evidence() {
+        let (validator_keypair, _, csv) = setup_test(4);
+        let block = |time: Timestamp| {
+            let header = generate_block_header(
+                &validator_keypair,
+                0,
+                FinalizationProof::genesis(),
+                csv.header.to_hash256(),
+                1,
+                time,
+                OneshotMerkleTree::create(vec![]).root(),
+            );
+            let proof = generate_unanimous_finalization_proof(&validator_keypair, &header, 0, time);
+            (header, proof)
+        };
+        let (first, first_proof) = block(1);
+        let (second, second_proof) = block(2);
+        let evidence = ForkEvidence {
+            first: first.clone(),
+            first_proof: first_proof.clone(),
+            second,
+            second_proof: second_proof.clone(),
+        };
+        verify_fork_evidence(&evidence).unwrap();
+
+        // The same block.
+        let mut invalid = evidence.clone();
+        invalid.second = first;
+        invalid.second_proof = first_proof;
+        verify_fork_evidence(&invalid).unwrap_err();
+        // A block whose proof lacks the quorum.
+        let mut invalid = evidence.clone();
+        invalid.second_proof.signatures.truncate(2);
+        verify_fork_evidence(&invalid).unwrap_err();
+        // A block on another parent.
+        let mut invalid = evidence;
+        invalid.second.previous_hash = Hash256::zero();
+        verify_fork_evidence(&invalid).unwrap_err();
+    }
 }
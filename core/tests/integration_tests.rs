@@ -25,7 +25,9 @@ fn basic1() {
         height: 1,
         author: rs.query_name(&keys[0].0).unwrap(),
         timestamp: 0,
-        transactions_hash: Agenda::calculate_transactions_hash(&[tx.clone()]),
+        transactions_hash: Agenda::calculate_transactions_hash(std::slice::from_ref(&tx)),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
     csv.apply_commit(&Commit::AgendaProof(AgendaProof {
@@ -56,14 +58,7 @@ fn basic1() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
@@ -108,7 +103,9 @@ fn basic2() {
         height: 1,
         author: reserved_state.query_name(&keys[1].0).unwrap(),
         timestamp: 0,
-        transactions_hash: Agenda::calculate_transactions_hash(&[tx.clone()]),
+        transactions_hash: Agenda::calculate_transactions_hash(std::slice::from_ref(&tx)),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
     csv.apply_commit(&Commit::AgendaProof(AgendaProof {
@@ -139,14 +136,7 @@ fn basic2() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
@@ -190,7 +180,9 @@ fn basic3() {
         height,
         author: rs.query_name(&keys[0].0).unwrap(),
         timestamp,
-        transactions_hash: Agenda::calculate_transactions_hash(&[tx.clone()]),
+        transactions_hash: Agenda::calculate_transactions_hash(std::slice::from_ref(&tx)),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
 
@@ -205,18 +197,18 @@ fn basic3() {
     }))
     .unwrap();
 
-    let data = DelegationTransactionData {
-        delegator: rs.members[0].name.to_owned(),
-        delegatee: rs.members[2].name.to_owned(),
-        governance: true,
-        block_height: height,
-        timestamp,
-        chain_name: "PDAO-mainnet".to_owned(),
-    };
-    let tx_delegate = ExtraAgendaTransaction::Delegate(TxDelegate {
-        proof: TypedSignature::sign(&data, &keys[0].1).unwrap(),
-        data,
-    });
+    let tx_delegate = ExtraAgendaTransaction::Delegate(
+        TxDelegate::builder()
+            .delegator(rs.members[0].name.to_owned())
+            .delegatee(rs.members[2].name.to_owned())
+            .governance(true)
+            .block_height(height)
+            .timestamp(timestamp)
+            .chain_name(rs.genesis_info.chain_name.clone())
+            .nonce(1)
+            .build(&keys[0].1)
+            .unwrap(),
+    );
     csv.apply_commit(&Commit::ExtraAgendaTransaction(tx_delegate))
         .unwrap();
 
@@ -239,14 +231,7 @@ fn basic3() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 1, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
@@ -275,6 +260,8 @@ fn basic3() {
         None
     );
 
+    // The next block has the validator set with the delegation applied.
+    let validator_set = csv.get_reserved_state().get_validator_set().unwrap();
     let (height, timestamp) = (2, 1);
 
     assert_eq!(
@@ -289,6 +276,8 @@ fn basic3() {
         author: rs.query_name(&keys[1].0).unwrap(),
         timestamp,
         transactions_hash: Agenda::calculate_transactions_hash(&[]),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
 
@@ -320,7 +309,8 @@ fn basic3() {
         delegator: rs.members[0].name.to_owned(),
         block_height: height,
         timestamp,
-        chain_name: "PDAO-mainnet".to_owned(),
+        chain_name: rs.genesis_info.chain_name.clone(),
+        nonce: 2,
     };
     let tx_delegate = ExtraAgendaTransaction::Undelegate(TxUndelegate {
         proof: TypedSignature::sign(&data, &keys[0].1).unwrap(),
@@ -339,7 +329,7 @@ fn basic3() {
             &csv.get_total_commits()[5..],
         ),
         repository_merkle_root: Hash256::zero(),
-        validator_set: rs.get_validator_set().unwrap(),
+        validator_set,
         version: genesis_info.header.version,
     };
     csv.apply_commit(&Commit::Block(block_header.clone()))
@@ -347,14 +337,7 @@ fn basic3() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
@@ -0,0 +1,87 @@
+use simperby_core::*;
+
+/// The fixtures of every historical schema, of the same chain.
+const FIXTURES: [&str; 12] = [
+    include_str!("fixtures/reserved_state_v0.json"),
+    include_str!("fixtures/reserved_state_v1.json"),
+    include_str!("fixtures/reserved_state_v2.json"),
+    include_str!("fixtures/reserved_state_v3.json"),
+    include_str!("fixtures/reserved_state_v4.json"),
+    include_str!("fixtures/reserved_state_v5.json"),
+    include_str!("fixtures/reserved_state_v6.json"),
+    include_str!("fixtures/reserved_state_v7.json"),
+    include_str!("fixtures/reserved_state_v8.json"),
+    include_str!("fixtures/reserved_state_v9.json"),
+    include_str!("fixtures/reserved_state_v10.json"),
+    include_str!("fixtures/reserved_state_v11.json"),
+];
+
+#[test]
+fn load_every_schema() {
+    assert_eq!(FIXTURES.len() as u32, RESERVED_STATE_SCHEMA_VERSION + 1);
+    let current: ReservedState = serde_spb::from_str(FIXTURES[11]).unwrap();
+    for fixture in FIXTURES {
+        let state: ReservedState = serde_spb::from_str(fixture).unwrap();
+        verify::verify_finalization_proof(
+            &state.genesis_info.header,
+            &state.genesis_info.genesis_proof,
+        )
+        .unwrap();
+        assert_eq!(state.members.len(), 2);
+        assert_eq!(state.genesis_info, current.genesis_info);
+        assert_eq!(state.consensus_leader_order, current.consensus_leader_order);
+    }
+}
+
+#[test]
+fn upgrade_v0() {
+    let state: ReservedState = serde_spb::from_str(FIXTURES[0]).unwrap();
+    // The fields absent in the schema are filled with the defaults.
+    assert_eq!(state.max_blob_size, 0);
+    assert_eq!(state.max_commit_body_size, 0);
+    assert_eq!(state.max_agenda_transactions, 0);
+    assert_eq!(state.max_block_extra_agenda_transactions, 0);
+    assert!(state.scheduled_changes.is_empty());
+    assert_eq!(state.binding, None);
+    assert!(state.banned_members.is_empty());
+    assert!(state.veto_holders.is_empty());
+    assert_eq!(state.transaction_spec, None);
+    assert!(state.processed_evidence.is_empty());
+    for member in &state.members {
+        assert_eq!(member.auth, MemberAuth::Single);
+        assert_eq!(member.bls_public_key, None);
+        assert_eq!(member.git_signing_key, None);
+        assert_eq!(member.nonce, 0);
+        assert!(member.metadata.is_empty());
+    }
+    // Always serialized in the current schema.
+    let serialized = serde_spb::to_string(&state).unwrap();
+    assert!(serialized.contains(&format!(
+        "\"schema_version\": {RESERVED_STATE_SCHEMA_VERSION}"
+    )));
+    assert_eq!(
+        serde_spb::from_str::<ReservedState>(&serialized).unwrap(),
+        state
+    );
+}
+
+#[test]
+fn roundtrip_current() {
+    let state: ReservedState = serde_spb::from_str(FIXTURES[11]).unwrap();
+    assert_eq!(
+        serde_spb::to_string(&state).unwrap().trim(),
+        FIXTURES[11].trim()
+    );
+    let bytes = serde_spb::to_vec(&state).unwrap();
+    assert_eq!(
+        serde_spb::from_slice::<ReservedState>(&bytes).unwrap(),
+        state
+    );
+}
+
+#[test]
+fn reject_newer_schema() {
+    let mut value: serde_json::Value = serde_json::from_str(FIXTURES[11]).unwrap();
+    value["schema_version"] = (RESERVED_STATE_SCHEMA_VERSION + 1).into();
+    assert!(serde_spb::from_str::<ReservedState>(&value.to_string()).is_err());
+}
@@ -0,0 +1,93 @@
+//! Checks the golden files of the test vectors (see [`simperby_core::test_vectors`]).
+//!
+//! To rewrite them after a bump of the protocol version, run this with `UPDATE_TEST_VECTORS=1`.
+use simperby_core::test_vectors::{self, TestVector};
+use simperby_core::*;
+use std::path::Path;
+
+const GOLDEN_FILES: [(&str, &str); 6] = [
+    ("block_header", include_str!("vectors/block_header.json")),
+    ("agenda", include_str!("vectors/agenda.json")),
+    (
+        "agenda_with_description",
+        include_str!("vectors/agenda_with_description.json"),
+    ),
+    (
+        "agenda_with_deadline",
+        include_str!("vectors/agenda_with_deadline.json"),
+    ),
+    ("delegation", include_str!("vectors/delegation.json")),
+    (
+        "finalization_sign_target",
+        include_str!("vectors/finalization_sign_target.json"),
+    ),
+];
+
+#[test]
+fn golden_files() {
+    if std::env::var_os("UPDATE_TEST_VECTORS").is_some() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+        let changed = test_vectors::regenerate(&directory).unwrap();
+        println!("regenerated {changed:?}; rerun to check them");
+        return;
+    }
+    let vectors = test_vectors::generate();
+    assert_eq!(vectors.len(), GOLDEN_FILES.len());
+    for (vector, (name, golden)) in vectors.iter().zip(GOLDEN_FILES) {
+        assert_eq!(vector.name, name);
+        assert_eq!(
+            vector.to_golden_file(),
+            golden,
+            "the vector {name} doesn't match its golden file"
+        );
+    }
+}
+
+#[test]
+fn consistent_vectors() {
+    let signer = test_vectors::signer().public_key();
+    for (name, golden) in GOLDEN_FILES {
+        let vector: TestVector = serde_spb::from_str(golden).unwrap();
+        assert_eq!(vector.protocol_version, SIMPERBY_CORE_PROTOCOL_VERSION);
+        let preimage = hex::decode(&vector.preimage).unwrap();
+        assert_eq!(Hash256::hash(&preimage), vector.hash, "{name}");
+        if let Some(signature) = &vector.signature {
+            signature.verify(vector.hash, &signer).unwrap();
+        }
+    }
+}
+
+#[test]
+fn regenerate_requires_version_bump() {
+    let directory = std::env::temp_dir().join(format!("simperby-vectors-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    assert_eq!(
+        test_vectors::regenerate(&directory).unwrap().len(),
+        GOLDEN_FILES.len()
+    );
+    assert!(test_vectors::regenerate(&directory).unwrap().is_empty());
+
+    // A payload may gain a field as long as the hashed bytes don't change.
+    let mut vector: TestVector = serde_spb::from_str(GOLDEN_FILES[1].1).unwrap();
+    vector.payload.as_object_mut().unwrap().remove("deadline");
+    std::fs::write(directory.join(vector.file_name()), vector.to_golden_file()).unwrap();
+    assert_eq!(
+        test_vectors::regenerate(&directory).unwrap(),
+        vec!["agenda".to_owned()]
+    );
+
+    // A changed vector of the same protocol version is refused...
+    let mut vector: TestVector = serde_spb::from_str(GOLDEN_FILES[0].1).unwrap();
+    vector.hash = Hash256::zero();
+    let path = directory.join(vector.file_name());
+    std::fs::write(&path, vector.to_golden_file()).unwrap();
+    assert!(test_vectors::regenerate(&directory).is_err());
+    // ...but not one of an older version.
+    vector.protocol_version = "0.0.0".to_owned();
+    std::fs::write(&path, vector.to_golden_file()).unwrap();
+    assert_eq!(
+        test_vectors::regenerate(&directory).unwrap(),
+        vec!["block_header".to_owned()]
+    );
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,170 @@
+#ifndef SIMPERBY_H
+#define SIMPERBY_H
+
+/* Generated by cbindgen from simperby-ffi; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The payload types for [`simperby_hash_payload`], [`simperby_sign_payload`]
+ * and [`simperby_verify_payload_signature`], given in the JSON of the type.
+ */
+#define SIMPERBY_PAYLOAD_AGENDA 0
+
+#define SIMPERBY_PAYLOAD_BLOCK_HEADER 1
+
+#define SIMPERBY_PAYLOAD_FINALIZATION_SIGN_TARGET 2
+
+#define SIMPERBY_PAYLOAD_TRANSACTION 3
+
+#define SIMPERBY_PAYLOAD_DELEGATION_TRANSACTION_DATA 4
+
+#define SIMPERBY_PAYLOAD_UNDELEGATION_TRANSACTION_DATA 5
+
+#define SIMPERBY_PAYLOAD_JOIN_REQUEST_DATA 6
+
+#define SIMPERBY_PAYLOAD_COMMIT 7
+
+/**
+ * The result of a call.
+ */
+typedef enum SimperbyStatus {
+  SIMPERBY_STATUS_OK = 0,
+  /**
+   * A null pointer, a string that isn't UTF-8 or an unknown payload type.
+   */
+  SIMPERBY_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * A value that can't be decoded into its type.
+   */
+  SIMPERBY_STATUS_INVALID_FORMAT = 2,
+  /**
+   * A signature, a proof or a reserved state that fails the verification.
+   */
+  SIMPERBY_STATUS_VERIFICATION_FAILED = 3,
+  /**
+   * A bug in the library.
+   */
+  SIMPERBY_STATUS_INTERNAL = 4,
+} SimperbyStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last failure on this thread, or null if the last call succeeded.
+ *
+ * The message must be freed with [`simperby_string_free`].
+ */
+char *simperby_last_error(void);
+
+/**
+ * Frees a string returned by this library. It does nothing with null.
+ *
+ * # Safety
+ *
+ * `s` must be null or a string returned by this library, not freed yet.
+ */
+void simperby_string_free(char *s);
+
+/**
+ * Generates a keypair from the seed, as `simperby_core::generate_keypair` does.
+ *
+ * # Safety
+ *
+ * `seed` must point to `seed_len` readable bytes, and the out-parameters must be writable.
+ */
+SimperbyStatus simperby_generate_keypair(const uint8_t *seed,
+                                         size_t seed_len,
+                                         char **public_key_out,
+                                         char **private_key_out);
+
+/**
+ * Generates a keypair from the entropy of the OS.
+ *
+ * # Safety
+ *
+ * The out-parameters must be writable.
+ */
+SimperbyStatus simperby_generate_keypair_random(char **public_key_out, char **private_key_out);
+
+/**
+ * Derives the public key of the private key.
+ *
+ * # Safety
+ *
+ * `private_key` must be a NUL-terminated string, and the out-parameter must be writable.
+ */
+SimperbyStatus simperby_public_key(const char *private_key, char **public_key_out);
+
+/**
+ * Computes the hash of the payload, which is what is signed for it.
+ *
+ * # Safety
+ *
+ * `payload_json` must be a NUL-terminated string, and the out-parameter must be writable.
+ */
+SimperbyStatus simperby_hash_payload(uint32_t payload, const char *payload_json, char **hash_out);
+
+/**
+ * Signs the payload, producing the signature of a `TypedSignature` of its type.
+ *
+ * # Safety
+ *
+ * The strings must be NUL-terminated, and the out-parameter must be writable.
+ */
+SimperbyStatus simperby_sign_payload(uint32_t payload,
+                                     const char *payload_json,
+                                     const char *private_key,
+                                     char **signature_out);
+
+/**
+ * Verifies the signature of the payload by the public key.
+ *
+ * # Safety
+ *
+ * The strings must be NUL-terminated.
+ */
+SimperbyStatus simperby_verify_payload_signature(uint32_t payload,
+                                                 const char *payload_json,
+                                                 const char *signature,
+                                                 const char *public_key);
+
+/**
+ * Verifies the finalization proof of the block header against its validator set.
+ *
+ * # Safety
+ *
+ * The strings must be NUL-terminated.
+ */
+SimperbyStatus simperby_verify_finalization_proof(const char *header_json, const char *proof_json);
+
+/**
+ * Verifies that the block header can follow the previous one,
+ * including the finalization proof of the previous one that it carries.
+ *
+ * # Safety
+ *
+ * The strings must be NUL-terminated.
+ */
+SimperbyStatus simperby_verify_header_to_header(const char *prev_header_json,
+                                                const char *header_json);
+
+/**
+ * Parses and checks the reserved state, writing a [`ReservedStateSummary`] in JSON.
+ *
+ * # Safety
+ *
+ * `reserved_state_json` must be a NUL-terminated string, and the out-parameter must be writable.
+ */
+SimperbyStatus simperby_parse_reserved_state(const char *reserved_state_json, char **summary_out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SIMPERBY_H */
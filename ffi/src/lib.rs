@@ -0,0 +1,531 @@
+//! The C ABI of `simperby-core`, for the validator tooling not written in Rust
+//! to reuse the exact consensus rules rather than reimplementing them.
+//!
+//! Every value crosses the boundary as a NUL-terminated UTF-8 string:
+//! the keys, the signatures and the hashes in hex, and the other types
+//! in the JSON of their serde encodings, as they are stored in the repository.
+//!
+//! Every function returns a [`SimperbyStatus`]; on a failure, [`simperby_last_error`]
+//! tells why. A string given through an out-parameter is owned by the caller,
+//! who must free it with [`simperby_string_free`].
+//!
+//! The header `include/simperby.h` is generated by [cbindgen](https://github.com/mozilla/cbindgen);
+//! regenerate it after changing the API with
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --crate simperby-ffi --output include/simperby.h
+//! ```
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simperby_core::*;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimperbyStatus {
+    Ok = 0,
+    /// A null pointer, a string that isn't UTF-8 or an unknown payload type.
+    InvalidArgument = 1,
+    /// A value that can't be decoded into its type.
+    InvalidFormat = 2,
+    /// A signature, a proof or a reserved state that fails the verification.
+    VerificationFailed = 3,
+    /// A bug in the library.
+    Internal = 4,
+}
+
+/// The payload types for [`simperby_hash_payload`], [`simperby_sign_payload`]
+/// and [`simperby_verify_payload_signature`], given in the JSON of the type.
+pub const SIMPERBY_PAYLOAD_AGENDA: u32 = 0;
+pub const SIMPERBY_PAYLOAD_BLOCK_HEADER: u32 = 1;
+pub const SIMPERBY_PAYLOAD_FINALIZATION_SIGN_TARGET: u32 = 2;
+pub const SIMPERBY_PAYLOAD_TRANSACTION: u32 = 3;
+pub const SIMPERBY_PAYLOAD_DELEGATION_TRANSACTION_DATA: u32 = 4;
+pub const SIMPERBY_PAYLOAD_UNDELEGATION_TRANSACTION_DATA: u32 = 5;
+pub const SIMPERBY_PAYLOAD_JOIN_REQUEST_DATA: u32 = 6;
+pub const SIMPERBY_PAYLOAD_COMMIT: u32 = 7;
+
+/// What [`simperby_parse_reserved_state`] reports of a reserved state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservedStateSummary {
+    pub chain_name: String,
+    pub version: String,
+    pub genesis_height: BlockHeight,
+    pub members: Vec<MemberName>,
+    pub validator_set: Vec<(PublicKey, VotingPower)>,
+    pub governance_set: Vec<(PublicKey, VotingPower)>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+type Failure = (SimperbyStatus, String);
+
+/// Runs the body of an exported function, recording its error for [`simperby_last_error`].
+fn run(f: impl FnOnce() -> Result<(), Failure>) -> SimperbyStatus {
+    let (status, error) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (SimperbyStatus::Ok, None),
+        Ok(Err((status, message))) => (status, Some(message)),
+        Err(_) => (SimperbyStatus::Internal, Some("panicked".to_owned())),
+    };
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = error);
+    status
+}
+
+fn invalid_argument(message: impl ToString) -> Failure {
+    (SimperbyStatus::InvalidArgument, message.to_string())
+}
+
+fn invalid_format(message: impl ToString) -> Failure {
+    (SimperbyStatus::InvalidFormat, message.to_string())
+}
+
+fn verification_failed(message: impl ToString) -> Failure {
+    (SimperbyStatus::VerificationFailed, message.to_string())
+}
+
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if s.is_null() {
+        return Err(invalid_argument(format!("`{name}` is null")));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| invalid_argument(format!("`{name}` is not UTF-8")))
+}
+
+unsafe fn read_json<T: DeserializeOwned>(s: *const c_char, name: &str) -> Result<T, Failure> {
+    serde_json::from_str(read_str(s, name)?)
+        .map_err(|e| invalid_format(format!("invalid `{name}`: {e}")))
+}
+
+/// Reads a key, a signature or a hash written in hex, as in their serde encodings.
+unsafe fn read_hex<T: DeserializeOwned>(s: *const c_char, name: &str) -> Result<T, Failure> {
+    serde_json::from_value(serde_json::Value::String(read_str(s, name)?.to_owned()))
+        .map_err(|e| invalid_format(format!("invalid `{name}`: {e}")))
+}
+
+unsafe fn write_str(out: *mut *mut c_char, s: String, name: &str) -> Result<(), Failure> {
+    if out.is_null() {
+        return Err(invalid_argument(format!("`{name}` is null")));
+    }
+    *out = CString::new(s)
+        .map_err(|_| (SimperbyStatus::Internal, "NUL in the output".to_owned()))?
+        .into_raw();
+    Ok(())
+}
+
+/// Writes the value in JSON; a key, a signature or a hash is written as a hex string
+/// without the quotes.
+unsafe fn write_json<T: Serialize>(
+    out: *mut *mut c_char,
+    value: &T,
+    name: &str,
+) -> Result<(), Failure> {
+    let s =
+        match serde_json::to_value(value).map_err(|e| (SimperbyStatus::Internal, e.to_string()))? {
+            serde_json::Value::String(s) => s,
+            value => value.to_string(),
+        };
+    write_str(out, s, name)
+}
+
+fn payload_hash(payload: u32, json: &str) -> Result<Hash256, Failure> {
+    fn hash<T: DeserializeOwned + ToHash256>(json: &str) -> Result<Hash256, Failure> {
+        serde_json::from_str::<T>(json)
+            .map(|x| x.to_hash256())
+            .map_err(|e| invalid_format(format!("invalid `payload`: {e}")))
+    }
+    match payload {
+        SIMPERBY_PAYLOAD_AGENDA => hash::<Agenda>(json),
+        SIMPERBY_PAYLOAD_BLOCK_HEADER => hash::<BlockHeader>(json),
+        SIMPERBY_PAYLOAD_FINALIZATION_SIGN_TARGET => hash::<FinalizationSignTarget>(json),
+        SIMPERBY_PAYLOAD_TRANSACTION => hash::<Transaction>(json),
+        SIMPERBY_PAYLOAD_DELEGATION_TRANSACTION_DATA => hash::<DelegationTransactionData>(json),
+        SIMPERBY_PAYLOAD_UNDELEGATION_TRANSACTION_DATA => hash::<UndelegationTransactionData>(json),
+        SIMPERBY_PAYLOAD_JOIN_REQUEST_DATA => hash::<JoinRequestData>(json),
+        SIMPERBY_PAYLOAD_COMMIT => hash::<Commit>(json),
+        _ => Err(invalid_argument(format!("unknown payload type {payload}"))),
+    }
+}
+
+/// Returns the message of the last failure on this thread, or null if the last call succeeded.
+///
+/// The message must be freed with [`simperby_string_free`].
+#[no_mangle]
+pub extern "C" fn simperby_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .and_then(|message| CString::new(message.as_str()).ok())
+            .map_or(std::ptr::null_mut(), CString::into_raw)
+    })
+}
+
+/// Frees a string returned by this library. It does nothing with null.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Generates a keypair from the seed, as `simperby_core::generate_keypair` does.
+///
+/// # Safety
+///
+/// `seed` must point to `seed_len` readable bytes, and the out-parameters must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_generate_keypair(
+    seed: *const u8,
+    seed_len: usize,
+    public_key_out: *mut *mut c_char,
+    private_key_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        if seed.is_null() {
+            return Err(invalid_argument("`seed` is null"));
+        }
+        let (public_key, private_key) =
+            generate_keypair(std::slice::from_raw_parts(seed, seed_len));
+        write_json(public_key_out, &public_key, "public_key_out")?;
+        write_json(private_key_out, &private_key, "private_key_out")
+    })
+}
+
+/// Generates a keypair from the entropy of the OS.
+///
+/// # Safety
+///
+/// The out-parameters must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_generate_keypair_random(
+    public_key_out: *mut *mut c_char,
+    private_key_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let (public_key, private_key) = generate_keypair_random();
+        write_json(public_key_out, &public_key, "public_key_out")?;
+        write_json(private_key_out, &private_key, "private_key_out")
+    })
+}
+
+/// Derives the public key of the private key.
+///
+/// # Safety
+///
+/// `private_key` must be a NUL-terminated string, and the out-parameter must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_public_key(
+    private_key: *const c_char,
+    public_key_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let private_key: PrivateKey = read_hex(private_key, "private_key")?;
+        write_json(public_key_out, &private_key.public_key(), "public_key_out")
+    })
+}
+
+/// Computes the hash of the payload, which is what is signed for it.
+///
+/// # Safety
+///
+/// `payload_json` must be a NUL-terminated string, and the out-parameter must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_hash_payload(
+    payload: u32,
+    payload_json: *const c_char,
+    hash_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let hash = payload_hash(payload, read_str(payload_json, "payload_json")?)?;
+        write_json(hash_out, &hash, "hash_out")
+    })
+}
+
+/// Signs the payload, producing the signature of a `TypedSignature` of its type.
+///
+/// # Safety
+///
+/// The strings must be NUL-terminated, and the out-parameter must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_sign_payload(
+    payload: u32,
+    payload_json: *const c_char,
+    private_key: *const c_char,
+    signature_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let hash = payload_hash(payload, read_str(payload_json, "payload_json")?)?;
+        let private_key: PrivateKey = read_hex(private_key, "private_key")?;
+        let signature = Signature::sign(hash, &private_key).map_err(invalid_argument)?;
+        write_json(signature_out, &signature, "signature_out")
+    })
+}
+
+/// Verifies the signature of the payload by the public key.
+///
+/// # Safety
+///
+/// The strings must be NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_verify_payload_signature(
+    payload: u32,
+    payload_json: *const c_char,
+    signature: *const c_char,
+    public_key: *const c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let hash = payload_hash(payload, read_str(payload_json, "payload_json")?)?;
+        let signature: Signature = read_hex(signature, "signature")?;
+        let public_key: PublicKey = read_hex(public_key, "public_key")?;
+        signature
+            .verify(hash, &public_key)
+            .map_err(verification_failed)
+    })
+}
+
+/// Verifies the finalization proof of the block header against its validator set.
+///
+/// # Safety
+///
+/// The strings must be NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_verify_finalization_proof(
+    header_json: *const c_char,
+    proof_json: *const c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let header: BlockHeader = read_json(header_json, "header_json")?;
+        let proof: FinalizationProof = read_json(proof_json, "proof_json")?;
+        verify::verify_finalization_proof(&header, &proof).map_err(verification_failed)
+    })
+}
+
+/// Verifies that the block header can follow the previous one,
+/// including the finalization proof of the previous one that it carries.
+///
+/// # Safety
+///
+/// The strings must be NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_verify_header_to_header(
+    prev_header_json: *const c_char,
+    header_json: *const c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let prev_header: BlockHeader = read_json(prev_header_json, "prev_header_json")?;
+        let header: BlockHeader = read_json(header_json, "header_json")?;
+        verify::verify_header_to_header(&prev_header, &header).map_err(verification_failed)
+    })
+}
+
+/// Parses and checks the reserved state, writing a [`ReservedStateSummary`] in JSON.
+///
+/// # Safety
+///
+/// `reserved_state_json` must be a NUL-terminated string, and the out-parameter must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn simperby_parse_reserved_state(
+    reserved_state_json: *const c_char,
+    summary_out: *mut *mut c_char,
+) -> SimperbyStatus {
+    run(|| {
+        let reserved_state: ReservedState = read_json(reserved_state_json, "reserved_state_json")?;
+        reserved_state
+            .check_member_consistency()
+            .map_err(verification_failed)?;
+        let summary = ReservedStateSummary {
+            chain_name: reserved_state.genesis_info.chain_name.clone(),
+            version: reserved_state.version.clone(),
+            genesis_height: reserved_state.genesis_info.header.height,
+            members: reserved_state
+                .members
+                .iter()
+                .map(|member| member.name.clone())
+                .collect(),
+            validator_set: reserved_state
+                .get_validator_set()
+                .map_err(verification_failed)?,
+            governance_set: reserved_state
+                .get_governance_set()
+                .map_err(verification_failed)?,
+        };
+        write_json(summary_out, &summary, "summary_out")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_core::test_utils::generate_standard_genesis;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn take(s: *mut c_char) -> String {
+        let result = CStr::from_ptr(s).to_str().unwrap().to_owned();
+        simperby_string_free(s);
+        result
+    }
+
+    unsafe fn last_error() -> String {
+        take(simperby_last_error())
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        unsafe {
+            let (mut public_key, mut private_key) = (std::ptr::null_mut(), std::ptr::null_mut());
+            let seed = b"seed";
+            assert_eq!(
+                simperby_generate_keypair(
+                    seed.as_ptr(),
+                    seed.len(),
+                    &mut public_key,
+                    &mut private_key
+                ),
+                SimperbyStatus::Ok
+            );
+            assert!(simperby_last_error().is_null());
+            let (public_key, private_key) = (take(public_key), take(private_key));
+            let expected = generate_keypair("seed");
+            assert_eq!(public_key, expected.0.to_string());
+            assert_eq!(serde_json::to_value(&expected.1).unwrap(), private_key);
+
+            let mut derived = std::ptr::null_mut();
+            assert_eq!(
+                simperby_public_key(c(&private_key).as_ptr(), &mut derived),
+                SimperbyStatus::Ok
+            );
+            assert_eq!(take(derived), public_key);
+
+            let (reserved_state, _) = generate_standard_genesis(1);
+            let header = serde_json::to_string(&reserved_state.genesis_info.header).unwrap();
+            let mut signature = std::ptr::null_mut();
+            assert_eq!(
+                simperby_sign_payload(
+                    SIMPERBY_PAYLOAD_BLOCK_HEADER,
+                    c(&header).as_ptr(),
+                    c(&private_key).as_ptr(),
+                    &mut signature
+                ),
+                SimperbyStatus::Ok
+            );
+            let signature = take(signature);
+            TypedSignature::<BlockHeader>::new(
+                serde_json::from_value(serde_json::Value::String(signature.clone())).unwrap(),
+                expected.0.clone(),
+            )
+            .verify(&reserved_state.genesis_info.header)
+            .unwrap();
+            assert_eq!(
+                simperby_verify_payload_signature(
+                    SIMPERBY_PAYLOAD_BLOCK_HEADER,
+                    c(&header).as_ptr(),
+                    c(&signature).as_ptr(),
+                    c(&public_key).as_ptr()
+                ),
+                SimperbyStatus::Ok
+            );
+            // A block commit is signed as its header.
+            let commit =
+                serde_json::to_string(&Commit::Block(reserved_state.genesis_info.header)).unwrap();
+            assert_eq!(
+                simperby_verify_payload_signature(
+                    SIMPERBY_PAYLOAD_COMMIT,
+                    c(&commit).as_ptr(),
+                    c(&signature).as_ptr(),
+                    c(&public_key).as_ptr()
+                ),
+                SimperbyStatus::Ok
+            );
+            assert_eq!(
+                simperby_verify_payload_signature(
+                    SIMPERBY_PAYLOAD_BLOCK_HEADER,
+                    c(&header).as_ptr(),
+                    c(&signature).as_ptr(),
+                    c(&generate_keypair("other").0.to_string()).as_ptr()
+                ),
+                SimperbyStatus::VerificationFailed
+            );
+            assert!(!last_error().is_empty());
+
+            let mut out = std::ptr::null_mut();
+            assert_eq!(
+                simperby_sign_payload(100, c(&header).as_ptr(), c(&private_key).as_ptr(), &mut out),
+                SimperbyStatus::InvalidArgument
+            );
+            assert_eq!(
+                simperby_sign_payload(
+                    SIMPERBY_PAYLOAD_AGENDA,
+                    c(&header).as_ptr(),
+                    c(&private_key).as_ptr(),
+                    &mut out
+                ),
+                SimperbyStatus::InvalidFormat
+            );
+            assert_eq!(
+                simperby_public_key(std::ptr::null(), &mut out),
+                SimperbyStatus::InvalidArgument
+            );
+            assert_eq!(last_error(), "`private_key` is null");
+        }
+    }
+
+    #[test]
+    fn verify_genesis() {
+        unsafe {
+            let (reserved_state, _) = generate_standard_genesis(4);
+            let header = serde_json::to_string(&reserved_state.genesis_info.header).unwrap();
+            let proof = serde_json::to_string(&reserved_state.genesis_info.genesis_proof).unwrap();
+            assert_eq!(
+                simperby_verify_finalization_proof(c(&header).as_ptr(), c(&proof).as_ptr()),
+                SimperbyStatus::Ok
+            );
+            let mut other_header = reserved_state.genesis_info.header.clone();
+            other_header.timestamp += 1;
+            let other_header = serde_json::to_string(&other_header).unwrap();
+            assert_eq!(
+                simperby_verify_finalization_proof(c(&other_header).as_ptr(), c(&proof).as_ptr()),
+                SimperbyStatus::VerificationFailed
+            );
+
+            let mut summary = std::ptr::null_mut();
+            assert_eq!(
+                simperby_parse_reserved_state(
+                    c(&serde_json::to_string(&reserved_state).unwrap()).as_ptr(),
+                    &mut summary
+                ),
+                SimperbyStatus::Ok
+            );
+            let summary: ReservedStateSummary = serde_json::from_str(&take(summary)).unwrap();
+            assert_eq!(summary.chain_name, reserved_state.genesis_info.chain_name);
+            assert_eq!(summary.members.len(), 4);
+            assert_eq!(
+                summary.validator_set,
+                reserved_state.get_validator_set().unwrap()
+            );
+
+            let mut broken = reserved_state;
+            broken.consensus_leader_order.push("nobody".to_owned());
+            assert_eq!(
+                simperby_parse_reserved_state(
+                    c(&serde_json::to_string(&broken).unwrap()).as_ptr(),
+                    &mut std::ptr::null_mut()
+                ),
+                SimperbyStatus::VerificationFailed
+            );
+            assert_eq!(last_error(), "nobody in the leader order is not a member");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! The audit log of the governance, exported from the finalized history.
+//!
+//! Every finalized block is preceded by the agenda it executes and the agenda proof,
+//! which carries the signatures of the governance members who voted for the agenda.
+//! An [`AuditLog`] collects them over a range of heights, sealed with the signature of the exporter,
+//! so that an auditor can check who approved what without running a node.
+//! Replaying it against the finalized history (see [`AuditLog::verify`]) confirms that
+//! nothing has been left out or altered.
+//!
+//! The votes for the other agendas of a height live only in the governance DMS of that height,
+//! so they are not in the log. Nor does a vote have its own timestamp;
+//! an entry has the timestamps of the agenda and of the agenda proof, which records the approval.
+use super::*;
+use std::ops::RangeInclusive;
+
+/// A vote for a finalized agenda.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditVote {
+    pub voter: PublicKey,
+    /// The member that the key belongs to in the reserved state given to the export.
+    pub member: Option<MemberName>,
+    pub signature: Signature,
+}
+
+/// A finalized agenda with the votes that approved it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The height of the block that finalized the agenda.
+    pub height: BlockHeight,
+    pub agenda_hash: Hash256,
+    pub agenda: Agenda,
+    pub votes: Vec<AuditVote>,
+    /// The timestamp of the agenda proof.
+    pub approved_at: Timestamp,
+    /// The hash of the block that finalized the agenda.
+    pub block_hash: Hash256,
+}
+
+/// The signature of the exporter on the entries of an [`AuditLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditSeal {
+    pub from: BlockHeight,
+    pub to: BlockHeight,
+    pub entries_hash: Hash256,
+    pub exporter: PublicKey,
+    pub signature: Signature,
+}
+
+/// The finalized agendas and their votes over a range of heights, one entry per height.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+    pub seal: AuditSeal,
+}
+
+/// Collects the audit entries from the finalized commits,
+/// naming the voters with the members of the given reserved state.
+///
+/// The commits must be consecutive in the finalized history; an agenda is collected
+/// once the block that finalizes it is reached.
+pub fn collect_audit_entries(
+    commits: &[Commit],
+    reserved_state: &ReservedState,
+) -> Result<Vec<AuditEntry>, Error> {
+    let member_of = |key: &PublicKey| {
+        reserved_state
+            .members
+            .iter()
+            .find(|member| member.governance_keys().contains(key))
+            .map(|member| member.name.clone())
+    };
+    let mut entries = Vec::new();
+    let mut agenda = None;
+    let mut agenda_proof = None;
+    for commit in commits {
+        match commit {
+            Commit::Agenda(x) => {
+                agenda = Some(x.clone());
+                agenda_proof = None;
+            }
+            Commit::AgendaProof(x) => agenda_proof = Some(x.clone()),
+            Commit::Block(header) => {
+                if let (Some(agenda), Some(agenda_proof)) = (agenda.take(), agenda_proof.take()) {
+                    if agenda_proof.agenda_hash != agenda.to_hash256() {
+                        return Err(eyre::eyre!(
+                            "the agenda proof of height {} is not for its agenda",
+                            header.height
+                        ));
+                    }
+                    if agenda.height != header.height {
+                        return Err(eyre::eyre!(
+                            "the agenda of height {} is finalized at height {}",
+                            agenda.height,
+                            header.height
+                        ));
+                    }
+                    entries.push(AuditEntry {
+                        height: header.height,
+                        agenda_hash: agenda_proof.agenda_hash,
+                        votes: agenda_proof
+                            .proof
+                            .iter()
+                            .map(|signature| AuditVote {
+                                voter: signature.signer().clone(),
+                                member: member_of(signature.signer()),
+                                signature: signature.get_raw_signature(),
+                            })
+                            .collect(),
+                        agenda,
+                        approved_at: agenda_proof.timestamp,
+                        block_hash: header.to_hash256(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(entries)
+}
+
+impl AuditLog {
+    /// Seals the entries of the heights in `range`, failing if any of them is missing.
+    pub fn seal(
+        range: RangeInclusive<BlockHeight>,
+        entries: Vec<AuditEntry>,
+        private_key: &PrivateKey,
+    ) -> Result<Self, Error> {
+        let entries = entries
+            .into_iter()
+            .filter(|entry| range.contains(&entry.height))
+            .collect::<Vec<_>>();
+        let heights = entries.iter().map(|entry| entry.height);
+        if !heights.eq(range.clone()) {
+            return Err(eyre::eyre!(
+                "the history doesn't cover the heights {} to {}",
+                range.start(),
+                range.end()
+            ));
+        }
+        let entries_hash = serde_spb::to_hash256(&entries)?;
+        let seal_target = seal_target(*range.start(), *range.end(), entries_hash);
+        Ok(Self {
+            entries,
+            seal: AuditSeal {
+                from: *range.start(),
+                to: *range.end(),
+                entries_hash,
+                exporter: private_key.public_key(),
+                signature: Signature::sign(seal_target, private_key)?,
+            },
+        })
+    }
+
+    /// Checks the seal and the signatures of the votes.
+    pub fn verify_signatures(&self) -> Result<(), String> {
+        let entries_hash = serde_spb::to_hash256(&self.entries).map_err(|e| e.to_string())?;
+        if entries_hash != self.seal.entries_hash {
+            return Err("the entries don't match the seal".to_owned());
+        }
+        self.seal
+            .signature
+            .verify(
+                seal_target(self.seal.from, self.seal.to, entries_hash),
+                &self.seal.exporter,
+            )
+            .map_err(|e| format!("invalid seal: {e}"))?;
+        for entry in &self.entries {
+            if entry.agenda.to_hash256() != entry.agenda_hash {
+                return Err(format!("height {}: the agenda doesn't match", entry.height));
+            }
+            for vote in &entry.votes {
+                TypedSignature::<Agenda>::new(vote.signature.clone(), vote.voter.clone())
+                    .verify(&entry.agenda)
+                    .map_err(|e| format!("height {}: invalid vote: {e}", entry.height))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies the log, replaying it against the entries collected from the finalized history
+    /// (see [`collect_audit_entries`]), which may cover more heights than the log.
+    pub fn verify(&self, history: &[AuditEntry]) -> Result<(), String> {
+        self.verify_signatures()?;
+        let range = self.seal.from..=self.seal.to;
+        let mut history = history
+            .iter()
+            .filter(|entry| range.contains(&entry.height))
+            .peekable();
+        for entry in &self.entries {
+            if let Some(finalized) = history.next_if(|x| x.height < entry.height) {
+                return Err(format!("height {}: missing from the log", finalized.height));
+            }
+            match history.next() {
+                Some(finalized) if finalized.height == entry.height => {
+                    if !same_record(finalized, entry) {
+                        return Err(format!(
+                            "height {}: differs from the finalized history",
+                            entry.height
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "height {}: not in the finalized history",
+                        entry.height
+                    ))
+                }
+            }
+        }
+        if let Some(finalized) = history.next() {
+            return Err(format!("height {}: missing from the log", finalized.height));
+        }
+        Ok(())
+    }
+
+    /// Writes the log in JSON Lines: an entry per line, followed by the seal.
+    pub fn to_jsonl(&self) -> Result<String, Error> {
+        let mut lines = Vec::new();
+        for entry in &self.entries {
+            lines.push(serde_json::to_string(entry)?);
+        }
+        lines.push(serde_json::to_string(&self.seal)?);
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// Reads the log written by [`Self::to_jsonl`].
+    pub fn from_jsonl(s: &str) -> Result<Self, Error> {
+        let mut lines = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        let seal = lines
+            .pop()
+            .ok_or_else(|| eyre::eyre!("the audit log is empty"))?;
+        Ok(Self {
+            entries: lines
+                .into_iter()
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?,
+            seal: serde_json::from_str(seal)?,
+        })
+    }
+
+    /// Writes the log in CSV, a vote per row, for the spreadsheets.
+    ///
+    /// The seal is in the leading comment line; verify the JSON Lines form instead.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!(
+            "# heights {} to {} sealed by {}: {} on {}\n",
+            self.seal.from,
+            self.seal.to,
+            self.seal.exporter,
+            self.seal.signature,
+            self.seal.entries_hash
+        );
+        csv.push_str(
+            "height,agenda_hash,author,agenda_timestamp,approved_at,block_hash,voter,member,signature\n",
+        );
+        for entry in &self.entries {
+            for vote in &entry.votes {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{}\n",
+                    entry.height,
+                    entry.agenda_hash,
+                    entry.agenda.author,
+                    entry.agenda.timestamp,
+                    entry.approved_at,
+                    entry.block_hash,
+                    vote.voter,
+                    vote.member.as_deref().unwrap_or_default(),
+                    vote.signature
+                ));
+            }
+        }
+        csv
+    }
+}
+
+fn seal_target(from: BlockHeight, to: BlockHeight, entries_hash: Hash256) -> Hash256 {
+    serde_spb::to_hash256(&(from, to, entries_hash)).unwrap()
+}
+
+/// Compares the entries except the member names, which depend on the reserved state of the export.
+fn same_record(a: &AuditEntry, b: &AuditEntry) -> bool {
+    let votes = |entry: &AuditEntry| {
+        entry
+            .votes
+            .iter()
+            .map(|vote| (vote.voter.clone(), vote.signature.clone()))
+            .collect::<Vec<_>>()
+    };
+    a.agenda_hash == b.agenda_hash
+        && a.approved_at == b.approved_at
+        && a.block_hash == b.block_hash
+        && votes(a) == votes(b)
+}
@@ -1,33 +1,519 @@
+pub mod audit;
+pub mod poll;
+
 use serde::{Deserialize, Serialize};
+use simperby_core::reserved::ReservedState;
 use simperby_core::*;
 use simperby_network::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 pub type Error = eyre::Error;
 
+/// Returns the keys that can vote in the governance of the reserved state,
+/// which must be among the members of the governance DMS (see [`dms_members`]).
+///
+/// A member that has delegated its governance voting power is represented by its delegatee,
+/// and a member under a threshold authorization votes with each of its keys.
+pub fn eligible_voters(reserved_state: &ReservedState) -> Result<Vec<PublicKey>, Error> {
+    let governance_set = reserved_state
+        .get_governance_set()
+        .map_err(|e| eyre::eyre!(e))?
+        .into_iter()
+        .map(|(public_key, _)| public_key)
+        .collect::<BTreeSet<_>>();
+    let voters = reserved_state
+        .members
+        .iter()
+        .filter(|member| governance_set.contains(&member.public_key))
+        .flat_map(|member| member.governance_keys())
+        .collect::<BTreeSet<_>>();
+    Ok(voters.into_iter().collect())
+}
+
+/// Returns the keys that can send messages to the governance DMS:
+/// the [`eligible_voters`] and the veto holders (see [`ReservedState::veto_holders`]),
+/// who can veto even without the governance voting power.
+pub fn dms_members(reserved_state: &ReservedState) -> Result<Vec<PublicKey>, Error> {
+    let mut members = eligible_voters(reserved_state)?
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    members.extend(
+        reserved_state
+            .members
+            .iter()
+            .filter(|member| reserved_state.veto_holders.contains(&member.name))
+            .flat_map(|member| member.governance_keys()),
+    );
+    Ok(members.into_iter().collect())
+}
+
+/// Generates the DMS key for the governance of the height next to the given block.
+pub fn generate_dms_key(last_finalized_header: &BlockHeader) -> DmsKey {
+    format!("governance-{}", last_finalized_header.to_hash256())
+}
+
+/// How the votes on an agenda are cast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum VotingMode {
+    /// A vote is the signature on the agenda hash, visible as soon as it's cast.
+    #[default]
+    Open,
+    /// The members first broadcast the salted commitments to their votes ([`VoteCommitment`]),
+    /// and reveal them ([`VoteReveal`]) once `reveal_after` has passed.
+    ///
+    /// Only the reveals matching the commitments count, and none of them before the deadline.
+    CommitReveal { reveal_after: Timestamp },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GovernanceStatus {
     /// Agenda hashes and their voters.
     pub votes: HashMap<Hash256, HashMap<PublicKey, Signature>>,
+    /// Agenda hashes and the commitments to the hidden votes on them, by the voters.
+    ///
+    /// A voter who committed more than once to an agenda is never counted on it.
+    #[serde(default)]
+    pub commitments: HashMap<Hash256, HashMap<PublicKey, BTreeSet<Hash256>>>,
+    /// Agenda hashes and the reveals of the hidden votes on them, by the voters.
+    ///
+    /// They're not checked against the commitments yet.
+    #[serde(default)]
+    pub reveals: HashMap<Hash256, HashMap<PublicKey, Vec<VoteReveal>>>,
+    /// Poll hashes and the polls.
+    #[serde(default)]
+    pub polls: HashMap<Hash256, poll::Poll>,
+    /// Poll hashes and the votes on them, by the voters.
+    #[serde(default)]
+    pub poll_votes: HashMap<Hash256, HashMap<PublicKey, Vec<poll::PollVote>>>,
+    /// Agenda hashes and the vetoes on them.
+    ///
+    /// They're not checked against [`ReservedState::veto_holders`] yet.
+    #[serde(default)]
+    pub vetoes: HashMap<Hash256, Vec<SignedAgendaVeto>>,
+}
+
+impl GovernanceStatus {
+    /// Returns all the vetoes, to check the agenda proofs against.
+    pub fn all_vetoes(&self) -> Vec<SignedAgendaVeto> {
+        self.vetoes.values().flatten().cloned().collect()
+    }
+
+    /// Returns the veto holders who block the agenda at the time `now`; see [`ReservedState::vetoers`].
+    pub fn vetoers(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+        now: Timestamp,
+    ) -> Vec<MemberName> {
+        let vetoes = self.vetoes.get(&agenda_hash).cloned().unwrap_or_default();
+        reserved_state.vetoers(agenda_hash, &vetoes, now)
+    }
+
+    /// Tallies the votes on the agenda with the governance set of the reserved state.
+    ///
+    /// Since the votes are only for agendas, a member is considered to be against the agenda
+    /// if it voted for another agenda (of the same height, which the DMS is dedicated to).
+    pub fn tally(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+    ) -> Result<Tally, Error> {
+        let signers_for = self
+            .votes
+            .get(&agenda_hash)
+            .map(|votes| votes.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let signers_any = self
+            .votes
+            .values()
+            .flat_map(|votes| votes.keys().cloned())
+            .collect::<Vec<_>>();
+        self.tally_signers(agenda_hash, reserved_state, &signers_for, &signers_any, &[])
+    }
+
+    /// Tallies the votes on the agenda in the given mode at the time `now`.
+    ///
+    /// In the commit-reveal mode, a member is against the agenda only if it revealed so,
+    /// and the members who committed but haven't revealed are [`VoteStatus::Committed`].
+    pub fn tally_in_mode(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+        mode: VotingMode,
+        now: Timestamp,
+    ) -> Result<Tally, Error> {
+        if mode == VotingMode::Open {
+            return self.tally(agenda_hash, reserved_state);
+        }
+        let committed = self
+            .commitments
+            .get(&agenda_hash)
+            .map(|commitments| commitments.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let revealed = self.revealed_votes(agenda_hash, mode, now);
+        let signers = |approve: bool| {
+            revealed
+                .iter()
+                .filter(|(_, (x, _))| *x == approve)
+                .map(|(public_key, _)| public_key.clone())
+                .collect::<Vec<_>>()
+        };
+        self.tally_signers(
+            agenda_hash,
+            reserved_state,
+            &signers(true),
+            &signers(false),
+            &committed,
+        )
+    }
+
+    /// Returns the signatures for the agenda proof, of the votes for the agenda that count in the mode.
+    pub fn approvals(
+        &self,
+        agenda_hash: Hash256,
+        mode: VotingMode,
+        now: Timestamp,
+    ) -> HashMap<PublicKey, Signature> {
+        match mode {
+            VotingMode::Open => self.votes.get(&agenda_hash).cloned().unwrap_or_default(),
+            VotingMode::CommitReveal { .. } => self
+                .revealed_votes(agenda_hash, mode, now)
+                .into_iter()
+                .filter_map(|(public_key, (approve, signature))| {
+                    approve.then_some((public_key, signature?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the approvals of the agenda as [`Self::approvals`], or none once its voting deadline
+    /// ([`Agenda::deadline`]) has passed at the time `now`,
+    /// so that the votes arriving after the deadline never approve it.
+    pub fn approvals_by_deadline(
+        &self,
+        agenda: &Agenda,
+        mode: VotingMode,
+        now: Timestamp,
+    ) -> HashMap<PublicKey, Signature> {
+        if !agenda.is_open_at(now) {
+            return HashMap::new();
+        }
+        self.approvals(agenda.to_hash256(), mode, now)
+    }
+
+    /// Returns the reveals that match the commitments, once the deadline has passed,
+    /// with the signatures of the approving ones on the agenda hash.
+    ///
+    /// An approving reveal without the signature (i.e., not followed by the open vote yet) is
+    /// counted in the tally, but can't be in the agenda proof.
+    fn revealed_votes(
+        &self,
+        agenda_hash: Hash256,
+        mode: VotingMode,
+        now: Timestamp,
+    ) -> BTreeMap<PublicKey, (bool, Option<Signature>)> {
+        let VotingMode::CommitReveal { reveal_after } = mode else {
+            return BTreeMap::new();
+        };
+        if now < reveal_after {
+            return BTreeMap::new();
+        }
+        let (Some(commitments), Some(reveals)) = (
+            self.commitments.get(&agenda_hash),
+            self.reveals.get(&agenda_hash),
+        ) else {
+            return BTreeMap::new();
+        };
+        reveals
+            .iter()
+            .filter_map(|(public_key, reveals)| {
+                let commitment = match commitments.get(public_key) {
+                    Some(commitments) if commitments.len() == 1 => commitments.first()?,
+                    _ => return None,
+                };
+                let reveal = reveals
+                    .iter()
+                    .find(|reveal| &reveal.commitment() == commitment)?;
+                let signature = self
+                    .votes
+                    .get(&agenda_hash)
+                    .and_then(|votes| votes.get(public_key))
+                    .cloned();
+                Some((public_key.clone(), (reveal.approve, signature)))
+            })
+            .collect()
+    }
+
+    fn tally_signers(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+        signers_for: &[PublicKey],
+        signers_against: &[PublicKey],
+        signers_committed: &[PublicKey],
+    ) -> Result<Tally, Error> {
+        let governance_set = reserved_state
+            .get_governance_set()
+            .map_err(|e| eyre::eyre!(e))?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+
+        let mut votes = Vec::new();
+        for member in &reserved_state.members {
+            // A delegator is represented by its delegatee.
+            let Some(voting_power) = governance_set.get(&member.public_key).copied() else {
+                continue;
+            };
+            let status = if member.is_authorized_by(signers_for) {
+                VoteStatus::For
+            } else if member.is_authorized_by(signers_against) {
+                VoteStatus::Against
+            } else if member
+                .governance_keys()
+                .iter()
+                .any(|key| signers_committed.contains(key))
+            {
+                VoteStatus::Committed
+            } else {
+                VoteStatus::Absent
+            };
+            votes.push(MemberVote {
+                name: member.name.clone(),
+                voting_power,
+                status,
+            });
+        }
+        let power = |status: VoteStatus| {
+            votes
+                .iter()
+                .filter(|vote| vote.status == status)
+                .map(|vote| vote.voting_power)
+                .sum::<VotingPower>()
+        };
+        let for_power = power(VoteStatus::For);
+        let against_power = power(VoteStatus::Against);
+        let absent_power = power(VoteStatus::Absent) + power(VoteStatus::Committed);
+        let total_power = for_power + against_power + absent_power;
+
+        // The largest ones first to find the fewest voters,
+        // preferring the absent (or committed) ones to those who voted against.
+        let mut candidates = votes
+            .iter()
+            .filter(|vote| vote.status != VoteStatus::For)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|vote| {
+            (
+                std::cmp::Reverse(vote.voting_power),
+                vote.status == VoteStatus::Against,
+            )
+        });
+        let mut projected_power = for_power;
+        let mut additional_voters = Vec::new();
+        for candidate in candidates {
+            if Tally::is_threshold_met(projected_power, total_power) {
+                break;
+            }
+            projected_power += candidate.voting_power;
+            additional_voters.push(candidate.name.clone());
+        }
+
+        Ok(Tally {
+            agenda_hash,
+            votes,
+            for_power,
+            against_power,
+            absent_power,
+            threshold_met: Tally::is_threshold_met(for_power, total_power),
+            additional_voters,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteStatus {
+    For,
+    /// Voted for another agenda, or revealed a vote against it (see [`VotingMode::CommitReveal`]).
+    Against,
+    Absent,
+    /// Committed to a hidden vote which hasn't been revealed (validly) yet;
+    /// counted as [`Tally::absent_power`].
+    Committed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberVote {
+    pub name: MemberName,
+    /// The governance voting power, including the delegated ones.
+    pub voting_power: VotingPower,
+    pub status: VoteStatus,
+}
+
+/// The governance status of an agenda.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub agenda_hash: Hash256,
+    /// The votes of the members who hold the governance voting power.
+    pub votes: Vec<MemberVote>,
+    pub for_power: VotingPower,
+    pub against_power: VotingPower,
+    /// The power of the absent members, including those who committed to a hidden vote but haven't revealed it.
+    pub absent_power: VotingPower,
+    /// Whether the agenda can be approved with the current votes.
+    pub threshold_met: bool,
+    /// The fewest members whose additional votes would meet the threshold.
+    ///
+    /// Empty if the threshold is already met.
+    pub additional_voters: Vec<MemberName>,
+}
+
+impl Tally {
+    /// An agenda needs more than half of the governance voting power,
+    /// as required for the agenda proof.
+    pub fn is_threshold_met(for_power: VotingPower, total_power: VotingPower) -> bool {
+        for_power * 2 > total_power
+    }
+
+    pub fn total_power(&self) -> VotingPower {
+        self.for_power + self.against_power + self.absent_power
+    }
+}
+
+/// A message of the governance DMS.
+///
+/// In JSON (of the DMS storage), an open vote is serialized as the bare agenda hash,
+/// as it's always been. The binary encoding (of the packets) is tagged,
+/// since it's not self-describing.
+#[derive(Clone, Debug)]
+pub enum Vote {
+    /// A vote for the agenda of the hash, whose commitment signature goes into the agenda proof.
+    Open(Hash256),
+    Commitment(VoteCommitment),
+    Reveal(VoteReveal),
+    /// A non-binding poll (see [`poll`]), identified by its hash.
+    Poll(poll::Poll),
+    PollVote(poll::PollVote),
+    /// A veto on the agenda by a veto holder, whose commitment signature is the signed veto.
+    Veto(AgendaVeto),
+}
+
+// The remote definitions are only for the derives, never constructed themselves.
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Vote", untagged)]
+enum UntaggedVote {
+    Open(Hash256),
+    Commitment(VoteCommitment),
+    Reveal(VoteReveal),
+    Poll(poll::Poll),
+    PollVote(poll::PollVote),
+    Veto(AgendaVeto),
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Vote")]
+enum TaggedVote {
+    Open(Hash256),
+    Commitment(VoteCommitment),
+    Reveal(VoteReveal),
+    Poll(poll::Poll),
+    PollVote(poll::PollVote),
+    Veto(AgendaVeto),
+}
+
+impl Serialize for Vote {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            UntaggedVote::serialize(self, serializer)
+        } else {
+            TaggedVote::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Vote {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            UntaggedVote::deserialize(deserializer)
+        } else {
+            TaggedVote::deserialize(deserializer)
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct Vote {
+/// A commitment to a hidden vote (see [`VotingMode::CommitReveal`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteCommitment {
     pub agenda_hash: Hash256,
+    /// [`VoteReveal::commitment`] of the vote to reveal.
+    pub commitment: Hash256,
+}
+
+/// A reveal of a hidden vote, which counts only if it matches the commitment of the same member.
+///
+/// An approving reveal is followed by the open vote ([`Vote::Open`]),
+/// whose signature goes into the agenda proof.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteReveal {
+    pub agenda_hash: Hash256,
+    pub approve: bool,
+    pub salt: Hash256,
+}
+
+impl VoteReveal {
+    /// The commitment to this vote, which hides it as long as the salt is secret.
+    pub fn commitment(&self) -> Hash256 {
+        Hash256::hash(
+            [
+                self.agenda_hash.as_ref(),
+                &[self.approve as u8],
+                self.salt.as_ref(),
+            ]
+            .concat(),
+        )
+    }
+}
+
+impl Vote {
+    /// The agenda that the message is about, if it's not about a poll.
+    pub fn agenda_hash(&self) -> Option<Hash256> {
+        match self {
+            Vote::Open(agenda_hash) => Some(*agenda_hash),
+            Vote::Commitment(commitment) => Some(commitment.agenda_hash),
+            Vote::Reveal(reveal) => Some(reveal.agenda_hash),
+            Vote::Veto(veto) => Some(veto.agenda_hash),
+            Vote::Poll(_) | Vote::PollVote(_) => None,
+        }
+    }
 }
 
 impl ToHash256 for Vote {
+    /// The agenda hash for an open vote, so that its commitment signature is the vote itself,
+    /// the poll hash for a poll, and the veto hash for a veto, for the same reason.
     fn to_hash256(&self) -> Hash256 {
-        self.agenda_hash
+        match self {
+            Vote::Open(agenda_hash) => *agenda_hash,
+            Vote::Poll(poll) => poll.to_hash256(),
+            Vote::Veto(veto) => veto.to_hash256(),
+            Vote::Commitment(_) | Vote::Reveal(_) | Vote::PollVote(_) => {
+                Hash256::hash(serde_spb::to_vec(self).expect("vote serialization never fails"))
+            }
+        }
     }
 }
 
 impl DmsMessage for Vote {
     fn check(&self) -> Result<(), Error> {
-        Ok(())
+        match self {
+            Vote::Poll(poll) => poll.check().map_err(|e| eyre::eyre!(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn priority(&self) -> MessagePriority {
+        MessagePriority::Governance
     }
 
     /// Agenda hash cryptographically contains the information of height. It's safe to ignore `dms_key`.
@@ -66,28 +552,252 @@ impl Governance {
 
     pub async fn read(&self) -> Result<GovernanceStatus, Error> {
         let votes = self.dms.read().await.read_messages().await?;
-        let mut result = HashMap::<Hash256, HashMap<PublicKey, Signature>>::default();
+        let mut status = GovernanceStatus {
+            votes: HashMap::default(),
+            commitments: HashMap::default(),
+            reveals: HashMap::default(),
+            polls: HashMap::default(),
+            poll_votes: HashMap::default(),
+            vetoes: HashMap::default(),
+        };
         for vote in votes {
             for committers in vote.committers {
-                result
-                    .entry(vote.message.to_hash256())
-                    .or_default()
-                    .insert(committers.committer, committers.signature);
+                match &vote.message {
+                    Vote::Open(agenda_hash) => {
+                        status
+                            .votes
+                            .entry(*agenda_hash)
+                            .or_default()
+                            .insert(committers.committer, committers.signature);
+                    }
+                    Vote::Commitment(commitment) => {
+                        status
+                            .commitments
+                            .entry(commitment.agenda_hash)
+                            .or_default()
+                            .entry(committers.committer)
+                            .or_default()
+                            .insert(commitment.commitment);
+                    }
+                    Vote::Reveal(reveal) => {
+                        status
+                            .reveals
+                            .entry(reveal.agenda_hash)
+                            .or_default()
+                            .entry(committers.committer)
+                            .or_default()
+                            .push(reveal.clone());
+                    }
+                    Vote::Poll(poll) => {
+                        status.polls.insert(poll.to_hash256(), poll.clone());
+                    }
+                    Vote::PollVote(poll_vote) => {
+                        status
+                            .poll_votes
+                            .entry(poll_vote.poll_hash)
+                            .or_default()
+                            .entry(committers.committer)
+                            .or_default()
+                            .push(poll_vote.clone());
+                    }
+                    Vote::Veto(veto) => {
+                        status
+                            .vetoes
+                            .entry(veto.agenda_hash)
+                            .or_default()
+                            .push(SignedAgendaVeto {
+                                veto: veto.clone(),
+                                signature: TypedSignature::new(
+                                    committers.signature,
+                                    committers.committer,
+                                ),
+                            });
+                    }
+                }
             }
         }
-        let status = GovernanceStatus { votes: result };
         Ok(status)
     }
 
+    /// Tallies the votes on the agenda; see [`GovernanceStatus::tally`].
+    pub async fn tally(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+    ) -> Result<Tally, Error> {
+        self.read().await?.tally(agenda_hash, reserved_state)
+    }
+
+    /// Tallies the votes on the agenda in the mode; see [`GovernanceStatus::tally_in_mode`].
+    pub async fn tally_in_mode(
+        &self,
+        agenda_hash: Hash256,
+        reserved_state: &ReservedState,
+        mode: VotingMode,
+        now: Timestamp,
+    ) -> Result<Tally, Error> {
+        self.read()
+            .await?
+            .tally_in_mode(agenda_hash, reserved_state, mode, now)
+    }
+
     pub async fn vote(&mut self, agenda_hash: Hash256) -> Result<(), Error> {
         self.dms
             .write()
             .await
-            .commit_message(&Vote { agenda_hash })
+            .commit_message(&Vote::Open(agenda_hash))
+            .await?;
+        Ok(())
+    }
+
+    /// Commits to a hidden vote on the agenda, to be revealed with [`Self::reveal_vote`]
+    /// with the same salt after the deadline.
+    pub async fn commit_vote(
+        &mut self,
+        agenda_hash: Hash256,
+        approve: bool,
+        salt: Hash256,
+    ) -> Result<(), Error> {
+        let reveal = VoteReveal {
+            agenda_hash,
+            approve,
+            salt,
+        };
+        self.dms
+            .write()
+            .await
+            .commit_message(&Vote::Commitment(VoteCommitment {
+                agenda_hash,
+                commitment: reveal.commitment(),
+            }))
+            .await?;
+        Ok(())
+    }
+
+    /// Reveals the hidden vote committed with [`Self::commit_vote`],
+    /// casting the open vote too if it's approving.
+    pub async fn reveal_vote(
+        &mut self,
+        agenda_hash: Hash256,
+        approve: bool,
+        salt: Hash256,
+    ) -> Result<(), Error> {
+        let mut dms = self.dms.write().await;
+        dms.commit_message(&Vote::Reveal(VoteReveal {
+            agenda_hash,
+            approve,
+            salt,
+        }))
+        .await?;
+        if approve {
+            dms.commit_message(&Vote::Open(agenda_hash)).await?;
+        }
+        Ok(())
+    }
+
+    /// Vetoes the agenda until `expires_at`, which counts only if this node is of a veto holder.
+    pub async fn veto(&mut self, agenda_hash: Hash256, expires_at: Timestamp) -> Result<(), Error> {
+        self.dms
+            .write()
+            .await
+            .commit_message(&Vote::Veto(AgendaVeto {
+                agenda_hash,
+                expires_at,
+            }))
+            .await?;
+        Ok(())
+    }
+
+    /// Broadcasts a poll, returning its hash.
+    pub async fn create_poll(&mut self, poll: poll::Poll) -> Result<Hash256, Error> {
+        poll.check().map_err(|e| eyre::eyre!(e))?;
+        self.dms
+            .write()
+            .await
+            .commit_message(&Vote::Poll(poll.clone()))
+            .await?;
+        Ok(poll.to_hash256())
+    }
+
+    /// Votes for the option (the index in [`poll::Poll::options`]) of the poll.
+    pub async fn vote_poll(
+        &mut self,
+        poll_hash: Hash256,
+        option: u32,
+        timestamp: Timestamp,
+    ) -> Result<(), Error> {
+        self.dms
+            .write()
+            .await
+            .commit_message(&Vote::PollVote(poll::PollVote {
+                poll_hash,
+                option,
+                timestamp,
+            }))
             .await?;
         Ok(())
     }
 
+    /// Tallies the votes on the poll; see [`GovernanceStatus::tally_poll`].
+    pub async fn tally_poll(
+        &self,
+        poll_hash: Hash256,
+        reserved_state: &ReservedState,
+        now: Timestamp,
+    ) -> Result<poll::PollTally, Error> {
+        self.read()
+            .await?
+            .tally_poll(poll_hash, reserved_state, now)
+    }
+
+    /// Derives the salt of a hidden vote from the private key of the voter,
+    /// so that it can be recomputed for the reveal (e.g., after a restart) but not guessed.
+    pub fn derive_vote_salt(private_key: &PrivateKey, agenda_hash: Hash256) -> Hash256 {
+        Hash256::hash([private_key.as_ref(), agenda_hash.as_ref()].concat())
+    }
+
+    /// Returns the payload that a governance member signs to vote on the agenda.
+    ///
+    /// It's for the members who keep their keys offline; see [`Self::import_signed_vote`].
+    pub fn vote_payload(agenda_hash: Hash256) -> Vec<u8> {
+        agenda_hash.as_ref().to_vec()
+    }
+
+    /// Adds a vote signed externally on the payload from [`Self::vote_payload`].
+    pub async fn import_signed_vote(
+        &mut self,
+        agenda_hash: Hash256,
+        signer: PublicKey,
+        signature: Signature,
+    ) -> Result<(), Error> {
+        self.dms
+            .write()
+            .await
+            .add_committed_message(
+                &Vote::Open(agenda_hash),
+                MessageCommitmentProof {
+                    committer: signer,
+                    signature,
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Exports the agendas finalized at the heights in `range` with their votes and proofs,
+    /// read from the finalized commits and sealed with the given key.
+    ///
+    /// See [`audit`] for what the log contains.
+    pub fn export_audit_log(
+        range: std::ops::RangeInclusive<BlockHeight>,
+        commits: &[Commit],
+        reserved_state: &ReservedState,
+        private_key: &PrivateKey,
+    ) -> Result<audit::AuditLog, Error> {
+        let entries = audit::collect_audit_entries(commits, reserved_state)?;
+        audit::AuditLog::seal(range, entries, private_key)
+    }
+
     pub async fn flush(&self) -> Result<(), Error> {
         Ok(())
     }
@@ -0,0 +1,207 @@
+//! Non-binding polls of the governance members, with multiple options.
+//!
+//! A poll ([`Poll`]) and the votes on it ([`PollVote`]) are broadcast through the governance DMS
+//! like the votes on agendas, and tallied by the governance voting power ([`PollTally`]).
+//! They never change the reserved state, but an agenda can cite the result
+//! with a line of [`POLL_REFERENCE_PREFIX`] in the body of its transactions.
+//!
+//! Like the votes, a poll lives in the governance DMS of the height.
+use super::*;
+
+pub const MAX_POLL_OPTIONS: usize = 16;
+pub const MAX_POLL_TITLE_SIZE: usize = 256;
+pub const MAX_POLL_DESCRIPTION_SIZE: usize = 4096;
+pub const MAX_POLL_OPTION_SIZE: usize = 256;
+
+/// The prefix of a line that references a poll, in the form of `Poll: <hash>`.
+pub const POLL_REFERENCE_PREFIX: &str = "Poll: ";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Poll {
+    pub title: String,
+    pub description: String,
+    pub options: Vec<String>,
+    /// The votes timestamped after this don't count.
+    pub deadline: Timestamp,
+    /// When the poll was created, which tells apart the polls of the same content.
+    pub timestamp: Timestamp,
+}
+
+impl Poll {
+    pub fn check(&self) -> Result<(), String> {
+        if self.title.is_empty() || self.title.len() > MAX_POLL_TITLE_SIZE {
+            return Err(format!(
+                "the title must be in 1 to {MAX_POLL_TITLE_SIZE} bytes"
+            ));
+        }
+        if self.description.len() > MAX_POLL_DESCRIPTION_SIZE {
+            return Err(format!(
+                "too long description: {} > {MAX_POLL_DESCRIPTION_SIZE}",
+                self.description.len()
+            ));
+        }
+        if self.options.len() < 2 || self.options.len() > MAX_POLL_OPTIONS {
+            return Err(format!(
+                "a poll must have 2 to {MAX_POLL_OPTIONS} options, not {}",
+                self.options.len()
+            ));
+        }
+        let mut options = BTreeSet::new();
+        for option in &self.options {
+            if option.is_empty() || option.len() > MAX_POLL_OPTION_SIZE {
+                return Err(format!(
+                    "an option must be in 1 to {MAX_POLL_OPTION_SIZE} bytes"
+                ));
+            }
+            if !options.insert(option) {
+                return Err(format!("duplicate option: {option}"));
+            }
+        }
+        if self.deadline < self.timestamp {
+            return Err("the deadline is before the creation".to_owned());
+        }
+        Ok(())
+    }
+
+    /// The line that references this poll; see [`poll_references`].
+    pub fn reference(&self) -> String {
+        format!("{POLL_REFERENCE_PREFIX}{}", self.to_hash256())
+    }
+}
+
+impl ToHash256 for Poll {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).expect("poll serialization never fails"))
+    }
+}
+
+/// A vote for an option of the poll, as the index in [`Poll::options`].
+///
+/// A member who voted for more than one option isn't counted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollVote {
+    pub poll_hash: Hash256,
+    pub option: u32,
+    /// When the vote was cast, by the voter's own clock.
+    pub timestamp: Timestamp,
+}
+
+/// Parses the poll references in the text, e.g. the body of a transaction.
+pub fn poll_references(text: &str) -> Result<Vec<Hash256>, String> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix(POLL_REFERENCE_PREFIX))
+        .map(|reference| {
+            let hash = hex::decode(reference.trim())
+                .ok()
+                .and_then(|hash| hash.try_into().ok())
+                .ok_or_else(|| format!("invalid poll reference: {reference}"))?;
+            Ok(Hash256::from_array(hash))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollOptionResult {
+    pub option: String,
+    pub voting_power: VotingPower,
+    pub voters: Vec<MemberName>,
+}
+
+/// The result of a poll, weighted by the governance voting power.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollTally {
+    pub poll_hash: Hash256,
+    pub poll: Poll,
+    /// In the order of [`Poll::options`].
+    pub options: Vec<PollOptionResult>,
+    pub absent_power: VotingPower,
+    /// The members who voted for more than one option, counted as absent.
+    pub conflicting_voters: Vec<MemberName>,
+    /// Whether the deadline has passed, so that the result is final.
+    pub closed: bool,
+}
+
+impl PollTally {
+    /// The option with the most voting power, if it's the only one.
+    pub fn leading_option(&self) -> Option<&PollOptionResult> {
+        let max = self.options.iter().map(|x| x.voting_power).max()?;
+        let mut leading = self.options.iter().filter(|x| x.voting_power == max);
+        match (leading.next(), leading.next()) {
+            (Some(option), None) if max > 0 => Some(option),
+            _ => None,
+        }
+    }
+}
+
+impl GovernanceStatus {
+    /// Tallies the votes on the poll with the governance set of the reserved state, at the time `now`.
+    ///
+    /// As in [`Self::tally`], a delegator is represented by its delegatee
+    /// and a member under a threshold authorization needs enough of its keys to vote alike.
+    pub fn tally_poll(
+        &self,
+        poll_hash: Hash256,
+        reserved_state: &ReservedState,
+        now: Timestamp,
+    ) -> Result<PollTally, Error> {
+        let poll = self
+            .polls
+            .get(&poll_hash)
+            .ok_or_else(|| eyre::eyre!("unknown poll: {poll_hash}"))?;
+        let governance_set = reserved_state
+            .get_governance_set()
+            .map_err(|e| eyre::eyre!(e))?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let mut signers = vec![Vec::new(); poll.options.len()];
+        for (public_key, votes) in self.poll_votes.get(&poll_hash).into_iter().flatten() {
+            for vote in votes {
+                if vote.timestamp <= poll.deadline && (vote.option as usize) < poll.options.len() {
+                    signers[vote.option as usize].push(public_key.clone());
+                }
+            }
+        }
+
+        let mut options = poll
+            .options
+            .iter()
+            .map(|option| PollOptionResult {
+                option: option.clone(),
+                voting_power: 0,
+                voters: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut absent_power = 0;
+        let mut conflicting_voters = Vec::new();
+        for member in &reserved_state.members {
+            let Some(voting_power) = governance_set.get(&member.public_key).copied() else {
+                continue;
+            };
+            let chosen = signers
+                .iter()
+                .enumerate()
+                .filter(|(_, signers)| member.is_authorized_by(signers))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            match chosen.as_slice() {
+                [i] => {
+                    options[*i].voting_power += voting_power;
+                    options[*i].voters.push(member.name.clone());
+                }
+                [] => absent_power += voting_power,
+                _ => {
+                    absent_power += voting_power;
+                    conflicting_voters.push(member.name.clone());
+                }
+            }
+        }
+        Ok(PollTally {
+            poll_hash,
+            poll: poll.clone(),
+            options,
+            absent_power,
+            conflicting_voters,
+            closed: now > poll.deadline,
+        })
+    }
+}
@@ -72,3 +72,553 @@ async fn basic_1() {
     }
     serve_task.await.unwrap();
 }
+
+#[tokio::test]
+async fn import_signed_vote() {
+    setup_test();
+
+    let (online, online_key) = generate_keypair_random();
+    let (offline, offline_key) = generate_keypair_random();
+    let (outsider, outsider_key) = generate_keypair_random();
+    let mut node = Governance::new(Arc::new(RwLock::new(
+        create_test_dms(
+            "governance-import-signed-vote".to_string(),
+            vec![online, offline.clone()],
+            online_key,
+        )
+        .await,
+    )))
+    .await
+    .unwrap();
+
+    let agenda_hash = Hash256::hash("agenda");
+    let payload = Governance::vote_payload(agenda_hash);
+    let sign = |key: &PrivateKey| {
+        Signature::sign(
+            Hash256::from_array(payload.clone().try_into().unwrap()),
+            key,
+        )
+        .unwrap()
+    };
+
+    assert!(node
+        .import_signed_vote(agenda_hash, offline.clone(), sign(&outsider_key))
+        .await
+        .is_err());
+    assert!(node
+        .import_signed_vote(agenda_hash, outsider, sign(&outsider_key))
+        .await
+        .is_err());
+    node.import_signed_vote(agenda_hash, offline.clone(), sign(&offline_key))
+        .await
+        .unwrap();
+    let votes = node.read().await.unwrap().votes;
+    assert_eq!(votes[&agenda_hash].len(), 1);
+    assert!(votes[&agenda_hash].contains_key(&offline));
+}
+
+#[tokio::test]
+async fn tally() {
+    setup_test();
+
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    let mut node = Governance::new(Arc::new(RwLock::new(
+        create_test_dms(
+            "governance-tally".to_string(),
+            keys.iter()
+                .map(|(public_key, _)| public_key.clone())
+                .collect(),
+            keys[0].1.clone(),
+        )
+        .await,
+    )))
+    .await
+    .unwrap();
+    let names = reserved_state
+        .members
+        .iter()
+        .map(|member| member.name.clone())
+        .collect::<Vec<_>>();
+    let agenda_hash = Hash256::hash("agenda");
+    let other_agenda_hash = Hash256::hash("other agenda");
+
+    for (hash, (public_key, private_key)) in
+        [(agenda_hash, &keys[0]), (other_agenda_hash, &keys[1])]
+    {
+        node.import_signed_vote(
+            hash,
+            public_key.clone(),
+            Signature::sign(hash, private_key).unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+    let tally = node.tally(agenda_hash, &reserved_state).await.unwrap();
+    assert_eq!(
+        tally
+            .votes
+            .iter()
+            .map(|vote| vote.status)
+            .collect::<Vec<_>>(),
+        vec![
+            VoteStatus::For,
+            VoteStatus::Against,
+            VoteStatus::Absent,
+            VoteStatus::Absent
+        ]
+    );
+    assert_eq!(
+        (tally.for_power, tally.against_power, tally.absent_power),
+        (1, 1, 2)
+    );
+    assert!(!tally.threshold_met);
+    assert_eq!(
+        tally.additional_voters,
+        vec![names[2].clone(), names[3].clone()]
+    );
+
+    for (i, (public_key, private_key)) in keys.iter().enumerate().skip(2) {
+        node.import_signed_vote(
+            agenda_hash,
+            public_key.clone(),
+            Signature::sign(agenda_hash, private_key).unwrap(),
+        )
+        .await
+        .unwrap();
+        let tally = node.tally(agenda_hash, &reserved_state).await.unwrap();
+        assert_eq!(tally.threshold_met, i == 3);
+        assert_eq!(tally.additional_voters, names[i + 1..].to_vec());
+    }
+}
+
+#[tokio::test]
+async fn vote_deadline() {
+    setup_test();
+
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    let mut node = Governance::new(Arc::new(RwLock::new(
+        create_test_dms(
+            "governance-vote-deadline".to_string(),
+            keys.iter()
+                .map(|(public_key, _)| public_key.clone())
+                .collect(),
+            keys[0].1.clone(),
+        )
+        .await,
+    )))
+    .await
+    .unwrap();
+    let agenda = Agenda {
+        height: 1,
+        author: reserved_state.members[0].name.clone(),
+        timestamp: 0,
+        transactions_hash: Agenda::calculate_transactions_hash(&[]),
+        description: None,
+        deadline: Some(VotingDeadline::Timestamp(100)),
+    };
+    for (public_key, private_key) in &keys {
+        node.import_signed_vote(
+            agenda.to_hash256(),
+            public_key.clone(),
+            Signature::sign(agenda.to_hash256(), private_key).unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+    let status = node.read().await.unwrap();
+    let mode = VotingMode::Open;
+    assert_eq!(status.approvals_by_deadline(&agenda, mode, 100).len(), 4);
+    assert!(status.approvals_by_deadline(&agenda, mode, 101).is_empty());
+
+    // A deadline before the agenda itself is invalid.
+    let agenda = Agenda {
+        timestamp: 101,
+        ..agenda
+    };
+    assert!(agenda.check_deadline().is_err());
+}
+
+#[tokio::test]
+async fn eligible_voters_exclude_delegators() {
+    setup_test();
+    let (reserved_state, keys) = test_utils::generate_delegated_genesis(4, true);
+    let voters = eligible_voters(&reserved_state).unwrap();
+    // member-0000 delegates to member-0002.
+    let mut expected = keys[1..]
+        .iter()
+        .map(|(public_key, _)| public_key.clone())
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(voters, expected);
+
+    let (reserved_state, keys) = test_utils::generate_delegated_genesis(4, false);
+    let voters = eligible_voters(&reserved_state).unwrap();
+    let mut expected = keys
+        .iter()
+        .map(|(public_key, _)| public_key.clone())
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(voters, expected);
+}
+
+#[tokio::test]
+async fn audit_log() {
+    setup_test();
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    let mut header = reserved_state.genesis_info.header.clone();
+    let mut commits = vec![Commit::Block(header.clone())];
+    for height in 1..=3 {
+        let agenda = Agenda {
+            height,
+            author: reserved_state.members[0].name.clone(),
+            timestamp: height as Timestamp * 10,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            description: None,
+            deadline: None,
+        };
+        let proof = keys[..3]
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+            .collect();
+        commits.push(Commit::Agenda(agenda.clone()));
+        commits.push(Commit::AgendaProof(AgendaProof {
+            height,
+            agenda_hash: agenda.to_hash256(),
+            proof,
+            timestamp: height as Timestamp * 10 + 5,
+        }));
+        header = BlockHeader {
+            previous_hash: header.to_hash256(),
+            height,
+            timestamp: height as Timestamp * 10 + 7,
+            ..header
+        };
+        commits.push(Commit::Block(header.clone()));
+    }
+    let history = audit::collect_audit_entries(&commits, &reserved_state).unwrap();
+    assert_eq!(
+        history.iter().map(|entry| entry.height).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(
+        history[0].votes[0].member.as_ref(),
+        Some(&reserved_state.members[0].name)
+    );
+
+    let exporter = &keys[3].1;
+    let log = Governance::export_audit_log(2..=3, &commits, &reserved_state, exporter).unwrap();
+    assert_eq!(log.entries, history[1..].to_vec());
+    assert_eq!(
+        audit::AuditLog::from_jsonl(&log.to_jsonl().unwrap()).unwrap(),
+        log
+    );
+    assert_eq!(log.to_csv().lines().count(), 2 + 2 * 3);
+    log.verify(&history).unwrap();
+    assert!(Governance::export_audit_log(2..=4, &commits, &reserved_state, exporter).is_err());
+
+    // An entry left out, even if resealed.
+    let mut incomplete = log.clone();
+    incomplete.entries.remove(0);
+    assert!(incomplete.verify(&history).is_err());
+    let resealed = audit::AuditLog::seal(3..=3, incomplete.entries, exporter).unwrap();
+    resealed.verify(&history).unwrap();
+    let mut widened = resealed.clone();
+    widened.seal.from = 2;
+    assert!(widened.verify(&history).is_err());
+
+    // A vote left out, even if resealed.
+    let mut entries = log.entries.clone();
+    entries[0].votes.pop();
+    let forged = audit::AuditLog::seal(2..=3, entries, exporter).unwrap();
+    forged.verify_signatures().unwrap();
+    assert!(forged.verify(&history).is_err());
+}
+
+// Gathers the messages of all the nodes into the first one.
+async fn gather(nodes: &[Governance]) {
+    let mut messages = Vec::new();
+    for node in &nodes[1..] {
+        messages.extend(node.get_dms().read().await.read_messages().await.unwrap());
+    }
+    let dms = nodes[0].get_dms();
+    for message in messages {
+        for proof in message.committers {
+            dms.write()
+                .await
+                .add_committed_message(&message.message, proof)
+                .await
+                .unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn commit_reveal() {
+    setup_test();
+
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    let mut nodes = Vec::new();
+    for (_, private_key) in &keys {
+        nodes.push(
+            Governance::new(Arc::new(RwLock::new(
+                create_test_dms(
+                    "governance-commit-reveal".to_string(),
+                    keys.iter()
+                        .map(|(public_key, _)| public_key.clone())
+                        .collect(),
+                    private_key.clone(),
+                )
+                .await,
+            )))
+            .await
+            .unwrap(),
+        );
+    }
+    let agenda_hash = Hash256::hash("agenda");
+    let mode = VotingMode::CommitReveal { reveal_after: 100 };
+    let salt = |i: usize| Governance::derive_vote_salt(&keys[i].1, agenda_hash);
+
+    for (i, approve) in [true, true, false].into_iter().enumerate() {
+        nodes[i]
+            .commit_vote(agenda_hash, approve, salt(i))
+            .await
+            .unwrap();
+    }
+    // An open vote doesn't count in the commit-reveal mode.
+    nodes[3].vote(agenda_hash).await.unwrap();
+    gather(&nodes).await;
+    let status = nodes[0].read().await.unwrap();
+    let tally = status
+        .tally_in_mode(agenda_hash, &reserved_state, mode, 200)
+        .unwrap();
+    assert_eq!(
+        tally
+            .votes
+            .iter()
+            .map(|vote| vote.status)
+            .collect::<Vec<_>>(),
+        vec![
+            VoteStatus::Committed,
+            VoteStatus::Committed,
+            VoteStatus::Committed,
+            VoteStatus::Absent
+        ]
+    );
+    assert_eq!(tally.absent_power, 4);
+
+    for (i, approve) in [true, true, false].into_iter().enumerate() {
+        nodes[i]
+            .reveal_vote(agenda_hash, approve, salt(i))
+            .await
+            .unwrap();
+    }
+    // A reveal that doesn't match the commitment doesn't count.
+    nodes[2]
+        .reveal_vote(agenda_hash, true, Hash256::hash("guess"))
+        .await
+        .unwrap();
+    gather(&nodes).await;
+    let status = nodes[0].read().await.unwrap();
+
+    // Nothing counts before the deadline.
+    let tally = status
+        .tally_in_mode(agenda_hash, &reserved_state, mode, 99)
+        .unwrap();
+    assert_eq!((tally.for_power, tally.against_power), (0, 0));
+    assert!(status.approvals(agenda_hash, mode, 99).is_empty());
+
+    let tally = status
+        .tally_in_mode(agenda_hash, &reserved_state, mode, 100)
+        .unwrap();
+    assert_eq!(
+        (tally.for_power, tally.against_power, tally.absent_power),
+        (2, 1, 1)
+    );
+    assert!(!tally.threshold_met);
+    let approvals = status.approvals(agenda_hash, mode, 100);
+    assert_eq!(approvals.len(), 2);
+    for (public_key, signature) in approvals {
+        signature.verify(agenda_hash, &public_key).unwrap();
+    }
+    // The open votes are still counted as usual in the open mode.
+    assert_eq!(
+        status
+            .tally(agenda_hash, &reserved_state)
+            .unwrap()
+            .for_power,
+        4
+    );
+}
+
+#[test]
+fn vote_encoding() {
+    let agenda_hash = Hash256::hash("agenda");
+    let reveal = VoteReveal {
+        agenda_hash,
+        approve: true,
+        salt: Hash256::hash("salt"),
+    };
+    let votes = vec![
+        Vote::Open(agenda_hash),
+        Vote::Commitment(VoteCommitment {
+            agenda_hash,
+            commitment: reveal.commitment(),
+        }),
+        Vote::Reveal(reveal),
+        Vote::Poll(poll::Poll {
+            title: "poll".to_owned(),
+            description: String::new(),
+            options: vec!["yes".to_owned(), "no".to_owned()],
+            deadline: 100,
+            timestamp: 0,
+        }),
+        Vote::PollVote(poll::PollVote {
+            poll_hash: Hash256::hash("poll"),
+            option: 1,
+            timestamp: 0,
+        }),
+        Vote::Veto(AgendaVeto {
+            agenda_hash,
+            expires_at: 100,
+        }),
+    ];
+    for vote in votes {
+        // The packets are binary, and the storage is in JSON.
+        let decoded = serde_spb::from_slice::<Vote>(&serde_spb::to_vec(&vote).unwrap()).unwrap();
+        assert_eq!(decoded.to_hash256(), vote.to_hash256());
+        let decoded = serde_spb::from_str::<Vote>(&serde_spb::to_string(&vote).unwrap()).unwrap();
+        assert_eq!(decoded.to_hash256(), vote.to_hash256());
+    }
+    // An open vote in JSON is the bare agenda hash.
+    assert_eq!(
+        serde_spb::to_string(&Vote::Open(agenda_hash)).unwrap(),
+        serde_spb::to_string(&agenda_hash).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn poll() {
+    setup_test();
+
+    let (reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    let mut nodes = Vec::new();
+    for (_, private_key) in &keys {
+        nodes.push(
+            Governance::new(Arc::new(RwLock::new(
+                create_test_dms(
+                    "governance-poll".to_string(),
+                    keys.iter()
+                        .map(|(public_key, _)| public_key.clone())
+                        .collect(),
+                    private_key.clone(),
+                )
+                .await,
+            )))
+            .await
+            .unwrap(),
+        );
+    }
+    let mut poll = poll::Poll {
+        title: "Where to hold the next meetup?".to_owned(),
+        description: String::new(),
+        options: vec!["Seoul".to_owned(), "Pohang".to_owned()],
+        deadline: 100,
+        timestamp: 0,
+    };
+    poll.options.push("Seoul".to_owned());
+    assert!(nodes[0].create_poll(poll.clone()).await.is_err());
+    poll.options[2] = "Online".to_owned();
+    let poll_hash = nodes[0].create_poll(poll.clone()).await.unwrap();
+    assert_eq!(poll_hash, poll.to_hash256());
+    assert_eq!(
+        poll::poll_references(&format!("Some transaction\n\n{}", poll.reference())).unwrap(),
+        vec![poll_hash]
+    );
+
+    nodes[0].vote_poll(poll_hash, 0, 10).await.unwrap();
+    nodes[1].vote_poll(poll_hash, 0, 50).await.unwrap();
+    // A vote after the deadline doesn't count.
+    nodes[2].vote_poll(poll_hash, 1, 150).await.unwrap();
+    // Nor does one for more than one option.
+    nodes[3].vote_poll(poll_hash, 1, 10).await.unwrap();
+    nodes[3].vote_poll(poll_hash, 2, 20).await.unwrap();
+    gather(&nodes).await;
+
+    let status = nodes[0].read().await.unwrap();
+    assert_eq!(status.polls.get(&poll_hash), Some(&poll));
+    let tally = status.tally_poll(poll_hash, &reserved_state, 50).unwrap();
+    assert!(!tally.closed);
+    assert_eq!(
+        tally
+            .options
+            .iter()
+            .map(|x| x.voting_power)
+            .collect::<Vec<_>>(),
+        vec![2, 0, 0]
+    );
+    assert_eq!(
+        tally.options[0].voters,
+        vec!["member-0000".to_owned(), "member-0001".to_owned()]
+    );
+    assert_eq!(tally.absent_power, 2);
+    assert_eq!(tally.conflicting_voters, vec!["member-0003".to_owned()]);
+    assert_eq!(tally.leading_option().unwrap().option, "Seoul");
+    assert!(
+        status
+            .tally_poll(poll_hash, &reserved_state, 101)
+            .unwrap()
+            .closed
+    );
+    assert!(status
+        .tally_poll(Hash256::hash("unknown"), &reserved_state, 0)
+        .is_err());
+}
+
+#[tokio::test]
+async fn veto() {
+    setup_test();
+
+    let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+    reserved_state.veto_holders = vec!["member-0003".to_owned()];
+    let mut nodes = Vec::new();
+    for (_, private_key) in &keys {
+        nodes.push(
+            Governance::new(Arc::new(RwLock::new(
+                create_test_dms(
+                    "governance-veto".to_string(),
+                    dms_members(&reserved_state).unwrap(),
+                    private_key.clone(),
+                )
+                .await,
+            )))
+            .await
+            .unwrap(),
+        );
+    }
+    let agenda_hash = Hash256::hash("agenda");
+    for node in nodes.iter_mut().take(3) {
+        node.vote(agenda_hash).await.unwrap();
+    }
+    // Not a veto holder.
+    nodes[0].veto(agenda_hash, 100).await.unwrap();
+    nodes[3].veto(agenda_hash, 100).await.unwrap();
+    gather(&nodes).await;
+
+    let status = nodes[0].read().await.unwrap();
+    assert!(
+        status
+            .tally(agenda_hash, &reserved_state)
+            .unwrap()
+            .threshold_met
+    );
+    assert_eq!(status.vetoes[&agenda_hash].len(), 2);
+    assert_eq!(
+        status.vetoers(agenda_hash, &reserved_state, 99),
+        vec!["member-0003".to_owned()]
+    );
+    // Expired.
+    assert!(status.vetoers(agenda_hash, &reserved_state, 100).is_empty());
+    assert!(status
+        .vetoers(Hash256::hash("other"), &reserved_state, 0)
+        .is_empty());
+}
@@ -0,0 +1,226 @@
+//! The addresses of the peers.
+use super::*;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// The address of a peer: an IPv4 or IPv6 socket address, or a DNS hostname with the port.
+///
+/// It's written as `1.2.3.4:1155`, `[::1]:1155` or `node.example.com:1155`.
+///
+/// A hostname is resolved on every dial rather than once, so a peer whose address has changed
+/// is reached again as soon as its record is updated, retried on the next failure.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    Ip(SocketAddr),
+    Dns { host: String, port: u16 },
+}
+
+impl PeerAddress {
+    pub fn port(&self) -> u16 {
+        match self {
+            PeerAddress::Ip(address) => address.port(),
+            PeerAddress::Dns { port, .. } => *port,
+        }
+    }
+
+    pub fn set_port(&mut self, new_port: u16) {
+        match self {
+            PeerAddress::Ip(address) => address.set_port(new_port),
+            PeerAddress::Dns { port, .. } => *port = new_port,
+        }
+    }
+
+    /// The host part to put in a URL, with an IPv6 address in brackets.
+    pub fn host(&self) -> String {
+        match self {
+            PeerAddress::Ip(SocketAddr::V4(address)) => address.ip().to_string(),
+            PeerAddress::Ip(SocketAddr::V6(address)) => format!("[{}]", address.ip()),
+            PeerAddress::Dns { host, .. } => host.clone(),
+        }
+    }
+
+    /// Resolves the address, looking up the hostname.
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>, Error> {
+        match self {
+            PeerAddress::Ip(address) => Ok(vec![*address]),
+            PeerAddress::Dns { host, port } => {
+                let addresses = tokio::net::lookup_host((host.as_str(), *port))
+                    .await
+                    .map_err(|e| eyre::eyre!("failed to resolve {host}: {e}"))?
+                    .collect::<Vec<_>>();
+                if addresses.is_empty() {
+                    return Err(eyre::eyre!("{host} has no address"));
+                }
+                Ok(addresses)
+            }
+        }
+    }
+
+    /// Checks that the address can be dialed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port() == 0 {
+            return Err(format!("`{self}` has no port"));
+        }
+        if let PeerAddress::Ip(address) = self {
+            if address.ip().is_unspecified() {
+                return Err(format!("`{self}` is not the address of a host"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        PeerAddress::Ip(address)
+    }
+}
+
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddress::Ip(address) => write!(f, "{address}"),
+            PeerAddress::Dns { host, port } => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
+impl FromStr for PeerAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = s.parse::<SocketAddr>() {
+            return Ok(PeerAddress::Ip(address));
+        }
+        let (host, port) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("`{s}` has no port"))?;
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| format!("`{s}` has an invalid port"))?;
+        if !is_valid_hostname(host) {
+            return Err(format!("`{host}` is neither an IP address nor a hostname"));
+        }
+        Ok(PeerAddress::Dns {
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+}
+
+fn is_valid_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        // Otherwise it's a malformed IPv4 address.
+        && !host.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+// Written as a string, which is also how the IPv4 socket addresses were written before.
+impl Serialize for PeerAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_core::serde_spb;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("127.0.0.1:1155", "127.0.0.1", 1155),
+            ("[::1]:1155", "[::1]", 1155),
+            ("[2001:db8::1]:80", "[2001:db8::1]", 80),
+            ("Node-1.Example.com:1155", "node-1.example.com", 1155),
+            ("localhost:1", "localhost", 1),
+        ];
+        for (s, host, port) in cases {
+            let address = s.parse::<PeerAddress>().unwrap();
+            assert_eq!(address.host(), host);
+            assert_eq!(address.port(), port);
+            assert_eq!(address.to_string().parse::<PeerAddress>().unwrap(), address);
+        }
+        for s in [
+            "127.0.0.1",
+            "::1:1155",
+            "1.2.3:1155",
+            "-node.example.com:1155",
+            "node..example.com:1155",
+            "node_1:1155",
+            "node:port",
+            ":1155",
+        ] {
+            assert!(s.parse::<PeerAddress>().is_err(), "{s}");
+        }
+        assert!("0.0.0.0:1155"
+            .parse::<PeerAddress>()
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!("[::]:1155"
+            .parse::<PeerAddress>()
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!("node:0".parse::<PeerAddress>().unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn serde_compatible() {
+        let legacy =
+            serde_spb::to_string(&"1.2.3.4:5".parse::<std::net::SocketAddrV4>().unwrap()).unwrap();
+        let address = serde_spb::from_str::<PeerAddress>(&legacy).unwrap();
+        assert_eq!(address, PeerAddress::Ip("1.2.3.4:5".parse().unwrap()));
+        for s in ["[::1]:5", "node.example.com:5"] {
+            let address = s.parse::<PeerAddress>().unwrap();
+            assert_eq!(
+                serde_spb::from_str::<PeerAddress>(&serde_spb::to_string(&address).unwrap())
+                    .unwrap(),
+                address
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve() {
+        let mut families = "localhost:1155"
+            .parse::<PeerAddress>()
+            .unwrap()
+            .resolve()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|address| address.ip().is_loopback())
+            .collect::<Vec<_>>();
+        families.dedup();
+        assert_eq!(families, vec![true]);
+        assert_eq!(
+            "[::1]:1155"
+                .parse::<PeerAddress>()
+                .unwrap()
+                .resolve()
+                .await
+                .unwrap(),
+            vec!["[::1]:1155".parse().unwrap()]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! The delivery acknowledgments of the messages, to tell how far a message has propagated.
+//!
+//! After broadcasting, the client asks each peer to acknowledge the messages it holds.
+//! The peer signs the hash of each message together with the DMS key,
+//! so that the acknowledgment can't be reused for another network.
+//!
+//! Like the statistics, the acknowledgments are kept only in memory.
+use super::*;
+use simperby_core::utils::get_timestamp;
+
+/// The data that a peer signs to acknowledge a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AckTarget {
+    pub dms_key: DmsKey,
+    pub message_hash: Hash256,
+}
+
+impl ToHash256 for AckTarget {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// An acknowledgment that the signer holds the message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryAck {
+    pub message_hash: Hash256,
+    pub signature: TypedSignature<AckTarget>,
+}
+
+/// How far a message has propagated, by the acknowledgments of the peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessagePropagation {
+    pub dms_key: DmsKey,
+    pub message_hash: Hash256,
+    /// The members who have acknowledged the message, with the local time of the first one.
+    pub acknowledged: Vec<(PublicKey, Timestamp)>,
+    /// The other members, except this node.
+    pub pending: Vec<PublicKey>,
+}
+
+impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
+    /// Returns the propagation of the message, or `None` if it's not in this DMS.
+    pub async fn get_message_propagation(
+        &self,
+        message_hash: Hash256,
+    ) -> Result<Option<MessagePropagation>, Error> {
+        if self.read_raw_message(message_hash).await?.is_none() {
+            return Ok(None);
+        }
+        let acknowledged = self
+            .acknowledgments
+            .get(&message_hash)
+            .cloned()
+            .unwrap_or_default();
+        let this = self.private_key.as_ref().map(PrivateKey::public_key);
+        Ok(Some(MessagePropagation {
+            dms_key: self.config.dms_key.clone(),
+            message_hash,
+            pending: self
+                .config
+                .members
+                .iter()
+                .filter(|member| {
+                    this.as_ref() != Some(*member) && !acknowledged.contains_key(*member)
+                })
+                .cloned()
+                .collect(),
+            acknowledged: acknowledged.into_iter().collect(),
+        }))
+    }
+
+    /// Signs the acknowledgments of the messages of the given hashes that this DMS holds.
+    pub(super) async fn acknowledge(
+        &self,
+        message_hashes: &[Hash256],
+    ) -> Result<Vec<DeliveryAck>, Error> {
+        // A read-only one isn't a member to acknowledge.
+        let Some(private_key) = &self.private_key else {
+            return Ok(Vec::new());
+        };
+        let mut acks = Vec::new();
+        for message_hash in message_hashes {
+            if self.read_raw_message(*message_hash).await?.is_none() {
+                continue;
+            }
+            let target = AckTarget {
+                dms_key: self.config.dms_key.clone(),
+                message_hash: *message_hash,
+            };
+            acks.push(DeliveryAck {
+                message_hash: *message_hash,
+                signature: TypedSignature::sign(&target, private_key)?,
+            });
+        }
+        Ok(acks)
+    }
+
+    /// Returns the hashes of the given messages that the peer hasn't acknowledged yet.
+    pub(super) fn unacknowledged(
+        &self,
+        peer: &PublicKey,
+        message_hashes: &[Hash256],
+    ) -> Vec<Hash256> {
+        message_hashes
+            .iter()
+            .filter(|message_hash| {
+                !self
+                    .acknowledgments
+                    .get(message_hash)
+                    .is_some_and(|acknowledged| acknowledged.contains_key(peer))
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Records the acknowledgments returned by the peer, dropping those not signed by it.
+    pub(super) fn record_acks(&mut self, peer: &PublicKey, acks: Vec<DeliveryAck>) {
+        let now = get_timestamp();
+        for ack in acks {
+            let target = AckTarget {
+                dms_key: self.config.dms_key.clone(),
+                message_hash: ack.message_hash,
+            };
+            if ack.signature.signer() != peer || ack.signature.verify(&target).is_err() {
+                log::warn!("peer {peer} returned an invalid acknowledgment");
+                continue;
+            }
+            self.acknowledgments
+                .entry(ack.message_hash)
+                .or_default()
+                .entry(peer.clone())
+                .or_insert(now);
+        }
+    }
+}
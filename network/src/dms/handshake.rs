@@ -0,0 +1,188 @@
+//! A handshake with a peer, to check its reachability and that it holds the key it is known by.
+//!
+//! The client sends a fresh challenge, and the peer signs it
+//! together with the DMS key so that the signature can't be reused for another network.
+//!
+//! The peers also exchange their [`ChainId`]s, so that a peer of another chain
+//! listening on the same ports (e.g., a testnet) is rejected before any packet is exchanged.
+use super::*;
+use simperby_core::utils::get_timestamp;
+
+/// The identity of the chain that a DMS belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChainId {
+    pub chain_name: String,
+    /// The hash of the genesis block header.
+    pub genesis_hash: Hash256,
+}
+
+/// The error for a peer on another chain.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error(
+    "the peer is on chain `{}` (genesis {}), not `{}` (genesis {})",
+    remote.chain_name,
+    remote.genesis_hash,
+    local.chain_name,
+    local.genesis_hash
+)]
+pub struct ChainMismatchError {
+    pub local: ChainId,
+    pub remote: ChainId,
+}
+
+/// A peer found to be on another chain, which has been rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainMismatch {
+    pub peer: PublicKey,
+    /// The chain that the peer reported most recently.
+    pub remote: ChainId,
+    /// The number of the rejected connections.
+    pub count: u64,
+    pub last_detected_at: Timestamp,
+}
+
+/// Checks that the peer of the given protocol version is on the local chain, returning its chain.
+///
+/// The chain can't be checked if either the local one is unknown
+/// or the peer is older than version 6 (or doesn't know its own).
+pub(super) async fn check_chain(
+    stub: &DistributedMessageSetRpcInterfaceStub,
+    protocol_version: u32,
+    local: Option<&ChainId>,
+) -> Result<Option<ChainId>, Error> {
+    if protocol_version < 6 {
+        return Ok(None);
+    }
+    let remote = stub
+        .chain_id()
+        .await
+        .map_err(|e| eyre!(e))?
+        .map_err(|e| eyre!(e))?;
+    if let (Some(local), Some(remote)) = (local, &remote) {
+        if local != remote {
+            return Err(ChainMismatchError {
+                local: local.clone(),
+                remote: remote.clone(),
+            }
+            .into());
+        }
+    }
+    Ok(remote)
+}
+
+/// The data that a peer signs in the handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandshakeTarget {
+    pub dms_key: DmsKey,
+    pub challenge: Hash256,
+}
+
+impl ToHash256 for HandshakeTarget {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// The result of a handshake with a peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandshakeReport {
+    pub protocol_version: u32,
+    /// The round-trip time of the first request, in milliseconds.
+    pub latency_ms: u64,
+    /// Whether the peer proved to hold the private key of [`Peer::public_key`].
+    ///
+    /// Always `false` for the peers older than version 3, which can't sign the challenge.
+    pub key_verified: bool,
+    /// The chain that the peer reported, if it's of version 6 or later.
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+}
+
+/// Performs a handshake with the peer on the DMS of the given key, over the transport.
+///
+/// It fails if the peer is unreachable or on a chain other than `chain_id`
+/// (with [`ChainMismatchError`]); a wrong key is reported in the result,
+/// except over QUIC, where it fails the connection.
+pub async fn handshake(
+    peer: &Peer,
+    dms_key: &DmsKey,
+    chain_id: Option<&ChainId>,
+    transport: Transport,
+) -> Result<HandshakeReport, Error> {
+    let port_key = format!("dms-{dms_key}");
+    let (url, _) = connect(peer, &port_key, transport).await?;
+    let stub = create_stub(url, transport, &peer.public_key);
+    let started = std::time::Instant::now();
+    let protocol_version = match stub.protocol_version().await {
+        Ok(Ok(version)) => version,
+        Ok(Err(e)) => return Err(eyre!(e)),
+        // Peers that don't know `protocol_version()` are of version 1.
+        Err(_) => 1,
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    if protocol_version < 3 {
+        return Ok(HandshakeReport {
+            protocol_version,
+            latency_ms,
+            key_verified: false,
+            chain_id: None,
+        });
+    }
+    let chain_id = check_chain(&stub, protocol_version, chain_id).await?;
+    let target = HandshakeTarget {
+        dms_key: dms_key.clone(),
+        challenge: Hash256::hash(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("the clock is before the epoch")
+                .as_nanos()
+                .to_be_bytes(),
+        ),
+    };
+    let signature = stub
+        .identify(target.challenge)
+        .await
+        .map_err(|e| eyre!(e))?
+        .map_err(|e| eyre!(e))?;
+    Ok(HandshakeReport {
+        protocol_version,
+        latency_ms,
+        key_verified: signature.signer() == &peer.public_key && signature.verify(&target).is_ok(),
+        chain_id,
+    })
+}
+
+impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
+    /// Sets the chain of this DMS, to reject the peers of the other chains
+    /// and to report to the peers.
+    pub fn set_chain_id(&mut self, chain_id: Option<ChainId>) {
+        self.chain_id = chain_id;
+    }
+
+    pub fn get_chain_id(&self) -> Option<ChainId> {
+        self.chain_id.clone()
+    }
+
+    /// Returns the peers that have been rejected as on another chain since this instance was created.
+    pub fn get_chain_mismatches(&self) -> Vec<ChainMismatch> {
+        let mut mismatches = self.chain_mismatches.values().cloned().collect::<Vec<_>>();
+        mismatches.sort_by_key(|mismatch| mismatch.last_detected_at);
+        mismatches
+    }
+
+    pub(super) fn record_chain_mismatch(&mut self, peer: &PublicKey, error: &ChainMismatchError) {
+        log::warn!("rejected peer {peer} on {}: {error}", self.config.dms_key);
+        let mismatch = self
+            .chain_mismatches
+            .entry(peer.clone())
+            .or_insert_with(|| ChainMismatch {
+                peer: peer.clone(),
+                remote: error.remote.clone(),
+                count: 0,
+                last_detected_at: 0,
+            });
+        mismatch.remote = error.remote.clone();
+        mismatch.count += 1;
+        mismatch.last_detected_at = get_timestamp();
+    }
+}
@@ -7,6 +7,11 @@ pub trait DmsMessage: Send + Sync + 'static + ToHash256 + Serialize + Deserializ
     /// Checks if the message is valid.
     fn check(&self) -> Result<(), Error>;
 
+    /// The priority class of the message, which decides the order of the network transfer.
+    fn priority(&self) -> MessagePriority {
+        MessagePriority::Misc
+    }
+
     /// Defines how to commit a message, by cryptographically signing it.
     ///
     /// In case that the message can't be guaranteed to be unique among other protocols,
@@ -76,6 +81,50 @@ impl ToHash256 for Packet {
     }
 }
 
+/// The packets of the same message, sent as a single payload.
+///
+/// When the members vote for the same thing (e.g., prevote on the same block),
+/// their messages are identical and differ only in the commitments;
+/// a batch carries the message once with all the commitments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketBatch {
+    /// The original message data encoded in `serde_spb`.
+    pub message: Vec<u8>,
+    pub commitments: Vec<MessageCommitmentProof>,
+}
+
+impl PacketBatch {
+    /// Groups the packets by their message, keeping the order of the first packet of each.
+    pub fn from_packets(packets: Vec<Packet>) -> Vec<Self> {
+        let mut batches = Vec::<Self>::new();
+        let mut indices = std::collections::HashMap::<Vec<u8>, usize>::new();
+        for packet in packets {
+            match indices.get(&packet.message) {
+                Some(&index) => batches[index].commitments.push(packet.commitment),
+                None => {
+                    indices.insert(packet.message.clone(), batches.len());
+                    batches.push(Self {
+                        message: packet.message,
+                        commitments: vec![packet.commitment],
+                    });
+                }
+            }
+        }
+        batches
+    }
+
+    pub fn into_packets(self) -> Vec<Packet> {
+        let message = self.message;
+        self.commitments
+            .into_iter()
+            .map(|commitment| Packet {
+                message: message.clone(),
+                commitment,
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MessageMetadata {
     pub message_hash: Hash256,
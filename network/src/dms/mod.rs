@@ -1,6 +1,14 @@
+mod ack;
+mod handshake;
 mod messages;
+mod priority;
+mod quic;
+mod reconciliation;
+mod relay;
 mod rpc;
 pub mod server;
+mod snapshot;
+mod tap;
 #[cfg(test)]
 mod tests;
 
@@ -10,21 +18,42 @@ use async_trait::async_trait;
 use eyre::eyre;
 use futures::future::join;
 use futures::prelude::*;
+use handshake::check_chain;
 use messages::*;
+use priority::*;
+use quic::*;
+use reconciliation::*;
+use relay::*;
 use rpc::*;
 use serde_tc::http::*;
 use serde_tc::{serde_tc_full, StubCall};
 use simperby_core::*;
+use snapshot::*;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
+use tap::Origin;
 use tokio::sync::RwLock;
 
 const STATE_FILE_PATH: &str = "state.json";
 
+/// The number of the unauthorized packets that a peer may serve before it stops being dialed.
+const MAX_UNAUTHORIZED_PACKETS: u64 = 16;
+
 pub type Error = eyre::Error;
 
-pub use messages::{DmsKey, DmsMessage, Message, MessageCommitmentProof};
+pub use ack::{AckTarget, DeliveryAck, MessagePropagation};
+pub use handshake::{
+    handshake, ChainId, ChainMismatch, ChainMismatchError, HandshakeReport, HandshakeTarget,
+};
+pub use messages::{DmsKey, DmsMessage, Message, MessageCommitmentProof, PacketBatch};
+pub use priority::{MessagePriority, PriorityWeights};
+pub use quic::Transport;
+pub use reconciliation::{BucketDigests, SyncStatistics};
+pub use relay::RelayConfig;
 pub use server::*;
+pub use snapshot::SnapshotManifest;
+pub use tap::{Direction, MessageTap, PayloadRedaction, TapConfig, TapReader, TapRecord, Verdict};
 
 #[derive(thiserror::Error, Debug)]
 #[error("dms integrity broken: {msg}")]
@@ -41,13 +70,31 @@ impl IntegrityError {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     pub dms_key: String,
+    /// The keys authorized to commit messages, e.g. the validators of the height for the consensus.
+    ///
+    /// The packets committed by the others are dropped, penalizing the peer that served them.
     pub members: Vec<PublicKey>,
+    /// The weights of the priority classes for the network transfer.
+    #[serde(default)]
+    pub priority_weights: PriorityWeights,
 }
 
 pub struct DistributedMessageSet<S, M> {
     storage: Arc<RwLock<S>>,
     config: Config,
-    private_key: PrivateKey,
+    /// `None` for a read-only one (see [`DistributedMessageSet::new_read_only`]).
+    private_key: Option<PrivateKey>,
+    statistics: SyncStatistics,
+    /// The number of the unauthorized packets served by each peer.
+    penalties: HashMap<PublicKey, u64>,
+    tap: Option<MessageTap>,
+    /// The chain to check the peers against (see [`handshake`]).
+    chain_id: Option<ChainId>,
+    chain_mismatches: HashMap<PublicKey, ChainMismatch>,
+    /// The members banned by the governance, whose packets are dropped.
+    banned_members: HashSet<PublicKey>,
+    /// The peers that have acknowledged each message, with the local time (see [`ack`]).
+    acknowledgments: HashMap<Hash256, std::collections::BTreeMap<PublicKey, Timestamp>>,
     _marker: std::marker::PhantomData<M>,
 }
 
@@ -72,8 +119,6 @@ impl<S, M> std::fmt::Debug for DistributedMessageSet<S, M> {
 /// - If the given directory is locked (possibly by another instance of `DistributedMessageSet`),
 ///   it will `await` until the lock is released.
 /// - It takes 'Arc<RwLock<Self>>' instead of `self` if network clients are used.
-///
-/// TODO: add read only type that does not require the private key.
 impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
     /// Creates a message set instance.
     ///
@@ -83,15 +128,26 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
     /// It clears all and initializes a new one if not.
     ///
     /// - `private_key`: The private key for signing messages.
-    pub async fn new(
-        mut storage: S,
-        config: Config,
-        private_key: PrivateKey,
-    ) -> Result<Self, Error> {
+    pub async fn new(storage: S, config: Config, private_key: PrivateKey) -> Result<Self, Error> {
         if !config.members.contains(&private_key.public_key()) {
             return Err(eyre!("given private key is not in the member list"));
         }
+        Self::open(storage, config, Some(private_key)).await
+    }
 
+    /// Creates a message set instance that only syncs and serves the messages of the members,
+    /// without a private key.
+    ///
+    /// It can't commit messages, acknowledge them or identify itself in the handshakes.
+    pub async fn new_read_only(storage: S, config: Config) -> Result<Self, Error> {
+        Self::open(storage, config, None).await
+    }
+
+    async fn open(
+        mut storage: S,
+        config: Config,
+        private_key: Option<PrivateKey>,
+    ) -> Result<Self, Error> {
         match storage.read_file(STATE_FILE_PATH).await {
             Ok(x) => {
                 let config2: Config = serde_spb::from_str(&x)?;
@@ -118,6 +174,13 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
             storage: Arc::new(RwLock::new(storage)),
             config,
             private_key,
+            statistics: SyncStatistics::default(),
+            penalties: HashMap::new(),
+            tap: None,
+            chain_id: None,
+            chain_mismatches: HashMap::new(),
+            banned_members: HashSet::new(),
+            acknowledgments: HashMap::new(),
             _marker: std::marker::PhantomData,
         })
     }
@@ -136,7 +199,72 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
         self.config.clone()
     }
 
+    /// Returns the statistics of the network transfer since this instance was created.
+    pub fn get_sync_statistics(&self) -> SyncStatistics {
+        self.statistics.clone()
+    }
+
+    /// Returns the number of the unauthorized packets served by each peer.
+    ///
+    /// The peers that have served too many of them are no longer fetched from or broadcast to.
+    pub fn get_penalties(&self) -> HashMap<PublicKey, u64> {
+        self.penalties.clone()
+    }
+
+    /// Records every packet sent or received from now on to the tap (see [`tap`]),
+    /// or stops recording if `None`.
+    pub fn set_tap(&mut self, tap: Option<MessageTap>) {
+        self.tap = tap;
+    }
+
+    /// Records the packets sent to the peer, or served to an unknown one.
+    fn tap_sent(&self, peer: Option<&PublicKey>, packets: &[Packet], verdict: Verdict) {
+        if let Some(tap) = &self.tap {
+            let records = packets
+                .iter()
+                .filter_map(|packet| {
+                    let message = serde_spb::from_slice::<M>(&packet.message).ok()?;
+                    Some(TapRecord::new(
+                        Direction::Outbound,
+                        peer,
+                        &self.config.dms_key,
+                        &message,
+                        &packet.commitment.committer,
+                        verdict.clone(),
+                        tap.redaction(),
+                    ))
+                })
+                .collect::<Vec<_>>();
+            tap.record(&records);
+        }
+    }
+
+    fn penalize(&mut self, peer: &PublicKey, unauthorized_packets: u64) {
+        if unauthorized_packets > 0 {
+            log::warn!("peer {peer} served {unauthorized_packets} unauthorized packets");
+            *self.penalties.entry(peer.clone()).or_default() += unauthorized_packets;
+        }
+    }
+
+    /// Sets the members banned by the governance (see [`ReservedState::banned_members`]).
+    ///
+    /// They are no longer fetched from or broadcast to, and the packets they commit are dropped
+    /// wherever they come from, without penalizing the peer that served them.
+    pub fn set_banned_members(&mut self, members: Vec<PublicKey>) {
+        self.banned_members = members.into_iter().collect();
+    }
+
+    pub fn get_banned_members(&self) -> Vec<PublicKey> {
+        self.banned_members.iter().cloned().collect()
+    }
+
+    fn is_banned(&self, peer: &PublicKey) -> bool {
+        self.banned_members.contains(peer)
+            || self.penalties.get(peer).copied().unwrap_or_default() >= MAX_UNAUTHORIZED_PACKETS
+    }
+
     pub async fn clear(&mut self) -> Result<(), Error> {
+        self.acknowledgments.clear();
         self.storage.write().await.remove_all_files().await?;
         self.storage
             .write()
@@ -172,11 +300,54 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
     /// Signs the given message and adds it to the storage.
     pub async fn commit_message(&mut self, message: &M) -> Result<(), Error> {
         message.check()?;
-        let commitment = message.commit(&self.config.dms_key, &self.private_key)?;
+        let private_key = self
+            .private_key
+            .as_ref()
+            .ok_or_else(|| eyre!("can't commit messages to a read-only DMS"))?;
+        let commitment = message.commit(&self.config.dms_key, private_key)?;
         self.store_message(message, commitment).await?;
         Ok(())
     }
 
+    /// Adds a message that has been committed elsewhere (e.g., signed with an offline key).
+    ///
+    /// The commitment is verified in the same way as the one received from the network.
+    pub async fn add_committed_message(
+        &mut self,
+        message: &M,
+        commitment: MessageCommitmentProof,
+    ) -> Result<(), Error> {
+        self.add_committed_messages(message, vec![commitment]).await
+    }
+
+    /// Adds the commitments of a message that have been made elsewhere, e.g. the votes
+    /// of the members on the same block delivered together.
+    ///
+    /// The known commitments are skipped, and the others are verified in parallel.
+    pub async fn add_committed_messages(
+        &mut self,
+        message: &M,
+        commitments: Vec<MessageCommitmentProof>,
+    ) -> Result<(), Error> {
+        message.check()?;
+        if commitments
+            .iter()
+            .any(|commitment| !self.test_membership(&commitment.committer))
+        {
+            return Err(eyre!("commitment committer is not a member"));
+        }
+        self.receive(
+            std::slice::from_ref(message),
+            commitments
+                .into_iter()
+                .map(|commitment| Ok((0, commitment)))
+                .collect(),
+            None,
+            Origin::Local,
+        )
+        .await
+    }
+
     /// Removes the message from the storage.
     /// If `permanent` is `Some` with the reason, it permanently rejects the message.
     pub async fn remove_message(
@@ -226,114 +397,305 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
     }
 
     async fn read_raw_messages(&self) -> Result<Vec<(M, MessageMetadata)>, Error> {
-        let files = self.storage.read().await.list_files().await?;
-        let tasks = files
-            .iter()
-            .filter(|x| x.starts_with("message-"))
-            .map(|f| async move {
-                self.storage
-                    .read()
-                    .await
-                    .read_file(f)
-                    .await
-                    .map(|message| (message, f.to_owned()))
-            });
-        let messages = future::join_all(tasks)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
-        let tasks = messages.into_iter().map(|(message, file_name)| async move {
-            // TODO: it must be an integrity error if not found
-            self.storage
-                .read()
-                .await
-                .read_file(&format!("metadata-{}", &file_name[8..]))
-                .await
-                .map(|metadata| (metadata, message))
-        });
-        let messages = future::join_all(tasks)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
-
-        let mut result = Vec::new();
-        for (metadata, message) in &messages {
-            let metadata = serde_spb::from_str::<MessageMetadata>(metadata)
-                .map_err(|e| IntegrityError::new(format!("can't decode stored data: {e}")))?;
-            let message = serde_spb::from_str::<M>(message)
-                .map_err(|e| IntegrityError::new(format!("can't decode stored data: {e}")))?;
-            result.push((message, metadata));
-        }
-        Ok(result)
+        read_raw_messages(&*self.storage.read().await).await
     }
 
     fn test_membership(&self, member: &PublicKey) -> bool {
         self.config.members.contains(member)
     }
 
-    async fn receive_packet(&mut self, packet: Packet) -> Result<(), Error> {
-        let message = serde_spb::from_slice::<M>(&packet.message)?;
-        message.verify_commitment(&packet.commitment, &self.config.dms_key)?;
-        if !self.test_membership(&packet.commitment.committer) {
-            return Err(eyre!("commitment committer is not a member"));
+    /// Verifies and stores the received packets in order, stopping at the first invalid one.
+    ///
+    /// If relaying (see [`relay`]), the packets of each committer beyond the limit are dropped.
+    async fn receive_packets(
+        &mut self,
+        packets: Vec<Packet>,
+        limiter: Option<&mut RelayLimiter>,
+        peer: Option<&PublicKey>,
+    ) -> Result<(), Error> {
+        // Decode each message once, however many commitments it comes with.
+        let mut messages = Vec::new();
+        let mut indices = HashMap::<Vec<u8>, usize>::new();
+        let mut commitments = Vec::new();
+        for packet in packets {
+            if let Some(&index) = indices.get(&packet.message) {
+                commitments.push(Ok((index, packet.commitment)));
+                continue;
+            }
+            match serde_spb::from_slice::<M>(&packet.message) {
+                Ok(message) => {
+                    indices.insert(packet.message, messages.len());
+                    commitments.push(Ok((messages.len(), packet.commitment)));
+                    messages.push(message);
+                }
+                Err(e) => {
+                    if let Some(tap) = &self.tap {
+                        tap.record(&[TapRecord::undecodable::<M>(
+                            peer,
+                            &self.config.dms_key,
+                            &packet,
+                            e.to_string(),
+                        )]);
+                    }
+                    commitments.push(Err(e.into()));
+                    break;
+                }
+            }
         }
-        self.store_message(&message, packet.commitment).await?;
-        Ok(())
+        self.receive(&messages, commitments, limiter, Origin::Network(peer))
+            .await
+    }
+
+    /// Verifies and stores the received commitments of the messages in order,
+    /// stopping at the first invalid one.
+    ///
+    /// The known commitments (either stored or repeated in the same payload)
+    /// and those of the non-members are dropped before the verification,
+    /// and the rest are verified in parallel.
+    /// Those of the same message are stored together.
+    async fn receive(
+        &mut self,
+        messages: &[M],
+        commitments: Vec<Result<(usize, MessageCommitmentProof), Error>>,
+        mut limiter: Option<&mut RelayLimiter>,
+        origin: Origin<'_>,
+    ) -> Result<(), Error> {
+        self.statistics.packets_received += commitments.len() as u64;
+        let mut records = Vec::new();
+        let tap = self.tap.clone();
+        let dms_key = self.config.dms_key.clone();
+        let mut record = |index: usize, committer: &PublicKey, verdict: Verdict| {
+            if let (Some(tap), Origin::Network(peer)) = (&tap, origin) {
+                records.push(TapRecord::new(
+                    Direction::Inbound,
+                    peer,
+                    &dms_key,
+                    &messages[index],
+                    committer,
+                    verdict,
+                    tap.redaction(),
+                ));
+            }
+        };
+        let mut known = HashMap::<usize, Vec<MessageCommitmentProof>>::new();
+        let mut fresh = Vec::new();
+        for commitment in commitments {
+            let (index, commitment) = match commitment {
+                Ok(x) => x,
+                Err(e) => {
+                    // Reported below.
+                    fresh.push(Err(e));
+                    break;
+                }
+            };
+            if !self.test_membership(&commitment.committer) {
+                self.statistics.packets_unauthorized += 1;
+                record(index, &commitment.committer, Verdict::Unauthorized);
+                continue;
+            }
+            if self.banned_members.contains(&commitment.committer) {
+                self.statistics.packets_banned += 1;
+                record(index, &commitment.committer, Verdict::Banned);
+                continue;
+            }
+            let committers = match known.entry(index) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    self.read_raw_message(messages[index].to_hash256())
+                        .await?
+                        .map(|(_, metadata)| metadata.committers)
+                        .unwrap_or_default(),
+                ),
+            };
+            if committers.contains(&commitment) {
+                self.statistics.packets_deduplicated += 1;
+                record(index, &commitment.committer, Verdict::Duplicate);
+                continue;
+            }
+            committers.push(commitment.clone());
+            fresh.push(Ok((index, commitment)));
+        }
+
+        let verifications = verify_in_parallel(&fresh, |commitment| match commitment {
+            Ok((index, commitment)) => messages[*index].verify_commitment(commitment, &dms_key),
+            // Reported below.
+            Err(_) => Ok(()),
+        });
+        let mut batches = Vec::<(usize, Vec<MessageCommitmentProof>)>::new();
+        let mut result = Ok(());
+        let now = std::time::Instant::now();
+        let mut fresh = fresh.into_iter().zip(verifications);
+        for (commitment, verification) in fresh.by_ref() {
+            let (index, commitment) = match commitment {
+                Ok(x) => x,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            if let Err(e) = verification {
+                record(
+                    index,
+                    &commitment.committer,
+                    Verdict::Rejected(e.to_string()),
+                );
+                result = Err(e.into());
+                break;
+            }
+            if let Some(limiter) = &mut limiter {
+                if !limiter.admit(&commitment.committer, now) {
+                    self.statistics.packets_throttled += 1;
+                    record(index, &commitment.committer, Verdict::Throttled);
+                    continue;
+                }
+            }
+            record(index, &commitment.committer, Verdict::Accepted);
+            match batches.iter_mut().find(|(i, _)| *i == index) {
+                Some((_, commitments)) => commitments.push(commitment),
+                None => batches.push((index, vec![commitment])),
+            }
+        }
+        for (commitment, _) in fresh {
+            if let Ok((index, commitment)) = commitment {
+                record(index, &commitment.committer, Verdict::Skipped);
+            }
+        }
+        if let Some(tap) = &self.tap {
+            tap.record(&records);
+        }
+        for (index, commitments) in batches {
+            self.store_commitments(&messages[index], commitments)
+                .await?;
+        }
+        result
     }
 
     async fn store_message(
         &mut self,
         message: &M,
         commitment: MessageCommitmentProof,
+    ) -> Result<(), Error> {
+        self.store_commitments(message, vec![commitment]).await
+    }
+
+    /// Stores the commitments of a message, writing its metadata only once.
+    async fn store_commitments(
+        &mut self,
+        message: &M,
+        commitments: Vec<MessageCommitmentProof>,
     ) -> Result<(), Error> {
         let message_hash = message.to_hash256();
-        if let Some((_, mut metadata)) = self.read_raw_message(message_hash).await? {
-            if metadata.committers.contains(&commitment) {
-                return Ok(());
-            } else {
+        let (mut metadata, is_new) = match self.read_raw_message(message_hash).await? {
+            Some((_, metadata)) => (metadata, false),
+            None => (
+                MessageMetadata {
+                    message_hash,
+                    committers: Vec::new(),
+                },
+                true,
+            ),
+        };
+        let stored = metadata.committers.len();
+        for commitment in commitments {
+            if !metadata.committers.contains(&commitment) {
                 metadata.committers.push(commitment);
-                self.storage
-                    .write()
-                    .await
-                    .add_or_overwrite_file(
-                        &format!("metadata-{message_hash}.json"),
-                        serde_spb::to_string(&metadata).unwrap(),
-                    )
-                    .await?;
-            };
-        } else {
-            let mut storage = self.storage.write().await;
-            storage
-                .add_or_overwrite_file(
-                    &format!("metadata-{message_hash}.json"),
-                    serde_spb::to_string(&MessageMetadata {
-                        message_hash,
-                        committers: vec![commitment],
-                    })
-                    .unwrap(),
-                )
-                .await?;
+            }
+        }
+        if metadata.committers.len() == stored {
+            return Ok(());
+        }
+        let mut storage = self.storage.write().await;
+        storage
+            .add_or_overwrite_file(
+                &format!("metadata-{message_hash}.json"),
+                serde_spb::to_string(&metadata).unwrap(),
+            )
+            .await?;
+        if is_new {
             storage
                 .add_or_overwrite_file(
                     &format!("message-{message_hash}.json"),
                     serde_spb::to_string(&message).unwrap(),
                 )
                 .await?;
-        };
+        }
         Ok(())
     }
 
+    /// Retrieves all the packets in the order of the network transfer.
     async fn retrieve_packets(&self) -> Result<Vec<Packet>, Error> {
         let messages = self.read_raw_messages().await?;
-        let mut result = Vec::new();
+        let mut queues = PriorityQueues::default();
         for (message, metadata) in messages {
             for commitment in metadata.committers {
-                result.push(Packet {
-                    commitment,
-                    message: serde_spb::to_vec(&message).unwrap(),
-                });
+                queues.push(
+                    message.priority(),
+                    Packet {
+                        commitment,
+                        message: serde_spb::to_vec(&message).unwrap(),
+                    },
+                );
             }
         }
-        Ok(result)
+        Ok(queues.drain_weighted(&self.config.priority_weights))
+    }
+}
+
+/// Reads the config and the messages of a DMS storage without opening it,
+/// so it needs no private key of the members.
+///
+/// This is for inspecting a storage captured from another node, e.g., to debug it.
+pub async fn read_stored_messages<S: Storage, M: DmsMessage>(
+    storage: &S,
+) -> Result<(Config, Vec<Message<M>>), Error> {
+    let config = serde_spb::from_str(&storage.read_file(STATE_FILE_PATH).await?)?;
+    let messages = read_raw_messages(storage)
+        .await?
+        .into_iter()
+        .map(|(message, metadata)| Message {
+            message,
+            committers: metadata.committers,
+        })
+        .collect();
+    Ok((config, messages))
+}
+
+async fn read_raw_messages<S: Storage, M: DmsMessage>(
+    storage: &S,
+) -> Result<Vec<(M, MessageMetadata)>, Error> {
+    let files = storage.list_files().await?;
+    let tasks = files
+        .iter()
+        .filter(|x| x.starts_with("message-"))
+        .map(|f| async move {
+            storage
+                .read_file(f)
+                .await
+                .map(|message| (message, f.to_owned()))
+        });
+    let messages = future::join_all(tasks)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    let tasks = messages.into_iter().map(|(message, file_name)| async move {
+        // TODO: it must be an integrity error if not found
+        storage
+            .read_file(&format!("metadata-{}", &file_name[8..]))
+            .await
+            .map(|metadata| (metadata, message))
+    });
+    let messages = future::join_all(tasks)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut result = Vec::new();
+    for (metadata, message) in &messages {
+        let metadata = serde_spb::from_str::<MessageMetadata>(metadata)
+            .map_err(|e| IntegrityError::new(format!("can't decode stored data: {e}")))?;
+        let message = serde_spb::from_str::<M>(message)
+            .map_err(|e| IntegrityError::new(format!("can't decode stored data: {e}")))?;
+        result.push((message, metadata));
     }
+    Ok(result)
 }
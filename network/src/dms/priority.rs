@@ -0,0 +1,110 @@
+use super::*;
+
+/// The priority class of a DMS message.
+///
+/// Under load, messages of a higher class are transferred before the others,
+/// so that consensus-critical messages don't wait behind bulk governance traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MessagePriority {
+    Consensus,
+    Governance,
+    Misc,
+}
+
+/// The relative weights of the priority classes.
+///
+/// In each round of draining, up to `weight` messages are taken from each class
+/// in the order of priority. Every class is drained at least one message per round,
+/// so that a lower class is never starved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriorityWeights {
+    pub consensus: u32,
+    pub governance: u32,
+    pub misc: u32,
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        Self {
+            consensus: 4,
+            governance: 2,
+            misc: 1,
+        }
+    }
+}
+
+impl PriorityWeights {
+    fn get(&self, priority: MessagePriority) -> usize {
+        let weight = match priority {
+            MessagePriority::Consensus => self.consensus,
+            MessagePriority::Governance => self.governance,
+            MessagePriority::Misc => self.misc,
+        };
+        std::cmp::max(weight, 1) as usize
+    }
+}
+
+/// Per-class queues that are drained by their weights.
+#[derive(Debug)]
+pub(super) struct PriorityQueues<T> {
+    queues: [std::collections::VecDeque<T>; 3],
+}
+
+impl<T> Default for PriorityQueues<T> {
+    fn default() -> Self {
+        Self {
+            queues: Default::default(),
+        }
+    }
+}
+
+impl<T> PriorityQueues<T> {
+    const CLASSES: [MessagePriority; 3] = [
+        MessagePriority::Consensus,
+        MessagePriority::Governance,
+        MessagePriority::Misc,
+    ];
+
+    pub(super) fn push(&mut self, priority: MessagePriority, item: T) {
+        self.queues[priority as usize].push_back(item);
+    }
+
+    /// Drains all the items in the weighted round-robin order.
+    pub(super) fn drain_weighted(mut self, weights: &PriorityWeights) -> Vec<T> {
+        let mut result = Vec::new();
+        while self.queues.iter().any(|queue| !queue.is_empty()) {
+            for priority in Self::CLASSES {
+                let queue = &mut self.queues[priority as usize];
+                let n = std::cmp::min(weights.get(priority), queue.len());
+                result.extend(queue.drain(..n));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_draining() {
+        let mut queues = PriorityQueues::default();
+        for i in 0..3 {
+            queues.push(MessagePriority::Misc, format!("m{i}"));
+            queues.push(MessagePriority::Governance, format!("g{i}"));
+        }
+        for i in 0..5 {
+            queues.push(MessagePriority::Consensus, format!("c{i}"));
+        }
+        let weights = PriorityWeights {
+            consensus: 3,
+            governance: 2,
+            misc: 0,
+        };
+        assert_eq!(
+            queues.drain_weighted(&weights),
+            vec!["c0", "c1", "c2", "g0", "g1", "m0", "c3", "c4", "g2", "m1", "m2"]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! The QUIC transport of the DMS RPC.
+//!
+//! The server listens on the UDP port of the same number as the HTTP one,
+//! and serves the same RPC interface: each request is a bidirectional stream
+//! carrying a `{"method", "params"}` object as the HTTP request body does,
+//! answered with `{"Ok": <value>}` or `{"Err": <message>}`.
+//!
+//! A client stub keeps a connection to the DMS of the peer, on which its requests
+//! are multiplexed as streams. The requests are never sent in 0-RTT, which could be replayed.
+//!
+//! The servers present a self-signed certificate, which the clients don't verify by itself.
+//! Instead, right after the TLS handshake, the server sends a [`ConnectionIdentity`]
+//! signed by the key of its DMS, which binds the key to the TLS session;
+//! the client verifies it against the known public key of the peer before any request.
+use super::*;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+
+const ALPN: &[u8] = b"simperby-dms";
+/// The limit of a request or a response.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The label of the keying material exported for [`ConnectionIdentity`].
+const IDENTITY_LABEL: &[u8] = b"EXPORTER-simperby-dms-identity";
+/// The limit of the signed [`ConnectionIdentity`].
+const MAX_IDENTITY_SIZE: usize = 4 * 1024;
+
+/// The transport of the DMS RPC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transport {
+    /// HTTP over TCP, which every peer serves.
+    #[default]
+    Http,
+    /// QUIC over UDP. The server serves it in addition to HTTP.
+    Quic,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    method: String,
+    params: serde_json::Value,
+}
+
+/// What the server signs to prove that it holds the key of its DMS on the connection.
+///
+/// The keying material is exported from the TLS session, so the signature can't be
+/// relayed to another connection by a man in the middle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ConnectionIdentity {
+    keying_material: Hash256,
+}
+
+impl ToHash256 for ConnectionIdentity {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+impl ConnectionIdentity {
+    fn new(connection: &quinn::Connection) -> Result<Self, anyhow::Error> {
+        let mut keying_material = [0; 32];
+        connection
+            .export_keying_material(&mut keying_material, IDENTITY_LABEL, &[])
+            .map_err(|_| anyhow::anyhow!("failed to export the keying material"))?;
+        Ok(Self {
+            keying_material: Hash256::hash(keying_material),
+        })
+    }
+}
+
+/// The error for a QUIC server that fails to prove the key it is known by.
+#[derive(thiserror::Error, Debug)]
+#[error("the QUIC peer failed to prove the key {public_key}: {reason}")]
+pub(super) struct PeerKeyMismatch {
+    public_key: PublicKey,
+    reason: String,
+}
+
+/// A failure of the QUIC connection or its streams, as opposed to an error reported by the peer.
+#[derive(thiserror::Error, Debug)]
+#[error("QUIC connection failed: {0}")]
+pub(super) struct ConnectionFailure(Box<dyn std::error::Error + Send + Sync>);
+
+fn failure(error: impl std::error::Error + Send + Sync + 'static) -> anyhow::Error {
+    anyhow::Error::new(ConnectionFailure(Box::new(error)))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn transport_config() -> Arc<quinn::TransportConfig> {
+    let mut config = quinn::TransportConfig::default();
+    config.max_idle_timeout(Some(IDLE_TIMEOUT.try_into().expect("valid timeout")));
+    config.keep_alive_interval(Some(IDLE_TIMEOUT / 3));
+    Arc::new(config)
+}
+
+/// Accepts the certificate of any server, checking only the signatures of the TLS handshake.
+///
+/// The server is authenticated by its [`ConnectionIdentity`] instead.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// The config of all the clients.
+fn client_config() -> Result<quinn::ClientConfig, Error> {
+    static CONFIG: OnceLock<quinn::ClientConfig> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(config.clone());
+    }
+    let mut crypto = rustls::ClientConfig::builder_with_provider(provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider())))
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto)?));
+    config.transport_config(transport_config());
+    Ok(CONFIG.get_or_init(|| config).clone())
+}
+
+fn server_config() -> Result<quinn::ServerConfig, Error> {
+    let certified = rcgen::generate_simple_self_signed(vec!["simperby-dms".to_owned()])?;
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key.into())?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut config =
+        quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    config.transport_config(transport_config());
+    Ok(config)
+}
+
+/// The name of the server at `address`, which the session tickets are stored by.
+///
+/// Each server has its own certificate and tickets, so the name tells apart the ports too.
+fn server_name(address: SocketAddr) -> String {
+    format!(
+        "{}-{}.dms",
+        address.port(),
+        address.ip().to_string().replace(['.', ':'], "-")
+    )
+}
+
+/// Serves the RPC object over QUIC, proving the key of the DMS to every client.
+/// This function will block the current thread.
+pub(super) async fn serve_quic(
+    port: u16,
+    object: Arc<dyn HttpInterface>,
+    private_key: PrivateKey,
+) -> Result<(), Error> {
+    let endpoint =
+        quinn::Endpoint::server(server_config()?, SocketAddr::from(([0, 0, 0, 0], port)))?;
+    let private_key = Arc::new(private_key);
+    while let Some(incoming) = endpoint.accept().await {
+        let object = Arc::clone(&object);
+        let private_key = Arc::clone(&private_key);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(incoming, object, &private_key).await {
+                log::debug!("QUIC connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_connection(
+    incoming: quinn::Incoming,
+    object: Arc<dyn HttpInterface>,
+    private_key: &PrivateKey,
+) -> Result<(), Error> {
+    let connection = incoming.await?;
+    let identity = ConnectionIdentity::new(&connection).map_err(|e| eyre!("{}", e))?;
+    let mut send = connection.open_uni().await?;
+    send.write_all(&serde_json::to_vec(&TypedSignature::sign(
+        &identity,
+        private_key,
+    )?)?)
+    .await?;
+    send.finish()?;
+    loop {
+        let (send, recv) = match connection.accept_bi().await {
+            Ok(stream) => stream,
+            Err(quinn::ConnectionError::ApplicationClosed(_)) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let object = Arc::clone(&object);
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(send, recv, object.as_ref()).await {
+                log::debug!("failed to serve a QUIC request: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    mut send: quinn::SendStream,
+    mut recv: quinn::RecvStream,
+    object: &dyn HttpInterface,
+) -> Result<(), Error> {
+    let request = recv.read_to_end(MAX_MESSAGE_SIZE).await?;
+    let response = match serde_json::from_slice::<Request>(&request) {
+        Ok(request) => dispatch(object, &request.method, &request.params).await,
+        Err(e) => Err(format!("invalid request: {e}")),
+    };
+    send.write_all(&serde_json::to_vec(&response)?).await?;
+    send.finish()?;
+    Ok(())
+}
+
+async fn dispatch(
+    object: &dyn HttpInterface,
+    method: &str,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let result = if params.is_array() {
+        serde_tc::DispatchStringTupleAsync::dispatch(object, method, &params.to_string()).await
+    } else if params.is_object() {
+        serde_tc::DispatchStringDictAsync::dispatch(object, method, &params.to_string()).await
+    } else {
+        return Err(format!("invalid argument type: {params}"));
+    };
+    let result = result.map_err(|e| e.to_string())?;
+    serde_json::from_str(&result).map_err(|e| e.to_string())
+}
+
+/// A RPC client over QUIC. Use `123.1.2.3:123/dms` for `address` as for `HttpClient`.
+///
+/// It talks only to the server that proves to hold the private key of `public_key`.
+pub(super) struct QuicClient {
+    address: String,
+    public_key: PublicKey,
+    connection: tokio::sync::Mutex<Option<(quinn::Endpoint, quinn::Connection)>>,
+}
+
+impl QuicClient {
+    pub(super) fn new(address: String, public_key: PublicKey) -> Self {
+        Self {
+            address,
+            public_key,
+            connection: Default::default(),
+        }
+    }
+
+    /// Returns the connection to the server, (re)connecting if there is no live one.
+    async fn connection(&self) -> Result<quinn::Connection, anyhow::Error> {
+        let mut connection = self.connection.lock().await;
+        if let Some((_, connection)) = connection.as_ref() {
+            if connection.close_reason().is_none() {
+                return Ok(connection.clone());
+            }
+        }
+        let address = self
+            .address
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .parse::<SocketAddr>()
+            .map_err(|e| anyhow::anyhow!("invalid address {}: {}", self.address, e))?;
+        let local = if address.is_ipv6() {
+            SocketAddr::from(([0u16; 8], 0))
+        } else {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        };
+        let endpoint = quinn::Endpoint::client(local).map_err(failure)?;
+        let connecting = endpoint
+            .connect_with(
+                client_config().map_err(|e| anyhow::anyhow!("{}", e))?,
+                address,
+                &server_name(address),
+            )
+            .map_err(failure)?;
+        let new = connecting.await.map_err(failure)?;
+        self.verify_identity(&new).await?;
+        *connection = Some((endpoint, new.clone()));
+        Ok(new)
+    }
+
+    /// Checks that the server has signed the identity of the connection by the expected key.
+    async fn verify_identity(&self, connection: &quinn::Connection) -> Result<(), anyhow::Error> {
+        let mismatch = |reason: String| {
+            connection.close(0u32.into(), b"unexpected key");
+            anyhow::Error::new(PeerKeyMismatch {
+                public_key: self.public_key.clone(),
+                reason,
+            })
+        };
+        let mut recv = connection.accept_uni().await.map_err(failure)?;
+        let signature = recv.read_to_end(MAX_IDENTITY_SIZE).await.map_err(failure)?;
+        let signature = serde_json::from_slice::<TypedSignature<ConnectionIdentity>>(&signature)
+            .map_err(|e| mismatch(format!("invalid identity: {e}")))?;
+        if signature.signer() != &self.public_key {
+            return Err(mismatch(format!("signed by {}", signature.signer())));
+        }
+        let identity = ConnectionIdentity::new(connection)?;
+        signature
+            .verify(&identity)
+            .map_err(|e| mismatch(format!("invalid signature: {e}")))
+    }
+
+    async fn request(&self, body: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let connection = self.connection().await?;
+        let (mut send, mut recv) = connection.open_bi().await.map_err(failure)?;
+        send.write_all(body).await.map_err(failure)?;
+        send.finish().map_err(failure)?;
+        recv.read_to_end(MAX_MESSAGE_SIZE).await.map_err(failure)
+    }
+}
+
+#[async_trait]
+impl StubCall for QuicClient {
+    type Error = anyhow::Error;
+
+    async fn call(&self, method: &'static str, params: String) -> Result<String, Self::Error> {
+        let body = format!(r#"{{"method": "{method}", "params": {params}}}"#);
+        let response = self.request(body.as_bytes()).await?;
+        match serde_json::from_slice::<Result<serde_json::Value, String>>(&response)? {
+            Ok(value) => Ok(value.to_string()),
+            Err(e) => Err(anyhow::anyhow!(r#"QUIC request failed: "{}""#, e)),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Set reconciliation for bandwidth-efficient fetching.
+//!
+//! Packets are distributed into a fixed number of buckets by their hash.
+//! A peer first asks for the digest of each bucket, and then requests only the packets
+//! of the differing buckets, telling the hashes of the packets it already has in them.
+use super::*;
+use std::collections::BTreeMap;
+
+/// The protocol version of the DMS RPC.
+///
+/// - `1`: fetches the full packet set.
+/// - `2`: supports the set reconciliation.
+/// - `3`: supports the [handshake](super::handshake()).
+/// - `4`: receives the packets in [`PacketBatch`]es.
+/// - `5`: serves the [snapshots](super::snapshot) in chunks.
+/// - `6`: reports its [`ChainId`](super::ChainId) in the handshake.
+/// - `7`: signs the [delivery acknowledgments](super::ack) of the messages it holds.
+pub(super) const PROTOCOL_VERSION: u32 = 7;
+
+/// The number of buckets for the set reconciliation.
+pub(super) const BUCKETS: usize = 64;
+
+/// The cumulative statistics of the network transfer of a DMS instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStatistics {
+    /// The (estimated) number of bytes received by `fetch()`.
+    pub bytes_received: u64,
+    /// The (estimated) number of bytes that would have been received additionally
+    /// without the set reconciliation.
+    pub bytes_saved: u64,
+    /// The number of packets received, either fetched or sent by the peers.
+    pub packets_received: u64,
+    /// The number of the received packets that were dropped as already known.
+    pub packets_deduplicated: u64,
+    /// The number of the received packets that were dropped as committed by a non-member.
+    pub packets_unauthorized: u64,
+    /// The number of the packets pushed by the peers that were dropped by the relay limit.
+    pub packets_throttled: u64,
+    /// The number of the received packets that were dropped as committed by a banned member.
+    #[serde(default)]
+    pub packets_banned: u64,
+    /// The number of packets sent by `broadcast()`.
+    pub packets_sent: u64,
+    /// The number of payloads that carried the packets sent by `broadcast()`.
+    pub payloads_sent: u64,
+    /// The number of the snapshot chunks received by `fetch()`, when it started empty.
+    #[serde(default)]
+    pub snapshot_chunks_received: u64,
+}
+
+/// The digests of the buckets, reported by the serving peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BucketDigests {
+    pub digests: Vec<Hash256>,
+    /// The size of the full packet set, used to estimate the bytes saved.
+    pub total_bytes: u64,
+}
+
+pub(super) fn bucket_of(packet_hash: &Hash256) -> usize {
+    packet_hash.hash.data[0] as usize % BUCKETS
+}
+
+/// Returns the hashes of the packets for each bucket, in a sorted order.
+pub(super) fn bucket_hashes(packets: &[Packet]) -> BTreeMap<usize, Vec<Hash256>> {
+    let mut result = BTreeMap::<usize, Vec<Hash256>>::new();
+    for packet in packets {
+        let hash = packet.to_hash256();
+        result.entry(bucket_of(&hash)).or_default().push(hash);
+    }
+    for hashes in result.values_mut() {
+        hashes.sort();
+    }
+    result
+}
+
+pub(super) fn bucket_digests(packets: &[Packet]) -> BucketDigests {
+    let hashes = bucket_hashes(packets);
+    let digests = (0..BUCKETS)
+        .map(|i| {
+            hashes
+                .get(&i)
+                .map(|hashes| {
+                    hashes
+                        .iter()
+                        .fold(Hash256::zero(), |acc, hash| acc.aggregate(hash))
+                })
+                .unwrap_or_else(Hash256::zero)
+        })
+        .collect();
+    BucketDigests {
+        digests,
+        total_bytes: encoded_size(packets),
+    }
+}
+
+/// Returns the packets in the given buckets, excluding the known ones.
+pub(super) fn missing_packets(
+    packets: Vec<Packet>,
+    buckets: &[u32],
+    known: &[Hash256],
+) -> Vec<Packet> {
+    packets
+        .into_iter()
+        .filter(|packet| {
+            let hash = packet.to_hash256();
+            buckets.contains(&(bucket_of(&hash) as u32)) && !known.contains(&hash)
+        })
+        .collect()
+}
+
+pub(super) fn encoded_size(packets: &[Packet]) -> u64 {
+    serde_spb::to_vec(&packets).unwrap().len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(message: &str) -> Packet {
+        let (public_key, private_key) = generate_keypair(message);
+        Packet {
+            message: message.as_bytes().to_vec(),
+            commitment: MessageCommitmentProof {
+                committer: public_key,
+                signature: Signature::sign(Hash256::hash(message), &private_key).unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn reconcile() {
+        let common = (0..100)
+            .map(|i| packet(&format!("common-{i}")))
+            .collect::<Vec<_>>();
+        let remote_only = (0..3)
+            .map(|i| packet(&format!("remote-{i}")))
+            .collect::<Vec<_>>();
+        let local = common.clone();
+        let remote = [common, remote_only.clone()].concat();
+
+        let local_digests = bucket_digests(&local);
+        let remote_digests = bucket_digests(&remote);
+        let differing = (0..BUCKETS)
+            .filter(|&i| local_digests.digests[i] != remote_digests.digests[i])
+            .map(|i| i as u32)
+            .collect::<Vec<_>>();
+        assert!(!differing.is_empty() && differing.len() <= remote_only.len());
+
+        let local_hashes = bucket_hashes(&local);
+        let known = differing
+            .iter()
+            .flat_map(|i| {
+                local_hashes
+                    .get(&(*i as usize))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let mut received = missing_packets(remote, &differing, &known)
+            .iter()
+            .map(|packet| packet.to_hash256())
+            .collect::<Vec<_>>();
+        let mut expected = remote_only
+            .iter()
+            .map(|packet| packet.to_hash256())
+            .collect::<Vec<_>>();
+        received.sort();
+        expected.sort();
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,118 @@
+//! Relaying the DMS traffic of the members behind a NAT.
+//!
+//! A member that can't accept inbound connections is listed with its relay (see [`Peer::relay`]),
+//! a publicly reachable member. The others never dial it; instead, it dials the relay
+//! to fetch and broadcast, and the relay stores and forwards its messages as a part of the set.
+//!
+//! Since a relay accepts the pushes of the members that the others can't reach,
+//! it limits the packets of each of them by a token bucket (see [`RelayConfig`]).
+//! The packets of the other committers, which reach the set directly, are never limited.
+//! The packets are limited only after their commitments are verified,
+//! so that a forged packet can't spend the budget of another member.
+//!
+//! There is no hole punching: a member behind a NAT always goes through its relay,
+//! even when both ends could reach each other over UDP (see [`Transport::Quic`]).
+use super::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// The rate limit of the relayed packets, applied to each committer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayConfig {
+    /// The packets accepted per second in the long run.
+    pub packets_per_second: u32,
+    /// The packets accepted at once after a quiet period.
+    pub burst: u32,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            packets_per_second: 100,
+            burst: 1000,
+        }
+    }
+}
+
+/// The token buckets of the relayed committers.
+#[derive(Debug)]
+pub(super) struct RelayLimiter {
+    config: RelayConfig,
+    relayed: HashSet<PublicKey>,
+    buckets: HashMap<PublicKey, (f64, Instant)>,
+}
+
+impl RelayLimiter {
+    pub(super) fn new(config: RelayConfig, relayed: impl IntoIterator<Item = PublicKey>) -> Self {
+        Self {
+            config,
+            relayed: relayed.into_iter().collect(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token of the committer if it's relayed, returning whether the packet is within the limit.
+    pub(super) fn admit(&mut self, committer: &PublicKey, now: Instant) -> bool {
+        if !self.relayed.contains(committer) {
+            return true;
+        }
+        let burst = self.config.burst as f64;
+        let (tokens, last) = self
+            .buckets
+            .entry(committer.clone())
+            .or_insert((burst, now));
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.config.packets_per_second as f64).min(burst);
+        *last = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/// Returns the peers that can be dialed, excluding those behind a NAT.
+pub(super) fn dialable_peers(peers: &[Peer]) -> impl Iterator<Item = &Peer> {
+    peers.iter().filter(|peer| peer.relay.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn token_bucket() {
+        let (a, _) = generate_keypair("a");
+        let (b, _) = generate_keypair("b");
+        let (c, _) = generate_keypair("c");
+        let mut limiter = RelayLimiter::new(
+            RelayConfig {
+                packets_per_second: 2,
+                burst: 3,
+            },
+            [a.clone(), b.clone()],
+        );
+        let now = Instant::now();
+        // Not relayed, so not limited.
+        assert!((0..10).all(|_| limiter.admit(&c, now)));
+        assert_eq!(
+            (0..4).map(|_| limiter.admit(&a, now)).collect::<Vec<_>>(),
+            vec![true, true, true, false]
+        );
+        // The others have their own buckets.
+        assert!(limiter.admit(&b, now));
+        // Refilled by the rate, up to the burst.
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.admit(&a, later));
+        assert!(!limiter.admit(&a, later));
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(
+            (0..4)
+                .map(|_| limiter.admit(&a, much_later))
+                .collect::<Vec<_>>(),
+            vec![true, true, true, false]
+        );
+    }
+}
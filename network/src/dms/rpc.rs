@@ -1,5 +1,8 @@
 use super::*;
 
+/// The maximum number of packets (or batches of them) sent in a single RPC request of `broadcast()`.
+const BROADCAST_BATCH_SIZE: usize = 64;
+
 /// The interface that will be wrapped into an HTTP RPC server for the peers.
 #[serde_tc_full]
 pub(super) trait DistributedMessageSetRpcInterface: Send + Sync + 'static {
@@ -8,6 +11,42 @@ pub(super) trait DistributedMessageSetRpcInterface: Send + Sync + 'static {
 
     /// Sends packets to the peer.
     async fn send_packets(&self, packets: Vec<Packet>) -> Result<(), String>;
+
+    /// Returns the protocol version of the peer. Added in version 2.
+    async fn protocol_version(&self) -> Result<u32, String>;
+
+    /// Requests the digests of the packet buckets. Added in version 2.
+    async fn request_bucket_digests(&self) -> Result<BucketDigests, String>;
+
+    /// Requests the packets in the given buckets except the known ones. Added in version 2.
+    async fn request_missing_packets(
+        &self,
+        buckets: Vec<u32>,
+        known: Vec<Hash256>,
+    ) -> Result<Vec<Packet>, String>;
+
+    /// Signs the given challenge for the handshake. Added in version 3.
+    async fn identify(&self, challenge: Hash256)
+        -> Result<TypedSignature<HandshakeTarget>, String>;
+
+    /// Sends packets to the peer, those of the same message in a batch. Added in version 4.
+    async fn send_packet_batches(&self, batches: Vec<PacketBatch>) -> Result<(), String>;
+
+    /// Takes a snapshot of the packets and describes it. Added in version 5.
+    async fn request_snapshot_manifest(&self) -> Result<SnapshotManifest, String>;
+
+    /// Requests a chunk of the snapshot described earlier. Added in version 5.
+    async fn request_snapshot_chunk(
+        &self,
+        snapshot: Hash256,
+        index: u32,
+    ) -> Result<Vec<Packet>, String>;
+
+    /// Returns the chain of the DMS, if it's set. Added in version 6.
+    async fn chain_id(&self) -> Result<Option<ChainId>, String>;
+
+    /// Acknowledges the messages of the given hashes that the peer holds. Added in version 7.
+    async fn acknowledge(&self, message_hashes: Vec<Hash256>) -> Result<Vec<DeliveryAck>, String>;
 }
 
 pub(super) struct DmsWrapper<S: Storage, M: DmsMessage> {
@@ -15,43 +54,205 @@ pub(super) struct DmsWrapper<S: Storage, M: DmsMessage> {
     /// This is an `Option` because we have to explicitly drop the server
     /// (it could live forever in the RPC server (`axum`) otherwise)
     pub(super) dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<DistributedMessageSet<S, M>>>>>>,
+    /// The limit of the pushed packets, if relaying.
+    pub(super) limiter: Option<tokio::sync::Mutex<RelayLimiter>>,
+    pub(super) snapshots: parking_lot::Mutex<SnapshotCache>,
 }
 
-/// Server-side implementation of the RPC interface.
-#[async_trait]
-impl<S: Storage, M: DmsMessage> DistributedMessageSetRpcInterface for DmsWrapper<S, M> {
-    async fn request_packets(&self) -> Result<Vec<Packet>, String> {
-        let dms = Arc::clone(
+impl<S: Storage, M: DmsMessage> DmsWrapper<S, M> {
+    fn get_dms(&self) -> Result<Arc<RwLock<DistributedMessageSet<S, M>>>, String> {
+        Ok(Arc::clone(
             self.dms
                 .read()
                 .as_ref()
                 .ok_or_else(|| "server terminated".to_owned())?,
-        );
-        let packets = dms
+        ))
+    }
+}
+
+/// Server-side implementation of the RPC interface.
+#[async_trait]
+impl<S: Storage, M: DmsMessage> DistributedMessageSetRpcInterface for DmsWrapper<S, M> {
+    async fn request_packets(&self) -> Result<Vec<Packet>, String> {
+        let dms = self.get_dms()?;
+        let dms = dms.read().await;
+        let packets = dms.retrieve_packets().await.map_err(|e| e.to_string())?;
+        dms.tap_sent(None, &packets, Verdict::Sent);
+        Ok(packets)
+    }
+
+    async fn send_packets(&self, packets: Vec<Packet>) -> Result<(), String> {
+        let dms = self.get_dms()?;
+        let mut limiter = match &self.limiter {
+            Some(limiter) => Some(limiter.lock().await),
+            None => None,
+        };
+        dms.write()
+            .await
+            .receive_packets(packets, limiter.as_deref_mut(), None)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn protocol_version(&self) -> Result<u32, String> {
+        Ok(PROTOCOL_VERSION)
+    }
+
+    async fn request_bucket_digests(&self) -> Result<BucketDigests, String> {
+        let packets = self
+            .get_dms()?
             .read()
             .await
             .retrieve_packets()
             .await
             .map_err(|e| e.to_string())?;
+        Ok(bucket_digests(&packets))
+    }
+
+    async fn request_missing_packets(
+        &self,
+        buckets: Vec<u32>,
+        known: Vec<Hash256>,
+    ) -> Result<Vec<Packet>, String> {
+        let dms = self.get_dms()?;
+        let dms = dms.read().await;
+        let packets = dms.retrieve_packets().await.map_err(|e| e.to_string())?;
+        let packets = missing_packets(packets, &buckets, &known);
+        dms.tap_sent(None, &packets, Verdict::Sent);
         Ok(packets)
     }
 
-    async fn send_packets(&self, packets: Vec<Packet>) -> Result<(), String> {
-        let dms = Arc::clone(
-            self.dms
-                .read()
-                .as_ref()
-                .ok_or_else(|| "server terminated".to_owned())?,
-        );
-        for packet in packets {
-            dms.write()
-                .await
-                .receive_packet(packet)
-                .await
-                .map_err(|e| e.to_string())?;
+    async fn identify(
+        &self,
+        challenge: Hash256,
+    ) -> Result<TypedSignature<HandshakeTarget>, String> {
+        let dms = self.get_dms()?;
+        let dms = dms.read().await;
+        let target = HandshakeTarget {
+            dms_key: dms.config.dms_key.clone(),
+            challenge,
+        };
+        let private_key = dms
+            .private_key
+            .as_ref()
+            .ok_or_else(|| "this node has no key to identify with".to_owned())?;
+        TypedSignature::sign(&target, private_key).map_err(|e| e.to_string())
+    }
+
+    async fn send_packet_batches(&self, batches: Vec<PacketBatch>) -> Result<(), String> {
+        self.send_packets(
+            batches
+                .into_iter()
+                .flat_map(PacketBatch::into_packets)
+                .collect(),
+        )
+        .await
+    }
+
+    async fn request_snapshot_manifest(&self) -> Result<SnapshotManifest, String> {
+        let dms = self.get_dms()?;
+        let dms = dms.read().await;
+        let packets = dms.retrieve_packets().await.map_err(|e| e.to_string())?;
+        Ok(self.snapshots.lock().take(&dms.config.dms_key, packets))
+    }
+
+    async fn request_snapshot_chunk(
+        &self,
+        snapshot: Hash256,
+        index: u32,
+    ) -> Result<Vec<Packet>, String> {
+        let packets = self.snapshots.lock().chunk(&snapshot, index as usize)?;
+        let dms = self.get_dms()?;
+        dms.read().await.tap_sent(None, &packets, Verdict::Sent);
+        Ok(packets)
+    }
+
+    async fn chain_id(&self) -> Result<Option<ChainId>, String> {
+        Ok(self.get_dms()?.read().await.get_chain_id())
+    }
+
+    async fn acknowledge(&self, message_hashes: Vec<Hash256>) -> Result<Vec<DeliveryAck>, String> {
+        self.get_dms()?
+            .read()
+            .await
+            .acknowledge(&message_hashes)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the HTTP client shared by the stubs, as creating one is expensive (and blocking).
+///
+/// It keeps no idle connections, which would be bound to the runtime that opened them.
+fn http_client() -> reqwest::Client {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            reqwest::Client::builder()
+                .pool_max_idle_per_host(0)
+                .build()
+                .expect("failed to create the HTTP client")
+        })
+        .clone()
+}
+
+/// Creates the stub of the DMS RPC at `url` over the transport.
+///
+/// Over QUIC, the stub talks only to the peer that proves to hold the key of `public_key`.
+pub(super) fn create_stub(
+    url: String,
+    transport: Transport,
+    public_key: &PublicKey,
+) -> DistributedMessageSetRpcInterfaceStub {
+    match transport {
+        Transport::Http => DistributedMessageSetRpcInterfaceStub::new(Box::new(HttpClient::new(
+            url,
+            http_client(),
+        ))),
+        Transport::Quic => DistributedMessageSetRpcInterfaceStub::new(Box::new(QuicClient::new(
+            url,
+            public_key.clone(),
+        ))),
+    }
+}
+
+/// Finds the URL of the DMS RPC of the peer, with the protocol version if the peer reports it.
+///
+/// The address of the peer is resolved on every call, and the resolved ones
+/// (possibly of both IPv6 and IPv4) are tried in order until one responds.
+/// It fails if none does.
+pub(super) async fn connect(
+    peer: &Peer,
+    port_key: &str,
+    transport: Transport,
+) -> Result<(String, Option<u32>), Error> {
+    let port = *peer
+        .ports
+        .get(port_key)
+        .ok_or_else(|| eyre!("can't find port key: {}", port_key))?;
+    let mut address = peer.address.clone();
+    address.set_port(port);
+    let urls = address
+        .resolve()
+        .await?
+        .into_iter()
+        .map(|address| format!("{}:{}/dms", PeerAddress::from(address).host(), port))
+        .collect::<Vec<_>>();
+    for url in &urls {
+        let stub = create_stub(url.clone(), transport, &peer.public_key);
+        match stub.protocol_version().await {
+            Ok(Ok(version)) => return Ok((url.clone(), Some(version))),
+            Ok(Err(_)) => return Ok((url.clone(), None)),
+            // Not even connected.
+            Err(e) if e.is::<reqwest::Error>() || e.is::<ConnectionFailure>() => continue,
+            // Connected to another peer than the one dialed.
+            Err(e) if e.is::<PeerKeyMismatch>() => return Err(eyre!("{}", e)),
+            // Peers of version 1 respond, but don't know `protocol_version()`.
+            Err(_) => return Ok((url.clone(), None)),
         }
-        Ok(())
     }
+    Err(eyre!("the peer is unreachable at {}", urls.join(", ")))
 }
 
 impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
@@ -62,39 +263,94 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
         network_config: &ClientNetworkConfig,
     ) -> Result<(), Error> {
         let mut tasks = Vec::new();
-        for peer in &network_config.peers {
+        let peers = {
+            let this_read = this.read().await;
+            dialable_peers(&network_config.peers)
+                .filter(|peer| !this_read.is_banned(&peer.public_key))
+                .collect::<Vec<_>>()
+        };
+        let transport = network_config.transport;
+        for peer in peers.iter().copied() {
             let this_ = Arc::clone(&this);
             let task = async move {
                 let this_read = this_.read().await;
                 let port_key = format!("dms-{}", this_read.config.dms_key);
-                let stub = DistributedMessageSetRpcInterfaceStub::new(Box::new(HttpClient::new(
-                    format!(
-                        "{}:{}/dms",
-                        peer.address.ip(),
-                        peer.ports
-                            .get(&port_key)
-                            .ok_or_else(|| eyre!("can't find port key: {}", port_key))?
-                    ),
-                    reqwest::Client::new(),
-                )));
-                let packets = stub
-                    .request_packets()
-                    .await
-                    .map_err(|e| eyre!("{}", e))?
-                    .map_err(|e| eyre!(e))?;
+                let (url, version) = connect(peer, &port_key, transport).await?;
+                let stub = create_stub(url.clone(), transport, &peer.public_key);
+                // Peers that don't know `protocol_version()` are of version 1.
+                let version = version.unwrap_or(1);
+                check_chain(&stub, version, this_read.chain_id.as_ref()).await?;
+                let (packets, bytes_saved) = if version >= 2 {
+                    let local_packets = this_read.retrieve_packets().await?;
+                    if version >= 5 && local_packets.is_empty() {
+                        // Nothing to reconcile; download the whole set in chunks instead.
+                        drop(this_read);
+                        return Self::download_snapshot(&this_, url, transport, &peer.public_key)
+                            .await;
+                    }
+                    let local_hashes = bucket_hashes(&local_packets);
+                    let local_digests = bucket_digests(&local_packets);
+                    let remote_digests = stub
+                        .request_bucket_digests()
+                        .await
+                        .map_err(|e| eyre!("{}", e))?
+                        .map_err(|e| eyre!(e))?;
+                    let buckets = (0..BUCKETS)
+                        .filter(|&i| remote_digests.digests.get(i) != local_digests.digests.get(i))
+                        .collect::<Vec<_>>();
+                    let packets = if buckets.is_empty() {
+                        Vec::new()
+                    } else {
+                        let known = buckets
+                            .iter()
+                            .flat_map(|i| local_hashes.get(i).cloned().unwrap_or_default())
+                            .collect();
+                        stub.request_missing_packets(
+                            buckets.into_iter().map(|i| i as u32).collect(),
+                            known,
+                        )
+                        .await
+                        .map_err(|e| eyre!("{}", e))?
+                        .map_err(|e| eyre!(e))?
+                    };
+                    let bytes_saved = remote_digests
+                        .total_bytes
+                        .saturating_sub(encoded_size(&packets));
+                    (packets, bytes_saved)
+                } else {
+                    let packets = stub
+                        .request_packets()
+                        .await
+                        .map_err(|e| eyre!("{}", e))?
+                        .map_err(|e| eyre!(e))?;
+                    (packets, 0)
+                };
                 // Important: drop the lock before `write()`
                 drop(this_read);
-                for packet in packets {
-                    this_.write().await.receive_packet(packet).await?;
-                }
+                let mut this_write = this_.write().await;
+                this_write.statistics.bytes_received += encoded_size(&packets);
+                this_write.statistics.bytes_saved += bytes_saved;
+                let unauthorized = this_write.statistics.packets_unauthorized;
+                let result = this_write
+                    .receive_packets(packets, None, Some(&peer.public_key))
+                    .await;
+                let unauthorized = this_write.statistics.packets_unauthorized - unauthorized;
+                this_write.penalize(&peer.public_key, unauthorized);
+                result?;
                 Result::<(), Error>::Ok(())
             };
             tasks.push(task);
         }
         let results = future::join_all(tasks).await;
-        for (result, peer) in results.into_iter().zip(network_config.peers.iter()) {
+        for (result, peer) in results.into_iter().zip(peers) {
             if let Err(e) = result {
-                log::warn!("failed to fetch from client {:?}: {}", peer, e);
+                match e.downcast_ref::<ChainMismatchError>() {
+                    Some(mismatch) => this
+                        .write()
+                        .await
+                        .record_chain_mismatch(&peer.public_key, mismatch),
+                    None => log::warn!("failed to fetch from client {:?}: {}", peer, e),
+                }
             }
         }
         Ok(())
@@ -102,6 +358,9 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
 
     /// Tries to broadcast all the message that this DMS instance has.
     ///
+    /// The peers of version 7 or later acknowledge the messages they hold afterwards,
+    /// which are recorded for [`Self::get_message_propagation`].
+    ///
     /// Note: this function may take just `&self` due to its simple implementation,
     /// but keeps `Arc<RwLock<Self>>` to make sure the interface to indicate
     /// that this is a network-involved method (unlike others)
@@ -112,37 +371,99 @@ impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
         let mut tasks_and_messages = Vec::new();
 
         let packets = this.read().await.retrieve_packets().await?;
-        for peer in &network_config.peers {
+        let batches = PacketBatch::from_packets(packets.clone());
+        let message_hashes = this
+            .read()
+            .await
+            .read_raw_messages()
+            .await?
+            .into_iter()
+            .map(|(_, metadata)| metadata.message_hash)
+            .collect::<Vec<_>>();
+        let peers = {
+            let this_read = this.read().await;
+            dialable_peers(&network_config.peers)
+                .filter(|peer| !this_read.is_banned(&peer.public_key))
+                .collect::<Vec<_>>()
+        };
+        let chain_id = this.read().await.chain_id.clone();
+        let transport = network_config.transport;
+        for peer in peers {
             let key = this.read().await.config.dms_key.clone();
             let port_key = format!("dms-{key}");
+            let chain_id = chain_id.clone();
             let packets_ = packets.clone();
+            let batches_ = batches.clone();
+            // Those acknowledged by the peer in the previous rounds are not asked again.
+            let message_hashes = this
+                .read()
+                .await
+                .unacknowledged(&peer.public_key, &message_hashes);
             let task = async move {
-                let stub = DistributedMessageSetRpcInterfaceStub::new(Box::new(HttpClient::new(
-                    format!(
-                        "{}:{}/dms",
-                        peer.address.ip(),
-                        peer.ports
-                            .get(&port_key)
-                            .ok_or_else(|| eyre!("can't find port key: {}", port_key))?
-                    ),
-                    reqwest::Client::new(),
-                )));
-                stub.send_packets(packets_.clone())
-                    .await
-                    .map_err(|e| eyre!(e))?
-                    .map_err(|e| eyre!(e))?;
-                Result::<(), Error>::Ok(())
+                let (url, version) = connect(peer, &port_key, transport).await?;
+                let stub = create_stub(url, transport, &peer.public_key);
+                let version = version.unwrap_or(1);
+                check_chain(&stub, version, chain_id.as_ref()).await?;
+                // Send in chunks so that the higher classes arrive first under load.
+                let mut payloads = 0;
+                if version >= 4 {
+                    for chunk in batches_.chunks(BROADCAST_BATCH_SIZE) {
+                        stub.send_packet_batches(chunk.to_vec())
+                            .await
+                            .map_err(|e| eyre!(e))?
+                            .map_err(|e| eyre!(e))?;
+                        payloads += chunk.len() as u64;
+                    }
+                } else {
+                    for chunk in packets_.chunks(BROADCAST_BATCH_SIZE) {
+                        stub.send_packets(chunk.to_vec())
+                            .await
+                            .map_err(|e| eyre!(e))?
+                            .map_err(|e| eyre!(e))?;
+                        payloads += chunk.len() as u64;
+                    }
+                }
+                let acks = if version >= 7 && !message_hashes.is_empty() {
+                    stub.acknowledge(message_hashes)
+                        .await
+                        .map_err(|e| eyre!(e))?
+                        .map_err(|e| eyre!(e))?
+                } else {
+                    Vec::new()
+                };
+                Result::<(u64, u64, Vec<DeliveryAck>), Error>::Ok((
+                    packets_.len() as u64,
+                    payloads,
+                    acks,
+                ))
             };
-            tasks_and_messages.push((task, format!("RPC message add to {}", peer.public_key)));
+            tasks_and_messages.push((task, peer));
         }
-        let (tasks, messages) = tasks_and_messages
+        let (tasks, peers) = tasks_and_messages
             .into_iter()
             .unzip::<_, _, Vec<_>, Vec<_>>();
 
         let results = future::join_all(tasks).await;
-        for (result, msg) in results.into_iter().zip(messages.iter()) {
-            if let Err(e) = result {
-                log::warn!("failure in {}: {}", msg, e);
+        let mut this_write = this.write().await;
+        for (result, peer) in results.into_iter().zip(peers) {
+            match result {
+                Ok((sent, payloads, acks)) => {
+                    this_write.statistics.packets_sent += sent;
+                    this_write.statistics.payloads_sent += payloads;
+                    this_write.record_acks(&peer.public_key, acks);
+                    this_write.tap_sent(Some(&peer.public_key), &packets, Verdict::Sent);
+                }
+                Err(e) => match e.downcast_ref::<ChainMismatchError>() {
+                    Some(mismatch) => this_write.record_chain_mismatch(&peer.public_key, mismatch),
+                    None => {
+                        log::warn!("failure in RPC message add to {}: {}", peer.public_key, e);
+                        this_write.tap_sent(
+                            Some(&peer.public_key),
+                            &packets,
+                            Verdict::Failed(e.to_string()),
+                        );
+                    }
+                },
             }
         }
         Ok(())
@@ -11,32 +11,50 @@ pub async fn serve<S: Storage, M: DmsMessage>(
         .get(&port_key)
         .ok_or_else(|| eyre!(format!("`ports` has no field of {port_key}")))?;
 
-    let rpc_task = async move {
-        let wrapped_dms = Arc::new(parking_lot::RwLock::new(Some(dms)));
-        let wrapped_dms_ = Arc::clone(&wrapped_dms);
-        struct DropHelper<T> {
-            wrapped_dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<T>>>>>,
-        }
-        impl<T> Drop for DropHelper<T> {
-            fn drop(&mut self) {
-                self.wrapped_dms.write().take().unwrap();
-            }
+    let port = *port;
+    let transport = network_config.transport;
+    // Proves the key of the DMS to the QUIC clients.
+    let private_key = dms.read().await.private_key.clone();
+
+    let wrapped_dms = Arc::new(parking_lot::RwLock::new(Some(dms)));
+    let wrapped_dms_ = Arc::clone(&wrapped_dms);
+    struct DropHelper<T> {
+        wrapped_dms: Arc<parking_lot::RwLock<Option<Arc<RwLock<T>>>>>,
+    }
+    impl<T> Drop for DropHelper<T> {
+        fn drop(&mut self) {
+            self.wrapped_dms.write().take().unwrap();
         }
-        let _drop_helper = DropHelper { wrapped_dms };
-        run_server(
-            *port,
-            [(
-                "dms".to_owned(),
-                create_http_object(Arc::new(DmsWrapper { dms: wrapped_dms_ })
-                    as Arc<dyn DistributedMessageSetRpcInterface>),
-            )]
+    }
+    let _drop_helper = DropHelper { wrapped_dms };
+    let object = create_http_object(Arc::new(DmsWrapper {
+        dms: wrapped_dms_,
+        limiter: network_config.relay.map(|config| {
+            tokio::sync::Mutex::new(RelayLimiter::new(config, network_config.relayed_peers))
+        }),
+        snapshots: Default::default(),
+    }) as Arc<dyn DistributedMessageSetRpcInterface>);
+    let rpc_task = run_server(
+        port,
+        [("dms".to_owned(), Arc::clone(&object))]
             .iter()
             .cloned()
             .collect(),
-        )
-        .await;
-    };
-    rpc_task.await;
+    );
+    match (transport, private_key) {
+        (Transport::Http, _) => rpc_task.await,
+        // The HTTP server is kept for the peers that dial it.
+        (Transport::Quic, Some(private_key)) => {
+            tokio::select! {
+                _ = rpc_task => (),
+                result = serve_quic(port, object, private_key) => result?,
+            }
+        }
+        (Transport::Quic, None) => {
+            log::warn!("a read-only DMS can't prove its key over QUIC; serving only HTTP");
+            rpc_task.await
+        }
+    }
     Ok(())
 }
 
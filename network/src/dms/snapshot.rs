@@ -0,0 +1,218 @@
+//! Snapshots of the message set, for the nodes joining in the middle of the height.
+//!
+//! A late joiner has nothing to reconcile, and would otherwise receive the whole set in a single response.
+//! Instead, it asks a peer for the manifest of the set ([`SnapshotManifest`])
+//! and downloads it chunk by chunk, checking each against the hash in the manifest before storing it.
+//!
+//! The serving peer keeps the snapshots it has recently described,
+//! so that the chunks stay consistent while the set keeps growing;
+//! what's committed after the snapshot is left to the usual fetch.
+use super::*;
+use std::collections::VecDeque;
+
+/// The maximum number of packets in a chunk.
+pub(super) const SNAPSHOT_CHUNK_SIZE: usize = 256;
+
+/// The number of snapshots that a server keeps for the ongoing downloads.
+const MAX_CACHED_SNAPSHOTS: usize = 4;
+
+/// The description of a snapshot, reported by the serving peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub dms_key: DmsKey,
+    /// The hash of each chunk, which is the hash of its encoded packets.
+    pub chunk_hashes: Vec<Hash256>,
+    /// The number of the packets in the snapshot.
+    pub packets: u64,
+    pub total_bytes: u64,
+}
+
+impl ToHash256 for SnapshotManifest {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+impl SnapshotManifest {
+    /// Checks that the chunk of the index is the one described in the manifest.
+    pub(super) fn verify_chunk(&self, index: usize, packets: &[Packet]) -> Result<(), Error> {
+        let expected = self
+            .chunk_hashes
+            .get(index)
+            .ok_or_else(|| eyre!("chunk index out of range: {index}"))?;
+        let hash = chunk_hash(packets);
+        if &hash != expected {
+            return Err(eyre!(
+                "chunk {index} mismatch: expected {expected}, got {hash}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn chunk_hash(packets: &[Packet]) -> Hash256 {
+    Hash256::hash(serde_spb::to_vec(&packets).unwrap())
+}
+
+struct Snapshot {
+    hash: Hash256,
+    manifest: SnapshotManifest,
+    chunks: Vec<Vec<Packet>>,
+}
+
+/// The snapshots described by a server, the most recent last.
+#[derive(Default)]
+pub(super) struct SnapshotCache {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotCache {
+    /// Takes a snapshot of the packets, in the order given, and returns its manifest.
+    ///
+    /// The same set of packets is described by the same snapshot.
+    pub(super) fn take(&mut self, dms_key: &DmsKey, packets: Vec<Packet>) -> SnapshotManifest {
+        let chunks = packets
+            .chunks(SNAPSHOT_CHUNK_SIZE)
+            .map(<[Packet]>::to_vec)
+            .collect::<Vec<_>>();
+        let manifest = SnapshotManifest {
+            dms_key: dms_key.clone(),
+            chunk_hashes: chunks.iter().map(|chunk| chunk_hash(chunk)).collect(),
+            packets: packets.len() as u64,
+            total_bytes: encoded_size(&packets),
+        };
+        let hash = manifest.to_hash256();
+        if let Some(index) = self.snapshots.iter().position(|x| x.hash == hash) {
+            let snapshot = self.snapshots.remove(index).unwrap();
+            self.snapshots.push_back(snapshot);
+        } else {
+            if self.snapshots.len() == MAX_CACHED_SNAPSHOTS {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(Snapshot {
+                hash,
+                manifest: manifest.clone(),
+                chunks,
+            });
+        }
+        manifest
+    }
+
+    /// Returns the chunk of the snapshot, if it's still kept.
+    pub(super) fn chunk(
+        &self,
+        snapshot_hash: &Hash256,
+        index: usize,
+    ) -> Result<Vec<Packet>, String> {
+        let snapshot = self
+            .snapshots
+            .iter()
+            .find(|x| &x.hash == snapshot_hash)
+            .ok_or_else(|| format!("unknown or expired snapshot: {snapshot_hash}"))?;
+        snapshot.chunks.get(index).cloned().ok_or_else(|| {
+            format!(
+                "chunk index out of range: {index} >= {}",
+                snapshot.manifest.chunk_hashes.len()
+            )
+        })
+    }
+}
+
+impl<S: Storage, M: DmsMessage> DistributedMessageSet<S, M> {
+    /// Downloads the snapshot of the peer at `url` chunk by chunk, storing each once verified.
+    ///
+    /// The chunks stored before a failure are kept.
+    pub(super) async fn download_snapshot(
+        this: &Arc<RwLock<Self>>,
+        url: String,
+        transport: Transport,
+        peer: &PublicKey,
+    ) -> Result<(), Error> {
+        let stub = create_stub(url, transport, peer);
+        let manifest = stub
+            .request_snapshot_manifest()
+            .await
+            .map_err(|e| eyre!("{}", e))?
+            .map_err(|e| eyre!(e))?;
+        let dms_key = this.read().await.config.dms_key.clone();
+        if manifest.dms_key != dms_key {
+            return Err(eyre!("snapshot of another DMS: {}", manifest.dms_key));
+        }
+        let snapshot_hash = manifest.to_hash256();
+        for index in 0..manifest.chunk_hashes.len() {
+            let packets = stub
+                .request_snapshot_chunk(snapshot_hash, index as u32)
+                .await
+                .map_err(|e| eyre!("{}", e))?
+                .map_err(|e| eyre!(e))?;
+            manifest.verify_chunk(index, &packets)?;
+            let mut this_write = this.write().await;
+            this_write.statistics.bytes_received += encoded_size(&packets);
+            this_write.statistics.snapshot_chunks_received += 1;
+            let unauthorized = this_write.statistics.packets_unauthorized;
+            let result = this_write.receive_packets(packets, None, Some(peer)).await;
+            let unauthorized = this_write.statistics.packets_unauthorized - unauthorized;
+            this_write.penalize(peer, unauthorized);
+            result?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(message: &str) -> Packet {
+        let (public_key, private_key) = generate_keypair(message);
+        Packet {
+            message: message.as_bytes().to_vec(),
+            commitment: MessageCommitmentProof {
+                committer: public_key,
+                signature: Signature::sign(Hash256::hash(message), &private_key).unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn snapshot_chunks() {
+        let packets = (0..SNAPSHOT_CHUNK_SIZE * 2 + 10)
+            .map(|i| packet(&format!("message-{i}")))
+            .collect::<Vec<_>>();
+        let dms_key = "dms".to_owned();
+        let mut cache = SnapshotCache::default();
+        let manifest = cache.take(&dms_key, packets.clone());
+        assert_eq!(manifest.chunk_hashes.len(), 3);
+        assert_eq!(manifest.packets, packets.len() as u64);
+
+        let snapshot_hash = manifest.to_hash256();
+        let mut received = Vec::new();
+        for index in 0..manifest.chunk_hashes.len() {
+            let chunk = cache.chunk(&snapshot_hash, index).unwrap();
+            manifest.verify_chunk(index, &chunk).unwrap();
+            received.extend(chunk);
+        }
+        assert_eq!(
+            received.iter().map(|x| x.to_hash256()).collect::<Vec<_>>(),
+            packets.iter().map(|x| x.to_hash256()).collect::<Vec<_>>()
+        );
+        cache.chunk(&snapshot_hash, 3).unwrap_err();
+
+        // A tampered chunk is rejected.
+        let mut chunk = cache.chunk(&snapshot_hash, 1).unwrap();
+        chunk.pop();
+        assert!(manifest.verify_chunk(1, &chunk).is_err());
+        assert!(manifest.verify_chunk(3, &[]).is_err());
+
+        // The snapshot is kept while the set grows, until evicted.
+        assert_eq!(cache.take(&dms_key, packets.clone()), manifest);
+        for i in 0..MAX_CACHED_SNAPSHOTS - 1 {
+            let mut packets = packets.clone();
+            packets.push(packet(&format!("new-{i}")));
+            cache.take(&dms_key, packets);
+        }
+        cache.chunk(&snapshot_hash, 0).unwrap();
+        cache.take(&dms_key, vec![packet("another")]);
+        cache.chunk(&snapshot_hash, 0).unwrap_err();
+    }
+}
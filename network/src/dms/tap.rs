@@ -0,0 +1,329 @@
+//! The message tap, which records every packet that a DMS sends or receives, for debugging.
+//!
+//! A record ([`TapRecord`]) is a line of JSON appended to the tap file,
+//! which is rotated when it reaches the size limit.
+//! The payloads are redacted as configured ([`PayloadRedaction`]),
+//! since the file is likely to be attached to a bug report.
+//!
+//! The peer of a packet is known only in the transfers that this node initiates
+//! (`fetch()` and `broadcast()`); those initiated by the peers are served
+//! without identifying them.
+use super::*;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TapConfig {
+    /// The size at which the tap file is rotated, in bytes.
+    pub max_file_size: u64,
+    /// The number of the rotated files to keep, named `<file>.1` (the newest) to `<file>.<n>`.
+    pub max_rotated_files: usize,
+    #[serde(default)]
+    pub redaction: PayloadRedaction,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: 16 * 1024 * 1024,
+            max_rotated_files: 4,
+            redaction: PayloadRedaction::default(),
+        }
+    }
+}
+
+/// How much of the message to put in a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PayloadRedaction {
+    /// No payload; the hash and the size identify the message.
+    #[default]
+    Omit,
+    /// The payload in JSON, cut at the given number of characters.
+    Truncate(usize),
+    /// The whole payload in JSON.
+    Full,
+}
+
+impl PayloadRedaction {
+    fn apply<M: Serialize>(&self, message: &M) -> Option<String> {
+        let payload = match self {
+            PayloadRedaction::Omit => return None,
+            PayloadRedaction::Truncate(_) | PayloadRedaction::Full => {
+                serde_json::to_string(message).ok()?
+            }
+        };
+        match self {
+            PayloadRedaction::Truncate(length) => Some(payload.chars().take(*length).collect()),
+            _ => Some(payload),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// What happened to a packet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// Received, verified and stored.
+    Accepted,
+    /// Received but already known.
+    Duplicate,
+    /// Received from a key out of the members (see [`Config::members`]).
+    Unauthorized,
+    /// Received but dropped by the relay limit (see [`RelayConfig`]).
+    Throttled,
+    /// Received from a banned member (see [`DistributedMessageSet::set_banned_members`]).
+    Banned,
+    /// Received but failed to decode or verify.
+    Rejected(String),
+    /// Received after a rejected one in the same payload, so not processed.
+    Skipped,
+    Sent,
+    /// Failed to send, with the error of the request.
+    ///
+    /// The packets of the earlier requests to the peer may have been delivered.
+    Failed(String),
+}
+
+/// A packet that a DMS has sent or received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TapRecord {
+    pub timestamp: Timestamp,
+    pub direction: Direction,
+    /// The peer that the packet was sent to or received from, if known.
+    pub peer: Option<PublicKey>,
+    pub dms_key: DmsKey,
+    /// The Rust type of the message.
+    pub message_type: String,
+    /// The hash of the message, or of the raw bytes if it failed to decode.
+    pub message_hash: Hash256,
+    pub committer: PublicKey,
+    /// The size of the encoded message, in bytes.
+    pub size: u64,
+    pub verdict: Verdict,
+    /// The message in JSON, redacted as configured.
+    pub payload: Option<String>,
+}
+
+impl TapRecord {
+    pub(super) fn new<M: DmsMessage>(
+        direction: Direction,
+        peer: Option<&PublicKey>,
+        dms_key: &DmsKey,
+        message: &M,
+        committer: &PublicKey,
+        verdict: Verdict,
+        redaction: PayloadRedaction,
+    ) -> Self {
+        Self {
+            timestamp: simperby_core::utils::get_timestamp(),
+            direction,
+            peer: peer.cloned(),
+            dms_key: dms_key.clone(),
+            message_type: std::any::type_name::<M>().to_owned(),
+            message_hash: message.to_hash256(),
+            committer: committer.clone(),
+            size: serde_spb::to_vec(message)
+                .map(|x| x.len() as u64)
+                .unwrap_or_default(),
+            verdict,
+            payload: redaction.apply(message),
+        }
+    }
+
+    /// Records a packet whose message failed to decode.
+    pub(super) fn undecodable<M: DmsMessage>(
+        peer: Option<&PublicKey>,
+        dms_key: &DmsKey,
+        packet: &Packet,
+        error: String,
+    ) -> Self {
+        Self {
+            timestamp: simperby_core::utils::get_timestamp(),
+            direction: Direction::Inbound,
+            peer: peer.cloned(),
+            dms_key: dms_key.clone(),
+            message_type: std::any::type_name::<M>().to_owned(),
+            message_hash: Hash256::hash(&packet.message),
+            committer: packet.commitment.committer.clone(),
+            size: packet.message.len() as u64,
+            verdict: Verdict::Rejected(error),
+            payload: None,
+        }
+    }
+}
+
+/// Where the commitments given to `receive()` come from.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Origin<'a> {
+    /// Committed elsewhere and added by the user; not recorded.
+    Local,
+    /// Received from the peer, if known.
+    Network(Option<&'a PublicKey>),
+}
+
+/// The writer of the tap file, shared by the DMSs of a node.
+#[derive(Clone)]
+pub struct MessageTap {
+    file: Arc<parking_lot::Mutex<TapFile>>,
+    redaction: PayloadRedaction,
+}
+
+struct TapFile {
+    path: PathBuf,
+    config: TapConfig,
+    file: std::fs::File,
+    size: u64,
+}
+
+impl MessageTap {
+    /// Opens the tap file, appending to it if it exists.
+    pub fn open(path: impl AsRef<Path>, config: TapConfig) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            redaction: config.redaction,
+            file: Arc::new(parking_lot::Mutex::new(TapFile {
+                path,
+                config,
+                file,
+                size,
+            })),
+        })
+    }
+
+    pub(super) fn redaction(&self) -> PayloadRedaction {
+        self.redaction
+    }
+
+    /// Appends the records, rotating the file if it's full.
+    ///
+    /// A failure is only logged, so that the tap never disturbs the DMS.
+    pub fn record(&self, records: &[TapRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        let mut file = self.file.lock();
+        if let Err(e) = file.append(records) {
+            log::warn!("failed to write the message tap: {e}");
+        }
+    }
+}
+
+impl TapFile {
+    fn append(&mut self, records: &[TapRecord]) -> Result<(), Error> {
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record)?);
+            lines.push('\n');
+        }
+        if self.size > 0 && self.size + lines.len() as u64 > self.config.max_file_size {
+            self.rotate()?;
+        }
+        self.file.write_all(lines.as_bytes())?;
+        self.size += lines.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Error> {
+        let rotated = |i: usize| PathBuf::from(format!("{}.{i}", self.path.display()));
+        let max = self.config.max_rotated_files;
+        if max == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..max).rev() {
+                if rotated(i).exists() {
+                    std::fs::rename(rotated(i), rotated(i + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<std::fs::File, Error> {
+    Ok(std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?)
+}
+
+/// Reads the records of a tap file as they're appended.
+pub struct TapReader {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl TapReader {
+    /// Starts reading from the beginning of the file.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            offset: 0,
+        }
+    }
+
+    /// Reads the records appended since the last call.
+    ///
+    /// A record being written is left for the next call.
+    /// If the file has shrunk, it's been rotated, so it starts over from the beginning;
+    /// the records appended to the old file just before the rotation may be missed.
+    pub fn read_new(&mut self) -> Result<Vec<TapRecord>, Error> {
+        let mut file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        let complete = data.rfind('\n').map_or(0, |i| i + 1);
+        self.offset += complete as u64;
+        data[..complete]
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for TapRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            Direction::Inbound => "<-",
+            Direction::Outbound => "->",
+        };
+        let peer = self
+            .peer
+            .as_ref()
+            .map_or_else(|| "?".to_owned(), ToString::to_string);
+        write!(
+            f,
+            "{} {} {} {} {} {} by {} ({} bytes) {:?}",
+            self.timestamp,
+            self.dms_key,
+            direction,
+            peer,
+            self.message_type,
+            self.message_hash,
+            self.committer,
+            self.size,
+            self.verdict
+        )?;
+        if let Some(payload) = &self.payload {
+            write!(f, " {payload}")?;
+        }
+        Ok(())
+    }
+}
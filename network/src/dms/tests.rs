@@ -40,13 +40,14 @@ fn generate_node_configs(
     let server_peer = Peer {
         public_key: keys[0].0.clone(),
         name: format!("{}", keys[0].0),
-        address: SocketAddrV4::new("127.0.0.1".parse().unwrap(), serving_node_port),
+        address: format!("127.0.0.1:{serving_node_port}").parse().unwrap(),
         ports: [(format!("dms-{network_id}"), serving_node_port)]
             .iter()
             .cloned()
             .collect(),
         message: "".to_owned(),
         recently_seen_timestamp: 0,
+        relay: None,
     };
 
     for i in 0..size - 1 {
@@ -55,6 +56,7 @@ fn generate_node_configs(
             members: keys.iter().map(|(x, _)| x).cloned().collect(),
             private_key: keys[i + 1].1.clone(),
             peers: vec![server_peer.clone()],
+            transport: Transport::Http,
         });
     }
     (
@@ -66,6 +68,9 @@ fn generate_node_configs(
                 .collect(),
             members: keys.iter().map(|(x, _)| x).cloned().collect(),
             private_key: keys[0].1.clone(),
+            relay: None,
+            relayed_peers: Vec::new(),
+            transport: Transport::Http,
         },
         client_configs,
         keys.into_iter().map(|(x, _)| x).collect(),
@@ -87,6 +92,7 @@ async fn single_1() {
         Config {
             dms_key: key,
             members: vec![network_config.private_key.public_key()],
+            priority_weights: Default::default(),
         },
         network_config.private_key.clone(),
     )
@@ -114,6 +120,398 @@ async fn single_1() {
     );
 }
 
+#[tokio::test]
+async fn batch_and_deduplicate() {
+    let key = generate_random_string();
+    let keys = (0..4)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let config = Config {
+        dms_key: key,
+        members: keys.iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut packets = Vec::new();
+    for (_, private_key) in &keys[1..] {
+        let mut dms = create_dms(config.clone(), private_key.clone()).await;
+        dms.commit_message(&"vote".to_owned()).await.unwrap();
+        dms.commit_message(&format!("{}", private_key.public_key()))
+            .await
+            .unwrap();
+        packets.extend(dms.retrieve_packets().await.unwrap());
+    }
+
+    // The same votes of the members share a batch.
+    let batches = PacketBatch::from_packets(packets);
+    assert_eq!(batches.len(), 4);
+    assert_eq!(
+        batches
+            .iter()
+            .map(|batch| batch.commitments.len())
+            .max()
+            .unwrap(),
+        3
+    );
+
+    let mut dms = create_dms(config, keys[0].1.clone()).await;
+    let packets = batches
+        .into_iter()
+        .flat_map(PacketBatch::into_packets)
+        .collect::<Vec<_>>();
+    dms.receive_packets([packets.clone(), packets].concat(), None, None)
+        .await
+        .unwrap();
+    let statistics = dms.get_sync_statistics();
+    assert_eq!(statistics.packets_received, 12);
+    assert_eq!(statistics.packets_deduplicated, 6);
+    let messages = dms.read_messages().await.unwrap();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(
+        messages
+            .iter()
+            .find(|message| message.message == "vote")
+            .unwrap()
+            .committers
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn relay_limit() {
+    let keys = (0..4)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let config = Config {
+        dms_key: generate_random_string(),
+        members: keys.iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut packets = Vec::new();
+    for (_, private_key) in &keys[1..] {
+        let mut dms = create_dms(config.clone(), private_key.clone()).await;
+        for i in 0..5 {
+            dms.commit_message(&format!("{i}")).await.unwrap();
+        }
+        packets.extend(dms.retrieve_packets().await.unwrap());
+    }
+
+    let mut relay = create_dms(config, keys[0].1.clone()).await;
+    // The last one reaches the set directly.
+    let mut limiter = relay::RelayLimiter::new(
+        RelayConfig {
+            packets_per_second: 1,
+            burst: 3,
+        },
+        keys[1..3].iter().map(|(x, _)| x.clone()),
+    );
+    relay
+        .receive_packets(packets, Some(&mut limiter), None)
+        .await
+        .unwrap();
+    // Each committer is limited by its own budget.
+    assert_eq!(relay.get_sync_statistics().packets_throttled, 4);
+    let committers = relay
+        .read_messages()
+        .await
+        .unwrap()
+        .into_iter()
+        .flat_map(|message| message.committers)
+        .map(|commitment| commitment.committer)
+        .collect::<Vec<_>>();
+    for (public_key, _) in &keys[1..3] {
+        assert_eq!(committers.iter().filter(|x| *x == public_key).count(), 3);
+    }
+    assert_eq!(committers.iter().filter(|x| **x == keys[3].0).count(), 5);
+}
+
+#[tokio::test]
+async fn unauthorized_packets() {
+    let keys = (0..3)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let dms_key = generate_random_string();
+    // The outsider (the last one) commits in a DMS of its own view.
+    let config = |n: usize| Config {
+        dms_key: dms_key.clone(),
+        members: keys[..n].iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut packets = Vec::new();
+    for (_, private_key) in &keys[1..] {
+        let mut dms = create_dms(config(3), private_key.clone()).await;
+        dms.commit_message(&"vote".to_owned()).await.unwrap();
+        packets.extend(dms.retrieve_packets().await.unwrap());
+    }
+
+    let mut dms = create_dms(config(2), keys[0].1.clone()).await;
+    dms.receive_packets(packets.clone(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(dms.get_sync_statistics().packets_unauthorized, 1);
+    let messages = dms.read_messages().await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].committers.len(), 1);
+    assert_eq!(messages[0].committers[0].committer, keys[1].0);
+    assert!(dms
+        .add_committed_message(&"vote".to_owned(), packets[1].commitment.clone())
+        .await
+        .is_err());
+
+    // The peer that serves them is penalized, and eventually no longer dialed.
+    let peer = &keys[1].0;
+    dms.penalize(peer, MAX_UNAUTHORIZED_PACKETS - 1);
+    assert!(!dms.is_banned(peer));
+    dms.penalize(peer, 1);
+    assert!(dms.is_banned(peer));
+    assert_eq!(dms.get_penalties()[peer], MAX_UNAUTHORIZED_PACKETS);
+}
+
+#[tokio::test]
+async fn banned_members() {
+    let keys = (0..3)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let config = Config {
+        dms_key: generate_random_string(),
+        members: keys.iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut packets = Vec::new();
+    for (_, private_key) in &keys[1..] {
+        let mut dms = create_dms(config.clone(), private_key.clone()).await;
+        dms.commit_message(&"vote".to_owned()).await.unwrap();
+        packets.extend(dms.retrieve_packets().await.unwrap());
+    }
+
+    let mut dms = create_dms(config, keys[0].1.clone()).await;
+    let banned = &keys[2].0;
+    dms.set_banned_members(vec![banned.clone()]);
+    assert!(dms.is_banned(banned));
+    dms.receive_packets(packets, None, None).await.unwrap();
+    assert_eq!(dms.get_sync_statistics().packets_banned, 1);
+    assert_eq!(dms.get_sync_statistics().packets_unauthorized, 0);
+    let messages = dms.read_messages().await.unwrap();
+    assert_eq!(messages[0].committers.len(), 1);
+    assert_eq!(messages[0].committers[0].committer, keys[1].0);
+
+    dms.set_banned_members(Vec::new());
+    assert!(!dms.is_banned(banned));
+}
+
+#[tokio::test]
+async fn acknowledgments() {
+    let keys = (0..3)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let config = Config {
+        dms_key: generate_random_string(),
+        members: keys.iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut sender = create_dms(config.clone(), keys[0].1.clone()).await;
+    let mut receiver = create_dms(config, keys[1].1.clone()).await;
+    let message = "vote".to_owned();
+    sender.commit_message(&message).await.unwrap();
+    let message_hash = message.to_hash256();
+    assert!(receiver
+        .acknowledge(&[message_hash])
+        .await
+        .unwrap()
+        .is_empty());
+    receiver
+        .receive_packets(sender.retrieve_packets().await.unwrap(), None, None)
+        .await
+        .unwrap();
+    let acks = receiver.acknowledge(&[message_hash]).await.unwrap();
+    assert_eq!(acks.len(), 1);
+
+    // Not signed by the peer.
+    sender.record_acks(&keys[2].0, acks.clone());
+    let propagation = sender
+        .get_message_propagation(message_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(propagation.acknowledged.is_empty());
+    assert_eq!(
+        propagation.pending,
+        vec![keys[1].0.clone(), keys[2].0.clone()]
+    );
+
+    sender.record_acks(&keys[1].0, acks);
+    let propagation = sender
+        .get_message_propagation(message_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(propagation.acknowledged.len(), 1);
+    assert_eq!(propagation.acknowledged[0].0, keys[1].0);
+    assert_eq!(propagation.pending, vec![keys[2].0.clone()]);
+    // Not asked again from the peer that has acknowledged it.
+    assert!(sender
+        .unacknowledged(&keys[1].0, &[message_hash])
+        .is_empty());
+    assert_eq!(
+        sender.unacknowledged(&keys[2].0, &[message_hash]),
+        vec![message_hash]
+    );
+    assert!(sender
+        .get_message_propagation(Hash256::hash("unknown"))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn read_only() {
+    let keys = (0..2)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let config = Config {
+        dms_key: generate_random_string(),
+        members: keys.iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut member = create_dms(config.clone(), keys[0].1.clone()).await;
+    let path = create_temp_dir();
+    StorageImpl::create(&path).await.unwrap();
+    let storage = StorageImpl::open(&path).await.unwrap();
+    let mut observer = Dms::new_read_only(storage, config).await.unwrap();
+
+    let message = "vote".to_owned();
+    member.commit_message(&message).await.unwrap();
+    observer
+        .receive_packets(member.retrieve_packets().await.unwrap(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(observer.read_messages().await.unwrap()[0].message, message);
+    assert!(observer
+        .acknowledge(&[message.to_hash256()])
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(observer
+        .commit_message(&"another".to_owned())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn message_tap() {
+    let keys = (0..3)
+        .map(|_| generate_keypair_random())
+        .collect::<Vec<_>>();
+    let dms_key = generate_random_string();
+    let config = |n: usize| Config {
+        dms_key: dms_key.clone(),
+        members: keys[..n].iter().map(|(x, _)| x).cloned().collect(),
+        priority_weights: Default::default(),
+    };
+    let mut packets = Vec::new();
+    for (_, private_key) in &keys[1..] {
+        let mut dms = create_dms(config(3), private_key.clone()).await;
+        dms.commit_message(&"vote".to_owned()).await.unwrap();
+        packets.extend(dms.retrieve_packets().await.unwrap());
+    }
+
+    let path = format!("{}/tap.jsonl", create_temp_dir());
+    let tap = MessageTap::open(
+        &path,
+        TapConfig {
+            redaction: PayloadRedaction::Truncate(3),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut reader = TapReader::new(&path);
+    let mut dms = create_dms(config(2), keys[0].1.clone()).await;
+    dms.set_tap(Some(tap));
+    let peer = &keys[1].0;
+    dms.receive_packets(packets.clone(), None, Some(peer))
+        .await
+        .unwrap();
+    dms.receive_packets(packets[..1].to_vec(), None, None)
+        .await
+        .unwrap();
+    // Not from the network.
+    dms.commit_message(&"local".to_owned()).await.unwrap();
+
+    let records = reader.read_new().unwrap();
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| (record.verdict.clone(), record.peer.as_ref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (Verdict::Unauthorized, Some(peer)),
+            (Verdict::Accepted, Some(peer)),
+            (Verdict::Duplicate, None),
+        ]
+    );
+    for record in &records {
+        assert_eq!(record.direction, Direction::Inbound);
+        assert_eq!(record.dms_key, dms_key);
+        assert_eq!(record.message_hash, "vote".to_owned().to_hash256());
+        assert_eq!(record.payload.as_deref(), Some("\"vo"));
+    }
+    assert_eq!(records[0].committer, keys[2].0);
+
+    dms.tap_sent(
+        Some(peer),
+        &dms.retrieve_packets().await.unwrap(),
+        Verdict::Sent,
+    );
+    let records = reader.read_new().unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records
+        .iter()
+        .all(|record| record.direction == Direction::Outbound && record.verdict == Verdict::Sent));
+    assert!(reader.read_new().unwrap().is_empty());
+}
+
+#[test]
+fn message_tap_rotation() {
+    let path = format!("{}/tap.jsonl", create_temp_dir());
+    let tap = MessageTap::open(
+        &path,
+        TapConfig {
+            max_file_size: 1,
+            max_rotated_files: 2,
+            redaction: PayloadRedaction::Full,
+        },
+    )
+    .unwrap();
+    let (public_key, _) = generate_keypair_random();
+    let record = |i: usize| {
+        TapRecord::new(
+            Direction::Outbound,
+            None,
+            &"key".to_owned(),
+            &format!("{i}"),
+            &public_key,
+            Verdict::Sent,
+            PayloadRedaction::Full,
+        )
+    };
+    let records = (0..4).map(record).collect::<Vec<_>>();
+    let mut reader = TapReader::new(&path);
+    for record in &records {
+        tap.record(std::slice::from_ref(record));
+    }
+    // Each record exceeds the limit, so the file has been rotated on every write.
+    assert_eq!(reader.read_new().unwrap(), vec![records[3].clone()]);
+    let payload = |path: String| {
+        TapReader::new(path).read_new().unwrap()[0]
+            .payload
+            .clone()
+            .unwrap()
+    };
+    assert_eq!(payload(format!("{path}.1")), "\"2\"");
+    assert_eq!(payload(format!("{path}.2")), "\"1\"");
+    assert!(!std::path::Path::new(&format!("{path}.3")).exists());
+}
+
 async fn run_client_node(
     dms: Arc<RwLock<Dms>>,
     message_to_create: Vec<usize>,
@@ -150,6 +548,7 @@ async fn multi_1() {
             Config {
                 dms_key: key.clone(),
                 members: members.clone(),
+                priority_weights: Default::default(),
             },
             server_network_config.private_key.clone(),
         )
@@ -165,6 +564,7 @@ async fn multi_1() {
                 Config {
                     dms_key: key.clone(),
                     members: members.clone(),
+                    priority_weights: Default::default(),
                 },
                 client_network_config.private_key.clone(),
             )
@@ -204,3 +604,170 @@ async fn multi_1() {
         );
     }
 }
+
+#[tokio::test]
+async fn handshake_1() {
+    let (server_network_config, client_network_configs, members) =
+        generate_node_configs(dispense_port(), 2);
+    let key = server_network_config.network_id.clone();
+    let server_dms = Arc::new(RwLock::new(
+        create_dms(
+            Config {
+                dms_key: key.clone(),
+                members,
+                priority_weights: Default::default(),
+            },
+            server_network_config.private_key.clone(),
+        )
+        .await,
+    ));
+    tokio::spawn(serve(server_dms, server_network_config));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut peer = client_network_configs[0].peers[0].clone();
+    let report = handshake(&peer, &key, None, Transport::Http).await.unwrap();
+    assert_eq!(report.protocol_version, PROTOCOL_VERSION);
+    assert!(report.key_verified);
+
+    // The peer is reachable, but doesn't hold the key it is known by.
+    peer.public_key = generate_keypair_random().0;
+    let report = handshake(&peer, &key, None, Transport::Http).await.unwrap();
+    assert!(!report.key_verified);
+
+    peer.address.set_port(dispense_port());
+    peer.ports.insert(format!("dms-{key}"), peer.address.port());
+    assert!(handshake(&peer, &key, None, Transport::Http).await.is_err());
+}
+
+#[tokio::test]
+async fn chain_mismatch_1() {
+    let (server_network_config, client_network_configs, members) =
+        generate_node_configs(dispense_port(), 2);
+    let key = server_network_config.network_id.clone();
+    let config = Config {
+        dms_key: key.clone(),
+        members,
+        priority_weights: Default::default(),
+    };
+    let chain = |name: &str| ChainId {
+        chain_name: name.to_owned(),
+        genesis_hash: Hash256::hash(name),
+    };
+    let mut server_dms =
+        create_dms(config.clone(), server_network_config.private_key.clone()).await;
+    server_dms.set_chain_id(Some(chain("mainnet")));
+    server_dms
+        .commit_message(&"hello".to_owned())
+        .await
+        .unwrap();
+    tokio::spawn(serve(
+        Arc::new(RwLock::new(server_dms)),
+        server_network_config,
+    ));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let client_network_config = client_network_configs[0].clone();
+    let peer = &client_network_config.peers[0];
+    let report = handshake(peer, &key, Some(&chain("mainnet")), Transport::Http)
+        .await
+        .unwrap();
+    assert_eq!(report.chain_id, Some(chain("mainnet")));
+    let error = handshake(peer, &key, Some(&chain("testnet")), Transport::Http)
+        .await
+        .unwrap_err();
+    assert!(error.is::<ChainMismatchError>());
+
+    let mut client_dms = create_dms(config, client_network_config.private_key.clone()).await;
+    client_dms.set_chain_id(Some(chain("testnet")));
+    let client_dms = Arc::new(RwLock::new(client_dms));
+    Dms::fetch(Arc::clone(&client_dms), &client_network_config)
+        .await
+        .unwrap();
+    Dms::fetch(Arc::clone(&client_dms), &client_network_config)
+        .await
+        .unwrap();
+    let client_dms = client_dms.read().await;
+    assert!(client_dms.read_messages().await.unwrap().is_empty());
+    let mismatches = client_dms.get_chain_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].peer, peer.public_key);
+    assert_eq!(mismatches[0].remote, chain("mainnet"));
+    assert_eq!(mismatches[0].count, 2);
+}
+
+#[tokio::test]
+async fn quic_1() {
+    let (mut server_network_config, client_network_configs, members) =
+        generate_node_configs(dispense_port(), 3);
+    server_network_config.transport = Transport::Quic;
+    let key = server_network_config.network_id.clone();
+    let config = Config {
+        dms_key: key.clone(),
+        members,
+        priority_weights: Default::default(),
+    };
+    let mut server_dms =
+        create_dms(config.clone(), server_network_config.private_key.clone()).await;
+    server_dms
+        .commit_message(&"hello".to_owned())
+        .await
+        .unwrap();
+    tokio::spawn(serve(
+        Arc::new(RwLock::new(server_dms)),
+        server_network_config,
+    ));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let peer = &client_network_configs[0].peers[0];
+    let report = handshake(peer, &key, None, Transport::Quic).await.unwrap();
+    assert_eq!(report.protocol_version, PROTOCOL_VERSION);
+    assert!(report.key_verified);
+    // The HTTP server is still there.
+    assert!(handshake(peer, &key, None, Transport::Http).await.is_ok());
+
+    // The peer doesn't hold the key it is known by, which fails the connection over QUIC.
+    let mut impostor = peer.clone();
+    impostor.public_key = generate_keypair_random().0;
+    assert!(handshake(&impostor, &key, None, Transport::Quic)
+        .await
+        .is_err());
+    let report = handshake(&impostor, &key, None, Transport::Http)
+        .await
+        .unwrap();
+    assert!(!report.key_verified);
+
+    let mut client_dmses = Vec::new();
+    for (i, client_network_config) in client_network_configs.iter().enumerate() {
+        let mut client_network_config = client_network_config.clone();
+        client_network_config.transport = Transport::Quic;
+        let mut dms = create_dms(config.clone(), client_network_config.private_key.clone()).await;
+        dms.commit_message(&format!("{i}")).await.unwrap();
+        let dms = Arc::new(RwLock::new(dms));
+        Dms::broadcast(Arc::clone(&dms), &client_network_config)
+            .await
+            .unwrap();
+        client_dmses.push((dms, client_network_config));
+    }
+    // Each fetch reconnects, verifying the key of the server again.
+    for (dms, client_network_config) in client_dmses {
+        Dms::fetch(Arc::clone(&dms), &client_network_config)
+            .await
+            .unwrap();
+        let messages = dms
+            .read()
+            .await
+            .read_messages()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.message)
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(
+            messages,
+            ["hello", "0", "1"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<std::collections::BTreeSet<_>>()
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Signed heartbeats for tracking the liveness of the network members.
+//!
+//! Each node periodically commits a [`Heartbeat`] to a dedicated DMS,
+//! which is gossiped like any other DMS message.
+//! Since the commitment is signed, a heartbeat can't be forged on behalf of another member.
+use super::*;
+use crate::dms::DistributedMessageSet;
+use simperby_core::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// A heartbeat message. The committers of the message are the members who were alive at `timestamp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub timestamp: Timestamp,
+}
+
+impl ToHash256 for Heartbeat {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+impl DmsMessage for Heartbeat {
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Generates the DMS key for the heartbeats of the given network.
+pub fn generate_dms_key(network_id: &str) -> DmsKey {
+    format!("heartbeat-{network_id}")
+}
+
+/// The liveness of a member, observed from the heartbeats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberLiveness {
+    pub member: PublicKey,
+    /// The timestamp of the latest heartbeat. `None` if it has never been seen.
+    pub last_seen: Option<Timestamp>,
+}
+
+impl MemberLiveness {
+    /// Returns whether the member has been seen within `threshold_ms` before `now`.
+    pub fn is_online(&self, now: Timestamp, threshold_ms: Timestamp) -> bool {
+        self.last_seen
+            .map(|last_seen| now - last_seen <= threshold_ms)
+            .unwrap_or(false)
+    }
+}
+
+/// Commits a heartbeat of this node.
+pub async fn beat<S: Storage>(
+    dms: &mut DistributedMessageSet<S, Heartbeat>,
+    timestamp: Timestamp,
+) -> Result<(), Error> {
+    dms.commit_message(&Heartbeat { timestamp }).await
+}
+
+/// Removes the heartbeats older than `before`, which are no longer needed to track the liveness.
+pub async fn prune<S: Storage>(
+    dms: &mut DistributedMessageSet<S, Heartbeat>,
+    before: Timestamp,
+) -> Result<(), Error> {
+    for message in dms.read_messages().await? {
+        if message.message.timestamp < before {
+            dms.remove_message(message.message.to_hash256(), None)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the liveness of the given members from the heartbeats.
+pub async fn read_liveness<S: Storage>(
+    dms: &DistributedMessageSet<S, Heartbeat>,
+    members: &[PublicKey],
+) -> Result<Vec<MemberLiveness>, Error> {
+    let messages = dms.read_messages().await?;
+    let result = members
+        .iter()
+        .map(|member| MemberLiveness {
+            member: member.clone(),
+            last_seen: messages
+                .iter()
+                .filter(|message| {
+                    message
+                        .committers
+                        .iter()
+                        .any(|proof| &proof.committer == member)
+                })
+                .map(|message| message.message.timestamp)
+                .max(),
+        })
+        .collect();
+    Ok(result)
+}
+
+/// Runs the heartbeat of this node indefinitely. This function will block the current thread.
+///
+/// Gossiping the heartbeats is done by [`dms::sync()`] like other DMS instances.
+pub async fn run<S: Storage>(
+    dms: Arc<RwLock<DistributedMessageSet<S, Heartbeat>>>,
+    interval: Duration,
+    retention: Duration,
+) -> Result<(), Error> {
+    loop {
+        let now = simperby_core::utils::get_timestamp();
+        let mut dms_ = dms.write().await;
+        if let Err(e) = beat(&mut dms_, now).await {
+            log::warn!("failed to commit a heartbeat: {}", e);
+        }
+        if let Err(e) = prune(&mut dms_, now - retention.as_millis() as Timestamp).await {
+            log::warn!("failed to prune the heartbeats: {}", e);
+        }
+        drop(dms_);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageImpl;
+    use simperby_test_suite::*;
+
+    #[tokio::test]
+    async fn liveness() {
+        let (me, my_key) = generate_keypair_random();
+        let (other, _) = generate_keypair_random();
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let storage = StorageImpl::open(&path).await.unwrap();
+        let mut dms = DistributedMessageSet::<_, Heartbeat>::new(
+            storage,
+            dms::Config {
+                dms_key: generate_dms_key("test"),
+                members: vec![me.clone(), other.clone()],
+                priority_weights: Default::default(),
+            },
+            my_key,
+        )
+        .await
+        .unwrap();
+
+        beat(&mut dms, 10).await.unwrap();
+        beat(&mut dms, 20).await.unwrap();
+        let liveness = read_liveness(&dms, &[me.clone(), other.clone()])
+            .await
+            .unwrap();
+        assert_eq!(liveness[0].last_seen, Some(20));
+        assert_eq!(liveness[1].last_seen, None);
+        assert!(liveness[0].is_online(25, 10));
+        assert!(!liveness[0].is_online(35, 10));
+        assert!(!liveness[1].is_online(25, 10));
+
+        prune(&mut dms, 15).await.unwrap();
+        assert_eq!(dms.read_messages().await.unwrap().len(), 1);
+    }
+}
@@ -1,4 +1,6 @@
+mod address;
 pub mod dms;
+pub mod heartbeat;
 #[cfg(never)]
 mod peer_discovery;
 pub mod primitives;
@@ -7,12 +9,12 @@ pub mod storage;
 use serde::{Deserialize, Serialize};
 use simperby_core::{crypto::*, MemberName, Timestamp};
 use std::collections::HashMap;
-use std::net::SocketAddrV4;
 
 pub type Error = eyre::Error;
 pub type Dms<T> = dms::DistributedMessageSet<storage::StorageImpl, T>;
 
-pub use dms::{DmsKey, DmsMessage, Message, MessageCommitmentProof};
+pub use address::PeerAddress;
+pub use dms::{DmsKey, DmsMessage, Message, MessageCommitmentProof, MessagePriority, Transport};
 pub use primitives::*;
 pub use storage::StorageImpl;
 
@@ -22,12 +24,18 @@ pub struct Peer {
     pub public_key: PublicKey,
     pub name: MemberName,
     /// The address used for the discovery protocol
-    pub address: SocketAddrV4,
+    pub address: PeerAddress,
     /// For the other network services like gossip or RPC,
     /// it provides a map of `identifier->port`.
     pub ports: HashMap<String, u16>,
     pub message: String,
     pub recently_seen_timestamp: Timestamp,
+    /// The member that relays the DMS traffic for this peer,
+    /// if the peer is behind a NAT and can't accept inbound connections.
+    ///
+    /// Such a peer is never dialed; it dials the relay instead (see [`dms::RelayConfig`]).
+    #[serde(default)]
+    pub relay: Option<PublicKey>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +48,9 @@ pub struct ClientNetworkConfig {
     pub private_key: PrivateKey,
     /// The peer nodes to broadcast the message.
     pub peers: Vec<Peer>,
+    /// The transport to reach the peers with.
+    #[serde(default)]
+    pub transport: Transport,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,5 +66,15 @@ pub struct ServerNetworkConfig {
     /// The server advertises this port mappings on the peer discovery protocol,
     /// so that other peers can know on which port the server provides a specific service.
     pub ports: HashMap<String, u16>,
-    // TODO: add various configurations for NAT traversal
+    /// If set, the node relays the DMS traffic of the peers behind a NAT, with the limit.
+    #[serde(default)]
+    pub relay: Option<dms::RelayConfig>,
+    /// The peers behind a NAT that this node relays for (see [`Peer::relay`]),
+    /// whose packets are limited by `relay`.
+    #[serde(default)]
+    pub relayed_peers: Vec<PublicKey>,
+    /// If set to [`Transport::Quic`], the DMS servers serve QUIC on the UDP ports
+    /// of the same numbers, in addition to HTTP.
+    #[serde(default)]
+    pub transport: Transport,
 }
@@ -18,6 +18,8 @@ pub trait Storage: Send + Sync + 'static {
     async fn list_files(&self) -> Result<Vec<String>, StorageError>;
 
     /// Adds the given file to the storage.
+    ///
+    /// The write is atomic; a crash leaves either the previous content or the new one.
     async fn add_or_overwrite_file(
         &mut self,
         name: &str,
@@ -31,5 +33,12 @@ pub trait Storage: Send + Sync + 'static {
     async fn remove_file(&mut self, name: &str) -> Result<(), StorageError>;
 
     /// Removes all files.
+    ///
+    /// If interrupted by a crash, the removal is completed on the next `open()`.
     async fn remove_all_files(&mut self) -> Result<(), StorageError>;
+
+    /// Makes all the previous mutations durable, even against a crash of the machine.
+    ///
+    /// Must be called before anything depending on them leaves the node.
+    async fn checkpoint(&mut self) -> Result<(), StorageError>;
 }
@@ -4,6 +4,74 @@ use fs2::FileExt;
 use futures::stream::*;
 use tokio::{fs, io::AsyncWriteExt, task::spawn_blocking};
 
+/// The prefix of a file being written, which is renamed to the actual name once complete.
+const PARTIAL_FILE_PREFIX: &str = ".partial-";
+/// The marker of `remove_all_files()` in progress, which is removed once complete.
+const CLEARING_MARKER_FILE_NAME: &str = ".clearing";
+/// The file recording the namespace that the storage belongs to.
+pub const NAMESPACE_FILE_NAME: &str = ".namespace";
+
+fn is_internal_file(name: &str) -> bool {
+    name == "lock"
+        || name == CLEARING_MARKER_FILE_NAME
+        || name == NAMESPACE_FILE_NAME
+        || name.starts_with(PARTIAL_FILE_PREFIX)
+}
+
+/// Writes the file aside and then renames it, so that an interrupted write never leaves
+/// a half-written file but the previous one.
+async fn write_file_atomically(
+    storage_directory: &str,
+    name: &str,
+    content: &str,
+) -> Result<(), StorageError> {
+    let partial_path = format!("{storage_directory}/{PARTIAL_FILE_PREFIX}{name}");
+    let mut file = fs::File::create(&partial_path).await?;
+    file.write_all(content.as_bytes()).await?;
+    // IMPORTANT!
+    file.flush().await?;
+    // The content must be on the disk before the rename makes it visible.
+    file.sync_data().await?;
+    drop(file);
+    fs::rename(partial_path, format!("{storage_directory}/{name}")).await
+}
+
+/// Discards the torn writes and completes the interrupted `remove_all_files()`,
+/// left by a crash or a kill.
+async fn recover(storage_directory: &str) -> Result<(), StorageError> {
+    let clearing = fs::metadata(format!("{storage_directory}/{CLEARING_MARKER_FILE_NAME}"))
+        .await
+        .is_ok();
+    let mut dir = fs::read_dir(storage_directory).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(PARTIAL_FILE_PREFIX) {
+            log::warn!("discarding a torn write in {storage_directory}: {name}");
+            fs::remove_file(entry.path()).await?;
+        } else if clearing && !is_internal_file(&name) {
+            fs::remove_file(entry.path()).await?;
+        }
+    }
+    if clearing {
+        log::warn!("completed the interrupted removal of all files in {storage_directory}");
+        fs::remove_file(format!("{storage_directory}/{CLEARING_MARKER_FILE_NAME}")).await?;
+    }
+    sync_directory(storage_directory).await
+}
+
+/// Makes the creations, the renames and the removals of the files in the directory durable.
+async fn sync_directory(path: &str) -> Result<(), StorageError> {
+    #[cfg(unix)]
+    {
+        let path = path.to_owned();
+        spawn_blocking(move || std::fs::File::open(path)?.sync_all()).await??;
+    }
+    // A directory can't be opened on the other platforms, where the metadata is synced with the files.
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
 pub struct StorageImpl {
     lock_file: Option<std::fs::File>,
     path: String,
@@ -31,6 +99,7 @@ impl Storage for StorageImpl {
             result.map(|_| file)
         })
         .await??;
+        recover(storage_directory).await?;
         Ok(Self {
             lock_file: Some(file),
             path: storage_directory.to_owned(),
@@ -47,7 +116,7 @@ impl Storage for StorageImpl {
         Ok(files
             .into_iter()
             .map(|file| file.file_name().into_string().unwrap())
-            .filter(|file| file != "lock")
+            .filter(|file| !is_internal_file(file))
             .collect())
     }
 
@@ -56,11 +125,7 @@ impl Storage for StorageImpl {
         name: &str,
         content: String,
     ) -> Result<(), StorageError> {
-        let mut file = fs::File::create(format!("{}/{}", self.path, name)).await?;
-        file.write_all(content.as_bytes()).await?;
-        // IMPORTANT!
-        file.flush().await?;
-        Ok(())
+        write_file_atomically(&self.path, name, &content).await
     }
 
     async fn read_file(&self, name: &str) -> Result<String, StorageError> {
@@ -72,11 +137,61 @@ impl Storage for StorageImpl {
     }
 
     async fn remove_all_files(&mut self) -> Result<(), StorageError> {
+        // Marked first, so that a crash in the middle never leaves only some of the files.
+        let marker_path = format!("{}/{CLEARING_MARKER_FILE_NAME}", self.path);
+        fs::File::create(&marker_path).await?.sync_all().await?;
+        sync_directory(&self.path).await?;
         let files = self.list_files().await?;
         for file in files {
             self.remove_file(&file).await?;
         }
-        Ok(())
+        fs::remove_file(marker_path).await?;
+        sync_directory(&self.path).await
+    }
+
+    async fn checkpoint(&mut self) -> Result<(), StorageError> {
+        // The contents are synced by each write; what remains is the directory entries.
+        sync_directory(&self.path).await
+    }
+}
+
+impl StorageImpl {
+    /// Creates a new and empty directory (like `create()`) that belongs to the given namespace.
+    pub async fn create_in_namespace(
+        storage_directory: &str,
+        namespace: &str,
+    ) -> Result<(), StorageError> {
+        Self::create(storage_directory).await?;
+        write_file_atomically(storage_directory, NAMESPACE_FILE_NAME, namespace).await?;
+        sync_directory(storage_directory).await
+    }
+
+    /// Opens an existing directory (like `open()`),
+    /// failing unless it has been created for the given namespace.
+    ///
+    /// This prevents a subsystem from reading or overwriting the storage of another one
+    /// when their paths are mixed up.
+    pub async fn open_in_namespace(
+        storage_directory: &str,
+        namespace: &str,
+    ) -> Result<Self, StorageError> {
+        let storage = Self::open(storage_directory).await?;
+        let invalid = |message| Err(StorageError::new(std::io::ErrorKind::InvalidData, message));
+        match fs::read_to_string(format!("{storage_directory}/{NAMESPACE_FILE_NAME}")).await {
+            Ok(recorded) if recorded == namespace => {}
+            Ok(recorded) => {
+                return invalid(format!(
+                    "the storage at {storage_directory} belongs to `{recorded}`, not `{namespace}`"
+                ))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return invalid(format!(
+                    "the storage at {storage_directory} has no namespace; it must be migrated"
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(storage)
     }
 }
 
@@ -163,4 +278,90 @@ mod tests {
         // assert that files are removed
         assert_eq!(storage.list_files().await.unwrap().len(), 0);
     }
+
+    #[tokio::test]
+    async fn interrupted_write() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        storage
+            .add_or_overwrite_file("a", "complete".to_owned())
+            .await
+            .unwrap();
+        // As if killed while overwriting.
+        fs::write(format!("{dir}/{PARTIAL_FILE_PREFIX}a"), "compl")
+            .await
+            .unwrap();
+        assert_eq!(storage.list_files().await.unwrap(), vec!["a".to_owned()]);
+        assert_eq!(storage.read_file("a").await.unwrap(), "complete");
+        drop(storage);
+
+        let storage = StorageImpl::open(&dir).await.unwrap();
+        assert!(!std::path::Path::new(&format!("{dir}/{PARTIAL_FILE_PREFIX}a")).exists());
+        assert_eq!(storage.read_file("a").await.unwrap(), "complete");
+    }
+
+    #[tokio::test]
+    async fn interrupted_clear() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        for name in ["a", "b", "c"] {
+            storage
+                .add_or_overwrite_file(name, name.to_owned())
+                .await
+                .unwrap();
+        }
+        storage.checkpoint().await.unwrap();
+        // As if killed after removing `a` in `remove_all_files()`.
+        fs::write(format!("{dir}/{CLEARING_MARKER_FILE_NAME}"), "")
+            .await
+            .unwrap();
+        storage.remove_file("a").await.unwrap();
+        assert_eq!(storage.list_files().await.unwrap().len(), 2);
+        drop(storage);
+
+        let mut storage = StorageImpl::open(&dir).await.unwrap();
+        assert!(storage.list_files().await.unwrap().is_empty());
+        assert!(!std::path::Path::new(&format!("{dir}/{CLEARING_MARKER_FILE_NAME}")).exists());
+        storage
+            .add_or_overwrite_file("d", "d".to_owned())
+            .await
+            .unwrap();
+        storage.checkpoint().await.unwrap();
+        drop(storage);
+        let storage = StorageImpl::open(&dir).await.unwrap();
+        assert_eq!(storage.list_files().await.unwrap(), vec!["d".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn namespace() {
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create_in_namespace(&dir, "consensus-state")
+            .await
+            .unwrap();
+        let mut storage = StorageImpl::open_in_namespace(&dir, "consensus-state")
+            .await
+            .unwrap();
+        storage
+            .add_or_overwrite_file("state", "state".to_owned())
+            .await
+            .unwrap();
+        storage.remove_all_files().await.unwrap();
+        assert!(storage.list_files().await.unwrap().is_empty());
+        drop(storage);
+
+        StorageImpl::open_in_namespace(&dir, "consensus-state")
+            .await
+            .unwrap();
+        assert!(StorageImpl::open_in_namespace(&dir, "governance-dms")
+            .await
+            .is_err());
+
+        let dir = gerenate_random_storage_directory();
+        StorageImpl::create(&dir).await.unwrap();
+        assert!(StorageImpl::open_in_namespace(&dir, "consensus-state")
+            .await
+            .is_err());
+    }
 }
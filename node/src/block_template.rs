@@ -0,0 +1,112 @@
+//! Customization of the blocks that this node proposes.
+//!
+//! A block finalizes at most one agenda, which is chosen among the governance-approved ones
+//! when the node creates a block. A proposer that wants a say in it can set
+//! a [`BlockTemplateFilter`] on the node, which reorders or excludes the candidates.
+use simperby_core::*;
+use simperby_repository::CommitHash;
+
+/// A governance-approved agenda that a block can be created on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgendaCandidate {
+    /// The agenda proof commit, on which the block would be created.
+    pub commit_hash: CommitHash,
+    pub agenda: Agenda,
+    pub proof: AgendaProof,
+}
+
+/// Decides which agendas the blocks of this node may finalize, in the order of preference.
+///
+/// The node creates the block on the first agenda returned.
+/// Closures of `Fn(Vec<AgendaCandidate>) -> Vec<AgendaCandidate>` are filters as well.
+pub trait BlockTemplateFilter: Send + Sync {
+    /// Receives the candidates in the order of [`sort_candidates`].
+    ///
+    /// Candidates that are not given are ignored in the result.
+    fn filter(&self, candidates: Vec<AgendaCandidate>) -> Vec<AgendaCandidate>;
+}
+
+impl<F> BlockTemplateFilter for F
+where
+    F: Fn(Vec<AgendaCandidate>) -> Vec<AgendaCandidate> + Send + Sync,
+{
+    fn filter(&self, candidates: Vec<AgendaCandidate>) -> Vec<AgendaCandidate> {
+        self(candidates)
+    }
+}
+
+/// Takes all the approved agendas as they are given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultBlockTemplateFilter;
+
+impl BlockTemplateFilter for DefaultBlockTemplateFilter {
+    fn filter(&self, candidates: Vec<AgendaCandidate>) -> Vec<AgendaCandidate> {
+        candidates
+    }
+}
+
+/// Sorts the candidates in the canonical order of [`AgendaProof::canonical_cmp`].
+pub fn sort_candidates(candidates: &mut [AgendaCandidate]) {
+    candidates.sort_by(|a, b| a.proof.canonical_cmp(&b.proof));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the candidates of the same height and timestamp, in the order of the given bytes.
+    fn candidates(bytes: &[u8]) -> Vec<AgendaCandidate> {
+        bytes
+            .iter()
+            .map(|&byte| {
+                let agenda = Agenda {
+                    height: 1,
+                    author: "member-0000".to_owned(),
+                    timestamp: 0,
+                    transactions_hash: Hash256::hash([byte]),
+                    description: None,
+                    deadline: None,
+                };
+                AgendaCandidate {
+                    commit_hash: CommitHash { hash: [byte; 20] },
+                    proof: AgendaProof {
+                        height: 1,
+                        agenda_hash: agenda.to_hash256(),
+                        proof: Vec::new(),
+                        timestamp: 0,
+                    },
+                    agenda,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_agenda_hash() {
+        let mut sorted = candidates(&[0, 1, 2, 3]);
+        sort_candidates(&mut sorted);
+        for pair in sorted.windows(2) {
+            assert!(pair[0].proof.agenda_hash < pair[1].proof.agenda_hash);
+        }
+        let mut shuffled = candidates(&[3, 1, 0, 2]);
+        sort_candidates(&mut shuffled);
+        assert_eq!(shuffled, sorted);
+    }
+
+    #[test]
+    fn apply_filters() {
+        let given = candidates(&[0, 1, 2]);
+        assert_eq!(DefaultBlockTemplateFilter.filter(given.clone()), given);
+
+        let reverse = |mut candidates: Vec<AgendaCandidate>| {
+            candidates.reverse();
+            candidates
+        };
+        let filter: Box<dyn BlockTemplateFilter> = Box::new(reverse);
+        let filtered = filter.filter(given.clone());
+        assert_eq!(filtered.first(), given.last());
+
+        let exclude_all = |_: Vec<AgendaCandidate>| Vec::new();
+        assert!(exclude_all.filter(given).is_empty());
+    }
+}
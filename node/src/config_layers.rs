@@ -0,0 +1,400 @@
+//! Loading the config of the node from the layered sources.
+//!
+//! The fields are read from `config.json` (with the defaults for the missing ones),
+//! then overridden by the environment variables, and then by the CLI flags.
+//! The result is checked by [`Config::validate`] before the node starts.
+use super::*;
+use config_watch::CONFIG_FILE_NAME;
+use eyre::eyre;
+use serde_json::Value;
+
+pub const DEFAULT_GOVERNANCE_PORT: u16 = 1155;
+pub const DEFAULT_CONSENSUS_PORT: u16 = 1166;
+pub const DEFAULT_REPOSITORY_PORT: u16 = 1177;
+pub const DEFAULT_HEARTBEAT_PORT: u16 = 1188;
+pub const DEFAULT_BLOB_PORT: u16 = 1199;
+pub const DEFAULT_TRANSACTION_POOL_PORT: u16 = 1210;
+
+/// The prefix of the environment variables that override the config.
+///
+/// The rest of the name is the field in upper case, with `__` for the nesting:
+/// e.g. `SIMPERBY_FETCH_INTERVAL_MS` or `SIMPERBY_CONSENSUS_PARAMS__PROPOSE_TIMEOUT_MS`.
+pub const ENV_PREFIX: &str = "SIMPERBY_";
+
+pub(crate) fn default_governance_port() -> u16 {
+    DEFAULT_GOVERNANCE_PORT
+}
+
+pub(crate) fn default_consensus_port() -> u16 {
+    DEFAULT_CONSENSUS_PORT
+}
+
+pub(crate) fn default_repository_port() -> u16 {
+    DEFAULT_REPOSITORY_PORT
+}
+
+pub(crate) fn default_heartbeat_port() -> u16 {
+    DEFAULT_HEARTBEAT_PORT
+}
+
+pub(crate) fn default_blob_port() -> u16 {
+    DEFAULT_BLOB_PORT
+}
+
+pub(crate) fn default_transaction_pool_port() -> u16 {
+    DEFAULT_TRANSACTION_POOL_PORT
+}
+
+/// The error for a config that can't run a node, listing every problem found.
+#[derive(thiserror::Error, Debug)]
+#[error("invalid config:\n- {}", .problems.join("\n- "))]
+pub struct InvalidConfigError {
+    pub problems: Vec<String>,
+}
+
+/// An override of a field, which is a `.`-separated path of the field and its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigOverride {
+    pub field: String,
+    pub value: String,
+    /// Where the override came from, for the error messages.
+    pub source: String,
+}
+
+/// Parses a CLI flag in the form of `<field>=<value>`, e.g. `consensus_params.max_timeout_ms=30000`.
+pub fn parse_flag(flag: &str) -> Result<ConfigOverride> {
+    let (field, value) = flag
+        .split_once('=')
+        .ok_or_else(|| eyre!("a config override must be in the form of `<field>=<value>`"))?;
+    Ok(ConfigOverride {
+        field: field.trim().to_owned(),
+        value: value.to_owned(),
+        source: format!("--set {flag}"),
+    })
+}
+
+/// Returns the overrides from the environment variables with [`ENV_PREFIX`].
+pub fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> Vec<ConfigOverride> {
+    let mut overrides = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let field = name
+                .strip_prefix(ENV_PREFIX)?
+                .to_lowercase()
+                .replace("__", ".");
+            Some(ConfigOverride {
+                field,
+                value,
+                source: name,
+            })
+        })
+        .collect::<Vec<_>>();
+    // The environment has no order; make the result not depend on it.
+    overrides.sort_by(|a, b| a.source.cmp(&b.source));
+    overrides
+}
+
+/// Applies the override to the config.
+///
+/// The value is read as JSON, or as a string if it isn't valid JSON or doesn't fit the field
+/// (so that the keys and the names can be given without quotes).
+pub fn apply_override(config: &Config, config_override: &ConfigOverride) -> Result<Config> {
+    let ConfigOverride {
+        field,
+        value,
+        source,
+    } = config_override;
+    let object = serde_json::to_value(config)?;
+    let mut target = &object;
+    for key in field.split('.') {
+        if target.is_null() {
+            return Err(eyre!(
+                "`{field}` is in an unset field; override the whole field instead (from {source})"
+            ));
+        }
+        target = target
+            .get(key)
+            .ok_or_else(|| eyre!("unknown config field `{field}` (from {source})"))?;
+    }
+    let pointer = format!("/{}", field.replace('.', "/"));
+    let mut candidates = Vec::new();
+    if let Ok(parsed) = serde_json::from_str::<Value>(value) {
+        candidates.push(parsed);
+    }
+    candidates.push(Value::String(value.clone()));
+    let mut last_error = None;
+    for candidate in candidates {
+        let mut object = object.clone();
+        *object
+            .pointer_mut(&pointer)
+            .expect("the field has been checked to exist") = candidate;
+        match serde_json::from_value(object) {
+            Ok(config) => return Ok(config),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(eyre!(
+        "invalid value `{value}` for `{field}` (from {source}): {}",
+        last_error.expect("there is at least one candidate")
+    ))
+}
+
+/// Reads the config of the node at the given path,
+/// applying the environment variables and then the given CLI flags, and validates it.
+pub async fn load(path: &str, flags: &[ConfigOverride]) -> Result<Config> {
+    let mut config = config_watch::read_config(path)
+        .await
+        .map_err(|e| eyre!("failed to read {path}/{CONFIG_FILE_NAME}: {e}"))?;
+    for config_override in env_overrides(std::env::vars()).iter().chain(flags) {
+        config = apply_override(&config, config_override)?;
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+impl Config {
+    /// Cross-checks the fields, reporting every problem with how to fix it.
+    pub fn validate(&self) -> Result<(), InvalidConfigError> {
+        let mut problems = Vec::new();
+        if self.chain_name.is_empty() {
+            problems.push("`chain_name` is empty".to_owned());
+        }
+        match (&self.public_key, &self.private_key) {
+            (Some(public_key), Some(private_key)) if *public_key != private_key.public_key() => {
+                problems.push(format!(
+                    "`public_key` doesn't match `private_key`; it should be {}",
+                    private_key.public_key()
+                ))
+            }
+            (Some(_), Some(_)) => {}
+            (None, Some(private_key)) => problems.push(format!(
+                "`public_key` is missing; it should be {}",
+                private_key.public_key()
+            )),
+            (Some(_), None) => problems.push("`private_key` is missing".to_owned()),
+            (None, None) if !self.observer => {
+                problems.push("the keys are missing; only an observer may go without".to_owned())
+            }
+            (None, None) => {}
+        }
+        if self.private_key.is_none() && !self.webhooks.is_empty() {
+            problems.push("`webhooks` need `private_key` to sign the deliveries".to_owned());
+        }
+
+        let ports = [
+            ("governance_port", self.governance_port),
+            ("consensus_port", self.consensus_port),
+            ("repository_port", self.repository_port),
+            ("heartbeat_port", self.heartbeat_port),
+            ("blob_port", self.blob_port),
+            ("transaction_pool_port", self.transaction_pool_port),
+        ];
+        for (i, (name, port)) in ports.iter().enumerate() {
+            if *port == 0 {
+                problems.push(format!("`{name}` is 0; choose a fixed port"));
+            } else if let Some((other, _)) = ports[..i].iter().find(|(_, p)| p == port) {
+                problems.push(format!(
+                    "`{name}` {port} is already used by `{other}`; choose another port"
+                ));
+            }
+        }
+
+        for (name, interval) in [
+            ("broadcast_interval_ms", self.broadcast_interval_ms),
+            ("fetch_interval_ms", self.fetch_interval_ms),
+            ("heartbeat_interval_ms", self.heartbeat_interval_ms),
+            ("prune_interval_ms", self.prune_interval_ms),
+        ] {
+            if interval == Some(0) {
+                problems.push(format!("`{name}` is 0; set it to null to disable"));
+            }
+        }
+
+        let params = &self.consensus_params;
+        for (name, timeout) in [
+            ("propose_timeout_ms", params.propose_timeout_ms),
+            ("prevote_timeout_ms", params.prevote_timeout_ms),
+            ("precommit_timeout_ms", params.precommit_timeout_ms),
+        ] {
+            if timeout == 0 {
+                problems.push(format!("`consensus_params.{name}` is 0"));
+            } else if timeout > params.max_timeout_ms {
+                problems.push(format!(
+                    "`consensus_params.{name}` {timeout} exceeds `consensus_params.max_timeout_ms` {}",
+                    params.max_timeout_ms
+                ));
+            }
+        }
+        if params.timeout_backoff_permille < 1000 {
+            problems.push(format!(
+                "`consensus_params.timeout_backoff_permille` {} would shorten the timeouts; \
+                it must be at least 1000",
+                params.timeout_backoff_permille
+            ));
+        }
+
+        if let Some(policy) = &self.offline_report_policy {
+            if policy.window == 0 {
+                problems.push("`offline_report_policy.window` is 0".to_owned());
+            }
+            if !(0.0..=1.0).contains(&policy.max_missed_fraction) {
+                problems.push(format!(
+                    "`offline_report_policy.max_missed_fraction` {} is not between 0 and 1",
+                    policy.max_missed_fraction
+                ));
+            }
+        }
+
+        for (i, peer) in self.peers.iter().enumerate() {
+            if self.peers[..i]
+                .iter()
+                .any(|p| p.public_key == peer.public_key)
+            {
+                problems.push(format!(
+                    "peer `{}` is listed more than once; remove the duplicate from `peers`",
+                    peer.name
+                ));
+            }
+            if let Err(e) = peer.address.validate() {
+                problems.push(format!("peer `{}` has an invalid address: {e}", peer.name));
+            }
+            if peer.relay.as_ref() == Some(&peer.public_key) {
+                problems.push(format!("peer `{}` can't relay for itself", peer.name));
+            }
+        }
+        if let Some(relay) = &self.relay {
+            if relay.packets_per_second == 0 || relay.burst == 0 {
+                problems.push(
+                    "`relay` would accept no packets; set `packets_per_second` and `burst`"
+                        .to_owned(),
+                );
+            }
+        }
+        if let Some(watchdog) = &self.watchdog {
+            if watchdog.check_interval_ms == 0 {
+                problems.push("`watchdog.check_interval_ms` is 0".to_owned());
+            }
+        }
+        if let Some(tap) = &self.message_tap {
+            if tap.max_file_size == 0 {
+                problems.push("`message_tap.max_file_size` is 0".to_owned());
+            }
+        }
+        for webhook in &self.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                problems.push(format!(
+                    "webhook URL `{}` must start with http:// or https://",
+                    webhook.url
+                ));
+            }
+        }
+        if let Some(GitSigner::Ssh { key_path }) = &self.git_signer {
+            if !std::path::Path::new(key_path).exists() {
+                problems.push(format!(
+                    "the SSH key of `git_signer` doesn't exist at {key_path}"
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfigError { problems })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn observer_config() -> Config {
+        serde_spb::from_str(r#"{ "chain_name": "test", "observer": true }"#).unwrap()
+    }
+
+    #[test]
+    fn parse_flags_and_env() {
+        let flag = parse_flag("consensus_params.max_timeout_ms=30000").unwrap();
+        assert_eq!(flag.field, "consensus_params.max_timeout_ms");
+        assert_eq!(flag.value, "30000");
+        assert!(parse_flag("fetch_interval_ms").is_err());
+
+        let overrides = env_overrides([
+            ("SIMPERBY_FETCH_INTERVAL_MS".to_owned(), "500".to_owned()),
+            ("PATH".to_owned(), "/bin".to_owned()),
+            (
+                "SIMPERBY_CONSENSUS_PARAMS__PROPOSE_TIMEOUT_MS".to_owned(),
+                "700".to_owned(),
+            ),
+        ]);
+        assert_eq!(
+            overrides
+                .iter()
+                .map(|o| o.field.as_str())
+                .collect::<Vec<_>>(),
+            vec!["consensus_params.propose_timeout_ms", "fetch_interval_ms"]
+        );
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let config = observer_config();
+        let config =
+            apply_override(&config, &parse_flag("fetch_interval_ms=500").unwrap()).unwrap();
+        assert_eq!(config.fetch_interval_ms, Some(500));
+        // A string without quotes.
+        let config = apply_override(&config, &parse_flag("chain_name=mainnet").unwrap()).unwrap();
+        assert_eq!(config.chain_name, "mainnet");
+        let config = apply_override(
+            &config,
+            &parse_flag("consensus_params.propose_timeout_ms=700").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.consensus_params.propose_timeout_ms, 700);
+
+        assert!(apply_override(&config, &parse_flag("no_such_field=1").unwrap()).is_err());
+        assert!(apply_override(&config, &parse_flag("governance_port=port").unwrap()).is_err());
+        assert!(apply_override(
+            &config,
+            &parse_flag("watchdog.check_interval_ms=1").unwrap(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn report_every_problem() {
+        observer_config().validate().unwrap();
+
+        let mut config = observer_config();
+        config.observer = false;
+        config.consensus_port = config.governance_port;
+        config.fetch_interval_ms = Some(0);
+        let problems = config.validate().unwrap_err().problems;
+        assert_eq!(problems.len(), 3, "{problems:?}");
+
+        let (public_key, _) = generate_keypair("a");
+        let (_, private_key) = generate_keypair("b");
+        let mut config = observer_config();
+        config.public_key = Some(public_key);
+        config.private_key = Some(private_key);
+        assert_eq!(config.validate().unwrap_err().problems.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn load_with_flags() {
+        let path = create_temp_dir();
+        std::fs::write(
+            format!("{path}/{CONFIG_FILE_NAME}"),
+            serde_spb::to_string(&observer_config()).unwrap(),
+        )
+        .unwrap();
+        let config = load(&path, &[parse_flag("fetch_interval_ms=500").unwrap()])
+            .await
+            .unwrap();
+        assert_eq!(config.fetch_interval_ms, Some(500));
+        assert!(load(&path, &[parse_flag("governance_port=0").unwrap()])
+            .await
+            .is_err());
+    }
+}
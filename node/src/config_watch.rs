@@ -0,0 +1,299 @@
+//! Reloading the config of a running node.
+//!
+//! [`watch`] notices the changes of `config.json`, either by its modification time or on `SIGHUP`,
+//! and [`SimperbyNode::reload_config`](crate::node::SimperbyNode::reload_config) applies
+//! the fields that can change without a restart.
+use super::*;
+use eyre::eyre;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+/// The changed fields of a reloaded config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigReloadReport {
+    /// The fields applied to the running node.
+    pub applied: Vec<String>,
+    /// The fields that keep their previous values until the node restarts.
+    pub restart_required: Vec<String>,
+}
+
+/// Reads the config of the node at the given path.
+pub async fn read_config(path: &str) -> Result<Config> {
+    Ok(serde_spb::from_str(
+        &tokio::fs::read_to_string(format!("{path}/{CONFIG_FILE_NAME}")).await?,
+    )?)
+}
+
+/// Checks whether the new config can replace the current one.
+///
+/// Unlike the fields that require a restart, these problems reject the whole config.
+pub fn validate(current: &Config, new: &Config) -> Result<()> {
+    if new.chain_name != current.chain_name {
+        return Err(eyre!(
+            "the chain name has changed from {} to {}",
+            current.chain_name,
+            new.chain_name
+        ));
+    }
+    new.validate()?;
+    Ok(())
+}
+
+/// Returns the config to run with, which takes only the reloadable fields from the new one,
+/// along with the report of the changed fields.
+pub fn merge(current: &Config, new: &Config) -> (Config, ConfigReloadReport) {
+    let mut report = ConfigReloadReport::default();
+    for (name, changed) in [
+        (
+            "broadcast_interval_ms",
+            changed(&current.broadcast_interval_ms, &new.broadcast_interval_ms),
+        ),
+        (
+            "fetch_interval_ms",
+            changed(&current.fetch_interval_ms, &new.fetch_interval_ms),
+        ),
+        (
+            "public_repo_url",
+            changed(&current.public_repo_url, &new.public_repo_url),
+        ),
+        ("peers", changed(&current.peers, &new.peers)),
+        (
+            "offline_report_policy",
+            changed(&current.offline_report_policy, &new.offline_report_policy),
+        ),
+        (
+            "require_signed_commits",
+            changed(&current.require_signed_commits, &new.require_signed_commits),
+        ),
+        ("git_signer", changed(&current.git_signer, &new.git_signer)),
+    ] {
+        if changed {
+            report.applied.push(name.to_owned());
+        }
+    }
+
+    // The others are captured by the network, the consensus and the background tasks at the start.
+    let mut merged = new.clone();
+    for (name, changed) in [
+        ("public_key", changed(&current.public_key, &new.public_key)),
+        (
+            "private_key",
+            changed(&current.private_key, &new.private_key),
+        ),
+        (
+            "heartbeat_interval_ms",
+            changed(&current.heartbeat_interval_ms, &new.heartbeat_interval_ms),
+        ),
+        (
+            "prune_interval_ms",
+            changed(&current.prune_interval_ms, &new.prune_interval_ms),
+        ),
+        ("retention", changed(&current.retention, &new.retention)),
+        (
+            "governance_port",
+            changed(&current.governance_port, &new.governance_port),
+        ),
+        (
+            "consensus_port",
+            changed(&current.consensus_port, &new.consensus_port),
+        ),
+        (
+            "repository_port",
+            changed(&current.repository_port, &new.repository_port),
+        ),
+        (
+            "heartbeat_port",
+            changed(&current.heartbeat_port, &new.heartbeat_port),
+        ),
+        ("blob_port", changed(&current.blob_port, &new.blob_port)),
+        (
+            "transaction_pool_port",
+            changed(&current.transaction_pool_port, &new.transaction_pool_port),
+        ),
+        (
+            "consensus_params",
+            changed(&current.consensus_params, &new.consensus_params),
+        ),
+        (
+            "round_history_heights",
+            changed(&current.round_history_heights, &new.round_history_heights),
+        ),
+        ("webhooks", changed(&current.webhooks, &new.webhooks)),
+        ("watchdog", changed(&current.watchdog, &new.watchdog)),
+        ("indexer", changed(&current.indexer, &new.indexer)),
+        ("gateway", changed(&current.gateway, &new.gateway)),
+        (
+            "version_check",
+            changed(&current.version_check, &new.version_check),
+        ),
+        (
+            "trusted_checkpoint",
+            changed(&current.trusted_checkpoint, &new.trusted_checkpoint),
+        ),
+        ("observer", changed(&current.observer, &new.observer)),
+        ("standby", changed(&current.standby, &new.standby)),
+        ("relay", changed(&current.relay, &new.relay)),
+        ("transport", changed(&current.transport, &new.transport)),
+        (
+            "message_tap",
+            changed(&current.message_tap, &new.message_tap),
+        ),
+        // Changing it would change the mode of the agendas being voted on.
+        (
+            "vote_reveal_delay_ms",
+            changed(&current.vote_reveal_delay_ms, &new.vote_reveal_delay_ms),
+        ),
+    ] {
+        if changed {
+            report.restart_required.push(name.to_owned());
+        }
+    }
+    merged.public_key = current.public_key.clone();
+    merged.private_key = current.private_key.clone();
+    merged.heartbeat_interval_ms = current.heartbeat_interval_ms;
+    merged.prune_interval_ms = current.prune_interval_ms;
+    merged.retention = current.retention;
+    merged.governance_port = current.governance_port;
+    merged.consensus_port = current.consensus_port;
+    merged.repository_port = current.repository_port;
+    merged.heartbeat_port = current.heartbeat_port;
+    merged.blob_port = current.blob_port;
+    merged.transaction_pool_port = current.transaction_pool_port;
+    merged.consensus_params = current.consensus_params.clone();
+    merged.round_history_heights = current.round_history_heights;
+    merged.webhooks = current.webhooks.clone();
+    merged.watchdog = current.watchdog.clone();
+    merged.indexer = current.indexer.clone();
+    merged.gateway = current.gateway.clone();
+    merged.version_check = current.version_check.clone();
+    merged.trusted_checkpoint = current.trusted_checkpoint.clone();
+    merged.observer = current.observer;
+    merged.standby = current.standby.clone();
+    merged.relay = current.relay.clone();
+    merged.transport = current.transport;
+    merged.message_tap = current.message_tap.clone();
+    merged.vote_reveal_delay_ms = current.vote_reveal_delay_ms;
+    (merged, report)
+}
+
+fn changed<T: Serialize>(current: &T, new: &T) -> bool {
+    serde_spb::to_vec(current).unwrap() != serde_spb::to_vec(new).unwrap()
+}
+
+/// Watches `config.json` of the node at the given path, sending the config whenever it changes.
+///
+/// The file is checked every `poll_interval`, and also right away on `SIGHUP`.
+/// A config that fails to be read is logged and skipped.
+/// The watcher stops when the receiver is dropped.
+pub fn watch(path: &str, poll_interval: Duration) -> mpsc::Receiver<Config> {
+    let (sender, receiver) = mpsc::channel(1);
+    let path = path.to_owned();
+    tokio::spawn(async move {
+        let file_path = PathBuf::from(format!("{path}/{CONFIG_FILE_NAME}"));
+        let mut last_modified = modified_time(&file_path);
+        #[cfg(unix)]
+        let mut hangup =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+        loop {
+            #[cfg(unix)]
+            let forced = match &mut hangup {
+                Some(hangup) => tokio::select! {
+                    _ = hangup.recv() => true,
+                    _ = tokio::time::sleep(poll_interval) => false,
+                },
+                None => {
+                    tokio::time::sleep(poll_interval).await;
+                    false
+                }
+            };
+            #[cfg(not(unix))]
+            let forced = {
+                tokio::time::sleep(poll_interval).await;
+                false
+            };
+            let modified = modified_time(&file_path);
+            if !forced && modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            match read_config(&path).await {
+                Ok(config) => {
+                    if sender.send(config).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log::warn!("failed to read the config: {e}"),
+            }
+        }
+    });
+    receiver
+}
+
+fn modified_time(file_path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file_path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn observer_config() -> Config {
+        serde_spb::from_str(r#"{ "chain_name": "test", "observer": true }"#).unwrap()
+    }
+
+    fn write_config(path: &str, config: &Config) {
+        std::fs::write(
+            format!("{path}/{CONFIG_FILE_NAME}"),
+            serde_spb::to_string(config).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reject_other_chain() {
+        let current = observer_config();
+        let mut new = current.clone();
+        new.chain_name = "other".to_owned();
+        assert!(validate(&current, &new).is_err());
+        new.chain_name = current.chain_name.clone();
+        validate(&current, &new).unwrap();
+    }
+
+    #[test]
+    fn merge_reloadable_fields() {
+        let current = observer_config();
+        let mut new = current.clone();
+        new.fetch_interval_ms = Some(500);
+        new.governance_port += 1000;
+        let (merged, report) = merge(&current, &new);
+        assert_eq!(report.applied, vec!["fetch_interval_ms".to_owned()]);
+        assert_eq!(report.restart_required, vec!["governance_port".to_owned()]);
+        assert_eq!(merged.fetch_interval_ms, Some(500));
+        assert_eq!(merged.governance_port, current.governance_port);
+
+        let (_, report) = merge(&current, &current);
+        assert_eq!(report, ConfigReloadReport::default());
+    }
+
+    #[tokio::test]
+    async fn watch_changes() {
+        let path = create_temp_dir();
+        let mut config = observer_config();
+        write_config(&path, &config);
+        let mut receiver = watch(&path, Duration::from_millis(20));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(receiver.try_recv().is_err());
+
+        config.fetch_interval_ms = Some(500);
+        write_config(&path, &config);
+        let reloaded = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reloaded.fetch_interval_ms, Some(500));
+    }
+}
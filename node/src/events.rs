@@ -0,0 +1,92 @@
+//! Events emitted by the node, which can be subscribed by the node users.
+use crate::watchdog::HealthStatus;
+use serde::{Deserialize, Serialize};
+use simperby_core::*;
+use simperby_repository::CommitHash;
+use tokio::sync::broadcast;
+
+/// The capacity of the event channel.
+///
+/// A subscriber that lags behind more than this will miss the oldest events.
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeEvent {
+    BlockFinalized {
+        commit_hash: CommitHash,
+        header: BlockHeader,
+    },
+    AgendaCreated {
+        commit_hash: CommitHash,
+        agenda: Agenda,
+    },
+    AgendaApproved {
+        commit_hash: CommitHash,
+        agenda_hash: Hash256,
+    },
+    /// The member list of the reserved state has changed by a finalized block.
+    MemberChanged {
+        height: BlockHeight,
+        members: Vec<Member>,
+    },
+    /// A validator has missed too many precommits by the offline report policy,
+    /// so a report of it has been drafted for the operator to review and submit.
+    OfflineReportDrafted { report: OfflineReport },
+    /// Two different blocks have been finalized at the same height, which is a fatal safety failure.
+    ///
+    /// The node has stopped finalizing blocks until the fork is resolved.
+    ForkDetected { evidence: Box<ForkEvidence> },
+    /// A health check of the [`watchdog`](crate::watchdog) has started failing.
+    HealthDegraded { status: HealthStatus },
+}
+
+/// The kind of a [`NodeEvent`], used for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeEventKind {
+    BlockFinalized,
+    AgendaCreated,
+    AgendaApproved,
+    MemberChanged,
+    OfflineReportDrafted,
+    ForkDetected,
+    HealthDegraded,
+}
+
+impl NodeEvent {
+    pub fn kind(&self) -> NodeEventKind {
+        match self {
+            NodeEvent::BlockFinalized { .. } => NodeEventKind::BlockFinalized,
+            NodeEvent::AgendaCreated { .. } => NodeEventKind::AgendaCreated,
+            NodeEvent::AgendaApproved { .. } => NodeEventKind::AgendaApproved,
+            NodeEvent::MemberChanged { .. } => NodeEventKind::MemberChanged,
+            NodeEvent::OfflineReportDrafted { .. } => NodeEventKind::OfflineReportDrafted,
+            NodeEvent::ForkDetected { .. } => NodeEventKind::ForkDetected,
+            NodeEvent::HealthDegraded { .. } => NodeEventKind::HealthDegraded,
+        }
+    }
+}
+
+/// The publishing side of the node events.
+#[derive(Debug, Clone)]
+pub struct EventPublisher {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl Default for EventPublisher {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl EventPublisher {
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes the event. It is never blocked and never fails even if there is no subscriber.
+    pub fn publish(&self, event: NodeEvent) {
+        let _ = self.sender.send(event);
+    }
+}
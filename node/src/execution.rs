@@ -0,0 +1,213 @@
+//! Application-level execution hooks.
+//!
+//! Simperby itself does not interpret the non-reserved part of the transactions;
+//! it only finalizes them. Applications that want to maintain their own state
+//! derived from the finalized history can register an [`ExecutionHook`] on the node.
+//!
+//! The last height executed by each hook is persisted (see [`ExecutionHooks::set_progress_storage`]),
+//! so a block is never delivered again to the hooks that have already executed it.
+use eyre::eyre;
+use simperby_core::*;
+use simperby_network::primitives::Storage;
+use simperby_network::storage::StorageImpl;
+use std::collections::BTreeMap;
+
+pub type Error = eyre::Error;
+
+const PROGRESS_FILE_NAME: &str = "execution-progress.json";
+
+/// Decides what to do when an [`ExecutionHook`] returns an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookErrorPolicy {
+    /// Stops the execution. The failed block will be executed again on the next attempt.
+    Halt,
+    /// Logs the error and moves on to the next commit.
+    Skip,
+}
+
+/// A state machine hook that observes the finalized history of the chain.
+///
+/// It is invoked in a deterministic order:
+/// every transaction of a block (in the order of commits) and then the block itself.
+/// Blocks are delivered in the order of height, and each block is delivered exactly once
+/// unless the hook fails on it with [`HookErrorPolicy::Halt`].
+pub trait ExecutionHook: Send + Sync {
+    /// The name that the progress of the hook is persisted by.
+    ///
+    /// It defaults to the type name; override it if more than one hook of a type is registered.
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    /// Called for each finalized transaction.
+    fn on_transaction(&mut self, transaction: &Transaction) -> Result<(), Error>;
+
+    /// Called after all the transactions of the block have been delivered.
+    fn on_block_finalized(&mut self, header: &BlockHeader) -> Result<(), Error>;
+
+    /// The policy to apply when this hook returns an error.
+    fn error_policy(&self) -> HookErrorPolicy {
+        HookErrorPolicy::Halt
+    }
+}
+
+/// A list of execution hooks, invoked in the order of registration.
+#[derive(Default)]
+pub struct ExecutionHooks {
+    hooks: Vec<Box<dyn ExecutionHook>>,
+    /// Where the last executed height of each hook is persisted, if set.
+    progress_storage: Option<StorageImpl>,
+}
+
+impl ExecutionHooks {
+    pub fn register(&mut self, hook: Box<dyn ExecutionHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Sets the storage to persist the last executed height of each hook in.
+    pub fn set_progress_storage(&mut self, storage: StorageImpl) {
+        self.progress_storage = Some(storage);
+    }
+
+    /// Returns the last executed height of each hook, by the name of the hook.
+    pub async fn read_progress(&self) -> Result<BTreeMap<String, BlockHeight>, Error> {
+        let Some(storage) = &self.progress_storage else {
+            return Ok(BTreeMap::new());
+        };
+        if !storage
+            .list_files()
+            .await?
+            .iter()
+            .any(|file_name| file_name == PROGRESS_FILE_NAME)
+        {
+            return Ok(BTreeMap::new());
+        }
+        Ok(serde_spb::from_str(
+            &storage.read_file(PROGRESS_FILE_NAME).await?,
+        )?)
+    }
+
+    /// Delivers the commits of a finalized block, which end with the block itself,
+    /// to every hook that hasn't executed the block yet.
+    ///
+    /// Commits other than transactions and blocks are ignored.
+    /// The height is persisted for each hook as it finishes the block,
+    /// so if a hook halts, only it and the ones after it get the block again on the next call.
+    pub async fn execute_block(&mut self, commits: &[Commit]) -> Result<(), Error> {
+        let Some(Commit::Block(header)) = commits.last() else {
+            return Err(eyre!("the commits of a block must end with the block"));
+        };
+        let mut progress = self.read_progress().await?;
+        for (index, hook) in self.hooks.iter_mut().enumerate() {
+            let name = hook.name();
+            if progress
+                .get(&name)
+                .is_some_and(|height| *height >= header.height)
+            {
+                continue;
+            }
+            for commit in commits {
+                let result = match commit {
+                    Commit::Transaction(transaction) => hook.on_transaction(transaction),
+                    Commit::Block(header) => hook.on_block_finalized(header),
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    match hook.error_policy() {
+                        HookErrorPolicy::Halt => {
+                            return Err(eyre!("execution hook #{index} ({name}) halted: {e}"));
+                        }
+                        HookErrorPolicy::Skip => {
+                            log::warn!("execution hook #{index} ({name}) failed (skipped): {e}");
+                        }
+                    }
+                }
+            }
+            if let Some(storage) = &mut self.progress_storage {
+                progress.insert(name, header.height);
+                storage
+                    .add_or_overwrite_file(PROGRESS_FILE_NAME, serde_spb::to_string(&progress)?)
+                    .await?;
+                storage.checkpoint().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the heights of the blocks, failing on the first `failures` blocks.
+    struct RecordingHook {
+        name: &'static str,
+        blocks: Arc<Mutex<Vec<BlockHeight>>>,
+        failures: usize,
+    }
+
+    impl ExecutionHook for RecordingHook {
+        fn name(&self) -> String {
+            self.name.to_owned()
+        }
+
+        fn on_transaction(&mut self, _transaction: &Transaction) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn on_block_finalized(&mut self, header: &BlockHeader) -> Result<(), Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(eyre!("failed on purpose"));
+            }
+            self.blocks.lock().unwrap().push(header.height);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn skip_executed_hooks() {
+        let (reserved_state, _) = test_utils::generate_standard_genesis(4);
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let mut hooks = ExecutionHooks::default();
+        hooks.set_progress_storage(StorageImpl::open(&path).await.unwrap());
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        hooks.register(Box::new(RecordingHook {
+            name: "first",
+            blocks: Arc::clone(&first),
+            failures: 0,
+        }));
+        hooks.register(Box::new(RecordingHook {
+            name: "second",
+            blocks: Arc::clone(&second),
+            failures: 1,
+        }));
+        let header = BlockHeader {
+            height: 1,
+            ..reserved_state.genesis_info.header.clone()
+        };
+        let block = [Commit::Block(header)];
+
+        assert!(hooks.execute_block(&block).await.is_err());
+        assert_eq!(*first.lock().unwrap(), vec![1]);
+        assert!(second.lock().unwrap().is_empty());
+        hooks.execute_block(&block).await.unwrap();
+        hooks.execute_block(&block).await.unwrap();
+        assert_eq!(*first.lock().unwrap(), vec![1]);
+        assert_eq!(*second.lock().unwrap(), vec![1]);
+        assert_eq!(
+            hooks.read_progress().await.unwrap(),
+            [("first".to_owned(), 1), ("second".to_owned(), 1)]
+                .into_iter()
+                .collect()
+        );
+    }
+}
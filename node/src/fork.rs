@@ -0,0 +1,29 @@
+//! The evidence of the forks observed by the node, kept in `fork_evidence.json` of the node directory.
+//!
+//! A fork is a fatal safety failure that only a manual governance action can resolve,
+//! so the node stops finalizing blocks as long as the file has any evidence.
+//! The operator removes the file to resume after the resolution.
+use super::*;
+
+pub const FORK_EVIDENCE_FILE_NAME: &str = "fork_evidence.json";
+
+/// Reads the evidence from `fork_evidence.json`, which may not exist.
+pub async fn read(path: &str) -> Result<Vec<ForkEvidence>> {
+    let file_path = format!("{path}/{FORK_EVIDENCE_FILE_NAME}");
+    if !std::path::Path::new(&file_path).exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_spb::from_str(
+        &tokio::fs::read_to_string(file_path).await?,
+    )?)
+}
+
+/// Overwrites `fork_evidence.json` with the given evidence.
+pub async fn write(path: &str, evidence: &[ForkEvidence]) -> Result<()> {
+    tokio::fs::write(
+        format!("{path}/{FORK_EVIDENCE_FILE_NAME}"),
+        serde_spb::to_string(&evidence)?,
+    )
+    .await?;
+    Ok(())
+}
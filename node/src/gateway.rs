@@ -0,0 +1,250 @@
+//! The read-through HTTP gateway of the finalized repository.
+//!
+//! It lets the consumers fetch the governed content (docs, configs) over plain HTTP
+//! and verify it with a light client instead of trusting the gateway.
+//!
+//! - `GET /{height}/{path}` returns the file at the path in the finalized block at the height.
+//!   The block is identified by the `X-Simperby-Height` and `X-Simperby-Block-Hash` headers,
+//!   and `X-Simperby-Merkle-Proof` carries the JSON of the merkle proof of
+//!   [`BlockHeader::repository_merkle_leaf`] of the file against the `repository_merkle_root`
+//!   of the block. The proof is missing if the block doesn't commit to the repository.
+//!   `X-Simperby-Finalization-Proof` refers to the endpoint below for the block.
+//! - `GET /-/finalization/{height}` returns the JSON of [`FinalizedHeader`],
+//!   with which the light client is updated to the block.
+//!
+//! The finalized content never changes, so the responses can be cached indefinitely.
+use super::*;
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use simperby_core::merkle_tree::MerkleProof;
+use simperby_repository::interpret;
+use simperby_repository::raw::RawRepository;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const HEIGHT_HEADER: &str = "x-simperby-height";
+pub const BLOCK_HASH_HEADER: &str = "x-simperby-block-hash";
+pub const MERKLE_PROOF_HEADER: &str = "x-simperby-merkle-proof";
+pub const FINALIZATION_PROOF_HEADER: &str = "x-simperby-finalization-proof";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayConfig {
+    /// The address to listen on, e.g., `0.0.0.0:8080`.
+    pub listen_address: String,
+}
+
+/// A finalized block header with its finalization proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalizedHeader {
+    pub header: BlockHeader,
+    pub finalization_proof: FinalizationProof,
+}
+
+/// Serves the gateway until `stopped` resolves.
+pub async fn serve(
+    raw: Arc<RwLock<RawRepository>>,
+    address: SocketAddr,
+    stopped: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let raw = Arc::clone(&raw);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let raw = Arc::clone(&raw);
+                async move { Ok::<_, Infallible>(handle(&raw, request).await) }
+            }))
+        }
+    });
+    hyper::Server::try_bind(&address)?
+        .serve(make_service)
+        .with_graceful_shutdown(stopped)
+        .await?;
+    Ok(())
+}
+
+async fn handle(raw: &RwLock<RawRepository>, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "only GET is allowed");
+    }
+    let path = request.uri().path().trim_start_matches('/');
+    let path = match percent_encoding::percent_decode_str(path).decode_utf8() {
+        Ok(path) => path.into_owned(),
+        Err(_) => return error(StatusCode::BAD_REQUEST, "the path is not UTF-8"),
+    };
+    let result = if let Some(height) = path.strip_prefix("-/finalization/") {
+        match height.parse() {
+            Ok(height) => get_finalized_header(&*raw.read().await, height).await,
+            Err(_) => return error(StatusCode::BAD_REQUEST, "invalid height"),
+        }
+    } else {
+        let Some((height, file_path)) = path.split_once('/') else {
+            return error(StatusCode::NOT_FOUND, "expected /{height}/{path}");
+        };
+        match height.parse() {
+            Ok(height) => get_file(&*raw.read().await, height, file_path).await,
+            Err(_) => return error(StatusCode::BAD_REQUEST, "invalid height"),
+        }
+    };
+    match result {
+        Ok(Some(response)) => response,
+        Ok(None) => error(StatusCode::NOT_FOUND, "not found"),
+        Err(e) => {
+            log::warn!("failed to serve {path} by the gateway: {e}");
+            error(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+        }
+    }
+}
+
+async fn get_file(
+    raw: &RawRepository,
+    height: BlockHeight,
+    path: &str,
+) -> Result<Option<Response<Body>>> {
+    let Some(file) = interpret::read_finalized_file(raw, height, path).await? else {
+        return Ok(None);
+    };
+    let mut response = Response::new(Body::from(file.content));
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=31536000, immutable"),
+    );
+    headers.insert(HEIGHT_HEADER, HeaderValue::from(height));
+    headers.insert(
+        BLOCK_HASH_HEADER,
+        HeaderValue::from_str(&file.header.to_hash256().to_string())?,
+    );
+    if let Some(proof) = &file.merkle_proof {
+        headers.insert(MERKLE_PROOF_HEADER, merkle_proof_header(proof)?);
+    }
+    headers.insert(
+        FINALIZATION_PROOF_HEADER,
+        HeaderValue::from_str(&format!("/-/finalization/{height}"))?,
+    );
+    Ok(Some(response))
+}
+
+fn merkle_proof_header(proof: &MerkleProof) -> Result<HeaderValue> {
+    Ok(HeaderValue::from_str(&serde_json::to_string(proof)?)?)
+}
+
+async fn get_finalized_header(
+    raw: &RawRepository,
+    height: BlockHeight,
+) -> Result<Option<Response<Body>>> {
+    let Some((commit_hash, header)) = interpret::locate_finalized_block(raw, height).await? else {
+        return Ok(None);
+    };
+    let finalization_proof = interpret::read_finalization_proof(raw, commit_hash).await?;
+    let mut response = Response::new(Body::from(serde_json::to_string(&FinalizedHeader {
+        header,
+        finalization_proof,
+    })?));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(Some(response))
+}
+
+fn error(status: StatusCode, message: &'static str) -> Response<Body> {
+    let mut response = Response::new(Body::from(message));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    async fn setup_genesis_repository() -> (RwLock<RawRepository>, ReservedState) {
+        let (reserved_state, _) = test_utils::generate_standard_genesis(4);
+        let dir = create_temp_dir();
+        setup_pre_genesis_repository(&dir, reserved_state.clone()).await;
+        let mut raw = RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap();
+        interpret::genesis(&mut raw).await.unwrap();
+        (RwLock::new(raw), reserved_state)
+    }
+
+    async fn get(raw: &RwLock<RawRepository>, uri: &str) -> Response<Body> {
+        handle(raw, Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn serve_finalized_file() {
+        let (raw, reserved_state) = setup_genesis_repository().await;
+        let response = get(&raw, "/0/reserved/version").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers[HEIGHT_HEADER], "0");
+        assert_eq!(
+            headers[BLOCK_HASH_HEADER],
+            reserved_state
+                .genesis_info
+                .header
+                .to_hash256()
+                .to_string()
+                .as_str()
+        );
+        assert_eq!(headers[FINALIZATION_PROOF_HEADER], "/-/finalization/0");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            serde_spb::to_string(&reserved_state.version)
+                .unwrap()
+                .as_bytes()
+        );
+
+        assert_eq!(
+            get(&raw, "/0/no-such-file").await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&raw, "/1/reserved/version").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_finalized_header() {
+        let (raw, reserved_state) = setup_genesis_repository().await;
+        let response = get(&raw, "/-/finalization/0").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let finalized: FinalizedHeader = serde_json::from_slice(&body).unwrap();
+        assert_eq!(finalized.header, reserved_state.genesis_info.header);
+        assert_eq!(
+            finalized.finalization_proof,
+            reserved_state.genesis_info.genesis_proof
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_bad_requests() {
+        let (raw, _) = setup_genesis_repository().await;
+        assert_eq!(
+            get(&raw, "/-/finalization/latest").await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get(&raw, "/first/reserved/version").await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        let post = Request::post("/0/reserved/version")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            handle(&raw, post).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+}
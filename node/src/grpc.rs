@@ -0,0 +1,584 @@
+//! The gRPC interface of the node.
+//!
+//! The service definition is in `proto/node.proto`, so that clients in other languages
+//! can generate their own stubs from it.
+use crate::events::{NodeEvent, NodeEventKind};
+use crate::page::{Page, PageRequest, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::shutdown::{OperationGuard, ShutdownController};
+use crate::stats::{ChainStats, DEFAULT_STATS_WINDOW};
+use crate::watchdog::{HealthMonitor, HealthStatus};
+use crate::{CommitInfo, ConsensusProgressOutcome, SimperbyNode};
+use futures::{Stream, StreamExt};
+use simperby_core::*;
+use simperby_governance::{Tally, VoteStatus};
+use simperby_repository::progress::Progress;
+use simperby_repository::CommitHash;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{watch, RwLock};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, WatchStream};
+use tonic::{Request, Response, Status};
+
+pub type Error = eyre::Error;
+
+pub mod proto {
+    tonic::include_proto!("simperby.node.v1");
+}
+
+use proto::node_server::NodeServer;
+
+/// Serves the gRPC interface of the given node until the server fails or the node shuts down.
+///
+/// On the shutdown, the new calls are rejected with `UNAVAILABLE`,
+/// the streams end, and the server returns once the calls in flight finish.
+pub async fn serve(node: Arc<RwLock<SimperbyNode>>, address: SocketAddr) -> Result<(), Error> {
+    let (progress, health, shutdown) = {
+        let node = node.read().await;
+        (
+            node.subscribe_progress(),
+            node.get_health_monitor(),
+            node.get_shutdown_controller(),
+        )
+    };
+    let stopped = shutdown.requested();
+    tonic::transport::Server::builder()
+        .add_service(NodeServer::new(GrpcServer::new(
+            node, progress, health, shutdown,
+        )))
+        .serve_with_shutdown(address, stopped)
+        .await?;
+    Ok(())
+}
+
+pub struct GrpcServer {
+    node: Arc<RwLock<SimperbyNode>>,
+    /// Kept apart from the node, which is locked during the operations in progress.
+    progress: watch::Receiver<Option<Progress>>,
+    /// Kept apart from the node as well.
+    health: HealthMonitor,
+    shutdown: ShutdownController,
+}
+
+impl GrpcServer {
+    pub fn new(
+        node: Arc<RwLock<SimperbyNode>>,
+        progress: watch::Receiver<Option<Progress>>,
+        health: HealthMonitor,
+        shutdown: ShutdownController,
+    ) -> Self {
+        Self {
+            node,
+            progress,
+            health,
+            shutdown,
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn begin(&self, operation: &str) -> Result<OperationGuard, Status> {
+        self.shutdown
+            .begin(operation)
+            .map_err(|e| Status::unavailable(e.to_string()))
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+type ProgressStream = Pin<Box<dyn Stream<Item = Result<proto::Progress, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl proto::node_server::Node for GrpcServer {
+    async fn get_last_finalized_block(
+        &self,
+        _request: Request<proto::GetLastFinalizedBlockRequest>,
+    ) -> Result<Response<proto::FinalizedBlock>, Status> {
+        let _guard = self.begin("get_last_finalized_block")?;
+        let info = self
+            .node
+            .read()
+            .await
+            .get_last_finalization_info()
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(proto::FinalizedBlock {
+            commit_hash: info.commit_hash.hash.to_vec(),
+            header: Some(block_header(&info.header)),
+            encoded_proof: serde_spb::to_vec(&info.proof).map_err(internal)?,
+        }))
+    }
+
+    async fn get_members(
+        &self,
+        request: Request<proto::GetMembersRequest>,
+    ) -> Result<Response<proto::GetMembersResponse>, Status> {
+        let _guard = self.begin("get_members")?;
+        let page = page_request(request.into_inner().page);
+        let info = self
+            .node
+            .read()
+            .await
+            .get_last_finalization_info()
+            .await
+            .map_err(internal)?;
+        let members = Page::paginate(info.reserved_state.members, &page)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        Ok(Response::new(proto::GetMembersResponse {
+            page: Some(page_info(&members)),
+            members: members.items.iter().map(member).collect(),
+        }))
+    }
+
+    async fn get_commit(
+        &self,
+        request: Request<proto::GetCommitRequest>,
+    ) -> Result<Response<proto::CommitInfo>, Status> {
+        let _guard = self.begin("get_commit")?;
+        let hash = request
+            .into_inner()
+            .commit_hash
+            .try_into()
+            .map_err(|_| Status::invalid_argument("commit hash must be 20 bytes"))?;
+        let commit_info = self
+            .node
+            .read()
+            .await
+            .show(CommitHash { hash })
+            .await
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        Ok(Response::new(
+            commit(commit_info).map_err(|e| Status::failed_precondition(e.to_string()))?,
+        ))
+    }
+
+    async fn get_tally(
+        &self,
+        request: Request<proto::GetTallyRequest>,
+    ) -> Result<Response<proto::Tally>, Status> {
+        let _guard = self.begin("get_tally")?;
+        let hash: [u8; 32] = request
+            .into_inner()
+            .agenda_hash
+            .try_into()
+            .map_err(|_| Status::invalid_argument("agenda hash must be 32 bytes"))?;
+        let tally = self
+            .node
+            .read()
+            .await
+            .tally(Hash256::from_array(hash))
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(self::tally(tally)))
+    }
+
+    async fn get_chain_stats(
+        &self,
+        request: Request<proto::GetChainStatsRequest>,
+    ) -> Result<Response<proto::ChainStats>, Status> {
+        let _guard = self.begin("get_chain_stats")?;
+        let request = request.into_inner();
+        let window = match request.blocks {
+            0 => DEFAULT_STATS_WINDOW,
+            blocks => blocks,
+        };
+        let page = page_request(request.validators_page);
+        let stats = self
+            .node
+            .read()
+            .await
+            .get_chain_stats(window)
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(chain_stats(stats, &page)?))
+    }
+
+    type SubscribeEventsStream = EventStream;
+
+    /// Streams the events from the moment of the subscription.
+    ///
+    /// If the client falls too far behind, the stream ends with `DATA_LOSS`
+    /// so that it can re-synchronize with the query methods and subscribe again.
+    async fn subscribe_events(
+        &self,
+        request: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let _guard = self.begin("subscribe_events")?;
+        let mut kinds = Vec::new();
+        for kind in request.into_inner().kinds {
+            let Some(kind) = event_kind(kind) else {
+                return Err(Status::invalid_argument(format!(
+                    "invalid event kind: {kind}"
+                )));
+            };
+            kinds.push(kind);
+        }
+        let receiver = self.node.read().await.subscribe_events();
+        let stream = BroadcastStream::new(receiver).filter_map(move |event| {
+            let item = match event {
+                Ok(event) if kinds.is_empty() || kinds.contains(&event.kind()) => {
+                    Some(Ok(self::event(event)))
+                }
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(n)) => Some(Err(Status::data_loss(format!(
+                    "subscriber lagged behind; {n} events are dropped"
+                )))),
+            };
+            futures::future::ready(item)
+        });
+        Ok(Response::new(Box::pin(
+            stream.take_until(self.shutdown.requested()),
+        )))
+    }
+
+    type SubscribeProgressStream = ProgressStream;
+
+    /// Streams the latest progress from the moment of the subscription.
+    #[allow(clippy::result_large_err)]
+    async fn subscribe_progress(
+        &self,
+        _request: Request<proto::SubscribeProgressRequest>,
+    ) -> Result<Response<Self::SubscribeProgressStream>, Status> {
+        let _guard = self.begin("subscribe_progress")?;
+        let stream = WatchStream::new(self.progress.clone())
+            .filter_map(|progress| futures::future::ready(progress.map(|p| Ok(self::progress(p)))))
+            .take_until(self.shutdown.requested());
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_health(
+        &self,
+        _request: Request<proto::GetHealthRequest>,
+    ) -> Result<Response<proto::Health>, Status> {
+        let _guard = self.begin("get_health")?;
+        Ok(Response::new(health(self.health.status())))
+    }
+
+    async fn progress_for_consensus(
+        &self,
+        _request: Request<proto::ProgressForConsensusRequest>,
+    ) -> Result<Response<proto::ConsensusProgress>, Status> {
+        let _guard = self.begin("progress_for_consensus")?;
+        let outcome = self
+            .node
+            .write()
+            .await
+            .progress_for_consensus()
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(consensus_progress(outcome)))
+    }
+}
+
+fn internal(e: impl std::fmt::Display) -> Status {
+    Status::internal(e.to_string())
+}
+
+/// Applies the default and the maximum of the limit, as the remote callers can't take all at once.
+fn page_request(page: Option<proto::PageRequest>) -> PageRequest {
+    let page = page.unwrap_or_default();
+    PageRequest {
+        limit: Some(match page.limit as usize {
+            0 => DEFAULT_PAGE_LIMIT,
+            limit => limit.min(MAX_PAGE_LIMIT),
+        }),
+        cursor: (!page.cursor.is_empty()).then(|| page.cursor.into()),
+    }
+}
+
+fn page_info<T>(page: &Page<T>) -> proto::PageInfo {
+    proto::PageInfo {
+        next_cursor: page
+            .next_cursor
+            .as_ref()
+            .map_or_else(String::new, |cursor| cursor.as_str().to_owned()),
+        total: page.total,
+    }
+}
+
+fn event_kind(kind: i32) -> Option<NodeEventKind> {
+    match proto::EventKind::from_i32(kind)? {
+        proto::EventKind::Unspecified => None,
+        proto::EventKind::BlockFinalized => Some(NodeEventKind::BlockFinalized),
+        proto::EventKind::AgendaCreated => Some(NodeEventKind::AgendaCreated),
+        proto::EventKind::AgendaApproved => Some(NodeEventKind::AgendaApproved),
+        proto::EventKind::MemberChanged => Some(NodeEventKind::MemberChanged),
+        proto::EventKind::OfflineReportDrafted => Some(NodeEventKind::OfflineReportDrafted),
+        proto::EventKind::ForkDetected => Some(NodeEventKind::ForkDetected),
+        proto::EventKind::HealthDegraded => Some(NodeEventKind::HealthDegraded),
+    }
+}
+
+fn consensus_progress(outcome: ConsensusProgressOutcome) -> proto::ConsensusProgress {
+    use proto::consensus_progress::Outcome;
+    let round_step = |round, block_hash: Hash256| proto::RoundStep {
+        round,
+        block_hash: block_hash.as_ref().to_vec(),
+    };
+    proto::ConsensusProgress {
+        outcome: match outcome {
+            ConsensusProgressOutcome::Proposed { round, block_hash } => {
+                Some(Outcome::Proposed(round_step(round, block_hash)))
+            }
+            ConsensusProgressOutcome::Prevoted { round, block_hash } => {
+                Some(Outcome::Prevoted(round_step(round, block_hash)))
+            }
+            ConsensusProgressOutcome::Precommitted { round, block_hash } => {
+                Some(Outcome::Precommitted(round_step(round, block_hash)))
+            }
+            ConsensusProgressOutcome::Finalized { height, hash } => {
+                Some(Outcome::Finalized(proto::ConsensusFinalized {
+                    height,
+                    hash: hash.as_ref().to_vec(),
+                }))
+            }
+            ConsensusProgressOutcome::TimedOut { round } => Some(Outcome::TimedOut(round)),
+            ConsensusProgressOutcome::Noop => None,
+        },
+    }
+}
+
+fn block_header(header: &BlockHeader) -> proto::BlockHeader {
+    proto::BlockHeader {
+        author: header.author.as_ref().to_vec(),
+        previous_hash: header.previous_hash.as_ref().to_vec(),
+        height: header.height,
+        timestamp: header.timestamp,
+        commit_merkle_root: header.commit_merkle_root.as_ref().to_vec(),
+        repository_merkle_root: header.repository_merkle_root.as_ref().to_vec(),
+        validator_set: header
+            .validator_set
+            .iter()
+            .map(|(public_key, voting_power)| proto::Validator {
+                public_key: public_key.as_ref().to_vec(),
+                voting_power: *voting_power,
+            })
+            .collect(),
+        version: header.version.clone(),
+        encoded: serde_spb::to_vec(header).unwrap(),
+    }
+}
+
+fn agenda(agenda: &Agenda) -> proto::Agenda {
+    proto::Agenda {
+        height: agenda.height,
+        author: agenda.author.clone(),
+        timestamp: agenda.timestamp,
+        transactions_hash: agenda.transactions_hash.as_ref().to_vec(),
+        hash: agenda.to_hash256().as_ref().to_vec(),
+        encoded: serde_spb::to_vec(agenda).unwrap(),
+        description: agenda
+            .description
+            .as_ref()
+            .map(|description| proto::AgendaDescription {
+                title: description.title.clone(),
+                rationale: description.rationale.clone(),
+                links: description.links.clone(),
+            }),
+        deadline: agenda.deadline.map(|deadline| proto::VotingDeadline {
+            deadline: Some(match deadline {
+                VotingDeadline::Timestamp(timestamp) => {
+                    proto::voting_deadline::Deadline::Timestamp(timestamp)
+                }
+            }),
+        }),
+    }
+}
+
+fn member(member: &Member) -> proto::Member {
+    proto::Member {
+        public_key: member.public_key.as_ref().to_vec(),
+        name: member.name.clone(),
+        governance_voting_power: member.governance_voting_power,
+        consensus_voting_power: member.consensus_voting_power,
+        governance_delegatee: member.governance_delegatee.clone(),
+        consensus_delegatee: member.consensus_delegatee.clone(),
+        metadata: member.metadata.clone().into_iter().collect(),
+    }
+}
+
+fn tally(tally: Tally) -> proto::Tally {
+    proto::Tally {
+        agenda_hash: tally.agenda_hash.as_ref().to_vec(),
+        votes: tally
+            .votes
+            .into_iter()
+            .map(|vote| proto::MemberVote {
+                name: vote.name,
+                voting_power: vote.voting_power,
+                status: match vote.status {
+                    VoteStatus::For => proto::VoteStatus::For,
+                    VoteStatus::Against => proto::VoteStatus::Against,
+                    VoteStatus::Absent => proto::VoteStatus::Absent,
+                    VoteStatus::Committed => proto::VoteStatus::Committed,
+                } as i32,
+            })
+            .collect(),
+        for_power: tally.for_power,
+        against_power: tally.against_power,
+        absent_power: tally.absent_power,
+        threshold_met: tally.threshold_met,
+        additional_voters: tally.additional_voters,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn chain_stats(stats: ChainStats, page: &PageRequest) -> Result<proto::ChainStats, Status> {
+    let validators = Page::paginate(stats.validators, page)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    Ok(proto::ChainStats {
+        from_height: stats.from_height,
+        to_height: stats.to_height,
+        validators_page: Some(page_info(&validators)),
+        validators: validators
+            .items
+            .into_iter()
+            .map(|validator| proto::ValidatorStats {
+                public_key: validator.public_key.as_ref().to_vec(),
+                eligible_blocks: validator.eligible_blocks,
+                precommits: validator.precommits,
+                proposed_blocks: validator.proposed_blocks,
+                expected_proposals: validator.expected_proposals,
+            })
+            .collect(),
+        average_block_interval_ms: stats.average_block_interval_ms,
+        agendas_per_block: stats.agendas_per_block,
+    })
+}
+
+fn commit(commit_info: CommitInfo) -> Result<proto::CommitInfo, Error> {
+    use proto::commit_info::Commit as C;
+    let (semantic_commit, commit) = match commit_info {
+        CommitInfo::Block {
+            semantic_commit,
+            block_header,
+        } => (semantic_commit, C::Block(self::block_header(&block_header))),
+        CommitInfo::Agenda {
+            semantic_commit,
+            agenda,
+            ..
+        } => (semantic_commit, C::Agenda(self::agenda(&agenda))),
+        CommitInfo::AgendaProof {
+            semantic_commit,
+            agenda_proof,
+        } => (
+            semantic_commit,
+            C::AgendaProof(agenda_proof.agenda_hash.as_ref().to_vec()),
+        ),
+        CommitInfo::Transaction {
+            semantic_commit,
+            transaction,
+        } => (
+            semantic_commit,
+            C::Transaction(serde_spb::to_vec(&transaction)?),
+        ),
+        CommitInfo::PreGenesisCommit { title } => {
+            return Ok(proto::CommitInfo {
+                title,
+                ..Default::default()
+            })
+        }
+        CommitInfo::Unknown { msg, .. } => return Err(eyre::eyre!("unknown commit: {msg}")),
+    };
+    Ok(proto::CommitInfo {
+        title: semantic_commit.title,
+        body: semantic_commit.body,
+        author: semantic_commit.author,
+        timestamp: semantic_commit.timestamp,
+        commit: Some(commit),
+    })
+}
+
+fn event(event: NodeEvent) -> proto::Event {
+    use proto::event::Event as E;
+    let event = match event {
+        NodeEvent::BlockFinalized {
+            commit_hash,
+            header,
+        } => E::BlockFinalized(proto::BlockFinalized {
+            commit_hash: commit_hash.hash.to_vec(),
+            header: Some(block_header(&header)),
+        }),
+        NodeEvent::AgendaCreated {
+            commit_hash,
+            agenda,
+        } => E::AgendaCreated(proto::AgendaCreated {
+            commit_hash: commit_hash.hash.to_vec(),
+            agenda: Some(self::agenda(&agenda)),
+        }),
+        NodeEvent::AgendaApproved {
+            commit_hash,
+            agenda_hash,
+        } => E::AgendaApproved(proto::AgendaApproved {
+            commit_hash: commit_hash.hash.to_vec(),
+            agenda_hash: agenda_hash.as_ref().to_vec(),
+        }),
+        NodeEvent::MemberChanged { height, members } => E::MemberChanged(proto::MemberChanged {
+            height,
+            members: members.iter().map(member).collect(),
+        }),
+        NodeEvent::OfflineReportDrafted { report } => {
+            E::OfflineReportDrafted(proto::OfflineReportDrafted {
+                offender: report.offender,
+                from_height: report.from_height,
+                to_height: report.to_height,
+                missed_precommits: report.missed_precommits,
+                eligible_blocks: report.eligible_blocks,
+            })
+        }
+        NodeEvent::ForkDetected { evidence } => E::ForkDetected(proto::ForkDetected {
+            height: evidence.height(),
+            first: Some(block_header(&evidence.first)),
+            second: Some(block_header(&evidence.second)),
+            encoded: serde_spb::to_vec(&evidence).unwrap(),
+        }),
+        NodeEvent::HealthDegraded { status } => E::HealthDegraded(health(status)),
+    };
+    proto::Event { event: Some(event) }
+}
+
+fn health(status: HealthStatus) -> proto::Health {
+    proto::Health {
+        healthy: status.is_healthy(),
+        failures: status
+            .failures
+            .iter()
+            .map(|failure| format!("{failure:?}"))
+            .collect(),
+        last_finalized_height: status.last_finalized_height,
+        last_progress: status.last_progress,
+        consecutive_fetch_failures: status.consecutive_fetch_failures,
+        checked_at: status.checked_at,
+    }
+}
+
+fn progress(progress: Progress) -> proto::Progress {
+    use proto::progress::Progress as P;
+    let progress = match progress {
+        Progress::Fetching {
+            remote,
+            received_objects,
+            total_objects,
+            received_bytes,
+            eta_ms,
+        } => P::Fetching(proto::Fetching {
+            remote,
+            received_objects,
+            total_objects,
+            received_bytes,
+            eta_ms,
+        }),
+        Progress::Verifying {
+            verified_commits,
+            total_commits,
+            height,
+            eta_ms,
+        } => P::Verifying(proto::Verifying {
+            verified_commits,
+            total_commits,
+            height,
+            eta_ms,
+        }),
+    };
+    proto::Progress {
+        progress: Some(progress),
+    }
+}
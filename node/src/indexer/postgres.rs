@@ -0,0 +1,176 @@
+//! The [`IndexerSink`] of a PostgreSQL database.
+//!
+//! The hashes and keys are stored as hex strings, and the headers, agendas and members
+//! as JSON of their serde form, so that they can be queried without the Simperby types.
+use super::*;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Executor;
+
+/// The schema migrations, applied in the order of the versions.
+///
+/// A migration must never be changed once released; add a new one instead.
+const MIGRATIONS: &[(i64, &str)] = &[(
+    1,
+    r#"
+CREATE TABLE blocks (
+    height BIGINT PRIMARY KEY,
+    commit_hash TEXT NOT NULL,
+    block_hash TEXT NOT NULL UNIQUE,
+    author TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    header JSONB NOT NULL
+);
+CREATE TABLE agendas (
+    agenda_hash TEXT PRIMARY KEY,
+    block_height BIGINT NOT NULL REFERENCES blocks (height) ON DELETE CASCADE,
+    commit_hash TEXT NOT NULL,
+    author TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    agenda JSONB NOT NULL
+);
+CREATE INDEX agendas_block_height ON agendas (block_height);
+CREATE TABLE votes (
+    agenda_hash TEXT NOT NULL REFERENCES agendas (agenda_hash) ON DELETE CASCADE,
+    voter TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    PRIMARY KEY (agenda_hash, voter)
+);
+CREATE TABLE member_changes (
+    block_height BIGINT PRIMARY KEY REFERENCES blocks (height) ON DELETE CASCADE,
+    members JSONB NOT NULL
+);
+"#,
+)];
+
+/// Serializes the concurrent migrations of the nodes sharing the database.
+const MIGRATION_LOCK_KEY: i64 = 0x5349_4d50_4552_4259;
+
+pub struct PostgresSink {
+    pool: PgPool,
+}
+
+impl PostgresSink {
+    pub async fn connect(database_url: &str) -> Result<Self, Error> {
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect(database_url)
+            .await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait::async_trait]
+impl IndexerSink for PostgresSink {
+    async fn migrate(&self) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK_KEY)
+            .execute(&mut *tx)
+            .await?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS simperby_schema_migrations (
+                version BIGINT PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+        let applied: Option<i64> =
+            sqlx::query_scalar("SELECT MAX(version) FROM simperby_schema_migrations")
+                .fetch_one(&mut *tx)
+                .await?;
+        for (version, migration) in MIGRATIONS {
+            if applied.is_some_and(|applied| *version <= applied) {
+                continue;
+            }
+            log::info!("applying the indexer schema migration {version}");
+            tx.execute(*migration).await?;
+            sqlx::query("INSERT INTO simperby_schema_migrations (version) VALUES ($1)")
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn last_indexed_height(&self) -> Result<Option<BlockHeight>, Error> {
+        let height: Option<i64> = sqlx::query_scalar("SELECT MAX(height) FROM blocks")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(height.map(|height| height as BlockHeight))
+    }
+
+    async fn write_block(&self, block: &IndexedBlock) -> Result<(), Error> {
+        let height = block.header.height as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO blocks (height, commit_hash, block_hash, author, timestamp, header)
+             VALUES ($1, $2, $3, $4, $5, CAST($6 AS JSONB))
+             ON CONFLICT (height) DO UPDATE SET
+                commit_hash = EXCLUDED.commit_hash,
+                block_hash = EXCLUDED.block_hash,
+                author = EXCLUDED.author,
+                timestamp = EXCLUDED.timestamp,
+                header = EXCLUDED.header",
+        )
+        .bind(height)
+        .bind(block.commit_hash.to_string())
+        .bind(block.header.to_hash256().to_string())
+        .bind(block.header.author.to_string())
+        .bind(block.header.timestamp)
+        .bind(serde_spb::to_string(&block.header)?)
+        .execute(&mut *tx)
+        .await?;
+        // Replaces the content of the block as a whole, so that a rewrite leaves nothing stale.
+        sqlx::query("DELETE FROM agendas WHERE block_height = $1")
+            .bind(height)
+            .execute(&mut *tx)
+            .await?;
+        for agenda in &block.agendas {
+            sqlx::query(
+                "INSERT INTO agendas (agenda_hash, block_height, commit_hash, author, timestamp, agenda)
+                 VALUES ($1, $2, $3, $4, $5, CAST($6 AS JSONB))",
+            )
+            .bind(agenda.agenda_hash.to_string())
+            .bind(height)
+            .bind(agenda.commit_hash.to_string())
+            .bind(&agenda.agenda.author)
+            .bind(agenda.agenda.timestamp)
+            .bind(serde_spb::to_string(&agenda.agenda)?)
+            .execute(&mut *tx)
+            .await?;
+            for vote in &agenda.votes {
+                sqlx::query(
+                    "INSERT INTO votes (agenda_hash, voter, signature) VALUES ($1, $2, $3)
+                     ON CONFLICT (agenda_hash, voter) DO NOTHING",
+                )
+                .bind(agenda.agenda_hash.to_string())
+                .bind(vote.voter.to_string())
+                .bind(vote.signature.to_string())
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        match &block.members {
+            Some(members) => {
+                sqlx::query(
+                    "INSERT INTO member_changes (block_height, members)
+                     VALUES ($1, CAST($2 AS JSONB))
+                     ON CONFLICT (block_height) DO UPDATE SET members = EXCLUDED.members",
+                )
+                .bind(height)
+                .bind(serde_spb::to_string(members)?)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM member_changes WHERE block_height = $1")
+                    .bind(height)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -14,68 +14,315 @@
 //! - `update`
 //! - `broadcast`
 //! - `chat`
+//! - `stats`
+//! - `peer`
+//! - `forks`
+//! - `patch`
 //!
 //! The following CLI commands are provided as global functions as they are node-stateless.
 //!
 //! - `genesis`
+//! - `patch generate`
+//! - `version`
 //!
 //! The following CLI commands are provided as global functions as they are about the node creation.
 //!
 //! - `init`
 //! - `clone`
+//! - `bootstrap`
 //! - `serve`
 //!
 //! The following CLI commands are not provided here because they are simple
 //! and so directly implemented in the CLI.
 //!
 //! - `sign`
+pub mod block_template;
+pub mod config_layers;
+pub mod config_watch;
+pub mod events;
+pub mod execution;
+pub mod fork;
+pub mod gateway;
+pub mod grpc;
+pub mod indexer;
+pub mod members;
+pub mod migrations;
 pub mod node;
+pub mod page;
+pub mod peers;
+pub mod release;
+pub mod shutdown;
+pub mod standby;
+pub mod stats;
+pub mod storage_path;
+pub mod watchdog;
+pub mod webhook;
 
+pub use simperby_consensus;
 pub use simperby_core;
+pub use simperby_governance;
 pub use simperby_network;
 pub use simperby_repository;
 
 use eyre::Result;
 use serde::{Deserialize, Serialize};
+use simperby_consensus::{ConsensusParams, PendingVeto, PruningMetrics, RoundHistory};
 use simperby_core::crypto::*;
 use simperby_core::*;
-use simperby_governance::Governance;
+use simperby_governance::{Governance, Tally, VotingMode};
+use simperby_network::dms::{ChainMismatch, RelayConfig, SyncStatistics, TapConfig};
+use simperby_network::heartbeat::MemberLiveness;
+use simperby_network::DmsKey;
 use simperby_network::Peer;
-use simperby_repository::raw::{RawRepository, SemanticCommit};
+use simperby_network::Transport;
+use simperby_repository::interpret;
+use simperby_repository::patch::PatchBundle;
+use simperby_repository::raw::{GitSigner, RawRepository, SemanticCommit};
 use simperby_repository::CommitHash;
-use simperby_repository::DistributedRepository;
+use simperby_repository::{DistributedRepository, RetentionPolicy, TrustedCheckpoint};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The number of commits to fetch first when bootstrapping from a checkpoint.
+const BOOTSTRAP_INITIAL_DEPTH: usize = 64;
+/// The branch of the pre-genesis commit created by [`init`].
+const PRE_GENESIS_BRANCH_NAME: &str = "main";
+/// The file of the message tap (see [`Config::message_tap`]), relative to the node directory.
+pub const MESSAGE_TAP_FILE: &str = "message-tap.jsonl";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub chain_name: String,
 
-    pub public_key: PublicKey,
-    pub private_key: PrivateKey,
+    /// The keys of the node, which only an observer may go without (see [`Config::observer`]).
+    #[serde(default)]
+    pub public_key: Option<PublicKey>,
+    #[serde(default)]
+    pub private_key: Option<PrivateKey>,
 
     pub broadcast_interval_ms: Option<u64>,
     pub fetch_interval_ms: Option<u64>,
+    /// If `None`, this node doesn't send heartbeats (but still tracks others').
+    pub heartbeat_interval_ms: Option<u64>,
+    /// If `None`, the repository is pruned only on `clean`.
+    #[serde(default)]
+    pub prune_interval_ms: Option<u64>,
+    /// Whether to keep the whole history (an archive node) or only the recent blocks of it
+    /// (a pruned node), which is applied as the blocks are finalized.
+    #[serde(default)]
+    pub retention: RetentionPolicy,
 
     /// Public repos (usually mirrors) for the read-only accesses
     ///
     /// They're added as a remote repo, named `public_#`.
+    #[serde(default)]
     pub public_repo_url: Vec<String>,
 
+    #[serde(default = "config_layers::default_governance_port")]
     pub governance_port: u16,
+    #[serde(default = "config_layers::default_consensus_port")]
     pub consensus_port: u16,
+    #[serde(default = "config_layers::default_repository_port")]
     pub repository_port: u16,
+    #[serde(default = "config_layers::default_heartbeat_port")]
+    pub heartbeat_port: u16,
+    #[serde(default = "config_layers::default_blob_port")]
+    pub blob_port: u16,
+    #[serde(default = "config_layers::default_transaction_pool_port")]
+    pub transaction_pool_port: u16,
 
     /// TODO: remove this and introduce a proper peer discovery protocol
+    #[serde(default)]
     pub peers: Vec<Peer>,
+    /// If set, the node relays the DMS traffic of the peers behind a NAT (see [`Peer::relay`]).
+    ///
+    /// It should be set only on a publicly reachable node. Only the packets of the peers
+    /// that name this node as their relay are limited, as of the start of the node.
+    #[serde(default)]
+    pub relay: Option<RelayConfig>,
+    /// The transport of the DMSs. With [`Transport::Quic`], the node serves QUIC
+    /// in addition to HTTP, and dials the peers over QUIC (so they must have it set too).
+    #[serde(default)]
+    pub transport: Transport,
+
+    /// If set, the agendas are voted in the commit-reveal mode
+    /// (see [`VotingMode::CommitReveal`]), revealed this long after the timestamps of the agendas.
+    ///
+    /// Every member of the network must set the same value.
+    #[serde(default)]
+    pub vote_reveal_delay_ms: Option<u64>,
+
+    /// The parameters of the consensus, including the timeouts.
+    #[serde(default)]
+    pub consensus_params: ConsensusParams,
+    /// The number of the heights to keep the consensus round history of.
+    /// Only the snapshot of the last finalized height is kept, regardless.
+    ///
+    /// If `None`, [`simperby_consensus::DEFAULT_ROUND_HISTORY_HEIGHTS`] is used.
+    #[serde(default)]
+    pub round_history_heights: Option<usize>,
+
+    /// The policy to draft the reports of the persistently offline validators.
+    ///
+    /// If `None`, the participation of the validators is not watched.
+    #[serde(default)]
+    pub offline_report_policy: Option<stats::OfflineReportPolicy>,
+
+    /// The webhook endpoints to notify the node events.
+    #[serde(default)]
+    pub webhooks: Vec<webhook::WebhookConfig>,
+
+    /// If set, every DMS packet sent or received is recorded to [`MESSAGE_TAP_FILE`],
+    /// which `debug messages` shows.
+    #[serde(default)]
+    pub message_tap: Option<TapConfig>,
+
+    /// The checkpoint that the node was bootstrapped from (see [`bootstrap`]).
+    #[serde(default)]
+    pub trusted_checkpoint: Option<TrustedCheckpoint>,
+
+    /// If enabled, the node only syncs, verifies and serves the chain,
+    /// without taking part in the governance and the consensus.
+    ///
+    /// The keys are optional, and don't have to belong to a member if given;
+    /// they are used only as the network identity.
+    #[serde(default)]
+    pub observer: bool,
+
+    /// If set, the node runs as a cold standby of the validator key,
+    /// signing only while it holds the leadership lease (see [`standby`]).
+    #[serde(default)]
+    pub standby: Option<standby::StandbyConfig>,
+
+    /// Whether to reject the unsigned agenda, block and transaction commits from the peers.
+    #[serde(default)]
+    pub require_signed_commits: bool,
+
+    /// The key to sign the commits with git's native signing as well,
+    /// which should match the [`GitSigningKey`] of the member.
+    #[serde(default)]
+    pub git_signer: Option<GitSigner>,
+
+    /// If set, [`watchdog::run`] checks the health of the node and recovers it.
+    #[serde(default)]
+    pub watchdog: Option<watchdog::WatchdogConfig>,
+
+    /// If set, the finalized data is exported to the database (see [`indexer`]).
+    #[serde(default)]
+    pub indexer: Option<indexer::IndexerConfig>,
+
+    /// If set, the finalized files are served over HTTP with their proofs (see [`gateway`]).
+    #[serde(default)]
+    pub gateway: Option<gateway::GatewayConfig>,
+
+    /// How to check the release against the version of the chain (see [`release`]).
+    #[serde(default)]
+    pub version_check: release::VersionCheckConfig,
+}
+
+impl Config {
+    /// Returns the private key of the node, failing if an observer goes without one.
+    pub fn signing_key(&self) -> Result<&PrivateKey> {
+        self.private_key
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("`private_key` is not set in the config"))
+    }
+}
+
+/// The error for calling a mutating method on an observer node (see [`Config::observer`]).
+#[derive(thiserror::Error, Debug)]
+#[error("`{operation}` is not available in the observer mode")]
+pub struct ObserverModeError {
+    pub operation: String,
+}
+
+/// The error for finalizing a block after a fork has been detected (see [`fork`]).
+#[derive(thiserror::Error, Debug)]
+#[error("`{operation}` is halted by the fork at height {height}")]
+pub struct ForkHaltError {
+    pub operation: String,
+    pub height: BlockHeight,
+}
+
+/// The error for starting an operation after the shutdown is requested (see [`shutdown`]).
+#[derive(thiserror::Error, Debug)]
+#[error("`{operation}` is rejected as the node is shutting down")]
+pub struct ShuttingDownError {
+    pub operation: String,
+}
+
+/// The error for signing on a node that doesn't hold the leadership lease (see [`standby`]).
+#[derive(thiserror::Error, Debug)]
+#[error("`{operation}` is not available in the standby mode: {reason}")]
+pub struct StandbyModeError {
+    pub operation: String,
+    pub reason: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConsensusStatus {
-    // TODO
+    /// The vetoes on the rounds in progress and on the proposed blocks of the height being decided.
+    pub pending_vetoes: Vec<PendingVeto>,
+    // TODO: the rest of the status
+}
+
+/// The furthest step made by a progress of the consensus (see `progress_for_consensus()`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum ConsensusProgressOutcome {
+    Proposed {
+        round: ConsensusRound,
+        block_hash: Hash256,
+    },
+    Prevoted {
+        round: ConsensusRound,
+        block_hash: Hash256,
+    },
+    Precommitted {
+        round: ConsensusRound,
+        block_hash: Hash256,
+    },
+    Finalized {
+        height: BlockHeight,
+        hash: Hash256,
+    },
+    /// Voted nil in the round, as it timed out without a block to vote for
+    /// (including those vetoed or invalid).
+    TimedOut {
+        round: ConsensusRound,
+    },
+    /// Nothing to do until more messages arrive or the timeout passes.
+    Noop,
+}
+
+impl std::fmt::Display for ConsensusProgressOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Proposed { round, block_hash } => {
+                write!(f, "proposed block {block_hash} in round {round}")
+            }
+            Self::Prevoted { round, block_hash } => {
+                write!(f, "prevoted block {block_hash} in round {round}")
+            }
+            Self::Precommitted { round, block_hash } => {
+                write!(f, "precommitted block {block_hash} in round {round}")
+            }
+            Self::Finalized { height, hash } => {
+                write!(f, "finalized block {hash} at height {height}")
+            }
+            Self::TimedOut { round } => write!(f, "round {round} timed out"),
+            Self::Noop => write!(f, "nothing to progress"),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NetworkStatus {
-    // TODO
+    /// The liveness of the members, observed from their heartbeats.
+    pub liveness: Vec<(MemberName, MemberLiveness)>,
+    /// The network transfer statistics of each DMS.
+    pub sync_statistics: Vec<(DmsKey, SyncStatistics)>,
+    /// The peers rejected by each DMS as on another chain.
+    pub chain_mismatches: Vec<(DmsKey, ChainMismatch)>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -89,6 +336,7 @@ pub enum CommitInfo {
         semantic_commit: SemanticCommit,
         agenda: Agenda,
         voters: Vec<(MemberName, Timestamp)>,
+        tally: Tally,
     },
     AgendaProof {
         semantic_commit: SemanticCommit,
@@ -107,21 +355,139 @@ pub enum CommitInfo {
     }, // TODO
 }
 
-pub type SimperbyNode = node::Node<simperby_network::storage::StorageImpl>;
+pub type SimperbyNode = node::Node;
 
 /// Creates a genesis commit.
 pub async fn genesis(config: Config, path: &str) -> Result<()> {
-    let raw_repository = RawRepository::open(&format!("{path}/repository/repo")).await?;
-    let mut repository = DistributedRepository::new(
-        raw_repository,
+    let mut repository = open_repository(&config, path, None).await?;
+    repository.genesis().await?;
+    Ok(())
+}
+
+/// Opens the repository of the node directory,
+/// adding the public repos in the config as the remotes named `public_#`.
+async fn open_repository(
+    config: &Config,
+    path: &str,
+    trusted_checkpoint: Option<TrustedCheckpoint>,
+) -> Result<DistributedRepository> {
+    let mut raw_repository = RawRepository::open(&format!("{path}/repository/repo")).await?;
+    let remotes = raw_repository.list_remotes().await?;
+    for (i, url) in config.public_repo_url.iter().enumerate() {
+        let name = format!("public_{i}");
+        if !remotes.iter().any(|(remote_name, _)| remote_name == &name) {
+            raw_repository.add_remote(name, url.clone()).await?;
+        }
+    }
+    DistributedRepository::new(
+        Arc::new(RwLock::new(raw_repository)),
         simperby_repository::Config {
-            mirrors: config.public_repo_url.clone(),
             long_range_attack_distance: 3,
+            prune_policy: Default::default(),
+            trusted_checkpoint,
+            require_signed_commits: config.require_signed_commits,
+            retention: config.retention,
         },
     )
+    .await
+}
+
+/// Signs the uncommitted changes in the working tree of the repository as a patch bundle.
+///
+/// This is for a contributor who can't push to the repository;
+/// a member submits the bundle to their node to turn it into a transaction.
+pub async fn create_patch_bundle(
+    config: &Config,
+    path: &str,
+    message: String,
+) -> Result<PatchBundle> {
+    let raw_repository = RawRepository::open(&format!("{path}/repository/repo")).await?;
+    PatchBundle::from_working_tree(&raw_repository, message, config.signing_key()?).await
+}
+
+/// Scaffolds a new chain directory with the config and the genesis reserved state template.
+///
+/// It writes `config.json` and an empty `peers.json`,
+/// and creates the repository with the reserved state in its working tree.
+/// The pre-genesis commit is created once the genesis proof is complete,
+/// either right away or by [`add_genesis_signatures`].
+/// Returns whether the pre-genesis commit has been created.
+pub async fn init(config: &Config, path: &str, reserved_state: &ReservedState) -> Result<bool> {
+    if std::path::Path::new(&format!("{path}/config.json")).exists() {
+        return Err(eyre::eyre!("{path} is already initialized"));
+    }
+    tokio::fs::create_dir_all(path).await?;
+    tokio::fs::write(format!("{path}/config.json"), serde_spb::to_string(config)?).await?;
+    peers::write(path, &[]).await?;
+    migrations::write_manifest(
+        path,
+        &migrations::Manifest {
+            version: migrations::current_version(),
+        },
+    )?;
+
+    let repo_path = format!("{path}/repository/repo");
+    tokio::fs::create_dir_all(&repo_path).await?;
+    let mut raw = RawRepository::init(
+        &repo_path,
+        "initial commit",
+        &PRE_GENESIS_BRANCH_NAME.to_owned(),
+    )
     .await?;
-    repository.genesis().await?;
-    Ok(())
+    simperby_repository::raw::reserved_state::write_reserved_state(&repo_path, reserved_state)
+        .await?;
+    commit_pre_genesis_if_complete(&mut raw, reserved_state).await
+}
+
+/// Adds the members' signatures on the genesis sign target
+/// (see [`ReservedState::genesis_sign_target`]) to the reserved state template
+/// of a directory scaffolded by [`init`].
+///
+/// Returns whether the pre-genesis commit has been created.
+pub async fn add_genesis_signatures(
+    path: &str,
+    signatures: Vec<TypedSignature<FinalizationSignTarget>>,
+) -> Result<bool> {
+    let repo_path = format!("{path}/repository/repo");
+    let mut raw = RawRepository::open(&repo_path).await?;
+    if raw.get_head().await? != raw.get_initial_commit().await? {
+        return Err(eyre::eyre!(
+            "the pre-genesis commit has already been created"
+        ));
+    }
+    let mut reserved_state =
+        simperby_repository::raw::reserved_state::read_reserved_state(&repo_path).await?;
+    for signature in signatures {
+        reserved_state
+            .add_genesis_signature(signature)
+            .map_err(|e| eyre::eyre!(e))?;
+    }
+    simperby_repository::raw::reserved_state::write_reserved_state(&repo_path, &reserved_state)
+        .await?;
+    commit_pre_genesis_if_complete(&mut raw, &reserved_state).await
+}
+
+async fn commit_pre_genesis_if_complete(
+    raw: &mut RawRepository,
+    reserved_state: &ReservedState,
+) -> Result<bool> {
+    if verify::verify_finalization_proof(
+        &reserved_state.genesis_info.header,
+        &reserved_state.genesis_info.genesis_proof,
+    )
+    .is_err()
+    {
+        return Ok(false);
+    }
+    raw.create_commit(simperby_repository::raw::RawCommit {
+        message: "genesis".to_owned(),
+        diff: None,
+        author: "simperby".to_owned(),
+        email: "hello@simperby.net".to_owned(),
+        timestamp: utils::get_timestamp() / 1000,
+    })
+    .await?;
+    Ok(true)
 }
 
 /// Initializes a node.
@@ -137,6 +503,36 @@ pub async fn clone(config: Config, path: &str, url: &str) -> Result<SimperbyNode
     SimperbyNode::initialize(config, path).await
 }
 
+/// Clones a remote repository from the trusted checkpoint and initializes a node.
+///
+/// Only the history from the checkpoint is downloaded;
+/// the shallow clone is deepened until it reaches the checkpoint.
+pub async fn bootstrap(
+    mut config: Config,
+    path: &str,
+    url: &str,
+    checkpoint: TrustedCheckpoint,
+) -> Result<SimperbyNode> {
+    let mut depth = BOOTSTRAP_INITIAL_DEPTH;
+    let mut raw =
+        RawRepository::clone_shallow(&format!("{path}/repository/repo"), url, depth).await?;
+    while interpret::locate_checkpoint(&raw, &checkpoint)
+        .await?
+        .is_none()
+    {
+        if !raw.is_shallow().await? {
+            return Err(eyre::eyre!(
+                "the checkpoint is not in the remote repository"
+            ));
+        }
+        depth *= 2;
+        raw.fetch_all_with_depth(depth).await?;
+    }
+    interpret::verify_from_checkpoint(&raw, &checkpoint).await?;
+    config.trusted_checkpoint = Some(checkpoint);
+    SimperbyNode::initialize(config, path).await
+}
+
 /// Runs a server node indefinitely.
 pub async fn serve(_config: Config, _path: &str) -> Result<()> {
     todo!()
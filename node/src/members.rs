@@ -0,0 +1,98 @@
+//! The members of the reserved state with their delegations resolved, for the operators.
+use serde::{Deserialize, Serialize};
+use simperby_core::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MemberInfo {
+    pub name: MemberName,
+    pub public_key: PublicKey,
+    /// The voting powers of the member's own.
+    pub governance_voting_power: VotingPower,
+    pub consensus_voting_power: VotingPower,
+    pub governance_delegatee: Option<MemberName>,
+    pub consensus_delegatee: Option<MemberName>,
+    /// The governance voting power that the member exercises,
+    /// including the delegated ones; zero if it has delegated its own.
+    pub effective_governance_power: VotingPower,
+    /// The consensus voting power that the member exercises, as in the validator set.
+    pub effective_consensus_power: VotingPower,
+    /// The position in [`ReservedState::consensus_leader_order`], if it's there.
+    pub leader_order: Option<usize>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Resolves the delegations of the members, in the order of the reserved state.
+///
+/// The effective powers are taken from the validator set and the governance set,
+/// so that they're exactly what the consensus and the governance count.
+pub fn member_infos(reserved_state: &ReservedState) -> Result<Vec<MemberInfo>, String> {
+    let validator_set = reserved_state
+        .get_validator_set()?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let governance_set = reserved_state
+        .get_governance_set()?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    Ok(reserved_state
+        .members
+        .iter()
+        .map(|member| MemberInfo {
+            name: member.name.clone(),
+            public_key: member.public_key.clone(),
+            governance_voting_power: member.governance_voting_power,
+            consensus_voting_power: member.consensus_voting_power,
+            governance_delegatee: member.governance_delegatee.clone(),
+            consensus_delegatee: member.consensus_delegatee.clone(),
+            effective_governance_power: governance_set
+                .get(&member.public_key)
+                .copied()
+                .unwrap_or_default(),
+            effective_consensus_power: validator_set
+                .get(&member.public_key)
+                .copied()
+                .unwrap_or_default(),
+            leader_order: reserved_state
+                .consensus_leader_order
+                .iter()
+                .position(|name| name == &member.name),
+            metadata: member.metadata.clone(),
+        })
+        .collect())
+}
+
+/// Renders the delegation graph in the DOT language of Graphviz.
+///
+/// An edge goes from the delegator to the delegatee, labeled with what is delegated.
+pub fn delegation_graph_dot(members: &[MemberInfo]) -> String {
+    let escape = |name: &str| name.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut dot = "digraph delegation {\n    node [shape=box];\n".to_owned();
+    for member in members {
+        dot.push_str(&format!(
+            "    \"{0}\" [label=\"{0}\\ngovernance {1} / consensus {2}\"];\n",
+            escape(&member.name),
+            member.effective_governance_power,
+            member.effective_consensus_power
+        ));
+    }
+    for member in members {
+        let mut edges = BTreeMap::<&MemberName, Vec<&str>>::new();
+        if let Some(delegatee) = &member.governance_delegatee {
+            edges.entry(delegatee).or_default().push("governance");
+        }
+        if let Some(delegatee) = &member.consensus_delegatee {
+            edges.entry(delegatee).or_default().push("consensus");
+        }
+        for (delegatee, kinds) in edges {
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                escape(&member.name),
+                escape(delegatee),
+                kinds.join(", ")
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
@@ -0,0 +1,299 @@
+//! Migrations of the on-disk formats of a node directory.
+//!
+//! The version of a node directory is recorded in its manifest ([`MANIFEST_FILE_NAME`]).
+//! A directory without the manifest is considered to be version `0`.
+//!
+//! Migrations must run before the config is loaded, because the config schema itself
+//! is subject to migration.
+use crate::storage_path::StorageNamespace;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use simperby_network::storage::NAMESPACE_FILE_NAME;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+/// The directory where the files are backed up during a migration.
+pub const BACKUP_DIRECTORY_NAME: &str = "migration-backup";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: u32,
+}
+
+/// A step that upgrades the node directory from `version - 1` to `version`.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// The files and directories (relative to the node directory) that the migration modifies.
+    ///
+    /// They are backed up before the migration and restored if it fails.
+    pub paths: &'static [&'static str],
+    pub run: fn(&Path) -> Result<()>,
+}
+
+/// Returns all the migrations in the order of the versions.
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            description: "config schema: add `blob_port`",
+            paths: &["config.json"],
+            run: add_blob_port,
+        },
+        Migration {
+            version: 2,
+            description: "storages: record the namespace of each storage directory",
+            paths: &["consensus/history", "repository/proofs"],
+            run: stamp_storage_namespaces,
+        },
+    ]
+}
+
+/// Returns the version of the node directory that this build uses.
+pub fn current_version() -> u32 {
+    migrations().last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn read_manifest(path: &str) -> Result<Manifest> {
+    let manifest_path = Path::new(path).join(MANIFEST_FILE_NAME);
+    if !manifest_path.exists() {
+        return Ok(Manifest { version: 0 });
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(manifest_path)?)?)
+}
+
+pub fn write_manifest(path: &str, manifest: &Manifest) -> Result<()> {
+    fs::write(
+        Path::new(path).join(MANIFEST_FILE_NAME),
+        serde_json::to_string_pretty(manifest)?,
+    )?;
+    Ok(())
+}
+
+/// Returns the migrations that haven't been applied to the node directory.
+pub fn pending_migrations(path: &str) -> Result<Vec<Migration>> {
+    let manifest = read_manifest(path)?;
+    if manifest.version > current_version() {
+        return Err(eyre!(
+            "the node directory is in version {}, which is newer than this build ({})",
+            manifest.version,
+            current_version()
+        ));
+    }
+    Ok(migrations()
+        .into_iter()
+        .filter(|m| m.version > manifest.version)
+        .collect())
+}
+
+/// Applies the pending migrations in order, returning them.
+///
+/// If `dry_run` is set, it only returns the migrations to apply.
+/// If any migration fails, all the modified files are restored from the backup.
+pub fn migrate(path: &str, dry_run: bool) -> Result<Vec<Migration>> {
+    let pending = pending_migrations(path)?;
+    if dry_run || pending.is_empty() {
+        return Ok(pending);
+    }
+    let root = Path::new(path);
+    let backup = root.join(BACKUP_DIRECTORY_NAME);
+    if backup.exists() {
+        return Err(eyre!(
+            "a backup from an interrupted migration exists at {}; restore or remove it first",
+            backup.display()
+        ));
+    }
+    let mut paths = pending
+        .iter()
+        .flat_map(|m| m.paths.iter().copied())
+        .chain(std::iter::once(MANIFEST_FILE_NAME))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    fs::create_dir(&backup)?;
+    for p in &paths {
+        if root.join(p).exists() {
+            copy_recursively(&root.join(p), &backup.join(p))?;
+        }
+    }
+    for migration in &pending {
+        log::info!(
+            "migrating the node directory to version {}: {}",
+            migration.version,
+            migration.description
+        );
+        let result = (migration.run)(root).and_then(|_| {
+            write_manifest(
+                path,
+                &Manifest {
+                    version: migration.version,
+                },
+            )
+        });
+        if let Err(e) = result {
+            for p in &paths {
+                let target = root.join(p);
+                remove_recursively(&target)?;
+                if backup.join(p).exists() {
+                    copy_recursively(&backup.join(p), &target)?;
+                }
+            }
+            fs::remove_dir_all(&backup)?;
+            return Err(e.wrap_err(format!(
+                "failed to migrate to version {}; rolled back to version {}",
+                migration.version,
+                pending[0].version - 1
+            )));
+        }
+    }
+    fs::remove_dir_all(&backup)?;
+    Ok(pending)
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+fn remove_recursively(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// `blob_port` became a required field of the config with the blob store.
+fn add_blob_port(root: &Path) -> Result<()> {
+    let config_path = root.join("config.json");
+    if !config_path.exists() {
+        return Ok(());
+    }
+    let mut config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&config_path)?)?;
+    let object = config
+        .as_object_mut()
+        .ok_or_else(|| eyre!("the config is not an object"))?;
+    if object.contains_key("blob_port") {
+        return Ok(());
+    }
+    let heartbeat_port = object
+        .get("heartbeat_port")
+        .and_then(|x| x.as_u64())
+        .ok_or_else(|| eyre!("the config doesn't have a valid `heartbeat_port`"))?;
+    object.insert("blob_port".to_owned(), (heartbeat_port + 1).into());
+    fs::write(config_path, serde_json::to_string_pretty(&config)?)?;
+    Ok(())
+}
+
+/// The storages became namespaced, refusing to open without the namespace recorded.
+///
+/// Only the persistent ones need it; the others are recreated on every start.
+fn stamp_storage_namespaces(root: &Path) -> Result<()> {
+    for namespace in StorageNamespace::ALL {
+        let directory = root.join(namespace.relative_path());
+        if !namespace.is_persistent() || !directory.exists() {
+            continue;
+        }
+        let namespace_path = directory.join(NAMESPACE_FILE_NAME);
+        if namespace_path.exists() {
+            let recorded = fs::read_to_string(&namespace_path)?;
+            if recorded != namespace.name() {
+                return Err(eyre!(
+                    "{} is recorded to belong to `{recorded}`, not `{}`",
+                    directory.display(),
+                    namespace.name()
+                ));
+            }
+            continue;
+        }
+        fs::write(namespace_path, namespace.name())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn write_config(root: &str, config: serde_json::Value) {
+        fs::write(
+            Path::new(root).join("config.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn read_config(root: &str) -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(Path::new(root).join("config.json")).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn migrate_from_version_0() {
+        let root = create_temp_dir();
+        write_config(&root, serde_json::json!({ "heartbeat_port": 1188 }));
+        assert_eq!(read_manifest(&root).unwrap().version, 0);
+
+        let dry_run = migrate(&root, true).unwrap();
+        assert_eq!(dry_run.len(), migrations().len());
+        assert_eq!(read_manifest(&root).unwrap().version, 0);
+
+        migrate(&root, false).unwrap();
+        assert_eq!(read_manifest(&root).unwrap().version, current_version());
+        assert_eq!(read_config(&root)["blob_port"], 1189);
+        assert!(!Path::new(&root).join(BACKUP_DIRECTORY_NAME).exists());
+        assert!(migrate(&root, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn roll_back_on_failure() {
+        let root = create_temp_dir();
+        // Fails `add_blob_port`, as there is no `heartbeat_port`.
+        let config = serde_json::json!({ "chain_name": "test" });
+        write_config(&root, config.clone());
+
+        assert!(migrate(&root, false).is_err());
+        assert_eq!(read_manifest(&root).unwrap().version, 0);
+        assert!(!Path::new(&root).join(MANIFEST_FILE_NAME).exists());
+        assert_eq!(read_config(&root), config);
+        assert!(!Path::new(&root).join(BACKUP_DIRECTORY_NAME).exists());
+    }
+
+    #[test]
+    fn refuse_interrupted_backup() {
+        let root = create_temp_dir();
+        write_config(&root, serde_json::json!({ "heartbeat_port": 1188 }));
+        fs::create_dir(Path::new(&root).join(BACKUP_DIRECTORY_NAME)).unwrap();
+        assert!(migrate(&root, false).is_err());
+        assert_eq!(read_manifest(&root).unwrap().version, 0);
+    }
+
+    #[test]
+    fn refuse_newer_version() {
+        let root = create_temp_dir();
+        write_manifest(
+            &root,
+            &Manifest {
+                version: current_version() + 1,
+            },
+        )
+        .unwrap();
+        assert!(pending_migrations(&root).is_err());
+    }
+}
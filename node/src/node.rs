@@ -1,50 +1,125 @@
 use super::*;
+use block_template::{AgendaCandidate, BlockTemplateFilter, DefaultBlockTemplateFilter};
+use config_watch::ConfigReloadReport;
+use events::{EventPublisher, NodeEvent};
+use execution::{ExecutionHook, ExecutionHooks};
 use eyre::eyre;
+use indexer::{open_sink, Indexer};
+use page::{Page, PageRequest};
+use peers::PeerStatus;
+use shutdown::ShutdownController;
+use simperby_consensus::{Consensus, ProgressResult, SignWatermark};
 use simperby_core::utils::get_timestamp;
-use simperby_consensus::{Consensus, ConsensusParameters, ProgressResult};
+use simperby_governance::audit::{self, AuditLog};
+use simperby_governance::poll::{Poll, PollTally};
+use simperby_governance::{Vote, VoteCommitment, VoteReveal};
+use simperby_network::dms::ChainId;
+use simperby_network::dms::DistributedMessageSet;
+use simperby_network::dms::HandshakeReport;
+use simperby_network::dms::MessagePropagation;
+use simperby_network::dms::MessageTap;
+use simperby_network::heartbeat::{self, Heartbeat};
 use simperby_network::primitives::Storage;
-use simperby_network::{dms::Config as DmsConfig, storage::StorageImpl, Dms};
-use simperby_network::{ClientNetworkConfig, ServerNetworkConfig};
+use simperby_network::DmsMessage;
+use simperby_network::{dms::Config as DmsConfig, Dms};
+use simperby_network::{ClientNetworkConfig, PeerAddress, ServerNetworkConfig, StorageImpl};
+use simperby_repository::blob::{self, BlobStore};
+use simperby_repository::divergence::{DivergenceResolution, DivergentBranch, ResolutionStrategy};
+use simperby_repository::epoch::Epoch;
+use simperby_repository::interpret::{CommitSignatureReport, HistoryVerificationReport};
+use simperby_repository::patch::PatchBundle;
+use simperby_repository::progress::{Progress, ProgressReporter};
+use simperby_repository::proof::ProofStore;
 use simperby_repository::raw::RawRepository;
-use simperby_repository::{DistributedRepository, WORK_BRANCH_NAME};
+use simperby_repository::review::{AgendaBundle, AgendaReview};
+use simperby_repository::transaction_pool::{self, PendingTransaction, TransactionPool};
+use simperby_repository::{DistributedRepository, FinalizationInfo, WORK_BRANCH_NAME};
+use standby::{FileLeaseStore, LeadershipLease, LeaseStore};
+use stats::ChainStats;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use storage_path::StorageLayout;
 use tokio::sync::RwLock;
+use watchdog::{HealthMonitor, HealthStatus};
 
-pub struct Node<S: Storage> {
+/// How long the heartbeats are kept, which is the longest period that the liveness view covers.
+const HEARTBEAT_RETENTION_MS: u64 = 24 * 60 * 60 * 1000;
+
+pub struct Node {
     config: Config,
     repository: DistributedRepository,
-    governance: Governance<S>,
-    consensus: Consensus<S>,
+    governance: Governance,
+    consensus: Consensus,
+    heartbeat: Arc<RwLock<Dms<Heartbeat>>>,
 
     last_reserved_state: ReservedState,
-    #[allow(dead_code)]
     last_finalized_header: BlockHeader,
-    _path: String,
+    path: String,
+    /// Shared by all the DMSs, if enabled.
+    tap: Option<MessageTap>,
+
+    events: EventPublisher,
+    progress: tokio::sync::watch::Receiver<Option<Progress>>,
+    execution_hooks: ExecutionHooks,
+    block_template_filter: Box<dyn BlockTemplateFilter>,
+    /// The last block commit that has been delivered to the execution hooks.
+    last_executed_commit_hash: CommitHash,
+    /// The evidence of misbehaviors that are to be reported in the next block.
+    evidence_pool: Vec<DoubleSignEvidence>,
+    /// The reports of the offline validators drafted by the policy, waiting for the operator.
+    offline_reports: Vec<OfflineReport>,
+    /// The evidence of the forks observed so far. If any, the finalization is halted.
+    fork_evidence: Vec<ForkEvidence>,
+    shutdown: ShutdownController,
+    health: HealthMonitor,
+    /// Set in the standby mode (see [`Config::standby`]).
+    lease_store: Option<Box<dyn LeaseStore>>,
+    /// The leadership lease held by this node, if it's an active one in the standby mode.
+    lease: Option<LeadershipLease>,
+    /// The tasks spawned by the node, which stop on the shutdown.
+    background_tasks: Vec<tokio::task::JoinHandle<()>>,
 
-    _client_network_config: ClientNetworkConfig,
-    _server_network_config: ServerNetworkConfig,
+    client_network_config: ClientNetworkConfig,
+    server_network_config: ServerNetworkConfig,
 }
 
 impl SimperbyNode {
     pub async fn initialize(config: Config, path: &str) -> Result<Self> {
         // Step 0: initialize the repository module
-        let raw_repository = RawRepository::open(&format!("{path}/repository/repo")).await?;
-        let repository = DistributedRepository::new(
-            raw_repository,
-            simperby_repository::Config {
-                mirrors: config.public_repo_url.clone(),
-                long_range_attack_distance: 3,
-            },
-        )
-        .await?;
+        let mut repository =
+            open_repository(&config, path, config.trusted_checkpoint.clone()).await?;
+        if !config.observer {
+            repository.set_signing_key(config.signing_key()?.clone());
+            repository
+                .get_raw()
+                .write()
+                .await
+                .set_git_signer(config.git_signer.clone())
+                .await;
+        }
 
         // Step 1: initialize configs
-        let last_finalized_header = repository.get_last_finalized_block_header().await?;
-        let reserved_state = repository.get_reserved_state().await?;
+        let storage_layout = StorageLayout::new(path);
+        let restored = Self::restore_from_snapshot(&mut repository, &storage_layout).await?;
+        // Unlike the others of the repository, the epochs are needed from the start.
+        repository
+            .set_epoch_storage(storage_layout.epochs().open().await?)
+            .await?;
+        let lfi = repository.read_last_finalization_info().await?;
+        let last_finalized_header = lfi.header;
+        let last_executed_commit_hash = lfi.commit_hash;
+        let reserved_state = lfi.reserved_state;
+        release::check(&config.version_check, &reserved_state)?;
         let governance_dms_key = simperby_governance::generate_dms_key(&last_finalized_header);
         let consensus_dms_key = simperby_consensus::generate_dms_key(&last_finalized_header);
+        let heartbeat_dms_key =
+            heartbeat::generate_dms_key(&reserved_state.genesis_info.chain_name);
+        let blob_dms_key = blob::generate_dms_key(&reserved_state.genesis_info.chain_name);
+        let transaction_pool_dms_key =
+            transaction_pool::generate_dms_key(&reserved_state.genesis_info.chain_name);
 
+        let peers = peers::read_all(path, &config.peers).await?;
         let server_network_config = ServerNetworkConfig {
             network_id: reserved_state.genesis_info.chain_name.clone(),
             ports: vec![
@@ -56,6 +131,15 @@ impl SimperbyNode {
                     format!("dms-{}", consensus_dms_key.clone()),
                     config.consensus_port,
                 ),
+                (
+                    format!("dms-{}", heartbeat_dms_key.clone()),
+                    config.heartbeat_port,
+                ),
+                (format!("dms-{}", blob_dms_key.clone()), config.blob_port),
+                (
+                    format!("dms-{}", transaction_pool_dms_key.clone()),
+                    config.transaction_pool_port,
+                ),
                 ("repository".to_owned(), config.repository_port),
             ]
             .into_iter()
@@ -63,108 +147,409 @@ impl SimperbyNode {
             members: reserved_state
                 .members
                 .iter()
-                .map(|m| m.public_key.clone())
+                .flat_map(|m| {
+                    let mut keys = m.governance_keys();
+                    keys.push(m.public_key.clone());
+                    keys.sort();
+                    keys.dedup();
+                    keys
+                })
                 .collect(),
-            public_key: config.public_key.clone(),
-            private_key: config.private_key.clone(),
+            // An observer without a key of its own joins the network with a one-off key.
+            private_key: match &config.private_key {
+                Some(private_key) => private_key.clone(),
+                None => generate_keypair_random().1,
+            },
+            relay: config.relay.clone(),
+            relayed_peers: peers
+                .iter()
+                .filter(|peer| peer.relay.is_some() && peer.relay == config.public_key)
+                .map(|peer| peer.public_key.clone())
+                .collect(),
+            transport: config.transport,
         };
 
         let client_network_config = ClientNetworkConfig {
             network_id: server_network_config.network_id.clone(),
             members: server_network_config.members.clone(),
-            public_key: server_network_config.public_key.clone(),
             private_key: server_network_config.private_key.clone(),
-            peers: config.peers.clone(),
+            peers,
+            transport: config.transport,
         };
 
-        let dms_peers = reserved_state
-            .get_governance_set()
-            .map_err(|e| eyre!("{e}"))?
-            .into_iter()
-            .map(|(public_key, _)| public_key)
-            .collect::<Vec<_>>();
+        // All the DMSs share the tap, if enabled.
+        let tap = config
+            .message_tap
+            .clone()
+            .map(|tap_config| MessageTap::open(format!("{path}/{MESSAGE_TAP_FILE}"), tap_config))
+            .transpose()?;
+
+        // Step 2-3: initialize the governance and the consensus modules
+        // (cleared if restored, as they're of the height already finalized)
+        let epoch = repository.current_epoch().await?;
+        let (governance, consensus) = Self::open_height_modules(
+            &config,
+            &storage_layout,
+            &last_finalized_header,
+            &epoch,
+            tap.clone(),
+            restored,
+        )
+        .await?;
+
+        let shutdown = ShutdownController::default();
+        let mut background_tasks = Vec::new();
 
-        // Step 2: initialize the governance module
-        let dms_path = format!("{path}/governance/dms");
-        StorageImpl::create(&dms_path).await.unwrap();
-        let storage = StorageImpl::open(&dms_path).await.unwrap();
-        let dms = Dms::new(
+        // Step 4: initialize the heartbeat
+        let storage = storage_layout.heartbeat_dms().open().await?;
+        let mut dms = open_dms(
             storage,
             DmsConfig {
-                dms_key: governance_dms_key,
-                peers: dms_peers.clone(),
+                dms_key: heartbeat_dms_key,
+                members: server_network_config.members.clone(),
+                priority_weights: Default::default(),
             },
-            config.private_key.clone(),
+            &config,
         )
         .await?;
-        let governance =
-            Governance::new(Arc::new(RwLock::new(dms)), Some(config.private_key.clone())).await?;
-
-        // Step 3: initialize the consensus module
-        let dms_path = format!("{path}/consensus/dms");
-        StorageImpl::create(&dms_path).await.unwrap();
-        let storage = StorageImpl::open(&dms_path).await.unwrap();
-        let dms = Dms::new(
+        dms.set_tap(tap.clone());
+        dms.set_chain_id(Some(chain_id(&reserved_state)));
+        dms.set_banned_members(reserved_state.banned_public_keys());
+        let heartbeat = Arc::new(RwLock::new(dms));
+        // An observer isn't a member, so the others wouldn't accept its heartbeats.
+        if let Some(interval) = config.heartbeat_interval_ms.filter(|_| !config.observer) {
+            let beating = heartbeat::run(
+                Arc::clone(&heartbeat),
+                Duration::from_millis(interval),
+                Duration::from_millis(HEARTBEAT_RETENTION_MS),
+            );
+            let stopped = shutdown.requested();
+            background_tasks.push(tokio::spawn(async move {
+                tokio::select! {
+                    _ = beating => {}
+                    _ = stopped => {}
+                }
+            }));
+        }
+
+        // Step 5: initialize the blob store, the transaction pool and the finalization proof store
+        let storage = storage_layout.blob_dms().open().await?;
+        let mut dms = open_dms(
             storage,
             DmsConfig {
-                dms_key: consensus_dms_key,
-                peers: dms_peers.clone(),
+                dms_key: blob_dms_key,
+                members: server_network_config.members.clone(),
+                priority_weights: Default::default(),
             },
-            config.private_key.clone(),
+            &config,
         )
         .await?;
-        let state_path = format!("{path}/consensus/state");
-        StorageImpl::create(&state_path).await.unwrap();
-        let consensus_state_storage = StorageImpl::open(&state_path).await.unwrap();
-        let consensus = Consensus::new(
-            Arc::new(RwLock::new(dms)),
-            consensus_state_storage,
-            last_finalized_header.clone(),
-            // TODO: replace params and timestamp with proper values
-            ConsensusParameters {
-                timeout_ms: 10000000,
-                repeat_round_for_first_leader: 100,
+        dms.set_tap(tap.clone());
+        dms.set_chain_id(Some(chain_id(&reserved_state)));
+        dms.set_banned_members(reserved_state.banned_public_keys());
+        repository.set_blob_store(BlobStore::new(Arc::new(RwLock::new(dms))));
+        let storage = storage_layout.transaction_pool_dms().open().await?;
+        let mut dms = open_dms(
+            storage,
+            DmsConfig {
+                dms_key: transaction_pool_dms_key,
+                members: server_network_config.members.clone(),
+                priority_weights: Default::default(),
             },
-            0,
-            Some(config.private_key.clone()),
+            &config,
         )
         .await?;
-        Ok(Self {
+        dms.set_tap(tap.clone());
+        dms.set_chain_id(Some(chain_id(&reserved_state)));
+        dms.set_banned_members(reserved_state.banned_public_keys());
+        repository.set_transaction_pool(TransactionPool::new(Arc::new(RwLock::new(dms))));
+        // Unlike the DMSs, the proofs are kept across the restarts.
+        repository.set_proof_store(ProofStore::new(
+            storage_layout.finalization_proofs().open().await?,
+        ));
+        repository
+            .set_header_cache_storage(storage_layout.header_cache().open().await?)
+            .await?;
+        repository
+            .set_transaction_index_storage(storage_layout.transaction_index().open().await?)
+            .await?;
+        let (progress_reporter, progress) = ProgressReporter::new();
+        repository.set_progress_reporter(progress_reporter);
+
+        // Step 6: schedule the repository pruning
+        if let Some(interval) = config.prune_interval_ms {
+            let raw = repository.get_raw();
+            let policy = repository.get_config().prune_policy.clone();
+            let stopped = shutdown.requested();
+            background_tasks.push(tokio::spawn(async move {
+                tokio::pin!(stopped);
+                let mut interval = tokio::time::interval(Duration::from_millis(interval));
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = &mut stopped => return,
+                    }
+                    // A pruning in progress is finished even if the shutdown is requested meanwhile.
+                    let result =
+                        simperby_repository::interpret::prune(&mut *raw.write().await, &policy)
+                            .await;
+                    if let Err(e) = result {
+                        log::warn!("failed to prune the repository: {e}");
+                    }
+                }
+            }));
+        }
+
+        // Step 7: start the webhook dispatcher
+        let events = EventPublisher::default();
+        if !config.webhooks.is_empty() {
+            let dispatcher = webhook::WebhookDispatcher::new(
+                config.webhooks.clone(),
+                config.signing_key()?.clone(),
+            );
+            let dispatching = dispatcher.run(events.subscribe());
+            let stopped = shutdown.requested();
+            background_tasks.push(tokio::spawn(async move {
+                tokio::select! {
+                    _ = dispatching => {}
+                    _ = stopped => {}
+                }
+            }));
+        }
+
+        // Step 8: start the indexer
+        if let Some(indexer_config) = &config.indexer {
+            let indexer =
+                Indexer::new(repository.get_raw(), open_sink(indexer_config).await?).await?;
+            let indexing = indexer.run(events.subscribe());
+            let stopped = shutdown.requested();
+            background_tasks.push(tokio::spawn(async move {
+                tokio::select! {
+                    _ = indexing => {}
+                    _ = stopped => {}
+                }
+            }));
+        }
+
+        // Step 9: start the HTTP gateway
+        if let Some(gateway_config) = &config.gateway {
+            let address = gateway_config.listen_address.parse()?;
+            let raw = repository.get_raw();
+            let stopped = shutdown.requested();
+            background_tasks.push(tokio::spawn(async move {
+                if let Err(e) = gateway::serve(raw, address, stopped).await {
+                    log::error!("the HTTP gateway has stopped: {e}");
+                }
+            }));
+        }
+        let fork_evidence = fork::read(path).await?;
+        if let Some(evidence) = fork_evidence.first() {
+            log::error!(
+                "the finalization is halted by the fork at height {}; remove {} after resolving it",
+                evidence.height(),
+                fork::FORK_EVIDENCE_FILE_NAME
+            );
+        }
+        let health = HealthMonitor::new(last_finalized_header.height, get_timestamp());
+        let lease_store = config.standby.as_ref().map(|standby| {
+            Box::new(FileLeaseStore::new(&standby.lease_file)) as Box<dyn LeaseStore>
+        });
+        let mut execution_hooks = ExecutionHooks::default();
+        execution_hooks.set_progress_storage(storage_layout.execution_progress().open().await?);
+        let mut node = Self {
             config,
             repository,
             governance,
             consensus,
+            heartbeat,
             last_reserved_state: reserved_state,
             last_finalized_header,
-            _path: path.to_owned(),
-            _client_network_config: client_network_config,
-            _server_network_config: server_network_config,
-        })
+            path: path.to_owned(),
+            tap,
+            events,
+            progress,
+            execution_hooks,
+            block_template_filter: Box::new(DefaultBlockTemplateFilter),
+            last_executed_commit_hash,
+            evidence_pool: Vec::new(),
+            offline_reports: Vec::new(),
+            fork_evidence,
+            shutdown,
+            health,
+            lease_store,
+            lease: None,
+            background_tasks,
+            client_network_config,
+            server_network_config,
+        };
+        // Step 8: draft the reports of the offline validators
+        node.draft_offline_reports().await?;
+        Ok(node)
     }
 
-    pub fn get_raw_repo(&self) -> &RawRepository {
-        self.repository.get_raw()
+    /// Applies the new config to the running node, without dropping the consensus state.
+    ///
+    /// Only some of the fields take effect right away;
+    /// the others keep their previous values until the restart, as reported.
+    /// An invalid config is rejected as a whole (see [`config_watch::validate`]).
+    pub async fn reload_config(&mut self, config: Config) -> Result<ConfigReloadReport> {
+        config_watch::validate(&self.config, &config)?;
+        let (config, report) = config_watch::merge(&self.config, &config);
+        self.repository
+            .set_require_signed_commits(config.require_signed_commits);
+        if !config.observer {
+            self.repository
+                .get_raw()
+                .write()
+                .await
+                .set_git_signer(config.git_signer.clone())
+                .await;
+        }
+        self.client_network_config.peers = peers::read_all(&self.path, &config.peers).await?;
+        self.config = config;
+        if !report.restart_required.is_empty() {
+            log::warn!(
+                "the config has changed, but these fields take effect after a restart: {}",
+                report.restart_required.join(", ")
+            );
+        }
+        // The drafts follow the new policy.
+        self.draft_offline_reports().await?;
+        Ok(report)
+    }
+
+    /// Returns the controller that the API servers guard their calls with,
+    /// which is shared with [`Self::shutdown`].
+    pub fn get_shutdown_controller(&self) -> ShutdownController {
+        self.shutdown.clone()
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the monitor that the [`watchdog`] checks,
+    /// which can be queried without locking the node.
+    pub fn get_health_monitor(&self) -> HealthMonitor {
+        self.health.clone()
+    }
+
+    /// Returns the health of the node as of the last check by the [`watchdog`].
+    pub fn get_health(&self) -> HealthStatus {
+        self.health.status()
+    }
+
+    /// Shuts the node down: stops the background tasks (letting a pruning in progress finish)
+    /// and flushes the pending consensus messages to the storage.
+    ///
+    /// The network operations are rejected afterwards; the node is meant to be dropped.
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.shutdown.request();
+        for task in self.background_tasks.drain(..) {
+            if let Err(e) = task.await {
+                log::warn!("a background task failed: {e}");
+            }
+        }
+        self.consensus.flush().await?;
+        self.governance.flush().await?;
+        log::info!("the node has been shut down");
+        Ok(())
+    }
+
+    /// Subscribes to the events emitted by this node.
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Watches the progress of fetching and verifying the commits.
+    ///
+    /// The receiver can be taken before a long operation starts,
+    /// as the operation holds the node meanwhile.
+    pub fn subscribe_progress(&self) -> tokio::sync::watch::Receiver<Option<Progress>> {
+        self.progress.clone()
+    }
+
+    /// Registers an application-level execution hook.
+    ///
+    /// Hooks are invoked in the order of registration, on every block finalized
+    /// after the registration.
+    pub fn register_execution_hook(&mut self, hook: Box<dyn ExecutionHook>) {
+        self.execution_hooks.register(hook);
+    }
+
+    /// Sets the filter that decides which agenda the blocks of this node finalize,
+    /// replacing the previous one.
+    pub fn set_block_template_filter(&mut self, filter: Box<dyn BlockTemplateFilter>) {
+        self.block_template_filter = filter;
+    }
+
+    /// Replaces the store of the leadership leases in the standby mode,
+    /// e.g., with a remote lock service instead of the lease file.
+    pub fn set_lease_store(&mut self, lease_store: Box<dyn LeaseStore>) {
+        self.lease_store = Some(lease_store);
+    }
+
+    /// Acquires the leadership lease in the standby mode, so that this node starts to sign.
+    ///
+    /// It fails if another node holds an unexpired lease.
+    pub async fn promote(&mut self) -> Result<LeadershipLease> {
+        self.check_not_observer("promote")?;
+        let (Some(lease_store), Some(standby)) = (&self.lease_store, &self.config.standby) else {
+            return Err(eyre!("the node is not in the standby mode"));
+        };
+        let lease = lease_store
+            .acquire(
+                &standby.instance_name,
+                &self.config.signing_key()?.public_key(),
+                standby.lease_ttl_ms,
+                get_timestamp(),
+            )
+            .await?;
+        log::info!(
+            "promoted with the leadership lease (fencing token {})",
+            lease.fencing_token
+        );
+        self.lease = Some(lease.clone());
+        Ok(lease)
+    }
+
+    /// Releases the leadership lease, so that this node stops signing and a standby can take over.
+    pub async fn demote(&mut self) -> Result<()> {
+        let Some(lease) = self.lease.take() else {
+            return Ok(());
+        };
+        if let Some(lease_store) = &self.lease_store {
+            lease_store.release(&lease).await?;
+        }
+        log::info!("demoted, releasing the leadership lease");
+        Ok(())
+    }
+
+    /// Returns the leadership lease held by this node, if it's an active one in the standby mode.
+    pub fn get_leadership_lease(&self) -> Option<&LeadershipLease> {
+        self.lease.as_ref()
     }
 
-    pub fn get_raw_repo_mut(&mut self) -> &mut RawRepository {
-        self.repository.get_raw_mut()
+    pub fn get_raw_repo(&self) -> Arc<RwLock<RawRepository>> {
+        self.repository.get_raw()
     }
 
     /// Synchronizes the `finalized` branch to the last block of the `work` branch.
     pub async fn sync(&mut self, last_finalization_proof: LastFinalizationProof) -> Result<()> {
+        self.check_not_halted("sync")?;
         let work_branch_tip = self
             .repository
             .get_raw()
+            .read()
+            .await
             .locate_branch(WORK_BRANCH_NAME.into())
             .await?;
         let work_branch_tip_commit = self.repository.read_commit(work_branch_tip).await?;
         if let Commit::Block(_) = work_branch_tip_commit {
             self.repository
-                .sync(
-                    &work_branch_tip_commit.to_hash256(),
-                    &last_finalization_proof.proof,
-                )
-                .await
+                .finalize(work_branch_tip, last_finalization_proof.proof)
+                .await?;
+            self.process_finalized_commits().await
         } else {
             Err(eyre!(
                 "last commit of the work branch is not a block commit"
@@ -177,11 +562,64 @@ impl SimperbyNode {
         self.repository.clean(hard).await
     }
 
+    /// Returns the local branches that don't extend the finalized tip (e.g., after a vetoed round),
+    /// with the reasons.
+    pub async fn get_divergent_branches(&self) -> Result<Vec<DivergentBranch>> {
+        self.repository.get_divergent_branches().await
+    }
+
+    /// Discards the divergent branch or rebases it onto the finalized tip.
+    pub async fn resolve_divergence(
+        &mut self,
+        branch: String,
+        strategy: ResolutionStrategy,
+    ) -> Result<DivergenceResolution> {
+        self.check_not_shutting_down("resolve_divergence")?;
+        let resolution = self.repository.resolve_divergence(branch, strategy).await?;
+        log::info!(
+            "resolved the divergence of branch {} ({:?}): {} commit(s) replayed, {} dropped",
+            resolution.branch,
+            resolution.strategy,
+            resolution.replayed.len(),
+            resolution.dropped.len()
+        );
+        Ok(resolution)
+    }
+
+    /// Adds an evidence of a misbehavior to the pool, which will be reported in the next block
+    /// that this node creates.
+    pub fn add_evidence(&mut self, evidence: DoubleSignEvidence) -> Result<()> {
+        self.check_not_observer("add_evidence")?;
+        verify::verify_double_sign_evidence(&evidence)?;
+        if !self.evidence_pool.contains(&evidence) {
+            self.evidence_pool.push(evidence);
+        }
+        Ok(())
+    }
+
     /// Creates a block commit on the `work` branch.
+    ///
+    /// The block is created on the first agenda chosen by the block template filter,
+    /// to which the `work` branch is moved unless it's already on it.
+    /// The pending evidence in the pool is included as report transactions
+    /// unless it has been expired or already reported.
     pub async fn create_block(&mut self) -> Result<CommitHash> {
+        self.check_not_observer("create_block")?;
+        self.check_lease("create_block").await?;
+        self.check_not_halted("create_block")?;
+        self.apply_block_template().await?;
+        for evidence in self.evidence_pool.clone() {
+            let tx = ExtraAgendaTransaction::Report(TxReport {
+                evidence: Box::new(evidence),
+                timestamp: get_timestamp(),
+            });
+            if let Err(e) = self.repository.create_extra_agenda_transaction(&tx).await {
+                log::warn!("failed to include an evidence: {e}");
+            }
+        }
         let (header, commit_hash) = self
             .repository
-            .create_block(self.config.public_key.clone())
+            .create_block(self.config.signing_key()?.public_key())
             .await?;
         // automatically set as my proposal
         self.consensus
@@ -193,180 +631,1359 @@ impl SimperbyNode {
         Ok(commit_hash)
     }
 
-    /// Creates an agenda commit on the `work` branch.
-    pub async fn create_agenda(&mut self) -> Result<CommitHash> {
-        let rs = self.repository.get_reserved_state().await?;
-        let (_, commit_hash) = self
+    /// Returns the currently valid and height-acceptable agendas with their hashes,
+    /// in the order of the repository.
+    pub async fn get_agendas(&self, page: &PageRequest) -> Result<Page<(CommitHash, Hash256)>> {
+        Page::paginate(self.repository.read_agendas().await?, page)
+    }
+
+    /// Returns the governance-approved agendas, in the deterministic order.
+    pub async fn get_agenda_candidates(&self) -> Result<Vec<AgendaCandidate>> {
+        let raw = self.repository.get_raw();
+        let raw = raw.read().await;
+        let governance = self.governance.read().await?;
+        let now = get_timestamp();
+        let mut candidates = Vec::new();
+        for (commit_hash, _) in self.repository.read_governance_approved_agendas().await? {
+            let Commit::AgendaProof(proof) = self.repository.read_commit(commit_hash).await? else {
+                return Err(eyre!("{commit_hash} is not an agenda proof commit"));
+            };
+            // The vetoes are known only locally, so the vetoed agendas are still valid
+            // for the others; this node just doesn't build on them.
+            if !governance
+                .vetoers(proof.agenda_hash, &self.last_reserved_state, now)
+                .is_empty()
+            {
+                continue;
+            }
+            let agenda_commit_hash = *raw
+                .list_ancestors(commit_hash, Some(1))
+                .await?
+                .first()
+                .ok_or_else(|| eyre!("the agenda proof {commit_hash} has no parent"))?;
+            let Commit::Agenda(agenda) = self.repository.read_commit(agenda_commit_hash).await?
+            else {
+                return Err(eyre!("the agenda proof {commit_hash} is not on an agenda"));
+            };
+            // A block finalized after the voting deadline is invalid.
+            if !agenda.is_open_at(now) {
+                continue;
+            }
+            candidates.push(AgendaCandidate {
+                commit_hash,
+                agenda,
+                proof,
+            });
+        }
+        block_template::sort_candidates(&mut candidates);
+        Ok(candidates)
+    }
+
+    /// Moves the `work` branch to the agenda preferred by the block template filter.
+    ///
+    /// If the filter excludes every agenda, the `work` branch is left as it is
+    /// unless it's on one of the excluded agendas.
+    async fn apply_block_template(&mut self) -> Result<()> {
+        let candidates = self.get_agenda_candidates().await?;
+        let selected = self
+            .block_template_filter
+            .filter(candidates.clone())
+            .into_iter()
+            .filter(|candidate| candidates.contains(candidate))
+            .collect::<Vec<_>>();
+        let raw = self.repository.get_raw();
+        let mut raw = raw.write().await;
+        let work = raw.locate_branch(WORK_BRANCH_NAME.to_owned()).await?;
+        let mut work_agenda = None;
+        for candidate in &candidates {
+            if raw.find_merge_base(candidate.commit_hash, work).await.ok()
+                == Some(candidate.commit_hash)
+            {
+                work_agenda = Some(candidate);
+                break;
+            }
+        }
+        match (selected.first(), work_agenda) {
+            (Some(candidate), Some(work_agenda)) if candidate == work_agenda => Ok(()),
+            (Some(candidate), _) => {
+                raw.move_branch(WORK_BRANCH_NAME.to_owned(), candidate.commit_hash)
+                    .await?;
+                Ok(())
+            }
+            (None, Some(work_agenda)) => Err(eyre!(
+                "the work branch is on the agenda {} excluded by the block template filter",
+                work_agenda.proof.agenda_hash
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Returns the block that [`Self::create_block`] would create and its commit,
+    /// without writing to the repository.
+    ///
+    /// The pending evidence is not included, as it's committed before the block.
+    pub async fn preview_create_block(&self) -> Result<(BlockHeader, SemanticCommit)> {
+        self.check_not_observer("create_block")?;
+        self.check_not_halted("create_block")?;
+        self.repository
+            .preview_block(self.config.signing_key()?.public_key())
+            .await
+    }
+
+    /// Creates an agenda commit on the `work` branch, with the description and the voting deadline
+    /// if given.
+    pub async fn create_agenda(
+        &mut self,
+        description: Option<AgendaDescription>,
+        deadline: Option<VotingDeadline>,
+    ) -> Result<CommitHash> {
+        self.check_not_observer("create_agenda")?;
+        let rs = self
             .repository
-            .create_agenda(
-                rs.query_name(&self.config.public_key)
-                    .expect("already checked in initialization"),
+            .read_last_finalization_info()
+            .await?
+            .reserved_state;
+        let (agenda, commit_hash) = self
+            .repository
+            .create_agenda_with_description(
+                rs.query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                description,
+                deadline,
             )
             .await?;
+        self.events.publish(NodeEvent::AgendaCreated {
+            commit_hash,
+            agenda,
+        });
         Ok(commit_hash)
     }
 
+    /// Returns the agenda that [`Self::create_agenda`] would create and its commit,
+    /// without writing to the repository.
+    pub async fn preview_create_agenda(
+        &self,
+        description: Option<AgendaDescription>,
+        deadline: Option<VotingDeadline>,
+    ) -> Result<(Agenda, SemanticCommit)> {
+        self.check_not_observer("create_agenda")?;
+        let rs = self
+            .repository
+            .read_last_finalization_info()
+            .await?
+            .reserved_state;
+        self.repository
+            .preview_agenda(
+                rs.query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                description,
+                deadline,
+            )
+            .await
+    }
+
     /// Creates an extra-agenda transaction on the `work` branch.
     pub async fn create_extra_agenda_transaction(
         &mut self,
         tx: ExtraAgendaTransaction,
     ) -> Result<()> {
+        self.check_not_observer("create_extra_agenda_transaction")?;
         self.repository.create_extra_agenda_transaction(&tx).await?;
         Ok(())
     }
 
-    /// Votes on the agenda corresponding to the given `agenda_commit` and propagates the result.
-    pub async fn vote(&mut self, agenda_commit: CommitHash) -> Result<()> {
-        let valid_agendas = self.repository.get_agendas().await?;
-        let agenda_hash = if let Some(x) = valid_agendas.iter().find(|(x, _)| *x == agenda_commit) {
-            x.1
-        } else {
-            return Err(eyre!(
-                "the given commit hash {} is not one of the valid agendas",
-                agenda_commit
-            ));
-        };
-        self.repository.vote(agenda_commit).await?;
-        self.governance.vote(agenda_hash).await?;
-        Ok(())
+    /// Returns the commit that [`Self::create_extra_agenda_transaction`] would create,
+    /// without writing to the repository.
+    pub async fn preview_create_extra_agenda_transaction(
+        &self,
+        tx: ExtraAgendaTransaction,
+    ) -> Result<SemanticCommit> {
+        self.check_not_observer("create_extra_agenda_transaction")?;
+        self.repository.preview_extra_agenda_transaction(&tx).await
     }
 
-    /// Vetoes the current round.
-    pub async fn veto_round(&mut self) -> Result<()> {
-        unimplemented!()
+    /// Creates a transaction on the `work` branch that adds the member of the join request.
+    ///
+    /// It takes effect once an agenda including it is approved by the governance,
+    /// or once the block of `activation_height` is finalized if given.
+    pub async fn create_join_transaction(
+        &mut self,
+        request: JoinRequest,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash> {
+        self.check_not_observer("create_join_transaction")?;
+        self.repository
+            .create_join_transaction(
+                self.last_reserved_state
+                    .query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                &request,
+                activation_height,
+            )
+            .await
     }
 
-    /// Vetoes the given block.
-    pub async fn veto_block(&mut self, _block_commit: CommitHash) -> Result<()> {
-        unimplemented!()
+    /// Returns the reports of the offline validators drafted by
+    /// the [policy](Config::offline_report_policy), which are not submitted yet.
+    pub fn get_offline_reports(&self) -> &[OfflineReport] {
+        &self.offline_reports
     }
 
-    /// Shows information about the given commit.
-    pub async fn show(&self, commit_hash: CommitHash) -> Result<CommitInfo> {
-        let semantic_commit = self
+    /// Creates a transaction on the `work` branch from the drafted report of the offline validator.
+    ///
+    /// It takes effect as in [`Self::create_join_transaction`].
+    pub async fn create_offline_report_transaction(
+        &mut self,
+        offender: &MemberName,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash> {
+        self.check_not_observer("create_offline_report_transaction")?;
+        let report = self
+            .offline_reports
+            .iter()
+            .find(|report| &report.offender == offender)
+            .ok_or_else(|| eyre!("no drafted report of {offender}"))?
+            .clone();
+        let commit_hash = self
             .repository
-            .get_raw()
-            .read_semantic_commit(commit_hash)
+            .create_offline_report_transaction(
+                self.last_reserved_state
+                    .query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                &report,
+                activation_height,
+            )
             .await?;
-        let commit = simperby_repository::format::from_semantic_commit(semantic_commit.clone())?;
-        let result = match commit {
-            Commit::Block(block_header) => CommitInfo::Block {
-                semantic_commit,
-                block_header,
-            },
-            Commit::Agenda(agenda) => CommitInfo::Agenda {
-                semantic_commit,
-                agenda: agenda.clone(),
-                voters: self
-                    .governance
-                    .read()
-                    .await?
-                    .votes
-                    .get(&agenda.to_hash256())
-                    .unwrap_or(&Default::default())
-                    .iter()
-                    .filter_map(|(public_key, _)| {
-                        self.last_reserved_state
-                            .query_name(public_key)
-                            .map(|x| (x, 0))
-                    })
-                    .collect(), // TODO
-            },
-            Commit::AgendaProof(agenda_proof) => CommitInfo::AgendaProof {
-                semantic_commit,
-                agenda_proof,
-            },
-            x => CommitInfo::Unknown {
-                semantic_commit,
-                msg: format!("{x:?}"),
-            },
-        };
-        Ok(result)
+        self.offline_reports
+            .retain(|report| &report.offender != offender);
+        Ok(commit_hash)
     }
 
-    /// Makes a progress for the consensus, returning the result.
+    /// Creates a transaction on the `work` branch that replaces the metadata of a member.
     ///
-    /// TODO: it has to consume the object if finalized.
-    pub async fn progress_for_consensus(&mut self) -> Result<String> {
-        let result = self.consensus.progress(get_timestamp()).await?;
-        for result in result.iter() {
-            if let ProgressResult::Finalized(hash, _, proof) = result {
-                self.repository.sync(hash, proof).await?;
-            }
-        }
-        Ok(format!("{result:?}"))
+    /// It takes effect as in [`Self::create_join_transaction`].
+    pub async fn create_member_metadata_transaction(
+        &mut self,
+        update: TxUpdateMemberMetadata,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash> {
+        self.check_not_observer("create_member_metadata_transaction")?;
+        self.repository
+            .create_member_metadata_transaction(
+                self.last_reserved_state
+                    .query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                &update,
+                activation_height,
+            )
+            .await
     }
 
-    /// Gets the current status of the consensus.
-    pub async fn get_consensus_status(&self) -> Result<ConsensusStatus> {
-        todo!()
+    /// Creates a transaction on the `work` branch that replaces the consensus leader order
+    /// with the eligible leaders ordered by the strategy.
+    ///
+    /// It takes effect as in [`Self::create_join_transaction`].
+    pub async fn create_leader_order_transaction(
+        &mut self,
+        strategy: LeaderOrderStrategy,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash> {
+        self.check_not_observer("create_leader_order_transaction")?;
+        self.repository
+            .create_leader_order_transaction(
+                self.last_reserved_state
+                    .query_name(&self.config.signing_key()?.public_key())
+                    .ok_or_else(|| eyre!("this node is not a member"))?,
+                strategy,
+                activation_height,
+            )
+            .await
     }
 
-    /// Gets the current status of the p2p network.
-    pub async fn get_network_status(&self) -> Result<NetworkStatus> {
-        unimplemented!()
+    /// Returns the members of the last finalized reserved state with their delegations resolved,
+    /// in the order of the reserved state.
+    pub fn get_members(&self, page: &PageRequest) -> Result<Page<members::MemberInfo>> {
+        Page::paginate(
+            members::member_infos(&self.last_reserved_state).map_err(|e| eyre!(e))?,
+            page,
+        )
     }
 
-    pub async fn fetch(&mut self) -> Result<()> {
-        // TODO: perform the actual network operations
+    /// Returns the reserved state with the leader order rebalanced by the strategy
+    /// on top of the last finalized one, without creating a transaction.
+    pub fn review_leader_order(&self, strategy: LeaderOrderStrategy) -> Result<ReservedState> {
+        self.last_reserved_state
+            .rebalance_leader_order(strategy)
+            .map_err(|e| eyre!("failed to rebalance the leader order: {e}"))
+    }
 
-        // Update governance
-        let governance_set = self
+    /// Returns the reserved state that results from the join request
+    /// on top of the last finalized one, without creating a transaction.
+    pub fn review_join_request(&self, request: &JoinRequest) -> Result<ReservedState> {
+        self.last_reserved_state
+            .apply_join_request(request)
+            .map_err(|e| eyre!("invalid join request: {e}"))
+    }
+
+    /// Adds a large file to the blob store, returning the reference
+    /// to put in the body of a transaction (see [`BLOB_REFERENCE_PREFIX`]).
+    pub async fn put_blob(&mut self, data: Vec<u8>) -> Result<BlobReference> {
+        self.check_not_observer("put_blob")?;
+        self.blob_store()?
+            .put(data, &self.last_reserved_state)
+            .await
+    }
+
+    /// Shares the transaction commit with the other members through the transaction pool,
+    /// so that any of them can include it in an agenda.
+    pub async fn submit_pending_transaction(&mut self, commit_hash: CommitHash) -> Result<Hash256> {
+        self.check_not_observer("submit_pending_transaction")?;
+        self.repository
+            .submit_pending_transaction(commit_hash)
+            .await
+    }
+
+    /// Lists the pending transactions in the transaction pool, the oldest first.
+    pub async fn list_pending_transactions(
+        &self,
+        page: &PageRequest,
+    ) -> Result<Page<PendingTransaction>> {
+        Page::paginate(self.transaction_pool()?.list().await?, page)
+    }
+
+    /// Checks the patch bundle of a contributor and keeps it in the transaction pool,
+    /// returning its hash to accept it with.
+    pub async fn submit_patch(&mut self, bundle: PatchBundle) -> Result<Hash256> {
+        self.check_not_observer("submit_patch")?;
+        self.repository.submit_patch(bundle).await
+    }
+
+    /// Lists the patches of the contributors in the transaction pool, the oldest first.
+    pub async fn list_patches(&self, page: &PageRequest) -> Result<Page<PatchBundle>> {
+        Page::paginate(self.transaction_pool()?.list_patches().await?, page)
+    }
+
+    /// Turns the patch in the transaction pool into a transaction commit on the `work` branch,
+    /// authored by this node, and shares the transaction through the pool.
+    pub async fn accept_patch(&mut self, patch_hash: Hash256) -> Result<CommitHash> {
+        self.check_not_observer("accept_patch")?;
+        let author = self
             .last_reserved_state
-            .get_governance_set()
-            .unwrap()
-            .into_iter()
-            .collect::<HashMap<_, _>>();
-        let governance_state = self.governance.read().await?;
-        let votes: Vec<(Hash256, VotingPower)> = governance_state
-            .votes
-            .iter()
-            .map(|(agenda, votes)| {
-                (
-                    *agenda,
-                    votes
-                        .keys()
-                        .map(|voter| governance_set.get(voter).unwrap())
-                        .sum(),
-                )
-            })
-            .collect();
-        let total_voting_power = governance_set.values().sum::<VotingPower>();
-        for (agenda, voted_power) in votes {
-            if voted_power * 2 > total_voting_power {
-                // TODO: handle this error
-                let _ = self
-                    .repository
-                    .approve(
-                        &agenda,
-                        governance_state.votes[&agenda]
-                            .iter()
-                            .map(|(k, s)| TypedSignature::new(s.clone(), k.clone()))
-                            .collect(),
-                        get_timestamp(),
+            .query_name(&self.config.signing_key()?.public_key())
+            .ok_or_else(|| eyre!("this node is not a member"))?;
+        self.repository.accept_patch(author, patch_hash).await
+    }
+
+    /// Votes on the agenda corresponding to the given `agenda_commit` and propagates the result.
+    pub async fn vote(&mut self, agenda_commit: CommitHash) -> Result<()> {
+        self.check_not_observer("vote")?;
+        self.check_lease("vote").await?;
+        let agenda_hash = self.get_agenda_hash(agenda_commit).await?;
+        self.repository.vote(agenda_commit).await?;
+        match self.voting_mode(agenda_commit).await? {
+            VotingMode::Open => self.governance.vote(agenda_hash).await?,
+            // Revealed by `reveal_votes()` once the deadline has passed.
+            VotingMode::CommitReveal { .. } => {
+                self.governance
+                    .commit_vote(
+                        agenda_hash,
+                        true,
+                        Governance::derive_vote_salt(self.config.signing_key()?, agenda_hash),
                     )
-                    .await;
+                    .await?
             }
         }
+        Ok(())
+    }
 
-        // Update consensus
-        for (_, block_hash) in self.repository.get_blocks().await? {
-            self.consensus
-                .register_verified_block_hash(block_hash)
-                .await?;
+    /// Vetoes the agenda for `duration_ms`, which blocks its approval regardless of the votes
+    /// (see [`ReservedState::veto_holders`]).
+    pub async fn veto_agenda(&mut self, agenda_commit: CommitHash, duration_ms: u64) -> Result<()> {
+        self.check_not_observer("veto_agenda")?;
+        self.check_lease("veto_agenda").await?;
+        let name = self
+            .last_reserved_state
+            .query_name(&self.config.signing_key()?.public_key())
+            .ok_or_else(|| eyre!("this node is not a member"))?;
+        if !self.last_reserved_state.veto_holders.contains(&name) {
+            return Err(eyre!("{name} is not a veto holder"));
         }
-        Ok(())
+        let agenda_hash = self.get_agenda_hash(agenda_commit).await?;
+        self.governance
+            .veto(agenda_hash, get_timestamp() + duration_ms as Timestamp)
+            .await
     }
 
-    /// Broadcasts all the local messages and reports the result.
-    pub async fn broadcast(&mut self) -> Result<Vec<String>> {
-        // TODO
-        Ok(vec![])
+    /// Returns the message that [`Self::vote`] would broadcast,
+    /// without tagging the commit or writing to the governance DMS.
+    pub async fn preview_vote(&self, agenda_commit: CommitHash) -> Result<Vote> {
+        self.check_not_observer("vote")?;
+        let agenda_hash = self.get_agenda_hash(agenda_commit).await?;
+        Ok(match self.voting_mode(agenda_commit).await? {
+            VotingMode::Open => Vote::Open(agenda_hash),
+            VotingMode::CommitReveal { .. } => Vote::Commitment(VoteCommitment {
+                agenda_hash,
+                commitment: VoteReveal {
+                    agenda_hash,
+                    approve: true,
+                    salt: Governance::derive_vote_salt(self.config.signing_key()?, agenda_hash),
+                }
+                .commitment(),
+            }),
+        })
     }
 
-    pub async fn check_push(
-        &mut self,
-        _commit_hash: CommitHash,
+    /// Returns the voting mode of the agenda, by [`Config::vote_reveal_delay_ms`].
+    async fn voting_mode(&self, agenda_commit: CommitHash) -> Result<VotingMode> {
+        let Some(delay) = self.config.vote_reveal_delay_ms else {
+            return Ok(VotingMode::Open);
+        };
+        Ok(VotingMode::CommitReveal {
+            reveal_after: self.read_agenda(agenda_commit).await?.timestamp + delay as Timestamp,
+        })
+    }
+
+    async fn read_agenda(&self, agenda_commit: CommitHash) -> Result<Agenda> {
+        let semantic_commit = self
+            .repository
+            .get_raw()
+            .read()
+            .await
+            .read_semantic_commit_bounded(
+                agenda_commit,
+                self.last_reserved_state.max_commit_body_size,
+            )
+            .await?;
+        match simperby_repository::format::from_semantic_commit(semantic_commit)? {
+            Commit::Agenda(agenda) => Ok(agenda),
+            _ => Err(eyre!("{agenda_commit} is not an agenda")),
+        }
+    }
+
+    /// Reveals the hidden votes of this node whose deadlines have passed.
+    async fn reveal_votes(&mut self) -> Result<()> {
+        if self.config.vote_reveal_delay_ms.is_none() || self.config.observer {
+            return Ok(());
+        }
+        let status = self.governance.read().await?;
+        let now = get_timestamp();
+        let public_key = &self.config.signing_key()?.public_key();
+        for (agenda_commit, agenda_hash) in self.repository.read_agendas().await? {
+            let VotingMode::CommitReveal { reveal_after } = self.voting_mode(agenda_commit).await?
+            else {
+                continue;
+            };
+            let committed = status
+                .commitments
+                .get(&agenda_hash)
+                .is_some_and(|commitments| commitments.contains_key(public_key));
+            let revealed = status
+                .reveals
+                .get(&agenda_hash)
+                .is_some_and(|reveals| reveals.contains_key(public_key));
+            if now >= reveal_after && committed && !revealed {
+                // This node commits only to the approving votes (see `vote()`).
+                self.governance
+                    .reveal_vote(
+                        agenda_hash,
+                        true,
+                        Governance::derive_vote_salt(self.config.signing_key()?, agenda_hash),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the payload to sign externally for voting on the given agenda.
+    ///
+    /// This is for the governance members who keep their keys offline.
+    /// The payload is the agenda hash, which can be signed with `simperby sign custom`.
+    /// Such a vote is open, so it doesn't count in the commit-reveal mode.
+    pub async fn export_vote_payload(&self, agenda_commit: CommitHash) -> Result<Vec<u8>> {
+        let agenda_hash = self.get_agenda_hash(agenda_commit).await?;
+        Ok(Governance::vote_payload(agenda_hash))
+    }
+
+    /// Imports a vote signed externally on the payload from `export_vote_payload()`.
+    pub async fn import_signed_vote(
+        &mut self,
+        agenda_commit: CommitHash,
+        signer: PublicKey,
+        signature: Signature,
+    ) -> Result<()> {
+        self.check_not_observer("import_signed_vote")?;
+        let agenda_hash = self.get_agenda_hash(agenda_commit).await?;
+        self.governance
+            .import_signed_vote(agenda_hash, signer, signature)
+            .await?;
+        Ok(())
+    }
+
+    /// Exports the agenda for a review on a machine without network access,
+    /// sealed with the key of this node.
+    ///
+    /// The member signs it with `AgendaBundle::sign_review()` and brings the review
+    /// back to `import_agenda_review()`.
+    pub async fn export_agenda_bundle(&self, agenda_commit: CommitHash) -> Result<AgendaBundle> {
+        self.get_agenda_hash(agenda_commit).await?;
+        self.repository
+            .export_agenda_bundle(agenda_commit, self.config.signing_key()?)
+            .await
+    }
+
+    /// Imports a vote from an offline review, broadcasting it like `import_signed_vote()`.
+    pub async fn import_agenda_review(&mut self, review: AgendaReview) -> Result<()> {
+        self.check_not_observer("import_agenda_review")?;
+        let Some((agenda_commit, _)) = self
+            .repository
+            .read_agendas()
+            .await?
+            .into_iter()
+            .find(|(_, agenda_hash)| *agenda_hash == review.agenda_hash)
+        else {
+            return Err(eyre!(
+                "the reviewed agenda {} is not one of the valid agendas",
+                review.agenda_hash
+            ));
+        };
+        let agenda = self.read_agenda(agenda_commit).await?;
+        review.verify(&agenda)?;
+        if !agenda.is_open_at(get_timestamp()) {
+            return Err(eyre!(
+                "the voting deadline of the agenda {agenda_commit} has passed"
+            ));
+        }
+        self.governance
+            .import_signed_vote(
+                review.agenda_hash,
+                review.reviewer().clone(),
+                review.signature.get_raw_signature(),
+            )
+            .await?;
+        log::info!(
+            "imported the review of agenda {agenda_commit} by {}",
+            review.reviewer()
+        );
+        Ok(())
+    }
+
+    /// Broadcasts a non-binding poll to the governance members, open for `duration_ms`,
+    /// and returns its hash.
+    pub async fn create_poll(
+        &mut self,
+        title: String,
+        description: String,
+        options: Vec<String>,
+        duration_ms: u64,
+    ) -> Result<Hash256> {
+        self.check_not_observer("create_poll")?;
+        let now = get_timestamp();
+        self.governance
+            .create_poll(Poll {
+                title,
+                description,
+                options,
+                deadline: now + duration_ms as Timestamp,
+                timestamp: now,
+            })
+            .await
+    }
+
+    /// Votes for the option of the poll, by its index in [`Poll::options`].
+    pub async fn vote_poll(&mut self, poll_hash: Hash256, option: u32) -> Result<()> {
+        self.check_not_observer("vote_poll")?;
+        self.check_lease("vote_poll").await?;
+        let status = self.governance.read().await?;
+        let poll = status
+            .polls
+            .get(&poll_hash)
+            .ok_or_else(|| eyre!("unknown poll: {poll_hash}"))?;
+        if option as usize >= poll.options.len() {
+            return Err(eyre!(
+                "the poll has only {} options, not {}",
+                poll.options.len(),
+                option + 1
+            ));
+        }
+        self.governance
+            .vote_poll(poll_hash, option, get_timestamp())
+            .await
+    }
+
+    /// Returns the polls in the governance DMS with their current results, the latest first.
+    pub async fn get_polls(&self, page: &PageRequest) -> Result<Page<PollTally>> {
+        let status = self.governance.read().await?;
+        let now = get_timestamp();
+        let mut tallies = status
+            .polls
+            .keys()
+            .map(|poll_hash| status.tally_poll(*poll_hash, &self.last_reserved_state, now))
+            .collect::<Result<Vec<_>>>()?;
+        tallies.sort_by_key(|tally| std::cmp::Reverse(tally.poll.timestamp));
+        Page::paginate(tallies, page)
+    }
+
+    pub async fn tally_poll(&self, poll_hash: Hash256) -> Result<PollTally> {
+        self.governance
+            .tally_poll(poll_hash, &self.last_reserved_state, get_timestamp())
+            .await
+    }
+
+    async fn get_agenda_hash(&self, agenda_commit: CommitHash) -> Result<Hash256> {
+        let valid_agendas = self.repository.read_agendas().await?;
+        if let Some(x) = valid_agendas.iter().find(|(x, _)| *x == agenda_commit) {
+            Ok(x.1)
+        } else {
+            Err(eyre!(
+                "the given commit hash {} is not one of the valid agendas",
+                agenda_commit
+            ))
+        }
+    }
+
+    /// Vetoes the current round.
+    pub async fn veto_round(&mut self) -> Result<()> {
+        self.check_not_observer("veto_round")?;
+        self.check_lease("veto_round").await?;
+        let round = self.consensus.get_round().await?;
+        self.consensus.veto_round(round, get_timestamp()).await?;
+        Ok(())
+    }
+
+    /// Vetoes the given block.
+    pub async fn veto_block(&mut self, block_commit: CommitHash) -> Result<()> {
+        self.check_not_observer("veto_block")?;
+        self.check_lease("veto_block").await?;
+        let (_, block_hash) = self
+            .repository
+            .read_blocks()
+            .await?
+            .into_iter()
+            .find(|(commit_hash, _)| *commit_hash == block_commit)
+            .ok_or_else(|| {
+                eyre!("the given commit hash {block_commit} is not one of the valid blocks")
+            })?;
+        self.consensus.veto_block(block_hash).await?;
+        Ok(())
+    }
+
+    /// Tallies the votes on the agenda with the last finalized reserved state,
+    /// in its voting mode (see [`Config::vote_reveal_delay_ms`]).
+    pub async fn tally(&self, agenda_hash: Hash256) -> Result<Tally> {
+        let agenda_commit = self
+            .repository
+            .read_agendas()
+            .await?
+            .into_iter()
+            .find(|(_, hash)| *hash == agenda_hash)
+            .map(|(commit, _)| commit);
+        let mode = match agenda_commit {
+            Some(agenda_commit) => self.voting_mode(agenda_commit).await?,
+            None => VotingMode::Open,
+        };
+        self.governance
+            .tally_in_mode(
+                agenda_hash,
+                &self.last_reserved_state,
+                mode,
+                get_timestamp(),
+            )
+            .await
+    }
+
+    /// Shows information about the given commit.
+    pub async fn show(&self, commit_hash: CommitHash) -> Result<CommitInfo> {
+        let semantic_commit = self
+            .repository
+            .get_raw()
+            .read()
+            .await
+            .read_semantic_commit_bounded(
+                commit_hash,
+                self.last_reserved_state.max_commit_body_size,
+            )
+            .await?;
+        // A finalized block is in the header cache, not to be parsed again.
+        let commit = match self
+            .repository
+            .get_header_by_commit_hash(commit_hash)
+            .await?
+        {
+            Some(header) => Commit::Block(header),
+            None => simperby_repository::format::from_semantic_commit(semantic_commit.clone())?,
+        };
+        let result = match commit {
+            Commit::Block(block_header) => CommitInfo::Block {
+                semantic_commit,
+                block_header,
+            },
+            Commit::Agenda(agenda) => CommitInfo::Agenda {
+                semantic_commit,
+                agenda: agenda.clone(),
+                voters: self
+                    .governance
+                    .read()
+                    .await?
+                    .votes
+                    .get(&agenda.to_hash256())
+                    .unwrap_or(&Default::default())
+                    .keys()
+                    .filter_map(|public_key| {
+                        self.last_reserved_state
+                            .query_name(public_key)
+                            .map(|x| (x, 0))
+                    })
+                    .collect(), // TODO
+                tally: self.tally(agenda.to_hash256()).await?,
+            },
+            Commit::AgendaProof(agenda_proof) => CommitInfo::AgendaProof {
+                semantic_commit,
+                agenda_proof,
+            },
+            x => CommitInfo::Unknown {
+                semantic_commit,
+                msg: format!("{x:?}"),
+            },
+        };
+        Ok(result)
+    }
+
+    /// Replays the finalized history from the given height, re-verifying every commit.
+    pub async fn verify_finalized_history(
+        &self,
+        from_height: BlockHeight,
+    ) -> Result<HistoryVerificationReport> {
+        self.repository.verify_finalized_history(from_height).await
+    }
+
+    /// Verifies both the in-commit and the git's native signatures of the finalized commits,
+    /// in the order of the history.
+    pub async fn verify_commit_signatures(
+        &self,
+        page: &PageRequest,
+    ) -> Result<Page<CommitSignatureReport>> {
+        Page::paginate(self.repository.verify_commit_signatures().await?, page)
+    }
+
+    /// Writes the finalized blocks from the given height to the indexer database,
+    /// overwriting those written before, and returns the height of the last one.
+    pub async fn backfill_index(&self, from_height: BlockHeight) -> Result<Option<BlockHeight>> {
+        let config = self
+            .config
+            .indexer
+            .as_ref()
+            .ok_or_else(|| eyre!("the indexer is not configured"))?;
+        Indexer::new(self.repository.get_raw(), open_sink(config).await?)
+            .await?
+            .backfill(from_height)
+            .await
+    }
+
+    /// Reads the information of the last finalized block.
+    pub async fn get_last_finalization_info(&self) -> Result<FinalizationInfo> {
+        self.repository.read_last_finalization_info().await
+    }
+
+    /// Reads the finalization proof of the finalized block at the given height.
+    pub async fn get_finalization_proof(
+        &mut self,
+        height: BlockHeight,
+    ) -> Result<Option<FinalizationProof>> {
+        self.repository.get_finalization_proof(height).await
+    }
+
+    /// Checks whether the transaction of the given hash has been finalized.
+    pub async fn is_transaction_finalized(&self, transaction_hash: Hash256) -> Result<bool> {
+        self.repository
+            .is_transaction_finalized(transaction_hash)
+            .await
+    }
+
+    /// Computes the statistics of the chain over the given number of the most recent blocks.
+    pub async fn get_chain_stats(&self, window: u64) -> Result<ChainStats> {
+        let lfi = self.repository.read_last_finalization_info().await?;
+        let from_height = lfi.header.height.saturating_sub(window);
+        let (from_commit_hash, from_header) = self
+            .repository
+            .get_finalized_block(from_height)
+            .await?
+            .ok_or_else(|| eyre!("the block at height {from_height} is not available"))?;
+        let raw = self.repository.get_raw();
+        let raw = raw.read().await;
+        let mut commits = vec![Commit::Block(from_header)];
+        if from_commit_hash != lfi.commit_hash {
+            commits.extend(
+                simperby_repository::interpret::read_commits(
+                    &raw,
+                    from_commit_hash,
+                    lfi.commit_hash,
+                )
+                .await?
+                .into_iter()
+                .map(|(commit, _)| commit),
+            );
+        }
+        Ok(stats::compute(
+            &commits,
+            &lfi.proof,
+            &self.config.consensus_params,
+        ))
+    }
+
+    /// Exports the audit log of the governance over the given heights,
+    /// sealed with the configured private key.
+    pub async fn export_audit_log(
+        &self,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+    ) -> Result<AuditLog> {
+        if from_height == 0 || from_height > to_height {
+            return Err(eyre!("invalid height range: {from_height} to {to_height}"));
+        }
+        let (commits, reserved_state) = self.read_finalized_commits(from_height, to_height).await?;
+        Governance::export_audit_log(
+            from_height..=to_height,
+            &commits,
+            &reserved_state,
+            self.config.signing_key()?,
+        )
+    }
+
+    /// Verifies the audit log (see [`Self::export_audit_log`]) against the finalized history,
+    /// returning the first discrepancy if any.
+    pub async fn verify_audit_log(&self, log: &AuditLog) -> Result<Result<(), String>> {
+        if log.seal.from == 0 || log.seal.from > log.seal.to {
+            return Ok(Err("invalid height range".to_owned()));
+        }
+        let (commits, reserved_state) = self
+            .read_finalized_commits(log.seal.from, log.seal.to)
+            .await?;
+        let history = audit::collect_audit_entries(&commits, &reserved_state)?;
+        Ok(log.verify(&history))
+    }
+
+    /// Reads the finalized commits from the block before `from_height` to the block at `to_height`,
+    /// with the last finalized reserved state.
+    async fn read_finalized_commits(
+        &self,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+    ) -> Result<(Vec<Commit>, ReservedState)> {
+        let lfi = self.repository.read_last_finalization_info().await?;
+        let block = |height: BlockHeight| async move {
+            self.repository
+                .get_finalized_block(height)
+                .await?
+                .ok_or_else(|| eyre!("the block at height {height} is not available"))
+        };
+        let (from_commit_hash, from_header) = block(from_height - 1).await?;
+        let (to_commit_hash, _) = block(to_height).await?;
+        let raw = self.repository.get_raw();
+        let raw = raw.read().await;
+        let mut commits = vec![Commit::Block(from_header)];
+        commits.extend(
+            simperby_repository::interpret::read_commits(&raw, from_commit_hash, to_commit_hash)
+                .await?
+                .into_iter()
+                .map(|(commit, _)| commit),
+        );
+        Ok((commits, lfi.reserved_state))
+    }
+
+    /// Makes a progress for the consensus, returning the furthest step it has made.
+    ///
+    /// TODO: it has to consume the object if finalized.
+    pub async fn progress_for_consensus(&mut self) -> Result<ConsensusProgressOutcome> {
+        self.check_not_observer("progress_for_consensus")?;
+        self.check_lease("progress_for_consensus").await?;
+        self.check_not_shutting_down("progress_for_consensus")?;
+        self.check_not_halted("progress_for_consensus")?;
+        let result = self.consensus.progress(get_timestamp()).await;
+        self.health.record_storage_failure(&result);
+        let result = result?;
+        // How far each result goes: the finalization is the furthest,
+        // and otherwise the later round, and then the later step in the round
+        // (0 for the proposal, 1 for the prevote and 2 for the precommit).
+        let mut furthest: Option<((bool, ConsensusRound, u8), ConsensusProgressOutcome)> = None;
+        for result in result {
+            let (step, outcome) = match result {
+                ProgressResult::Proposed(round, block_hash, _) => (
+                    (false, round, 0),
+                    ConsensusProgressOutcome::Proposed { round, block_hash },
+                ),
+                ProgressResult::NonNilPreVoted(round, block_hash, _) => (
+                    (false, round, 1),
+                    ConsensusProgressOutcome::Prevoted { round, block_hash },
+                ),
+                ProgressResult::NilPreVoted(round, _) => (
+                    (false, round, 1),
+                    ConsensusProgressOutcome::TimedOut { round },
+                ),
+                ProgressResult::NonNilPreCommitted(round, block_hash, _) => (
+                    (false, round, 2),
+                    ConsensusProgressOutcome::Precommitted { round, block_hash },
+                ),
+                ProgressResult::NilPreCommitted(round, _) => (
+                    (false, round, 2),
+                    ConsensusProgressOutcome::TimedOut { round },
+                ),
+                ProgressResult::Finalized(hash, _, proof) => {
+                    let (commit_hash, _) = self
+                        .repository
+                        .read_blocks()
+                        .await?
+                        .into_iter()
+                        .find(|(_, block_hash)| *block_hash == hash)
+                        .ok_or_else(|| {
+                            eyre!("the finalized block {hash} is not in the repository")
+                        })?;
+                    self.repository.finalize(commit_hash, proof).await?;
+                    self.process_finalized_commits().await?;
+                    let height = self
+                        .repository
+                        .get_last_finalized_block_header()
+                        .await?
+                        .height;
+                    (
+                        (true, 0, 0),
+                        ConsensusProgressOutcome::Finalized { height, hash },
+                    )
+                }
+                ProgressResult::ViolationReported(public_key, description, _) => {
+                    log::warn!("consensus violation by {public_key}: {description}");
+                    continue;
+                }
+            };
+            if furthest
+                .as_ref()
+                .is_none_or(|(furthest_step, _)| step >= *furthest_step)
+            {
+                furthest = Some((step, outcome));
+            }
+        }
+        Ok(furthest.map_or(ConsensusProgressOutcome::Noop, |(_, outcome)| outcome))
+    }
+
+    /// Reads the log of the consensus rounds of the given height, for diagnosing liveness failures.
+    ///
+    /// Only the recent heights are kept (see [`Config::round_history_heights`]).
+    pub async fn get_round_history(&self, height: BlockHeight) -> Result<Option<RoundHistory>> {
+        self.consensus.get_round_history(height).await
+    }
+
+    /// Returns what the pruning of the consensus round histories and snapshots has reclaimed.
+    pub async fn get_consensus_pruning_metrics(&self) -> Result<PruningMetrics> {
+        self.consensus.get_pruning_metrics().await
+    }
+
+    /// Returns who vetoed which round or block of the height being decided,
+    /// with the accumulated voting power and whether it's enough to block the finalization.
+    pub async fn get_pending_vetoes(&self) -> Result<Vec<PendingVeto>> {
+        self.consensus.get_pending_vetoes().await
+    }
+
+    /// Returns the last consensus message position signed by this node,
+    /// at or below which nothing else is signed.
+    pub async fn get_sign_watermark(&self) -> Result<Option<SignWatermark>> {
+        self.consensus.get_sign_watermark().await
+    }
+
+    /// Removes the sign watermark, returning it, so that the consensus messages refused by it
+    /// are signed on the next progress.
+    ///
+    /// The operator must have made sure that the key hasn't signed them before.
+    pub async fn override_sign_watermark(&mut self) -> Result<Option<SignWatermark>> {
+        self.check_not_observer("override_sign_watermark")?;
+        let watermark = self.consensus.override_sign_watermark().await?;
+        if let Some(watermark) = &watermark {
+            log::warn!("the sign watermark has been overridden: {watermark:?}");
+        }
+        Ok(watermark)
+    }
+
+    /// Gets the current status of the consensus.
+    pub async fn get_consensus_status(&self) -> Result<ConsensusStatus> {
+        Ok(ConsensusStatus {
+            pending_vetoes: self.get_pending_vetoes().await?,
+        })
+    }
+
+    /// Gets the current status of the p2p network.
+    pub async fn get_network_status(&self) -> Result<NetworkStatus> {
+        let members = &self.last_reserved_state.members;
+        let liveness = heartbeat::read_liveness(
+            &*self.heartbeat.read().await,
+            &members
+                .iter()
+                .map(|member| member.public_key.clone())
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+        let sync_statistics = vec![
+            sync_statistics(&*self.governance.get_dms().read().await),
+            sync_statistics(&*self.consensus.get_dms().read().await),
+            sync_statistics(&*self.heartbeat.read().await),
+            sync_statistics(&*self.blob_store()?.get_dms().read().await),
+            sync_statistics(&*self.transaction_pool()?.get_dms().read().await),
+        ];
+        let chain_mismatches = vec![
+            chain_mismatches(&*self.governance.get_dms().read().await),
+            chain_mismatches(&*self.consensus.get_dms().read().await),
+            chain_mismatches(&*self.heartbeat.read().await),
+            chain_mismatches(&*self.blob_store()?.get_dms().read().await),
+            chain_mismatches(&*self.transaction_pool()?.get_dms().read().await),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        Ok(NetworkStatus {
+            liveness: members
+                .iter()
+                .map(|member| member.name.clone())
+                .zip(liveness)
+                .collect(),
+            sync_statistics,
+            chain_mismatches,
+        })
+    }
+
+    /// Returns which members have acknowledged the message (e.g., a vote) broadcast by this node,
+    /// looking it up in every DMS.
+    pub async fn get_message_propagation(
+        &self,
+        message_hash: Hash256,
+    ) -> Result<Option<MessagePropagation>> {
+        let propagations = [
+            self.governance
+                .get_dms()
+                .read()
+                .await
+                .get_message_propagation(message_hash)
+                .await?,
+            self.consensus
+                .get_dms()
+                .read()
+                .await
+                .get_message_propagation(message_hash)
+                .await?,
+            self.heartbeat
+                .read()
+                .await
+                .get_message_propagation(message_hash)
+                .await?,
+            self.blob_store()?
+                .get_dms()
+                .read()
+                .await
+                .get_message_propagation(message_hash)
+                .await?,
+            self.transaction_pool()?
+                .get_dms()
+                .read()
+                .await
+                .get_message_propagation(message_hash)
+                .await?,
+        ];
+        Ok(propagations.into_iter().flatten().next())
+    }
+
+    /// Adds the peer of the member with the given name to `peers.json`.
+    ///
+    /// The peer is assumed to serve on the same ports as this node.
+    /// If the peer is behind a NAT, `relay` is the member that relays for it.
+    pub async fn add_peer(
+        &mut self,
+        name: MemberName,
+        address: PeerAddress,
+        relay: Option<MemberName>,
+    ) -> Result<Peer> {
+        address.validate().map_err(|e| eyre!(e))?;
+        let public_key = self
+            .last_reserved_state
+            .query_public_key(&name)
+            .ok_or_else(|| eyre!("{name} is not a member"))?;
+        let relay = match relay {
+            Some(relay) if relay == name => return Err(eyre!("{name} can't relay for itself")),
+            Some(relay) => Some(
+                self.last_reserved_state
+                    .query_public_key(&relay)
+                    .ok_or_else(|| eyre!("{relay} is not a member"))?,
+            ),
+            None => None,
+        };
+        if self.config.public_key.as_ref() == Some(&public_key) {
+            return Err(eyre!("{name} is this node"));
+        }
+        if let Some(peer) = self
+            .client_network_config
+            .peers
+            .iter()
+            .find(|peer| peer.public_key == public_key || peer.name == name)
+        {
+            return Err(eyre!("{} is already a peer", peer.name));
+        }
+        let peer = Peer {
+            public_key,
+            name,
+            address,
+            ports: self.server_network_config.ports.clone(),
+            message: String::new(),
+            recently_seen_timestamp: 0,
+            relay,
+        };
+        let mut peers = peers::read(&self.path).await?;
+        peers.push(peer.clone());
+        peers::write(&self.path, &peers).await?;
+        self.client_network_config.peers.push(peer.clone());
+        Ok(peer)
+    }
+
+    /// Removes the peer with the given name from `peers.json`.
+    ///
+    /// The peers in the config can't be removed here.
+    pub async fn remove_peer(&mut self, name: &MemberName) -> Result<()> {
+        let mut peers = peers::read(&self.path).await?;
+        if !peers.iter().any(|peer| &peer.name == name) {
+            if self.config.peers.iter().any(|peer| &peer.name == name) {
+                return Err(eyre!("{name} is configured in config.json"));
+            }
+            return Err(eyre!("{name} is not a peer"));
+        }
+        peers.retain(|peer| &peer.name != name);
+        peers::write(&self.path, &peers).await?;
+        self.client_network_config
+            .peers
+            .retain(|peer| &peer.name != name || self.config.peers.contains(peer));
+        Ok(())
+    }
+
+    /// Lists the peers with the liveness observed from their heartbeats.
+    pub async fn list_peers(&self, page: &PageRequest) -> Result<Page<PeerStatus>> {
+        let peers = &self.client_network_config.peers;
+        let liveness = heartbeat::read_liveness(
+            &*self.heartbeat.read().await,
+            &peers
+                .iter()
+                .map(|peer| peer.public_key.clone())
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+        Page::paginate(
+            peers
+                .iter()
+                .zip(liveness)
+                .map(|(peer, liveness)| PeerStatus {
+                    peer: peer.clone(),
+                    managed: !self.config.peers.contains(peer),
+                    last_seen: liveness.last_seen,
+                })
+                .collect(),
+            page,
+        )
+    }
+
+    /// Performs a handshake with the peer of the given name on the governance DMS,
+    /// checking its latency and whether it holds the key of the member.
+    pub async fn ping_peer(&self, name: &MemberName) -> Result<HandshakeReport> {
+        let peer = self
+            .client_network_config
+            .peers
+            .iter()
+            .find(|peer| &peer.name == name)
+            .ok_or_else(|| eyre!("{name} is not a peer"))?;
+        let dms_key = self.governance.get_dms().read().await.get_config().dms_key;
+        simperby_network::dms::handshake(
+            peer,
+            &dms_key,
+            Some(&chain_id(&self.last_reserved_state)),
+            self.config.transport,
+        )
+        .await
+    }
+
+    /// Generates a report transaction on the validators that have been offline
+    /// for longer than `threshold_ms`, which can be proposed to the governance.
+    ///
+    /// Returns `None` if every validator is online.
+    pub async fn generate_offline_report(
+        &self,
+        threshold_ms: Timestamp,
+    ) -> Result<Option<Transaction>> {
+        let now = get_timestamp();
+        let validators = self
+            .last_reserved_state
+            .members
+            .iter()
+            .filter(|member| member.consensus_voting_power > 0)
+            .map(|member| member.name.clone())
+            .collect::<Vec<_>>();
+        let offline = self
+            .get_network_status()
+            .await?
+            .liveness
+            .into_iter()
+            .filter(|(name, liveness)| {
+                validators.contains(name) && !liveness.is_online(now, threshold_ms)
+            })
+            .collect::<Vec<_>>();
+        if offline.is_empty() {
+            return Ok(None);
+        }
+        let body = offline
+            .iter()
+            .map(|(name, liveness)| match liveness.last_seen {
+                Some(last_seen) => format!("{name}: last seen at {last_seen}"),
+                None => format!("{name}: never seen"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(Some(Transaction {
+            author: self
+                .last_reserved_state
+                .query_name(&self.config.signing_key()?.public_key())
+                .ok_or_else(|| eyre!("this node is not a member"))?,
+            timestamp: now,
+            head: format!("report: {} offline validator(s)", offline.len()),
+            body,
+            diff: Diff::None,
+        }))
+    }
+
+    pub async fn fetch(&mut self) -> Result<()> {
+        self.check_not_shutting_down("fetch")?;
+        let result = self.fetch_and_update().await;
+        self.health.record_fetch(&result);
+        self.health.record_storage_failure(&result);
+        result
+    }
+
+    /// Applies what the local repository and the DMSs already have
+    /// (e.g., the commits fetched manually with git), without any network operation.
+    pub async fn update(&mut self) -> Result<()> {
+        self.check_not_shutting_down("update")?;
+        let result = self.apply_updates().await;
+        self.health.record_storage_failure(&result);
+        result
+    }
+
+    async fn fetch_and_update(&mut self) -> Result<()> {
+        // TODO: perform the actual network operations
+        let report = self.repository.fetch().await?;
+        for evidence in report.forks {
+            self.record_fork(evidence).await?;
+        }
+        Dms::fetch(Arc::clone(&self.heartbeat), &self.client_network_config).await?;
+        Dms::fetch(self.blob_store()?.get_dms(), &self.client_network_config).await?;
+        Dms::fetch(
+            self.transaction_pool()?.get_dms(),
+            &self.client_network_config,
+        )
+        .await?;
+        self.apply_updates().await
+    }
+
+    async fn apply_updates(&mut self) -> Result<()> {
+        // The vetoed agendas are not approved by this node.
+        self.repository
+            .set_agenda_vetoes(self.governance.read().await?.all_vetoes());
+        // The branches are still fetched while halted, but never finalized.
+        if self.fork_evidence.is_empty() {
+            for (branch, result) in self.repository.sync_all().await? {
+                if let Err(e) = result {
+                    log::debug!("branch {branch} is not accepted: {e}");
+                }
+            }
+        }
+
+        // Update governance
+        self.reveal_votes().await?;
+        let governance_set = self
+            .last_reserved_state
+            .get_governance_set()
+            .unwrap()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let governance_state = self.governance.read().await?;
+        let now = get_timestamp();
+        let mut votes = Vec::new();
+        for (agenda_commit, agenda) in self.repository.read_agendas().await? {
+            let mode = self.voting_mode(agenda_commit).await?;
+            // The votes arriving after the deadline never approve the agenda.
+            let approvals = governance_state.approvals_by_deadline(
+                &self.read_agenda(agenda_commit).await?,
+                mode,
+                now,
+            );
+            if let Ok(voted_power) = self
+                .last_reserved_state
+                .get_governance_voting_power(&approvals.keys().cloned().collect::<Vec<_>>())
+            {
+                votes.push((agenda, voted_power, approvals));
+            }
+        }
+        let total_voting_power = governance_set.values().sum::<VotingPower>();
+        for (agenda, voted_power, approvals) in votes {
+            let vetoers = governance_state.vetoers(agenda, &self.last_reserved_state, now);
+            if !vetoers.is_empty() {
+                log::info!("agenda {agenda} is vetoed by {}", vetoers.join(", "));
+                continue;
+            }
+            if voted_power * 2 > total_voting_power {
+                // TODO: handle this error
+                if let Ok(commit_hash) = self
+                    .repository
+                    .approve(
+                        &agenda,
+                        approvals
+                            .iter()
+                            .map(|(k, s)| TypedSignature::new(s.clone(), k.clone()))
+                            .collect(),
+                        now,
+                    )
+                    .await
+                {
+                    self.events.publish(NodeEvent::AgendaApproved {
+                        commit_hash,
+                        agenda_hash: agenda,
+                    });
+                }
+            }
+        }
+
+        // Update consensus
+        for (_, block_hash) in self.repository.read_blocks().await? {
+            self.consensus
+                .register_verified_block_hash(block_hash)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the evidence of the forks observed so far, for the manual governance action.
+    ///
+    /// The finalization is halted as long as there is any; see [`fork`] to resume.
+    pub fn get_fork_evidence(&self) -> &[ForkEvidence] {
+        &self.fork_evidence
+    }
+
+    /// Broadcasts all the local messages and reports the result.
+    pub async fn broadcast(&mut self) -> Result<Vec<String>> {
+        self.check_not_shutting_down("broadcast")?;
+        // TODO: broadcast the governance and consensus messages too
+        Dms::broadcast(Arc::clone(&self.heartbeat), &self.client_network_config).await?;
+        Dms::broadcast(self.blob_store()?.get_dms(), &self.client_network_config).await?;
+        Dms::broadcast(
+            self.transaction_pool()?.get_dms(),
+            &self.client_network_config,
+        )
+        .await?;
+        Ok(vec![])
+    }
+
+    pub async fn check_push(
+        &mut self,
+        _commit_hash: CommitHash,
         _branch_name: String,
         _timestamp: Timestamp,
         _signature: Signature,
@@ -378,3 +1995,469 @@ impl SimperbyNode {
         todo!()
     }
 }
+
+// Various private methods.
+impl SimperbyNode {
+    fn check_not_observer(&self, operation: &str) -> Result<()> {
+        if self.config.observer {
+            return Err(ObserverModeError {
+                operation: operation.to_owned(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn check_not_shutting_down(&self, operation: &str) -> Result<()> {
+        if self.shutdown.is_requested() {
+            return Err(ShuttingDownError {
+                operation: operation.to_owned(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Renews the leadership lease in the standby mode, demoting this node if it fails.
+    async fn check_lease(&mut self, operation: &str) -> Result<()> {
+        let (Some(lease_store), Some(standby)) = (&self.lease_store, &self.config.standby) else {
+            return Ok(());
+        };
+        let error = |reason: String| StandbyModeError {
+            operation: operation.to_owned(),
+            reason,
+        };
+        let Some(lease) = &self.lease else {
+            return Err(error("not promoted".to_owned()).into());
+        };
+        match lease_store
+            .renew(lease, standby.lease_ttl_ms, get_timestamp())
+            .await
+        {
+            Ok(lease) => {
+                self.lease = Some(lease);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("demoted on the failure to renew the leadership lease: {e}");
+                self.lease = None;
+                Err(error(e.to_string()).into())
+            }
+        }
+    }
+
+    fn check_not_halted(&self, operation: &str) -> Result<()> {
+        if let Some(evidence) = self.fork_evidence.first() {
+            return Err(ForkHaltError {
+                operation: operation.to_owned(),
+                height: evidence.height(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Keeps the evidence of a fork, halting the finalization, and alerts the operator.
+    async fn record_fork(&mut self, evidence: ForkEvidence) -> Result<()> {
+        if self.fork_evidence.contains(&evidence) {
+            return Ok(());
+        }
+        log::error!(
+            "FORK DETECTED: two different blocks are finalized at height {} ({} and {}); \
+            the finalization is halted",
+            evidence.height(),
+            evidence.first.to_hash256(),
+            evidence.second.to_hash256()
+        );
+        self.fork_evidence.push(evidence.clone());
+        fork::write(&self.path, &self.fork_evidence).await?;
+        self.events.publish(NodeEvent::ForkDetected {
+            evidence: Box::new(evidence),
+        });
+        Ok(())
+    }
+
+    fn blob_store(&self) -> Result<BlobStore> {
+        self.repository
+            .get_blob_store()
+            .ok_or_else(|| eyre!("the blob store is not initialized"))
+    }
+
+    fn transaction_pool(&self) -> Result<TransactionPool> {
+        self.repository
+            .get_transaction_pool()
+            .ok_or_else(|| eyre!("the transaction pool is not initialized"))
+    }
+
+    /// Handles the commits finalized since the last call;
+    /// delivers them to the execution hooks and publishes the finalization events.
+    ///
+    /// If a hook halts, the progress is kept up to the last fully executed block
+    /// so that the failed block will be delivered again on the next call,
+    /// only to the hooks that haven't executed it yet.
+    async fn process_finalized_commits(&mut self) -> Result<()> {
+        let lfi = self.repository.read_last_finalization_info().await?;
+        if lfi.commit_hash == self.last_executed_commit_hash {
+            return Ok(());
+        }
+        lfi.reserved_state
+            .check_not_rolled_back(&self.last_reserved_state)
+            .map_err(|e| eyre!("refused to load the reserved state: {e}"))?;
+        let commits = {
+            let raw = self.repository.get_raw();
+            let raw = raw.read().await;
+            simperby_repository::interpret::read_commits(
+                &raw,
+                self.last_executed_commit_hash,
+                lfi.commit_hash,
+            )
+            .await?
+        };
+        let mut block = Vec::new();
+        for (commit, commit_hash) in commits {
+            block.push(commit);
+            if let Some(Commit::Block(header)) = block.last() {
+                let header = header.clone();
+                self.execution_hooks.execute_block(&block).await?;
+                block.clear();
+                self.last_executed_commit_hash = commit_hash;
+                self.events.publish(NodeEvent::BlockFinalized {
+                    commit_hash,
+                    header,
+                });
+            }
+        }
+        if lfi.reserved_state.members != self.last_reserved_state.members {
+            self.events.publish(NodeEvent::MemberChanged {
+                height: lfi.header.height,
+                members: lfi.reserved_state.members.clone(),
+            });
+        }
+        // Adds the proof to the store, which is the canonical copy from now on.
+        self.repository
+            .get_finalization_proof(lfi.header.height)
+            .await?;
+        // Cuts the old history on a pruned node; the block is finalized regardless.
+        if let Err(e) = self.repository.prune_history().await {
+            log::warn!("failed to prune the history: {e}");
+        }
+        self.health
+            .record_progress(lfi.header.height, get_timestamp());
+        // Lets the mirrors of this repository prove what they serve.
+        self.repository.refresh_attestation().await?;
+        self.last_reserved_state = lfi.reserved_state;
+        self.prune_evidence_pool(lfi.header.height);
+        self.draft_offline_reports().await
+    }
+
+    /// Drafts the reports of the validators that violate the offline report policy,
+    /// notifying the operator of the new ones.
+    ///
+    /// The drafts are refreshed on every block, dropping those of the validators back online.
+    async fn draft_offline_reports(&mut self) -> Result<()> {
+        let policy = match &self.config.offline_report_policy {
+            Some(policy) => policy.clone(),
+            None => return Ok(()),
+        };
+        let stats = self.get_chain_stats(policy.window).await?;
+        let reports = policy.check(&stats, &self.last_reserved_state);
+        for report in &reports {
+            if self
+                .offline_reports
+                .iter()
+                .all(|draft| draft.offender != report.offender)
+            {
+                log::warn!(
+                    "{} missed {} of the last {} precommits; a report has been drafted",
+                    report.offender,
+                    report.missed_precommits,
+                    report.eligible_blocks
+                );
+                self.events.publish(NodeEvent::OfflineReportDrafted {
+                    report: report.clone(),
+                });
+            }
+        }
+        self.offline_reports = reports;
+        Ok(())
+    }
+
+    /// Opens the governance and the consensus of the height after the given block,
+    /// whose epoch is given.
+    ///
+    /// If `clear`, their storage is emptied first, discarding the messages of another height.
+    async fn open_height_modules(
+        config: &Config,
+        storage_layout: &StorageLayout,
+        last_finalized_header: &BlockHeader,
+        epoch: &Epoch,
+        tap: Option<MessageTap>,
+        clear: bool,
+    ) -> Result<(Governance, Consensus)> {
+        // Each of them accepts the messages only of those who can vote at this height.
+        let reserved_state = &epoch.reserved_state;
+        let governance_members = simperby_governance::dms_members(reserved_state)?;
+        let consensus_members = epoch
+            .validator_set
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect::<Vec<_>>();
+        let mut governance_storage = storage_layout.governance_dms().open().await?;
+        let mut consensus_storage = storage_layout.consensus_dms().open().await?;
+        let mut consensus_state_storage = storage_layout.consensus_state().open().await?;
+        if clear {
+            governance_storage.remove_all_files().await?;
+            consensus_storage.remove_all_files().await?;
+            consensus_state_storage.remove_all_files().await?;
+        }
+
+        let mut dms = open_dms(
+            governance_storage,
+            DmsConfig {
+                dms_key: simperby_governance::generate_dms_key(last_finalized_header),
+                members: governance_members,
+                priority_weights: Default::default(),
+            },
+            config,
+        )
+        .await?;
+        dms.set_tap(tap.clone());
+        dms.set_chain_id(Some(chain_id(reserved_state)));
+        dms.set_banned_members(reserved_state.banned_public_keys());
+        // An observer doesn't vote; it only watches the votes of the members.
+        let node_key = if config.observer {
+            None
+        } else {
+            Some(config.signing_key()?.clone())
+        };
+        let governance = Governance::new(Arc::new(RwLock::new(dms))).await?;
+
+        let mut dms = open_dms(
+            consensus_storage,
+            DmsConfig {
+                dms_key: simperby_consensus::generate_dms_key(last_finalized_header),
+                members: consensus_members,
+                priority_weights: Default::default(),
+            },
+            config,
+        )
+        .await?;
+        dms.set_tap(tap);
+        dms.set_chain_id(Some(chain_id(reserved_state)));
+        dms.set_banned_members(reserved_state.banned_public_keys());
+        let mut consensus = Consensus::new(
+            Arc::new(RwLock::new(dms)),
+            consensus_state_storage,
+            last_finalized_header.clone(),
+            // TODO: replace the timestamp with a proper value
+            config.consensus_params.clone(),
+            0,
+            node_key,
+        )
+        .await?;
+        // Unlike the state, the round history is kept across the restarts.
+        consensus
+            .set_history_storage(
+                storage_layout.consensus_history().open().await?,
+                config
+                    .round_history_heights
+                    .unwrap_or(simperby_consensus::DEFAULT_ROUND_HISTORY_HEIGHTS),
+            )
+            .await?;
+        // So is the sign watermark, which must outlive any loss of the state.
+        consensus.set_watermark_storage(storage_layout.sign_watermark().open().await?);
+        consensus.set_banned_validators(reserved_state.banned_public_keys());
+        Ok((governance, consensus))
+    }
+
+    /// Finalizes the block of the latest consensus snapshot if the repository has missed it,
+    /// e.g. by a crash right after the consensus finalized it.
+    ///
+    /// Returns whether it has finalized the block.
+    async fn restore_from_snapshot(
+        repository: &mut DistributedRepository,
+        storage_layout: &StorageLayout,
+    ) -> Result<bool> {
+        let history_storage = storage_layout.consensus_history().open().await?;
+        let Some(snapshot) = simperby_consensus::read_latest_snapshot(&history_storage).await?
+        else {
+            return Ok(false);
+        };
+        let last_height = repository.get_last_finalized_block_header().await?.height;
+        if snapshot.height != last_height + 1 {
+            return Ok(false);
+        }
+        let Some((commit_hash, _)) = repository
+            .read_blocks()
+            .await?
+            .into_iter()
+            .find(|(_, block_hash)| *block_hash == snapshot.block_hash)
+        else {
+            log::warn!(
+                "the block {} of the consensus snapshot is not in the repository",
+                snapshot.block_hash
+            );
+            return Ok(false);
+        };
+        repository.finalize(commit_hash, snapshot.proof).await?;
+        log::warn!(
+            "the block at height {} is finalized from the consensus snapshot",
+            snapshot.height
+        );
+        Ok(true)
+    }
+
+    /// Publishes the health status, for the [`watchdog`].
+    pub(crate) fn publish_health(&self, status: HealthStatus) {
+        self.events.publish(NodeEvent::HealthDegraded { status });
+    }
+
+    /// Re-opens the governance and the consensus, for the recovery by the [`watchdog`].
+    ///
+    /// If `rekey`, they're re-created for the last finalized block of the repository,
+    /// discarding the messages of the outdated height if it has changed;
+    /// the DMS servers have to be restarted to serve the new ones.
+    /// Otherwise, they're flushed and re-opened from the storage as they are.
+    pub(crate) async fn reopen_height_modules(&mut self, rekey: bool) -> Result<()> {
+        self.consensus.flush().await?;
+        self.governance.flush().await?;
+        let old_keys = [
+            self.governance.get_dms().read().await.get_config().dms_key,
+            self.consensus.get_dms().read().await.get_config().dms_key,
+        ];
+        if rekey {
+            let reserved_state = self
+                .repository
+                .read_last_finalization_info()
+                .await?
+                .reserved_state;
+            reserved_state
+                .check_not_rolled_back(&self.last_reserved_state)
+                .map_err(|e| eyre!("refused to load the reserved state: {e}"))?;
+            // A scheduled fork may have been activated.
+            if reserved_state.version != self.last_reserved_state.version {
+                release::check(&self.config.version_check, &reserved_state)?;
+            }
+            self.last_finalized_header = self.repository.get_last_finalized_block_header().await?;
+            self.last_reserved_state = reserved_state;
+            self.update_banned_members().await?;
+        }
+        let new_keys = [
+            simperby_governance::generate_dms_key(&self.last_finalized_header),
+            simperby_consensus::generate_dms_key(&self.last_finalized_header),
+        ];
+        let rekeyed = old_keys != new_keys;
+        let height = self.last_finalized_header.height + 1;
+        let epoch = self
+            .repository
+            .get_epoch(height)
+            .await?
+            .ok_or_else(|| eyre!("no epoch of height {height}"))?;
+        let (governance, consensus) = Self::open_height_modules(
+            &self.config,
+            &StorageLayout::new(&self.path),
+            &self.last_finalized_header,
+            &epoch,
+            self.tap.clone(),
+            rekeyed,
+        )
+        .await?;
+        self.governance = governance;
+        self.consensus = consensus;
+        if rekeyed {
+            // The ports stay the same, under the new keys.
+            for (old_key, new_key) in old_keys.iter().zip(&new_keys) {
+                let ports = std::iter::once(&mut self.server_network_config.ports).chain(
+                    self.client_network_config
+                        .peers
+                        .iter_mut()
+                        .map(|peer| &mut peer.ports),
+                );
+                for ports in ports {
+                    if let Some(port) = ports.remove(&format!("dms-{old_key}")) {
+                        ports.insert(format!("dms-{new_key}"), port);
+                    }
+                }
+            }
+            log::warn!(
+                "the governance and the consensus are re-keyed to height {}",
+                self.last_finalized_header.height + 1
+            );
+        }
+        Ok(())
+    }
+
+    /// Applies the bans of the last reserved state to the DMSs that outlive the heights.
+    ///
+    /// Those of the height are opened with them (see [`Self::open_height_modules`]).
+    async fn update_banned_members(&self) -> Result<()> {
+        let banned = self.last_reserved_state.banned_public_keys();
+        self.heartbeat
+            .write()
+            .await
+            .set_banned_members(banned.clone());
+        self.blob_store()?
+            .get_dms()
+            .write()
+            .await
+            .set_banned_members(banned.clone());
+        self.transaction_pool()?
+            .get_dms()
+            .write()
+            .await
+            .set_banned_members(banned);
+        Ok(())
+    }
+
+    /// Removes the evidence that can't be reported anymore:
+    /// whose offender is already slashed, or which is expired.
+    fn prune_evidence_pool(&mut self, last_height: BlockHeight) {
+        let members = &self.last_reserved_state.members;
+        self.evidence_pool.retain(|evidence| {
+            let slashed = members.iter().any(|member| {
+                &member.public_key == evidence.offender()
+                    && member.consensus_voting_power == 0
+                    && member.governance_voting_power == 0
+            });
+            !slashed
+                && (last_height + 1).saturating_sub(evidence.height()) <= verify::EVIDENCE_MAX_AGE
+        });
+    }
+}
+
+/// Opens a DMS of the node, which is read-only for an observer.
+async fn open_dms<M: DmsMessage>(
+    storage: StorageImpl,
+    dms_config: DmsConfig,
+    config: &Config,
+) -> Result<Dms<M>> {
+    if config.observer {
+        Dms::new_read_only(storage, dms_config).await
+    } else {
+        Dms::new(storage, dms_config, config.signing_key()?.clone()).await
+    }
+}
+
+/// The chain that the peers must be on, checked in the handshakes.
+fn chain_id(reserved_state: &ReservedState) -> ChainId {
+    ChainId {
+        chain_name: reserved_state.genesis_info.chain_name.clone(),
+        genesis_hash: reserved_state.genesis_info.header.to_hash256(),
+    }
+}
+
+fn sync_statistics<S: Storage, M: DmsMessage>(
+    dms: &DistributedMessageSet<S, M>,
+) -> (DmsKey, SyncStatistics) {
+    (dms.get_config().dms_key, dms.get_sync_statistics())
+}
+
+fn chain_mismatches<S: Storage, M: DmsMessage>(
+    dms: &DistributedMessageSet<S, M>,
+) -> Vec<(DmsKey, ChainMismatch)> {
+    let dms_key = dms.get_config().dms_key;
+    dms.get_chain_mismatches()
+        .into_iter()
+        .map(|mismatch| (dms_key.clone(), mismatch))
+        .collect()
+}
@@ -0,0 +1,158 @@
+//! Pagination of the queries that may return large result sets.
+//!
+//! Each query returns its items in a fixed order, and a [`Cursor`] marks a position in it.
+//! The cursors are opaque to the clients, which only pass back the one they received.
+//! As a cursor is a position, the items added or removed between the pages
+//! may shift the following pages; the histories appended at the end are not affected.
+use super::*;
+use eyre::eyre;
+
+/// The number of items in a page if the limit is not given, in the RPC.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// The maximum number of items in a page, in the RPC.
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// The position where the next page starts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Cursor(String);
+
+impl Cursor {
+    fn from_offset(offset: usize) -> Self {
+        Self(format!("o{offset}"))
+    }
+
+    fn offset(&self) -> Result<usize> {
+        self.0
+            .strip_prefix('o')
+            .and_then(|offset| offset.parse().ok())
+            .ok_or_else(|| eyre!("invalid cursor: {}", self.0))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Cursor {
+    fn from(cursor: String) -> Self {
+        Self(cursor)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    /// The maximum number of items to return. If `None`, all the remaining items are returned.
+    pub limit: Option<usize>,
+    /// Where to start. If `None`, it starts from the first item.
+    pub cursor: Option<Cursor>,
+}
+
+impl PageRequest {
+    /// Requests all the items at once, which is fine only for the local callers.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn first(limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            cursor: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The cursor of the next page, or `None` if this is the last one.
+    pub next_cursor: Option<Cursor>,
+    /// The number of all the items, which may change by the time the next page is requested.
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    /// Takes the requested page out of all the items in the order of the query.
+    pub fn paginate(items: Vec<T>, request: &PageRequest) -> Result<Self> {
+        let total = items.len();
+        let offset = match &request.cursor {
+            Some(cursor) => cursor.offset()?,
+            None => 0,
+        };
+        if offset > total {
+            return Err(eyre!("the cursor is out of range: {offset} > {total}"));
+        }
+        let end = request
+            .limit
+            .map_or(total, |limit| offset.saturating_add(limit).min(total));
+        Ok(Self {
+            items: items.into_iter().skip(offset).take(end - offset).collect(),
+            next_cursor: (end < total).then(|| Cursor::from_offset(end)),
+            total: total as u64,
+        })
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paginate_by_cursor() {
+        let items = (0..5).collect::<Vec<_>>();
+        let first = Page::paginate(items.clone(), &PageRequest::first(2)).unwrap();
+        assert_eq!(first.items, vec![0, 1]);
+        assert_eq!(first.total, 5);
+        let second = Page::paginate(
+            items.clone(),
+            &PageRequest {
+                limit: Some(2),
+                cursor: first.next_cursor,
+            },
+        )
+        .unwrap();
+        assert_eq!(second.items, vec![2, 3]);
+        let last = Page::paginate(
+            items,
+            &PageRequest {
+                limit: Some(2),
+                cursor: second.next_cursor,
+            },
+        )
+        .unwrap();
+        assert_eq!(last.items, vec![4]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn paginate_all() {
+        let page = Page::paginate(vec![1, 2, 3], &PageRequest::all()).unwrap();
+        assert_eq!(page.items, vec![1, 2, 3]);
+        assert_eq!(page.next_cursor, None);
+        let empty = Page::paginate(Vec::<u32>::new(), &PageRequest::first(10)).unwrap();
+        assert!(empty.items.is_empty());
+        assert_eq!(empty.next_cursor, None);
+    }
+
+    #[test]
+    fn reject_bad_cursors() {
+        let out_of_range = PageRequest {
+            limit: None,
+            cursor: Some(Cursor::from_offset(4)),
+        };
+        assert!(Page::paginate(vec![1, 2, 3], &out_of_range).is_err());
+        let invalid = PageRequest {
+            limit: None,
+            cursor: Some(Cursor::from("x1".to_owned())),
+        };
+        assert!(Page::paginate(vec![1, 2, 3], &invalid).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! The peer list managed by the operator, kept in `peers.json` of the node directory.
+//!
+//! The node uses these peers along with those in [`Config::peers`].
+use super::*;
+
+pub const PEERS_FILE_NAME: &str = "peers.json";
+
+/// A peer with its liveness, as shown by `peer list`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PeerStatus {
+    pub peer: Peer,
+    /// Whether the peer is listed in `peers.json`, rather than in the config.
+    pub managed: bool,
+    /// The timestamp of the latest heartbeat of the peer. `None` if it has never been seen.
+    pub last_seen: Option<Timestamp>,
+}
+
+/// Reads the peers from `peers.json`, which may not exist.
+pub async fn read(path: &str) -> Result<Vec<Peer>> {
+    let file_path = format!("{path}/{PEERS_FILE_NAME}");
+    if !std::path::Path::new(&file_path).exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_spb::from_str(
+        &tokio::fs::read_to_string(file_path).await?,
+    )?)
+}
+
+/// Returns the configured peers followed by those in `peers.json`, with the same key only once.
+pub async fn read_all(path: &str, configured: &[Peer]) -> Result<Vec<Peer>> {
+    let mut peers = configured.to_vec();
+    for peer in read(path).await? {
+        if peers.iter().all(|p| p.public_key != peer.public_key) {
+            peers.push(peer);
+        }
+    }
+    Ok(peers)
+}
+
+/// Overwrites `peers.json` with the given peers.
+pub async fn write(path: &str, peers: &[Peer]) -> Result<()> {
+    tokio::fs::write(
+        format!("{path}/{PEERS_FILE_NAME}"),
+        serde_spb::to_string(&peers)?,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! The release manifest embedded in the binary, and its check against the chain.
+//!
+//! The manifest ([`RELEASE_MANIFEST`], `release.json` at build time) declares the protocol versions
+//! (see [`ReservedState::version`]) that this release can run.
+//! On startup, the node compares it with the version of the chain in effect and those of the
+//! scheduled forks (see [`ScheduledChange`]), warning or refusing by [`VersionCheckConfig::mode`].
+//!
+//! A release is signed by the release key of the project (see `sign release-manifest`),
+//! so that an operator who pins the key ([`VersionCheckConfig::release_key`])
+//! refuses a binary built with a manifest of unknown origin.
+use super::*;
+use eyre::eyre;
+
+/// The manifest of this release, embedded at build time.
+pub const RELEASE_MANIFEST: &str = include_str!("../release.json");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseManifest {
+    pub node_version: String,
+    /// The supported protocol versions as a semver requirement, e.g. `>=0.1.0, <0.2.0`.
+    pub protocol_versions: String,
+}
+
+impl ToHash256 for ReleaseManifest {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+impl ReleaseManifest {
+    /// Returns the manifest of this release.
+    pub fn current() -> Result<SignedReleaseManifest> {
+        Ok(serde_json::from_str(RELEASE_MANIFEST)?)
+    }
+
+    /// Checks whether the release can run the protocol version.
+    pub fn supports(&self, version: &str) -> Result<bool> {
+        let requirement = semver::VersionReq::parse(&self.protocol_versions)
+            .map_err(|e| eyre!("invalid protocol versions of the release: {e}"))?;
+        let version = semver::Version::parse(version)
+            .map_err(|e| eyre!("invalid protocol version {version}: {e}"))?;
+        Ok(requirement.matches(&version))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedReleaseManifest {
+    pub manifest: ReleaseManifest,
+    /// `None` for a development build.
+    pub signature: Option<TypedSignature<ReleaseManifest>>,
+}
+
+impl SignedReleaseManifest {
+    /// Checks that the manifest is signed by the release key.
+    pub fn verify(&self, release_key: &PublicKey) -> Result<()> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| eyre!("the release manifest is not signed"))?;
+        if signature.signer() != release_key {
+            return Err(eyre!(
+                "the release manifest is signed by {}, not the release key {release_key}",
+                signature.signer()
+            ));
+        }
+        signature
+            .verify(&self.manifest)
+            .map_err(|e| eyre!("invalid signature of the release manifest: {e}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VersionCheckMode {
+    /// Logs a warning and runs anyway.
+    #[default]
+    Warn,
+    /// Refuses to start (or to go on past a fork) on an unsupported chain version.
+    Refuse,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionCheckConfig {
+    /// What to do if the chain version in effect is not supported.
+    ///
+    /// An unsupported scheduled fork is only warned about, as it may be upgraded for in time.
+    #[serde(default)]
+    pub mode: VersionCheckMode,
+    /// If set, the release manifest must be signed by the key, regardless of the mode.
+    #[serde(default)]
+    pub release_key: Option<PublicKey>,
+}
+
+/// The error for running on a chain version that the release doesn't support.
+#[derive(thiserror::Error, Debug)]
+#[error("the chain version {chain_version} is not supported by this release ({protocol_versions})")]
+pub struct IncompatibleVersionError {
+    pub chain_version: String,
+    pub protocol_versions: String,
+}
+
+/// A version of the chain, with whether this release supports it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainVersion {
+    pub version: String,
+    /// `None` for the one in effect.
+    pub activation_height: Option<BlockHeight>,
+    pub supported: bool,
+}
+
+/// The release compared with the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compatibility {
+    pub release: SignedReleaseManifest,
+    /// The version in effect first, followed by the scheduled forks in the order of the heights.
+    pub chain_versions: Vec<ChainVersion>,
+}
+
+impl Compatibility {
+    /// Compares the release with the reserved state in effect.
+    pub fn new(release: SignedReleaseManifest, reserved_state: &ReservedState) -> Result<Self> {
+        let mut chain_versions = vec![ChainVersion {
+            version: reserved_state.version.clone(),
+            activation_height: None,
+            supported: release.manifest.supports(&reserved_state.version)?,
+        }];
+        for change in &reserved_state.scheduled_changes {
+            if let Some(version) = &change.version {
+                chain_versions.push(ChainVersion {
+                    version: version.clone(),
+                    activation_height: Some(change.activation_height),
+                    supported: release.manifest.supports(version)?,
+                });
+            }
+        }
+        Ok(Self {
+            release,
+            chain_versions,
+        })
+    }
+}
+
+/// Checks this release against the reserved state in effect, by the config.
+pub fn check(config: &VersionCheckConfig, reserved_state: &ReservedState) -> Result<Compatibility> {
+    let release = ReleaseManifest::current()?;
+    if let Some(release_key) = &config.release_key {
+        release.verify(release_key)?;
+    }
+    let compatibility = Compatibility::new(release, reserved_state)?;
+    let protocol_versions = &compatibility.release.manifest.protocol_versions;
+    for chain_version in &compatibility.chain_versions {
+        if chain_version.supported {
+            continue;
+        }
+        match chain_version.activation_height {
+            None if config.mode == VersionCheckMode::Refuse => {
+                return Err(IncompatibleVersionError {
+                    chain_version: chain_version.version.clone(),
+                    protocol_versions: protocol_versions.clone(),
+                }
+                .into())
+            }
+            None => log::warn!(
+                "the chain version {} is not supported by this release ({protocol_versions})",
+                chain_version.version
+            ),
+            Some(height) => log::warn!(
+                "the chain version {} scheduled at height {height} is not supported by this release ({protocol_versions}); upgrade before then",
+                chain_version.version
+            ),
+        }
+    }
+    Ok(compatibility)
+}
+
+/// Compares this release with the chain of the node directory, without checking it by the config.
+pub async fn read_compatibility(path: &str) -> Result<Compatibility> {
+    let raw = RawRepository::open(&format!("{path}/repository/repo")).await?;
+    let reserved_state = interpret::read_last_finalized_reserved_state(&raw).await?;
+    Compatibility::new(ReleaseManifest::current()?, &reserved_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> ReleaseManifest {
+        ReleaseManifest {
+            node_version: "0.1.0".to_owned(),
+            protocol_versions: ">=0.1.0, <0.2.0".to_owned(),
+        }
+    }
+
+    #[test]
+    fn supported_versions() {
+        let manifest = manifest();
+        assert!(manifest.supports("0.1.0").unwrap());
+        assert!(manifest.supports("0.1.7").unwrap());
+        assert!(!manifest.supports("0.2.0").unwrap());
+        assert!(manifest.supports("0.1").is_err());
+    }
+
+    #[test]
+    fn verify_release_key() {
+        let (release_key, private_key) = generate_keypair("release");
+        let (other_key, other_private_key) = generate_keypair("other");
+        let signed = SignedReleaseManifest {
+            manifest: manifest(),
+            signature: Some(TypedSignature::sign(&manifest(), &private_key).unwrap()),
+        };
+        signed.verify(&release_key).unwrap();
+        assert!(signed.verify(&other_key).is_err());
+
+        let unsigned = SignedReleaseManifest {
+            manifest: manifest(),
+            signature: None,
+        };
+        assert!(unsigned.verify(&release_key).is_err());
+
+        let mut tampered = SignedReleaseManifest {
+            manifest: manifest(),
+            signature: Some(TypedSignature::sign(&manifest(), &other_private_key).unwrap()),
+        };
+        tampered.manifest.protocol_versions = ">=0.1.0".to_owned();
+        assert!(tampered.verify(&other_key).is_err());
+    }
+
+    #[test]
+    fn compare_with_scheduled_forks() {
+        let (mut reserved_state, _) = test_utils::generate_standard_genesis(4);
+        reserved_state.version = "0.1.0".to_owned();
+        reserved_state.scheduled_changes = vec![
+            ScheduledChange {
+                activation_height: 10,
+                members: None,
+                consensus_leader_order: None,
+                version: Some("0.2.0".to_owned()),
+            },
+            ScheduledChange {
+                activation_height: 20,
+                members: None,
+                consensus_leader_order: Some(reserved_state.consensus_leader_order.clone()),
+                version: None,
+            },
+        ];
+        let release = SignedReleaseManifest {
+            manifest: manifest(),
+            signature: None,
+        };
+        let compatibility = Compatibility::new(release, &reserved_state).unwrap();
+        assert_eq!(
+            compatibility.chain_versions,
+            vec![
+                ChainVersion {
+                    version: "0.1.0".to_owned(),
+                    activation_height: None,
+                    supported: true,
+                },
+                ChainVersion {
+                    version: "0.2.0".to_owned(),
+                    activation_height: Some(10),
+                    supported: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn refuse_unsupported_version() {
+        let (mut reserved_state, _) = test_utils::generate_standard_genesis(4);
+        reserved_state.version = "9.0.0".to_owned();
+        let refuse = VersionCheckConfig {
+            mode: VersionCheckMode::Refuse,
+            release_key: None,
+        };
+        let error = check(&refuse, &reserved_state).unwrap_err();
+        assert!(error.is::<IncompatibleVersionError>());
+        let compatibility = check(&VersionCheckConfig::default(), &reserved_state).unwrap();
+        assert!(!compatibility.chain_versions[0].supported);
+
+        // The embedded manifest of a development build is not signed.
+        let pinned = VersionCheckConfig {
+            mode: VersionCheckMode::Warn,
+            release_key: Some(generate_keypair("release").0),
+        };
+        assert!(check(&pinned, &reserved_state).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Graceful shutdown of a running node.
+//!
+//! Once a shutdown is requested (by [`wait_for_signal`] or [`SimperbyNode::shutdown`]),
+//! the API calls guarded by [`ShutdownController::begin`] are rejected,
+//! and those in flight are given time to finish before the node flushes its state.
+//! The storage writes themselves are atomic, so an abort never leaves a half-written file.
+use super::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, Notify, RwLock};
+
+#[derive(Debug)]
+struct Inner {
+    requested: watch::Sender<bool>,
+    in_flight: Mutex<usize>,
+    drained: Notify,
+}
+
+/// Tracks the in-flight operations and the shutdown request. Clones share the state.
+#[derive(Debug, Clone)]
+pub struct ShutdownController {
+    inner: Arc<Inner>,
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                requested: watch::channel(false).0,
+                in_flight: Mutex::new(0),
+                drained: Notify::new(),
+            }),
+        }
+    }
+}
+
+/// Marks an operation in flight until dropped.
+#[derive(Debug)]
+pub struct OperationGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.inner.in_flight.lock().unwrap();
+        *in_flight -= 1;
+        if *in_flight == 0 {
+            self.inner.drained.notify_waiters();
+        }
+    }
+}
+
+impl ShutdownController {
+    /// Starts an operation, which fails if a shutdown has been requested.
+    pub fn begin(&self, operation: &str) -> Result<OperationGuard, ShuttingDownError> {
+        let mut in_flight = self.inner.in_flight.lock().unwrap();
+        if self.is_requested() {
+            return Err(ShuttingDownError {
+                operation: operation.to_owned(),
+            });
+        }
+        *in_flight += 1;
+        Ok(OperationGuard {
+            inner: Arc::clone(&self.inner),
+        })
+    }
+
+    /// Requests the shutdown; no more operations can begin from now on.
+    pub fn request(&self) {
+        // Under the lock, so that no operation begins after this.
+        let _in_flight = self.inner.in_flight.lock().unwrap();
+        self.inner.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.inner.requested.borrow()
+    }
+
+    /// Resolves when the shutdown is requested.
+    pub fn requested(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut receiver = self.inner.requested.subscribe();
+        async move {
+            while !*receiver.borrow() {
+                // An error means the controller is gone, which is a shutdown as well.
+                if receiver.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Waits for the in-flight operations to finish, up to the timeout.
+    ///
+    /// Returns whether all of them have finished.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let drained = async {
+            loop {
+                // Created before the check, not to miss the notification in between.
+                let notified = self.inner.drained.notified();
+                if *self.inner.in_flight.lock().unwrap() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(timeout, drained).await.is_ok()
+    }
+}
+
+/// Resolves on `SIGINT` or `SIGTERM` (only on Ctrl-C on non-Unix platforms).
+pub async fn wait_for_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = interrupt.recv() => log::info!("received SIGINT; shutting down"),
+            _ = terminate.recv() => log::info!("received SIGTERM; shutting down"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        log::info!("received Ctrl-C; shutting down");
+    }
+    Ok(())
+}
+
+/// Waits for a shutdown signal, and then shuts the node down gracefully:
+/// stops accepting the API calls, waits up to `drain_timeout` for those in flight,
+/// and flushes the node by [`SimperbyNode::shutdown`].
+///
+/// If the operations don't finish in time, it returns without flushing
+/// so that the process can exit, aborting them; no storage is left half-written by that.
+pub async fn run_until_signal(
+    node: Arc<RwLock<SimperbyNode>>,
+    drain_timeout: Duration,
+) -> Result<()> {
+    wait_for_signal().await?;
+    let controller = node.read().await.get_shutdown_controller();
+    controller.request();
+    // The operations on the node hold its lock, so taking the lock waits for them as well.
+    let drained = async {
+        controller.drain(drain_timeout).await;
+        node.write().await
+    };
+    match tokio::time::timeout(drain_timeout, drained).await {
+        Ok(mut node) => node.shutdown().await,
+        Err(_) => {
+            log::warn!(
+                "the in-flight operations didn't finish in {}ms; aborting them",
+                drain_timeout.as_millis()
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reject_after_request() {
+        let controller = ShutdownController::default();
+        let guard = controller.begin("update").unwrap();
+        assert!(!controller.is_requested());
+        controller.request();
+        assert!(controller.is_requested());
+        assert_eq!(controller.begin("update").unwrap_err().operation, "update");
+        drop(guard);
+        assert!(controller.drain(Duration::from_millis(100)).await);
+    }
+
+    #[tokio::test]
+    async fn drain_in_flight() {
+        let controller = ShutdownController::default();
+        let guard = controller.begin("update").unwrap();
+        controller.request();
+        assert!(!controller.drain(Duration::from_millis(50)).await);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(guard);
+        });
+        assert!(controller.drain(Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn resolve_requested() {
+        let controller = ShutdownController::default();
+        let requested = tokio::spawn(controller.requested());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!requested.is_finished());
+        controller.clone().request();
+        tokio::time::timeout(Duration::from_secs(5), requested)
+            .await
+            .unwrap()
+            .unwrap();
+        // Once requested, it resolves right away.
+        tokio::time::timeout(Duration::from_secs(5), controller.requested())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+//! The cold-standby mode, for running a hot spare of a validator without double-signing.
+//!
+//! Two or more nodes may share a validator key, but only the holder of the
+//! [`LeadershipLease`] of the key signs anything that can conflict: votes, vetoes,
+//! blocks and the consensus messages. The others run as standbys, which sync
+//! and verify the chain as usual but refuse to sign until promoted.
+//!
+//! # Safety model
+//!
+//! - A lease is granted by a [`LeaseStore`], which is the single source of truth
+//!   shared by the nodes (a file on a shared volume, or a remote lock service).
+//! - Each grant carries a fencing token, strictly greater than any token granted before for the key.
+//!   The store renews a lease only if its token is still the latest one,
+//!   so a node that was paused past its lease can't resume signing once another has taken over.
+//! - The active node renews the lease right before each signing operation,
+//!   and demotes itself if the renewal fails.
+//! - A standby can be promoted only if the lease is free or expired.
+//!
+//! The lease expiry is compared by the clocks of the nodes, so the TTL must exceed
+//! their clock skew plus the time that a signing operation may take after the renewal.
+//! Within that bound, at most one node signs with the key at any moment.
+use super::*;
+use eyre::eyre;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandbyConfig {
+    /// The name of this node among those sharing the key, which must be unique.
+    pub instance_name: String,
+    /// The lease file shared by the nodes, for [`FileLeaseStore`].
+    pub lease_file: String,
+    /// How long a lease lasts without a renewal.
+    pub lease_ttl_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadershipLease {
+    /// The [`StandbyConfig::instance_name`] of the holder.
+    pub holder: String,
+    pub public_key: PublicKey,
+    /// Grows with every grant of the lease, never reused.
+    pub fencing_token: u64,
+    pub expires_at: Timestamp,
+}
+
+impl LeadershipLease {
+    pub fn is_expired(&self, now: Timestamp) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// Grants the leadership leases of the validator keys.
+///
+/// Every method must be atomic with respect to the other nodes using the same store.
+#[async_trait::async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Reads the latest lease of the key, which may have expired or been released.
+    async fn read(&self, public_key: &PublicKey) -> Result<Option<LeadershipLease>>;
+
+    /// Grants a new lease to the holder, with a new fencing token.
+    ///
+    /// It fails if another holder has an unexpired lease.
+    async fn acquire(
+        &self,
+        holder: &str,
+        public_key: &PublicKey,
+        ttl_ms: u64,
+        now: Timestamp,
+    ) -> Result<LeadershipLease>;
+
+    /// Extends the lease, failing if its fencing token is no longer the latest or it has expired.
+    async fn renew(
+        &self,
+        lease: &LeadershipLease,
+        ttl_ms: u64,
+        now: Timestamp,
+    ) -> Result<LeadershipLease>;
+
+    /// Gives up the lease, so that a standby can be promoted right away.
+    ///
+    /// It does nothing if the lease is no longer the latest.
+    async fn release(&self, lease: &LeadershipLease) -> Result<()>;
+}
+
+/// A [`LeaseStore`] of a file on a volume shared by the nodes.
+///
+/// The read-modify-write of the file is guarded by a lock file created next to it,
+/// which holds a token of the locker so that no one removes a lock taken by another.
+pub struct FileLeaseStore {
+    path: PathBuf,
+}
+
+/// A lock file older than this is regarded as left by a crashed node.
+const STALE_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Makes a token unique among the lockers of the lease file.
+fn generate_lock_token() -> String {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    format!("{}-{nanos}-{count}", std::process::id())
+}
+
+impl FileLeaseStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        path.into()
+    }
+
+    /// Takes the lock, returning the token written in the lock file.
+    async fn lock(&self) -> Result<String> {
+        let lock_path = self.lock_path();
+        let token = generate_lock_token();
+        loop {
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+                .await
+            {
+                Ok(mut file) => {
+                    use tokio::io::AsyncWriteExt;
+                    file.write_all(token.as_bytes()).await?;
+                    file.sync_all().await?;
+                    return Ok(token);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !self.remove_stale_lock().await? {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Removes the lock file if it's stale, returning whether it has been removed.
+    ///
+    /// The lock file is moved aside before being removed, and put back if it turns out
+    /// to be another one than that found stale, i.e., taken by another node in between.
+    async fn remove_stale_lock(&self) -> Result<bool> {
+        let lock_path = self.lock_path();
+        let stale = std::fs::metadata(&lock_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_LOCK_TIMEOUT);
+        if !stale {
+            return Ok(false);
+        }
+        let Ok(stale_token) = tokio::fs::read_to_string(&lock_path).await else {
+            // Removed by another node in the meantime.
+            return Ok(false);
+        };
+        let mut moved_path = lock_path.clone().into_os_string();
+        moved_path.push(format!(".{}", generate_lock_token()));
+        let moved_path = PathBuf::from(moved_path);
+        if tokio::fs::rename(&lock_path, &moved_path).await.is_err() {
+            return Ok(false);
+        }
+        if tokio::fs::read_to_string(&moved_path).await? == stale_token {
+            log::warn!("removing the stale lease lock {}", lock_path.display());
+            tokio::fs::remove_file(&moved_path).await?;
+            Ok(true)
+        } else {
+            // Never overwrites a lock taken after the move.
+            let _ = tokio::fs::hard_link(&moved_path, &lock_path).await;
+            tokio::fs::remove_file(&moved_path).await?;
+            Ok(false)
+        }
+    }
+
+    /// Checks that the lock is still ours, i.e., it hasn't been removed as stale.
+    async fn check_lock(&self, token: &str) -> Result<()> {
+        match tokio::fs::read_to_string(self.lock_path()).await {
+            Ok(content) if content == token => Ok(()),
+            Ok(_) => Err(eyre!("the lease lock has been taken by another node")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(eyre!("the lease lock has been removed by another node"))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Releases the lock, unless it has been taken by another node.
+    async fn unlock(&self, token: &str) -> Result<()> {
+        if self.check_lock(token).await.is_ok() {
+            tokio::fs::remove_file(self.lock_path()).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_all(&self) -> Result<Vec<LeadershipLease>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(serde_spb::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write_all(&self, leases: &[LeadershipLease]) -> Result<()> {
+        tokio::fs::write(&self.path, serde_spb::to_string(&leases)?).await?;
+        Ok(())
+    }
+
+    /// Updates the lease of the key under the lock.
+    async fn update<T>(
+        &self,
+        public_key: &PublicKey,
+        f: impl FnOnce(Option<&LeadershipLease>) -> Result<(Option<LeadershipLease>, T)>,
+    ) -> Result<T> {
+        let token = self.lock().await?;
+        let result: Result<T> = async {
+            let mut leases = self.read_all().await?;
+            let index = leases.iter().position(|l| &l.public_key == public_key);
+            let (new, result) = f(index.map(|i| &leases[i]))?;
+            if let Some(new) = new {
+                match index {
+                    Some(i) => leases[i] = new,
+                    None => leases.push(new),
+                }
+                self.check_lock(&token).await?;
+                self.write_all(&leases).await?;
+            }
+            Ok(result)
+        }
+        .await;
+        self.unlock(&token).await?;
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl LeaseStore for FileLeaseStore {
+    async fn read(&self, public_key: &PublicKey) -> Result<Option<LeadershipLease>> {
+        Ok(self
+            .read_all()
+            .await?
+            .into_iter()
+            .find(|l| &l.public_key == public_key))
+    }
+
+    async fn acquire(
+        &self,
+        holder: &str,
+        public_key: &PublicKey,
+        ttl_ms: u64,
+        now: Timestamp,
+    ) -> Result<LeadershipLease> {
+        self.update(public_key, |current| {
+            if let Some(current) = current.filter(|l| !l.is_expired(now) && l.holder != holder) {
+                return Err(eyre!(
+                    "the lease is held by {} until {}",
+                    current.holder,
+                    current.expires_at
+                ));
+            }
+            let lease = LeadershipLease {
+                holder: holder.to_owned(),
+                public_key: public_key.clone(),
+                fencing_token: current.map_or(0, |l| l.fencing_token) + 1,
+                expires_at: now + ttl_ms as Timestamp,
+            };
+            Ok((Some(lease.clone()), lease))
+        })
+        .await
+    }
+
+    async fn renew(
+        &self,
+        lease: &LeadershipLease,
+        ttl_ms: u64,
+        now: Timestamp,
+    ) -> Result<LeadershipLease> {
+        self.update(&lease.public_key, |current| {
+            let current = current.ok_or_else(|| eyre!("no lease has been granted"))?;
+            if current.fencing_token != lease.fencing_token {
+                return Err(eyre!(
+                    "the lease has been taken over by {} (fencing token {} > {})",
+                    current.holder,
+                    current.fencing_token,
+                    lease.fencing_token
+                ));
+            }
+            if current.is_expired(now) {
+                return Err(eyre!("the lease has expired at {}", current.expires_at));
+            }
+            let lease = LeadershipLease {
+                expires_at: now + ttl_ms as Timestamp,
+                ..current.clone()
+            };
+            Ok((Some(lease.clone()), lease))
+        })
+        .await
+    }
+
+    async fn release(&self, lease: &LeadershipLease) -> Result<()> {
+        self.update(&lease.public_key, |current| {
+            Ok((
+                current
+                    .filter(|l| l.fencing_token == lease.fencing_token)
+                    .map(|l| LeadershipLease {
+                        // Keeps the token so that it's never granted again.
+                        expires_at: 0,
+                        ..l.clone()
+                    }),
+                (),
+            ))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    const TTL_MS: u64 = 1000;
+
+    fn setup_store() -> FileLeaseStore {
+        FileLeaseStore::new(format!("{}/lease.json", create_temp_dir()))
+    }
+
+    #[tokio::test]
+    async fn acquire_renew_release() {
+        let store = setup_store();
+        let (public_key, _) = generate_keypair("validator");
+        assert_eq!(store.read(&public_key).await.unwrap(), None);
+
+        let lease = store.acquire("a", &public_key, TTL_MS, 0).await.unwrap();
+        assert_eq!(lease.fencing_token, 1);
+        assert_eq!(lease.expires_at, 1000);
+        // Another holder can't acquire an unexpired lease.
+        assert!(store.acquire("b", &public_key, TTL_MS, 500).await.is_err());
+
+        let renewed = store.renew(&lease, TTL_MS, 500).await.unwrap();
+        assert_eq!(renewed.fencing_token, 1);
+        assert_eq!(renewed.expires_at, 1500);
+        assert_eq!(
+            store.read(&public_key).await.unwrap(),
+            Some(renewed.clone())
+        );
+
+        store.release(&renewed).await.unwrap();
+        let lease = store.acquire("b", &public_key, TTL_MS, 600).await.unwrap();
+        assert_eq!(lease.holder, "b");
+        assert_eq!(lease.fencing_token, 2);
+        assert!(!store.lock_path().exists());
+    }
+
+    #[tokio::test]
+    async fn fence_taken_over_lease() {
+        let store = setup_store();
+        let (public_key, _) = generate_keypair("validator");
+        let old = store.acquire("a", &public_key, TTL_MS, 0).await.unwrap();
+        assert!(store.renew(&old, TTL_MS, 1000).await.is_err());
+
+        let new = store.acquire("b", &public_key, TTL_MS, 1000).await.unwrap();
+        assert_eq!(new.fencing_token, 2);
+        // The paused holder can neither renew nor release the new lease.
+        assert!(store.renew(&old, TTL_MS, 1100).await.is_err());
+        store.release(&old).await.unwrap();
+        assert_eq!(store.read(&public_key).await.unwrap(), Some(new));
+    }
+
+    #[tokio::test]
+    async fn remove_stale_lock() {
+        let store = setup_store();
+        let (public_key, _) = generate_keypair("validator");
+        let lock = std::fs::File::create(store.lock_path()).unwrap();
+        lock.set_modified(SystemTime::now() - STALE_LOCK_TIMEOUT * 2)
+            .unwrap();
+        drop(lock);
+        store.acquire("a", &public_key, TTL_MS, 0).await.unwrap();
+        assert!(!store.lock_path().exists());
+    }
+
+    #[tokio::test]
+    async fn wait_for_fresh_lock() {
+        let store = setup_store();
+        let (public_key, _) = generate_keypair("validator");
+        std::fs::write(store.lock_path(), "another node").unwrap();
+        assert!(tokio::time::timeout(
+            Duration::from_millis(100),
+            store.acquire("a", &public_key, TTL_MS, 0)
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            std::fs::read_to_string(store.lock_path()).unwrap(),
+            "another node"
+        );
+    }
+
+    #[tokio::test]
+    async fn keep_lock_of_another() {
+        let store = setup_store();
+        let token = store.lock().await.unwrap();
+        store.check_lock(&token).await.unwrap();
+        // Taken over as if this node had been paused past the stale timeout.
+        std::fs::write(store.lock_path(), "another node").unwrap();
+        assert!(store.check_lock(&token).await.is_err());
+        store.unlock(&token).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(store.lock_path()).unwrap(),
+            "another node"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Rolling metrics of the chain computed from the finalized history, for governance reporting.
+use serde::{Deserialize, Serialize};
+use simperby_consensus::ConsensusParams;
+use simperby_core::*;
+use std::collections::BTreeMap;
+
+/// The number of the most recent blocks to compute the statistics over, by default.
+pub const DEFAULT_STATS_WINDOW: u64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainStats {
+    /// The lowest height of the blocks in the window.
+    pub from_height: BlockHeight,
+    /// The highest height of the blocks in the window (the last finalized one).
+    pub to_height: BlockHeight,
+    /// The validators, in the order of the validator set of the last block.
+    pub validators: Vec<ValidatorStats>,
+    /// The average interval between the blocks in the window, in milliseconds.
+    ///
+    /// `None` if the window is empty.
+    pub average_block_interval_ms: Option<f64>,
+    /// The number of the agendas finalized per block in the window.
+    pub agendas_per_block: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ValidatorStats {
+    pub public_key: PublicKey,
+    /// The number of the blocks in the window that the validator was eligible to precommit.
+    pub eligible_blocks: u64,
+    /// The number of the blocks whose finalization proof includes the validator's precommit.
+    pub precommits: u64,
+    /// The number of the blocks that the validator authored.
+    pub proposed_blocks: u64,
+    /// The number of the blocks that the validator was scheduled to propose,
+    /// according to the rounds in which the blocks were finalized.
+    pub expected_proposals: u64,
+}
+
+impl ValidatorStats {
+    /// The ratio of the precommits to the eligible blocks, between 0 and 1.
+    pub fn participation_rate(&self) -> f64 {
+        if self.eligible_blocks == 0 {
+            return 0.0;
+        }
+        self.precommits as f64 / self.eligible_blocks as f64
+    }
+}
+
+/// When to draft an [`OfflineReport`] of a validator.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OfflineReportPolicy {
+    /// The number of the most recent blocks to count the missed precommits over.
+    pub window: u64,
+    /// The fraction of the missed precommits (between 0 and 1)
+    /// above which the validator is reported.
+    pub max_missed_fraction: f64,
+}
+
+impl OfflineReportPolicy {
+    /// Returns the reports of the current validators who missed too many precommits.
+    ///
+    /// Only those eligible over the whole window are considered,
+    /// not to report the validators that have just joined.
+    pub fn check(&self, stats: &ChainStats, reserved_state: &ReservedState) -> Vec<OfflineReport> {
+        let mut reports = Vec::new();
+        for validator in &stats.validators {
+            let member = match reserved_state
+                .members
+                .iter()
+                .find(|member| member.public_key == validator.public_key)
+            {
+                Some(member) if member.consensus_voting_power > 0 => member,
+                _ => continue,
+            };
+            if validator.eligible_blocks < self.window {
+                continue;
+            }
+            let missed_precommits = validator.eligible_blocks - validator.precommits;
+            if missed_precommits as f64
+                > self.max_missed_fraction * validator.eligible_blocks as f64
+            {
+                reports.push(OfflineReport {
+                    offender: member.name.clone(),
+                    from_height: stats.from_height,
+                    to_height: stats.to_height,
+                    missed_precommits,
+                    eligible_blocks: validator.eligible_blocks,
+                });
+            }
+        }
+        reports
+    }
+}
+
+/// Computes the statistics over the given finalized commits.
+///
+/// `commits` must start with the block commit right before the window
+/// and end with the last finalized block commit, whose proof is `last_proof`.
+/// The block finalized in a round is expected to be authored by the proposer of the round.
+pub fn compute(
+    commits: &[Commit],
+    last_proof: &FinalizationProof,
+    consensus_params: &ConsensusParams,
+) -> ChainStats {
+    let headers = commits
+        .iter()
+        .filter_map(|commit| match commit {
+            Commit::Block(header) => Some(header),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let first = headers.first().expect("there must be at least one block");
+    let last = headers.last().expect("there must be at least one block");
+
+    let mut validators = BTreeMap::new();
+    for (i, pair) in headers.windows(2).enumerate() {
+        let (previous, header) = (pair[0], pair[1]);
+        // The proof of a block is carried by the next one.
+        let proof = match headers.get(i + 2) {
+            Some(next) => &next.prev_block_finalization_proof,
+            None => last_proof,
+        };
+        for (public_key, _) in &previous.validator_set {
+            stats_mut(&mut validators, public_key).eligible_blocks += 1;
+        }
+        for precommit in &proof.signatures {
+            stats_mut(&mut validators, precommit.signer()).precommits += 1;
+        }
+        stats_mut(&mut validators, &header.author).proposed_blocks += 1;
+        let expected = simperby_consensus::get_proposer(previous, consensus_params, proof.round);
+        stats_mut(&mut validators, &expected).expected_proposals += 1;
+    }
+
+    // The current validators first, and then the others who left during the window.
+    let mut ordered = Vec::new();
+    for (public_key, _) in &last.validator_set {
+        if let Some(stats) = validators.remove(public_key) {
+            ordered.push(stats);
+        }
+    }
+    ordered.extend(validators.into_values());
+
+    let blocks = headers.len() as u64 - 1;
+    let agendas = commits
+        .iter()
+        .filter(|commit| matches!(commit, Commit::Agenda(_)))
+        .count();
+    ChainStats {
+        from_height: if blocks == 0 {
+            last.height
+        } else {
+            first.height + 1
+        },
+        to_height: last.height,
+        validators: ordered,
+        average_block_interval_ms: (blocks > 0)
+            .then(|| (last.timestamp - first.timestamp) as f64 / blocks as f64),
+        agendas_per_block: if blocks == 0 {
+            0.0
+        } else {
+            agendas as f64 / blocks as f64
+        },
+    }
+}
+
+fn stats_mut<'a>(
+    validators: &'a mut BTreeMap<PublicKey, ValidatorStats>,
+    public_key: &PublicKey,
+) -> &'a mut ValidatorStats {
+    validators
+        .entry(public_key.clone())
+        .or_insert_with(|| ValidatorStats {
+            public_key: public_key.clone(),
+            eligible_blocks: 0,
+            precommits: 0,
+            proposed_blocks: 0,
+            expected_proposals: 0,
+        })
+}
@@ -0,0 +1,244 @@
+//! The layout of the storages in a node directory.
+//!
+//! Every subsystem gets its own directory, stamped with its namespace when created,
+//! and opens it only through the [`StoragePath`] of the namespace.
+//! Opening a directory of another namespace fails, so the subsystems can never share a storage.
+use super::*;
+use eyre::eyre;
+use simperby_network::storage::StorageImpl;
+
+/// A subsystem that owns a storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StorageNamespace {
+    GovernanceDms,
+    ConsensusDms,
+    ConsensusState,
+    ConsensusHistory,
+    SignWatermark,
+    HeartbeatDms,
+    BlobDms,
+    TransactionPoolDms,
+    FinalizationProofs,
+    HeaderCache,
+    TransactionIndex,
+    Epochs,
+    ExecutionProgress,
+}
+
+impl StorageNamespace {
+    pub const ALL: [StorageNamespace; 13] = [
+        StorageNamespace::GovernanceDms,
+        StorageNamespace::ConsensusDms,
+        StorageNamespace::ConsensusState,
+        StorageNamespace::ConsensusHistory,
+        StorageNamespace::SignWatermark,
+        StorageNamespace::HeartbeatDms,
+        StorageNamespace::BlobDms,
+        StorageNamespace::TransactionPoolDms,
+        StorageNamespace::FinalizationProofs,
+        StorageNamespace::HeaderCache,
+        StorageNamespace::TransactionIndex,
+        StorageNamespace::Epochs,
+        StorageNamespace::ExecutionProgress,
+    ];
+
+    /// The name recorded in the storage directory.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageNamespace::GovernanceDms => "governance-dms",
+            StorageNamespace::ConsensusDms => "consensus-dms",
+            StorageNamespace::ConsensusState => "consensus-state",
+            StorageNamespace::ConsensusHistory => "consensus-history",
+            StorageNamespace::SignWatermark => "sign-watermark",
+            StorageNamespace::HeartbeatDms => "heartbeat-dms",
+            StorageNamespace::BlobDms => "blob-dms",
+            StorageNamespace::TransactionPoolDms => "transaction-pool-dms",
+            StorageNamespace::FinalizationProofs => "finalization-proofs",
+            StorageNamespace::HeaderCache => "header-cache",
+            StorageNamespace::TransactionIndex => "transaction-index",
+            StorageNamespace::Epochs => "epochs",
+            StorageNamespace::ExecutionProgress => "execution-progress",
+        }
+    }
+
+    /// The directory of the storage, relative to the node directory.
+    pub fn relative_path(&self) -> &'static str {
+        match self {
+            StorageNamespace::GovernanceDms => "governance/dms",
+            StorageNamespace::ConsensusDms => "consensus/dms",
+            StorageNamespace::ConsensusState => "consensus/state",
+            StorageNamespace::ConsensusHistory => "consensus/history",
+            StorageNamespace::SignWatermark => "consensus/watermark",
+            StorageNamespace::HeartbeatDms => "heartbeat/dms",
+            StorageNamespace::BlobDms => "blob/dms",
+            StorageNamespace::TransactionPoolDms => "transaction-pool/dms",
+            StorageNamespace::FinalizationProofs => "repository/proofs",
+            StorageNamespace::HeaderCache => "repository/headers",
+            StorageNamespace::TransactionIndex => "repository/transactions",
+            StorageNamespace::Epochs => "repository/epochs",
+            StorageNamespace::ExecutionProgress => "execution/progress",
+        }
+    }
+
+    /// Whether the storage is kept across the restarts; the others are recreated empty.
+    pub fn is_persistent(&self) -> bool {
+        matches!(
+            self,
+            StorageNamespace::ConsensusHistory
+                | StorageNamespace::SignWatermark
+                | StorageNamespace::FinalizationProofs
+                | StorageNamespace::HeaderCache
+                | StorageNamespace::TransactionIndex
+                | StorageNamespace::Epochs
+                | StorageNamespace::ExecutionProgress
+        )
+    }
+}
+
+/// Builds the [`StoragePath`]s of the subsystems under a node directory.
+#[derive(Debug, Clone)]
+pub struct StorageLayout {
+    root: String,
+}
+
+impl StorageLayout {
+    pub fn new(path: &str) -> Self {
+        Self {
+            root: path.to_owned(),
+        }
+    }
+
+    pub fn governance_dms(&self) -> StoragePath {
+        self.path(StorageNamespace::GovernanceDms)
+    }
+
+    pub fn consensus_dms(&self) -> StoragePath {
+        self.path(StorageNamespace::ConsensusDms)
+    }
+
+    pub fn consensus_state(&self) -> StoragePath {
+        self.path(StorageNamespace::ConsensusState)
+    }
+
+    pub fn consensus_history(&self) -> StoragePath {
+        self.path(StorageNamespace::ConsensusHistory)
+    }
+
+    pub fn sign_watermark(&self) -> StoragePath {
+        self.path(StorageNamespace::SignWatermark)
+    }
+
+    pub fn heartbeat_dms(&self) -> StoragePath {
+        self.path(StorageNamespace::HeartbeatDms)
+    }
+
+    pub fn blob_dms(&self) -> StoragePath {
+        self.path(StorageNamespace::BlobDms)
+    }
+
+    pub fn transaction_pool_dms(&self) -> StoragePath {
+        self.path(StorageNamespace::TransactionPoolDms)
+    }
+
+    pub fn finalization_proofs(&self) -> StoragePath {
+        self.path(StorageNamespace::FinalizationProofs)
+    }
+
+    pub fn header_cache(&self) -> StoragePath {
+        self.path(StorageNamespace::HeaderCache)
+    }
+
+    pub fn transaction_index(&self) -> StoragePath {
+        self.path(StorageNamespace::TransactionIndex)
+    }
+
+    pub fn epochs(&self) -> StoragePath {
+        self.path(StorageNamespace::Epochs)
+    }
+
+    pub fn execution_progress(&self) -> StoragePath {
+        self.path(StorageNamespace::ExecutionProgress)
+    }
+
+    fn path(&self, namespace: StorageNamespace) -> StoragePath {
+        StoragePath {
+            directory: format!("{}/{}", self.root, namespace.relative_path()),
+            namespace,
+        }
+    }
+}
+
+/// The storage directory of a subsystem, which can only be made by [`StorageLayout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoragePath {
+    directory: String,
+    namespace: StorageNamespace,
+}
+
+impl StoragePath {
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
+    pub fn namespace(&self) -> StorageNamespace {
+        self.namespace
+    }
+
+    /// Opens the storage, creating it if it doesn't exist or if it isn't persistent.
+    pub async fn open(&self) -> Result<StorageImpl> {
+        let name = self.namespace.name();
+        if !self.namespace.is_persistent() || !std::path::Path::new(&self.directory).exists() {
+            StorageImpl::create_in_namespace(&self.directory, name).await?;
+        }
+        StorageImpl::open_in_namespace(&self.directory, name)
+            .await
+            .map_err(|e| eyre!("failed to open the {name} storage: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_network::primitives::Storage;
+    use simperby_test_suite::*;
+
+    #[test]
+    fn distinct_namespaces() {
+        let mut names = StorageNamespace::ALL.map(|n| n.name()).to_vec();
+        let mut paths = StorageNamespace::ALL.map(|n| n.relative_path()).to_vec();
+        names.sort();
+        names.dedup();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(names.len(), StorageNamespace::ALL.len());
+        assert_eq!(paths.len(), StorageNamespace::ALL.len());
+    }
+
+    #[tokio::test]
+    async fn keep_only_persistent_storages() {
+        let layout = StorageLayout::new(&create_temp_dir());
+        for path in [layout.consensus_history(), layout.consensus_state()] {
+            let mut storage = path.open().await.unwrap();
+            storage
+                .add_or_overwrite_file("file", "content".to_owned())
+                .await
+                .unwrap();
+        }
+        let history = layout.consensus_history().open().await.unwrap();
+        assert_eq!(history.list_files().await.unwrap(), vec!["file".to_owned()]);
+        let state = layout.consensus_state().open().await.unwrap();
+        assert!(state.list_files().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuse_other_namespace() {
+        let root = create_temp_dir();
+        let layout = StorageLayout::new(&root);
+        drop(layout.header_cache().open().await.unwrap());
+        let misplaced = StoragePath {
+            directory: format!("{root}/{}", StorageNamespace::HeaderCache.relative_path()),
+            namespace: StorageNamespace::Epochs,
+        };
+        assert!(misplaced.open().await.is_err());
+    }
+}
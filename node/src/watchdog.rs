@@ -0,0 +1,348 @@
+//! The watchdog, which checks the health of a running node and recovers it as configured.
+//!
+//! The node records what the checks need into its [`HealthMonitor`] as it operates:
+//! the finalized height, the results of `fetch()` and the failures of the storage.
+//! [`run`] checks them periodically and takes the [`RecoveryAction`]s when a check starts failing.
+//!
+//! The monitor is kept apart from the node, which is locked during the operations in progress,
+//! so that a load balancer can query the health at any time.
+use super::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    pub check_interval_ms: u64,
+    /// The node is unhealthy if no block has been finalized for this long.
+    ///
+    /// If `None`, the progress is not checked.
+    #[serde(default)]
+    pub max_stall_ms: Option<u64>,
+    /// The node is unhealthy if this many `fetch()`es have failed in a row.
+    #[serde(default)]
+    pub max_fetch_failures: Option<u64>,
+    /// The node is unhealthy if the storage has failed this many times since the last check.
+    #[serde(default)]
+    pub max_storage_failures: Option<u64>,
+    /// The actions to take, in order, when a check starts failing.
+    #[serde(default = "default_actions")]
+    pub actions: Vec<RecoveryAction>,
+}
+
+fn default_actions() -> Vec<RecoveryAction> {
+    vec![RecoveryAction::LogCritical, RecoveryAction::EmitEvent]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryAction {
+    LogCritical,
+    /// Publishes [`NodeEvent::HealthDegraded`](crate::events::NodeEvent::HealthDegraded).
+    EmitEvent,
+    /// Re-creates the governance and the consensus DMSs for the last finalized block,
+    /// discarding the messages of an outdated height.
+    RekeyDms,
+    /// Flushes the governance and the consensus and re-opens them from the storage,
+    /// resetting their in-memory state.
+    RestartSubsystems,
+}
+
+/// A check that has failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthFailure {
+    /// No block has been finalized after `height` since `since`.
+    Stalled {
+        height: BlockHeight,
+        since: Timestamp,
+    },
+    FetchFailing {
+        consecutive_failures: u64,
+        last_error: String,
+    },
+    StorageFailing {
+        failures: u64,
+    },
+}
+
+impl HealthFailure {
+    fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// The failing checks, which are empty if the node is healthy.
+    pub failures: Vec<HealthFailure>,
+    pub last_finalized_height: BlockHeight,
+    /// When the last finalized height was observed to change, by the node's clock.
+    pub last_progress: Timestamp,
+    pub consecutive_fetch_failures: u64,
+    /// When the checks were made; zero if not yet.
+    pub checked_at: Timestamp,
+}
+
+impl HealthStatus {
+    pub fn is_healthy(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    last_finalized_height: BlockHeight,
+    last_progress: Timestamp,
+    consecutive_fetch_failures: u64,
+    last_fetch_error: Option<String>,
+    /// Since the last check.
+    storage_failures: u64,
+    last_status: HealthStatus,
+}
+
+/// What the node records for the health checks. Clones share the state.
+#[derive(Debug, Clone)]
+pub struct HealthMonitor {
+    state: Arc<Mutex<State>>,
+}
+
+impl HealthMonitor {
+    /// Starts monitoring from the given height, as if it's just been finalized at `now`.
+    pub fn new(height: BlockHeight, now: Timestamp) -> Self {
+        let last_status = HealthStatus {
+            failures: Vec::new(),
+            last_finalized_height: height,
+            last_progress: now,
+            consecutive_fetch_failures: 0,
+            checked_at: 0,
+        };
+        Self {
+            state: Arc::new(Mutex::new(State {
+                last_finalized_height: height,
+                last_progress: now,
+                consecutive_fetch_failures: 0,
+                last_fetch_error: None,
+                storage_failures: 0,
+                last_status,
+            })),
+        }
+    }
+
+    pub fn record_progress(&self, height: BlockHeight, now: Timestamp) {
+        let mut state = self.state.lock().unwrap();
+        if height != state.last_finalized_height {
+            state.last_finalized_height = height;
+            state.last_progress = now;
+        }
+    }
+
+    pub fn record_fetch<T>(&self, result: &Result<T>) {
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Ok(_) => {
+                state.consecutive_fetch_failures = 0;
+                state.last_fetch_error = None;
+            }
+            Err(e) => {
+                state.consecutive_fetch_failures += 1;
+                state.last_fetch_error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Counts the error as a storage failure if it's caused by an I/O error.
+    pub fn record_storage_failure<T>(&self, result: &Result<T>) {
+        if let Err(e) = result {
+            if e.chain().any(|cause| cause.is::<std::io::Error>()) {
+                self.state.lock().unwrap().storage_failures += 1;
+            }
+        }
+    }
+
+    /// Returns the status of the last check.
+    pub fn status(&self) -> HealthStatus {
+        self.state.lock().unwrap().last_status.clone()
+    }
+
+    /// Checks the health at `now`, returning the status
+    /// and the failures that weren't there in the last check.
+    pub fn check(
+        &self,
+        config: &WatchdogConfig,
+        now: Timestamp,
+    ) -> (HealthStatus, Vec<HealthFailure>) {
+        let mut state = self.state.lock().unwrap();
+        let mut failures = Vec::new();
+        if let Some(max_stall_ms) = config.max_stall_ms {
+            if now.saturating_sub(state.last_progress) > max_stall_ms as Timestamp {
+                failures.push(HealthFailure::Stalled {
+                    height: state.last_finalized_height,
+                    since: state.last_progress,
+                });
+            }
+        }
+        if let Some(max_fetch_failures) = config.max_fetch_failures {
+            if state.consecutive_fetch_failures >= max_fetch_failures {
+                failures.push(HealthFailure::FetchFailing {
+                    consecutive_failures: state.consecutive_fetch_failures,
+                    last_error: state.last_fetch_error.clone().unwrap_or_default(),
+                });
+            }
+        }
+        if let Some(max_storage_failures) = config.max_storage_failures {
+            if state.storage_failures >= max_storage_failures {
+                failures.push(HealthFailure::StorageFailing {
+                    failures: state.storage_failures,
+                });
+            }
+        }
+        state.storage_failures = 0;
+        let new_failures = failures
+            .iter()
+            .filter(|failure| {
+                !state
+                    .last_status
+                    .failures
+                    .iter()
+                    .any(|last| last.same_kind(failure))
+            })
+            .cloned()
+            .collect();
+        state.last_status = HealthStatus {
+            failures,
+            last_finalized_height: state.last_finalized_height,
+            last_progress: state.last_progress,
+            consecutive_fetch_failures: state.consecutive_fetch_failures,
+            checked_at: now,
+        };
+        (state.last_status.clone(), new_failures)
+    }
+}
+
+/// Checks the health of the node every [`WatchdogConfig::check_interval_ms`]
+/// and takes the configured actions on the new failures, until the node shuts down.
+///
+/// It does nothing if the node has no [`Config::watchdog`].
+pub async fn run(node: Arc<RwLock<SimperbyNode>>) -> Result<()> {
+    let (config, monitor, shutdown) = {
+        let node = node.read().await;
+        let Some(config) = node.get_config().watchdog.clone() else {
+            return Ok(());
+        };
+        (
+            config,
+            node.get_health_monitor(),
+            node.get_shutdown_controller(),
+        )
+    };
+    let stopped = shutdown.requested();
+    tokio::pin!(stopped);
+    let mut interval = tokio::time::interval(Duration::from_millis(config.check_interval_ms));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut stopped => return Ok(()),
+        }
+        let (status, new_failures) = monitor.check(&config, utils::get_timestamp());
+        if new_failures.is_empty() {
+            continue;
+        }
+        for action in &config.actions {
+            let result = match action {
+                RecoveryAction::LogCritical => {
+                    log::error!("CRITICAL: the node is unhealthy: {new_failures:?}");
+                    Ok(())
+                }
+                RecoveryAction::EmitEvent => {
+                    node.read().await.publish_health(status.clone());
+                    Ok(())
+                }
+                RecoveryAction::RekeyDms => node.write().await.reopen_height_modules(true).await,
+                RecoveryAction::RestartSubsystems => {
+                    node.write().await.reopen_height_modules(false).await
+                }
+            };
+            if let Err(e) = result {
+                log::error!("the recovery action {action:?} failed: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> WatchdogConfig {
+        WatchdogConfig {
+            check_interval_ms: 1000,
+            max_stall_ms: Some(10_000),
+            max_fetch_failures: Some(3),
+            max_storage_failures: Some(1),
+            actions: default_actions(),
+        }
+    }
+
+    #[test]
+    fn detect_stall() {
+        let monitor = HealthMonitor::new(1, 0);
+        let (status, new_failures) = monitor.check(&config(), 5_000);
+        assert!(status.is_healthy());
+        assert!(new_failures.is_empty());
+
+        let (status, new_failures) = monitor.check(&config(), 20_000);
+        assert_eq!(
+            new_failures,
+            vec![HealthFailure::Stalled {
+                height: 1,
+                since: 0
+            }]
+        );
+        assert_eq!(status.failures, new_failures);
+        // Still failing, but not newly.
+        let (status, new_failures) = monitor.check(&config(), 21_000);
+        assert!(!status.is_healthy());
+        assert!(new_failures.is_empty());
+
+        monitor.record_progress(2, 22_000);
+        let (status, _) = monitor.check(&config(), 23_000);
+        assert!(status.is_healthy());
+        assert_eq!(status.last_finalized_height, 2);
+        assert_eq!(monitor.status(), status);
+    }
+
+    #[test]
+    fn detect_fetch_failures() {
+        let monitor = HealthMonitor::new(1, 0);
+        for _ in 0..3 {
+            monitor.record_fetch::<()>(&Err(eyre::eyre!("unreachable")));
+        }
+        let (_, new_failures) = monitor.check(&config(), 1_000);
+        assert_eq!(
+            new_failures,
+            vec![HealthFailure::FetchFailing {
+                consecutive_failures: 3,
+                last_error: "unreachable".to_owned()
+            }]
+        );
+        monitor.record_fetch(&Ok(()));
+        let (status, _) = monitor.check(&config(), 2_000);
+        assert!(status.is_healthy());
+        assert_eq!(status.consecutive_fetch_failures, 0);
+    }
+
+    #[test]
+    fn count_storage_failures_since_last_check() {
+        let monitor = HealthMonitor::new(1, 0);
+        monitor.record_storage_failure::<()>(&Err(eyre::eyre!("not an I/O error")));
+        assert!(monitor.check(&config(), 1_000).0.is_healthy());
+
+        let io_error = std::io::Error::other("disk full");
+        monitor.record_storage_failure::<()>(&Err(io_error.into()));
+        let (_, new_failures) = monitor.check(&config(), 2_000);
+        assert_eq!(
+            new_failures,
+            vec![HealthFailure::StorageFailing { failures: 1 }]
+        );
+        assert!(monitor.check(&config(), 3_000).0.is_healthy());
+    }
+}
@@ -0,0 +1,171 @@
+//! Outbound webhook notifications for the node events.
+use crate::events::{NodeEvent, NodeEventKind};
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use simperby_core::utils::get_timestamp;
+use simperby_core::*;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+pub type Error = eyre::Error;
+
+/// The HTTP header that carries the hex-encoded signature of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Simperby-Signature";
+/// The number of deliveries that can wait for an endpoint; the events beyond it are dropped.
+pub const DELIVERY_QUEUE_SIZE: usize = 64;
+/// The longest delay between the retries.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// The kinds of events to deliver. If empty, every event is delivered.
+    pub events: Vec<NodeEventKind>,
+    /// The number of retries after the first failed attempt.
+    pub max_retries: u32,
+    /// The delay before the first retry, which doubles for every retry up to [`MAX_BACKOFF`].
+    pub initial_backoff_ms: u64,
+}
+
+impl WebhookConfig {
+    pub fn accepts(&self, event: &NodeEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event.kind())
+    }
+}
+
+/// The JSON body of a webhook request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: NodeEvent,
+    pub timestamp: Timestamp,
+    /// The public key of the node; the receiver verifies the signature with it.
+    pub signer: PublicKey,
+}
+
+/// A signed payload to deliver.
+#[derive(Debug, Clone)]
+struct Delivery {
+    body: String,
+    signature: Signature,
+}
+
+/// Delivers the node events to the configured webhook endpoints.
+///
+/// Each endpoint has its own delivery task and queue,
+/// so that a slow or unreachable endpoint doesn't hold up the others.
+pub struct WebhookDispatcher {
+    webhooks: Vec<WebhookConfig>,
+    private_key: PrivateKey,
+    client: reqwest::Client,
+}
+
+impl WebhookDispatcher {
+    pub fn new(webhooks: Vec<WebhookConfig>, private_key: PrivateKey) -> Self {
+        Self {
+            webhooks,
+            private_key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Runs the dispatcher until the event channel is closed.
+    ///
+    /// The delivery tasks finish the queued deliveries after the dispatcher stops.
+    pub async fn run(self, mut events: broadcast::Receiver<NodeEvent>) {
+        let queues = self
+            .webhooks
+            .iter()
+            .map(|webhook| {
+                let (sender, receiver) = mpsc::channel(DELIVERY_QUEUE_SIZE);
+                tokio::spawn(run_endpoint(self.client.clone(), webhook.clone(), receiver));
+                sender
+            })
+            .collect::<Vec<_>>();
+        loop {
+            match events.recv().await {
+                Ok(event) => self.dispatch(&event, &queues),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("webhook dispatcher lagged behind; {n} events are dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Queues the event for every webhook that accepts it,
+    /// dropping it for those whose queue is full.
+    fn dispatch(&self, event: &NodeEvent, queues: &[mpsc::Sender<Delivery>]) {
+        let payload = WebhookPayload {
+            event: event.clone(),
+            timestamp: get_timestamp(),
+            signer: self.private_key.public_key(),
+        };
+        let body = serde_spb::to_string(&payload).unwrap();
+        let signature = match Signature::sign(Hash256::hash(&body), &self.private_key) {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("failed to sign a webhook payload: {e}");
+                return;
+            }
+        };
+        for (webhook, queue) in self.webhooks.iter().zip(queues) {
+            if !webhook.accepts(event) {
+                continue;
+            }
+            let delivery = Delivery {
+                body: body.clone(),
+                signature: signature.clone(),
+            };
+            if let Err(mpsc::error::TrySendError::Full(_)) = queue.try_send(delivery) {
+                log::warn!(
+                    "the deliveries to {} are backed up; an event is dropped",
+                    webhook.url
+                );
+            }
+        }
+    }
+}
+
+/// Delivers the queued payloads to the endpoint one by one, until the queue is closed.
+async fn run_endpoint(
+    client: reqwest::Client,
+    webhook: WebhookConfig,
+    mut queue: mpsc::Receiver<Delivery>,
+) {
+    while let Some(delivery) = queue.recv().await {
+        if let Err(e) = deliver(&client, &webhook, &delivery).await {
+            log::warn!("failed to deliver a webhook to {}: {e}", webhook.url);
+        }
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    delivery: &Delivery,
+) -> Result<(), Error> {
+    let mut backoff = Duration::from_millis(webhook.initial_backoff_ms).min(MAX_BACKOFF);
+    let mut attempt = 0;
+    loop {
+        let result = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, delivery.signature.to_string())
+            .body(delivery.body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt >= webhook.max_retries => {
+                return Err(eyre!("gave up after {} attempts: {e}", attempt + 1));
+            }
+            Err(e) => {
+                log::debug!("webhook to {} failed, retrying: {e}", webhook.url);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        }
+    }
+}
@@ -61,6 +61,7 @@ async fn normal_1() {
                 ports: proposer_node.network_config().ports.clone(),
                 message: "123".to_owned(),
                 recently_seen_timestamp: 0,
+                relay: None,
             }],
         )
         .await;
@@ -69,7 +70,7 @@ async fn normal_1() {
 
     // Step 1: create an agenda and propagate it
     log::info!("STEP 1");
-    proposer_node.create_agenda().await.unwrap();
+    proposer_node.create_agenda(None, None).await.unwrap();
     let agenda_commit = proposer_node
         .get_raw_repo_mut()
         .locate_branch("work".to_owned())
@@ -94,7 +95,11 @@ async fn normal_1() {
     // Step 2: create block and run prevote phase
     log::info!("STEP 2");
     proposer_node.create_block().await.unwrap();
-    proposer_node.progress_for_consensus().await.unwrap();
+    // The proposer proposes and prevotes at once, reporting the prevote.
+    assert!(matches!(
+        proposer_node.progress_for_consensus().await.unwrap(),
+        ConsensusProgressOutcome::Prevoted { round: 0, .. }
+    ));
     proposer_node.broadcast().await.unwrap();
     let serve = tokio::spawn(async move { proposer_node.serve(5000).await.unwrap() });
     sleep_ms(500).await;
@@ -105,11 +110,17 @@ async fn normal_1() {
         node.fetch().await.unwrap();
     }
     for node in other_nodes.iter_mut() {
-        node.progress_for_consensus().await.unwrap();
+        assert!(matches!(
+            node.progress_for_consensus().await.unwrap(),
+            ConsensusProgressOutcome::Prevoted { round: 0, .. }
+        ));
         node.broadcast().await.unwrap();
     }
     let mut proposer_node = serve.await.unwrap();
-    proposer_node.progress_for_consensus().await.unwrap();
+    assert!(matches!(
+        proposer_node.progress_for_consensus().await.unwrap(),
+        ConsensusProgressOutcome::Precommitted { round: 0, .. }
+    ));
     proposer_node.broadcast().await.unwrap();
 
     // Step 3: Run precommit phase
@@ -120,11 +131,17 @@ async fn normal_1() {
         node.fetch().await.unwrap();
     }
     for node in other_nodes.iter_mut() {
-        let _ = node.progress_for_consensus().await.unwrap();
+        assert!(matches!(
+            node.progress_for_consensus().await.unwrap(),
+            ConsensusProgressOutcome::Precommitted { round: 0, .. }
+        ));
         node.broadcast().await.unwrap();
     }
     let mut proposer_node = serve.await.unwrap();
-    let _ = proposer_node.progress_for_consensus().await.unwrap();
+    assert!(matches!(
+        proposer_node.progress_for_consensus().await.unwrap(),
+        ConsensusProgressOutcome::Finalized { height: 1, .. }
+    ));
     proposer_node.broadcast().await.unwrap();
 
     // Step 4: Propagate finalized proof
@@ -135,11 +152,14 @@ async fn normal_1() {
         node.fetch().await.unwrap();
     }
     for node in other_nodes.iter_mut() {
-        let _ = node.progress_for_consensus().await;
+        assert!(matches!(
+            node.progress_for_consensus().await.unwrap(),
+            ConsensusProgressOutcome::Finalized { height: 1, .. }
+        ));
         node.broadcast().await.unwrap();
     }
     let mut proposer_node = serve.await.unwrap();
-    let _ = proposer_node.progress_for_consensus().await;
+    proposer_node.progress_for_consensus().await.unwrap();
     proposer_node.broadcast().await.unwrap();
 
     for node in std::iter::once(proposer_node).chain(other_nodes.into_iter()) {
@@ -0,0 +1,165 @@
+//! Signed attestations of the last finalized state, for the mirrors and CDNs.
+//!
+//! A node keeps the attestation of its last finalized block in `attestation.json`,
+//! which is stored at [`ATTESTATION_REF`] apart from the history and refreshed on each finalization.
+//! The peers fetch it along with the branches and check it against the `finalized` branch
+//! that came with it, which is far cheaper than verifying the history
+//! yet tells whether a mirror serves what the node has signed.
+use super::*;
+
+/// The reference to the commit of [`ATTESTATION_FILE_NAME`].
+pub const ATTESTATION_REF: &str = "refs/simperby/attestation";
+pub const ATTESTATION_FILE_NAME: &str = "attestation.json";
+
+/// Where the attestation of the remote is fetched to.
+pub fn remote_attestation_ref(remote: &str) -> String {
+    format!("refs/simperby/remotes/{remote}/attestation")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// The commit of the last finalized block, i.e., the tip of the `finalized` branch.
+    pub commit_hash: CommitHash,
+    pub block_hash: Hash256,
+    pub height: BlockHeight,
+    pub reserved_state_hash: Hash256,
+    /// When the attestation was made, by the node's clock.
+    pub timestamp: Timestamp,
+}
+
+impl ToHash256 for Attestation {
+    fn to_hash256(&self) -> Hash256 {
+        serde_spb::to_hash256(self).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    pub attestation: Attestation,
+    pub signature: TypedSignature<Attestation>,
+}
+
+/// The result of checking the attestation of a remote, reported by [`DistributedRepository::fetch`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationCheck {
+    pub remote: String,
+    pub result: Result<SignedAttestation, String>,
+}
+
+/// Attests the last finalized block of the repository, signed by the key.
+pub async fn create_attestation(
+    raw: &RawRepository,
+    signing_key: &PrivateKey,
+    timestamp: Timestamp,
+) -> Result<SignedAttestation, Error> {
+    let header = read_last_finalized_block_header(raw).await?;
+    let attestation = Attestation {
+        commit_hash: get_last_finalized_block_commit_hash(raw).await?,
+        block_hash: header.to_hash256(),
+        height: header.height,
+        reserved_state_hash: read_last_finalized_reserved_state(raw).await?.to_hash256(),
+        timestamp,
+    };
+    let signature = TypedSignature::sign(&attestation, signing_key).map_err(|e| eyre!(e))?;
+    Ok(SignedAttestation {
+        attestation,
+        signature,
+    })
+}
+
+pub async fn write_attestation(
+    raw: &mut RawRepository,
+    attestation: &SignedAttestation,
+) -> Result<(), Error> {
+    raw.write_files_at_ref(
+        ATTESTATION_REF.to_owned(),
+        vec![(
+            ATTESTATION_FILE_NAME.to_owned(),
+            serde_spb::to_string(attestation)?,
+        )],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Reads the attestation of this repository, or of the remote if given.
+pub async fn read_attestation(
+    raw: &RawRepository,
+    remote: Option<&str>,
+) -> Result<Option<SignedAttestation>, Error> {
+    let reference = match remote {
+        Some(remote) => remote_attestation_ref(remote),
+        None => ATTESTATION_REF.to_owned(),
+    };
+    let content = raw
+        .read_file_at_ref(reference, ATTESTATION_FILE_NAME.to_owned())
+        .await?;
+    Ok(content
+        .map(|content| serde_spb::from_str(&content))
+        .transpose()?)
+}
+
+/// Checks the fetched attestation of the remote against its fetched `finalized` branch
+/// and the local one.
+///
+/// The attested block must be the tip of the remote's branch,
+/// and must not conflict with the local branch if it's not ahead.
+/// Returns `None` if the remote has no attestation.
+pub async fn check_remote_attestation(
+    raw: &RawRepository,
+    remote: &str,
+) -> Result<Option<Result<SignedAttestation, String>>, Error> {
+    let signed = match read_attestation(raw, Some(remote)).await {
+        Ok(Some(signed)) => signed,
+        Ok(None) => return Ok(None),
+        Err(e) => return Ok(Some(Err(format!("invalid attestation: {e}")))),
+    };
+    let attestation = &signed.attestation;
+    if let Err(e) = signed.signature.verify(attestation) {
+        return Ok(Some(Err(format!("invalid signature: {e}"))));
+    }
+    let tip = match raw
+        .locate_remote_tracking_branch(remote.to_owned(), FINALIZED_BRANCH_NAME.to_owned())
+        .await
+    {
+        Ok(tip) => tip,
+        Err(_) => return Ok(Some(Err("no finalized branch".to_owned()))),
+    };
+    if tip != attestation.commit_hash {
+        return Ok(Some(Err(format!(
+            "the finalized branch is at {tip}, not at the attested {}",
+            attestation.commit_hash
+        ))));
+    }
+    match read_commit(raw, tip).await {
+        Ok(Commit::Block(header))
+            if header.to_hash256() == attestation.block_hash
+                && header.height == attestation.height => {}
+        _ => {
+            return Ok(Some(Err(format!(
+                "the finalized commit is not the attested block {}",
+                attestation.block_hash
+            ))))
+        }
+    }
+    match raw.read_reserved_state_at_commit(tip).await {
+        Ok(reserved_state) if reserved_state.to_hash256() == attestation.reserved_state_hash => {}
+        _ => {
+            return Ok(Some(Err(format!(
+                "the reserved state is not the attested {}",
+                attestation.reserved_state_hash
+            ))))
+        }
+    }
+    let local_header = read_last_finalized_block_header(raw).await?;
+    if local_header.height >= attestation.height {
+        let local_tip = get_last_finalized_block_commit_hash(raw).await?;
+        if raw.find_merge_base(tip, local_tip).await.ok() != Some(tip) {
+            return Ok(Some(Err(format!(
+                "the attested block at height {} is not in the local finalized branch",
+                attestation.height
+            ))));
+        }
+    }
+    Ok(Some(Ok(signed)))
+}
@@ -0,0 +1,139 @@
+//! A content-addressed store for the large files referenced by transactions.
+//!
+//! Committing a large artifact as a Git blob bloats every clone of the repository.
+//! Instead, a transaction references the content hash (see [`BlobReference`])
+//! and the content is distributed through a dedicated DMS.
+use super::*;
+use simperby_network::Error;
+use simperby_network::*;
+
+/// A blob, which is identified by the hash of its content.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blob {
+    pub data: HexSerializedVec,
+}
+
+impl ToHash256 for Blob {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(&self.data.data)
+    }
+}
+
+impl DmsMessage for Blob {
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Generates the DMS key for the blobs of the given network.
+pub fn generate_dms_key(network_id: &str) -> DmsKey {
+    format!("blob-{network_id}")
+}
+
+#[derive(Clone)]
+pub struct BlobStore {
+    dms: Arc<RwLock<Dms<Blob>>>,
+}
+
+impl BlobStore {
+    pub fn new(dms: Arc<RwLock<Dms<Blob>>>) -> Self {
+        Self { dms }
+    }
+
+    pub fn get_dms(&self) -> Arc<RwLock<Dms<Blob>>> {
+        Arc::clone(&self.dms)
+    }
+
+    /// Adds a blob, returning the reference to put in a transaction.
+    ///
+    /// It fails if the blob is larger than the limit of the reserved state.
+    pub async fn put(
+        &mut self,
+        data: Vec<u8>,
+        reserved_state: &ReservedState,
+    ) -> Result<BlobReference, Error> {
+        let size = data.len() as u64;
+        if size > reserved_state.max_blob_size {
+            return Err(eyre!(
+                "blob is too large: {} > {}",
+                size,
+                reserved_state.max_blob_size
+            ));
+        }
+        let blob = Blob { data: data.into() };
+        self.dms.write().await.commit_message(&blob).await?;
+        Ok(BlobReference {
+            hash: blob.to_hash256(),
+            size,
+        })
+    }
+
+    /// Reads the content of the blob, if it's available.
+    pub async fn get(&self, hash: Hash256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .dms
+            .read()
+            .await
+            .query_message(hash)
+            .await?
+            .map(|message| message.message.data.data))
+    }
+
+    /// Checks that all the referenced blobs are available and match the references.
+    pub async fn check_available(&self, references: &[BlobReference]) -> Result<(), Error> {
+        for reference in references {
+            let data = self
+                .get(reference.hash)
+                .await?
+                .ok_or_else(|| eyre!("blob {} is not available", reference.hash))?;
+            if data.len() as u64 != reference.size {
+                return Err(eyre!(
+                    "blob {} has a wrong size: expected {}, got {}",
+                    reference.hash,
+                    reference.size,
+                    data.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    #[tokio::test]
+    async fn put_and_check() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        reserved_state.max_blob_size = 1024;
+        let mut store = BlobStore::new(Arc::new(RwLock::new(
+            create_test_dms(
+                generate_dms_key("test"),
+                keys.iter()
+                    .map(|(public_key, _)| public_key.clone())
+                    .collect(),
+                keys[0].1.clone(),
+            )
+            .await,
+        )));
+
+        let data = vec![7; 1000];
+        let reference = store.put(data.clone(), &reserved_state).await.unwrap();
+        assert_eq!(reference.hash, Hash256::hash(&data));
+        assert_eq!(store.get(reference.hash).await.unwrap(), Some(data));
+        store
+            .check_available(std::slice::from_ref(&reference))
+            .await
+            .unwrap();
+
+        let missing = BlobReference {
+            hash: Hash256::hash("missing"),
+            size: 1,
+        };
+        assert!(store.check_available(&[reference, missing]).await.is_err());
+        assert!(store.put(vec![0; 1025], &reserved_state).await.is_err());
+    }
+}
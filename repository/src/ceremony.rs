@@ -0,0 +1,152 @@
+//! The genesis ceremony, which collects the members' signatures for the genesis proof.
+//!
+//! The coordinator starts a ceremony from a template reserved state
+//! (see [`ReservedState::genesis_template`]) and hands out the payload to sign.
+//! The members sign it on their own machines and send back the signatures,
+//! which are accumulated in the ceremony file until the proof is complete.
+use super::*;
+use tokio::fs;
+
+/// What a member reviews and signs for the genesis proof.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisPayload {
+    pub chain_name: String,
+    /// The candidate genesis header.
+    pub header: BlockHeader,
+    /// The members should check that this is on `header` before signing its hash.
+    pub sign_target: FinalizationSignTarget,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisCeremony {
+    /// The reserved state to seal, whose genesis proof is partial.
+    pub reserved_state: ReservedState,
+}
+
+impl GenesisCeremony {
+    pub fn new(reserved_state: ReservedState) -> Self {
+        Self { reserved_state }
+    }
+
+    /// Loads the ceremony file.
+    pub async fn load(path: &str) -> Result<Self, Error> {
+        Ok(serde_spb::from_str(&fs::read_to_string(path).await?)?)
+    }
+
+    /// Saves the ceremony file, overwriting the existing one.
+    pub async fn save(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, serde_spb::to_string(self)?).await?;
+        Ok(())
+    }
+
+    pub fn export_genesis_payload(&self) -> GenesisPayload {
+        GenesisPayload {
+            chain_name: self.reserved_state.genesis_info.chain_name.clone(),
+            header: self.reserved_state.genesis_info.header.clone(),
+            sign_target: self.reserved_state.genesis_sign_target(),
+        }
+    }
+
+    /// Adds a member's signature on the payload to the partial genesis proof.
+    pub fn submit_genesis_signature(
+        &mut self,
+        signature: TypedSignature<FinalizationSignTarget>,
+    ) -> Result<(), Error> {
+        self.reserved_state
+            .add_genesis_signature(signature)
+            .map_err(|e| eyre!(e))
+    }
+
+    /// Returns the members who haven't signed yet.
+    pub fn missing_signers(&self) -> Vec<MemberName> {
+        let signers = self
+            .reserved_state
+            .genesis_info
+            .genesis_proof
+            .signatures
+            .iter()
+            .map(|precommit| precommit.signer().clone())
+            .collect::<HashSet<_>>();
+        self.reserved_state
+            .members
+            .iter()
+            .filter(|member| !signers.contains(&member.public_key))
+            .map(|member| member.name.clone())
+            .collect()
+    }
+
+    /// Checks that every member has signed and the genesis proof is valid.
+    pub fn check_complete(&self) -> Result<(), Error> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            return Err(eyre!("missing genesis signatures from {:?}", missing));
+        }
+        verify::verify_finalization_proof(
+            &self.reserved_state.genesis_info.header,
+            &self.reserved_state.genesis_info.genesis_proof,
+        )?;
+        Ok(())
+    }
+
+    /// Writes the completed reserved state to the given repository directory.
+    pub async fn seal_genesis(&self, path: &str) -> Result<ReservedState, Error> {
+        self.check_complete()?;
+        raw::reserved_state::write_reserved_state(path, &self.reserved_state).await?;
+        Ok(self.reserved_state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    #[tokio::test]
+    async fn ceremony() {
+        setup_test();
+        let keys = (0..3)
+            .map(|i| generate_keypair(format!("{i}")))
+            .collect::<Vec<_>>();
+        let reserved_state = ReservedState::genesis_template(
+            "test-chain".to_owned(),
+            keys.iter()
+                .enumerate()
+                .map(|(i, (public_key, _))| (format!("member-{i:04}"), public_key.clone()))
+                .collect(),
+        )
+        .unwrap();
+        let dir = create_temp_dir();
+        let ceremony_path = format!("{dir}/ceremony.json");
+        GenesisCeremony::new(reserved_state)
+            .save(&ceremony_path)
+            .await
+            .unwrap();
+
+        // Each member signs the payload and submits the signature separately.
+        let payload = GenesisCeremony::load(&ceremony_path)
+            .await
+            .unwrap()
+            .export_genesis_payload();
+        assert_eq!(payload.sign_target.block_hash, payload.header.to_hash256());
+        for (i, (_, private_key)) in keys.iter().enumerate() {
+            let mut ceremony = GenesisCeremony::load(&ceremony_path).await.unwrap();
+            assert_eq!(ceremony.missing_signers().len(), keys.len() - i);
+            assert!(ceremony.seal_genesis(&dir).await.is_err());
+            ceremony
+                .submit_genesis_signature(
+                    TypedSignature::sign(&payload.sign_target, private_key).unwrap(),
+                )
+                .unwrap();
+            ceremony.save(&ceremony_path).await.unwrap();
+        }
+
+        let ceremony = GenesisCeremony::load(&ceremony_path).await.unwrap();
+        let sealed = ceremony.seal_genesis(&dir).await.unwrap();
+        assert_eq!(
+            raw::reserved_state::read_reserved_state(&dir)
+                .await
+                .unwrap(),
+            sealed
+        );
+    }
+}
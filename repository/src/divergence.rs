@@ -0,0 +1,247 @@
+//! The local branches that have diverged from the `finalized` branch, and their resolution.
+//!
+//! After a vetoed round or a block finalized by the others, the local candidate branches
+//! (`work`, `a-#`, `b-#` or those of the user) may no longer extend the finalized tip.
+//! [`read_divergent_branches`] lists them with the reasons, and [`resolve_divergence`]
+//! either discards one or rebases it onto the tip, carrying over the transactions that still apply.
+use super::*;
+
+/// Why a branch doesn't extend the `finalized` branch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DivergenceReason {
+    /// The branch is based on an earlier commit of the `finalized` branch (the merge base),
+    /// missing the blocks finalized since.
+    Outdated { merge_base: CommitHash },
+    /// The branch shares no available history with the `finalized` branch.
+    Unrelated,
+    /// The branch is based on the finalized tip, but the commit doesn't verify on it.
+    Invalid {
+        commit_hash: CommitHash,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DivergentBranch {
+    pub branch: Branch,
+    pub commit_hash: CommitHash,
+    pub reason: DivergenceReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResolutionStrategy {
+    /// Deletes the branch, or resets it to the finalized tip if it's the `work` branch.
+    Discard,
+    /// Replays the transactions of the branch onto the finalized tip,
+    /// dropping those already finalized or not applying anymore.
+    ///
+    /// The other commits are bound to the height they were made for, so they're dropped too,
+    /// and a branch of an agenda or a block (`a-#` or `b-#`) can only be discarded.
+    Rebase,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DivergenceResolution {
+    pub branch: Branch,
+    pub strategy: ResolutionStrategy,
+    /// Where the branch points to now, or `None` if it's deleted.
+    pub commit_hash: Option<CommitHash>,
+    /// The commits replayed onto the finalized tip, by their original hashes.
+    pub replayed: Vec<CommitHash>,
+    /// The commits not carried over, by their original hashes, with the reasons.
+    pub dropped: Vec<(CommitHash, String)>,
+}
+
+fn is_protocol_branch(branch: &str) -> bool {
+    branch.starts_with("a-") || branch.starts_with("b-")
+}
+
+/// Lists the local branches that don't extend the finalized tip, except `finalized` and `fp`
+/// and those merely behind it.
+pub async fn read_divergent_branches(raw: &RawRepository) -> Result<Vec<DivergentBranch>, Error> {
+    let mut result = Vec::new();
+    for (branch, commit_hash) in read_local_branches(raw).await? {
+        if branch == FINALIZED_BRANCH_NAME || branch == FP_BRANCH_NAME {
+            continue;
+        }
+        if let Some(reason) = check_divergence(raw, commit_hash).await? {
+            result.push(DivergentBranch {
+                branch,
+                commit_hash,
+                reason,
+            });
+        }
+    }
+    result.sort_by(|a, b| a.branch.cmp(&b.branch));
+    Ok(result)
+}
+
+/// Returns why the commit doesn't extend the finalized tip, or `None` if it does
+/// or is an ancestor of it.
+async fn check_divergence(
+    raw: &RawRepository,
+    commit_hash: CommitHash,
+) -> Result<Option<DivergenceReason>, Error> {
+    let finalized_commit_hash = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    let merge_base = match raw
+        .find_merge_base(commit_hash, finalized_commit_hash)
+        .await
+    {
+        Ok(merge_base) => merge_base,
+        Err(raw::Error::NotFound(_)) => return Ok(Some(DivergenceReason::Unrelated)),
+        Err(e) => return Err(e.into()),
+    };
+    // Merely behind the tip, with nothing to lose.
+    if merge_base == commit_hash {
+        return Ok(None);
+    }
+    if merge_base != finalized_commit_hash {
+        return Ok(Some(DivergenceReason::Outdated { merge_base }));
+    }
+    let last_header = read_last_finalized_block_header(raw).await?;
+    let reserved_state = read_last_finalized_reserved_state(raw).await?;
+    let mut verifier = CommitSequenceVerifier::new(last_header, reserved_state)
+        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
+    let commits = match read_commits(raw, finalized_commit_hash, commit_hash).await {
+        Ok(commits) => commits,
+        Err(e) => {
+            return Ok(Some(DivergenceReason::Invalid {
+                commit_hash,
+                error: e.to_string(),
+            }))
+        }
+    };
+    for (commit, commit_hash) in commits {
+        if let Err(e) = verifier.apply_commit(&commit) {
+            return Ok(Some(DivergenceReason::Invalid {
+                commit_hash,
+                error: e.to_string(),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves the divergence of the branch by the strategy, failing if it hasn't diverged.
+pub async fn resolve_divergence(
+    raw: &mut RawRepository,
+    branch: Branch,
+    strategy: ResolutionStrategy,
+    finalized_transactions: &mut TransactionIndex,
+) -> Result<DivergenceResolution, Error> {
+    if branch == FINALIZED_BRANCH_NAME || branch == FP_BRANCH_NAME {
+        return Err(eyre!("branch {branch} can't diverge"));
+    }
+    let commit_hash = raw.locate_branch(branch.clone()).await?;
+    let Some(reason) = check_divergence(raw, commit_hash).await? else {
+        return Err(eyre!("branch {branch} extends the finalized tip"));
+    };
+    let finalized_commit_hash = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    raw.checkout_clean().await?;
+    match strategy {
+        ResolutionStrategy::Discard => {
+            let commit_hash = if branch == WORK_BRANCH_NAME {
+                raw.move_branch(branch.clone(), finalized_commit_hash)
+                    .await?;
+                raw.checkout_clean().await?;
+                Some(finalized_commit_hash)
+            } else {
+                if raw.get_currently_checkout_branch().await?.as_ref() == Some(&branch) {
+                    raw.checkout(WORK_BRANCH_NAME.into()).await?;
+                }
+                raw.delete_branch(branch.clone()).await?;
+                None
+            };
+            Ok(DivergenceResolution {
+                branch,
+                strategy,
+                commit_hash,
+                replayed: Vec::new(),
+                dropped: Vec::new(),
+            })
+        }
+        ResolutionStrategy::Rebase => {
+            if is_protocol_branch(&branch) {
+                return Err(eyre!(
+                    "the commits of branch {branch} are bound to its height; discard it instead"
+                ));
+            }
+            let base = match reason {
+                DivergenceReason::Outdated { merge_base } => merge_base,
+                DivergenceReason::Invalid { .. } => finalized_commit_hash,
+                DivergenceReason::Unrelated => {
+                    return Err(eyre!(
+                        "branch {branch} shares no history with the finalized branch to rebase"
+                    ))
+                }
+            };
+            let commits = read_commits(raw, base, commit_hash)
+                .await
+                .map_err(|e| eyre!("failed to read the commits of branch {branch}: {e}"))?;
+            raw.checkout(branch.clone()).await?;
+            raw.move_branch(branch.clone(), finalized_commit_hash)
+                .await?;
+            raw.checkout_clean().await?;
+            let (replayed, dropped) =
+                replay_transactions(raw, &branch, commits, finalized_transactions).await?;
+            Ok(DivergenceResolution {
+                commit_hash: Some(raw.locate_branch(branch.clone()).await?),
+                branch,
+                strategy,
+                replayed,
+                dropped,
+            })
+        }
+    }
+}
+
+/// Replays the transactions on top of the branch, which is checked out on the finalized tip.
+async fn replay_transactions(
+    raw: &mut RawRepository,
+    branch: &Branch,
+    commits: Vec<(Commit, CommitHash)>,
+    finalized_transactions: &mut TransactionIndex,
+) -> Result<(Vec<CommitHash>, Vec<(CommitHash, String)>), Error> {
+    let last_header = read_last_finalized_block_header(raw).await?;
+    let reserved_state = read_last_finalized_reserved_state(raw).await?;
+    let mut verifier = CommitSequenceVerifier::new(last_header, reserved_state)
+        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
+    let mut replayed = Vec::new();
+    let mut dropped = Vec::new();
+    for (commit, commit_hash) in commits {
+        let Commit::Transaction(transaction) = &commit else {
+            dropped.push((commit_hash, "it is bound to its height".to_owned()));
+            continue;
+        };
+        if let Some(height) = finalized_transactions
+            .finalized_height(raw, transaction.to_hash256())
+            .await?
+        {
+            dropped.push((
+                commit_hash,
+                format!("it has already been finalized at height {height}"),
+            ));
+            continue;
+        }
+        let parent = raw.get_head().await?;
+        if let Err(e) = raw.create_commit(raw.read_commit(commit_hash).await?).await {
+            dropped.push((commit_hash, format!("it doesn't apply: {e}")));
+            raw.checkout_clean().await?;
+            continue;
+        }
+        // Not to leave the verifier half-applied on failure.
+        let mut next = verifier.clone();
+        match next.apply_commit(&commit) {
+            Ok(()) => {
+                verifier = next;
+                replayed.push(commit_hash);
+            }
+            Err(e) => {
+                dropped.push((commit_hash, e.to_string()));
+                raw.move_branch(branch.clone(), parent).await?;
+                raw.checkout_clean().await?;
+            }
+        }
+    }
+    Ok((replayed, dropped))
+}
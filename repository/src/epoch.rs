@@ -0,0 +1,271 @@
+//! The epochs of the chain, each a range of heights under the same reserved state.
+//!
+//! A finalization that changes the reserved state in effect (by its transactions,
+//! an activated scheduled change or a lifted ban) closes the current epoch and starts a new one
+//! from the next height. An epoch fixes who validates and who governs its blocks,
+//! so that they're looked up by the height rather than recomputed from the reserved state.
+//! Like [`crate::transaction_index::TransactionIndex`], the index follows the `finalized` branch:
+//! the new blocks are added as the branch advances,
+//! and the whole index is rebuilt from the available history if the branch moves otherwise.
+use super::*;
+use simperby_network::{Storage, StorageImpl};
+
+const EPOCH_FILE_PREFIX: &str = "epoch-";
+const TIP_FILE_NAME: &str = "tip.json";
+
+/// A range of heights under the same reserved state.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Epoch {
+    /// The height of the first block of the epoch.
+    pub start_height: BlockHeight,
+    /// The height of the last block of the epoch, or `None` for the current one.
+    pub end_height: Option<BlockHeight>,
+    /// The validators who finalize the blocks of the epoch, as in their headers.
+    pub validator_set: Vec<(PublicKey, VotingPower)>,
+    /// The voters who approve the agendas of the epoch.
+    pub governance_set: Vec<(PublicKey, VotingPower)>,
+    /// The reserved state in effect, which holds the other parameters.
+    pub reserved_state: ReservedState,
+}
+
+impl Epoch {
+    /// Creates the (current) epoch of the reserved state in effect from the given height.
+    pub fn new(start_height: BlockHeight, reserved_state: ReservedState) -> Result<Self, String> {
+        Ok(Self {
+            start_height,
+            end_height: None,
+            validator_set: reserved_state.get_validator_set()?,
+            governance_set: reserved_state.get_governance_set()?,
+            reserved_state,
+        })
+    }
+
+    /// Returns whether the block of the given height is in the epoch.
+    pub fn contains(&self, height: BlockHeight) -> bool {
+        height >= self.start_height && self.end_height.is_none_or(|end| height <= end)
+    }
+
+    /// Verifies the finalization proof of a block of the epoch,
+    /// checking the validator set of the header against that of the epoch.
+    pub fn verify_finalization_proof(
+        &self,
+        header: &BlockHeader,
+        proof: &FinalizationProof,
+    ) -> Result<(), String> {
+        if !self.contains(header.height) {
+            return Err(format!(
+                "the block at height {} is not in the epoch from {}",
+                header.height, self.start_height
+            ));
+        }
+        let sorted = |set: &[(PublicKey, VotingPower)]| {
+            let mut set = set.to_vec();
+            set.sort();
+            set
+        };
+        if sorted(&header.validator_set) != sorted(&self.validator_set) {
+            return Err(format!(
+                "the validator set of the block at height {} doesn't match the epoch from {}",
+                header.height, self.start_height
+            ));
+        }
+        verify::verify_finalization_proof(header, proof).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct StoredTip {
+    commit_hash: CommitHash,
+    height: BlockHeight,
+}
+
+#[derive(Default)]
+pub struct EpochIndex {
+    /// The tip of the `finalized` branch that the index is consistent with.
+    tip: Option<StoredTip>,
+    /// The epochs by their start heights.
+    epochs: BTreeMap<BlockHeight, Epoch>,
+    /// Where the index is persisted, if set.
+    storage: Option<StorageImpl>,
+}
+
+impl EpochIndex {
+    /// Creates an index that persists in the storage, loading what is already stored.
+    ///
+    /// The loaded index is checked against the repository on the first lookup.
+    pub async fn load(storage: StorageImpl) -> Result<Self, Error> {
+        let mut this = Self::default();
+        for file_name in storage.list_files().await? {
+            if file_name == TIP_FILE_NAME {
+                this.tip = Some(serde_spb::from_str(&storage.read_file(&file_name).await?)?);
+            } else if file_name.starts_with(EPOCH_FILE_PREFIX) {
+                let epoch: Epoch = serde_spb::from_str(&storage.read_file(&file_name).await?)?;
+                this.epochs.insert(epoch.start_height, epoch);
+            }
+        }
+        this.storage = Some(storage);
+        Ok(this)
+    }
+
+    /// Returns the epoch of the block at the given height, or `None` if it's
+    /// beyond the next height to finalize or below the available history.
+    pub async fn get(
+        &mut self,
+        raw: &RawRepository,
+        height: BlockHeight,
+    ) -> Result<Option<Epoch>, Error> {
+        self.refresh(raw).await?;
+        let last_finalized_height = self.tip.as_ref().expect("refreshed").height;
+        if height > last_finalized_height + 1 {
+            return Ok(None);
+        }
+        Ok(self
+            .epochs
+            .range(..=height)
+            .next_back()
+            .map(|(_, epoch)| epoch)
+            .filter(|epoch| epoch.contains(height))
+            .cloned())
+    }
+
+    /// Returns the epoch of the next block to finalize.
+    pub async fn current(&mut self, raw: &RawRepository) -> Result<Epoch, Error> {
+        self.refresh(raw).await?;
+        Ok(self
+            .epochs
+            .values()
+            .next_back()
+            .expect("there must be at least one epoch")
+            .clone())
+    }
+
+    /// Drops the whole index, including the stored one.
+    pub async fn invalidate(&mut self) -> Result<(), Error> {
+        self.tip = None;
+        self.epochs.clear();
+        if let Some(storage) = &mut self.storage {
+            storage.remove_all_files().await?;
+        }
+        Ok(())
+    }
+
+    /// Follows the `finalized` branch, which costs a single reference lookup if it hasn't moved.
+    async fn refresh(&mut self, raw: &RawRepository) -> Result<(), Error> {
+        let tip = raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .map_err(|e| match e {
+                raw::Error::NotFound(_) => eyre!(IntegrityError::new(
+                    "cannot locate `finalized` branch".to_string()
+                )),
+                _ => eyre!(e),
+            })?;
+        if self.tip.as_ref().map(|tip| tip.commit_hash) == Some(tip) && !self.epochs.is_empty() {
+            return Ok(());
+        }
+        // Fails unless the branch has advanced from the indexed tip.
+        let advanced = match &self.tip {
+            Some(indexed) if !self.epochs.is_empty() => {
+                read_commits(raw, indexed.commit_hash, tip).await.ok()
+            }
+            _ => None,
+        };
+        let blocks = match advanced {
+            Some(commits) => commits
+                .into_iter()
+                .filter_map(|(commit, commit_hash)| match commit {
+                    Commit::Block(header) => Some((header.height, commit_hash)),
+                    _ => None,
+                })
+                .collect(),
+            None => {
+                self.invalidate().await?;
+                Self::read_blocks(raw, tip).await?
+            }
+        };
+        for (height, commit_hash) in blocks {
+            let reserved_state = raw
+                .read_reserved_state_at_commit(commit_hash)
+                .await?
+                .effective_at(height);
+            self.observe(height, reserved_state).await?;
+        }
+        let tip = StoredTip {
+            commit_hash: tip,
+            height: read_last_finalized_block_header(raw).await?.height,
+        };
+        if let Some(storage) = &mut self.storage {
+            storage
+                .add_or_overwrite_file(TIP_FILE_NAME, serde_spb::to_string(&tip).unwrap())
+                .await?;
+        }
+        self.tip = Some(tip);
+        Ok(())
+    }
+
+    /// Reads the finalized blocks down from the tip, to the genesis block or the shallow boundary,
+    /// in the ascending order of the heights.
+    async fn read_blocks(
+        raw: &RawRepository,
+        tip: CommitHash,
+    ) -> Result<Vec<(BlockHeight, CommitHash)>, Error> {
+        let mut blocks = Vec::new();
+        for commit_hash in std::iter::once(tip).chain(raw.list_ancestors(tip, None).await?) {
+            let Ok(commit) = read_commit(raw, commit_hash).await else {
+                // The commit is at the shallow boundary.
+                break;
+            };
+            if let Commit::Block(header) = commit {
+                let height = header.height;
+                blocks.push((height, commit_hash));
+                if height == 0 {
+                    break;
+                }
+            }
+        }
+        blocks.reverse();
+        Ok(blocks)
+    }
+
+    /// Takes the reserved state in effect once the block of the given height is finalized,
+    /// which starts a new epoch from the next height if it has changed.
+    async fn observe(
+        &mut self,
+        height: BlockHeight,
+        reserved_state: ReservedState,
+    ) -> Result<(), Error> {
+        let current = self.epochs.values_mut().next_back();
+        if let Some(current) = &current {
+            if current.reserved_state == reserved_state {
+                return Ok(());
+            }
+        }
+        if let Some(current) = current {
+            current.end_height = Some(height);
+            let closed = current.clone();
+            self.store(&closed).await?;
+        }
+        // The genesis state governs the genesis block too.
+        let start_height = if height == 0 { 0 } else { height + 1 };
+        let epoch = Epoch::new(start_height, reserved_state).map_err(|e| {
+            eyre!(IntegrityError::new(format!(
+                "invalid reserved state at height {height}: {e}"
+            )))
+        })?;
+        self.store(&epoch).await?;
+        self.epochs.insert(start_height, epoch);
+        Ok(())
+    }
+
+    async fn store(&mut self, epoch: &Epoch) -> Result<(), Error> {
+        if let Some(storage) = &mut self.storage {
+            storage
+                .add_or_overwrite_file(
+                    &format!("{EPOCH_FILE_PREFIX}{}.json", epoch.start_height),
+                    serde_spb::to_string(epoch).unwrap(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
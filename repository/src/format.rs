@@ -6,7 +6,7 @@ use simperby_core::{reserved::ReservedState, *};
 /// Converts a commit to a semantic commit.
 pub fn to_semantic_commit(
     commit: &Commit,
-    mut reserved_state: ReservedState,
+    reserved_state: ReservedState,
 ) -> Result<SemanticCommit, Error> {
     match commit {
         Commit::Agenda(agenda) => {
@@ -18,6 +18,7 @@ pub fn to_semantic_commit(
                 diff: Diff::None,
                 author: agenda.author.clone(),
                 timestamp: agenda.timestamp,
+                signature: None,
             })
         }
         Commit::Block(block_header) => {
@@ -40,6 +41,7 @@ pub fn to_semantic_commit(
                         })?
                 },
                 timestamp: block_header.timestamp,
+                signature: None,
             })
         }
         Commit::Transaction(transaction) => Ok(SemanticCommit {
@@ -48,6 +50,7 @@ pub fn to_semantic_commit(
             diff: transaction.diff.clone(),
             author: transaction.author.clone(),
             timestamp: transaction.timestamp,
+            signature: None,
         }),
         Commit::AgendaProof(agenda_proof) => {
             let title = format!(">agenda-proof: {}", agenda_proof.height);
@@ -58,6 +61,7 @@ pub fn to_semantic_commit(
                 diff: Diff::None,
                 author: UNKNOWN_COMMIT_AUTHOR.to_owned(),
                 timestamp: agenda_proof.timestamp,
+                signature: None,
             })
         }
         Commit::ExtraAgendaTransaction(tx) => {
@@ -75,6 +79,7 @@ pub fn to_semantic_commit(
                         diff,
                         author: tx.data.delegator.clone(),
                         timestamp: tx.data.timestamp,
+                        signature: None,
                     })
                 }
                 ExtraAgendaTransaction::Undelegate(tx) => {
@@ -87,10 +92,41 @@ pub fn to_semantic_commit(
                         diff,
                         author: tx.data.delegator.clone(),
                         timestamp: tx.data.timestamp,
+                        signature: None,
                     })
                 }
-                ExtraAgendaTransaction::Report(_) => {
-                    unimplemented!("report is not implemented yet.")
+                ExtraAgendaTransaction::Report(tx) => {
+                    let offender = reserved_state
+                        .query_name(tx.evidence.offender())
+                        .ok_or_else(|| {
+                            eyre!("offender {} is not a member", tx.evidence.offender())
+                        })?;
+                    let title = format!(">tx-report: {offender}");
+                    let diff = Diff::Reserved(Box::new(
+                        reserved_state.apply_report(tx).map_err(|e| eyre!(e))?,
+                    ));
+                    Ok(SemanticCommit {
+                        title,
+                        body,
+                        diff,
+                        author: UNKNOWN_COMMIT_AUTHOR.to_owned(),
+                        timestamp: tx.timestamp,
+                        signature: None,
+                    })
+                }
+                ExtraAgendaTransaction::Ban(tx) => {
+                    let title = format!(">tx-ban: {}", tx.data.member);
+                    let diff = Diff::Reserved(Box::new(
+                        reserved_state.apply_ban(tx).map_err(|e| eyre!(e))?,
+                    ));
+                    Ok(SemanticCommit {
+                        title,
+                        body,
+                        diff,
+                        author: UNKNOWN_COMMIT_AUTHOR.to_owned(),
+                        timestamp: tx.data.timestamp,
+                        signature: None,
+                    })
                 }
             }
         }
@@ -103,7 +139,7 @@ pub fn to_semantic_commit(
 /// TODO: retrieve author and timestamp from the commit metadata.
 pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, Error> {
     let pattern = Regex::new(
-        r"^>(((agenda)|(block)|(agenda-proof)): (\d+))|((tx-delegate): ((\D+)-(\d+)) to ((\D+)-(\d+)))|((tx-undelegate): ((\D+)-(\d+)))$"
+        r"^>(((agenda)|(block)|(agenda-proof)): (\d+))|((tx-delegate): ((\D+)-(\d+)) to ((\D+)-(\d+)))|((tx-undelegate): ((\D+)-(\d+)))|((tx-report): ((\D+)-(\d+)))|((tx-ban): ((\D+)-(\d+)))$"
     )
     .unwrap();
     let captures = pattern.captures(&semantic_commit.title);
@@ -112,6 +148,8 @@ pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, E
             .get(2)
             .or_else(|| captures.get(8))
             .or_else(|| captures.get(16))
+            .or_else(|| captures.get(21))
+            .or_else(|| captures.get(26))
             .map(|m| m.as_str())
             .ok_or_else(|| {
                 eyre!(
@@ -235,6 +273,41 @@ pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, E
                     _ => Err(eyre!("expected undelegation transaction, got {:?}", tx)),
                 }
             }
+            "tx-report" => {
+                let tx: ExtraAgendaTransaction = serde_spb::from_str(&semantic_commit.body)?;
+                match tx {
+                    // Note that the offender in the title can't be checked here,
+                    // since it requires the reserved state to query the member name.
+                    ExtraAgendaTransaction::Report(tx) => Ok(Commit::ExtraAgendaTransaction(
+                        ExtraAgendaTransaction::Report(tx),
+                    )),
+                    _ => Err(eyre!("expected report transaction, got {:?}", tx)),
+                }
+            }
+            "tx-ban" => {
+                let tx: ExtraAgendaTransaction = serde_spb::from_str(&semantic_commit.body)?;
+                match tx {
+                    ExtraAgendaTransaction::Ban(tx) => {
+                        let member = captures.get(27).map(|m| m.as_str()).ok_or_else(|| {
+                            eyre!(
+                                "failed to parse member from the commit title: {}",
+                                semantic_commit.title
+                            )
+                        })?;
+                        if member != tx.data.member {
+                            return Err(eyre!(
+                                "member mismatch: expected {}, got {}",
+                                tx.data.member,
+                                member
+                            ));
+                        }
+                        Ok(Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Ban(
+                            tx,
+                        )))
+                    }
+                    _ => Err(eyre!("expected ban transaction, got {:?}", tx)),
+                }
+            }
             _ => Err(eyre!("unknown commit type: {}", commit_type)),
         }
     } else {
@@ -257,6 +330,7 @@ pub fn fp_to_semantic_commit(fp: &LastFinalizationProof) -> SemanticCommit {
         diff: Diff::None,
         author: UNKNOWN_COMMIT_AUTHOR.to_owned(),
         timestamp: 0,
+        signature: None,
     }
 }
 
@@ -287,6 +361,104 @@ pub fn fp_from_semantic_commit(
     }
 }
 
+/// Converts a commit to a semantic commit signed by its author.
+pub fn to_signed_semantic_commit(
+    commit: &Commit,
+    reserved_state: ReservedState,
+    private_key: &PrivateKey,
+) -> Result<SemanticCommit, Error> {
+    let mut semantic_commit = to_semantic_commit(commit, reserved_state)?;
+    semantic_commit.signature = Some(TypedSignature::sign(commit, private_key)?);
+    Ok(semantic_commit)
+}
+
+/// Checks that the commit is signed by a member who is allowed to author it.
+///
+/// - An agenda must be signed by its author, who has a governance voting power.
+/// - A block must be signed by its author, who is in the validator set.
+/// - A transaction must be signed by its author.
+///
+/// Other commits carry their own proofs, so they don't need a signature.
+/// An unsigned commit is rejected only if `require_signature` is set.
+pub fn verify_commit_authorship(
+    commit: &Commit,
+    signature: Option<&TypedSignature<Commit>>,
+    reserved_state: &ReservedState,
+    require_signature: bool,
+) -> Result<(), String> {
+    verify_commit_authorship_with(
+        commit,
+        commit.to_hash256(),
+        signature,
+        reserved_state,
+        require_signature,
+        &verify::VerifiedSignatures::default(),
+    )
+}
+
+/// Same as [`verify_commit_authorship`], with the hash of the commit calculated ahead,
+/// skipping the signature if it's in `verified` (see [`verify::SignatureBatch`]).
+pub fn verify_commit_authorship_with(
+    commit: &Commit,
+    commit_hash: Hash256,
+    signature: Option<&TypedSignature<Commit>>,
+    reserved_state: &ReservedState,
+    require_signature: bool,
+    verified: &verify::VerifiedSignatures,
+) -> Result<(), String> {
+    let author = match commit {
+        Commit::Agenda(agenda) => Some(&agenda.author),
+        Commit::Transaction(transaction) => Some(&transaction.author),
+        // The genesis block has no author.
+        Commit::Block(header) if header.author == PublicKey::zero() => return Ok(()),
+        Commit::Block(_) => None,
+        _ => return Ok(()),
+    };
+    let signature = match signature {
+        Some(signature) => signature,
+        None if require_signature => return Err("the commit is not signed".to_owned()),
+        None => return Ok(()),
+    };
+    verified
+        .verify(signature, commit_hash)
+        .map_err(|e| format!("invalid commit signature: {e}"))?;
+    let signer = signature.signer();
+    match commit {
+        Commit::Block(header) => {
+            if &header.author != signer {
+                return Err(format!("the block is signed by {signer}, not its author"));
+            }
+            if !reserved_state
+                .get_validator_set()?
+                .iter()
+                .any(|(public_key, _)| public_key == signer)
+            {
+                return Err(format!("the block author {signer} is not a validator"));
+            }
+        }
+        _ => {
+            let member = reserved_state
+                .members
+                .iter()
+                .find(|member| &member.public_key == signer)
+                .ok_or_else(|| format!("the commit is signed by a non-member {signer}"))?;
+            if Some(&member.name) != author {
+                return Err(format!(
+                    "the commit is signed by {}, not its author",
+                    member.name
+                ));
+            }
+            if matches!(commit, Commit::Agenda(_)) && member.governance_voting_power == 0 {
+                return Err(format!(
+                    "the agenda author {} has no governance voting power",
+                    member.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,6 +489,8 @@ mod tests {
             author: "doesn't matter".to_owned(),
             timestamp: 123,
             transactions_hash: Hash256::hash("hello"),
+            description: None,
+            deadline: None,
         });
         assert_eq!(
             agenda,
@@ -332,7 +506,10 @@ mod tests {
             author: PublicKey::zero(),
             prev_block_finalization_proof: FinalizationProof {
                 round: 0,
-                signatures: vec![TypedSignature::new(Signature::zero(), PublicKey::zero())],
+                signatures: vec![PrecommitSignature(
+                    TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                    0,
+                )],
             },
             previous_hash: Hash256::hash("hello1"),
             timestamp: 0,
@@ -373,6 +550,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: reserved_state.genesis_info.chain_name.clone(),
+            nonce: 1,
         };
         let delegation_transaction =
             Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Delegate(TxDelegate {
@@ -398,6 +576,7 @@ mod tests {
             block_height: 0,
             timestamp: 0,
             chain_name: reserved_state.genesis_info.chain_name.clone(),
+            nonce: 2,
         };
         let undelegation_transaction =
             Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Undelegate(TxUndelegate {
@@ -413,6 +592,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn format_extra_agenda_transaction_commit3() {
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let precommit = |timestamp: Timestamp| {
+            let mut header = reserved_state.genesis_info.header.clone();
+            header.height = 1;
+            header.timestamp = timestamp;
+            SignedPrecommit {
+                signature: TypedSignature::sign(
+                    &FinalizationSignTarget {
+                        block_hash: header.to_hash256(),
+                        round: 0,
+                        timestamp,
+                    },
+                    &keys[1].1,
+                )
+                .unwrap(),
+                header,
+                round: 0,
+                timestamp,
+            }
+        };
+        let report_transaction =
+            Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Report(TxReport {
+                evidence: Box::new(DoubleSignEvidence {
+                    first: precommit(1),
+                    second: precommit(2),
+                }),
+                timestamp: 0,
+            }));
+        let semantic_commit =
+            to_semantic_commit(&report_transaction, reserved_state.clone()).unwrap();
+        assert_eq!(semantic_commit.title, ">tx-report: member-0001");
+        assert_eq!(
+            report_transaction,
+            from_semantic_commit(semantic_commit).unwrap()
+        );
+    }
+
+    #[test]
+    fn format_extra_agenda_transaction_commit4() {
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let ban_transaction_data = BanTransactionData {
+            member: reserved_state.members[3].name.clone(),
+            until_height: 10,
+            block_height: 1,
+            timestamp: 0,
+            chain_name: reserved_state.genesis_info.chain_name.clone(),
+        };
+        let ban_transaction = Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Ban(TxBan {
+            proof: keys[0..3]
+                .iter()
+                .map(|(_, private_key)| {
+                    TypedSignature::sign(&ban_transaction_data, private_key).unwrap()
+                })
+                .collect(),
+            data: ban_transaction_data,
+        }));
+        let semantic_commit = to_semantic_commit(&ban_transaction, reserved_state.clone()).unwrap();
+        assert_eq!(semantic_commit.title, ">tx-ban: member-0003");
+        assert_eq!(
+            ban_transaction,
+            from_semantic_commit(semantic_commit).unwrap()
+        );
+    }
+
     #[test]
     fn format_fp() {
         let fp = LastFinalizationProof {
@@ -420,8 +665,14 @@ mod tests {
             proof: FinalizationProof {
                 round: 0,
                 signatures: vec![
-                    TypedSignature::new(Signature::zero(), PublicKey::zero()),
-                    TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                    PrecommitSignature(
+                        TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                        0,
+                    ),
+                    PrecommitSignature(
+                        TypedSignature::new(Signature::zero(), PublicKey::zero()),
+                        0,
+                    ),
                 ],
             },
         };
@@ -430,4 +681,34 @@ mod tests {
             fp_from_semantic_commit(fp_to_semantic_commit(&fp)).unwrap()
         );
     }
+
+    #[test]
+    fn commit_authorship() {
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let agenda = Commit::Agenda(Agenda {
+            height: 1,
+            author: "member-0000".to_owned(),
+            timestamp: 0,
+            transactions_hash: Agenda::calculate_transactions_hash(&[]),
+            description: None,
+            deadline: None,
+        });
+        let signed_by = |i: usize| TypedSignature::sign(&agenda, &keys[i].1).unwrap();
+
+        verify_commit_authorship(&agenda, Some(&signed_by(0)), &reserved_state, true).unwrap();
+        // Signed by another member
+        verify_commit_authorship(&agenda, Some(&signed_by(1)), &reserved_state, true).unwrap_err();
+        // Unsigned
+        verify_commit_authorship(&agenda, None, &reserved_state, false).unwrap();
+        verify_commit_authorship(&agenda, None, &reserved_state, true).unwrap_err();
+        // Without governance voting power
+        let mut reserved_state_ = reserved_state.clone();
+        reserved_state_.members[0].governance_voting_power = 0;
+        verify_commit_authorship(&agenda, Some(&signed_by(0)), &reserved_state_, true).unwrap_err();
+
+        let semantic_commit =
+            to_signed_semantic_commit(&agenda, reserved_state.clone(), &keys[0].1).unwrap();
+        assert_eq!(semantic_commit.signature, Some(signed_by(0)));
+        assert_eq!(from_semantic_commit(semantic_commit).unwrap(), agenda);
+    }
 }
@@ -0,0 +1,217 @@
+//! A cache of the finalized block headers, indexed by the height and the commit hash.
+//!
+//! Locating a finalized block walks the history of the `finalized` branch, reading every commit.
+//! The cache keeps the headers it has seen, a contiguous range of heights
+//! up to the last finalized one, so that the repeated lookups don't read the repository again.
+//! It follows the `finalized` branch on every lookup: the new blocks are added
+//! as the branch advances (on finalization), and the whole cache is invalidated
+//! if the branch moves otherwise (e.g. to another chain).
+use super::*;
+use simperby_network::{Storage, StorageImpl};
+use std::collections::HashMap;
+
+const HEADER_FILE_PREFIX: &str = "header-";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct StoredHeader {
+    commit_hash: CommitHash,
+    header: BlockHeader,
+}
+
+#[derive(Default)]
+pub struct HeaderCache {
+    /// The tip of the `finalized` branch that the cache is consistent with.
+    tip: Option<CommitHash>,
+    headers: HashMap<BlockHeight, StoredHeader>,
+    heights: HashMap<CommitHash, BlockHeight>,
+    /// The lowest and the highest heights in the cache.
+    range: Option<(BlockHeight, BlockHeight)>,
+    /// Where the headers are persisted, if set.
+    storage: Option<StorageImpl>,
+}
+
+impl HeaderCache {
+    /// Creates a cache that persists the headers in the storage, loading those already stored.
+    ///
+    /// The loaded headers are checked against the repository on the first lookup.
+    pub async fn load(storage: StorageImpl) -> Result<Self, Error> {
+        let mut this = Self::default();
+        for file_name in storage.list_files().await? {
+            if !file_name.starts_with(HEADER_FILE_PREFIX) {
+                continue;
+            }
+            let stored: StoredHeader = serde_spb::from_str(&storage.read_file(&file_name).await?)?;
+            this.insert_in_memory(stored);
+        }
+        // Only the contiguous range down from the highest one is usable.
+        if let Some((_, highest)) = this.range {
+            let mut lowest = highest;
+            while lowest > 0 && this.headers.contains_key(&(lowest - 1)) {
+                lowest -= 1;
+            }
+            this.headers.retain(|height, _| *height >= lowest);
+            this.heights.retain(|_, height| *height >= lowest);
+            this.range = Some((lowest, highest));
+        }
+        this.storage = Some(storage);
+        Ok(this)
+    }
+
+    /// Returns whether the headers are persisted.
+    pub fn is_persistent(&self) -> bool {
+        self.storage.is_some()
+    }
+
+    /// Returns the last finalized block.
+    pub async fn last_finalized(
+        &mut self,
+        raw: &RawRepository,
+    ) -> Result<(CommitHash, BlockHeader), Error> {
+        self.refresh(raw).await?;
+        let (_, highest) = self.range.expect("refreshed");
+        let stored = &self.headers[&highest];
+        Ok((stored.commit_hash, stored.header.clone()))
+    }
+
+    /// Returns the finalized block at the given height, or `None` if there is no such block
+    /// or if it is below the available history.
+    pub async fn get(
+        &mut self,
+        raw: &RawRepository,
+        height: BlockHeight,
+    ) -> Result<Option<(CommitHash, BlockHeader)>, Error> {
+        self.refresh(raw).await?;
+        let (lowest, highest) = self.range.expect("refreshed");
+        if height > highest {
+            return Ok(None);
+        }
+        if height < lowest {
+            self.extend_down(raw, height).await?;
+        }
+        Ok(self
+            .headers
+            .get(&height)
+            .map(|stored| (stored.commit_hash, stored.header.clone())))
+    }
+
+    /// Returns the header of the given commit if it is a finalized block.
+    pub async fn get_by_commit_hash(
+        &mut self,
+        raw: &RawRepository,
+        commit_hash: CommitHash,
+    ) -> Result<Option<BlockHeader>, Error> {
+        self.refresh(raw).await?;
+        if let Some(height) = self.heights.get(&commit_hash) {
+            return Ok(Some(self.headers[height].header.clone()));
+        }
+        let Ok(Commit::Block(header)) = read_commit(raw, commit_hash).await else {
+            return Ok(None);
+        };
+        Ok(match self.get(raw, header.height).await? {
+            Some((finalized, _)) if finalized == commit_hash => Some(header),
+            _ => None,
+        })
+    }
+
+    /// Drops all the cached headers, including the stored ones.
+    pub async fn invalidate(&mut self) -> Result<(), Error> {
+        self.tip = None;
+        self.headers.clear();
+        self.heights.clear();
+        self.range = None;
+        if let Some(storage) = &mut self.storage {
+            storage.remove_all_files().await?;
+        }
+        Ok(())
+    }
+
+    /// Follows the `finalized` branch, which costs a single reference lookup if it hasn't moved.
+    async fn refresh(&mut self, raw: &RawRepository) -> Result<(), Error> {
+        let tip = raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .map_err(|e| match e {
+                raw::Error::NotFound(_) => eyre!(IntegrityError::new(
+                    "cannot locate `finalized` branch".to_string()
+                )),
+                _ => eyre!(e),
+            })?;
+        if self.tip == Some(tip) {
+            return Ok(());
+        }
+        let highest = self
+            .range
+            .map(|(_, highest)| self.headers[&highest].commit_hash);
+        // Fails unless the branch has advanced from the highest cached block.
+        let advanced = match highest {
+            Some(highest) => read_commits(raw, highest, tip).await.ok(),
+            None => None,
+        };
+        match advanced {
+            Some(commits) => {
+                for (commit, commit_hash) in commits {
+                    if let Commit::Block(header) = commit {
+                        self.insert(commit_hash, header).await?;
+                    }
+                }
+            }
+            None => {
+                self.invalidate().await?;
+                let header = read_last_finalized_block_header(raw).await?;
+                self.insert(tip, header).await?;
+            }
+        }
+        self.tip = Some(tip);
+        Ok(())
+    }
+
+    /// Reads the finalized blocks below the cached range, down to the given height.
+    async fn extend_down(&mut self, raw: &RawRepository, height: BlockHeight) -> Result<(), Error> {
+        let (lowest, _) = self.range.expect("refreshed");
+        let lowest_commit_hash = self.headers[&lowest].commit_hash;
+        for commit_hash in raw.list_ancestors(lowest_commit_hash, None).await? {
+            let Ok(commit) = read_commit(raw, commit_hash).await else {
+                // The commit is at the shallow boundary.
+                return Ok(());
+            };
+            if let Commit::Block(header) = commit {
+                let block_height = header.height;
+                self.insert(commit_hash, header).await?;
+                if block_height <= height {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert(&mut self, commit_hash: CommitHash, header: BlockHeader) -> Result<(), Error> {
+        let stored = StoredHeader {
+            commit_hash,
+            header,
+        };
+        if let Some(storage) = &mut self.storage {
+            storage
+                .add_or_overwrite_file(
+                    &format!("{HEADER_FILE_PREFIX}{}.json", stored.header.height),
+                    serde_spb::to_string(&stored).unwrap(),
+                )
+                .await?;
+        }
+        self.insert_in_memory(stored);
+        Ok(())
+    }
+
+    fn insert_in_memory(&mut self, stored: StoredHeader) {
+        let height = stored.header.height;
+        self.range = Some(match self.range {
+            Some((lowest, highest)) => (lowest.min(height), highest.max(height)),
+            None => (height, height),
+        });
+        if let Some(replaced) = self.headers.get(&height) {
+            self.heights.remove(&replaced.commit_hash);
+        }
+        self.heights.insert(stored.commit_hash, height);
+        self.headers.insert(height, stored);
+    }
+}
@@ -6,6 +6,8 @@ pub async fn approve(
     agenda_hash: &Hash256,
     proof: Vec<TypedSignature<Agenda>>,
     timestamp: Timestamp,
+    blob_store: Option<&BlobStore>,
+    vetoes: &[SignedAgendaVeto],
 ) -> Result<CommitHash, Error> {
     // Check if the agenda branch is rebased on top of the `finalized` branch.
     let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
@@ -44,12 +46,52 @@ pub async fn approve(
             .apply_commit(commit)
             .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
     }
+    // Check that the blobs referenced by the transactions are available.
+    let mut blob_references = Vec::new();
+    for (commit, hash) in commits.iter() {
+        if let Commit::Transaction(transaction) = commit {
+            blob_references.extend(
+                transaction
+                    .blob_references()
+                    .map_err(|e| eyre!("invalid transaction {}: {}", hash, e))?,
+            );
+        }
+    }
+    if !blob_references.is_empty() {
+        blob_store
+            .ok_or_else(|| eyre!("the agenda references blobs but there is no blob store"))?
+            .check_available(&blob_references)
+            .await?;
+    }
     // Verify agenda with agenda proof
-    let agenda_commit = commits.iter().map(|(commit, _)| commit).last().unwrap();
+    let agenda_commit = commits
+        .iter()
+        .map(|(commit, _)| commit)
+        .next_back()
+        .unwrap();
     let agenda = match agenda_commit {
         Commit::Agenda(agenda) => agenda,
         _ => return Err(eyre::eyre!("not an agenda commit")),
     };
+    // Check that the voting deadline hasn't passed, which the CSV would reject.
+    if !agenda.is_open_at(timestamp) {
+        return Err(eyre!(
+            "the voting deadline of agenda {} has passed",
+            agenda.to_hash256()
+        ));
+    }
+    // Check that no veto holder blocks the agenda. It's only for this node to refuse the approval,
+    // as the vetoes are not on-chain for the CSV to check.
+    let vetoers = verifier
+        .get_reserved_state()
+        .vetoers(agenda.to_hash256(), vetoes, timestamp);
+    if !vetoers.is_empty() {
+        return Err(eyre!(
+            "agenda {} is vetoed by {}",
+            agenda.to_hash256(),
+            vetoers.join(", ")
+        ));
+    }
     // Delete past `a-(trimmed agenda hash)` branch and create new `a-(trimmed agenda proof hash)` branch
     raw.delete_branch(agenda_branch_name.clone()).await?;
     // Create agenda proof commit
@@ -88,7 +130,83 @@ pub async fn approve(
 pub async fn create_agenda(
     raw: &mut RawRepository,
     author: MemberName,
+    description: Option<AgendaDescription>,
+    deadline: Option<VotingDeadline>,
+    signing_key: Option<&PrivateKey>,
+    finalized_transactions: &mut TransactionIndex,
+    transaction_pool: Option<&mut TransactionPool>,
 ) -> Result<(Agenda, CommitHash), Error> {
+    let (agenda, semantic_commit) = prepare_agenda(
+        raw,
+        author,
+        description,
+        deadline,
+        signing_key,
+        finalized_transactions,
+        transaction_pool,
+    )
+    .await?;
+    raw.checkout_clean().await?;
+    raw.checkout(WORK_BRANCH_NAME.into())
+        .await
+        .map_err(|e| match e {
+            raw::Error::NotFound(_) => {
+                eyre!(IntegrityError::new(format!(
+                    "failed to checkout to the work branch: {e}"
+                )))
+            }
+            _ => eyre!(e),
+        })?;
+    let result = raw.create_semantic_commit(semantic_commit).await?;
+    let mut agenda_branch_name = Commit::Agenda(agenda.clone()).to_hash256().to_string();
+    agenda_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
+    let agenda_branch_name = format!("a-{agenda_branch_name}");
+    raw.create_branch(agenda_branch_name, result).await?;
+    Ok((agenda, result))
+}
+
+/// Returns the agenda that [`create_agenda`] would create and its commit,
+/// without writing to the repository.
+///
+/// The pending transactions of the pool are not pulled,
+/// since they can be verified only once committed on the `work` branch.
+pub async fn preview_agenda(
+    raw: &mut RawRepository,
+    author: MemberName,
+    description: Option<AgendaDescription>,
+    deadline: Option<VotingDeadline>,
+    signing_key: Option<&PrivateKey>,
+    finalized_transactions: &mut TransactionIndex,
+) -> Result<(Agenda, SemanticCommit), Error> {
+    prepare_agenda(
+        raw,
+        author,
+        description,
+        deadline,
+        signing_key,
+        finalized_transactions,
+        None,
+    )
+    .await
+}
+
+/// Verifies the `work` branch and builds the agenda commit on top of it.
+///
+/// It writes to the repository only to pull the pending transactions of the pool.
+async fn prepare_agenda(
+    raw: &mut RawRepository,
+    author: MemberName,
+    description: Option<AgendaDescription>,
+    deadline: Option<VotingDeadline>,
+    signing_key: Option<&PrivateKey>,
+    finalized_transactions: &mut TransactionIndex,
+    transaction_pool: Option<&mut TransactionPool>,
+) -> Result<(Agenda, SemanticCommit), Error> {
+    if let Some(description) = &description {
+        description
+            .check()
+            .map_err(|e| eyre!("invalid agenda description: {e}"))?;
+    }
     let last_header = read_last_finalized_block_header(raw).await?;
     let work_commit = raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
     let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
@@ -105,17 +223,46 @@ pub async fn create_agenda(
     let reserved_state = read_last_finalized_reserved_state(raw).await?;
     let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state.clone())
         .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
-    let commits = read_commits(raw, last_header_commit, work_commit).await?;
+    let mut commits = read_commits(raw, last_header_commit, work_commit).await?;
     for (commit, hash) in commits.iter() {
+        if let Commit::Transaction(_) = commit {
+            check_transaction_spec(
+                raw,
+                verifier.get_reserved_state().transaction_spec.as_ref(),
+                *hash,
+            )
+            .await?
+            .map_err(|e| transaction_spec_error(e, hash))?;
+        }
         verifier
             .apply_commit(commit)
-            .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
+            .map_err(|e| verification_error(e, hash))?;
+    }
+    if let Some(pool) = transaction_pool {
+        let pulled = pull_pending_transactions(
+            raw,
+            pool,
+            &reserved_state,
+            &mut verifier,
+            &commits,
+            finalized_transactions,
+        )
+        .await?;
+        commits.extend(pulled);
     }
 
     // Create agenda commit
     let mut transactions = Vec::new();
-    for (commit, _) in commits {
+    for (commit, hash) in commits {
         if let Commit::Transaction(t) = commit {
+            if let Some(height) = finalized_transactions
+                .finalized_height(raw, t.to_hash256())
+                .await?
+            {
+                return Err(eyre!(
+                    "transaction {hash} has already been finalized at height {height}"
+                ));
+            }
             transactions.push(t.clone());
         }
     }
@@ -124,14 +271,116 @@ pub async fn create_agenda(
         timestamp: get_timestamp(),
         transactions_hash: Agenda::calculate_transactions_hash(&transactions),
         height: last_header.height + 1,
+        description,
+        deadline,
     };
+    agenda
+        .check_deadline()
+        .map_err(|e| eyre!("invalid agenda deadline: {e}"))?;
     let agenda_commit = Commit::Agenda(agenda.clone());
     verifier.apply_commit(&agenda_commit).map_err(|_| {
         eyre!("agenda commit cannot be created on top of the current commit sequence")
     })?;
 
-    let semantic_commit = to_semantic_commit(&agenda_commit, reserved_state)?;
+    let semantic_commit = to_authored_semantic_commit(&agenda_commit, reserved_state, signing_key)?;
+    Ok((agenda, semantic_commit))
+}
+
+/// Appends the pending transactions of the pool to the `work` branch, returning the new commits.
+///
+/// Those that don't apply or don't verify on top of the branch are skipped,
+/// and those already finalized are removed from the pool.
+async fn pull_pending_transactions(
+    raw: &mut RawRepository,
+    pool: &mut TransactionPool,
+    reserved_state: &ReservedState,
+    verifier: &mut CommitSequenceVerifier,
+    commits: &[(Commit, CommitHash)],
+    finalized_transactions: &mut TransactionIndex,
+) -> Result<Vec<(Commit, CommitHash)>, Error> {
+    let mut included = commits
+        .iter()
+        .filter_map(|(commit, _)| match commit {
+            Commit::Transaction(transaction) => Some(transaction.to_hash256()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut pulled = Vec::new();
+    raw.checkout_clean().await?;
+    raw.checkout(WORK_BRANCH_NAME.into()).await?;
+    for pending in pool.select(reserved_state).await? {
+        let pending_hash = pending.to_hash256();
+        let parent = raw.get_head().await?;
+        let commit_hash = match raw.create_commit(pending.commit).await {
+            Ok(commit_hash) => commit_hash,
+            Err(e) => {
+                log::debug!("pending transaction {pending_hash} doesn't apply: {e}");
+                raw.checkout_clean().await?;
+                continue;
+            }
+        };
+        let result = match read_commit(raw, commit_hash).await {
+            Ok(Commit::Transaction(transaction)) => {
+                let transaction_hash = transaction.to_hash256();
+                if included.contains(&transaction_hash) {
+                    Err("it is already on the work branch".to_owned())
+                } else if let Some(height) = finalized_transactions
+                    .finalized_height(raw, transaction_hash)
+                    .await?
+                {
+                    pool.remove(pending_hash).await?;
+                    Err(format!("it has already been finalized at height {height}"))
+                } else if let Err(e) = check_transaction_spec(
+                    raw,
+                    verifier.get_reserved_state().transaction_spec.as_ref(),
+                    commit_hash,
+                )
+                .await?
+                {
+                    Err(format!("it violates the transaction spec: {e}"))
+                } else {
+                    let commit = Commit::Transaction(transaction);
+                    // Not to leave the verifier half-applied on failure.
+                    let mut next = verifier.clone();
+                    match next.apply_commit(&commit) {
+                        Ok(()) => {
+                            *verifier = next;
+                            included.insert(transaction_hash);
+                            Ok(commit)
+                        }
+                        Err(e) => Err(e.to_string()),
+                    }
+                }
+            }
+            Ok(_) => Err("it is not a transaction".to_owned()),
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(commit) => pulled.push((commit, commit_hash)),
+            Err(e) => {
+                log::debug!("pending transaction {pending_hash} is not pulled: {e}");
+                raw.move_branch(WORK_BRANCH_NAME.into(), parent).await?;
+                raw.checkout_clean().await?;
+            }
+        }
+    }
+    Ok(pulled)
+}
+
+/// Keeps the exceeded limits typed, so that the proposer can tell them from the invalid commits.
+fn verification_error(e: verify::Error, hash: &CommitHash) -> Error {
+    match e {
+        verify::Error::LimitExceeded { .. } => eyre!(e),
+        _ => eyre!("verification error on commit {}: {}", hash, e),
+    }
+}
 
+pub async fn create_block(
+    raw: &mut RawRepository,
+    author: PublicKey,
+    signing_key: Option<&PrivateKey>,
+) -> Result<(BlockHeader, CommitHash), Error> {
+    let (block_header, semantic_commit) = preview_block(raw, author, signing_key).await?;
     raw.checkout_clean().await?;
     raw.checkout(WORK_BRANCH_NAME.into())
         .await
@@ -144,17 +393,20 @@ pub async fn create_agenda(
             _ => eyre!(e),
         })?;
     let result = raw.create_semantic_commit(semantic_commit).await?;
-    let mut agenda_branch_name = agenda_commit.to_hash256().to_string();
-    agenda_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
-    let agenda_branch_name = format!("a-{agenda_branch_name}");
-    raw.create_branch(agenda_branch_name, result).await?;
-    Ok((agenda, result))
+    let mut block_branch_name = Commit::Block(block_header.clone()).to_hash256().to_string();
+    block_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
+    let block_branch_name = format!("b-{block_branch_name}");
+    raw.create_branch(block_branch_name, result).await?;
+    Ok((block_header, result))
 }
 
-pub async fn create_block(
-    raw: &mut RawRepository,
+/// Returns the block that [`create_block`] would create and its commit,
+/// without writing to the repository.
+pub async fn preview_block(
+    raw: &RawRepository,
     author: PublicKey,
-) -> Result<(BlockHeader, CommitHash), Error> {
+    signing_key: Option<&PrivateKey>,
+) -> Result<(BlockHeader, SemanticCommit), Error> {
     let work_commit = raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
     let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
 
@@ -170,29 +422,23 @@ pub async fn create_block(
     // Check the validity of the commit sequence
     let commits = read_commits(raw, last_header_commit, work_commit).await?;
     let last_header = read_last_finalized_block_header(raw).await?;
-    raw.checkout(WORK_BRANCH_NAME.into())
-        .await
-        .map_err(|e| match e {
-            raw::Error::NotFound(_) => {
-                eyre!(IntegrityError::new(format!(
-                    "failed to checkout to the work branch: {e}"
-                )))
-            }
-            _ => eyre!(e),
-        })?;
     let reserved_state = read_last_finalized_reserved_state(raw).await?;
     let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state.clone())
         .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
     for (commit, hash) in commits.iter() {
         verifier
             .apply_commit(commit)
-            .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
+            .map_err(|e| verification_error(e, hash))?;
     }
 
     // Verify `finalization_proof`
     let fp_commit_hash = raw.locate_branch(FP_BRANCH_NAME.into()).await?;
     let fp_semantic_commit = raw.read_semantic_commit(fp_commit_hash).await?;
     let finalization_proof = fp_from_semantic_commit(fp_semantic_commit).unwrap().proof;
+    // The block timestamp is not from the local clock but from the precommits of the last block.
+    let timestamp = finalization_proof
+        .bft_time(&last_header.validator_set)
+        .ok_or_else(|| eyre!("the finalization proof has no valid timestamp"))?;
 
     // Create block commit
     let block_header = BlockHeader {
@@ -200,14 +446,17 @@ pub async fn create_block(
         prev_block_finalization_proof: finalization_proof,
         previous_hash: last_header.to_hash256(),
         height: last_header.height + 1,
-        timestamp: get_timestamp(),
+        timestamp,
         commit_merkle_root: BlockHeader::calculate_commit_merkle_root(
             &commits
                 .iter()
                 .map(|(commit, _)| commit.clone())
                 .collect::<Vec<_>>(),
         ),
-        repository_merkle_root: Hash256::zero(), // TODO
+        // The block commit has no diff, so it has the files of the `work` branch.
+        repository_merkle_root: calculate_repository_merkle_root(
+            &raw.read_files_at_commit(work_commit).await?,
+        ),
         validator_set: reserved_state.get_validator_set().unwrap(),
         version: SIMPERBY_CORE_PROTOCOL_VERSION.to_string(),
     };
@@ -216,31 +465,27 @@ pub async fn create_block(
         eyre!("block commit cannot be created on top of the current commit sequence")
     })?;
 
-    let semantic_commit = to_semantic_commit(&block_commit, reserved_state)?;
-
-    raw.checkout_clean().await?;
-    raw.checkout(WORK_BRANCH_NAME.into())
-        .await
-        .map_err(|e| match e {
-            raw::Error::NotFound(_) => {
-                eyre!(IntegrityError::new(format!(
-                    "failed to checkout to the work branch: {e}"
-                )))
-            }
-            _ => eyre!(e),
-        })?;
-    let result = raw.create_semantic_commit(semantic_commit).await?;
-    let mut block_branch_name = block_commit.to_hash256().to_string();
-    block_branch_name.truncate(BRANCH_NAME_HASH_DIGITS);
-    let block_branch_name = format!("b-{block_branch_name}");
-    raw.create_branch(block_branch_name, result).await?;
-    Ok((block_header, result))
+    let semantic_commit = to_authored_semantic_commit(&block_commit, reserved_state, signing_key)?;
+    Ok((block_header, semantic_commit))
 }
 
 pub async fn create_extra_agenda_transaction(
     raw: &mut RawRepository,
     transaction: &ExtraAgendaTransaction,
 ) -> Result<CommitHash, Error> {
+    let semantic_commit = preview_extra_agenda_transaction(raw, transaction).await?;
+    raw.checkout_clean().await?;
+    raw.checkout(WORK_BRANCH_NAME.into()).await?;
+    let result = raw.create_semantic_commit(semantic_commit).await?;
+    Ok(result)
+}
+
+/// Returns the commit that [`create_extra_agenda_transaction`] would create,
+/// without writing to the repository.
+pub async fn preview_extra_agenda_transaction(
+    raw: &RawRepository,
+    transaction: &ExtraAgendaTransaction,
+) -> Result<SemanticCommit, Error> {
     let work_commit = raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
     let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
     let reserved_state = read_last_finalized_reserved_state(raw).await?;
@@ -273,6 +518,233 @@ pub async fn create_extra_agenda_transaction(
         })?;
 
     let semantic_commit = to_semantic_commit(&extra_agenda_tx_commit, reserved_state)?;
+    Ok(semantic_commit)
+}
+
+/// Creates a transaction commit that adds the member of the join request,
+/// on top of the `work` branch.
+pub async fn create_join_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    request: &JoinRequest,
+    activation_height: Option<BlockHeight>,
+    signing_key: Option<&PrivateKey>,
+) -> Result<CommitHash, Error> {
+    create_reserved_transaction(
+        raw,
+        author,
+        format!("join: {}", request.data.name),
+        serde_spb::to_string(request)?,
+        |reserved_state| {
+            reserved_state
+                .apply_join_request(request)
+                .map_err(|e| eyre!("invalid join request: {}", e))
+        },
+        activation_height,
+        signing_key,
+    )
+    .await
+}
+
+/// Creates a transaction commit that takes away the consensus voting power
+/// of the reported offline validator, on top of the `work` branch.
+pub async fn create_offline_report_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    report: &OfflineReport,
+    activation_height: Option<BlockHeight>,
+    signing_key: Option<&PrivateKey>,
+) -> Result<CommitHash, Error> {
+    create_reserved_transaction(
+        raw,
+        author,
+        format!("report offline: {}", report.offender),
+        serde_spb::to_string(report)?,
+        |reserved_state| {
+            reserved_state
+                .apply_offline_report(report)
+                .map_err(|e| eyre!("invalid offline report: {}", e))
+        },
+        activation_height,
+        signing_key,
+    )
+    .await
+}
+
+/// Creates a transaction commit that replaces the metadata of a member,
+/// on top of the `work` branch.
+pub async fn create_member_metadata_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    update: &TxUpdateMemberMetadata,
+    activation_height: Option<BlockHeight>,
+    signing_key: Option<&PrivateKey>,
+) -> Result<CommitHash, Error> {
+    create_reserved_transaction(
+        raw,
+        author,
+        format!("update metadata: {}", update.member),
+        serde_spb::to_string(update)?,
+        |reserved_state| {
+            reserved_state
+                .apply_member_metadata_update(update)
+                .map_err(|e| eyre!("invalid metadata update: {}", e))
+        },
+        activation_height,
+        signing_key,
+    )
+    .await
+}
+
+/// Creates a transaction commit that replaces the consensus leader order
+/// with the eligible leaders ordered by the strategy, on top of the `work` branch.
+pub async fn create_leader_order_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    strategy: LeaderOrderStrategy,
+    activation_height: Option<BlockHeight>,
+    signing_key: Option<&PrivateKey>,
+) -> Result<CommitHash, Error> {
+    create_reserved_transaction(
+        raw,
+        author,
+        format!("rebalance leader order: {strategy}"),
+        serde_spb::to_string(&strategy)?,
+        |reserved_state| {
+            let next = reserved_state
+                .rebalance_leader_order(strategy)
+                .map_err(|e| eyre!("failed to rebalance the leader order: {}", e))?;
+            if next.consensus_leader_order == reserved_state.consensus_leader_order {
+                return Err(eyre!("the leader order is already {strategy}"));
+            }
+            Ok(next)
+        },
+        activation_height,
+        signing_key,
+    )
+    .await
+}
+
+/// Creates a transaction commit from the patch of a contributor, on top of the `work` branch.
+///
+/// The commit is authored by the given member, recording the contributor in the body.
+pub async fn create_patch_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    bundle: &PatchBundle,
+) -> Result<CommitHash, Error> {
+    bundle.verify()?;
+    let work_commit = raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+    let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+
+    // Check if the `work` branch is rebased on top of the `finalized` branch.
+    if raw.find_merge_base(last_header_commit, work_commit).await? != last_header_commit {
+        return Err(eyre!(
+            "branch {} should be rebased on {}",
+            WORK_BRANCH_NAME,
+            FINALIZED_BRANCH_NAME
+        ));
+    }
+
+    // Check the validity of the commit sequence
+    let commits = read_commits(raw, last_header_commit, work_commit).await?;
+    let last_header = read_last_finalized_block_header(raw).await?;
+    let reserved_state = read_last_finalized_reserved_state(raw).await?;
+    let mut verifier = CommitSequenceVerifier::new(last_header, reserved_state)
+        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
+    for (commit, hash) in commits.iter() {
+        verifier
+            .apply_commit(commit)
+            .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
+    }
+
+    raw.checkout_clean().await?;
+    raw.checkout(WORK_BRANCH_NAME.into()).await?;
+    let commit_hash = match raw.create_commit(bundle.to_raw_commit(author)).await {
+        Ok(commit_hash) => commit_hash,
+        Err(e) => {
+            raw.checkout_clean().await?;
+            return Err(eyre!("the patch doesn't apply on the work branch: {e}"));
+        }
+    };
+    let result = match read_commit(raw, commit_hash).await {
+        Ok(commit @ Commit::Transaction(_)) => verifier
+            .apply_commit(&commit)
+            .map_err(|e| eyre!("transaction cannot be created: {}", e)),
+        Ok(_) => Err(eyre!("the patch doesn't make a transaction")),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        raw.move_branch(WORK_BRANCH_NAME.into(), work_commit)
+            .await?;
+        raw.checkout_clean().await?;
+        return Err(e);
+    }
+    Ok(commit_hash)
+}
+
+/// Creates a transaction commit that changes the reserved state on top of the `work` branch,
+/// applying `apply` to the reserved state at the tip.
+/// Creates a reserved-diff transaction commit of the state that `apply` makes,
+/// which is scheduled to take effect at `activation_height` if given.
+async fn create_reserved_transaction(
+    raw: &mut RawRepository,
+    author: MemberName,
+    head: String,
+    body: String,
+    apply: impl FnOnce(&ReservedState) -> Result<ReservedState, Error>,
+    activation_height: Option<BlockHeight>,
+    signing_key: Option<&PrivateKey>,
+) -> Result<CommitHash, Error> {
+    let work_commit = raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+    let last_header_commit = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+    let reserved_state = read_last_finalized_reserved_state(raw).await?;
+
+    // Check if the `work` branch is rebased on top of the `finalized` branch.
+    if raw.find_merge_base(last_header_commit, work_commit).await? != last_header_commit {
+        return Err(eyre!(
+            "branch {} should be rebased on {}",
+            WORK_BRANCH_NAME,
+            FINALIZED_BRANCH_NAME
+        ));
+    }
+
+    // Check the validity of the commit sequence
+    let commits = read_commits(raw, last_header_commit, work_commit).await?;
+    let last_header = read_last_finalized_block_header(raw).await?;
+    let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state)
+        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
+    for (commit, hash) in commits.iter() {
+        verifier
+            .apply_commit(commit)
+            .map_err(|e| eyre!("verification error on commit {}: {}", hash, e))?;
+    }
+
+    let reserved_state = verifier.get_reserved_state().clone();
+    let mut next_reserved_state = apply(&reserved_state)?;
+    if let Some(activation_height) = activation_height {
+        next_reserved_state = reserved_state
+            .schedule(&next_reserved_state, activation_height)
+            .map_err(|e| eyre!("failed to schedule the change: {e}"))?;
+    }
+    let (header, _) = verifier
+        .get_block_headers()
+        .pop()
+        .expect("there is always the start header");
+    let next_reserved_state = reserved_state.bind(next_reserved_state, header.height + 1);
+    let transaction_commit = Commit::Transaction(Transaction {
+        author,
+        timestamp: get_timestamp(),
+        head,
+        body,
+        diff: Diff::Reserved(Box::new(next_reserved_state)),
+    });
+    verifier
+        .apply_commit(&transaction_commit)
+        .map_err(|e| eyre!("transaction cannot be created: {}", e))?;
+
+    let semantic_commit =
+        to_authored_semantic_commit(&transaction_commit, reserved_state, signing_key)?;
 
     raw.checkout_clean().await?;
     raw.checkout(WORK_BRANCH_NAME.into()).await?;
@@ -284,6 +756,8 @@ pub async fn finalize(
     raw: &mut RawRepository,
     block_commit_hash: CommitHash,
     proof: FinalizationProof,
+    config: &Config,
+    transactions: &mut TransactionIndex,
 ) -> Result<CommitHash, Error> {
     let csv = read_and_verify_commits_from_last_finalized_block(raw, block_commit_hash).await??;
     if let Commit::Block(block) = csv
@@ -319,11 +793,29 @@ pub async fn finalize(
                 proof,
             }))
             .await?;
-        sync(raw, commit_hash)
-            .await?
-            .expect("already checked by CSV");
+        sync(
+            raw,
+            commit_hash,
+            config,
+            &ProgressReporter::default(),
+            transactions,
+        )
+        .await?
+        .expect("already checked by CSV");
         Ok(commit_hash)
     } else {
         Err(eyre!("commit {} is not a block commit", block_commit_hash))
     }
 }
+
+/// Converts the commit to a semantic commit, signing it if the key is given.
+fn to_authored_semantic_commit(
+    commit: &Commit,
+    reserved_state: ReservedState,
+    signing_key: Option<&PrivateKey>,
+) -> Result<raw::SemanticCommit, Error> {
+    match signing_key {
+        Some(signing_key) => to_signed_semantic_commit(commit, reserved_state, signing_key),
+        None => to_semantic_commit(commit, reserved_state),
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+use simperby_core::merkle_tree::{MerkleProof, OneshotMerkleTree};
+
+/// A file of a finalized block, with the proof of its inclusion in the block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalizedFile {
+    pub content: Vec<u8>,
+    pub block_commit_hash: CommitHash,
+    pub header: BlockHeader,
+    /// The proof of [`BlockHeader::repository_merkle_leaf`] of the file
+    /// against the `repository_merkle_root` of the header.
+    ///
+    /// `None` if the block doesn't commit to the repository (see [`verify_repository_merkle_root`]).
+    pub merkle_proof: Option<MerkleProof>,
+}
+
+fn repository_merkle_tree(files: &[(String, Vec<u8>)]) -> OneshotMerkleTree {
+    OneshotMerkleTree::create(
+        files
+            .iter()
+            .map(|(path, content)| {
+                Hash256::hash(BlockHeader::repository_merkle_leaf(path, content))
+            })
+            .collect(),
+    )
+}
+
+/// Calculates `repository_merkle_root` of the files, in the order of the paths.
+pub fn calculate_repository_merkle_root(files: &[(String, Vec<u8>)]) -> Hash256 {
+    repository_merkle_tree(files).root()
+}
+
+/// Checks that the `repository_merkle_root` of the block commits to the files of the block commit.
+///
+/// The zero root of the blocks created before the root was calculated is accepted,
+/// as not committing to anything.
+pub async fn verify_repository_merkle_root(
+    raw: &RawRepository,
+    header: &BlockHeader,
+    block_commit_hash: CommitHash,
+) -> Result<Result<(), String>, Error> {
+    if header.repository_merkle_root == Hash256::zero() {
+        return Ok(Ok(()));
+    }
+    let root =
+        calculate_repository_merkle_root(&raw.read_files_at_commit(block_commit_hash).await?);
+    if root != header.repository_merkle_root {
+        return Ok(Err(format!(
+            "the repository merkle root of block {} doesn't match its files: {} != {}",
+            header.height, header.repository_merkle_root, root
+        )));
+    }
+    Ok(Ok(()))
+}
+
+/// Reads the file at the path (relative to the repository root, e.g. `docs/README.md`)
+/// in the finalized block at the height, with the merkle inclusion proof.
+///
+/// Returns `None` if there is no such block or file.
+pub async fn read_finalized_file(
+    raw: &RawRepository,
+    height: BlockHeight,
+    path: &str,
+) -> Result<Option<FinalizedFile>, Error> {
+    let Some((block_commit_hash, header)) = locate_finalized_block(raw, height).await? else {
+        return Ok(None);
+    };
+    let files = raw.read_files_at_commit(block_commit_hash).await?;
+    let Some((path, content)) = files.iter().find(|(p, _)| p == path) else {
+        return Ok(None);
+    };
+    let tree = repository_merkle_tree(&files);
+    let merkle_proof = if tree.root() == header.repository_merkle_root {
+        tree.create_merkle_proof(Hash256::hash(BlockHeader::repository_merkle_leaf(
+            path, content,
+        )))
+    } else {
+        None
+    };
+    Ok(Some(FinalizedFile {
+        content: content.clone(),
+        block_commit_hash,
+        header,
+        merkle_proof,
+    }))
+}
@@ -2,6 +2,9 @@ use super::*;
 
 pub async fn genesis(raw: &mut RawRepository) -> Result<(), Error> {
     let reserved_state = raw.read_reserved_state().await?;
+    reserved_state
+        .check_genesis()
+        .map_err(|e| eyre!("invalid genesis state: {e}"))?;
     let block_commit = Commit::Block(reserved_state.genesis_info.header.clone());
     let semantic_commit = to_semantic_commit(&block_commit, reserved_state.clone())?;
 
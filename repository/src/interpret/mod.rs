@@ -1,5 +1,6 @@
 pub mod create;
 pub mod dms;
+pub mod files;
 pub mod genesis;
 pub mod read;
 pub mod tags;
@@ -9,6 +10,7 @@ use super::*;
 
 pub use create::*;
 pub use dms::*;
+pub use files::*;
 pub use genesis::*;
 pub use read::*;
 pub use tags::*;
@@ -38,12 +38,22 @@ pub async fn get_last_finalized_block_commit_hash(
         })
 }
 
+/// Reads the reserved state in effect, with the scheduled changes due by the last finalized block.
+///
+/// A state bound to a later block (see [`ReservedState::binding`]) is an integrity error.
 pub async fn read_last_finalized_reserved_state(
     raw: &RawRepository,
 ) -> Result<ReservedState, Error> {
-    Ok(raw
+    let height = read_last_finalized_block_header(raw).await?.height;
+    let reserved_state = raw
         .read_reserved_state_at_commit(get_last_finalized_block_commit_hash(raw).await?)
-        .await?)
+        .await?;
+    reserved_state.check_finalized_at(height).map_err(|e| {
+        eyre!(IntegrityError::new(format!(
+            "the reserved state doesn't match the finalized block: {e}"
+        )))
+    })?;
+    Ok(reserved_state.effective_at(height))
 }
 
 pub async fn read_last_finalized_block_header(raw: &RawRepository) -> Result<BlockHeader, Error> {
@@ -99,6 +109,22 @@ pub async fn read_commits(
     ancestor: CommitHash,
     descendant: CommitHash,
 ) -> Result<Vec<(Commit, CommitHash)>, CommitError> {
+    Ok(read_signed_commits(raw, ancestor, descendant)
+        .await?
+        .into_iter()
+        .map(|(commit, _, hash)| (commit, hash))
+        .collect())
+}
+
+/// Below this number of commits, spawning threads costs more than parsing them.
+const PARALLEL_PARSING_THRESHOLD: usize = 64;
+
+/// Same as [`read_commits`], but also returns the authors' signatures of the commits.
+pub async fn read_signed_commits(
+    raw: &RawRepository,
+    ancestor: CommitHash,
+    descendant: CommitHash,
+) -> Result<Vec<(Commit, Option<TypedSignature<Commit>>, CommitHash)>, CommitError> {
     let commits = raw.query_commit_path(ancestor, descendant).await?;
     let commits = stream::iter(
         commits
@@ -110,15 +136,15 @@ pub async fn read_commits(
     .collect::<Vec<_>>()
     .await;
     let commits = commits.into_iter().collect::<Result<Vec<_>, _>>()?;
-    let commits = commits
-        .into_iter()
-        .map(|(commit, hash)| {
-            from_semantic_commit(commit)
-                .map_err(|e| (e, hash))
-                .map(|x| (x, hash))
-        })
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|(e, c)| CommitError::Commit(e, c))?;
+    // Parsing a commit doesn't depend on the others, so a long sequence is parsed in parallel.
+    let commits = utils::map_in_parallel(&commits, PARALLEL_PARSING_THRESHOLD, |(commit, hash)| {
+        from_semantic_commit(commit.clone())
+            .map_err(|e| (e, *hash))
+            .map(|x| (x, commit.signature.clone(), *hash))
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|(e, c)| CommitError::Commit(e, c))?;
     Ok(commits)
 }
 
@@ -152,14 +178,411 @@ pub async fn read_and_verify_commits_from_last_finalized_block(
         IntegrityError::new(format!("finalized branch is not accepted by CSV: {e}"))
     })?;
 
-    for commit in commits {
-        if let Err(e) = csv.apply_commit(&commit.0) {
+    for (commit, commit_hash) in commits {
+        if let Commit::Transaction(_) = commit {
+            if let Err(e) = check_transaction_spec(
+                raw,
+                csv.get_reserved_state().transaction_spec.as_ref(),
+                commit_hash,
+            )
+            .await?
+            {
+                return Ok(Err(transaction_spec_error(e, &commit_hash)));
+            }
+        }
+        if let Err(e) = csv.apply_commit(&commit) {
             return Ok(Err(eyre!(e)));
         }
     }
     Ok(Ok(csv))
 }
 
+/// Checks the files that the transaction commit changes against the transaction spec
+/// of the reserved state it's applied on, if any (see [`ReservedState::transaction_spec`]).
+pub async fn check_transaction_spec(
+    raw: &RawRepository,
+    spec: Option<&TransactionSpec>,
+    commit_hash: CommitHash,
+) -> Result<Result<(), TransactionSpecViolation>, Error> {
+    let Some(spec) = spec else {
+        return Ok(Ok(()));
+    };
+    let paths = raw.get_changed_paths(commit_hash).await?;
+    Ok(spec.check_paths(&paths))
+}
+
+/// Keeps the violation typed, so that it can be told from the invalid commits.
+pub(crate) fn transaction_spec_error(e: TransactionSpecViolation, hash: &CommitHash) -> Error {
+    eyre::Report::new(e.clone()).wrap_err(format!(
+        "transaction {hash} violates the transaction spec: {e}"
+    ))
+}
+
+/// Locates the commit of the checkpoint in the `finalized` branch.
+///
+/// Returns `None` if the checkpoint is not in the available history,
+/// which means that a shallow repository must be deepened.
+/// Note that the parent of the checkpoint commit must be available as well.
+pub async fn locate_checkpoint(
+    raw: &RawRepository,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<Option<CommitHash>, Error> {
+    let finalized_commit_hash = get_last_finalized_block_commit_hash(raw).await?;
+    for commit_hash in std::iter::once(finalized_commit_hash)
+        .chain(raw.list_ancestors(finalized_commit_hash, None).await?)
+    {
+        let Ok(commit) = read_commit(raw, commit_hash).await else {
+            // The commit is at the shallow boundary.
+            return Ok(None);
+        };
+        if let Commit::Block(header) = commit {
+            if header.height < checkpoint.height {
+                return Err(eyre!(
+                    "the checkpoint at height {} is not in the finalized branch",
+                    checkpoint.height
+                ));
+            }
+            if header.height == checkpoint.height {
+                return if header.to_hash256() == checkpoint.hash {
+                    Ok(Some(commit_hash))
+                } else {
+                    Err(eyre!(
+                        "the block at height {} doesn't match the checkpoint: {} != {}",
+                        checkpoint.height,
+                        header.to_hash256(),
+                        checkpoint.hash
+                    ))
+                };
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the header of the checkpoint with its finalization proof,
+/// which is in either the next block header or the `fp` branch.
+pub async fn read_checkpoint_bundle(
+    raw: &RawRepository,
+    checkpoint_commit: CommitHash,
+) -> Result<CheckpointBundle, Error> {
+    let header = match read_commit(raw, checkpoint_commit).await? {
+        Commit::Block(header) => header,
+        _ => return Err(eyre!("the checkpoint commit is not a block commit")),
+    };
+    let finalization_proof = read_finalization_proof(raw, checkpoint_commit).await?;
+    Ok(CheckpointBundle {
+        header,
+        finalization_proof,
+    })
+}
+
+/// Reads the finalization proof of the finalized block commit,
+/// which is in either the next block header or the `fp` branch.
+pub async fn read_finalization_proof(
+    raw: &RawRepository,
+    block_commit: CommitHash,
+) -> Result<FinalizationProof, Error> {
+    let lfi = read_last_finalization_info(raw).await?;
+    if block_commit == lfi.commit_hash {
+        return Ok(lfi.proof);
+    }
+    read_commits(raw, block_commit, lfi.commit_hash)
+        .await?
+        .into_iter()
+        .find_map(|(commit, _)| match commit {
+            Commit::Block(next_header) => Some(next_header.prev_block_finalization_proof),
+            _ => None,
+        })
+        .ok_or_else(|| eyre!("no block follows {block_commit} in the finalized branch"))
+}
+
+/// Locates the block commit of the given height in the `finalized` branch.
+///
+/// Returns `None` if the height is above the last finalized block
+/// or below the available history.
+pub async fn locate_finalized_block(
+    raw: &RawRepository,
+    height: BlockHeight,
+) -> Result<Option<(CommitHash, BlockHeader)>, Error> {
+    let finalized_commit_hash = get_last_finalized_block_commit_hash(raw).await?;
+    for commit_hash in std::iter::once(finalized_commit_hash)
+        .chain(raw.list_ancestors(finalized_commit_hash, None).await?)
+    {
+        let Ok(commit) = read_commit(raw, commit_hash).await else {
+            // The commit is at the shallow boundary.
+            return Ok(None);
+        };
+        if let Commit::Block(header) = commit {
+            if header.height == height {
+                return Ok(Some((commit_hash, header)));
+            }
+            if header.height < height {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Verifies the history from the trusted checkpoint to the `finalized` branch,
+/// returning the commit of the checkpoint.
+///
+/// The checkpoint is trusted, so the history before it doesn't have to be available
+/// (see [`RawRepository::clone_shallow`]).
+pub async fn verify_from_checkpoint(
+    raw: &RawRepository,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<CommitHash, Error> {
+    let checkpoint_commit = locate_checkpoint(raw, checkpoint)
+        .await?
+        .ok_or_else(|| eyre!("the checkpoint is not in the available history"))?;
+    let bundle = read_checkpoint_bundle(raw, checkpoint_commit).await?;
+    bundle
+        .verify(checkpoint)
+        .map_err(|e| eyre!("invalid checkpoint: {e}"))?;
+
+    let lfi = read_last_finalization_info(raw).await?;
+    let reserved_state = raw.read_reserved_state_at_commit(checkpoint_commit).await?;
+    reserved_state
+        .check_finalized_at(bundle.header.height)
+        .map_err(|e| eyre!("the reserved state doesn't match the checkpoint: {e}"))?;
+    let mut csv = CommitSequenceVerifier::new(bundle.header, reserved_state)
+        .map_err(|e| eyre!("the checkpoint is not accepted by CSV: {e}"))?;
+    for (commit, commit_hash) in read_commits(raw, checkpoint_commit, lfi.commit_hash).await? {
+        csv.apply_commit(&commit)
+            .map_err(|e| eyre!("verification error on commit {commit_hash}: {e}"))?;
+    }
+    verify::verify_finalization_proof(&lfi.header, &lfi.proof)
+        .map_err(|e| eyre!("invalid finalization proof of the last block: {e}"))?;
+    Ok(checkpoint_commit)
+}
+
+/// The summary of [`verify_finalized_history`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryVerificationReport {
+    /// The height of the block that the verification started from.
+    pub from_height: BlockHeight,
+    /// The highest height whose block and finalization have been verified.
+    pub verified_height: BlockHeight,
+    pub last_finalized_height: BlockHeight,
+    /// The number of the commits replayed.
+    pub commits: usize,
+    pub elapsed_ms: u64,
+    /// The first failure, if any, which stops the verification.
+    pub failure: Option<String>,
+}
+
+/// Replays the `finalized` branch from the block at `from_height`, verifying every block header,
+/// agenda proof, finalization proof and reserved state transition along the way.
+///
+/// The genesis block is verified with its genesis proof, while a block at another height
+/// is trusted as the starting point like a checkpoint. A failure in the history is reported
+/// in the result; `Err` is only for a failure to read the repository.
+pub async fn verify_finalized_history(
+    raw: &RawRepository,
+    from_height: BlockHeight,
+) -> Result<HistoryVerificationReport, Error> {
+    let started = std::time::Instant::now();
+    let lfi = read_last_finalization_info(raw).await?;
+    let (from_commit_hash, from_header) = locate_finalized_block(raw, from_height)
+        .await?
+        .ok_or_else(|| {
+            eyre!("the block at height {from_height} is not in the available history")
+        })?;
+    let reserved_state = raw.read_reserved_state_at_commit(from_commit_hash).await?;
+    let mut report = HistoryVerificationReport {
+        from_height,
+        verified_height: from_height,
+        last_finalized_height: lfi.header.height,
+        commits: 0,
+        elapsed_ms: 0,
+        failure: None,
+    };
+    let failure = async {
+        if from_height == 0 {
+            if reserved_state.genesis_info.header != from_header {
+                return Some("the genesis block doesn't match the genesis info".to_owned());
+            }
+            if let Err(e) = reserved_state.check_genesis() {
+                return Some(format!("invalid genesis state: {e}"));
+            }
+            if let Err(e) = verify::verify_finalization_proof(
+                &from_header,
+                &reserved_state.genesis_info.genesis_proof,
+            ) {
+                return Some(format!("invalid genesis proof: {e}"));
+            }
+        }
+        let mut csv = match CommitSequenceVerifier::new(from_header, reserved_state) {
+            Ok(csv) => csv,
+            Err(e) => return Some(format!("the starting block is not accepted by CSV: {e}")),
+        };
+        let commits = if from_commit_hash == lfi.commit_hash {
+            Vec::new()
+        } else {
+            match read_commits(raw, from_commit_hash, lfi.commit_hash).await {
+                Ok(commits) => commits,
+                Err(e) => return Some(e.to_string()),
+            }
+        };
+        for (commit, commit_hash) in commits {
+            if let Err(e) = csv.apply_commit(&commit) {
+                return Some(format!("verification error on commit {commit_hash}: {e}"));
+            }
+            report.commits += 1;
+            if let Commit::Block(header) = commit {
+                // The proof of the previous block is in this one.
+                report.verified_height = header.height - 1;
+                match verify_repository_merkle_root(raw, &header, commit_hash).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return Some(e),
+                    Err(e) => return Some(e.to_string()),
+                }
+                let reserved_state = match raw.read_reserved_state_at_commit(commit_hash).await {
+                    Ok(reserved_state)
+                        if &reserved_state.effective_at(header.height)
+                            == csv.get_reserved_state() =>
+                    {
+                        reserved_state
+                    }
+                    Ok(_) => {
+                        return Some(format!(
+                            "the reserved state at height {} doesn't match its transactions",
+                            header.height
+                        ))
+                    }
+                    Err(e) => return Some(e.to_string()),
+                };
+                // Block by block, as the blocks are received in `sync`.
+                csv = match CommitSequenceVerifier::new(header, reserved_state) {
+                    Ok(csv) => csv,
+                    Err(e) => return Some(e.to_string()),
+                };
+            }
+        }
+        if let Err(e) = csv.verify_last_header_finalization(&lfi.proof) {
+            return Some(format!("invalid finalization proof of the last block: {e}"));
+        }
+        report.verified_height = lfi.header.height;
+        None
+    }
+    .await;
+    report.failure = failure;
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+    Ok(report)
+}
+
+/// The result of verifying one of the signature layers of a commit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureStatus {
+    /// Signed by the member.
+    Verified(MemberName),
+    Unsigned,
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitSignatureReport {
+    pub commit_hash: CommitHash,
+    pub commit: Commit,
+    /// The in-commit signature (see [`raw::SemanticCommit::signature`]).
+    pub in_commit: SignatureStatus,
+    /// The git's native signature (see [`raw::GitSignature`]).
+    pub git: SignatureStatus,
+}
+
+/// Verifies both the in-commit signatures and the git's native signatures
+/// of the commits in the `finalized` branch, from the genesis block.
+pub async fn verify_commit_signatures(
+    raw: &RawRepository,
+) -> Result<Vec<CommitSignatureReport>, Error> {
+    let finalized_commit_hash = get_last_finalized_block_commit_hash(raw).await?;
+    let mut genesis = None;
+    for commit_hash in std::iter::once(finalized_commit_hash)
+        .chain(raw.list_ancestors(finalized_commit_hash, None).await?)
+    {
+        if let Ok(Commit::Block(header)) = read_commit(raw, commit_hash).await {
+            if header.height == 0 {
+                genesis = Some((commit_hash, header));
+                break;
+            }
+        }
+    }
+    let (genesis_commit_hash, genesis_header) =
+        genesis.ok_or_else(|| eyre!("the genesis block is not in the available history"))?;
+    let reserved_state = raw
+        .read_reserved_state_at_commit(genesis_commit_hash)
+        .await?;
+
+    let mut commits = vec![(
+        Commit::Block(genesis_header.clone()),
+        raw.read_semantic_commit(genesis_commit_hash)
+            .await?
+            .signature,
+        genesis_commit_hash,
+    )];
+    if genesis_commit_hash != finalized_commit_hash {
+        commits.extend(read_signed_commits(raw, genesis_commit_hash, finalized_commit_hash).await?);
+    }
+    let mut csv = CommitSequenceVerifier::new(genesis_header, reserved_state.clone())
+        .map_err(|e| IntegrityError::new(format!("genesis is not accepted by CSV: {e}")))?;
+    let mut reports = Vec::new();
+    for (i, (commit, signature, commit_hash)) in commits.into_iter().enumerate() {
+        let reserved_state = csv.get_reserved_state().clone();
+        let in_commit = match &signature {
+            Some(signature) => match format::verify_commit_authorship(
+                &commit,
+                Some(signature),
+                &reserved_state,
+                true,
+            ) {
+                Ok(()) => SignatureStatus::Verified(
+                    reserved_state
+                        .query_name(signature.signer())
+                        .unwrap_or_else(|| signature.signer().to_string()),
+                ),
+                Err(e) => SignatureStatus::Invalid(e),
+            },
+            None => SignatureStatus::Unsigned,
+        };
+        let git = match raw.read_git_signature(commit_hash).await? {
+            Some(git_signature) => match git_signature.verify(&reserved_state.members) {
+                Ok(signer) => match commit_author(&commit, &reserved_state) {
+                    Some(author) if author != signer => SignatureStatus::Invalid(format!(
+                        "the commit is signed by {signer}, not its author {author}"
+                    )),
+                    _ => SignatureStatus::Verified(signer),
+                },
+                Err(e) => SignatureStatus::Invalid(e),
+            },
+            None => SignatureStatus::Unsigned,
+        };
+        // The genesis block is the start of the CSV.
+        if i > 0 {
+            csv.apply_commit(&commit).map_err(|e| {
+                IntegrityError::new(format!("finalized branch is not accepted by CSV: {e}"))
+            })?;
+        }
+        reports.push(CommitSignatureReport {
+            commit_hash,
+            commit,
+            in_commit,
+            git,
+        });
+    }
+    Ok(reports)
+}
+
+/// Returns the member who is supposed to author the commit, if any.
+fn commit_author(commit: &Commit, reserved_state: &ReservedState) -> Option<MemberName> {
+    match commit {
+        Commit::Agenda(agenda) => Some(agenda.author.clone()),
+        Commit::Transaction(transaction) => Some(transaction.author.clone()),
+        Commit::Block(header) => reserved_state.query_name(&header.author),
+        _ => None,
+    }
+}
+
 pub async fn read_commit(raw: &RawRepository, commit_hash: CommitHash) -> Result<Commit, Error> {
     let semantic_commit = raw.read_semantic_commit(commit_hash).await?;
     format::from_semantic_commit(semantic_commit).map_err(|e| eyre!(e))
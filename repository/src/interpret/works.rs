@@ -1,4 +1,5 @@
 use super::*;
+use crate::progress::{estimate_remaining, Progress, ProgressReporter};
 use read::*;
 
 async fn advance_finalized_branch(
@@ -20,9 +21,144 @@ async fn advance_finalized_branch(
     Ok(())
 }
 
+/// Below this number of commits, spawning threads costs more than hashing them.
+const PARALLEL_HASHING_THRESHOLD: usize = 64;
+
+/// Applies the commits to the CSV, checking the authorship of each commit
+/// against the reserved state at the moment.
+///
+/// Only what depends on the order of the commits is done one by one:
+/// the commits are hashed in parallel, and the signatures of the commits and of their proofs
+/// are verified in a batch across the blocks (see [`verify::SignatureBatch`]) before the loop
+/// that applies the transitions of the reserved state and checks the signers on it.
+fn verify_commits(
+    csv: &mut CommitSequenceVerifier,
+    commits: &[(Commit, Option<TypedSignature<Commit>>, CommitHash)],
+    config: &Config,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let started = std::time::Instant::now();
+    let (start_header, _) = csv
+        .get_block_headers()
+        .pop()
+        .expect("the CSV starts with a block header");
+    let mut height = start_header.height;
+    let hashes = utils::map_in_parallel(commits, PARALLEL_HASHING_THRESHOLD, |(commit, ..)| {
+        commit.to_hash256()
+    });
+    // On a single thread, verifying ahead would only repeat the work of the CSV.
+    if std::thread::available_parallelism().map_or(1, |x| x.get()) > 1 {
+        let mut batch = verify::SignatureBatch::default();
+        batch.add_commits(&start_header, commits.iter().map(|(commit, ..)| commit));
+        for ((_, signature, _), hash) in commits.iter().zip(&hashes) {
+            if let Some(signature) = signature {
+                batch.add(signature, *hash);
+            }
+        }
+        csv.set_verified_signatures(batch.verify());
+    }
+    for (i, ((commit, signature, commit_hash), hash)) in commits.iter().zip(hashes).enumerate() {
+        format::verify_commit_authorship_with(
+            commit,
+            hash,
+            signature.as_ref(),
+            csv.get_reserved_state(),
+            config.require_signed_commits,
+            csv.get_verified_signatures(),
+        )
+        .map_err(|e| format!("commit authorship verification failed: {e} at {commit_hash}"))?;
+        csv.apply_commit_with_hash(commit, hash)
+            .map_err(|e| format!("commit sequence verification failed: {e} at {commit_hash}"))?;
+        if let Commit::Block(header) = commit {
+            height = header.height;
+        }
+        let verified_commits = i as u64 + 1;
+        let total_commits = commits.len() as u64;
+        progress.report(Progress::Verifying {
+            verified_commits,
+            total_commits,
+            height,
+            eta_ms: estimate_remaining(started, verified_commits, total_commits),
+        });
+    }
+    csv.set_verified_signatures(Default::default());
+    Ok(())
+}
+
+/// Checks that none of the transactions in the commits has been finalized
+/// or appears more than once.
+async fn check_duplicate_transactions(
+    raw: &RawRepository,
+    commits: &[(Commit, Option<TypedSignature<Commit>>, CommitHash)],
+    transactions: &mut TransactionIndex,
+) -> Result<Result<(), String>, Error> {
+    let mut seen = HashSet::new();
+    for (commit, _, commit_hash) in commits {
+        let Commit::Transaction(transaction) = commit else {
+            continue;
+        };
+        let transaction_hash = transaction.to_hash256();
+        if let Some(height) = transactions.finalized_height(raw, transaction_hash).await? {
+            return Ok(Err(format!(
+                "transaction {commit_hash} has already been finalized at height {height}"
+            )));
+        }
+        if !seen.insert(transaction_hash) {
+            return Ok(Err(format!(
+                "transaction {commit_hash} is included more than once"
+            )));
+        }
+    }
+    Ok(Ok(()))
+}
+
+async fn check_repository_merkle_roots(
+    raw: &RawRepository,
+    commits: &[(Commit, CommitHash)],
+) -> Result<Result<(), String>, Error> {
+    for (commit, commit_hash) in commits {
+        if let Commit::Block(header) = commit {
+            if let Err(e) = verify_repository_merkle_root(raw, header, *commit_hash).await? {
+                return Ok(Err(e));
+            }
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Checks the transactions in the commits against the transaction spec of the reserved state
+/// at the moment (see [`ReservedState::transaction_spec`]), starting from the given one.
+///
+/// Only a reserved-diff transaction changes the spec, so the commits need not be applied again.
+async fn check_transaction_specs(
+    raw: &RawRepository,
+    reserved_state: &ReservedState,
+    commits: &[(Commit, CommitHash)],
+) -> Result<Result<(), String>, Error> {
+    let mut spec = reserved_state.transaction_spec.as_ref();
+    for (commit, commit_hash) in commits {
+        let Commit::Transaction(transaction) = commit else {
+            continue;
+        };
+        if let Err(e) = check_transaction_spec(raw, spec, *commit_hash).await? {
+            return Ok(Err(format!(
+                "transaction {commit_hash} violates the transaction spec: {e}"
+            )));
+        }
+        if let Diff::Reserved(reserved_state) = &transaction.diff {
+            spec = reserved_state.transaction_spec.as_ref();
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Verifies the branch of the tip and accepts it, advancing the `finalized` branch if possible.
 pub async fn sync(
     raw: &mut RawRepository,
     tip_commit_hash: CommitHash,
+    config: &Config,
+    progress: &ProgressReporter,
+    transactions: &mut TransactionIndex,
 ) -> Result<Result<(), String>, Error> {
     let lfi = read_last_finalization_info(raw).await?;
     let mut csv = CommitSequenceVerifier::new(lfi.header.clone(), lfi.reserved_state.clone())
@@ -53,19 +189,28 @@ pub async fn sync(
         }
 
         // Read the commits in the branch and verify them
-        let commits = match read_commits(raw, lfi.commit_hash, commit_hash).await {
+        let commits = match read_signed_commits(raw, lfi.commit_hash, commit_hash).await {
             Ok(x) => x,
             Err(CommitError::Commit(error, commit)) => {
                 return Ok(Err(format!("failed to parse commit {commit}: {error}")));
             }
             Err(e) => return Err(e.into()),
         };
-        for (commit, commit_hash) in &commits {
-            if let Err(e) = csv.apply_commit(commit) {
-                return Ok(Err(format!(
-                    "commit sequence verification failed: {e} at {commit_hash}",
-                )));
-            }
+        if let Err(e) = check_duplicate_transactions(raw, &commits, transactions).await? {
+            return Ok(Err(e));
+        }
+        if let Err(e) = verify_commits(&mut csv, &commits, config, progress) {
+            return Ok(Err(e));
+        }
+        let commits = commits
+            .into_iter()
+            .map(|(commit, _, hash)| (commit, hash))
+            .collect::<Vec<_>>();
+        if let Err(e) = check_repository_merkle_roots(raw, &commits).await? {
+            return Ok(Err(e));
+        }
+        if let Err(e) = check_transaction_specs(raw, &lfi.reserved_state, &commits).await? {
+            return Ok(Err(e));
         }
 
         let (last_commit, last_commit_hash) = commits.last().expect(
@@ -92,19 +237,28 @@ pub async fn sync(
         }
 
         // Read the commits in the branch and verify them
-        let commits = match read_commits(raw, lfi.commit_hash, tip_commit_hash).await {
+        let commits = match read_signed_commits(raw, lfi.commit_hash, tip_commit_hash).await {
             Ok(x) => x,
             Err(CommitError::Commit(error, commit)) => {
                 return Ok(Err(format!("failed to parse commit {commit}: {error}",)));
             }
             Err(e) => return Err(e.into()),
         };
-        for (commit, commit_hash) in &commits {
-            if let Err(e) = csv.apply_commit(commit) {
-                return Ok(Err(format!(
-                    "commit sequence verification failed: {e} at {commit_hash}",
-                )));
-            }
+        if let Err(e) = check_duplicate_transactions(raw, &commits, transactions).await? {
+            return Ok(Err(e));
+        }
+        if let Err(e) = verify_commits(&mut csv, &commits, config, progress) {
+            return Ok(Err(e));
+        }
+        let commits = commits
+            .into_iter()
+            .map(|(commit, _, hash)| (commit, hash))
+            .collect::<Vec<_>>();
+        if let Err(e) = check_repository_merkle_roots(raw, &commits).await? {
+            return Ok(Err(e));
+        }
+        if let Err(e) = check_transaction_specs(raw, &lfi.reserved_state, &commits).await? {
+            return Ok(Err(e));
         }
 
         // If the commit sequence contains block commit(s) that can be finalized
@@ -159,7 +313,70 @@ pub async fn sync(
     Ok(Ok(()))
 }
 
-pub async fn sync_all(raw: &mut RawRepository) -> Result<Vec<(String, Result<(), String>)>, Error> {
+/// Checks whether the branch forks from the `finalized` branch below [`Config::min_fork_height`],
+/// which is considered a long range attack.
+///
+/// - Returns `Ok(Ok(()))` if the branch is a descendant of the last finalized block
+///   or forks at an acceptable height.
+/// - Returns `Ok(Err(_))` if the branch must be ignored, with a reason.
+pub async fn check_long_range_attack(
+    raw: &RawRepository,
+    tip_commit_hash: CommitHash,
+    config: &Config,
+    headers: &mut HeaderCache,
+) -> Result<Result<(), String>, Error> {
+    let (last_finalized_commit_hash, last_finalized_header) = headers.last_finalized(raw).await?;
+    let merge_base = match raw
+        .find_merge_base(last_finalized_commit_hash, tip_commit_hash)
+        .await
+    {
+        Ok(x) => x,
+        Err(raw::Error::NotFound(_)) => {
+            return Ok(Err(
+                "the branch forks before the available history (possibly a long range attack)."
+                    .to_owned(),
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // Not a fork if the tip is either a descendant or an ancestor of the last finalized block.
+    if merge_base == last_finalized_commit_hash || merge_base == tip_commit_hash {
+        return Ok(Ok(()));
+    }
+    // Find the last block before the fork.
+    let mut fork_height = None;
+    for commit_hash in
+        std::iter::once(merge_base).chain(raw.list_ancestors(merge_base, None).await?)
+    {
+        // Stop at the pre-genesis commits or the shallow boundary, which can't be read.
+        let Ok(commit) = read_commit(raw, commit_hash).await else {
+            break;
+        };
+        if let Commit::Block(header) = commit {
+            fork_height = Some(header.height);
+            break;
+        }
+    }
+    let min_fork_height = config.min_fork_height(last_finalized_header.height);
+    match fork_height {
+        Some(height) if height >= min_fork_height => Ok(Ok(())),
+        Some(height) => Ok(Err(format!(
+            "long range attack: the branch forks at height {height}, below {min_fork_height}."
+        ))),
+        None => Ok(Err(
+            "the branch forks before the genesis or the available history.".to_owned(),
+        )),
+    }
+}
+
+pub async fn sync_all(
+    raw: &mut RawRepository,
+    config: &Config,
+    progress: &ProgressReporter,
+    remote_priority: &[String],
+    headers: &mut HeaderCache,
+    transactions: &mut TransactionIndex,
+) -> Result<Vec<(String, Result<(), String>)>, Error> {
     let local_branches: Vec<String> = raw
         .list_branches()
         .await?
@@ -173,17 +390,29 @@ pub async fn sync_all(raw: &mut RawRepository) -> Result<Vec<(String, Result<(),
                 && s.as_str() != "p"
         })
         .collect();
-    let remote_tracking_branches = raw.list_remote_tracking_branches().await?;
+    let mut remote_tracking_branches = raw.list_remote_tracking_branches().await?;
+    // The same branch from the later remotes would be mostly rejected as already received.
+    remote_tracking_branches.sort_by_key(|(remote, _, _)| {
+        remote_priority
+            .iter()
+            .position(|r| r == remote)
+            .unwrap_or(remote_priority.len())
+    });
 
     let mut result = Vec::new();
+    let mut tips = Vec::new();
     for branch in local_branches {
-        result.push((
-            branch.to_owned(),
-            sync(raw, raw.locate_branch(branch.to_owned()).await?).await?,
-        ));
+        tips.push((branch.to_owned(), raw.locate_branch(branch).await?));
     }
     for (remote, branch, commit_hash) in remote_tracking_branches {
-        result.push((format!("{remote}/{branch}"), sync(raw, commit_hash).await?));
+        tips.push((format!("{remote}/{branch}"), commit_hash));
+    }
+    for (branch, commit_hash) in tips {
+        let sync_result = match check_long_range_attack(raw, commit_hash, config, headers).await? {
+            Ok(()) => sync(raw, commit_hash, config, progress, transactions).await?,
+            Err(e) => Err(e),
+        };
+        result.push((branch, sync_result));
     }
     Ok(result)
 }
@@ -254,6 +483,96 @@ pub async fn clean(raw: &mut RawRepository, hard: bool) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn prune(raw: &mut RawRepository, policy: &PrunePolicy) -> Result<PruneReport, Error> {
+    let finalized_commit_hash = get_last_finalized_block_commit_hash(raw).await?;
+    let mut deleted_branches = Vec::new();
+    let mut block_candidates = Vec::new();
+    for (branch, commit_hash) in read_local_branches(raw).await? {
+        let is_agenda = branch.starts_with("a-");
+        let is_block = branch.starts_with("b-");
+        if !is_agenda && !is_block {
+            continue;
+        }
+        let outdated = match raw
+            .find_merge_base(commit_hash, finalized_commit_hash)
+            .await
+        {
+            Ok(merge_base) => merge_base != finalized_commit_hash,
+            Err(raw::Error::NotFound(_)) => true,
+            Err(e) => return Err(e.into()),
+        };
+        if outdated {
+            deleted_branches.push(branch);
+        } else if is_block {
+            let timestamp = raw.read_commit(commit_hash).await?.timestamp;
+            block_candidates.push((timestamp, commit_hash, branch));
+        }
+    }
+    // Keep the most recent candidates only.
+    // The candidates at the same height share the BFT time, so the ties are broken by the commit hash.
+    block_candidates.sort();
+    let excess = block_candidates
+        .len()
+        .saturating_sub(policy.block_candidates_to_keep);
+    deleted_branches.extend(block_candidates.into_iter().take(excess).map(|(_, _, x)| x));
+
+    deleted_branches.sort();
+    if let Some(branch) = raw.get_currently_checkout_branch().await? {
+        if deleted_branches.contains(&branch) {
+            raw.checkout(WORK_BRANCH_NAME.into()).await?;
+        }
+    }
+    for branch in &deleted_branches {
+        raw.delete_branch(branch.clone()).await?;
+    }
+    let reclaimed_bytes = raw.gc().await?;
+    info!(
+        "pruned {} branch(es), reclaiming {} bytes",
+        deleted_branches.len(),
+        reclaimed_bytes
+    );
+    Ok(PruneReport {
+        deleted_branches,
+        reclaimed_bytes,
+    })
+}
+
+/// Cuts the history below the given block commit (see [`RawRepository::cut_history`]),
+/// removing the branches and the tags below it first, returning the reclaimed space in bytes.
+pub async fn cut_history(
+    raw: &mut RawRepository,
+    block_commit_hash: CommitHash,
+) -> Result<u64, Error> {
+    async fn is_below(
+        raw: &RawRepository,
+        commit_hash: CommitHash,
+        block_commit_hash: CommitHash,
+    ) -> Result<bool, Error> {
+        match raw.find_merge_base(commit_hash, block_commit_hash).await {
+            Ok(merge_base) => Ok(merge_base != block_commit_hash),
+            Err(raw::Error::NotFound(_)) => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    let current_branch = raw.get_currently_checkout_branch().await?;
+    for (branch, commit_hash) in read_local_branches(raw).await? {
+        if is_below(raw, commit_hash, block_commit_hash).await? {
+            if current_branch.as_ref() == Some(&branch) {
+                raw.checkout(WORK_BRANCH_NAME.into()).await?;
+            }
+            raw.delete_branch(branch).await?;
+        }
+    }
+    for tag in raw.list_tags().await? {
+        let commit_hash = raw.locate_tag(tag.clone()).await?;
+        if is_below(raw, commit_hash, block_commit_hash).await? {
+            raw.remove_tag(tag).await?;
+        }
+    }
+    Ok(raw.cut_history(block_commit_hash).await?)
+}
+
 pub async fn sync_old(
     raw: &mut RawRepository,
     block_hash: &Hash256,
@@ -1,23 +1,51 @@
+pub mod attestation;
+pub mod blob;
+pub mod ceremony;
+pub mod divergence;
+pub mod epoch;
 pub mod format;
+pub mod header_cache;
 pub mod interpret;
+pub mod patch;
+pub mod progress;
+pub mod proof;
 pub mod raw;
+pub mod review;
+pub mod sources;
+pub mod transaction_index;
+pub mod transaction_pool;
 // TODO: integrate the server feature with `DistributedRepository`
 pub mod server;
 
+use attestation::{AttestationCheck, SignedAttestation};
+use blob::BlobStore;
+use divergence::{DivergenceResolution, DivergentBranch, ResolutionStrategy};
+use epoch::{Epoch, EpochIndex};
 use eyre::eyre;
 use format::*;
 use futures::prelude::*;
+use header_cache::HeaderCache;
 use interpret::*;
 use log::info;
-use raw::RawRepository;
+use patch::PatchBundle;
+use progress::ProgressReporter;
+use proof::ProofStore;
+use raw::{RawRepository, SemanticCommit};
+use review::AgendaBundle;
 use serde::{Deserialize, Serialize};
 use simperby_core::reserved::ReservedState;
 use simperby_core::utils::get_timestamp;
 use simperby_core::verify::CommitSequenceVerifier;
 use simperby_core::*;
+use sources::{FetchReport, SourceStats};
 use std::sync::Arc;
-use std::{collections::HashSet, fmt};
-use tokio::sync::RwLock;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+use tokio::sync::{Mutex, RwLock};
+use transaction_index::TransactionIndex;
+use transaction_pool::{PendingTransaction, TransactionPool};
 
 pub type Branch = String;
 pub type Tag = String;
@@ -94,7 +122,119 @@ pub struct Config {
     /// will be considered a long range attack and thus ignored.
     ///
     /// If zero, fork can be detected only from the currently last-finalized commit.
+    ///
+    /// Regardless of the distance, forks below the trusted checkpoint are always ignored.
     pub long_range_attack_distance: usize,
+    #[serde(default)]
+    pub prune_policy: PrunePolicy,
+    /// The checkpoint that this repository was bootstrapped from, if any.
+    #[serde(default)]
+    pub trusted_checkpoint: Option<TrustedCheckpoint>,
+    /// Whether to reject the agenda, block and transaction commits
+    /// without their authors' signatures.
+    ///
+    /// Signed commits are verified regardless of this.
+    #[serde(default)]
+    pub require_signed_commits: bool,
+    /// How much of the finalized history to keep; see [`DistributedRepository::prune_history`].
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+}
+
+impl Config {
+    /// Returns the lowest height of the block that a fork may start from.
+    pub fn min_fork_height(&self, last_finalized_height: BlockHeight) -> BlockHeight {
+        let height =
+            last_finalized_height.saturating_sub(self.long_range_attack_distance as BlockHeight);
+        match &self.trusted_checkpoint {
+            Some(checkpoint) => height.max(checkpoint.height),
+            None => height,
+        }
+    }
+}
+
+/// A block pinned by social consensus (e.g., announced by the validators),
+/// which lets a node bootstrap without the history before it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrustedCheckpoint {
+    pub height: BlockHeight,
+    /// The hash of the block header.
+    pub hash: Hash256,
+}
+
+/// The pinned block header with its finalization proof.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CheckpointBundle {
+    pub header: BlockHeader,
+    pub finalization_proof: FinalizationProof,
+}
+
+impl CheckpointBundle {
+    /// Verifies the bundle against the checkpoint, using the validator set embedded in the header.
+    pub fn verify(&self, checkpoint: &TrustedCheckpoint) -> Result<(), String> {
+        if self.header.height != checkpoint.height {
+            return Err(format!(
+                "height mismatch: expected {}, got {}",
+                checkpoint.height, self.header.height
+            ));
+        }
+        if self.header.to_hash256() != checkpoint.hash {
+            return Err(format!(
+                "hash mismatch: expected {}, got {}",
+                checkpoint.hash,
+                self.header.to_hash256()
+            ));
+        }
+        verify::verify_finalization_proof(&self.header, &self.finalization_proof)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Decides which branches survive [`DistributedRepository::prune`].
+///
+/// The `finalized`, `fp` and `work` branches and the agenda (`a-#`) branches
+/// on top of the `finalized` branch are always kept.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PrunePolicy {
+    /// The number of the most recent block candidate (`b-#`) branches to keep.
+    pub block_candidates_to_keep: usize,
+}
+
+impl Default for PrunePolicy {
+    fn default() -> Self {
+        Self {
+            block_candidates_to_keep: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub deleted_branches: Vec<Branch>,
+    /// The space reclaimed by the garbage collection, in bytes.
+    pub reclaimed_bytes: u64,
+}
+
+/// How much of the finalized history a repository keeps.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keeps the whole history.
+    #[default]
+    Archive,
+    /// Keeps the commits of the last `heights_to_keep` blocks (at least),
+    /// and only the headers and the finalization proofs of the older ones.
+    ///
+    /// The history is cut in steps of `heights_to_keep` blocks,
+    /// so up to twice as many are kept in between.
+    Pruned { heights_to_keep: BlockHeight },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryPruneReport {
+    /// The height of the block that the history now starts from.
+    pub boundary_height: BlockHeight,
+    /// The space reclaimed by the garbage collection, in bytes.
+    pub reclaimed_bytes: u64,
 }
 
 /// The local Simperby blockchain data repository.
@@ -102,11 +242,24 @@ pub struct Config {
 /// It automatically locks the repository once created.
 ///
 /// - It **verifies** all the incoming changes and applies them to the local repository
-/// only if they are valid.
+///   only if they are valid.
 pub struct DistributedRepository {
     /// We keep the `RawRepository` in a `RwLock` for possible concurrent accesses in some operations.
     raw: Arc<RwLock<RawRepository>>,
-    _config: Config,
+    blob_store: Option<BlobStore>,
+    transaction_pool: Option<TransactionPool>,
+    proof_store: Option<ProofStore>,
+    signing_key: Option<PrivateKey>,
+    progress_reporter: ProgressReporter,
+    /// The performance of each remote, recorded by [`Self::fetch`].
+    source_stats: BTreeMap<String, SourceStats>,
+    /// In a `Mutex` to be filled by the read-only operations as well.
+    header_cache: Mutex<HeaderCache>,
+    transaction_index: Mutex<TransactionIndex>,
+    epoch_index: Mutex<EpochIndex>,
+    /// The vetoes that block the approvals; see [`Self::set_agenda_vetoes`].
+    agenda_vetoes: Vec<SignedAgendaVeto>,
+    config: Config,
 }
 
 impl DistributedRepository {
@@ -114,13 +267,118 @@ impl DistributedRepository {
         Arc::clone(&self.raw)
     }
 
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Sets whether to reject the unsigned commits, taking effect from the next fetch.
+    pub fn set_require_signed_commits(&mut self, require_signed_commits: bool) {
+        self.config.require_signed_commits = require_signed_commits;
+    }
+
+    /// Sets the vetoes of the governance, which block the vetoed agendas in [`Self::approve`],
+    /// taking effect from the next call.
+    ///
+    /// The vetoes are known only locally, not in the history, so the agenda proofs
+    /// received in [`Self::sync`] are accepted regardless.
+    pub fn set_agenda_vetoes(&mut self, vetoes: Vec<SignedAgendaVeto>) {
+        self.agenda_vetoes = vetoes;
+    }
+
     pub async fn new(raw: Arc<RwLock<RawRepository>>, config: Config) -> Result<Self, Error> {
         Ok(Self {
             raw,
-            _config: config,
+            blob_store: None,
+            transaction_pool: None,
+            proof_store: None,
+            signing_key: None,
+            progress_reporter: ProgressReporter::default(),
+            source_stats: BTreeMap::new(),
+            header_cache: Mutex::new(HeaderCache::default()),
+            transaction_index: Mutex::new(TransactionIndex::default()),
+            epoch_index: Mutex::new(EpochIndex::default()),
+            agenda_vetoes: Vec::new(),
+            config,
         })
     }
 
+    /// Sets the blob store that keeps the large files referenced by transactions.
+    ///
+    /// Without it, agendas that reference blobs can't be approved.
+    pub fn set_blob_store(&mut self, blob_store: BlobStore) {
+        self.blob_store = Some(blob_store);
+    }
+
+    pub fn get_blob_store(&self) -> Option<BlobStore> {
+        self.blob_store.clone()
+    }
+
+    /// Sets the pool of the pending transactions shared among the members.
+    ///
+    /// Once set, [`Self::create_agenda`] pulls the pending transactions into the agenda.
+    pub fn set_transaction_pool(&mut self, transaction_pool: TransactionPool) {
+        self.transaction_pool = Some(transaction_pool);
+    }
+
+    pub fn get_transaction_pool(&self) -> Option<TransactionPool> {
+        self.transaction_pool.clone()
+    }
+
+    /// Sets the store that keeps the finalization proofs, one per height.
+    ///
+    /// Once set, the proofs are read from and written to the store
+    /// rather than searched for in the repository every time.
+    pub fn set_proof_store(&mut self, proof_store: ProofStore) {
+        self.proof_store = Some(proof_store);
+    }
+
+    /// Sets the storage to persist the header cache in, loading the headers already stored.
+    ///
+    /// Without it, the cache is kept only in memory.
+    pub async fn set_header_cache_storage(
+        &mut self,
+        storage: simperby_network::StorageImpl,
+    ) -> Result<(), Error> {
+        self.header_cache = Mutex::new(HeaderCache::load(storage).await?);
+        Ok(())
+    }
+
+    /// Sets the storage to persist the index of the finalized transactions in,
+    /// loading the index already stored.
+    ///
+    /// Without it, the index is rebuilt from the history on every start.
+    pub async fn set_transaction_index_storage(
+        &mut self,
+        storage: simperby_network::StorageImpl,
+    ) -> Result<(), Error> {
+        self.transaction_index = Mutex::new(TransactionIndex::load(storage).await?);
+        Ok(())
+    }
+
+    /// Sets the storage to persist the index of the epochs in, loading the index already stored.
+    ///
+    /// Without it, the index is rebuilt from the history on every start.
+    pub async fn set_epoch_storage(
+        &mut self,
+        storage: simperby_network::StorageImpl,
+    ) -> Result<(), Error> {
+        self.epoch_index = Mutex::new(EpochIndex::load(storage).await?);
+        Ok(())
+    }
+
+    /// Sets where to report the progress of fetching and verifying the commits.
+    pub fn set_progress_reporter(&mut self, progress_reporter: ProgressReporter) {
+        self.progress_reporter = progress_reporter;
+    }
+
+    /// Sets the key to sign the agenda, block and transaction commits that this node creates.
+    ///
+    /// Without it, the commits are left unsigned
+    /// and rejected by the peers that [require signed commits](Config::require_signed_commits).
+    pub fn set_signing_key(&mut self, signing_key: PrivateKey) {
+        self.signing_key = Some(signing_key);
+    }
+
     /// Initializes the genesis repository, leaving a genesis header.
     ///
     /// It also
@@ -141,12 +399,119 @@ impl DistributedRepository {
         read_last_finalization_info(&*self.raw.read().await).await
     }
 
+    /// Returns the header of the last finalized block.
+    pub async fn get_last_finalized_block_header(&self) -> Result<BlockHeader, Error> {
+        let raw = self.raw.read().await;
+        let (_, header) = self.header_cache.lock().await.last_finalized(&raw).await?;
+        Ok(header)
+    }
+
+    /// Returns the finalized block at the given height with its commit hash,
+    /// or `None` if there is no such block or if it is below the available history.
+    pub async fn get_finalized_block(
+        &self,
+        height: BlockHeight,
+    ) -> Result<Option<(CommitHash, BlockHeader)>, Error> {
+        let raw = self.raw.read().await;
+        self.header_cache.lock().await.get(&raw, height).await
+    }
+
+    /// Returns the header of the finalized block at the given height (see [`Self::get_finalized_block`]).
+    pub async fn get_header(&self, height: BlockHeight) -> Result<Option<BlockHeader>, Error> {
+        Ok(self
+            .get_finalized_block(height)
+            .await?
+            .map(|(_, header)| header))
+    }
+
+    /// Returns the header of the given commit if it is a finalized block.
+    pub async fn get_header_by_commit_hash(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<BlockHeader>, Error> {
+        let raw = self.raw.read().await;
+        self.header_cache
+            .lock()
+            .await
+            .get_by_commit_hash(&raw, commit_hash)
+            .await
+    }
+
+    /// Checks whether the transaction of the given hash (see [`Transaction::to_hash256`])
+    /// has been finalized in the available history.
+    pub async fn is_transaction_finalized(&self, transaction_hash: Hash256) -> Result<bool, Error> {
+        let raw = self.raw.read().await;
+        Ok(self
+            .transaction_index
+            .lock()
+            .await
+            .finalized_height(&raw, transaction_hash)
+            .await?
+            .is_some())
+    }
+
+    /// Returns the epoch of the block at the given height, or `None` if it's
+    /// beyond the next height to finalize or below the available history.
+    pub async fn get_epoch(&self, height: BlockHeight) -> Result<Option<Epoch>, Error> {
+        let raw = self.raw.read().await;
+        self.epoch_index.lock().await.get(&raw, height).await
+    }
+
+    /// Returns the epoch of the next block to finalize.
+    pub async fn current_epoch(&self) -> Result<Epoch, Error> {
+        let raw = self.raw.read().await;
+        self.epoch_index.lock().await.current(&raw).await
+    }
+
     /// Reads the finalization information at specific height.
     pub async fn read_finalization_info(
-        &self,
-        _height: BlockHeight,
+        &mut self,
+        height: BlockHeight,
     ) -> Result<FinalizationInfo, Error> {
-        todo!()
+        let (commit_hash, header) = self
+            .get_finalized_block(height)
+            .await?
+            .ok_or_else(|| eyre!("no finalized block at height {height}"))?;
+        let raw = self.raw.read().await;
+        let reserved_state = raw.read_reserved_state_at_commit(commit_hash).await?;
+        drop(raw);
+        let proof = self
+            .get_finalization_proof(height)
+            .await?
+            .expect("the block is finalized");
+        Ok(FinalizationInfo {
+            header,
+            commit_hash,
+            reserved_state,
+            proof,
+        })
+    }
+
+    /// Returns the finalization proof of the finalized block at the given height,
+    /// or `None` if there is no such block.
+    ///
+    /// With the proof store set, a proof read from the repository is added to it.
+    pub async fn get_finalization_proof(
+        &mut self,
+        height: BlockHeight,
+    ) -> Result<Option<FinalizationProof>, Error> {
+        if let Some(proof_store) = &self.proof_store {
+            if let Some(proof) = proof_store.get(height).await? {
+                return Ok(Some(proof));
+            }
+        }
+        let (commit_hash, header) = match self.get_finalized_block(height).await? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let proof = read_finalization_proof(&*self.raw.read().await, commit_hash).await?;
+        if self.proof_store.is_some() {
+            let epoch = self.get_epoch(height).await?;
+            if let Some(proof_store) = &mut self.proof_store {
+                proof_store.put(epoch.as_ref(), &header, &proof).await?;
+            }
+        }
+        Ok(Some(proof))
     }
 
     /// Reads the given commit.
@@ -184,6 +549,41 @@ impl DistributedRepository {
         todo!()
     }
 
+    /// Verifies the repository from the trusted checkpoint
+    /// instead of the genesis, which is for the repositories with a partial history.
+    ///
+    /// Returns the commit of the checkpoint.
+    pub async fn verify_from_checkpoint(
+        &self,
+        checkpoint: &TrustedCheckpoint,
+    ) -> Result<CommitHash, Error> {
+        verify_from_checkpoint(&*self.raw.read().await, checkpoint).await
+    }
+
+    /// Replays the finalized history from the given height; see [`verify_finalized_history`].
+    pub async fn verify_finalized_history(
+        &self,
+        from_height: BlockHeight,
+    ) -> Result<HistoryVerificationReport, Error> {
+        verify_finalized_history(&*self.raw.read().await, from_height).await
+    }
+
+    /// Reads a file of the finalized block with its inclusion proof; see [`read_finalized_file`].
+    pub async fn read_finalized_file(
+        &self,
+        height: BlockHeight,
+        path: &str,
+    ) -> Result<Option<FinalizedFile>, Error> {
+        read_finalized_file(&*self.raw.read().await, height, path).await
+    }
+
+    /// Verifies both layers of the signatures of the finalized commits.
+    ///
+    /// See [`verify_commit_signatures`].
+    pub async fn verify_commit_signatures(&self) -> Result<Vec<CommitSignatureReport>, Error> {
+        verify_commit_signatures(&*self.raw.read().await).await
+    }
+
     // ---------------
     // Operations that interact with possible local works
     // (manually added commits or remote tracking branches)
@@ -201,15 +601,135 @@ impl DistributedRepository {
     /// This will verify every commit along the way.
     /// If the given commit is not a descendant of the
     /// current `finalized` (i.e., cannot be fast-forwarded), it fails.
+    ///
+    /// A branch forking below [`Config::min_fork_height`] is rejected as a long range attack.
     pub async fn sync(&mut self, commit_hash: CommitHash) -> Result<Result<(), String>, Error> {
-        sync(&mut *self.raw.write().await, commit_hash).await
+        let mut raw = self.raw.write().await;
+        if let Err(e) =
+            check_long_range_attack(&raw, commit_hash, &self.config, self.header_cache.get_mut())
+                .await?
+        {
+            return Ok(Err(e));
+        }
+        sync(
+            &mut raw,
+            commit_hash,
+            &self.config,
+            &self.progress_reporter,
+            self.transaction_index.get_mut(),
+        )
+        .await
     }
 
     /// Performs `sync()` on all local branches and remote tracking branches on the repository.
     ///
     /// Returns the list of `(branch name, result of sync())`.
     pub async fn sync_all(&mut self) -> Result<Vec<(String, Result<(), String>)>, Error> {
-        sync_all(&mut *self.raw.write().await).await
+        sync_all(
+            &mut *self.raw.write().await,
+            &self.config,
+            &self.progress_reporter,
+            &sources::priority(&self.source_stats),
+            self.header_cache.get_mut(),
+            self.transaction_index.get_mut(),
+        )
+        .await
+    }
+
+    /// Fetches all the remote repositories in parallel, which are to be verified by [`Self::sync_all`].
+    ///
+    /// It records the performance of each remote, so that the branches of the better ones
+    /// are verified first, and cross-checks their `finalized` branches.
+    /// Diverged ones are reported as possible forks rather than failing the fetch,
+    /// along with the evidence if both are really finalized.
+    ///
+    /// The [attestations](attestation) of the remotes are checked against what's fetched.
+    pub async fn fetch(&mut self) -> Result<FetchReport, Error> {
+        let mut raw = self.raw.write().await;
+        let fetches = raw
+            .fetch_all_with_progress(self.progress_reporter.clone())
+            .await?;
+        for fetch in &fetches {
+            if let Err(e) = &fetch.result {
+                log::warn!("failed to fetch from {}: {e}", fetch.remote);
+            }
+            self.source_stats
+                .entry(fetch.remote.clone())
+                .or_default()
+                .record(fetch);
+        }
+        let conflicts = sources::find_finalized_conflicts(&raw).await?;
+        let mut forks = Vec::new();
+        for conflict in &conflicts {
+            log::warn!(
+                "the finalized branches of {} ({}) and {} ({}) have diverged; possibly a fork",
+                conflict.sources.0,
+                conflict.commits.0,
+                conflict.sources.1,
+                conflict.commits.1
+            );
+            if let Some(evidence) = sources::find_fork_evidence(&raw, conflict).await? {
+                log::error!(
+                    "two different blocks are finalized at height {} by {} and {}",
+                    evidence.height(),
+                    conflict.sources.0,
+                    conflict.sources.1
+                );
+                forks.push(evidence);
+            }
+        }
+        let mut attestations = Vec::new();
+        for fetch in fetches.iter().filter(|fetch| fetch.result.is_ok()) {
+            if let Some(result) = attestation::check_remote_attestation(&raw, &fetch.remote).await?
+            {
+                if let Err(e) = &result {
+                    log::warn!("the attestation of {} is not valid: {e}", fetch.remote);
+                }
+                attestations.push(AttestationCheck {
+                    remote: fetch.remote.clone(),
+                    result,
+                });
+            }
+        }
+        Ok(FetchReport {
+            fetches,
+            conflicts,
+            forks,
+            attestations,
+        })
+    }
+
+    /// Signs the attestation of the last finalized block and stores it for the peers,
+    /// unless it's already attested.
+    ///
+    /// It requires the [signing key](Self::set_signing_key).
+    pub async fn refresh_attestation(&mut self) -> Result<SignedAttestation, Error> {
+        let signing_key = self
+            .signing_key
+            .as_ref()
+            .ok_or_else(|| eyre!("no signing key to attest with"))?;
+        let mut raw = self.raw.write().await;
+        let commit_hash = get_last_finalized_block_commit_hash(&raw).await?;
+        if let Some(signed) = attestation::read_attestation(&raw, None).await? {
+            if signed.attestation.commit_hash == commit_hash
+                && signed.signature.signer() == &signing_key.public_key()
+            {
+                return Ok(signed);
+            }
+        }
+        let signed = attestation::create_attestation(&raw, signing_key, get_timestamp()).await?;
+        attestation::write_attestation(&mut raw, &signed).await?;
+        Ok(signed)
+    }
+
+    /// Reads the attestation that this repository serves, if any.
+    pub async fn read_attestation(&self) -> Result<Option<SignedAttestation>, Error> {
+        attestation::read_attestation(&*self.raw.read().await, None).await
+    }
+
+    /// Returns the performance of each remote recorded by [`Self::fetch`].
+    pub fn get_source_stats(&self) -> &BTreeMap<String, SourceStats> {
+        &self.source_stats
     }
 
     /// Cleans all the outdated commits, remote repositories and branches.
@@ -218,17 +738,126 @@ impl DistributedRepository {
     /// - the `finalized` branch
     /// - the `work` branch
     /// - the `fp` branch
+    ///
     /// when `hard` is `true`,
     ///
     /// and when `hard` is `false`,
     /// - the `p` branch
     /// - the `a-#` branches
     /// - the `b-#` branches
+    ///
     /// will be left as well
     /// if only the branches have valid commit sequences
     /// and are not outdated (branched from the last finalized commit).
+    ///
+    /// Finally, it [prunes](Self::prune) the repository.
     pub async fn clean(&mut self, hard: bool) -> Result<(), Error> {
-        clean(&mut *self.raw.write().await, hard).await
+        clean(&mut *self.raw.write().await, hard).await?;
+        self.prune().await?;
+        Ok(())
+    }
+
+    /// Returns the local branches that don't extend the finalized tip, with the reasons.
+    pub async fn get_divergent_branches(&self) -> Result<Vec<DivergentBranch>, Error> {
+        divergence::read_divergent_branches(&*self.raw.read().await).await
+    }
+
+    /// Discards the divergent branch or rebases it onto the finalized tip;
+    /// see [`ResolutionStrategy`].
+    pub async fn resolve_divergence(
+        &mut self,
+        branch: Branch,
+        strategy: ResolutionStrategy,
+    ) -> Result<DivergenceResolution, Error> {
+        divergence::resolve_divergence(
+            &mut *self.raw.write().await,
+            branch,
+            strategy,
+            self.transaction_index.get_mut(),
+        )
+        .await
+    }
+
+    /// Exports the agenda of the given commit for an offline review, sealed with the given key.
+    pub async fn export_agenda_bundle(
+        &self,
+        agenda_commit: CommitHash,
+        private_key: &PrivateKey,
+    ) -> Result<AgendaBundle, Error> {
+        AgendaBundle::create(&*self.raw.read().await, agenda_commit, private_key).await
+    }
+
+    /// Removes the stale branches according to the prune policy
+    /// and collects the objects that became unreachable.
+    pub async fn prune(&mut self) -> Result<PruneReport, Error> {
+        prune(&mut *self.raw.write().await, &self.config.prune_policy).await
+    }
+
+    /// Cuts the finalized history by the [retention policy](RetentionPolicy),
+    /// returning `None` if there is nothing (enough) to cut.
+    ///
+    /// The headers and the finalization proofs of the blocks to cut are stored first,
+    /// so it requires the [proof store](Self::set_proof_store)
+    /// and the [header cache storage](Self::set_header_cache_storage).
+    /// The history within the long range attack distance (see [`Config::min_fork_height`])
+    /// is kept regardless of the policy, to detect the forks.
+    ///
+    /// The contents of the cut blocks, including the reserved states and the transactions,
+    /// are no longer available, nor are the branches and the tags on them;
+    /// the index of the finalized transactions keeps them only while it's not rebuilt.
+    pub async fn prune_history(&mut self) -> Result<Option<HistoryPruneReport>, Error> {
+        let RetentionPolicy::Pruned { heights_to_keep } = self.config.retention else {
+            return Ok(None);
+        };
+        if self.proof_store.is_none() || !self.header_cache.get_mut().is_persistent() {
+            return Err(eyre!(
+                "pruning the history requires the proof store and the header cache storage"
+            ));
+        }
+        let last_height = self.get_last_finalized_block_header().await?.height;
+        // The boundary commit itself can't be read as a block, so it's one below the kept ones.
+        let boundary_height = last_height
+            .saturating_sub(heights_to_keep)
+            .min(self.config.min_fork_height(last_height).saturating_sub(1));
+        let start_height = self.get_history_start_height().await?;
+        if boundary_height < start_height + heights_to_keep.max(1) {
+            return Ok(None);
+        }
+        for height in start_height..=boundary_height {
+            if self.get_finalization_proof(height).await?.is_none() {
+                return Err(eyre!(IntegrityError::new(format!(
+                    "no finalized block at height {height} to prune"
+                ))));
+            }
+        }
+        let (commit_hash, _) = self
+            .get_finalized_block(boundary_height)
+            .await?
+            .expect("the proof is found");
+        let reclaimed_bytes = cut_history(&mut *self.raw.write().await, commit_hash).await?;
+        info!(
+            "cut the history below height {}, reclaiming {} bytes",
+            boundary_height, reclaimed_bytes
+        );
+        Ok(Some(HistoryPruneReport {
+            boundary_height,
+            reclaimed_bytes,
+        }))
+    }
+
+    /// Returns the height of the oldest finalized block in the available history.
+    async fn get_history_start_height(&self) -> Result<BlockHeight, Error> {
+        let boundaries = self.raw.read().await.get_shallow_boundaries().await?;
+        let mut start_height = None;
+        for commit_hash in boundaries {
+            // The boundary of a shallow clone may not be a block.
+            if let Some(header) = self.get_header_by_commit_hash(commit_hash).await? {
+                start_height = start_height.max(Some(header.height));
+            }
+        }
+        Ok(start_height
+            .or_else(|| self.config.trusted_checkpoint.as_ref().map(|x| x.height))
+            .unwrap_or(0))
     }
 
     // ---------------
@@ -257,7 +886,15 @@ impl DistributedRepository {
         proof: Vec<TypedSignature<Agenda>>,
         timestamp: Timestamp,
     ) -> Result<CommitHash, Error> {
-        approve(&mut *self.raw.write().await, agenda_hash, proof, timestamp).await
+        approve(
+            &mut *self.raw.write().await,
+            agenda_hash,
+            proof,
+            timestamp,
+            self.blob_store.as_ref(),
+            &self.agenda_vetoes,
+        )
+        .await
     }
 
     /// Creates an agenda commit on top of the `work` branch.
@@ -265,7 +902,113 @@ impl DistributedRepository {
         &mut self,
         author: MemberName,
     ) -> Result<(Agenda, CommitHash), Error> {
-        create_agenda(&mut *self.raw.write().await, author).await
+        self.create_agenda_with_description(author, None, None)
+            .await
+    }
+
+    /// Creates an agenda commit on top of the `work` branch, with the description
+    /// and the voting deadline which are covered by the agenda hash.
+    pub async fn create_agenda_with_description(
+        &mut self,
+        author: MemberName,
+        description: Option<AgendaDescription>,
+        deadline: Option<VotingDeadline>,
+    ) -> Result<(Agenda, CommitHash), Error> {
+        create_agenda(
+            &mut *self.raw.write().await,
+            author,
+            description,
+            deadline,
+            self.signing_key.as_ref(),
+            self.transaction_index.get_mut(),
+            self.transaction_pool.as_mut(),
+        )
+        .await
+    }
+
+    /// Returns the agenda that [`Self::create_agenda_with_description`] would create
+    /// and its commit, without writing to the repository.
+    ///
+    /// It doesn't include the pending transactions of the pool (see [`preview_agenda`]).
+    pub async fn preview_agenda(
+        &self,
+        author: MemberName,
+        description: Option<AgendaDescription>,
+        deadline: Option<VotingDeadline>,
+    ) -> Result<(Agenda, SemanticCommit), Error> {
+        preview_agenda(
+            &mut *self.raw.write().await,
+            author,
+            description,
+            deadline,
+            self.signing_key.as_ref(),
+            &mut *self.transaction_index.lock().await,
+        )
+        .await
+    }
+
+    /// Submits the transaction commit to the pool of the pending transactions,
+    /// so that the other members can pull it into their agendas.
+    pub async fn submit_pending_transaction(
+        &mut self,
+        commit_hash: CommitHash,
+    ) -> Result<Hash256, Error> {
+        let pool = self
+            .transaction_pool
+            .as_mut()
+            .ok_or_else(|| eyre!("the transaction pool is not set"))?;
+        let raw = self.raw.read().await;
+        let Commit::Transaction(transaction) = read_commit(&raw, commit_hash).await? else {
+            return Err(eyre!("commit {commit_hash} is not a transaction"));
+        };
+        if let Some(height) = self
+            .transaction_index
+            .get_mut()
+            .finalized_height(&raw, transaction.to_hash256())
+            .await?
+        {
+            return Err(eyre!(
+                "transaction {commit_hash} has already been finalized at height {height}"
+            ));
+        }
+        let transaction = PendingTransaction {
+            commit: raw.read_commit(commit_hash).await?,
+        };
+        transaction.validate(&read_last_finalized_reserved_state(&raw).await?)?;
+        pool.submit(transaction).await
+    }
+
+    /// Checks the patch of a contributor and adds it to the pool of the pending transactions,
+    /// for a member to turn it into a transaction (see [`Self::accept_patch`]).
+    pub async fn submit_patch(&mut self, bundle: PatchBundle) -> Result<Hash256, Error> {
+        bundle.verify()?;
+        self.transaction_pool
+            .as_mut()
+            .ok_or_else(|| eyre!("the transaction pool is not set"))?
+            .submit_patch(bundle)
+            .await
+    }
+
+    /// Turns the patch in the pool into a transaction commit of the given member
+    /// on top of the `work` branch, and shares the transaction in the pool in place of the patch.
+    pub async fn accept_patch(
+        &mut self,
+        author: MemberName,
+        patch_hash: Hash256,
+    ) -> Result<CommitHash, Error> {
+        let mut pool = self
+            .transaction_pool
+            .clone()
+            .ok_or_else(|| eyre!("the transaction pool is not set"))?;
+        let bundle = pool
+            .get_patch(patch_hash)
+            .await?
+            .ok_or_else(|| eyre!("patch {patch_hash} is not in the pool"))?;
+        let commit_hash =
+            create_patch_transaction(&mut *self.raw.write().await, author, &bundle).await?;
+        self.submit_pending_transaction(commit_hash).await?;
+        pool.remove(patch_hash).await?;
+        Ok(commit_hash)
     }
 
     /// Creates a block commit on top of the `work` branch.
@@ -273,7 +1016,21 @@ impl DistributedRepository {
         &mut self,
         author: PublicKey,
     ) -> Result<(BlockHeader, CommitHash), Error> {
-        create_block(&mut *self.raw.write().await, author).await
+        create_block(
+            &mut *self.raw.write().await,
+            author,
+            self.signing_key.as_ref(),
+        )
+        .await
+    }
+
+    /// Returns the block that [`Self::create_block`] would create and its commit,
+    /// without writing to the repository.
+    pub async fn preview_block(
+        &self,
+        author: PublicKey,
+    ) -> Result<(BlockHeader, SemanticCommit), Error> {
+        preview_block(&*self.raw.read().await, author, self.signing_key.as_ref()).await
     }
 
     /// Creates an extra-agenda transaction commit on top of the `work` branch.
@@ -284,13 +1041,118 @@ impl DistributedRepository {
         create_extra_agenda_transaction(&mut *self.raw.write().await, transaction).await
     }
 
+    /// Returns the commit that [`Self::create_extra_agenda_transaction`] would create,
+    /// without writing to the repository.
+    pub async fn preview_extra_agenda_transaction(
+        &self,
+        transaction: &ExtraAgendaTransaction,
+    ) -> Result<SemanticCommit, Error> {
+        preview_extra_agenda_transaction(&*self.raw.read().await, transaction).await
+    }
+
+    /// Creates a transaction commit that adds the member of the join request,
+    /// on top of the `work` branch.
+    ///
+    /// With `activation_height`, the change is scheduled to take effect
+    /// once the block of the height is finalized (see [`ReservedState::schedule`]).
+    pub async fn create_join_transaction(
+        &mut self,
+        author: MemberName,
+        request: &JoinRequest,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash, Error> {
+        create_join_transaction(
+            &mut *self.raw.write().await,
+            author,
+            request,
+            activation_height,
+            self.signing_key.as_ref(),
+        )
+        .await
+    }
+
+    /// Creates a transaction commit that takes away the consensus voting power
+    /// of the reported offline validator, on top of the `work` branch.
+    ///
+    /// `activation_height` is as in [`Self::create_join_transaction`].
+    pub async fn create_offline_report_transaction(
+        &mut self,
+        author: MemberName,
+        report: &OfflineReport,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash, Error> {
+        create_offline_report_transaction(
+            &mut *self.raw.write().await,
+            author,
+            report,
+            activation_height,
+            self.signing_key.as_ref(),
+        )
+        .await
+    }
+
+    /// Creates a transaction commit that replaces the metadata of a member,
+    /// on top of the `work` branch.
+    ///
+    /// `activation_height` is as in [`Self::create_join_transaction`].
+    pub async fn create_member_metadata_transaction(
+        &mut self,
+        author: MemberName,
+        update: &TxUpdateMemberMetadata,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash, Error> {
+        create_member_metadata_transaction(
+            &mut *self.raw.write().await,
+            author,
+            update,
+            activation_height,
+            self.signing_key.as_ref(),
+        )
+        .await
+    }
+
+    /// Creates a transaction commit that replaces the consensus leader order
+    /// with the eligible leaders ordered by the strategy, on top of the `work` branch.
+    ///
+    /// `activation_height` is as in [`Self::create_join_transaction`].
+    pub async fn create_leader_order_transaction(
+        &mut self,
+        author: MemberName,
+        strategy: LeaderOrderStrategy,
+        activation_height: Option<BlockHeight>,
+    ) -> Result<CommitHash, Error> {
+        create_leader_order_transaction(
+            &mut *self.raw.write().await,
+            author,
+            strategy,
+            activation_height,
+            self.signing_key.as_ref(),
+        )
+        .await
+    }
+
     /// Finalizes the block with the given proof. Returns the commit hash of the updated `fp` branch.
     pub async fn finalize(
         &mut self,
         block_commit_hash: CommitHash,
         proof: FinalizationProof,
     ) -> Result<CommitHash, Error> {
-        finalize(&mut *self.raw.write().await, block_commit_hash, proof).await
+        let commit_hash = finalize(
+            &mut *self.raw.write().await,
+            block_commit_hash,
+            proof.clone(),
+            &self.config,
+            self.transaction_index.get_mut(),
+        )
+        .await?;
+        let header = self.get_last_finalized_block_header().await?;
+        if self.proof_store.is_some() {
+            let epoch = self.get_epoch(header.height).await?;
+            if let Some(proof_store) = &mut self.proof_store {
+                proof_store.put(epoch.as_ref(), &header, &proof).await?;
+            }
+        }
+        Ok(commit_hash)
     }
 
     // ---------------
@@ -0,0 +1,157 @@
+//! Changes submitted by the contributors who can't push to the repository.
+//!
+//! A contributor signs the patch of their working tree with their own key (see [`PatchBundle::create`])
+//! and hands the bundle to the node of a member, which checks it and keeps it in the transaction pool.
+//! A member then turns it into a transaction commit on the `work` branch, authored by the member
+//! and recording the contributor in the body (see [`CONTRIBUTOR_PREFIX`]).
+use super::*;
+use raw::RawCommit;
+
+/// The prefix of the line in the body of a transaction that records the contributor of the patch.
+///
+/// The line is in the form of `Contributed-by: <public key>`.
+pub const CONTRIBUTOR_PREFIX: &str = "Contributed-by: ";
+
+/// A change to the repository, not bound to any commit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Patch {
+    /// The message of the transaction commit to make, the title in the first line.
+    pub message: String,
+    /// The patch, in the format of [`RawRepository::get_working_tree_patch`].
+    pub diff: String,
+    pub timestamp: Timestamp,
+}
+
+impl ToHash256 for Patch {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// A patch signed by its contributor.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchBundle {
+    pub patch: Patch,
+    pub signature: TypedSignature<Patch>,
+}
+
+impl ToHash256 for PatchBundle {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+impl PatchBundle {
+    /// Signs the patch with the key of the contributor.
+    pub fn create(patch: Patch, private_key: &PrivateKey) -> Result<Self, Error> {
+        let signature = TypedSignature::sign(&patch, private_key)?;
+        Ok(Self { patch, signature })
+    }
+
+    /// Creates a bundle from the uncommitted changes in the working tree of the repository.
+    pub async fn from_working_tree(
+        raw: &RawRepository,
+        message: String,
+        private_key: &PrivateKey,
+    ) -> Result<Self, Error> {
+        let diff = raw.get_working_tree_patch().await?;
+        if diff.is_empty() {
+            return Err(eyre!("there is no change in the working tree"));
+        }
+        Self::create(
+            Patch {
+                message,
+                diff,
+                timestamp: get_timestamp(),
+            },
+            private_key,
+        )
+    }
+
+    pub fn contributor(&self) -> &PublicKey {
+        self.signature.signer()
+    }
+
+    /// The title of the transaction to make, which is the first line of the message.
+    pub fn title(&self) -> &str {
+        self.patch.message.lines().next().unwrap_or_default()
+    }
+
+    /// Checks the format of the patch and the signature of the contributor.
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.title().trim().is_empty() {
+            return Err(eyre!("patch has an empty title"));
+        }
+        if self.title().starts_with('>') {
+            return Err(eyre!(
+                "`{}` can't be the title of a transaction; the title is reserved",
+                self.title()
+            ));
+        }
+        if self.patch.diff.is_empty() {
+            return Err(eyre!("patch is empty"));
+        }
+        raw::check_patch(&self.patch.diff).map_err(|e| eyre!("invalid patch: {e}"))?;
+        self.signature
+            .verify(&self.patch)
+            .map_err(|e| eyre!("invalid signature of the contributor: {e}"))
+    }
+
+    /// The commit that the member of the given name makes from the patch.
+    pub fn to_raw_commit(&self, author: MemberName) -> RawCommit {
+        RawCommit {
+            message: format!(
+                "{}\n\n{CONTRIBUTOR_PREFIX}{}",
+                self.patch.message.trim_end(),
+                self.contributor()
+            ),
+            diff: Some(self.patch.diff.clone()),
+            email: format!("{author}@simperby.net"),
+            author,
+            timestamp: self.patch.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn patch() -> Patch {
+        Patch {
+            message: "Add a file\n\nFrom a contributor.".to_owned(),
+            diff: "diff --git a/a.txt b/a.txt\nnew file mode 100644\nindex 0000000..d00491f\n\
+                --- /dev/null\n+++ b/a.txt\n@@ -0,0 +1 @@\n+1\n"
+                .to_owned(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn verify() {
+        setup_test();
+        let (contributor, private_key) = generate_keypair("contributor");
+        let bundle = PatchBundle::create(patch(), &private_key).unwrap();
+        bundle.verify().unwrap();
+        assert_eq!(bundle.contributor(), &contributor);
+        let commit = bundle.to_raw_commit("member-0000".to_owned());
+        assert!(commit
+            .message
+            .ends_with(&format!("\n\n{CONTRIBUTOR_PREFIX}{contributor}")));
+
+        let mut tampered = bundle.clone();
+        tampered.patch.diff = tampered.patch.diff.replace("+1", "+2");
+        assert!(tampered.verify().is_err());
+
+        let mut reserved = bundle.clone();
+        reserved.patch.message = ">agenda: 1".to_owned();
+        reserved.signature = TypedSignature::sign(&reserved.patch, &private_key).unwrap();
+        assert!(reserved.verify().is_err());
+
+        let mut malformed = bundle;
+        malformed.patch.diff = "not a patch".to_owned();
+        malformed.signature = TypedSignature::sign(&malformed.patch, &private_key).unwrap();
+        assert!(malformed.verify().is_err());
+    }
+}
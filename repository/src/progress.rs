@@ -0,0 +1,67 @@
+//! Progress reports of the long-running operations, i.e., fetching and verifying the commits.
+//!
+//! The operation is given a [`ProgressReporter`], and the latest [`Progress`]
+//! can be watched from the receiver (e.g., to draw a progress bar or to stream it to clients).
+use super::*;
+use std::time::Instant;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Progress {
+    /// Receiving the objects from a remote repository.
+    Fetching {
+        remote: String,
+        received_objects: u64,
+        total_objects: u64,
+        received_bytes: u64,
+        /// The estimated remaining time in milliseconds, if it can be estimated yet.
+        eta_ms: Option<u64>,
+    },
+    /// Verifying the received commits.
+    Verifying {
+        verified_commits: u64,
+        total_commits: u64,
+        /// The height of the last verified block.
+        height: BlockHeight,
+        /// The estimated remaining time in milliseconds, if it can be estimated yet.
+        eta_ms: Option<u64>,
+    },
+}
+
+/// The sending side of the progress reports; the clones report to the same receiver.
+///
+/// The default one reports to nowhere.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    sender: Option<Arc<watch::Sender<Option<Progress>>>>,
+}
+
+impl ProgressReporter {
+    /// Creates a reporter with the receiver of the latest progress,
+    /// which is `None` until the first report.
+    pub fn new() -> (Self, watch::Receiver<Option<Progress>>) {
+        let (sender, receiver) = watch::channel(None);
+        (
+            Self {
+                sender: Some(Arc::new(sender)),
+            },
+            receiver,
+        )
+    }
+
+    pub fn report(&self, progress: Progress) {
+        if let Some(sender) = &self.sender {
+            sender.send_replace(Some(progress));
+        }
+    }
+}
+
+/// Estimates the remaining time in milliseconds of an operation started at `started`,
+/// assuming that the rest goes at the same pace.
+pub(crate) fn estimate_remaining(started: Instant, done: u64, total: u64) -> Option<u64> {
+    if done == 0 {
+        return None;
+    }
+    let elapsed = started.elapsed().as_millis() as u64;
+    Some(elapsed * total.saturating_sub(done) / done)
+}
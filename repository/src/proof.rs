@@ -0,0 +1,163 @@
+//! The canonical store of the finalization proofs, one per height.
+//!
+//! A finalization proof appears in both the `fp` branch and the next block header,
+//! and is carried around by the node. The store keeps a single copy for each height,
+//! so that the other components can refer to it by the height instead of carrying copies.
+use super::*;
+use simperby_network::{Storage, StorageImpl};
+
+const PROOF_FILE_PREFIX: &str = "finalization-proof-";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct StoredProof {
+    /// The hash of the header that the proof finalizes.
+    header_hash: Hash256,
+    proof: FinalizationProof,
+}
+
+pub struct ProofStore {
+    storage: StorageImpl,
+}
+
+impl ProofStore {
+    pub fn new(storage: StorageImpl) -> Self {
+        Self { storage }
+    }
+
+    /// Stores the proof of the header, returning whether it was newly added.
+    ///
+    /// The proof is verified against the [`Epoch`] of the block. Without one, which is the case
+    /// for the block at the start of the available history, the block is trusted like a checkpoint
+    /// and the proof is verified against the validator set of the header.
+    ///
+    /// The first proof stored for a height is canonical;
+    /// another valid proof of the same header is not stored again.
+    /// It fails if the proof is invalid or a different header is stored at the height.
+    pub async fn put(
+        &mut self,
+        epoch: Option<&Epoch>,
+        header: &BlockHeader,
+        proof: &FinalizationProof,
+    ) -> Result<bool, Error> {
+        match epoch {
+            Some(epoch) => epoch.verify_finalization_proof(header, proof),
+            None => verify::verify_finalization_proof(header, proof).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| eyre!("invalid finalization proof: {e}"))?;
+        if let Some(stored) = self.read(header.height).await? {
+            if stored.header_hash != header.to_hash256() {
+                return Err(eyre!(IntegrityError::new(format!(
+                    "a different block is already finalized at height {}: {}",
+                    header.height, stored.header_hash
+                ))));
+            }
+            return Ok(false);
+        }
+        self.storage
+            .add_or_overwrite_file(
+                &file_name(header.height),
+                serde_spb::to_string(&StoredProof {
+                    header_hash: header.to_hash256(),
+                    proof: proof.clone(),
+                })
+                .unwrap(),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Returns the proof of the given height, if stored.
+    pub async fn get(&self, height: BlockHeight) -> Result<Option<FinalizationProof>, Error> {
+        Ok(self.read(height).await?.map(|stored| stored.proof))
+    }
+
+    /// Returns the heights of the stored proofs, in ascending order.
+    pub async fn heights(&self) -> Result<Vec<BlockHeight>, Error> {
+        let mut heights = self
+            .storage
+            .list_files()
+            .await?
+            .into_iter()
+            .filter_map(|file_name| {
+                file_name
+                    .strip_prefix(PROOF_FILE_PREFIX)?
+                    .strip_suffix(".json")?
+                    .parse::<BlockHeight>()
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        heights.sort();
+        Ok(heights)
+    }
+
+    async fn read(&self, height: BlockHeight) -> Result<Option<StoredProof>, Error> {
+        let file_name = file_name(height);
+        if !self.storage.list_files().await?.contains(&file_name) {
+            return Ok(None);
+        }
+        Ok(Some(serde_spb::from_str(
+            &self.storage.read_file(&file_name).await?,
+        )?))
+    }
+}
+
+fn file_name(height: BlockHeight) -> String {
+    format!("{PROOF_FILE_PREFIX}{height}.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_core::test_utils::*;
+    use simperby_test_suite::*;
+
+    fn sign(header: &BlockHeader, keys: &[(PublicKey, PrivateKey)]) -> FinalizationProof {
+        FinalizationProof {
+            round: 0,
+            signatures: keys
+                .iter()
+                .map(|(_, private_key)| {
+                    PrecommitSignature::sign(header.to_hash256(), 0, 0, private_key).unwrap()
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn put_and_get() {
+        setup_test();
+        let (reserved_state, keys) = generate_standard_genesis(4);
+        let header = reserved_state.genesis_info.header.clone();
+        let proof = reserved_state.genesis_info.genesis_proof.clone();
+
+        let path = create_temp_dir();
+        StorageImpl::create(&path).await.unwrap();
+        let mut store = ProofStore::new(StorageImpl::open(&path).await.unwrap());
+        let epoch = Epoch::new(0, reserved_state.clone()).unwrap();
+        let epoch = Some(&epoch);
+        assert_eq!(store.get(0).await.unwrap(), None);
+        assert!(store.put(epoch, &header, &proof).await.unwrap());
+        // Deduplicated.
+        assert!(!store.put(epoch, &header, &proof).await.unwrap());
+        assert_eq!(store.get(0).await.unwrap(), Some(proof.clone()));
+        assert_eq!(store.heights().await.unwrap(), vec![0]);
+
+        // Another block at the same height is rejected.
+        let mut other = header.clone();
+        other.timestamp += 1;
+        let other_proof = sign(&other, &keys);
+        assert!(store.put(epoch, &other, &other_proof).await.is_err());
+        // So is an invalid proof.
+        let mut next = header.clone();
+        next.height = 1;
+        assert!(store.put(epoch, &next, &proof).await.is_err());
+        // And a proof valid for the header alone, whose validator set isn't that of the epoch.
+        next.validator_set.pop();
+        let next_proof = sign(&next, &keys[..3]);
+        assert!(store.put(epoch, &next, &next_proof).await.is_err());
+        assert_eq!(store.heights().await.unwrap(), vec![0]);
+        // Without the epoch, the header is trusted.
+        assert!(store.put(None, &next, &next_proof).await.unwrap());
+        assert_eq!(store.heights().await.unwrap(), vec![0, 1]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Git's native commit signing (`git commit -S`), in addition to the in-commit signatures.
+//!
+//! A commit is signed the same way git does, so that `git verify-commit` and the hosting services
+//! can verify it with the [`GitSigningKey`]s of the members.
+use super::*;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The namespace that git uses for the SSH signatures.
+const SSH_NAMESPACE: &str = "git";
+const SSH_SIGNATURE_HEADER: &str = "-----BEGIN SSH SIGNATURE-----";
+const GPG_SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// The private key to sign the commits with git's native signing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GitSigner {
+    /// The path to an OpenSSH private key, as `user.signingkey` with `gpg.format=ssh`.
+    Ssh { key_path: String },
+    /// The id of a secret key in the local GnuPG keyring.
+    Gpg { key_id: String },
+}
+
+impl GitSigner {
+    /// Creates an ASCII-armored detached signature on the data.
+    pub fn sign(&self, data: &[u8]) -> Result<String, Error> {
+        let signature = match self {
+            GitSigner::Ssh { key_path } => run(
+                Command::new("ssh-keygen").args([
+                    "-Y",
+                    "sign",
+                    "-n",
+                    SSH_NAMESPACE,
+                    "-f",
+                    key_path,
+                ]),
+                data,
+            )?,
+            GitSigner::Gpg { key_id } => run(
+                Command::new("gpg").args(["--batch", "--detach-sign", "--armor", "-u", key_id]),
+                data,
+            )?,
+        };
+        String::from_utf8(signature)
+            .map_err(|_| Error::Unknown("the signature is not valid utf-8".to_string()))
+    }
+}
+
+/// A git signature on a commit, with the commit object that it signs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSignature {
+    pub signature: String,
+    pub signed_data: Vec<u8>,
+}
+
+impl GitSignature {
+    /// Verifies the signature with the git signing keys of the members, returning the signer.
+    pub fn verify(&self, members: &[Member]) -> Result<MemberName, String> {
+        let is_ssh = self.signature.starts_with(SSH_SIGNATURE_HEADER);
+        if !is_ssh && !self.signature.starts_with(GPG_SIGNATURE_HEADER) {
+            return Err("unknown signature format".to_owned());
+        }
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let signature_path = dir.path().join("signature");
+        std::fs::write(&signature_path, &self.signature).map_err(|e| e.to_string())?;
+        let signature_path = signature_path.to_str().expect("temp path is valid utf-8");
+        for member in members {
+            let verified = match &member.git_signing_key {
+                Some(GitSigningKey::Ssh(key)) if is_ssh => {
+                    // The principal doesn't matter as the file has only one entry.
+                    let allowed_signers = dir.path().join("allowed_signers");
+                    std::fs::write(&allowed_signers, format!("signer {key}\n"))
+                        .map_err(|e| e.to_string())?;
+                    run(
+                        Command::new("ssh-keygen").args([
+                            "-Y",
+                            "verify",
+                            "-n",
+                            SSH_NAMESPACE,
+                            "-I",
+                            "signer",
+                            "-s",
+                            signature_path,
+                            "-f",
+                            allowed_signers.to_str().expect("temp path is valid utf-8"),
+                        ]),
+                        &self.signed_data,
+                    )
+                    .is_ok()
+                }
+                Some(GitSigningKey::Gpg(key)) if !is_ssh => {
+                    // A keyring that has only the key of the member.
+                    let home = tempfile::tempdir_in(dir.path()).map_err(|e| e.to_string())?;
+                    let home = home.path().to_str().expect("temp path is valid utf-8");
+                    run(
+                        Command::new("gpg").args(["--batch", "--homedir", home, "--import"]),
+                        key.as_bytes(),
+                    )
+                    .map_err(|e| format!("invalid gpg key of {}: {e}", member.name))?;
+                    run(
+                        Command::new("gpg").args([
+                            "--batch",
+                            "--homedir",
+                            home,
+                            "--verify",
+                            signature_path,
+                            "-",
+                        ]),
+                        &self.signed_data,
+                    )
+                    .is_ok()
+                }
+                _ => false,
+            };
+            if verified {
+                return Ok(member.name.clone());
+            }
+        }
+        Err("the signature doesn't match any member's git signing key".to_owned())
+    }
+}
+
+/// Runs the command with the given input, returning its output.
+fn run(command: &mut Command, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Unknown(format!("failed to execute {program}: {e}")))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input)
+        .map_err(|e| Error::Unknown(format!("failed to write to {program}: {e}")))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::Unknown(format!("failed to wait on {program}: {e}")))?;
+    if !output.status.success() {
+        return Err(Error::Unknown(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(output.stdout)
+}
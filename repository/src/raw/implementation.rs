@@ -10,6 +10,7 @@ impl fmt::Debug for RawRepositoryInner {
 
 pub(crate) struct RawRepositoryInner {
     repo: Repository,
+    git_signer: Option<GitSigner>,
 }
 
 /// TODO: Error handling and its messages
@@ -52,7 +53,10 @@ impl RawRepositoryInner {
                     let tree = repo.find_tree(id)?;
                     repo.commit(Some("HEAD"), &sig, &sig, init_commit_message, &tree, &[])?;
                 }
-                Ok(Self { repo })
+                Ok(Self {
+                    repo,
+                    git_signer: None,
+                })
             }
         }
     }
@@ -62,7 +66,10 @@ impl RawRepositoryInner {
         Self: Sized,
     {
         let repo = Repository::open(directory)?;
-        Ok(Self { repo })
+        Ok(Self {
+            repo,
+            git_signer: None,
+        })
     }
 
     pub(crate) fn clone(directory: &str, url: &str) -> Result<Self, Error>
@@ -73,7 +80,43 @@ impl RawRepositoryInner {
         let mut config = repo.config()?;
         config.set_str("receive.advertisePushOptions", "true")?;
         config.set_str("sendpack.sideband", "false")?;
-        Ok(Self { repo })
+        Ok(Self {
+            repo,
+            git_signer: None,
+        })
+    }
+
+    pub(crate) fn clone_shallow(directory: &str, url: &str, depth: usize) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        run_command(format!(
+            "git clone --quiet --depth {depth} --no-single-branch \"{url}\" \"{directory}\""
+        ))?;
+        let repo = Repository::open(directory)?;
+        // Create the local branches, which are required to interpret the repository.
+        for branch in repo.branches(Some(BranchType::Remote))? {
+            let (branch, _) = branch?;
+            let name = branch
+                .name()?
+                .ok_or_else(|| Error::Unknown("branch name is not valid utf-8".to_string()))?;
+            let local_name = name.split_once('/').map(|(_, x)| x).unwrap_or(name);
+            if local_name == "HEAD" || repo.find_branch(local_name, BranchType::Local).is_ok() {
+                continue;
+            }
+            repo.branch(local_name, &branch.get().peel_to_commit()?, false)?;
+        }
+        let mut config = repo.config()?;
+        config.set_str("receive.advertisePushOptions", "true")?;
+        config.set_str("sendpack.sideband", "false")?;
+        Ok(Self {
+            repo,
+            git_signer: None,
+        })
+    }
+
+    pub(crate) fn is_shallow(&self) -> bool {
+        self.repo.is_shallow()
     }
 
     pub(crate) fn retrieve_commit_hash(
@@ -325,25 +368,18 @@ impl RawRepositoryInner {
         &mut self,
         commit: SemanticCommit,
     ) -> Result<CommitHash, Error> {
+        let commit_message = to_commit_message(&commit);
         match commit.diff {
             Diff::None => {
                 let sig = self.repo.signature()?;
                 let mut index = self.repo.index()?;
                 let id = index.write_tree()?;
                 let tree = self.repo.find_tree(id)?;
-                let commit_message = format!("{}{}{}", commit.title, "\n\n", commit.body); // TODO: Check "\n" divides commit message's head and body.
                 let head = self.get_head()?;
                 let parent_oid = Oid::from_bytes(&head.hash)?;
                 let parent_commit = self.repo.find_commit(parent_oid)?;
 
-                let oid = self.repo.commit(
-                    Some("HEAD"),
-                    &sig,
-                    &sig,
-                    &commit_message,
-                    &tree,
-                    &[&parent_commit],
-                )?;
+                let oid = self.commit_on_head(&sig, &commit_message, &tree, &parent_commit)?;
                 let hash = <[u8; 20]>::try_from(oid.as_bytes())
                     .map_err(|_| Error::Unknown("err".to_string()))?;
                 Ok(CommitHash { hash })
@@ -363,19 +399,11 @@ impl RawRepositoryInner {
                 let sig = self.repo.signature()?;
                 let id = index.write_tree()?;
                 let tree = self.repo.find_tree(id)?;
-                let commit_message = format!("{}{}{}", commit.title, "\n\n", commit.body); // TODO: Check "\n" divides commit message's head and body.
                 let head = self.get_head()?;
                 let parent_oid = Oid::from_bytes(&head.hash)?;
                 let parent_commit = self.repo.find_commit(parent_oid)?;
 
-                let oid = self.repo.commit(
-                    Some("HEAD"),
-                    &sig,
-                    &sig,
-                    &commit_message,
-                    &tree,
-                    &[&parent_commit],
-                )?;
+                let oid = self.commit_on_head(&sig, &commit_message, &tree, &parent_commit)?;
                 let hash = <[u8; 20]>::try_from(oid.as_bytes())
                     .map_err(|_| Error::Unknown("err".to_string()))?;
                 Ok(CommitHash { hash })
@@ -389,12 +417,86 @@ impl RawRepositoryInner {
         }
     }
 
+    pub(crate) fn set_git_signer(&mut self, git_signer: Option<GitSigner>) {
+        self.git_signer = git_signer;
+    }
+
+    /// Creates a commit on `HEAD`, signing it if the git signer is set.
+    fn commit_on_head(
+        &self,
+        sig: &git2::Signature,
+        message: &str,
+        tree: &git2::Tree,
+        parent: &git2::Commit,
+    ) -> Result<Oid, Error> {
+        let git_signer = match &self.git_signer {
+            Some(git_signer) => git_signer,
+            None => {
+                return Ok(self
+                    .repo
+                    .commit(Some("HEAD"), sig, sig, message, tree, &[parent])?)
+            }
+        };
+        let buffer = self
+            .repo
+            .commit_create_buffer(sig, sig, message, tree, &[parent])?;
+        let content = buffer
+            .as_str()
+            .ok_or_else(|| Error::Unknown("commit content is not valid utf-8".to_string()))?;
+        let signature = git_signer.sign(content.as_bytes())?;
+        let oid = self.repo.commit_signed(content, &signature, None)?;
+        // Unlike `commit()`, `commit_signed()` doesn't update `HEAD`.
+        self.repo.head()?.set_target(oid, "commit (signed)")?;
+        Ok(oid)
+    }
+
+    /// Reads the git's native signature of the commit, if any.
+    pub(crate) fn read_git_signature(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<GitSignature>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        match self.repo.extract_signature(&oid, None) {
+            Ok((signature, signed_data)) => Ok(Some(GitSignature {
+                signature: signature
+                    .as_str()
+                    .ok_or_else(|| Error::Unknown("signature is not valid utf-8".to_string()))?
+                    .to_owned(),
+                signed_data: signed_data.to_vec(),
+            })),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub(crate) fn read_semantic_commit(
         &self,
         commit_hash: CommitHash,
+    ) -> Result<SemanticCommit, Error> {
+        self.read_semantic_commit_bounded(commit_hash, 0)
+    }
+
+    pub(crate) fn read_semantic_commit_bounded(
+        &self,
+        commit_hash: CommitHash,
+        max_body_size: u64,
     ) -> Result<SemanticCommit, Error> {
         let oid = Oid::from_bytes(&commit_hash.hash)?;
         let commit = self.repo.find_commit(oid)?;
+
+        let title = commit.summary();
+        let title = title.unwrap_or_default().to_string();
+        let body = commit.body();
+        let body = body.unwrap_or_default().to_string();
+        let (body, signature) = split_commit_signature(body)?;
+        // Checked before the diff, which can be far larger than the body.
+        if max_body_size != 0 && body.len() as u64 > max_body_size {
+            return Err(Error::InvalidRepository(format!(
+                "the body of commit {commit_hash} is too large: {} > {max_body_size}",
+                body.len()
+            )));
+        }
+
         let tree = commit.tree()?;
         let parent_tree = commit.parent(0)?.tree()?;
 
@@ -405,29 +507,16 @@ impl RawRepositoryInner {
 
         let diff = if diff.deltas().len() == 0 {
             Diff::None
+        } else if diff.deltas().all(|delta| {
+            [delta.old_file().path(), delta.new_file().path()]
+                .into_iter()
+                .flatten()
+                .all(|path| path.starts_with("reserved"))
+        }) {
+            Diff::Reserved(Box::new(self.read_reserved_state_at_commit(commit_hash)?))
         } else {
-            let patch = self.show_commit(commit_hash)?;
-            let hash = patch.to_hash256();
-            Diff::NonReserved(hash)
+            Diff::NonReserved(self.hash_patch(&commit)?)
         };
-        /* TODO: If reserved state
-        let reserved_state = self.read_reserved_state()?;
-        Diff::Reserved(Box::new(reserved_state))*/
-
-        let title = commit.summary();
-        let title = if let Some(msg_title) = title {
-            msg_title
-        } else {
-            ""
-        }
-        .to_string();
-        let body = commit.body();
-        let body = if let Some(msg_body) = body {
-            msg_body
-        } else {
-            ""
-        }
-        .to_string();
 
         let semantic_commit = SemanticCommit {
             title,
@@ -439,12 +528,165 @@ impl RawRepositoryInner {
                 .ok_or_else(|| Error::Unknown("failed to parse commit author".to_string()))?
                 .to_owned(),
             timestamp: commit.author().when().seconds() * 1000,
+            signature,
         };
         Ok(semantic_commit)
     }
 
-    pub(crate) fn run_garbage_collection(&mut self) -> Result<(), Error> {
-        todo!()
+    /// Hashes the patch of the commit, which is the same as hashing the result of `show_commit()`.
+    ///
+    /// The patch is hashed right from the buffer of libgit2, not copied into a string.
+    fn hash_patch(&self, commit: &git2::Commit) -> Result<Hash256, Error> {
+        let email = Email::from_commit(commit, &mut EmailCreateOptions::new())?;
+        // The same check as `show_commit()`, not to hash what it can't show.
+        str::from_utf8(email.as_slice()).map_err(|_| Error::Unknown("err".to_string()))?;
+        Ok(Hash256::hash(email.as_slice()))
+    }
+
+    pub(crate) fn gc(&mut self) -> Result<u64, Error> {
+        let objects_path = self.repo.path().join("objects");
+        let size_before = directory_size(&objects_path)?;
+
+        // Same as `git reflog expire --expire=now --all`.
+        let mut roots = Vec::new();
+        for reference in self.repo.references()? {
+            let reference = reference?;
+            if let Some(name) = reference.name() {
+                self.repo.reflog_delete(name)?;
+            }
+            if let Some(oid) = reference.resolve()?.target() {
+                roots.push(oid);
+            }
+        }
+        self.repo.reflog_delete("HEAD")?;
+        if let Ok(head) = self.repo.head() {
+            roots.extend(head.target());
+        }
+
+        // Pack the objects reachable from the references and the index,
+        // not going beyond the shallow boundaries.
+        let shallow_boundaries = self.get_shallow_boundaries()?;
+        let boundaries = shallow_boundaries
+            .iter()
+            .map(|x| Oid::from_bytes(&x.hash))
+            .collect::<Result<HashSet<_>, _>>()?;
+        let mut packbuilder = self.repo.packbuilder()?;
+        let mut visited = HashSet::new();
+        while let Some(oid) = roots.pop() {
+            if !visited.insert(oid) {
+                continue;
+            }
+            let object = self.repo.find_object(oid, None)?;
+            match object.kind() {
+                Some(ObjectType::Commit) => {
+                    packbuilder.insert_commit(oid)?;
+                    if !boundaries.contains(&oid) {
+                        roots.extend(object.peel_to_commit()?.parent_ids());
+                    }
+                }
+                Some(ObjectType::Tag) => {
+                    packbuilder.insert_object(oid, None)?;
+                    roots.push(object.peel(ObjectType::Any)?.id());
+                }
+                _ => packbuilder.insert_recursive(oid, None)?,
+            }
+        }
+        // Drop the boundaries that fell out of the history, as `git gc` does.
+        if !shallow_boundaries.is_empty() {
+            let content = shallow_boundaries
+                .iter()
+                .filter(|x| Oid::from_bytes(&x.hash).is_ok_and(|oid| visited.contains(&oid)))
+                .map(|x| format!("{x}\n"))
+                .collect::<String>();
+            let shallow_path = self.repo.path().join("shallow");
+            if content.is_empty() {
+                std::fs::remove_file(shallow_path)
+            } else {
+                std::fs::write(shallow_path, content)
+            }
+            .map_err(|e| Error::Unknown(e.to_string()))?;
+        }
+        let odb = self.repo.odb()?;
+        for entry in self.repo.index()?.iter() {
+            if odb.exists(entry.id) {
+                packbuilder.insert_object(entry.id, None)?;
+            }
+        }
+        let mut buf = git2::Buf::new();
+        packbuilder.write_buf(&mut buf)?;
+        // The indexer names the pack after its trailing checksum.
+        let pack_name = format!("pack-{}", hex::encode(&buf[buf.len().saturating_sub(20)..]));
+        let mut packwriter = odb.packwriter()?;
+        std::io::Write::write_all(&mut packwriter, &buf)
+            .map_err(|e| Error::Unknown(e.to_string()))?;
+        packwriter.commit()?;
+        drop(packwriter);
+        drop(odb);
+        drop(packbuilder);
+        let pack_path = objects_path.join("pack");
+        if !pack_path.join(format!("{pack_name}.idx")).exists() {
+            return Err(Error::Unknown(format!("failed to write {pack_name}")));
+        }
+
+        // Remove everything else.
+        for entry in std::fs::read_dir(&objects_path).map_err(|e| Error::Unknown(e.to_string()))? {
+            let path = entry.map_err(|e| Error::Unknown(e.to_string()))?.path();
+            let is_loose_objects = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| x.len() == 2 && x.chars().all(|c| c.is_ascii_hexdigit()));
+            if is_loose_objects {
+                std::fs::remove_dir_all(&path).map_err(|e| Error::Unknown(e.to_string()))?;
+            }
+        }
+        for entry in std::fs::read_dir(&pack_path).map_err(|e| Error::Unknown(e.to_string()))? {
+            let path = entry.map_err(|e| Error::Unknown(e.to_string()))?.path();
+            let is_stale = path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .is_none_or(|x| x != pack_name);
+            if is_stale {
+                std::fs::remove_file(&path).map_err(|e| Error::Unknown(e.to_string()))?;
+            }
+        }
+        let commit_graph_path = objects_path.join("info").join("commit-graph");
+        if commit_graph_path.exists() {
+            std::fs::remove_file(&commit_graph_path).map_err(|e| Error::Unknown(e.to_string()))?;
+        }
+        // Reopen to drop the cached objects and the stale packfiles.
+        self.repo = Repository::open(self.repo.path())?;
+        let size_after = directory_size(&objects_path)?;
+        Ok(size_before.saturating_sub(size_after))
+    }
+
+    pub(crate) fn cut_history(&mut self, commit_hash: CommitHash) -> Result<u64, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        self.repo.find_commit(oid)?;
+        let mut boundaries = self.get_shallow_boundaries()?;
+        if !boundaries.contains(&commit_hash) {
+            boundaries.push(commit_hash);
+        }
+        // The boundaries that fall out of the history are dropped by the garbage collection.
+        let content = boundaries
+            .iter()
+            .map(|x| format!("{x}\n"))
+            .collect::<String>();
+        std::fs::write(self.repo.path().join("shallow"), content)
+            .map_err(|e| Error::Unknown(e.to_string()))?;
+        self.gc()
+    }
+
+    pub(crate) fn get_shallow_boundaries(&self) -> Result<Vec<CommitHash>, Error> {
+        let content = match std::fs::read_to_string(self.repo.path().join("shallow")) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Unknown(e.to_string())),
+        };
+        content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| to_commit_hash(Oid::from_str(line)?))
+            .collect()
     }
 
     pub(crate) fn checkout_clean(&mut self) -> Result<(), Error> {
@@ -600,18 +842,43 @@ impl RawRepositoryInner {
         let diff = self
             .repo
             .diff_tree_to_tree(Some(&parent_tree), Some(&tree), None)?;
+        print_patch(&diff)
+    }
 
-        let mut patch = String::new();
-        diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
-            match line.origin() {
-                ' ' | '+' | '-' => patch.push_str(&line.origin().to_string()),
-                _ => {}
+    pub(crate) fn get_changed_paths(&self, commit_hash: CommitHash) -> Result<Vec<String>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let commit = self.repo.find_commit(oid)?;
+        let tree = commit.tree()?;
+        let parent_tree = commit.parent(0)?.tree()?;
+        let diff = self
+            .repo
+            .diff_tree_to_tree(Some(&parent_tree), Some(&tree), None)?;
+        let mut paths = std::collections::BTreeSet::new();
+        for delta in diff.deltas() {
+            for path in [delta.old_file().path(), delta.new_file().path()]
+                .into_iter()
+                .flatten()
+            {
+                let path = path
+                    .to_str()
+                    .ok_or_else(|| Error::Unknown(format!("non-UTF-8 path: {path:?}")))?;
+                paths.insert(path.to_owned());
             }
-            let line_text = str::from_utf8(line.content()).unwrap();
-            patch.push_str(line_text);
-            true
-        })?;
-        Ok(patch)
+        }
+        Ok(paths.into_iter().collect())
+    }
+
+    pub(crate) fn get_working_tree_patch(&self) -> Result<String, Error> {
+        let head = self.repo.head()?.peel_to_tree()?;
+        let mut options = git2::DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        let diff = self
+            .repo
+            .diff_tree_to_workdir_with_index(Some(&head), Some(&mut options))?;
+        print_patch(&diff)
     }
 
     pub(crate) fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error> {
@@ -639,7 +906,12 @@ impl RawRepositoryInner {
         let oid = Oid::from_bytes(&commit_hash.hash)?;
         let mut commit = self.repo.find_commit(oid)?;
         while commit.parent_count() == 1 {
-            let parent = commit.parent(0)?;
+            let parent = match commit.parent(0) {
+                Ok(parent) => parent,
+                // The history is cut at the shallow boundary.
+                Err(e) if e.code() == git2::ErrorCode::NotFound && self.repo.is_shallow() => break,
+                Err(e) => return Err(e.into()),
+            };
             ancestors.push(parent.id());
             commit = parent;
             if let Some(max) = max {
@@ -683,6 +955,19 @@ impl RawRepositoryInner {
             ));
         }
 
+        // libgit2 can't walk past the shallow boundary, so follow the (linear) history manually.
+        if self.repo.is_shallow() {
+            let ancestor_oid = Oid::from_bytes(&ancestor.hash)?;
+            let mut path = self
+                .shallow_ancestry(Oid::from_bytes(&descendant.hash)?)?
+                .into_iter()
+                .take_while(|oid| *oid != ancestor_oid)
+                .map(to_commit_hash)
+                .collect::<Result<Vec<_>, _>>()?;
+            path.reverse();
+            return Ok(path);
+        }
+
         let descendant_oid = Oid::from_bytes(&descendant.hash)?;
         let ancestor_oid = Oid::from_bytes(&ancestor.hash)?;
 
@@ -711,6 +996,29 @@ impl RawRepositoryInner {
         todo!()
     }
 
+    /// Returns the given commit and its ancestors available in the shallow repository.
+    fn shallow_ancestry(&self, oid: Oid) -> Result<Vec<Oid>, Error> {
+        let mut result = vec![oid];
+        let mut commit = self.repo.find_commit(oid)?;
+        while commit.parent_count() == 1 {
+            match commit.parent(0) {
+                Ok(parent) => {
+                    result.push(parent.id());
+                    commit = parent;
+                }
+                Err(e) if e.code() == git2::ErrorCode::NotFound => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if commit.parent_count() > 1 {
+            return Err(Error::InvalidRepository(format!(
+                "there exists a merge commit, {}",
+                commit.id()
+            )));
+        }
+        Ok(result)
+    }
+
     pub(crate) fn find_merge_base(
         &self,
         commit_hash1: CommitHash,
@@ -718,7 +1026,19 @@ impl RawRepositoryInner {
     ) -> Result<CommitHash, Error> {
         let oid1 = Oid::from_bytes(&commit_hash1.hash)?;
         let oid2 = Oid::from_bytes(&commit_hash2.hash)?;
-        let oid_merge = self.repo.merge_base(oid1, oid2)?;
+        let oid_merge = if self.repo.is_shallow() {
+            let ancestry1 = self.shallow_ancestry(oid1)?;
+            self.shallow_ancestry(oid2)?
+                .into_iter()
+                .find(|oid| ancestry1.contains(oid))
+                .ok_or_else(|| {
+                    Error::NotFound(format!(
+                        "merge base of {commit_hash1} and {commit_hash2} in the shallow history"
+                    ))
+                })?
+        } else {
+            self.repo.merge_base(oid1, oid2)?
+        };
         let commit_hash_merge: [u8; 20] = oid_merge
             .as_bytes()
             .try_into()
@@ -795,14 +1115,141 @@ impl RawRepositoryInner {
         let version: String =
             serde_spb::from_str(content).map_err(|e| Error::Unknown(e.to_string()))?;
 
+        let max_blob_size = self.read_optional(&tree, "reserved/max_blob_size")?;
+        let max_commit_body_size = self.read_optional(&tree, "reserved/max_commit_body_size")?;
+        let max_agenda_transactions =
+            self.read_optional(&tree, "reserved/max_agenda_transactions")?;
+        let max_block_extra_agenda_transactions =
+            self.read_optional(&tree, "reserved/max_block_extra_agenda_transactions")?;
+        let scheduled_changes = self.read_optional(&tree, "reserved/scheduled_changes.json")?;
+        let binding = self.read_optional(&tree, "reserved/binding.json")?;
+        let banned_members = self.read_optional(&tree, "reserved/banned_members.json")?;
+        let veto_holders = self.read_optional(&tree, "reserved/veto_holders.json")?;
+        let transaction_spec = self.read_optional(&tree, "reserved/transaction_spec.json")?;
+        let processed_evidence = self.read_optional(&tree, "reserved/processed_evidence.json")?;
+
         Ok(ReservedState {
             genesis_info,
             members,
             consensus_leader_order,
             version,
+            max_blob_size,
+            max_commit_body_size,
+            max_agenda_transactions,
+            max_block_extra_agenda_transactions,
+            scheduled_changes,
+            binding,
+            banned_members,
+            veto_holders,
+            transaction_spec,
+            processed_evidence,
         })
     }
 
+    /// Reads a field of the reserved state that is stored only if it's not the default
+    /// (e.g., a zero size).
+    fn read_optional<T: serde::de::DeserializeOwned + Default>(
+        &self,
+        tree: &git2::Tree,
+        path: &str,
+    ) -> Result<T, Error> {
+        let entry = match tree.get_path(std::path::Path::new(path)) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(T::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = entry.to_object(&self.repo)?;
+        let blob = blob
+            .as_blob()
+            .ok_or_else(|| Error::Unknown("failed to get a blob".to_string()))?;
+        let content = std::str::from_utf8(blob.content())
+            .map_err(|_| Error::Unknown(format!("content of {path} is not UTF-8")))?;
+        serde_spb::from_str(content).map_err(|e| Error::Unknown(e.to_string()))
+    }
+
+    pub(crate) fn read_files_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let tree = self.repo.find_commit(oid)?.tree()?;
+        let mut files = Vec::new();
+        let mut error = None;
+        tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            if entry.kind() != Some(git2::ObjectType::Blob) {
+                return git2::TreeWalkResult::Ok;
+            }
+            let Some(name) = entry.name() else {
+                error = Some(Error::Unknown("a file name is not UTF-8".to_string()));
+                return git2::TreeWalkResult::Abort;
+            };
+            match self.repo.find_blob(entry.id()) {
+                Ok(blob) => {
+                    files.push((format!("{root}{name}"), blob.content().to_vec()));
+                    git2::TreeWalkResult::Ok
+                }
+                Err(e) => {
+                    error = Some(e.into());
+                    git2::TreeWalkResult::Abort
+                }
+            }
+        })?;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    pub(crate) fn write_files_at_ref(
+        &mut self,
+        reference: String,
+        files: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let mut builder = self.repo.treebuilder(None)?;
+        for (name, content) in &files {
+            let oid = self.repo.blob(content.as_bytes())?;
+            builder.insert(name, oid, 0o100644)?;
+        }
+        let tree = self.repo.find_tree(builder.write()?)?;
+        // A fixed signature, so that the same files make the same commit.
+        let signature = git2::Signature::new(
+            UNKNOWN_COMMIT_AUTHOR,
+            UNKNOWN_COMMIT_AUTHOR,
+            &git2::Time::new(0, 0),
+        )?;
+        let oid = self
+            .repo
+            .commit(None, &signature, &signature, &reference, &tree, &[])?;
+        self.repo
+            .reference(&reference, oid, true, "write files at ref")?;
+        Ok(())
+    }
+
+    pub(crate) fn read_file_at_ref(
+        &self,
+        reference: String,
+        name: String,
+    ) -> Result<Option<String>, Error> {
+        let reference = match self.repo.find_reference(&reference) {
+            Ok(reference) => reference,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let tree = reference.peel_to_tree()?;
+        let entry = match tree.get_name(&name) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let blob = entry.to_object(&self.repo)?;
+        let blob = blob
+            .as_blob()
+            .ok_or_else(|| Error::Unknown(format!("{name} is not a file")))?;
+        let content = std::str::from_utf8(blob.content())
+            .map_err(|_| Error::Unknown(format!("content of {name} is not UTF-8")))?;
+        Ok(Some(content.to_owned()))
+    }
+
     pub(crate) fn add_remote(
         &mut self,
         remote_name: String,
@@ -816,7 +1263,10 @@ impl RawRepositoryInner {
         self.repo.remote_delete(&remote_name).map_err(Error::from)
     }
 
-    pub(crate) fn fetch_all(&mut self) -> Result<(), Error> {
+    pub(crate) fn fetch_all(
+        &mut self,
+        progress: ProgressReporter,
+    ) -> Result<Vec<RemoteFetch>, Error> {
         let remotes = self.repo.remotes()?;
         let remotes = remotes
             .iter()
@@ -824,14 +1274,52 @@ impl RawRepositoryInner {
                 let remote_name =
                     remote.ok_or_else(|| Error::Unknown("unable to get remote".to_string()))?;
 
-                Ok(remote_name)
+                Ok(remote_name.to_owned())
             })
-            .collect::<Result<Vec<&str>, Error>>()?;
+            .collect::<Result<Vec<String>, Error>>()?;
+
+        // Each remote is fetched by its own handle of the repository, as a handle is not `Sync`.
+        let path = self.repo.path().to_owned();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            for name in &remotes {
+                let (sender, progress, path) = (sender.clone(), &progress, &path);
+                scope.spawn(move || {
+                    let started = std::time::Instant::now();
+                    let mut received_bytes = 0;
+                    let result = fetch_remote(path, name, progress, &mut received_bytes)
+                        .map_err(|e| e.to_string());
+                    let _ = sender.send(RemoteFetch {
+                        remote: name.clone(),
+                        result,
+                        elapsed_ms: started.elapsed().as_millis() as u64,
+                        received_bytes,
+                    });
+                });
+            }
+        });
+        drop(sender);
+        let fetches = receiver.into_iter().collect();
+        // Reopen to load the new packfiles.
+        self.repo = Repository::open(&path)?;
+        Ok(fetches)
+    }
 
-        for name in remotes {
-            let mut remote = self.repo.find_remote(name)?;
-            remote.fetch(&[] as &[&str], None, None)?;
+    pub(crate) fn fetch_all_with_depth(&mut self, depth: usize) -> Result<(), Error> {
+        let git_dir = self
+            .repo
+            .path()
+            .to_str()
+            .ok_or_else(|| Error::Unknown("path is not valid utf-8".to_string()))?;
+        let remotes = self.repo.remotes()?;
+        for name in remotes.iter() {
+            let name = name.ok_or_else(|| Error::Unknown("unable to get remote".to_string()))?;
+            run_command(format!(
+                "git --git-dir=\"{git_dir}\" fetch --quiet --depth {depth} {name}"
+            ))?;
         }
+        // Reopen to load the new packfiles and the new shallow boundary.
+        self.repo = Repository::open(self.repo.path())?;
         Ok(())
     }
 
@@ -938,3 +1426,125 @@ impl RawRepositoryInner {
         Ok(CommitHash { hash })
     }
 }
+
+fn print_patch(diff: &git2::Diff) -> Result<String, Error> {
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        match line.origin() {
+            ' ' | '+' | '-' => patch.push(line.origin()),
+            _ => {}
+        }
+        let line_text = str::from_utf8(line.content()).unwrap();
+        patch.push_str(line_text);
+        true
+    })?;
+    Ok(patch)
+}
+
+fn to_commit_message(commit: &SemanticCommit) -> String {
+    // TODO: Check "\n" divides commit message's head and body.
+    let message = format!("{}{}{}", commit.title, "\n\n", commit.body);
+    match &commit.signature {
+        Some(signature) => format!(
+            "{message}\n\n{COMMIT_SIGNATURE_TRAILER}{} {}",
+            signature.signer(),
+            signature.get_raw_signature()
+        ),
+        None => message,
+    }
+}
+
+/// Splits the signature trailer (see [`COMMIT_SIGNATURE_TRAILER`]) off the commit body.
+fn split_commit_signature(body: String) -> Result<(String, Option<TypedSignature<Commit>>), Error> {
+    let (rest, line) = match body.rsplit_once('\n') {
+        Some((rest, line)) => (rest, line),
+        None => ("", body.as_str()),
+    };
+    let signature = match line.strip_prefix(COMMIT_SIGNATURE_TRAILER) {
+        Some(signature) => signature,
+        None => return Ok((body, None)),
+    };
+    let invalid = || Error::Unknown(format!("invalid commit signature: {signature}"));
+    let (signer, signature) = signature.split_once(' ').ok_or_else(invalid)?;
+    let signer = PublicKey::from_array_uncompressed(
+        hex::decode(signer)
+            .map_err(|_| invalid())?
+            .as_slice()
+            .try_into()
+            .map_err(|_| invalid())?,
+    )
+    .map_err(|_| invalid())?;
+    let signature = Signature::from_array(
+        hex::decode(signature)
+            .map_err(|_| invalid())?
+            .as_slice()
+            .try_into()
+            .map_err(|_| invalid())?,
+    );
+    let body = rest.strip_suffix('\n').unwrap_or(rest).to_owned();
+    Ok((body, Some(TypedSignature::new(signature, signer))))
+}
+
+/// Returns the total size of the files in the directory, in bytes.
+fn directory_size(path: &std::path::Path) -> Result<u64, Error> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path).map_err(|e| Error::Unknown(e.to_string()))? {
+        let entry = entry.map_err(|e| Error::Unknown(e.to_string()))?;
+        let metadata = entry
+            .metadata()
+            .map_err(|e| Error::Unknown(e.to_string()))?;
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+fn to_commit_hash(oid: Oid) -> Result<CommitHash, Error> {
+    let hash =
+        <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
+    Ok(CommitHash { hash })
+}
+
+/// Fetches a remote with a new handle of the repository at `path`.
+fn fetch_remote(
+    path: &std::path::Path,
+    name: &str,
+    progress: &ProgressReporter,
+    received_bytes: &mut u64,
+) -> Result<(), Error> {
+    let repo = Repository::open(path)?;
+    let mut remote = repo.find_remote(name)?;
+    let started = std::time::Instant::now();
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.transfer_progress(|stats| {
+        let received_objects = stats.received_objects() as u64;
+        let total_objects = stats.total_objects() as u64;
+        *received_bytes = stats.received_bytes() as u64;
+        progress.report(Progress::Fetching {
+            remote: name.to_owned(),
+            received_objects,
+            total_objects,
+            received_bytes: *received_bytes,
+            eta_ms: estimate_remaining(started, received_objects, total_objects),
+        });
+        true
+    });
+    let mut options = git2::FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    let mut refspecs = remote
+        .fetch_refspecs()?
+        .iter()
+        .flatten()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    refspecs.push(format!(
+        "+{}:{}",
+        attestation::ATTESTATION_REF,
+        attestation::remote_attestation_ref(name)
+    ));
+    remote.fetch(&refspecs, Some(&mut options), None)?;
+    Ok(())
+}
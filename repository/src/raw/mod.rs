@@ -1,3 +1,4 @@
+mod git_signing;
 mod implementation;
 pub mod reserved_state;
 mod templates;
@@ -5,11 +6,13 @@ mod templates;
 mod tests;
 
 use super::*;
+use crate::progress::{estimate_remaining, Progress, ProgressReporter};
 use eyre::Result;
 use git2::{
     ApplyLocation, BranchType, DiffFormat, Email, EmailCreateOptions, IndexAddOption, ObjectType,
     Oid, Repository, RepositoryInitOptions, ResetType, Sort, Status, StatusOptions, StatusShow,
 };
+pub use git_signing::{GitSignature, GitSigner};
 use implementation::RawRepositoryInner;
 use simperby_core::reserved::ReservedState;
 use std::convert::TryFrom;
@@ -38,6 +41,11 @@ impl From<git2::Error> for Error {
     }
 }
 
+/// The prefix of the last line of a commit message that carries [`SemanticCommit::signature`].
+///
+/// The line is in the form of `Simperby-Signature: <signer> <signature>`, both in hex.
+pub const COMMIT_SIGNATURE_TRAILER: &str = "Simperby-Signature: ";
+
 /// A commit with abstracted diff. The committer is always the same as the author.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SemanticCommit {
@@ -46,6 +54,9 @@ pub struct SemanticCommit {
     pub diff: Diff,
     pub author: MemberName,
     pub timestamp: Timestamp,
+    /// The signature of the author on the interpreted commit, if signed.
+    #[serde(default)]
+    pub signature: Option<TypedSignature<Commit>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -57,6 +68,16 @@ pub struct RawCommit {
     pub timestamp: Timestamp,
 }
 
+/// The result of fetching a remote repository.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteFetch {
+    pub remote: String,
+    pub result: Result<(), String>,
+    /// How long it took, in milliseconds.
+    pub elapsed_ms: u64,
+    pub received_bytes: u64,
+}
+
 #[derive(Debug)]
 pub struct RawRepository {
     inner: tokio::sync::Mutex<Option<RawRepositoryInner>>,
@@ -104,6 +125,26 @@ impl RawRepository {
         Ok(Self { inner })
     }
 
+    /// Clones a remote repository, fetching only the last `depth` commits of each branch.
+    /// Unlike `clone()`, it creates the local branches for all the remote branches.
+    ///
+    /// The history older than that is not available in the local repository,
+    /// so it must be verified from a trusted checkpoint instead of the genesis.
+    pub async fn clone_shallow(directory: &str, url: &str, depth: usize) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let repo = RawRepositoryInner::clone_shallow(directory, url, depth)?;
+        let inner = tokio::sync::Mutex::new(Some(repo));
+
+        Ok(Self { inner })
+    }
+
+    /// Returns whether the repository has an incomplete history (i.e., is a shallow clone).
+    pub async fn is_shallow(&self) -> Result<bool, Error> {
+        Ok(helper_0(self, RawRepositoryInner::is_shallow).await)
+    }
+
     /// Returns the full commit hash from the revision selection string.
     ///
     /// See the [reference](https://git-scm.com/book/en/v2/Git-Tools-Revision-Selection).
@@ -221,6 +262,19 @@ impl RawRepository {
         helper_1_mut(self, RawRepositoryInner::create_semantic_commit, commit).await
     }
 
+    /// Sets the key to sign the semantic commits with git's native signing, or unsets it.
+    pub async fn set_git_signer(&mut self, git_signer: Option<GitSigner>) {
+        helper_1_mut(self, RawRepositoryInner::set_git_signer, git_signer).await
+    }
+
+    /// Reads the git's native signature of the commit, which is `None` if unsigned.
+    pub async fn read_git_signature(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<GitSignature>, Error> {
+        helper_1(self, RawRepositoryInner::read_git_signature, commit_hash).await
+    }
+
     /// Reads the reserved state from the current working tree.
     pub async fn read_semantic_commit(
         &self,
@@ -229,9 +283,46 @@ impl RawRepository {
         helper_1(self, RawRepositoryInner::read_semantic_commit, commit_hash).await
     }
 
+    /// Same as [`RawRepository::read_semantic_commit`], but fails
+    /// if the body is larger than `max_body_size` bytes (unless it's zero),
+    /// without computing the diff of the commit.
+    pub async fn read_semantic_commit_bounded(
+        &self,
+        commit_hash: CommitHash,
+        max_body_size: u64,
+    ) -> Result<SemanticCommit, Error> {
+        helper_2(
+            self,
+            RawRepositoryInner::read_semantic_commit_bounded,
+            commit_hash,
+            max_body_size,
+        )
+        .await
+    }
+
     /// Removes orphaned commits. Same as `git gc --prune=now --aggressive`
     pub async fn run_garbage_collection(&mut self) -> Result<(), Error> {
-        helper_0_mut(self, RawRepositoryInner::run_garbage_collection).await
+        self.gc().await.map(|_| ())
+    }
+
+    /// Removes the unreachable objects (including those only in the reflogs)
+    /// and packs the rest, returning the reclaimed space in bytes.
+    pub async fn gc(&mut self) -> Result<u64, Error> {
+        helper_0_mut(self, RawRepositoryInner::gc).await
+    }
+
+    /// Cuts the history below the given commit, which becomes a boundary of the shallow history
+    /// as if the repository were cloned with [`Self::clone_shallow`], and collects the garbage,
+    /// returning the reclaimed space in bytes.
+    ///
+    /// The older commits still referred to by a branch or a tag are kept.
+    pub async fn cut_history(&mut self, commit_hash: CommitHash) -> Result<u64, Error> {
+        helper_1_mut(self, RawRepositoryInner::cut_history, commit_hash).await
+    }
+
+    /// Returns the commits that the shallow history starts from, or nothing if it's complete.
+    pub async fn get_shallow_boundaries(&self) -> Result<Vec<CommitHash>, Error> {
+        helper_0(self, RawRepositoryInner::get_shallow_boundaries).await
     }
 
     // ----------------------------
@@ -306,6 +397,18 @@ impl RawRepository {
         helper_1(self, RawRepositoryInner::get_patch, commit_hash).await
     }
 
+    /// Returns the paths of the files that the given commit changes, in order,
+    /// with both the old and the new paths of a renamed file.
+    pub async fn get_changed_paths(&self, commit_hash: CommitHash) -> Result<Vec<String>, Error> {
+        helper_1(self, RawRepositoryInner::get_changed_paths, commit_hash).await
+    }
+
+    /// Returns the patch of the uncommitted changes in the working tree (including the untracked files)
+    /// on top of `HEAD`, in the same format as [`Self::get_patch`].
+    pub async fn get_working_tree_patch(&self) -> Result<String, Error> {
+        helper_0(self, RawRepositoryInner::get_working_tree_patch).await
+    }
+
     /// Returns the diff of the given commit.
     pub async fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error> {
         helper_1(self, RawRepositoryInner::show_commit, commit_hash).await
@@ -314,6 +417,7 @@ impl RawRepository {
     /// Lists the ancestor commits of the given commit (The first element is the direct parent).
     ///
     /// It fails if there is a merge commit.
+    /// In a shallow repository, it stops at the oldest available commit.
     /// * `max`: the maximum number of entries to be returned.
     pub async fn list_ancestors(
         &self,
@@ -380,6 +484,40 @@ impl RawRepository {
         .await
     }
 
+    /// Reads all the files in the tree of the given commit with their paths, sorted by the paths.
+    pub async fn read_files_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        helper_1(self, RawRepositoryInner::read_files_at_commit, commit_hash).await
+    }
+
+    /// Points the reference to a commit of the given files only, with no parent.
+    ///
+    /// It keeps the files apart from the history, e.g. the ones refreshed on every block.
+    pub async fn write_files_at_ref(
+        &mut self,
+        reference: String,
+        files: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        helper_2_mut(
+            self,
+            RawRepositoryInner::write_files_at_ref,
+            reference,
+            files,
+        )
+        .await
+    }
+
+    /// Reads the file in the commit of the reference, if both exist.
+    pub async fn read_file_at_ref(
+        &self,
+        reference: String,
+        name: String,
+    ) -> Result<Option<String>, Error> {
+        helper_2(self, RawRepositoryInner::read_file_at_ref, reference, name).await
+    }
+
     // ----------------------
     // Remote-related methods
     // ----------------------
@@ -405,8 +543,35 @@ impl RawRepository {
     }
 
     /// Fetches the remote repository. Same as `git fetch --all -j <LARGE NUMBER>`.
+    ///
+    /// It fails if any of the remotes fails.
     pub async fn fetch_all(&mut self) -> Result<(), Error> {
-        helper_0_mut(self, RawRepositoryInner::fetch_all).await
+        for fetch in self
+            .fetch_all_with_progress(ProgressReporter::default())
+            .await?
+        {
+            fetch
+                .result
+                .map_err(|e| Error::Unknown(format!("failed to fetch {}: {e}", fetch.remote)))?;
+        }
+        Ok(())
+    }
+
+    /// Fetches the remote repositories in parallel, reporting the objects received from each.
+    ///
+    /// Returns the result of each remote in the order of completion;
+    /// a failing remote doesn't affect the others.
+    pub async fn fetch_all_with_progress(
+        &mut self,
+        progress: ProgressReporter,
+    ) -> Result<Vec<RemoteFetch>, Error> {
+        helper_1_mut(self, RawRepositoryInner::fetch_all, progress).await
+    }
+
+    /// Fetches only the last `depth` commits of each branch of the remote repositories.
+    /// Same as `git fetch --depth <depth> <remote>` for each remote.
+    pub async fn fetch_all_with_depth(&mut self, depth: usize) -> Result<(), Error> {
+        helper_1_mut(self, RawRepositoryInner::fetch_all_with_depth, depth).await
     }
 
     /// Pushes to the remote repository with the push option.
@@ -459,6 +624,18 @@ impl RawRepository {
     }
 }
 
+/// Checks that the given text is a patch that [`RawRepository::create_commit`] can read.
+///
+/// Whether it applies depends on the commit to apply it on.
+pub fn check_patch(patch: &str) -> Result<(), Error> {
+    if git2::Diff::from_buffer(patch.as_bytes())?.deltas().len() == 0 {
+        return Err(Error::Unknown(
+            "the patch doesn't change any file".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(target_os = "windows")]
 pub fn run_command(command: impl AsRef<str>) -> Result<(), Error> {
     println!("> RUN: {}", command.as_ref());
@@ -1,5 +1,6 @@
 use eyre::Error;
 use reserved::ReservedState;
+use serde::de::DeserializeOwned;
 use simperby_core::*;
 use std::path::Path;
 use tokio::fs;
@@ -31,16 +32,55 @@ pub async fn read_reserved_state(path: &str) -> Result<ReservedState, Error> {
     let version = fs::read_to_string(format!("{}/{}", path, "reserved/version")).await?;
     let version: String = serde_spb::from_str(version.as_str())?;
 
+    let max_blob_size = read_optional(&format!("{path}/reserved/max_blob_size")).await?;
+    let max_commit_body_size =
+        read_optional(&format!("{path}/reserved/max_commit_body_size")).await?;
+    let max_agenda_transactions =
+        read_optional(&format!("{path}/reserved/max_agenda_transactions")).await?;
+    let max_block_extra_agenda_transactions = read_optional(&format!(
+        "{path}/reserved/max_block_extra_agenda_transactions"
+    ))
+    .await?;
+    let scheduled_changes =
+        read_optional(&format!("{path}/reserved/scheduled_changes.json")).await?;
+    let binding = read_optional(&format!("{path}/reserved/binding.json")).await?;
+    let banned_members = read_optional(&format!("{path}/reserved/banned_members.json")).await?;
+    let veto_holders = read_optional(&format!("{path}/reserved/veto_holders.json")).await?;
+    let transaction_spec = read_optional(&format!("{path}/reserved/transaction_spec.json")).await?;
+    let processed_evidence =
+        read_optional(&format!("{path}/reserved/processed_evidence.json")).await?;
+
     let reserved_state = ReservedState {
         genesis_info,
         members,
         consensus_leader_order,
         version,
+        max_blob_size,
+        max_commit_body_size,
+        max_agenda_transactions,
+        max_block_extra_agenda_transactions,
+        scheduled_changes,
+        binding,
+        banned_members,
+        veto_holders,
+        transaction_spec,
+        processed_evidence,
     };
 
     Ok(reserved_state)
 }
 
+/// Reads a field that is stored only if it's not the default (e.g., a zero size).
+async fn read_optional<T: DeserializeOwned + Default>(path: &str) -> Result<T, Error> {
+    if Path::new(path).exists() {
+        Ok(serde_spb::from_str(
+            fs::read_to_string(path).await?.as_str(),
+        )?)
+    } else {
+        Ok(T::default())
+    }
+}
+
 /// Writes the given reserved state to the given path, overwriting the existing file.
 pub async fn write_reserved_state(path: &str, state: &ReservedState) -> Result<(), Error> {
     let genesis_info = serde_spb::to_string(&state.genesis_info)?;
@@ -68,6 +108,65 @@ pub async fn write_reserved_state(path: &str, state: &ReservedState) -> Result<(
     )
     .await?;
     fs::write(format!("{}/{}", path.as_str(), "version"), version).await?;
+    for (file_name, size) in [
+        ("max_blob_size", state.max_blob_size),
+        ("max_commit_body_size", state.max_commit_body_size),
+        ("max_agenda_transactions", state.max_agenda_transactions),
+        (
+            "max_block_extra_agenda_transactions",
+            state.max_block_extra_agenda_transactions,
+        ),
+    ] {
+        if size != 0 {
+            fs::write(
+                format!("{}/{}", path.as_str(), file_name),
+                serde_spb::to_string(&size)?,
+            )
+            .await?;
+        }
+    }
+    if !state.scheduled_changes.is_empty() {
+        fs::write(
+            format!("{}/{}", path.as_str(), "scheduled_changes.json"),
+            serde_spb::to_string(&state.scheduled_changes)?,
+        )
+        .await?;
+    }
+    if let Some(binding) = &state.binding {
+        fs::write(
+            format!("{}/{}", path.as_str(), "binding.json"),
+            serde_spb::to_string(binding)?,
+        )
+        .await?;
+    }
+    if !state.banned_members.is_empty() {
+        fs::write(
+            format!("{}/{}", path.as_str(), "banned_members.json"),
+            serde_spb::to_string(&state.banned_members)?,
+        )
+        .await?;
+    }
+    if !state.veto_holders.is_empty() {
+        fs::write(
+            format!("{}/{}", path.as_str(), "veto_holders.json"),
+            serde_spb::to_string(&state.veto_holders)?,
+        )
+        .await?;
+    }
+    if let Some(transaction_spec) = &state.transaction_spec {
+        fs::write(
+            format!("{}/{}", path.as_str(), "transaction_spec.json"),
+            serde_spb::to_string(transaction_spec)?,
+        )
+        .await?;
+    }
+    if !state.processed_evidence.is_empty() {
+        fs::write(
+            format!("{}/{}", path.as_str(), "processed_evidence.json"),
+            serde_spb::to_string(&state.processed_evidence)?,
+        )
+        .await?;
+    }
 
     let path = format!("{}/{}", path.as_str(), "members");
     let members_path = Path::new(path.as_str());
@@ -91,7 +190,12 @@ mod tests {
 
     #[tokio::test]
     async fn format_reserved_state() {
-        let (reserved_state, _) = generate_standard_genesis(10);
+        let (mut reserved_state, _) = generate_standard_genesis(10);
+        reserved_state.max_blob_size = 1 << 20;
+        reserved_state.max_commit_body_size = 1 << 16;
+        reserved_state.max_agenda_transactions = 256;
+        reserved_state.max_block_extra_agenda_transactions = 64;
+        reserved_state = reserved_state.bind(reserved_state.clone(), 1);
 
         let td = TempDir::new().unwrap();
         let path = td.path();
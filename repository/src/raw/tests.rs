@@ -354,6 +354,98 @@ async fn checkout_clean() {
     }
 }
 
+/// Garbage-collect a large commit that became unreachable after deleting its branch.
+#[tokio::test]
+async fn gc() {
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    let root_path = td.path().to_str().unwrap();
+
+    repo.create_branch(BRANCH_A.into(), repo.get_head().await.unwrap())
+        .await
+        .unwrap();
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    let data = (0..(1 << 20))
+        .map(|i: u32| i.to_le_bytes()[0] ^ (i >> 8) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(format!("{}/large", root_path), data).unwrap();
+    let commit = RawCommit {
+        message: "large".to_string(),
+        diff: None,
+        author: "name".to_string(),
+        email: "test@email.com".to_string(),
+        timestamp: get_timestamp(),
+    };
+    let commit_hash = repo.create_commit(commit).await.unwrap();
+
+    // Reachable objects survive.
+    repo.gc().await.unwrap();
+    repo.read_commit(commit_hash).await.unwrap();
+
+    repo.checkout(MAIN.into()).await.unwrap();
+    repo.delete_branch(BRANCH_A.into()).await.unwrap();
+    let reclaimed = repo.gc().await.unwrap();
+    assert!(reclaimed > 0);
+    repo.read_commit(commit_hash).await.unwrap_err();
+    // The packed repository is intact for git as well.
+    let fsck = std::process::Command::new("git")
+        .args(["fsck", "--full", "--no-dangling"])
+        .current_dir(root_path)
+        .output()
+        .unwrap();
+    assert!(fsck.status.success(), "{fsck:?}");
+}
+
+/// Cut the history below a commit, dropping the older commits.
+#[tokio::test]
+async fn cut_history() {
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    let mut commit_hashes = vec![repo.get_head().await.unwrap()];
+    for i in 0..4 {
+        std::fs::write(td.path().join("file"), format!("{i}")).unwrap();
+        let commit = RawCommit {
+            message: format!("commit {i}"),
+            diff: None,
+            author: "name".to_string(),
+            email: "test@email.com".to_string(),
+            timestamp: get_timestamp(),
+        };
+        commit_hashes.push(repo.create_commit(commit).await.unwrap());
+    }
+    assert!(repo.get_shallow_boundaries().await.unwrap().is_empty());
+
+    repo.cut_history(commit_hashes[2]).await.unwrap();
+    assert!(repo.is_shallow().await.unwrap());
+    assert_eq!(
+        repo.get_shallow_boundaries().await.unwrap(),
+        vec![commit_hashes[2]]
+    );
+    assert_eq!(
+        repo.list_ancestors(commit_hashes[4], None).await.unwrap(),
+        vec![commit_hashes[3], commit_hashes[2]]
+    );
+    // The boundary has no parent to diff with, but the later ones are intact.
+    repo.read_commit(commit_hashes[3]).await.unwrap();
+    repo.read_commit(commit_hashes[1]).await.unwrap_err();
+
+    // Cutting further drops the stale boundary.
+    repo.cut_history(commit_hashes[3]).await.unwrap();
+    assert_eq!(
+        repo.get_shallow_boundaries().await.unwrap(),
+        vec![commit_hashes[3]]
+    );
+    assert_eq!(
+        repo.list_ancestors(commit_hashes[4], None).await.unwrap(),
+        vec![commit_hashes[3]]
+    );
+    repo.read_commit(commit_hashes[2]).await.unwrap_err();
+}
+
 // Stash, apply a stash and drop a stash with tracked file.
 #[tokio::test]
 async fn stash() {
@@ -636,6 +728,7 @@ async fn reserved_state() {
             diff: Diff::Reserved(Box::new(rs1.clone())),
             author: "doesn't matter".to_owned(),
             timestamp: 0,
+            signature: None,
         })
         .await
         .unwrap();
@@ -646,8 +739,11 @@ async fn reserved_state() {
         .await
         .unwrap();
     assert_eq!(rs1, rs1_retrieve);
-    // TODO: Check if semantic_commit_retrieve after implementation
-    // let semantic_commit_retrieve = repo.read_semantic_commit(commit_hash1).await.unwrap();
+    let semantic_commit_retrieve = repo.read_semantic_commit(commit_hash1).await.unwrap();
+    assert_eq!(
+        semantic_commit_retrieve.diff,
+        Diff::Reserved(Box::new(rs1.clone()))
+    );
 
     let (rs2, _) = generate_standard_genesis(5);
     let commit_hash2 = repo
@@ -657,6 +753,7 @@ async fn reserved_state() {
             diff: Diff::Reserved(Box::new(rs2.clone())),
             author: "doesn't matter".to_owned(),
             timestamp: 0,
+            signature: None,
         })
         .await
         .unwrap();
@@ -690,6 +787,55 @@ async fn clone() {
     assert_eq!(branch_list, vec![MAIN.to_owned()]);
 }
 
+/// Shallow-clone a local repository and fetch more commits with a limited depth.
+#[tokio::test]
+async fn clone_shallow() {
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    let mut commit_hashes = vec![repo.get_head().await.unwrap()];
+    for i in 0..4 {
+        std::fs::write(td.path().join("file"), format!("{i}")).unwrap();
+        let commit = RawCommit {
+            message: format!("commit {i}"),
+            diff: None,
+            author: "name".to_string(),
+            email: "test@email.com".to_string(),
+            timestamp: get_timestamp(),
+        };
+        commit_hashes.push(repo.create_commit(commit).await.unwrap());
+    }
+    let url = format!("file://{}", td.path().to_str().unwrap());
+
+    let td_clone = TempDir::new().unwrap();
+    let mut clone = RawRepository::clone_shallow(td_clone.path().to_str().unwrap(), &url, 2)
+        .await
+        .unwrap();
+    assert!(clone.is_shallow().await.unwrap());
+    assert_eq!(clone.get_head().await.unwrap(), commit_hashes[4]);
+    assert_eq!(
+        clone.list_ancestors(commit_hashes[4], None).await.unwrap(),
+        vec![commit_hashes[3]]
+    );
+    assert_eq!(
+        clone
+            .query_commit_path(commit_hashes[3], commit_hashes[4])
+            .await
+            .unwrap(),
+        vec![commit_hashes[4]]
+    );
+    clone.read_commit(commit_hashes[4]).await.unwrap();
+    clone.read_commit(commit_hashes[2]).await.unwrap_err();
+
+    clone.fetch_all_with_depth(4).await.unwrap();
+    assert_eq!(
+        clone.list_ancestors(commit_hashes[4], None).await.unwrap(),
+        vec![commit_hashes[3], commit_hashes[2], commit_hashes[1]]
+    );
+    clone.read_commit(commit_hashes[2]).await.unwrap();
+}
+
 #[tokio::test]
 async fn semantic_commit() {
     let td = TempDir::new().unwrap();
@@ -714,6 +860,35 @@ async fn semantic_commit() {
     assert_eq!(semantic_commit_nonreserved.diff, Diff::NonReserved(hash));
 }
 
+#[tokio::test]
+async fn semantic_commit_bounded() {
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    let commit = RawCommit {
+        message: format!("a large body\n\n{}", "a".repeat(100)),
+        diff: None,
+        author: "name".to_string(),
+        email: "test@email.com".to_string(),
+        timestamp: get_timestamp(),
+    };
+    let commit_hash = repo.create_commit(commit).await.unwrap();
+
+    repo.read_semantic_commit_bounded(commit_hash, 99)
+        .await
+        .unwrap_err();
+    let semantic_commit = repo
+        .read_semantic_commit_bounded(commit_hash, 100)
+        .await
+        .unwrap();
+    assert_eq!(semantic_commit.body.len(), 100);
+    // Zero is no limit.
+    repo.read_semantic_commit_bounded(commit_hash, 0)
+        .await
+        .unwrap();
+}
+
 /*
     c3 (HEAD -> branch_b)
      |  c2 (branch_a, tag_a)
@@ -0,0 +1,239 @@
+//! Agendas reviewed on the machines without network access.
+//!
+//! A node exports an agenda with everything needed to review it (see [`AgendaBundle::create`]):
+//! the commits from the finalized tip with their patches, what they change in the reserved state,
+//! and the hashes, sealed with the key of the node.
+//! The member checks the bundle offline and signs the agenda (see [`AgendaBundle::sign_review`]),
+//! bringing the signature back to a node, which broadcasts it as a vote.
+use super::*;
+
+/// A commit of the agenda, with its patch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewCommit {
+    pub commit_hash: CommitHash,
+    pub commit: Commit,
+    /// The patch of the commit, in the format of [`RawRepository::get_patch`].
+    pub patch: String,
+}
+
+/// What the agenda changes in the reserved state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservedStateImplications {
+    /// The hash of the reserved state of the last finalized block.
+    pub before_hash: Hash256,
+    /// The hash of the reserved state once the agenda is finalized.
+    pub after_hash: Hash256,
+    pub added_members: Vec<MemberName>,
+    pub removed_members: Vec<MemberName>,
+    /// The members whose entries change (the keys, the voting powers, the delegation and so on).
+    pub changed_members: Vec<MemberName>,
+    /// The protocol versions before and after, if the version changes.
+    pub version_change: Option<(String, String)>,
+    /// Whether anything else changes, like the limits or the scheduled changes.
+    pub other_changes: bool,
+}
+
+impl ReservedStateImplications {
+    pub fn new(before: &ReservedState, after: &ReservedState) -> Self {
+        let find = |state: &ReservedState, name: &MemberName| {
+            state.members.iter().find(|m| &m.name == name).cloned()
+        };
+        let mut added_members = Vec::new();
+        let mut changed_members = Vec::new();
+        for member in &after.members {
+            match find(before, &member.name) {
+                None => added_members.push(member.name.clone()),
+                Some(previous) if &previous != member => changed_members.push(member.name.clone()),
+                Some(_) => {}
+            }
+        }
+        let removed_members = before
+            .members
+            .iter()
+            .filter(|m| find(after, &m.name).is_none())
+            .map(|m| m.name.clone())
+            .collect();
+        let version_change = (before.version != after.version)
+            .then(|| (before.version.clone(), after.version.clone()));
+        let mut rest = after.clone();
+        rest.members = before.members.clone();
+        rest.version = before.version.clone();
+        Self {
+            before_hash: before.to_hash256(),
+            after_hash: after.to_hash256(),
+            added_members,
+            removed_members,
+            changed_members,
+            version_change,
+            other_changes: &rest != before,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before_hash == self.after_hash
+    }
+}
+
+/// The content of an [`AgendaBundle`], which the exporter signs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgendaBundleContent {
+    pub chain_name: String,
+    /// The last finalized block, on which the agenda is based.
+    pub base_height: BlockHeight,
+    pub base_block_hash: Hash256,
+    pub agenda_commit: CommitHash,
+    pub agenda: Agenda,
+    /// The hash of the agenda, which is the payload of a vote on it.
+    pub agenda_hash: Hash256,
+    /// The commits from the finalized tip (exclusive) to the agenda (inclusive).
+    pub commits: Vec<ReviewCommit>,
+    pub implications: ReservedStateImplications,
+}
+
+impl ToHash256 for AgendaBundleContent {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(self).unwrap())
+    }
+}
+
+/// An agenda exported for an offline review, sealed by the node that exported it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgendaBundle {
+    pub content: AgendaBundleContent,
+    pub seal: TypedSignature<AgendaBundleContent>,
+}
+
+impl AgendaBundle {
+    /// Exports the agenda of the given commit, which must be on the finalized tip.
+    pub async fn create(
+        raw: &RawRepository,
+        agenda_commit: CommitHash,
+        private_key: &PrivateKey,
+    ) -> Result<Self, Error> {
+        let base_header = read_last_finalized_block_header(raw).await?;
+        let finalized_commit_hash = raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let mut verifier = CommitSequenceVerifier::new(
+            base_header.clone(),
+            read_last_finalized_reserved_state(raw).await?,
+        )
+        .map_err(|e| eyre!("failed to create a commit sequence verifier: {}", e))?;
+        let commits = read_commits(raw, finalized_commit_hash, agenda_commit)
+            .await
+            .map_err(|e| eyre!("failed to read the commits of the agenda: {e}"))?;
+        let Some((Commit::Agenda(agenda), _)) = commits.last() else {
+            return Err(eyre!("commit {agenda_commit} is not an agenda"));
+        };
+        let agenda = agenda.clone();
+        let mut review_commits = Vec::new();
+        for (commit, commit_hash) in commits {
+            verifier
+                .apply_commit(&commit)
+                .map_err(|e| eyre!("commit {commit_hash} of the agenda is invalid: {e}"))?;
+            review_commits.push(ReviewCommit {
+                commit_hash,
+                patch: raw.get_patch(commit_hash).await?,
+                commit,
+            });
+        }
+        let implications = ReservedStateImplications::new(
+            &raw.read_reserved_state_at_commit(finalized_commit_hash)
+                .await?,
+            &raw.read_reserved_state_at_commit(agenda_commit).await?,
+        );
+        let content = AgendaBundleContent {
+            chain_name: verifier
+                .get_reserved_state()
+                .genesis_info
+                .chain_name
+                .clone(),
+            base_height: base_header.height,
+            base_block_hash: base_header.to_hash256(),
+            agenda_commit,
+            agenda_hash: agenda.to_hash256(),
+            agenda,
+            commits: review_commits,
+            implications,
+        };
+        let seal = TypedSignature::sign(&content, private_key)?;
+        Ok(Self { content, seal })
+    }
+
+    pub fn exporter(&self) -> &PublicKey {
+        self.seal.signer()
+    }
+
+    /// Checks the seal and that the hashes match the agenda and its transactions.
+    ///
+    /// The patches are not checked against the commits; it's up to the reviewer
+    /// to trust the exporter for them.
+    pub fn verify(&self) -> Result<(), Error> {
+        self.seal
+            .verify(&self.content)
+            .map_err(|e| eyre!("invalid seal of the exporter: {e}"))?;
+        let content = &self.content;
+        let Some(last) = content.commits.last() else {
+            return Err(eyre!("the bundle has no commits"));
+        };
+        if last.commit != Commit::Agenda(content.agenda.clone())
+            || last.commit_hash != content.agenda_commit
+        {
+            return Err(eyre!("the last commit of the bundle is not the agenda"));
+        }
+        if content.agenda.to_hash256() != content.agenda_hash {
+            return Err(eyre!("the agenda hash doesn't match the agenda"));
+        }
+        if content.agenda.height != content.base_height + 1 {
+            return Err(eyre!(
+                "the agenda is for height {}, not the one after the base block",
+                content.agenda.height
+            ));
+        }
+        let transactions = content
+            .commits
+            .iter()
+            .filter_map(|c| match &c.commit {
+                Commit::Transaction(t) => Some(t.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if Agenda::calculate_transactions_hash(&transactions) != content.agenda.transactions_hash {
+            return Err(eyre!(
+                "the transactions of the bundle don't match the agenda"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Verifies the bundle and signs the agenda, as in a vote on it.
+    pub fn sign_review(&self, private_key: &PrivateKey) -> Result<AgendaReview, Error> {
+        self.verify()?;
+        Ok(AgendaReview {
+            agenda_hash: self.content.agenda_hash,
+            signature: TypedSignature::sign(&self.content.agenda, private_key)?,
+        })
+    }
+}
+
+/// A vote on an agenda signed offline from an [`AgendaBundle`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgendaReview {
+    pub agenda_hash: Hash256,
+    /// The signature on the agenda, the same as the one of a vote.
+    pub signature: TypedSignature<Agenda>,
+}
+
+impl AgendaReview {
+    pub fn reviewer(&self) -> &PublicKey {
+        self.signature.signer()
+    }
+
+    /// Checks that the review is on the given agenda.
+    pub fn verify(&self, agenda: &Agenda) -> Result<(), Error> {
+        if agenda.to_hash256() != self.agenda_hash {
+            return Err(eyre!("the review is on another agenda"));
+        }
+        self.signature
+            .verify(agenda)
+            .map_err(|e| eyre!("invalid signature of the reviewer: {e}"))
+    }
+}
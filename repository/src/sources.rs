@@ -0,0 +1,211 @@
+//! Bookkeeping of the remote repositories that the commits are fetched from.
+//!
+//! The remotes are fetched in parallel, and the branches of the better-performing ones
+//! are verified first, so that the others are mostly left with what is already verified.
+use super::*;
+use raw::RemoteFetch;
+use std::collections::BTreeMap;
+
+/// The weight of the latest fetch in the moving average of the fetch time.
+const FETCH_TIME_SMOOTHING: f64 = 0.3;
+
+/// The performance of a remote, recorded over the fetches.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceStats {
+    pub fetches: u64,
+    pub failures: u64,
+    /// The exponential moving average of the successful fetch time, in milliseconds.
+    pub average_fetch_ms: Option<f64>,
+    pub received_bytes: u64,
+}
+
+impl SourceStats {
+    pub fn record(&mut self, fetch: &RemoteFetch) {
+        self.fetches += 1;
+        self.received_bytes += fetch.received_bytes;
+        if fetch.result.is_err() {
+            self.failures += 1;
+            return;
+        }
+        let elapsed = fetch.elapsed_ms as f64;
+        self.average_fetch_ms = Some(match self.average_fetch_ms {
+            Some(average) => average + FETCH_TIME_SMOOTHING * (elapsed - average),
+            None => elapsed,
+        });
+    }
+
+    fn failure_rate(&self) -> f64 {
+        if self.fetches == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.fetches as f64
+    }
+}
+
+/// Returns the remotes in the order to verify their branches:
+/// the more reliable first, and then the faster first.
+pub fn priority(stats: &BTreeMap<String, SourceStats>) -> Vec<String> {
+    let mut remotes = stats.iter().collect::<Vec<_>>();
+    remotes.sort_by(|(_, a), (_, b)| {
+        a.failure_rate().total_cmp(&b.failure_rate()).then_with(|| {
+            a.average_fetch_ms
+                .unwrap_or(f64::MAX)
+                .total_cmp(&b.average_fetch_ms.unwrap_or(f64::MAX))
+        })
+    });
+    remotes
+        .into_iter()
+        .map(|(remote, _)| remote.clone())
+        .collect()
+}
+
+/// Two sources whose `finalized` branches have diverged, which indicates a possible fork.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalizedConflict {
+    /// The remote names, or [`LOCAL_SOURCE`] for this repository.
+    pub sources: (String, String),
+    pub commits: (CommitHash, CommitHash),
+}
+
+/// The source name of this repository in [`FinalizedConflict`].
+pub const LOCAL_SOURCE: &str = "local";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchReport {
+    /// The result of each remote, in the order of completion.
+    pub fetches: Vec<RemoteFetch>,
+    pub conflicts: Vec<FinalizedConflict>,
+    /// The conflicts that turned out to be real forks, proven by the finalization proofs.
+    pub forks: Vec<ForkEvidence>,
+    /// The attestations of the remotes that have one.
+    pub attestations: Vec<AttestationCheck>,
+}
+
+/// Cross-checks the `finalized` branches of the remotes and this repository.
+///
+/// A branch that is an ancestor of another is just behind, which is not a conflict.
+pub async fn find_finalized_conflicts(
+    raw: &RawRepository,
+) -> Result<Vec<FinalizedConflict>, Error> {
+    let mut tips = vec![(
+        LOCAL_SOURCE.to_owned(),
+        raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?,
+    )];
+    for (remote, branch, commit_hash) in raw.list_remote_tracking_branches().await? {
+        if branch == FINALIZED_BRANCH_NAME {
+            tips.push((remote, commit_hash));
+        }
+    }
+    let mut conflicts = Vec::new();
+    for (i, (source1, tip1)) in tips.iter().enumerate() {
+        for (source2, tip2) in &tips[i + 1..] {
+            if tip1 == tip2 {
+                continue;
+            }
+            let diverged = match raw.find_merge_base(*tip1, *tip2).await {
+                Ok(merge_base) => merge_base != *tip1 && merge_base != *tip2,
+                // No common history at all.
+                Err(raw::Error::NotFound(_)) => true,
+                Err(raw::Error::Git2Error(e)) if e.code() == git2::ErrorCode::NotFound => true,
+                Err(e) => return Err(e.into()),
+            };
+            if diverged {
+                conflicts.push(FinalizedConflict {
+                    sources: (source1.clone(), source2.clone()),
+                    commits: (*tip1, *tip2),
+                });
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Extracts the evidence of a fork from the conflict: the first blocks after the divergence,
+/// both finalized.
+///
+/// It returns `None` if either branch fails to prove the finalization of its block,
+/// which is just an invalid branch rather than a fork.
+pub async fn find_fork_evidence(
+    raw: &RawRepository,
+    conflict: &FinalizedConflict,
+) -> Result<Option<ForkEvidence>, Error> {
+    let merge_base = match raw
+        .find_merge_base(conflict.commits.0, conflict.commits.1)
+        .await
+    {
+        Ok(merge_base) => merge_base,
+        // Not even the same chain.
+        Err(raw::Error::NotFound(_)) => return Ok(None),
+        Err(raw::Error::Git2Error(e)) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let first =
+        first_finalized_block(raw, &conflict.sources.0, merge_base, conflict.commits.0).await?;
+    let second =
+        first_finalized_block(raw, &conflict.sources.1, merge_base, conflict.commits.1).await?;
+    let evidence = match (first, second) {
+        (Some((first, first_proof)), Some((second, second_proof))) => ForkEvidence {
+            first,
+            first_proof,
+            second,
+            second_proof,
+        },
+        _ => return Ok(None),
+    };
+    Ok(verify::verify_fork_evidence(&evidence)
+        .is_ok()
+        .then_some(evidence))
+}
+
+/// Returns the first block after `merge_base` towards `tip` of the source, with its proof.
+async fn first_finalized_block(
+    raw: &RawRepository,
+    source: &str,
+    merge_base: CommitHash,
+    tip: CommitHash,
+) -> Result<Option<(BlockHeader, FinalizationProof)>, Error> {
+    let commits = match read_commits(raw, merge_base, tip).await {
+        Ok(commits) => commits,
+        Err(e) => {
+            log::debug!("the finalized branch of {source} is not readable: {e}");
+            return Ok(None);
+        }
+    };
+    let mut headers = commits.into_iter().filter_map(|(commit, _)| match commit {
+        Commit::Block(header) => Some(header),
+        _ => None,
+    });
+    let header = match headers.next() {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    // The proof of a block is carried by the next one, or by the `fp` branch for the last one.
+    let proof = match headers.next() {
+        Some(next) => next.prev_block_finalization_proof,
+        None => match read_last_finalization_proof_of(raw, source).await? {
+            Some(fp) if fp.height == header.height => fp.proof,
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some((header, proof)))
+}
+
+async fn read_last_finalization_proof_of(
+    raw: &RawRepository,
+    source: &str,
+) -> Result<Option<LastFinalizationProof>, Error> {
+    let fp_commit_hash = if source == LOCAL_SOURCE {
+        raw.locate_branch(FP_BRANCH_NAME.into()).await.ok()
+    } else {
+        raw.list_remote_tracking_branches()
+            .await?
+            .into_iter()
+            .find(|(remote, branch, _)| remote == source && branch == FP_BRANCH_NAME)
+            .map(|(_, _, commit_hash)| commit_hash)
+    };
+    let fp_commit_hash = match fp_commit_hash {
+        Some(commit_hash) => commit_hash,
+        None => return Ok(None),
+    };
+    Ok(format::fp_from_semantic_commit(raw.read_semantic_commit(fp_commit_hash).await?).ok())
+}
@@ -0,0 +1,176 @@
+//! An index of the finalized transactions, to reject those included again in a later agenda.
+//!
+//! A transaction is identified by the hash of its content (not of its git commit),
+//! so the same transaction recommitted on top of another block is still detected.
+//! Like [`crate::header_cache::HeaderCache`], the index follows the `finalized` branch:
+//! the transactions of the new blocks are added as the branch advances,
+//! and the whole index is rebuilt from the available history if the branch moves otherwise.
+use super::*;
+use simperby_network::{Storage, StorageImpl};
+use std::collections::{BTreeMap, HashMap};
+
+const TRANSACTIONS_FILE_PREFIX: &str = "transactions-";
+const TIP_FILE_NAME: &str = "tip.json";
+
+#[derive(Default)]
+pub struct TransactionIndex {
+    /// The tip of the `finalized` branch that the index is consistent with.
+    tip: Option<CommitHash>,
+    /// The height of the block that finalized each transaction.
+    transactions: HashMap<Hash256, BlockHeight>,
+    /// Where the index is persisted, if set.
+    storage: Option<StorageImpl>,
+}
+
+impl TransactionIndex {
+    /// Creates an index that persists in the storage, loading what is already stored.
+    ///
+    /// The loaded index is checked against the repository on the first lookup.
+    pub async fn load(storage: StorageImpl) -> Result<Self, Error> {
+        let mut this = Self::default();
+        for file_name in storage.list_files().await? {
+            if file_name == TIP_FILE_NAME {
+                this.tip = Some(serde_spb::from_str(&storage.read_file(&file_name).await?)?);
+                continue;
+            }
+            let Some(height) = file_name
+                .strip_prefix(TRANSACTIONS_FILE_PREFIX)
+                .and_then(|x| x.strip_suffix(".json"))
+                .and_then(|x| x.parse::<BlockHeight>().ok())
+            else {
+                continue;
+            };
+            let transactions: Vec<Hash256> =
+                serde_spb::from_str(&storage.read_file(&file_name).await?)?;
+            for transaction in transactions {
+                this.transactions.insert(transaction, height);
+            }
+        }
+        this.storage = Some(storage);
+        Ok(this)
+    }
+
+    /// Returns the height of the block that finalized the transaction of the given hash,
+    /// or `None` if it hasn't been finalized (in the available history).
+    pub async fn finalized_height(
+        &mut self,
+        raw: &RawRepository,
+        transaction_hash: Hash256,
+    ) -> Result<Option<BlockHeight>, Error> {
+        self.refresh(raw).await?;
+        Ok(self.transactions.get(&transaction_hash).copied())
+    }
+
+    /// Drops the whole index, including the stored one.
+    pub async fn invalidate(&mut self) -> Result<(), Error> {
+        self.tip = None;
+        self.transactions.clear();
+        if let Some(storage) = &mut self.storage {
+            storage.remove_all_files().await?;
+        }
+        Ok(())
+    }
+
+    /// Follows the `finalized` branch, which costs a single reference lookup if it hasn't moved.
+    async fn refresh(&mut self, raw: &RawRepository) -> Result<(), Error> {
+        let tip = raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .map_err(|e| match e {
+                raw::Error::NotFound(_) => eyre!(IntegrityError::new(
+                    "cannot locate `finalized` branch".to_string()
+                )),
+                _ => eyre!(e),
+            })?;
+        if self.tip == Some(tip) {
+            return Ok(());
+        }
+        // Fails unless the branch has advanced from the indexed tip.
+        let advanced = match self.tip {
+            Some(indexed) => read_commits(raw, indexed, tip).await.ok(),
+            None => None,
+        };
+        match advanced {
+            Some(commits) => {
+                let mut transactions = Vec::new();
+                for (commit, _) in commits {
+                    match commit {
+                        Commit::Transaction(transaction) => {
+                            transactions.push(transaction.to_hash256())
+                        }
+                        Commit::Block(header) => {
+                            self.insert(header.height, std::mem::take(&mut transactions))
+                                .await?
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            None => {
+                self.invalidate().await?;
+                self.rebuild(raw, tip).await?;
+            }
+        }
+        if let Some(storage) = &mut self.storage {
+            storage
+                .add_or_overwrite_file(TIP_FILE_NAME, serde_spb::to_string(&tip).unwrap())
+                .await?;
+        }
+        self.tip = Some(tip);
+        Ok(())
+    }
+
+    /// Indexes the transactions of the finalized blocks down from the tip,
+    /// to the genesis block or the shallow boundary.
+    async fn rebuild(&mut self, raw: &RawRepository, tip: CommitHash) -> Result<(), Error> {
+        let mut blocks = BTreeMap::<BlockHeight, Vec<Hash256>>::new();
+        let mut height = None;
+        for commit_hash in std::iter::once(tip).chain(raw.list_ancestors(tip, None).await?) {
+            let Ok(commit) = read_commit(raw, commit_hash).await else {
+                // The commit is at the shallow boundary.
+                break;
+            };
+            match commit {
+                Commit::Block(header) if header.height == 0 => break,
+                Commit::Block(header) => height = Some(header.height),
+                Commit::Transaction(transaction) => {
+                    if let Some(height) = height {
+                        blocks
+                            .entry(height)
+                            .or_default()
+                            .push(transaction.to_hash256());
+                    }
+                }
+                _ => (),
+            }
+        }
+        for (height, mut transactions) in blocks {
+            // They have been read backwards.
+            transactions.reverse();
+            self.insert(height, transactions).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert(
+        &mut self,
+        height: BlockHeight,
+        transactions: Vec<Hash256>,
+    ) -> Result<(), Error> {
+        if transactions.is_empty() {
+            return Ok(());
+        }
+        if let Some(storage) = &mut self.storage {
+            storage
+                .add_or_overwrite_file(
+                    &format!("{TRANSACTIONS_FILE_PREFIX}{height}.json"),
+                    serde_spb::to_string(&transactions).unwrap(),
+                )
+                .await?;
+        }
+        for transaction in transactions {
+            self.transactions.insert(transaction, height);
+        }
+        Ok(())
+    }
+}
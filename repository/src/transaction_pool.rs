@@ -0,0 +1,276 @@
+//! A pool of the pending transactions, shared among the members before they are in an agenda.
+//!
+//! A transaction otherwise exists only on the `work` branch of its creator
+//! until the creator proposes an agenda. Instead, the members broadcast their transaction
+//! commits (as patches, see [`RawCommit`]) through a dedicated DMS,
+//! so that any proposer can pull them into the agenda (see [`crate::DistributedRepository::create_agenda`]).
+//!
+//! The pool also keeps the patches of the contributors (see [`crate::patch`])
+//! until a member turns them into transactions.
+use super::*;
+use patch::PatchBundle;
+use raw::RawCommit;
+use simperby_network::Error;
+use simperby_network::*;
+
+/// A transaction commit waiting to be included in an agenda.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub commit: RawCommit,
+}
+
+impl PendingTransaction {
+    /// The title of the commit, which is the first line of the message.
+    pub fn title(&self) -> &str {
+        self.commit.message.lines().next().unwrap_or_default()
+    }
+
+    /// Checks the transaction against the reserved state, before applying it to the repository.
+    ///
+    /// It doesn't tell whether the patch applies or the commit verifies,
+    /// which is checked when the transaction is pulled into an agenda.
+    pub fn validate(&self, reserved_state: &ReservedState) -> Result<(), Error> {
+        if self.title().starts_with('>') {
+            return Err(eyre!(
+                "`{}` is not a transaction commit; the title is reserved",
+                self.title()
+            ));
+        }
+        if reserved_state
+            .query_public_key(&self.commit.author)
+            .is_none()
+        {
+            return Err(eyre!("author {} is not a member", self.commit.author));
+        }
+        let size = self.commit.message.len() as u64;
+        if reserved_state.max_commit_body_size != 0 && size > reserved_state.max_commit_body_size {
+            return Err(eyre!(
+                "commit message is too large: {} > {}",
+                size,
+                reserved_state.max_commit_body_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ToHash256 for PendingTransaction {
+    fn to_hash256(&self) -> Hash256 {
+        Hash256::hash(serde_spb::to_vec(&self.commit).unwrap())
+    }
+}
+
+impl PendingTransaction {
+    fn check(&self) -> Result<(), Error> {
+        if self.title().trim().is_empty() {
+            return Err(eyre!("transaction commit has an empty title"));
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the pool.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoolMessage {
+    Transaction(PendingTransaction),
+    Patch(PatchBundle),
+}
+
+impl ToHash256 for PoolMessage {
+    fn to_hash256(&self) -> Hash256 {
+        match self {
+            PoolMessage::Transaction(transaction) => transaction.to_hash256(),
+            PoolMessage::Patch(bundle) => bundle.to_hash256(),
+        }
+    }
+}
+
+impl DmsMessage for PoolMessage {
+    fn check(&self) -> Result<(), Error> {
+        match self {
+            PoolMessage::Transaction(transaction) => transaction.check(),
+            PoolMessage::Patch(bundle) => bundle.verify(),
+        }
+    }
+}
+
+/// Generates the DMS key for the pending transactions of the given network.
+pub fn generate_dms_key(network_id: &str) -> DmsKey {
+    format!("transaction-pool-{network_id}")
+}
+
+#[derive(Clone)]
+pub struct TransactionPool {
+    dms: Arc<RwLock<Dms<PoolMessage>>>,
+}
+
+impl TransactionPool {
+    pub fn new(dms: Arc<RwLock<Dms<PoolMessage>>>) -> Self {
+        Self { dms }
+    }
+
+    pub fn get_dms(&self) -> Arc<RwLock<Dms<PoolMessage>>> {
+        Arc::clone(&self.dms)
+    }
+
+    /// Adds a transaction to the pool, returning its hash in the pool.
+    pub async fn submit(&mut self, transaction: PendingTransaction) -> Result<Hash256, Error> {
+        let message = PoolMessage::Transaction(transaction);
+        self.dms.write().await.commit_message(&message).await?;
+        Ok(message.to_hash256())
+    }
+
+    /// Adds the patch of a contributor to the pool, returning its hash in the pool.
+    ///
+    /// It fails unless the patch is well-formed and signed by the contributor.
+    pub async fn submit_patch(&mut self, bundle: PatchBundle) -> Result<Hash256, Error> {
+        let message = PoolMessage::Patch(bundle);
+        self.dms.write().await.commit_message(&message).await?;
+        Ok(message.to_hash256())
+    }
+
+    /// Lists the patches in the pool, the oldest first.
+    pub async fn list_patches(&self) -> Result<Vec<PatchBundle>, Error> {
+        let mut patches = self
+            .read_messages()
+            .await?
+            .into_iter()
+            .filter_map(|message| match message {
+                PoolMessage::Patch(bundle) => Some(bundle),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        patches.sort_by_key(|bundle| (bundle.patch.timestamp, bundle.to_hash256()));
+        Ok(patches)
+    }
+
+    /// Reads the patch of the given hash, if it's in the pool.
+    pub async fn get_patch(&self, hash: Hash256) -> Result<Option<PatchBundle>, Error> {
+        Ok(match self.dms.read().await.query_message(hash).await? {
+            Some(message) => match message.message {
+                PoolMessage::Patch(bundle) => Some(bundle),
+                _ => None,
+            },
+            None => None,
+        })
+    }
+
+    /// Lists the transactions in the pool, the oldest first.
+    pub async fn list(&self) -> Result<Vec<PendingTransaction>, Error> {
+        let mut transactions = self
+            .read_messages()
+            .await?
+            .into_iter()
+            .filter_map(|message| match message {
+                PoolMessage::Transaction(transaction) => Some(transaction),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Break the ties deterministically, so that every proposer sees the same order.
+        transactions
+            .sort_by_key(|transaction| (transaction.commit.timestamp, transaction.to_hash256()));
+        Ok(transactions)
+    }
+
+    /// Selects the valid transactions to pull into an agenda, in the order of [`Self::list`].
+    ///
+    /// At most [`ReservedState::max_agenda_transactions`] are selected, if limited.
+    pub async fn select(
+        &self,
+        reserved_state: &ReservedState,
+    ) -> Result<Vec<PendingTransaction>, Error> {
+        let mut transactions = Vec::new();
+        for transaction in self.list().await? {
+            if let Err(e) = transaction.validate(reserved_state) {
+                log::debug!(
+                    "pending transaction {} is not selected: {e}",
+                    transaction.to_hash256()
+                );
+                continue;
+            }
+            transactions.push(transaction);
+        }
+        if reserved_state.max_agenda_transactions != 0 {
+            transactions.truncate(reserved_state.max_agenda_transactions as usize);
+        }
+        Ok(transactions)
+    }
+
+    /// Removes a transaction or a patch from the pool,
+    /// e.g. once it has been finalized or turned into a transaction.
+    pub async fn remove(&mut self, hash: Hash256) -> Result<(), Error> {
+        self.dms.write().await.remove_message(hash, None).await
+    }
+
+    async fn read_messages(&self) -> Result<Vec<PoolMessage>, Error> {
+        Ok(self
+            .dms
+            .read()
+            .await
+            .read_messages()
+            .await?
+            .into_iter()
+            .map(|message| message.message)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simperby_test_suite::*;
+
+    fn transaction(message: &str, author: &str, timestamp: Timestamp) -> PendingTransaction {
+        PendingTransaction {
+            commit: RawCommit {
+                message: message.to_owned(),
+                diff: None,
+                author: author.to_owned(),
+                email: format!("{author}@simperby.net"),
+                timestamp,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn submit_and_select() {
+        setup_test();
+        let (mut reserved_state, keys) = test_utils::generate_standard_genesis(4);
+        let mut pool = TransactionPool::new(Arc::new(RwLock::new(
+            create_test_dms(
+                generate_dms_key("test"),
+                keys.iter()
+                    .map(|(public_key, _)| public_key.clone())
+                    .collect(),
+                keys[0].1.clone(),
+            )
+            .await,
+        )));
+
+        let first = transaction("first", "member-0001", 1);
+        let second = transaction("second", "member-0000", 2);
+        let not_member = transaction("third", "someone", 0);
+        let reserved = transaction(">agenda: 1", "member-0000", 0);
+        for transaction in [&second, &first, &not_member, &reserved] {
+            pool.submit(transaction.clone()).await.unwrap();
+        }
+        assert!(pool
+            .submit(transaction("", "member-0000", 0))
+            .await
+            .is_err());
+        assert_eq!(pool.list().await.unwrap().len(), 4);
+        assert_eq!(
+            pool.select(&reserved_state).await.unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+
+        reserved_state.max_agenda_transactions = 1;
+        assert_eq!(
+            pool.select(&reserved_state).await.unwrap(),
+            vec![first.clone()]
+        );
+
+        pool.remove(first.to_hash256()).await.unwrap();
+        assert_eq!(pool.select(&reserved_state).await.unwrap(), vec![second]);
+    }
+}
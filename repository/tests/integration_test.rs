@@ -1,5 +1,7 @@
+use simperby_core::light_client::LightClient;
 use simperby_core::*;
-use simperby_repository::{raw::*, *};
+use simperby_network::{Storage, StorageImpl};
+use simperby_repository::{interpret::SignatureStatus, raw::*, *};
 use simperby_test_suite::*;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -11,6 +13,10 @@ async fn basic_1() {
     let (rs, keys) = test_utils::generate_standard_genesis(4);
     let config = Config {
         long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
     };
     let server_node_dir = create_temp_dir();
     setup_pre_genesis_repository(&server_node_dir, rs.clone()).await;
@@ -103,14 +109,7 @@ async fn basic_1() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    round: 0,
-                    block_hash: block.to_hash256(),
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect();
     server_node_repo
@@ -140,3 +139,1991 @@ async fn basic_1() {
 
     git_server.await.unwrap();
 }
+
+#[tokio::test]
+async fn verify_from_checkpoint() {
+    setup_test();
+    let (rs, _) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let server_node_dir = create_temp_dir();
+    setup_pre_genesis_repository(&server_node_dir, rs.clone()).await;
+    let mut server_node_repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{server_node_dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    server_node_repo.genesis().await.unwrap();
+
+    let client_node_dir = create_temp_dir();
+    let client_node_repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::clone_shallow(
+                &format!("{client_node_dir}/repository"),
+                &format!("file://{server_node_dir}/repository"),
+                2,
+            )
+            .await
+            .unwrap(),
+        )),
+        config,
+    )
+    .await
+    .unwrap();
+    let checkpoint = TrustedCheckpoint {
+        height: 0,
+        hash: rs.genesis_info.header.to_hash256(),
+    };
+    client_node_repo
+        .verify_from_checkpoint(&checkpoint)
+        .await
+        .unwrap();
+    assert!(client_node_repo
+        .verify_from_checkpoint(&TrustedCheckpoint {
+            height: 0,
+            hash: Hash256::hash("unknown"),
+        })
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn join_request() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    let (public_key, private_key) = generate_keypair("newcomer");
+    let data = JoinRequestData {
+        name: "newcomer".to_owned(),
+        public_key,
+        voting_power: 1,
+        timestamp: 0,
+        chain_name: rs.genesis_info.chain_name.clone(),
+    };
+    let request = JoinRequest {
+        data: data.clone(),
+        proof: TypedSignature::sign(&data, &private_key).unwrap(),
+    };
+    let author = rs.query_name(&keys[0].0).unwrap();
+    let commit = repo
+        .create_join_transaction(author.clone(), &request, None)
+        .await
+        .unwrap();
+    let next = raw
+        .read()
+        .await
+        .read_reserved_state_at_commit(commit)
+        .await
+        .unwrap();
+    // Bound to the genesis state, in the first block.
+    assert_eq!(next, rs.bind(rs.apply_join_request(&request).unwrap(), 1));
+    // The same member can't join twice.
+    assert!(repo
+        .create_join_transaction(author.clone(), &request, None)
+        .await
+        .is_err());
+    // The transaction is included in the agenda.
+    let transaction = match repo.read_commit(commit).await.unwrap() {
+        Commit::Transaction(transaction) => transaction,
+        _ => panic!("not a transaction"),
+    };
+    let (agenda, _) = repo.create_agenda(author).await.unwrap();
+    assert_eq!(
+        agenda.transactions_hash,
+        Agenda::calculate_transactions_hash(&[transaction])
+    );
+}
+
+#[tokio::test]
+async fn agenda_review() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    let (public_key, private_key) = generate_keypair("newcomer");
+    let data = JoinRequestData {
+        name: "newcomer".to_owned(),
+        public_key,
+        voting_power: 1,
+        timestamp: 0,
+        chain_name: rs.genesis_info.chain_name.clone(),
+    };
+    let request = JoinRequest {
+        data: data.clone(),
+        proof: TypedSignature::sign(&data, &private_key).unwrap(),
+    };
+    let author = rs.query_name(&keys[0].0).unwrap();
+    let transaction_commit = repo
+        .create_join_transaction(author.clone(), &request, None)
+        .await
+        .unwrap();
+    let (agenda, agenda_commit) = repo.create_agenda(author).await.unwrap();
+
+    // Only an agenda can be exported.
+    assert!(repo
+        .export_agenda_bundle(transaction_commit, &keys[0].1)
+        .await
+        .is_err());
+    let bundle = repo
+        .export_agenda_bundle(agenda_commit, &keys[0].1)
+        .await
+        .unwrap();
+    // Carried to the offline machine.
+    let bundle: review::AgendaBundle =
+        serde_spb::from_str(&serde_spb::to_string(&bundle).unwrap()).unwrap();
+    bundle.verify().unwrap();
+    assert_eq!(bundle.exporter(), &keys[0].0);
+    assert_eq!(bundle.content.agenda_hash, agenda.to_hash256());
+    assert_eq!(
+        bundle
+            .content
+            .commits
+            .iter()
+            .map(|c| c.commit_hash)
+            .collect::<Vec<_>>(),
+        vec![transaction_commit, agenda_commit]
+    );
+    assert!(bundle.content.commits[0].patch.contains("newcomer"));
+    let implications = &bundle.content.implications;
+    assert_eq!(implications.added_members, vec!["newcomer".to_owned()]);
+    assert!(implications.removed_members.is_empty());
+    assert!(implications.changed_members.is_empty());
+    assert_eq!(implications.version_change, None);
+
+    // Tampered with after the export.
+    let mut tampered = bundle.clone();
+    tampered.content.commits.remove(0);
+    assert!(tampered.verify().is_err());
+    // Resealed by whoever tampered with it.
+    tampered.seal = TypedSignature::sign(&tampered.content, &keys[3].1).unwrap();
+    assert!(tampered.verify().is_err());
+    assert!(tampered.sign_review(&keys[1].1).is_err());
+
+    let review = bundle.sign_review(&keys[1].1).unwrap();
+    assert_eq!(review.reviewer(), &keys[1].0);
+    review.verify(&agenda).unwrap();
+    // The same signature as a vote on the agenda.
+    assert_eq!(
+        review.signature,
+        TypedSignature::sign(&agenda, &keys[1].1).unwrap()
+    );
+    let mut other = agenda.clone();
+    other.timestamp += 1;
+    assert!(review.verify(&other).is_err());
+}
+
+#[tokio::test]
+async fn leader_order() {
+    setup_test();
+    let (mut rs, keys) = test_utils::generate_standard_genesis(4);
+    rs.consensus_leader_order.reverse();
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    let author = rs.query_name(&keys[0].0).unwrap();
+    // Nothing to change.
+    assert!(repo
+        .create_leader_order_transaction(
+            author.clone(),
+            LeaderOrderStrategy::KeepWithInsertions,
+            None
+        )
+        .await
+        .is_err());
+    let commit = repo
+        .create_leader_order_transaction(author.clone(), LeaderOrderStrategy::Alphabetical, None)
+        .await
+        .unwrap();
+    let next = raw
+        .read()
+        .await
+        .read_reserved_state_at_commit(commit)
+        .await
+        .unwrap();
+    assert_eq!(
+        next.consensus_leader_order,
+        (0..4).map(|i| format!("member-{i:04}")).collect::<Vec<_>>()
+    );
+    next.check_leader_order().unwrap();
+    match repo.read_commit(commit).await.unwrap() {
+        Commit::Transaction(transaction) => {
+            assert_eq!(transaction.head, "rebalance leader order: alphabetical")
+        }
+        _ => panic!("not a transaction"),
+    }
+    // Included in the agenda as any other transaction.
+    repo.create_agenda(author).await.unwrap();
+}
+
+#[tokio::test]
+async fn vote_deadline() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let author = rs.query_name(&keys[0].0).unwrap();
+
+    // A deadline before the agenda itself is refused.
+    assert!(repo
+        .create_agenda_with_description(author.clone(), None, Some(VotingDeadline::Timestamp(0)))
+        .await
+        .is_err());
+    let deadline = simperby_core::utils::get_timestamp() + 60 * 1000;
+    let (agenda, _) = repo
+        .create_agenda_with_description(author, None, Some(VotingDeadline::Timestamp(deadline)))
+        .await
+        .unwrap();
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+        .collect::<Vec<_>>();
+
+    assert!(repo
+        .approve(&agenda.to_hash256(), proof.clone(), deadline + 1)
+        .await
+        .is_err());
+    repo.approve(&agenda.to_hash256(), proof, deadline)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn signed_commits() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: true,
+        retention: Default::default(),
+    };
+    let server_node_dir = create_temp_dir();
+    setup_pre_genesis_repository(&server_node_dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{server_node_dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut server_node_repo = DistributedRepository::new(Arc::clone(&raw), config.clone())
+        .await
+        .unwrap();
+    server_node_repo.genesis().await.unwrap();
+
+    let client_node_dir = create_temp_dir();
+    simperby_test_suite::run_command(format!(
+        "cp -r {server_node_dir}/repository {client_node_dir}/repository"
+    ))
+    .await;
+    simperby_test_suite::run_command(format!(
+        "cd {client_node_dir}/repository && git remote add peer {server_node_dir}/repository"
+    ))
+    .await;
+    let mut client_node_repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{client_node_dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+
+    // An agenda signed by its author
+    server_node_repo.set_signing_key(keys[0].1.clone());
+    let (agenda, agenda_commit) = server_node_repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    // An unsigned agenda
+    simperby_test_suite::run_command(format!(
+        "cd {server_node_dir}/repository && git checkout -B work finalized"
+    ))
+    .await;
+    let mut unsigned_repo = DistributedRepository::new(Arc::clone(&raw), config.clone())
+        .await
+        .unwrap();
+    unsigned_repo
+        .create_agenda(rs.query_name(&keys[1].0).unwrap())
+        .await
+        .unwrap();
+    // An agenda signed by another member
+    simperby_test_suite::run_command(format!(
+        "cd {server_node_dir}/repository && git checkout -B work finalized"
+    ))
+    .await;
+    server_node_repo.set_signing_key(keys[2].1.clone());
+    server_node_repo
+        .create_agenda(rs.query_name(&keys[3].0).unwrap())
+        .await
+        .unwrap();
+
+    simperby_test_suite::run_command(format!(
+        "cd {client_node_dir}/repository && git fetch --all"
+    ))
+    .await;
+    let results = client_node_repo.sync_all().await.unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .filter(|(_, result)| matches!(result, Err(e) if e.contains("authorship")))
+            .count(),
+        3
+    );
+    assert_eq!(
+        client_node_repo.read_agendas().await.unwrap(),
+        vec![(agenda_commit, agenda.to_hash256())]
+    );
+}
+
+#[tokio::test]
+async fn git_signatures() {
+    setup_test();
+    let (mut rs, keys) = test_utils::generate_standard_genesis(4);
+    let key_dir = create_temp_dir();
+    simperby_test_suite::run_command(format!(
+        "ssh-keygen -q -t ed25519 -N '' -f {key_dir}/id_ed25519"
+    ))
+    .await;
+    rs.members[0].git_signing_key = Some(GitSigningKey::Ssh(
+        std::fs::read_to_string(format!("{key_dir}/id_ed25519.pub"))
+            .unwrap()
+            .trim()
+            .to_owned(),
+    ));
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: true,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    repo.set_signing_key(keys[0].1.clone());
+    raw.write()
+        .await
+        .set_git_signer(Some(GitSigner::Ssh {
+            key_path: format!("{key_dir}/id_ed25519"),
+        }))
+        .await;
+
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!(
+        "cd {dir}/repository && git branch -f work {agenda_proof}"
+    ))
+    .await;
+    let (block, block_commit) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    let signatures = keys
+        .iter()
+        .map(|(_, private_key)| {
+            PrecommitSignature::sign(block.to_hash256(), 0, 0, private_key).unwrap()
+        })
+        .collect();
+    repo.finalize(
+        block_commit,
+        FinalizationProof {
+            signatures,
+            round: 0,
+        },
+    )
+    .await
+    .unwrap();
+
+    let reports = repo.verify_commit_signatures().await.unwrap();
+    let statuses = reports
+        .into_iter()
+        .map(|report| (report.in_commit, report.git))
+        .collect::<Vec<_>>();
+    let member = SignatureStatus::Verified(rs.members[0].name.clone());
+    assert_eq!(
+        statuses,
+        vec![
+            // The genesis block
+            (SignatureStatus::Unsigned, SignatureStatus::Unsigned),
+            // The agenda
+            (member.clone(), member.clone()),
+            // The agenda proof
+            (SignatureStatus::Unsigned, member.clone()),
+            // The block
+            (member.clone(), member),
+        ]
+    );
+
+    // Not verified with another key.
+    let mut members = rs.members.clone();
+    members[0].git_signing_key = Some(GitSigningKey::Ssh(
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl"
+            .to_owned(),
+    ));
+    let git_signature = raw
+        .read()
+        .await
+        .read_git_signature(block_commit)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(git_signature.verify(&members).is_err());
+}
+
+#[tokio::test]
+async fn finalization_proofs() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let proof_dir = create_temp_dir();
+    StorageImpl::create(&proof_dir).await.unwrap();
+    repo.set_proof_store(proof::ProofStore::new(
+        StorageImpl::open(&proof_dir).await.unwrap(),
+    ));
+
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!(
+        "cd {dir}/repository && git branch -f work {agenda_proof}"
+    ))
+    .await;
+    let (block, block_commit) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    let proof = FinalizationProof {
+        signatures: keys
+            .iter()
+            .map(|(_, private_key)| {
+                PrecommitSignature::sign(block.to_hash256(), 0, 0, private_key).unwrap()
+            })
+            .collect(),
+        round: 0,
+    };
+    repo.finalize(block_commit, proof.clone()).await.unwrap();
+
+    // The proof of the last block is from the `fp` branch,
+    // and that of the genesis block is from the next header.
+    assert_eq!(repo.get_finalization_proof(1).await.unwrap(), Some(proof));
+    assert_eq!(
+        repo.get_finalization_proof(0).await.unwrap(),
+        Some(block.prev_block_finalization_proof)
+    );
+    assert_eq!(repo.get_finalization_proof(2).await.unwrap(), None);
+    let info = repo.read_finalization_info(0).await.unwrap();
+    assert_eq!(info.header, rs.genesis_info.header);
+    assert_eq!(info.reserved_state, rs);
+}
+
+#[tokio::test]
+async fn sync_progress() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let (reporter, progress) = progress::ProgressReporter::new();
+    repo.set_progress_reporter(reporter);
+
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!(
+        "cd {dir}/repository && git branch -f work {agenda_proof}"
+    ))
+    .await;
+    let (_, block_commit) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    assert_eq!(*progress.borrow(), None);
+
+    // Receive the block again as if it were from a peer.
+    simperby_test_suite::run_command(format!(
+        "cd {dir}/repository && git branch --list 'b-*' | xargs git branch -D"
+    ))
+    .await;
+    repo.sync(block_commit).await.unwrap().unwrap();
+    let last_progress = progress.borrow().clone();
+    match last_progress {
+        Some(progress::Progress::Verifying {
+            verified_commits,
+            total_commits,
+            height,
+            eta_ms,
+        }) => {
+            assert_eq!((verified_commits, total_commits, height), (3, 3, 1));
+            assert_eq!(eta_ms, Some(0));
+        }
+        x => panic!("unexpected progress: {x:?}"),
+    }
+}
+
+#[tokio::test]
+async fn fetch_sources() {
+    setup_test();
+    let (rs, _) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    // Two mirrors whose `finalized` branches have diverged, and an unreachable one.
+    for mirror in ["mirror-a", "mirror-b"] {
+        simperby_test_suite::run_command(format!(
+            "cd {dir} && git clone --quiet repository {mirror} && cd {mirror} \
+            && git checkout --quiet finalized \
+            && git -c user.name=test -c user.email=test@test.com \
+            commit --quiet --allow-empty -m {mirror}"
+        ))
+        .await;
+        repo.get_raw()
+            .write()
+            .await
+            .add_remote(mirror.to_owned(), format!("{dir}/{mirror}"))
+            .await
+            .unwrap();
+    }
+    repo.get_raw()
+        .write()
+        .await
+        .add_remote("unreachable".to_owned(), format!("{dir}/unreachable"))
+        .await
+        .unwrap();
+
+    let report = repo.fetch().await.unwrap();
+    let mut results = report
+        .fetches
+        .iter()
+        .map(|fetch| (fetch.remote.as_str(), fetch.result.is_ok()))
+        .collect::<Vec<_>>();
+    results.sort();
+    assert_eq!(
+        results,
+        vec![
+            ("mirror-a", true),
+            ("mirror-b", true),
+            ("unreachable", false)
+        ]
+    );
+    // Both are ahead of the local one, but not of each other.
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(
+        report.conflicts[0].sources,
+        ("mirror-a".to_owned(), "mirror-b".to_owned())
+    );
+    // The empty commits can't prove anything.
+    assert!(report.forks.is_empty());
+
+    let stats = repo.get_source_stats();
+    assert_eq!(stats["unreachable"].failures, 1);
+    assert_eq!(stats["mirror-a"].failures, 0);
+    assert!(stats["mirror-a"].average_fetch_ms.is_some());
+    assert_eq!(
+        sources::priority(stats).last().map(String::as_str),
+        Some("unreachable")
+    );
+}
+
+/// Creates and finalizes a block on top of an empty agenda, authored by the given validator.
+async fn create_finalized_block(
+    repo: &mut DistributedRepository,
+    dir: &str,
+    rs: &ReservedState,
+    keys: &[(PublicKey, PrivateKey)],
+    author: usize,
+) -> BlockHeader {
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[author].0).unwrap())
+        .await
+        .unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!("cd {dir} && git branch -f work {agenda_proof}"))
+        .await;
+    let (block, block_commit) = repo.create_block(keys[author].0.clone()).await.unwrap();
+    let proof = FinalizationProof {
+        signatures: keys
+            .iter()
+            .map(|(_, private_key)| {
+                PrecommitSignature::sign(block.to_hash256(), 0, 0, private_key).unwrap()
+            })
+            .collect(),
+        round: 0,
+    };
+    repo.finalize(block_commit, proof).await.unwrap();
+    block
+}
+
+#[tokio::test]
+async fn verify_finalized_history() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let repo_dir = format!("{dir}/repository");
+    let report = repo.verify_finalized_history(0).await.unwrap();
+    assert_eq!((report.verified_height, report.commits), (0, 0));
+    assert_eq!(report.failure, None);
+
+    create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 1).await;
+    let report = repo.verify_finalized_history(0).await.unwrap();
+    assert_eq!(report.failure, None);
+    assert_eq!(
+        (
+            report.from_height,
+            report.verified_height,
+            report.last_finalized_height
+        ),
+        (0, 2, 2)
+    );
+    // An agenda, an agenda proof and a block for each height.
+    assert_eq!(report.commits, 6);
+    let report = repo.verify_finalized_history(1).await.unwrap();
+    assert_eq!(report.failure, None);
+    assert_eq!((report.verified_height, report.commits), (2, 3));
+    assert!(repo.verify_finalized_history(3).await.is_err());
+
+    // The last block isn't verified with another proof.
+    let lfi = repo.read_last_finalization_info().await.unwrap();
+    let (_, first) = repo.get_finalized_block(1).await.unwrap().unwrap();
+    {
+        let raw = repo.get_raw();
+        let mut raw = raw.write().await;
+        raw.move_branch(FP_BRANCH_NAME.to_owned(), lfi.commit_hash)
+            .await
+            .unwrap();
+        raw.checkout(FP_BRANCH_NAME.to_owned()).await.unwrap();
+        raw.create_semantic_commit(format::fp_to_semantic_commit(&LastFinalizationProof {
+            height: lfi.header.height,
+            proof: first.prev_block_finalization_proof,
+        }))
+        .await
+        .unwrap();
+    }
+    let report = repo.verify_finalized_history(0).await.unwrap();
+    assert_eq!(report.verified_height, 1);
+    assert!(report.failure.is_some());
+}
+
+#[tokio::test]
+async fn finalized_files() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let repo_dir = format!("{dir}/repository");
+    let genesis = repo.get_header(0).await.unwrap().unwrap();
+    let block = create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    assert_ne!(block.repository_merkle_root, Hash256::zero());
+    let mut light_client = LightClient::new(genesis);
+    light_client
+        .update(
+            block.clone(),
+            repo.read_last_finalization_info().await.unwrap().proof,
+        )
+        .unwrap();
+
+    let path = "reserved/genesis_info.json";
+    let file = repo.read_finalized_file(1, path).await.unwrap().unwrap();
+    assert_eq!(file.header, block);
+    let proof = file.merkle_proof.unwrap();
+    assert!(light_client.verify_state_commitment(
+        BlockHeader::repository_merkle_leaf(path, &file.content),
+        1,
+        proof.clone()
+    ));
+    // The proof is bound to the path and the content.
+    assert!(!light_client.verify_state_commitment(
+        BlockHeader::repository_merkle_leaf("reserved/other.json", &file.content),
+        1,
+        proof.clone()
+    ));
+    assert!(!light_client.verify_state_commitment(
+        BlockHeader::repository_merkle_leaf(path, b"forged"),
+        1,
+        proof
+    ));
+
+    // The genesis block doesn't commit to the repository.
+    let file = repo.read_finalized_file(0, path).await.unwrap().unwrap();
+    assert_eq!(file.merkle_proof, None);
+    assert_eq!(repo.read_finalized_file(1, "missing").await.unwrap(), None);
+    assert_eq!(repo.read_finalized_file(2, path).await.unwrap(), None);
+    let report = repo.verify_finalized_history(0).await.unwrap();
+    assert_eq!(report.failure, None);
+}
+
+#[tokio::test]
+async fn fetch_fork() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    simperby_test_suite::run_command(format!("cp -r {dir}/repository {dir}/mirror")).await;
+    let mut mirror = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/mirror")).await.unwrap(),
+        )),
+        config,
+    )
+    .await
+    .unwrap();
+
+    // The validators finalize different blocks at the same height.
+    let block =
+        create_finalized_block(&mut repo, &format!("{dir}/repository"), &rs, &keys, 0).await;
+    let other = create_finalized_block(&mut mirror, &format!("{dir}/mirror"), &rs, &keys, 1).await;
+    assert_ne!(block, other);
+
+    repo.get_raw()
+        .write()
+        .await
+        .add_remote("mirror".to_owned(), format!("{dir}/mirror"))
+        .await
+        .unwrap();
+    let report = repo.fetch().await.unwrap();
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.forks.len(), 1);
+    let evidence = &report.forks[0];
+    assert_eq!((&evidence.first, &evidence.second), (&block, &other));
+    assert_eq!(evidence.height(), 1);
+    verify::verify_fork_evidence(evidence).unwrap();
+}
+
+#[tokio::test]
+async fn header_cache() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let header_dir = create_temp_dir();
+    StorageImpl::create(&header_dir).await.unwrap();
+    repo.set_header_cache_storage(StorageImpl::open(&header_dir).await.unwrap())
+        .await
+        .unwrap();
+    let genesis = rs.genesis_info.header.clone();
+    assert_eq!(
+        repo.get_last_finalized_block_header().await.unwrap(),
+        genesis
+    );
+
+    // Extended on the finalizations.
+    let repo_dir = format!("{dir}/repository");
+    let first = create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    let second = create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 1).await;
+    assert_eq!(
+        repo.get_last_finalized_block_header().await.unwrap(),
+        second
+    );
+    assert_eq!(repo.get_header(1).await.unwrap(), Some(first.clone()));
+    assert_eq!(repo.get_header(0).await.unwrap(), Some(genesis.clone()));
+    assert_eq!(repo.get_header(3).await.unwrap(), None);
+    let (genesis_commit_hash, _) = repo.get_finalized_block(0).await.unwrap().unwrap();
+    let (first_commit_hash, _) = repo.get_finalized_block(1).await.unwrap().unwrap();
+    assert_eq!(
+        repo.get_header_by_commit_hash(first_commit_hash)
+            .await
+            .unwrap(),
+        Some(first.clone())
+    );
+    // Not a block.
+    let agenda_commit_hash = repo
+        .get_raw()
+        .read()
+        .await
+        .list_ancestors(first_commit_hash, Some(1))
+        .await
+        .unwrap()[0];
+    assert_eq!(
+        repo.get_header_by_commit_hash(agenda_commit_hash)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Invalidated if the `finalized` branch doesn't advance but moves otherwise.
+    simperby_test_suite::run_command(format!(
+        "cd {repo_dir} && git update-ref refs/heads/finalized {genesis_commit_hash}"
+    ))
+    .await;
+    assert_eq!(
+        repo.get_last_finalized_block_header().await.unwrap(),
+        genesis
+    );
+    assert_eq!(repo.get_header(1).await.unwrap(), None);
+    assert_eq!(
+        repo.get_header_by_commit_hash(first_commit_hash)
+            .await
+            .unwrap(),
+        None
+    );
+    drop(repo);
+    let storage = StorageImpl::open(&header_dir).await.unwrap();
+    assert_eq!(storage.list_files().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn pruned_history() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: RetentionPolicy::Pruned { heights_to_keep: 2 },
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    // The headers and the proofs must be kept somewhere else first.
+    assert!(repo.prune_history().await.is_err());
+    let proof_dir = create_temp_dir();
+    StorageImpl::create(&proof_dir).await.unwrap();
+    repo.set_proof_store(proof::ProofStore::new(
+        StorageImpl::open(&proof_dir).await.unwrap(),
+    ));
+    let header_dir = create_temp_dir();
+    StorageImpl::create(&header_dir).await.unwrap();
+    repo.set_header_cache_storage(StorageImpl::open(&header_dir).await.unwrap())
+        .await
+        .unwrap();
+
+    let repo_dir = format!("{dir}/repository");
+    let mut headers = vec![rs.genesis_info.header.clone()];
+    for author in 0..3 {
+        headers.push(create_finalized_block(&mut repo, &repo_dir, &rs, &keys, author).await);
+    }
+    // Not enough to cut yet.
+    assert_eq!(repo.prune_history().await.unwrap(), None);
+    headers.push(create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 3).await);
+    let report = repo.prune_history().await.unwrap().unwrap();
+    assert_eq!(report.boundary_height, 2);
+    assert!(repo.get_raw().read().await.is_shallow().await.unwrap());
+    assert_eq!(repo.prune_history().await.unwrap(), None);
+
+    // The headers and the proofs of the cut blocks are still available.
+    for (height, header) in headers.iter().enumerate() {
+        let height = height as BlockHeight;
+        assert_eq!(
+            repo.get_header(height).await.unwrap().as_ref(),
+            Some(header)
+        );
+        let proof = repo.get_finalization_proof(height).await.unwrap().unwrap();
+        verify::verify_finalization_proof(header, &proof).unwrap();
+    }
+    assert!(repo.read_finalization_info(1).await.is_err());
+    repo.read_finalization_info(3).await.unwrap();
+
+    // And the chain goes on.
+    let header = create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    assert_eq!(header.height, 5);
+}
+
+#[tokio::test]
+async fn epochs() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let mut repo = DistributedRepository::new(Arc::clone(&raw), config.clone())
+        .await
+        .unwrap();
+    repo.genesis().await.unwrap();
+    let epoch_dir = create_temp_dir();
+    StorageImpl::create(&epoch_dir).await.unwrap();
+    repo.set_epoch_storage(StorageImpl::open(&epoch_dir).await.unwrap())
+        .await
+        .unwrap();
+    let proof_dir = create_temp_dir();
+    StorageImpl::create(&proof_dir).await.unwrap();
+    repo.set_proof_store(proof::ProofStore::new(
+        StorageImpl::open(&proof_dir).await.unwrap(),
+    ));
+
+    // The validator sets are compared regardless of the order.
+    let sorted = |set: &[(PublicKey, VotingPower)]| {
+        let mut set = set.to_vec();
+        set.sort();
+        set
+    };
+    let genesis_epoch = repo.current_epoch().await.unwrap();
+    assert_eq!(genesis_epoch.start_height, 0);
+    assert_eq!(genesis_epoch.end_height, None);
+    assert_eq!(
+        sorted(&genesis_epoch.validator_set),
+        sorted(&rs.genesis_info.header.validator_set)
+    );
+    let repo_dir = format!("{dir}/repository");
+    create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    // A block that doesn't change the reserved state stays in the epoch.
+    assert_eq!(repo.current_epoch().await.unwrap(), genesis_epoch);
+    assert_eq!(repo.get_epoch(2).await.unwrap(), Some(genesis_epoch));
+    assert_eq!(repo.get_epoch(3).await.unwrap(), None);
+
+    // A delegation in the block at height 2 starts a new epoch from height 3.
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[1].0).unwrap())
+        .await
+        .unwrap();
+    let agenda_proof = repo
+        .approve(
+            &agenda.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!(
+        "cd {repo_dir} && git branch -f work {agenda_proof}"
+    ))
+    .await;
+    let tx = TxDelegate::builder()
+        .delegator(rs.members[0].name.clone())
+        .delegatee(rs.members[2].name.clone())
+        .block_height(2)
+        .timestamp(0)
+        .chain_name(rs.genesis_info.chain_name.clone())
+        .nonce(1)
+        .build(&keys[0].1)
+        .unwrap();
+    repo.create_extra_agenda_transaction(&ExtraAgendaTransaction::Delegate(tx))
+        .await
+        .unwrap();
+    let (block, block_commit) = repo.create_block(keys[1].0.clone()).await.unwrap();
+    let proof = FinalizationProof {
+        signatures: keys
+            .iter()
+            .map(|(_, private_key)| {
+                PrecommitSignature::sign(block.to_hash256(), 0, 0, private_key).unwrap()
+            })
+            .collect(),
+        round: 0,
+    };
+    repo.finalize(block_commit, proof).await.unwrap();
+
+    let closed = repo.get_epoch(2).await.unwrap().unwrap();
+    assert_eq!((closed.start_height, closed.end_height), (0, Some(2)));
+    let current = repo.current_epoch().await.unwrap();
+    assert_eq!((current.start_height, current.end_height), (3, None));
+    assert_eq!(current.validator_set.len(), 3);
+    assert_eq!(repo.get_epoch(3).await.unwrap(), Some(current.clone()));
+
+    // The next block is validated and finalized by the new epoch.
+    let header = create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 2).await;
+    assert_eq!(
+        sorted(&header.validator_set),
+        sorted(&current.validator_set)
+    );
+    assert!(current.contains(header.height));
+    repo.get_finalization_proof(header.height)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The epochs are kept in the storage.
+    drop(repo);
+    let mut reopened = DistributedRepository::new(raw, config).await.unwrap();
+    reopened
+        .set_epoch_storage(StorageImpl::open(&epoch_dir).await.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(reopened.get_epoch(2).await.unwrap(), Some(closed));
+    assert_eq!(reopened.current_epoch().await.unwrap(), current);
+}
+
+#[tokio::test]
+async fn agenda_limits() {
+    setup_test();
+    let (mut rs, keys) = test_utils::generate_standard_genesis(4);
+    rs.max_agenda_transactions = 1;
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+
+    raw.write()
+        .await
+        .checkout(WORK_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    for i in 0..2 {
+        raw.write()
+            .await
+            .create_commit(RawCommit {
+                message: format!("transaction {i}"),
+                diff: None,
+                author: "member-0000".to_owned(),
+                email: "member-0000@simperby.net".to_owned(),
+                timestamp: i,
+            })
+            .await
+            .unwrap();
+    }
+    let error = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<verify::Error>(),
+        Some(verify::Error::LimitExceeded {
+            limit: verify::Limit::AgendaTransactions,
+            size: 2,
+            max: 1
+        })
+    ));
+    let error = repo
+        .preview_agenda(rs.query_name(&keys[0].0).unwrap(), None, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<verify::Error>(),
+        Some(verify::Error::LimitExceeded { .. })
+    ));
+}
+
+#[tokio::test]
+async fn previews() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let author = rs.query_name(&keys[0].0).unwrap();
+    let locate_work = || async {
+        raw.read()
+            .await
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap()
+    };
+
+    // A preview leaves the repository as it is.
+    let work = locate_work().await;
+    let branches = raw.read().await.list_branches().await.unwrap();
+    let (agenda, semantic_commit) = repo
+        .preview_agenda(author.clone(), None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        format::from_semantic_commit(semantic_commit).unwrap(),
+        Commit::Agenda(agenda.clone())
+    );
+    assert_eq!(locate_work().await, work);
+    assert_eq!(raw.read().await.list_branches().await.unwrap(), branches);
+
+    let (created, agenda_commit) = repo.create_agenda(author.clone()).await.unwrap();
+    assert_eq!(created.transactions_hash, agenda.transactions_hash);
+    assert_eq!(created.height, agenda.height);
+    // The invalid ones fail as the creations would.
+    assert!(repo.preview_agenda(author, None, None).await.is_err());
+    assert!(repo.preview_block(keys[0].0.clone()).await.is_err());
+
+    let agenda_proof = repo
+        .approve(
+            &created.to_hash256(),
+            keys.iter()
+                .map(|(_, private_key)| TypedSignature::sign(&created, private_key).unwrap())
+                .collect(),
+            0,
+        )
+        .await
+        .unwrap();
+    assert_eq!(locate_work().await, agenda_commit);
+    raw.write()
+        .await
+        .move_branch(WORK_BRANCH_NAME.into(), agenda_proof)
+        .await
+        .unwrap();
+    let (header, semantic_commit) = repo.preview_block(keys[0].0.clone()).await.unwrap();
+    assert_eq!(locate_work().await, agenda_proof);
+    let (block, block_commit) = repo.create_block(keys[0].0.clone()).await.unwrap();
+    assert_eq!(header, block);
+    let created = raw
+        .read()
+        .await
+        .read_semantic_commit(block_commit)
+        .await
+        .unwrap();
+    assert_eq!(
+        (created.title, created.body),
+        (semantic_commit.title, semantic_commit.body)
+    );
+}
+
+#[tokio::test]
+async fn finalized_transactions() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let mut repo = DistributedRepository::new(Arc::clone(&raw), config.clone())
+        .await
+        .unwrap();
+    repo.genesis().await.unwrap();
+    let index_dir = create_temp_dir();
+    StorageImpl::create(&index_dir).await.unwrap();
+    repo.set_transaction_index_storage(StorageImpl::open(&index_dir).await.unwrap())
+        .await
+        .unwrap();
+
+    let create_transaction = || async {
+        let mut raw = raw.write().await;
+        raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+        raw.create_commit(RawCommit {
+            message: "transaction".to_owned(),
+            diff: None,
+            author: "member-0000".to_owned(),
+            email: "member-0000@simperby.net".to_owned(),
+            timestamp: 0,
+        })
+        .await
+        .unwrap()
+    };
+    let transaction = match repo.read_commit(create_transaction().await).await.unwrap() {
+        Commit::Transaction(transaction) => transaction,
+        _ => panic!("not a transaction"),
+    };
+    assert!(!repo
+        .is_transaction_finalized(transaction.to_hash256())
+        .await
+        .unwrap());
+    let repo_dir = format!("{dir}/repository");
+    create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    assert!(repo
+        .is_transaction_finalized(transaction.to_hash256())
+        .await
+        .unwrap());
+
+    // The same transaction can't be included again.
+    create_transaction().await;
+    let error = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("already been finalized at height 1"));
+
+    // Kept in the storage, which can be opened again once the repository is dropped.
+    drop(repo);
+    let mut repo = DistributedRepository::new(Arc::clone(&raw), config)
+        .await
+        .unwrap();
+    repo.set_transaction_index_storage(StorageImpl::open(&index_dir).await.unwrap())
+        .await
+        .unwrap();
+    assert!(repo
+        .is_transaction_finalized(transaction.to_hash256())
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn pending_transactions() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    repo.set_transaction_pool(transaction_pool::TransactionPool::new(Arc::new(
+        RwLock::new(
+            create_test_dms(
+                transaction_pool::generate_dms_key("test"),
+                keys.iter()
+                    .map(|(public_key, _)| public_key.clone())
+                    .collect(),
+                keys[0].1.clone(),
+            )
+            .await,
+        ),
+    )));
+
+    // The second one modifies the file that the first one adds.
+    let repo_dir = format!("{dir}/repository");
+    let mut transactions = Vec::new();
+    for (content, timestamp) in [(1, 1), (2, 0)] {
+        simperby_test_suite::run_command(format!("cd {repo_dir} && echo {content} > pending.txt"))
+            .await;
+        let mut raw = raw.write().await;
+        raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+        transactions.push(
+            raw.create_commit(RawCommit {
+                message: format!("pending {content}"),
+                diff: None,
+                author: "member-0001".to_owned(),
+                email: "member-0001@simperby.net".to_owned(),
+                timestamp,
+            })
+            .await
+            .unwrap(),
+        );
+    }
+    for transaction in &transactions {
+        repo.submit_pending_transaction(*transaction).await.unwrap();
+    }
+    // Only the members of the pool have them now.
+    let finalized = raw
+        .read()
+        .await
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    simperby_test_suite::run_command(format!("cd {repo_dir} && git reset --hard {finalized}"))
+        .await;
+
+    // The second one is older, but doesn't apply without the first one.
+    let (agenda, agenda_commit) = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    let pulled = raw
+        .read()
+        .await
+        .query_commit_path(finalized, agenda_commit)
+        .await
+        .unwrap();
+    assert_eq!(pulled, vec![transactions[0], agenda_commit]);
+    let Commit::Transaction(transaction) = repo.read_commit(transactions[0]).await.unwrap() else {
+        panic!("not a transaction");
+    };
+    assert_eq!(
+        agenda.transactions_hash,
+        Agenda::calculate_transactions_hash(&[transaction])
+    );
+    raw.read().await.check_clean().await.unwrap();
+}
+
+#[tokio::test]
+async fn contributed_patches() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let pool = transaction_pool::TransactionPool::new(Arc::new(RwLock::new(
+        create_test_dms(
+            transaction_pool::generate_dms_key("test"),
+            keys.iter()
+                .map(|(public_key, _)| public_key.clone())
+                .collect(),
+            keys[0].1.clone(),
+        )
+        .await,
+    )));
+    repo.set_transaction_pool(pool.clone());
+
+    // A contributor, who is not a member, makes a patch of their working tree.
+    let (contributor, private_key) = generate_keypair("contributor");
+    let repo_dir = format!("{dir}/repository");
+    simperby_test_suite::run_command(format!("cd {repo_dir} && echo 1 > contributed.txt")).await;
+    let bundle = patch::PatchBundle::from_working_tree(
+        &*raw.read().await,
+        "Add a file".to_owned(),
+        &private_key,
+    )
+    .await
+    .unwrap();
+    raw.write().await.checkout_clean().await.unwrap();
+
+    let mut tampered = bundle.clone();
+    tampered.patch.message = "Add another file".to_owned();
+    assert!(repo.submit_patch(tampered).await.is_err());
+    let patch_hash = repo.submit_patch(bundle).await.unwrap();
+    assert_eq!(pool.list_patches().await.unwrap().len(), 1);
+
+    // A member turns it into a transaction.
+    let commit_hash = repo
+        .accept_patch(rs.query_name(&keys[0].0).unwrap(), patch_hash)
+        .await
+        .unwrap();
+    let Commit::Transaction(transaction) = repo.read_commit(commit_hash).await.unwrap() else {
+        panic!("not a transaction");
+    };
+    assert_eq!(transaction.author, rs.query_name(&keys[0].0).unwrap());
+    assert_eq!(transaction.head, "Add a file");
+    assert!(transaction
+        .body
+        .contains(&format!("{}{contributor}", patch::CONTRIBUTOR_PREFIX)));
+    assert!(pool.list_patches().await.unwrap().is_empty());
+    assert_eq!(pool.list().await.unwrap().len(), 1);
+    assert!(pool.get_patch(patch_hash).await.unwrap().is_none());
+    assert!(repo
+        .accept_patch(rs.query_name(&keys[0].0).unwrap(), patch_hash)
+        .await
+        .is_err());
+
+    let (agenda, _) = repo
+        .create_agenda(rs.query_name(&keys[0].0).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        agenda.transactions_hash,
+        Agenda::calculate_transactions_hash(&[transaction])
+    );
+}
+
+#[tokio::test]
+async fn attestations() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let mut repo = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/repository"))
+                .await
+                .unwrap(),
+        )),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    let genesis_commit = repo
+        .read_last_finalization_info()
+        .await
+        .unwrap()
+        .commit_hash;
+    simperby_test_suite::run_command(format!("cp -r {dir}/repository {dir}/mirror")).await;
+    let mut mirror = DistributedRepository::new(
+        Arc::new(RwLock::new(
+            RawRepository::open(&format!("{dir}/mirror")).await.unwrap(),
+        )),
+        config,
+    )
+    .await
+    .unwrap();
+    assert!(mirror.refresh_attestation().await.is_err());
+    mirror.set_signing_key(keys[0].1.clone());
+    assert_eq!(mirror.read_attestation().await.unwrap(), None);
+
+    let block = create_finalized_block(&mut mirror, &format!("{dir}/mirror"), &rs, &keys, 0).await;
+    let attestation = mirror.refresh_attestation().await.unwrap();
+    assert_eq!(attestation.attestation.height, 1);
+    assert_eq!(attestation.attestation.block_hash, block.to_hash256());
+    assert_eq!(
+        mirror.read_attestation().await.unwrap(),
+        Some(attestation.clone())
+    );
+    // Already attested.
+    assert_eq!(mirror.refresh_attestation().await.unwrap(), attestation);
+
+    repo.get_raw()
+        .write()
+        .await
+        .add_remote("mirror".to_owned(), format!("{dir}/mirror"))
+        .await
+        .unwrap();
+    let report = repo.fetch().await.unwrap();
+    assert_eq!(report.attestations.len(), 1);
+    assert_eq!(report.attestations[0].remote, "mirror");
+    assert_eq!(report.attestations[0].result, Ok(attestation));
+
+    // The mirror serves a `finalized` branch other than the attested one.
+    simperby_test_suite::run_command(format!(
+        "cd {dir}/mirror && git update-ref refs/heads/finalized {genesis_commit}"
+    ))
+    .await;
+    let report = repo.fetch().await.unwrap();
+    assert_eq!(report.attestations.len(), 1);
+    assert!(report.attestations[0].result.is_err());
+}
+
+#[tokio::test]
+async fn divergent_branches() {
+    setup_test();
+    let (rs, keys) = test_utils::generate_standard_genesis(4);
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let config = Config {
+        long_range_attack_distance: 1,
+        prune_policy: Default::default(),
+        trusted_checkpoint: None,
+        require_signed_commits: false,
+        retention: Default::default(),
+    };
+    let mut repo = DistributedRepository::new(Arc::clone(&raw), config)
+        .await
+        .unwrap();
+    repo.genesis().await.unwrap();
+    let genesis_commit = raw
+        .read()
+        .await
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    let create_transaction = |branch: &'static str, message: &'static str| {
+        let raw = Arc::clone(&raw);
+        async move {
+            let mut raw = raw.write().await;
+            raw.checkout(branch.into()).await.unwrap();
+            raw.create_commit(RawCommit {
+                message: message.to_owned(),
+                diff: None,
+                author: "member-0000".to_owned(),
+                email: "member-0000@simperby.net".to_owned(),
+                timestamp: 0,
+            })
+            .await
+            .unwrap()
+        }
+    };
+    for branch in ["mine", "stale"] {
+        raw.write()
+            .await
+            .create_branch(branch.to_owned(), genesis_commit)
+            .await
+            .unwrap();
+    }
+    let transaction_b = create_transaction("mine", "transaction b").await;
+    let transaction_a = create_transaction("mine", "transaction a").await;
+    create_transaction("stale", "transaction c").await;
+    create_transaction(WORK_BRANCH_NAME, "transaction a").await;
+    assert!(repo.get_divergent_branches().await.unwrap().is_empty());
+
+    // The same transaction is finalized in another commit.
+    let repo_dir = format!("{dir}/repository");
+    create_finalized_block(&mut repo, &repo_dir, &rs, &keys, 0).await;
+    let divergent = repo.get_divergent_branches().await.unwrap();
+    for branch in ["mine", "stale"] {
+        let divergent = divergent.iter().find(|x| x.branch == branch).unwrap();
+        assert_eq!(
+            divergent.reason,
+            divergence::DivergenceReason::Outdated {
+                merge_base: genesis_commit
+            }
+        );
+    }
+
+    let resolution = repo
+        .resolve_divergence("mine".to_owned(), divergence::ResolutionStrategy::Rebase)
+        .await
+        .unwrap();
+    assert_eq!(resolution.replayed, vec![transaction_b]);
+    assert_eq!(resolution.dropped.len(), 1);
+    assert_eq!(resolution.dropped[0].0, transaction_a);
+    assert!(resolution.dropped[0]
+        .1
+        .contains("already been finalized at height 1"));
+    let finalized_commit = raw
+        .read()
+        .await
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let rebased = resolution.commit_hash.unwrap();
+    assert_eq!(
+        raw.read()
+            .await
+            .list_ancestors(rebased, Some(1))
+            .await
+            .unwrap(),
+        vec![finalized_commit]
+    );
+    // Resolved, so it can't be resolved again.
+    assert!(repo
+        .resolve_divergence("mine".to_owned(), divergence::ResolutionStrategy::Discard)
+        .await
+        .is_err());
+
+    let resolution = repo
+        .resolve_divergence("stale".to_owned(), divergence::ResolutionStrategy::Discard)
+        .await
+        .unwrap();
+    assert_eq!(resolution.commit_hash, None);
+    let branches = raw.read().await.list_branches().await.unwrap();
+    assert!(!branches.contains(&"stale".to_owned()));
+    assert!(repo
+        .get_divergent_branches()
+        .await
+        .unwrap()
+        .iter()
+        .all(|x| x.branch != "mine" && x.branch != "stale"));
+}
+
+#[tokio::test]
+async fn transaction_spec() {
+    setup_test();
+    let (mut rs, keys) = test_utils::generate_standard_genesis(4);
+    rs.transaction_spec = Some(TransactionSpec {
+        allowed_paths: vec!["docs/**".to_owned()],
+        forbidden_paths: vec!["docs/private/**".to_owned()],
+        max_files: 2,
+    });
+    let dir = create_temp_dir();
+    setup_pre_genesis_repository(&dir, rs.clone()).await;
+    let raw = Arc::new(RwLock::new(
+        RawRepository::open(&format!("{dir}/repository"))
+            .await
+            .unwrap(),
+    ));
+    let mut repo = DistributedRepository::new(
+        Arc::clone(&raw),
+        Config {
+            long_range_attack_distance: 1,
+            prune_policy: Default::default(),
+            trusted_checkpoint: None,
+            require_signed_commits: false,
+            retention: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+    repo.genesis().await.unwrap();
+    assert_eq!(
+        repo.read_last_finalization_info()
+            .await
+            .unwrap()
+            .reserved_state
+            .transaction_spec,
+        rs.transaction_spec
+    );
+    let author = rs.query_name(&keys[0].0).unwrap();
+    let repo_dir = format!("{dir}/repository");
+    raw.write()
+        .await
+        .checkout(WORK_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    // Commits the files on the `work` branch, returning the commit.
+    let commit_files = |files: &'static [&'static str], timestamp: Timestamp| {
+        let raw = Arc::clone(&raw);
+        let repo_dir = repo_dir.clone();
+        async move {
+            for file in files {
+                simperby_test_suite::run_command(format!(
+                    "cd {repo_dir} && mkdir -p $(dirname {file}) && echo 1 > {file}"
+                ))
+                .await;
+            }
+            let mut raw = raw.write().await;
+            let patch = raw.get_working_tree_patch().await.unwrap();
+            raw.checkout_clean().await.unwrap();
+            raw.create_commit(RawCommit {
+                message: format!("add {}", files.join(", ")),
+                diff: Some(patch),
+                author: "member-0000".to_owned(),
+                email: "member-0000@simperby.net".to_owned(),
+                timestamp,
+            })
+            .await
+            .unwrap()
+        }
+    };
+    let allowed = commit_files(&["docs/a.md", "docs/b/c.md"], 1).await;
+    assert_eq!(
+        raw.read().await.get_changed_paths(allowed).await.unwrap(),
+        vec!["docs/a.md".to_owned(), "docs/b/c.md".to_owned()]
+    );
+    repo.preview_agenda(author.clone(), None, None)
+        .await
+        .unwrap();
+
+    for (files, violation) in [
+        (
+            &["src/main.rs"][..],
+            TransactionSpecViolation::NotAllowedPath {
+                path: "src/main.rs".to_owned(),
+            },
+        ),
+        (
+            &["docs/private/key"][..],
+            TransactionSpecViolation::ForbiddenPath {
+                path: "docs/private/key".to_owned(),
+                glob: "docs/private/**".to_owned(),
+            },
+        ),
+        (
+            &["docs/d", "docs/e", "docs/f"][..],
+            TransactionSpecViolation::TooManyFiles { files: 3, max: 2 },
+        ),
+        (
+            &["docs/g", "reserved/other"][..],
+            TransactionSpecViolation::ReservedPath {
+                path: "reserved/other".to_owned(),
+            },
+        ),
+    ] {
+        commit_files(files, 2).await;
+        let error = repo.create_agenda(author.clone()).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TransactionSpecViolation>(),
+            Some(&violation)
+        );
+        raw.write()
+            .await
+            .move_branch(WORK_BRANCH_NAME.into(), allowed)
+            .await
+            .unwrap();
+        raw.write().await.checkout_clean().await.unwrap();
+    }
+    repo.create_agenda(author).await.unwrap();
+}
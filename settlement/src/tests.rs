@@ -117,6 +117,8 @@ pub async fn scenario_1(
         author: chain_info.reserved_state.consensus_leader_order[0].clone(),
         timestamp: 0,
         transactions_hash: Agenda::calculate_transactions_hash(&transactions),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
     csv.apply_commit(&Commit::AgendaProof(AgendaProof {
@@ -149,14 +151,7 @@ pub async fn scenario_1(
         .validators
         .iter()
         .map(|private_key| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
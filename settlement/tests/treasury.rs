@@ -182,6 +182,8 @@ fn relay_1() {
         author: reserved_state.query_name(&keys[0].0).unwrap(),
         timestamp: 0,
         transactions_hash: Agenda::calculate_transactions_hash(&[tx1.clone(), tx2.clone()]),
+        description: None,
+        deadline: None,
     };
     csv.apply_commit(&Commit::Agenda(agenda.clone())).unwrap();
     csv.apply_commit(&Commit::AgendaProof(AgendaProof {
@@ -212,14 +214,7 @@ fn relay_1() {
     let signatures = keys
         .iter()
         .map(|(_, private_key)| {
-            TypedSignature::sign(
-                &FinalizationSignTarget {
-                    block_hash: block_header.to_hash256(),
-                    round: 0,
-                },
-                private_key,
-            )
-            .unwrap()
+            PrecommitSignature::sign(block_header.to_hash256(), 0, 0, private_key).unwrap()
         })
         .collect::<Vec<_>>();
     let fp = FinalizationProof {
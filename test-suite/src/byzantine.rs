@@ -0,0 +1,221 @@
+//! A consensus node that misbehaves, for testing that the honest validators still finalize.
+use simperby_consensus::*;
+use simperby_core::*;
+use simperby_network::*;
+
+type Error = eyre::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByzantineBehavior {
+    /// Along with each vote, casts a conflicting one on the given block in the same round.
+    Equivocate { other_block_hash: Hash256 },
+    /// Never sends the prevotes and the precommits.
+    WithholdVotes,
+    /// Proposes the given block, which the others can't verify, instead of the candidate.
+    ProposeInvalidBlock { block_hash: Hash256 },
+    /// Along with each message, sends a copy with an invalid commitment
+    /// and a prevote on a block that nobody knows.
+    SendMalformedMessages,
+}
+
+/// A wrapper of a consensus instance that alters the outgoing messages by the behaviors.
+///
+/// Drive the inner consensus with [`ByzantineNode::consensus_mut`] as an honest node,
+/// but broadcast with [`ByzantineNode::flush`] and [`ByzantineNode::deliver`].
+pub struct ByzantineNode {
+    consensus: Consensus,
+    private_key: PrivateKey,
+    behaviors: Vec<ByzantineBehavior>,
+    /// The messages with invalid commitments, which can't be stored in the DMS.
+    malformed_messages: Vec<(ConsensusMessage, MessageCommitmentProof)>,
+}
+
+impl ByzantineNode {
+    pub fn new(
+        consensus: Consensus,
+        private_key: PrivateKey,
+        behaviors: Vec<ByzantineBehavior>,
+    ) -> Self {
+        Self {
+            consensus,
+            private_key,
+            behaviors,
+            malformed_messages: Vec::new(),
+        }
+    }
+
+    pub fn consensus(&self) -> &Consensus {
+        &self.consensus
+    }
+
+    pub fn consensus_mut(&mut self) -> &mut Consensus {
+        &mut self.consensus
+    }
+
+    /// Commits the messages of the inner consensus to the DMS, altered by the behaviors.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let dms = self.consensus.get_dms();
+        for message in self.consensus.messages_to_broadcast().await? {
+            for message in self.alter(message) {
+                dms.write().await.commit_message(&message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delivers the messages of this node to the DMS of another node, as the network would.
+    ///
+    /// Returns the number of the messages that the DMS rejected.
+    pub async fn deliver(&self, dms: &mut Dms<ConsensusMessage>) -> Result<usize, Error> {
+        let mut rejected = relay_messages(&*self.consensus.get_dms().read().await, dms).await?;
+        for (message, commitment) in &self.malformed_messages {
+            if dms
+                .add_committed_message(message, commitment.clone())
+                .await
+                .is_err()
+            {
+                rejected += 1;
+            }
+        }
+        Ok(rejected)
+    }
+
+    /// Returns the messages to send instead of the given one.
+    fn alter(&mut self, message: ConsensusMessage) -> Vec<ConsensusMessage> {
+        let mut messages = vec![message];
+        for behavior in self.behaviors.clone() {
+            messages = messages
+                .into_iter()
+                .flat_map(|message| self.apply(&behavior, message))
+                .collect();
+        }
+        messages
+    }
+
+    fn apply(
+        &mut self,
+        behavior: &ByzantineBehavior,
+        message: ConsensusMessage,
+    ) -> Vec<ConsensusMessage> {
+        match behavior {
+            ByzantineBehavior::Equivocate { other_block_hash } => {
+                let conflicting = match &message {
+                    ConsensusMessage::NonNilPreVoted(round, block_hash)
+                        if block_hash != other_block_hash =>
+                    {
+                        ConsensusMessage::NonNilPreVoted(*round, *other_block_hash)
+                    }
+                    ConsensusMessage::NilPreVoted(round) => {
+                        ConsensusMessage::NonNilPreVoted(*round, *other_block_hash)
+                    }
+                    ConsensusMessage::NonNilPreCommitted(round, block_hash, timestamp)
+                        if block_hash != other_block_hash =>
+                    {
+                        ConsensusMessage::NonNilPreCommitted(*round, *other_block_hash, *timestamp)
+                    }
+                    // The timestamp is fixed not to make a new message on every flush.
+                    ConsensusMessage::NilPreCommitted(round) => {
+                        ConsensusMessage::NonNilPreCommitted(*round, *other_block_hash, 0)
+                    }
+                    _ => return vec![message],
+                };
+                vec![message, conflicting]
+            }
+            ByzantineBehavior::WithholdVotes => match message {
+                ConsensusMessage::Proposal { .. } => vec![message],
+                _ => Vec::new(),
+            },
+            ByzantineBehavior::ProposeInvalidBlock { block_hash } => match message {
+                ConsensusMessage::Proposal {
+                    round, valid_round, ..
+                } => vec![ConsensusMessage::Proposal {
+                    round,
+                    valid_round,
+                    block_hash: *block_hash,
+                }],
+                _ => vec![message],
+            },
+            ByzantineBehavior::SendMalformedMessages => {
+                let commitment = MessageCommitmentProof {
+                    committer: self.private_key.public_key(),
+                    signature: Signature::sign(Hash256::hash("malformed"), &self.private_key)
+                        .expect("the private key is valid"),
+                };
+                if !self.malformed_messages.iter().any(|(m, _)| m == &message) {
+                    self.malformed_messages.push((message.clone(), commitment));
+                }
+                let round = match &message {
+                    ConsensusMessage::Proposal { round, .. }
+                    | ConsensusMessage::NonNilPreVoted(round, _)
+                    | ConsensusMessage::NonNilPreCommitted(round, _, _)
+                    | ConsensusMessage::NilPreVoted(round)
+                    | ConsensusMessage::NilPreCommitted(round) => *round,
+                };
+                vec![
+                    message,
+                    ConsensusMessage::NonNilPreVoted(round, Hash256::hash("unknown block")),
+                ]
+            }
+        }
+    }
+}
+
+/// Copies the messages in one DMS to another, as the network would.
+///
+/// Returns the number of the messages that the destination rejected.
+pub async fn relay_messages<M: DmsMessage>(from: &Dms<M>, to: &mut Dms<M>) -> Result<usize, Error> {
+    let mut rejected = 0;
+    for message in from.read_messages().await? {
+        for commitment in message.committers {
+            if to
+                .add_committed_message(&message.message, commitment)
+                .await
+                .is_err()
+            {
+                rejected += 1;
+            }
+        }
+    }
+    Ok(rejected)
+}
+
+/// Finds the validators that precommitted two different blocks in the same round,
+/// among the messages in the DMS.
+///
+/// `headers` are the known blocks, to which the conflicting precommits must belong.
+pub async fn collect_double_sign_evidence(
+    dms: &Dms<ConsensusMessage>,
+    headers: &[BlockHeader],
+) -> Result<Vec<DoubleSignEvidence>, Error> {
+    let mut precommits = Vec::new();
+    for message in dms.read_messages().await? {
+        if let ConsensusMessage::NonNilPreCommitted(round, block_hash, timestamp) = message.message
+        {
+            let header = match headers.iter().find(|h| h.to_hash256() == block_hash) {
+                Some(header) => header,
+                None => continue,
+            };
+            for commitment in message.committers {
+                precommits.push(SignedPrecommit {
+                    header: header.clone(),
+                    round,
+                    timestamp,
+                    signature: TypedSignature::new(commitment.signature, commitment.committer),
+                });
+            }
+        }
+    }
+    let mut evidence = Vec::new();
+    for (i, first) in precommits.iter().enumerate() {
+        for second in &precommits[i + 1..] {
+            let candidate = DoubleSignEvidence {
+                first: first.clone(),
+                second: second.clone(),
+            };
+            if verify::verify_double_sign_evidence(&candidate).is_ok() {
+                evidence.push(candidate);
+            }
+        }
+    }
+    Ok(evidence)
+}
@@ -1,7 +1,12 @@
+pub mod byzantine;
+pub mod test_network;
+
+pub use byzantine::*;
 use path_slash::PathExt as _;
 use simperby_core::*;
 use simperby_network::*;
 use tempfile::TempDir;
+pub use test_network::*;
 
 pub fn setup_test() {
     use std::sync::Once;
@@ -69,7 +74,7 @@ pub fn create_temp_dir() -> String {
 pub fn dispense_port() -> u16 {
     use once_cell::sync::OnceCell;
     static PORTS: OnceCell<parking_lot::RwLock<Vec<u16>>> = OnceCell::new();
-    PORTS
+    let mut ports = PORTS
         .get_or_init(|| {
             parking_lot::RwLock::new({
                 use rand::seq::SliceRandom;
@@ -79,9 +84,16 @@ pub fn dispense_port() -> u16 {
                 v
             })
         })
-        .write()
-        .pop()
-        .expect("wtf did we have tests more than 1000?")
+        .write();
+    loop {
+        let port = ports.pop().expect("wtf did we have tests more than 1000?");
+        // The range overlaps with the ephemeral ports, which the connections may be holding.
+        if std::net::TcpListener::bind(("0.0.0.0", port)).is_ok()
+            && std::net::UdpSocket::bind(("0.0.0.0", port)).is_ok()
+        {
+            return port;
+        }
+    }
 }
 
 pub async fn create_test_dms<M: DmsMessage>(
@@ -92,9 +104,17 @@ pub async fn create_test_dms<M: DmsMessage>(
     let path = create_temp_dir();
     StorageImpl::create(&path).await.unwrap();
     let storage = StorageImpl::open(&path).await.unwrap();
-    Dms::new(storage, dms::Config { dms_key, members }, private_key)
-        .await
-        .unwrap()
+    Dms::new(
+        storage,
+        dms::Config {
+            dms_key,
+            members,
+            priority_weights: Default::default(),
+        },
+        private_key,
+    )
+    .await
+    .unwrap()
 }
 
 pub async fn setup_server_client_nodes(
@@ -113,6 +133,9 @@ pub async fn setup_server_client_nodes(
         ports: vec![(format!("dms-{network_id}"), dispense_port())]
             .into_iter()
             .collect(),
+        relay: None,
+        relayed_peers: Vec::new(),
+        transport: Transport::Http,
     };
     let mut clients = Vec::new();
     for _ in 0..client_n {
@@ -128,7 +151,9 @@ pub async fn setup_server_client_nodes(
                 ports: server.ports.clone(),
                 message: "".to_owned(),
                 recently_seen_timestamp: 0,
+                relay: None,
             }],
+            transport: Transport::Http,
         };
         clients.push(network_config);
     }
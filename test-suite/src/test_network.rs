@@ -0,0 +1,180 @@
+//! An in-memory network among the DMSs of the nodes, with the faults injected.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use simperby_core::*;
+use simperby_network::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+type Error = eyre::Error;
+
+/// The faults of the links, applied to every message sent.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    /// The number of ticks that a message takes to arrive.
+    pub delay_ticks: u64,
+    /// The probability that a message is lost; the sender retries on the next tick.
+    pub loss_rate: f64,
+    /// The probability that a message is delivered twice.
+    pub duplication_rate: f64,
+}
+
+/// A payload on a link, carrying the commitments of a message.
+struct InFlight<M> {
+    from: usize,
+    to: usize,
+    message: M,
+    commitments: Vec<MessageCommitmentProof>,
+    arrival: u64,
+}
+
+/// Delivers the messages among the nodes on every [`TestNetwork::tick`],
+/// as the DMS synchronization would.
+///
+/// Like [`PacketBatch`], the new commitments of a message are sent together in a single payload.
+///
+/// The nodes are split into partitions, and a message is delivered only within the same one.
+/// It's deterministic for the same seed.
+pub struct TestNetwork<M: DmsMessage> {
+    nodes: Vec<Arc<RwLock<Dms<M>>>>,
+    /// The partition that each node belongs to.
+    partitions: Vec<usize>,
+    faults: Faults,
+    in_flight: Vec<InFlight<M>>,
+    /// `(receiver, message hash, committer)` of the messages sent, to send each only once.
+    sent: HashSet<(usize, Hash256, PublicKey)>,
+    tick: u64,
+    rng: StdRng,
+}
+
+/// The statistics of a tick, counted in payloads unless noted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickReport {
+    pub delivered: usize,
+    /// The commitments in the delivered payloads.
+    pub delivered_commitments: usize,
+    pub lost: usize,
+    pub duplicated: usize,
+    /// The payloads dropped on arrival because the link was partitioned meanwhile.
+    pub dropped: usize,
+}
+
+impl<M: DmsMessage + Clone> TestNetwork<M> {
+    pub fn new(nodes: Vec<Arc<RwLock<Dms<M>>>>, seed: u64) -> Self {
+        let partitions = vec![0; nodes.len()];
+        Self {
+            nodes,
+            partitions,
+            faults: Faults::default(),
+            in_flight: Vec::new(),
+            sent: HashSet::new(),
+            tick: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = faults;
+    }
+
+    /// Splits the nodes into the given groups of their indices.
+    ///
+    /// The nodes not in any group are isolated from all the others.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        for (i, partition) in self.partitions.iter_mut().enumerate() {
+            *partition = groups.len() + i;
+        }
+        for (group_index, group) in groups.iter().enumerate() {
+            for &node in group.iter() {
+                self.partitions[node] = group_index;
+            }
+        }
+    }
+
+    /// Reconnects all the nodes.
+    pub fn heal(&mut self) {
+        self.partitions = vec![0; self.nodes.len()];
+    }
+
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.partitions[a] == self.partitions[b]
+    }
+
+    /// Sends the new messages over the connected links and delivers those that arrive.
+    pub async fn tick(&mut self) -> Result<TickReport, Error> {
+        self.tick += 1;
+        let mut report = TickReport::default();
+        for from in 0..self.nodes.len() {
+            let messages = self.nodes[from].read().await.read_messages().await?;
+            for to in 0..self.nodes.len() {
+                if from == to || !self.is_connected(from, to) {
+                    continue;
+                }
+                for message in &messages {
+                    let message_hash = message.message.to_hash256();
+                    let commitments = message
+                        .committers
+                        .iter()
+                        .filter(|commitment| {
+                            !self
+                                .sent
+                                .contains(&(to, message_hash, commitment.committer.clone()))
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if commitments.is_empty() {
+                        continue;
+                    }
+                    if self.rng.gen_bool(self.faults.loss_rate) {
+                        report.lost += 1;
+                        continue;
+                    }
+                    for commitment in &commitments {
+                        self.sent
+                            .insert((to, message_hash, commitment.committer.clone()));
+                    }
+                    let copies = if self.rng.gen_bool(self.faults.duplication_rate) {
+                        report.duplicated += 1;
+                        2
+                    } else {
+                        1
+                    };
+                    for _ in 0..copies {
+                        self.in_flight.push(InFlight {
+                            from,
+                            to,
+                            message: message.message.clone(),
+                            commitments: commitments.clone(),
+                            arrival: self.tick + self.faults.delay_ticks,
+                        });
+                    }
+                }
+            }
+        }
+
+        let (arrived, in_flight) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| x.arrival <= self.tick);
+        self.in_flight = in_flight;
+        for x in arrived {
+            if !self.is_connected(x.from, x.to) {
+                // Let it be sent again once connected.
+                for commitment in &x.commitments {
+                    self.sent
+                        .remove(&(x.to, x.message.to_hash256(), commitment.committer.clone()));
+                }
+                report.dropped += 1;
+                continue;
+            }
+            report.delivered += 1;
+            report.delivered_commitments += x.commitments.len();
+            self.nodes[x.to]
+                .write()
+                .await
+                .add_committed_messages(&x.message, x.commitments)
+                .await?;
+        }
+        Ok(report)
+    }
+}
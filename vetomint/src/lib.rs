@@ -16,10 +16,42 @@ pub type Timestamp = i64;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ConsensusParams {
-    pub timeout_ms: u64,
+    /// The timeout for waiting a proposal in the first round.
+    pub propose_timeout_ms: u64,
+    /// The timeout for waiting the rest of the prevotes in the first round.
+    pub prevote_timeout_ms: u64,
+    /// The timeout for waiting the rest of the precommits in the first round.
+    pub precommit_timeout_ms: u64,
+    /// The multiplier applied to the timeouts for each subsequent round, in permille.
+    ///
+    /// For example, `1500` makes each timeout 1.5 times longer than that of the previous round.
+    pub timeout_backoff_permille: u64,
+    /// The upper bound of the timeouts.
+    pub max_timeout_ms: u64,
     pub repeat_round_for_first_leader: usize,
 }
 
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            propose_timeout_ms: 3_000,
+            prevote_timeout_ms: 1_000,
+            precommit_timeout_ms: 1_000,
+            timeout_backoff_permille: 1_500,
+            max_timeout_ms: 60_000,
+            repeat_round_for_first_leader: 1,
+        }
+    }
+}
+
+/// The step that a timeout is scheduled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TimeoutStep {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
 /// An event that (potentially) triggers a state transition of `StateMachine`.
 ///
 /// Note that there is no cryptography-related info here, because it's
@@ -126,6 +158,11 @@ impl Vetomint {
         &self.state.height_info
     }
 
+    /// Returns the round that the state machine is currently in.
+    pub fn get_round(&self) -> Round {
+        self.state.round
+    }
+
     pub fn progress(
         &mut self,
         event: ConsensusEvent,
@@ -199,6 +236,19 @@ pub fn decide_proposer(round: usize, height_info: &HeightInfo) -> ValidatorIndex
     }
 }
 
-pub fn decide_timeout(params: &ConsensusParams, _round: usize) -> Timestamp {
-    params.timeout_ms as i64
+/// Decides the timeout of the given step, which grows exponentially with the round up to the cap.
+pub fn decide_timeout(params: &ConsensusParams, step: TimeoutStep, round: usize) -> Timestamp {
+    let initial = match step {
+        TimeoutStep::Propose => params.propose_timeout_ms,
+        TimeoutStep::Prevote => params.prevote_timeout_ms,
+        TimeoutStep::Precommit => params.precommit_timeout_ms,
+    };
+    let mut timeout = std::cmp::min(initial, params.max_timeout_ms) as u128;
+    for _ in 0..round {
+        if timeout >= params.max_timeout_ms as u128 {
+            break;
+        }
+        timeout = timeout * params.timeout_backoff_permille as u128 / 1000;
+    }
+    std::cmp::min(timeout, params.max_timeout_ms as u128) as Timestamp
 }
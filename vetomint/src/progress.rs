@@ -45,7 +45,7 @@ pub(crate) fn progress(
             response.extend(on_4f_non_nil_prevote_in_prevote_step(
                 state, round, proposal,
             ));
-            response.extend(on_4f_non_nil_precommit(state, round, proposal));
+            response.extend(on_4f_non_nil_precommit(state, proposal, round));
             response
         }
         ConsensusEvent::SkipRound { round } => progress(
@@ -86,6 +86,7 @@ pub(crate) fn progress(
                 response.extend(on_4f_nil_prevote(state, round));
             }
             response.extend(on_5f_prevote(state, round, proposal));
+            response.extend(on_4f_prevote(state, round, timestamp));
             response
         }
         ConsensusEvent::Precommit {
@@ -99,10 +100,10 @@ pub(crate) fn progress(
                 round,
             });
             let mut response = Vec::new();
-            response.extend(on_5f_precommit(state, round));
+            response.extend(on_5f_precommit(state, round, timestamp));
             response.extend(on_4f_nil_precommit(state, round, timestamp));
             if let Some(proposal) = proposal {
-                response.extend(on_4f_non_nil_precommit(state, round, proposal));
+                response.extend(on_4f_non_nil_precommit(state, proposal, round));
             }
             response
         }
@@ -120,6 +121,18 @@ pub(crate) fn progress(
                     state.step = ConsensusStep::Prevote;
                 }
             }
+            for (round, timeout) in state.prevote_timeout_schedules.clone() {
+                if timestamp >= timeout
+                    && round == state.round
+                    && state.step == ConsensusStep::Prevote
+                {
+                    response.push(ConsensusResponse::BroadcastPrecommit {
+                        proposal: None,
+                        round,
+                    });
+                    state.step = ConsensusStep::Precommit;
+                }
+            }
             for (round, timeout) in state.precommit_timeout_schedules.clone() {
                 if timestamp >= timeout && round == state.round {
                     response.extend(start_round(state, round + 1, timestamp));
@@ -153,7 +166,12 @@ fn start_round(
     } else {
         state.propose_timeout_schedules.insert((
             round,
-            timestamp + decide_timeout(&state.height_info.consensus_params, round),
+            timestamp
+                + decide_timeout(
+                    &state.height_info.consensus_params,
+                    TimeoutStep::Propose,
+                    round,
+                ),
         ));
         Vec::new()
     }
@@ -344,7 +362,37 @@ fn on_5f_prevote(
     }
 }
 
-fn on_5f_precommit(state: &mut ConsensusState, target_round: Round) -> Vec<ConsensusResponse> {
+fn on_4f_prevote(
+    state: &mut ConsensusState,
+    target_round: Round,
+    timestamp: Timestamp,
+) -> Vec<ConsensusResponse> {
+    if target_round != state.round {
+        return Vec::new();
+    }
+    if state.step == ConsensusStep::Prevote
+        && !state.for_the_first_time_1.contains(&target_round)
+        && state.get_total_prevotes(target_round) * 3 > state.get_total_voting_power() * 2
+    {
+        state.for_the_first_time_1.insert(target_round);
+        state.prevote_timeout_schedules.insert((
+            target_round,
+            timestamp
+                + decide_timeout(
+                    &state.height_info.consensus_params,
+                    TimeoutStep::Prevote,
+                    target_round,
+                ),
+        ));
+    }
+    Vec::new()
+}
+
+fn on_5f_precommit(
+    state: &mut ConsensusState,
+    target_round: Round,
+    timestamp: Timestamp,
+) -> Vec<ConsensusResponse> {
     if target_round != state.round {
         return Vec::new();
     }
@@ -352,9 +400,15 @@ fn on_5f_precommit(state: &mut ConsensusState, target_round: Round) -> Vec<Conse
         && state.get_total_precommits(target_round) * 6 > state.get_total_voting_power() * 5
     {
         state.for_the_first_time_2.insert(target_round);
-        state
-            .precommit_timeout_schedules
-            .insert((target_round, 1000));
+        state.precommit_timeout_schedules.insert((
+            target_round,
+            timestamp
+                + decide_timeout(
+                    &state.height_info.consensus_params,
+                    TimeoutStep::Precommit,
+                    target_round,
+                ),
+        ));
     }
     Vec::new()
 }
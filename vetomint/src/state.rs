@@ -40,6 +40,7 @@ pub(crate) struct ConsensusState {
     pub prevotes: BTreeSet<Vote>,
     pub precommits: BTreeSet<Vote>,
     pub propose_timeout_schedules: BTreeSet<(Round, Timestamp)>,
+    pub prevote_timeout_schedules: BTreeSet<(Round, Timestamp)>,
     pub precommit_timeout_schedules: BTreeSet<(Round, Timestamp)>,
     pub for_the_first_time_1: BTreeSet<Round>,
     pub for_the_first_time_2: BTreeSet<Round>,
@@ -61,6 +62,7 @@ impl ConsensusState {
             prevotes: Default::default(),
             precommits: Default::default(),
             propose_timeout_schedules: Default::default(),
+            prevote_timeout_schedules: Default::default(),
             precommit_timeout_schedules: Default::default(),
             for_the_first_time_1: Default::default(),
             for_the_first_time_2: Default::default(),
@@ -8,7 +8,11 @@ fn normal_1() {
         this_node_index: Some(0),
         timestamp: 0,
         consensus_params: ConsensusParams {
-            timeout_ms: 100,
+            propose_timeout_ms: 100,
+            prevote_timeout_ms: 100,
+            precommit_timeout_ms: 100,
+            timeout_backoff_permille: 1_000,
+            max_timeout_ms: 100,
             repeat_round_for_first_leader: 1,
         },
         initial_block_candidate: 0,
@@ -60,7 +64,7 @@ fn normal_1() {
         );
     }
 
-    let mut nodes = vec![vec![proposer], nodes].concat();
+    let mut nodes = [vec![proposer], nodes].concat();
 
     for (i, node) in nodes.iter_mut().enumerate() {
         let response = node.progress(
@@ -126,3 +130,99 @@ fn normal_1() {
         );
     }
 }
+
+#[test]
+fn timeout_backoff() {
+    let params = ConsensusParams {
+        propose_timeout_ms: 1000,
+        prevote_timeout_ms: 400,
+        precommit_timeout_ms: 200,
+        timeout_backoff_permille: 2_000,
+        max_timeout_ms: 5000,
+        repeat_round_for_first_leader: 1,
+    };
+    let propose = (0..5)
+        .map(|round| decide_timeout(&params, TimeoutStep::Propose, round))
+        .collect::<Vec<_>>();
+    assert_eq!(propose, vec![1000, 2000, 4000, 5000, 5000]);
+    assert_eq!(decide_timeout(&params, TimeoutStep::Prevote, 2), 1600);
+    assert_eq!(decide_timeout(&params, TimeoutStep::Precommit, 1), 400);
+    assert_eq!(decide_timeout(&params, TimeoutStep::Precommit, 1000), 5000);
+}
+
+/// A round fails by timeouts, and the next round waits longer.
+#[test]
+fn round_escalation() {
+    let height_info = HeightInfo {
+        validators: vec![1, 1, 1, 1],
+        this_node_index: Some(3),
+        timestamp: 0,
+        consensus_params: ConsensusParams {
+            propose_timeout_ms: 100,
+            prevote_timeout_ms: 50,
+            precommit_timeout_ms: 20,
+            timeout_backoff_permille: 1_500,
+            max_timeout_ms: 10_000,
+            repeat_round_for_first_leader: 1,
+        },
+        initial_block_candidate: 0,
+    };
+    let mut node = Vetomint::new(height_info);
+    assert_eq!(node.progress(ConsensusEvent::Start, 0), vec![]);
+
+    // Round 0: no proposal arrives.
+    assert_eq!(node.progress(ConsensusEvent::Timer, 99), vec![]);
+    assert_eq!(
+        node.progress(ConsensusEvent::Timer, 100),
+        vec![ConsensusResponse::BroadcastPrevote {
+            proposal: None,
+            round: 0
+        }]
+    );
+
+    // The prevotes are split, so it waits for the prevote timeout.
+    for (signer, proposal) in [(0, Some(0)), (1, None), (3, None)] {
+        let response = node.progress(
+            ConsensusEvent::Prevote {
+                proposal,
+                signer,
+                round: 0,
+            },
+            110,
+        );
+        assert_eq!(response, vec![]);
+    }
+    assert_eq!(node.progress(ConsensusEvent::Timer, 159), vec![]);
+    assert_eq!(
+        node.progress(ConsensusEvent::Timer, 160),
+        vec![ConsensusResponse::BroadcastPrecommit {
+            proposal: None,
+            round: 0
+        }]
+    );
+
+    // Every precommit is nil, so it moves to the next round after the precommit timeout.
+    for signer in 0..4 {
+        let response = node.progress(
+            ConsensusEvent::Precommit {
+                proposal: None,
+                signer,
+                round: 0,
+            },
+            170,
+        );
+        assert_eq!(response, vec![]);
+    }
+    assert_eq!(node.progress(ConsensusEvent::Timer, 189), vec![]);
+    assert_eq!(node.progress(ConsensusEvent::Timer, 190), vec![]);
+
+    // Round 1: the propose timeout has grown by 1.5 times.
+    assert_eq!(node.progress(ConsensusEvent::Timer, 339), vec![]);
+    assert_eq!(
+        node.progress(ConsensusEvent::Timer, 340),
+        vec![ConsensusResponse::BroadcastPrevote {
+            proposal: None,
+            round: 1
+        }]
+    );
+}